@@ -1,21 +1,29 @@
 use std::{collections::{HashMap, VecDeque}, net::SocketAddr, time::{Duration, Instant}};
-use log::debug;
 
 use crate::{
     communicator::Communicator,
+    congestion_control::CongestionControl,
+    constants::MIN_SPLIT_PACKET_COUNT_FOR_PROGRESS,
     datagram_range_list::DatagramRangeList,
+    logging::debug,
     socket::DatagramSocket,
     error::Result,
     internal_packet::{InternalPacket, InternalReliability},
+    mtu_blackhole_detector::MtuBlackholeDetector,
+    split_packet_handler::is_split_packet_progress_reported,
     number::DatagramSequenceNumber,
     packet_datagram::PacketDatagram,
     peer_event::PeerEvent,
+    rtt_estimator::RttEstimator,
     send_receipt::SendReceipt,
 };
 
 #[derive(Debug)]
 struct DatagramItem {
+    pub send_time: Instant,
     pub timeout_time: Instant,
+    pub is_nacked: bool,
+    pub size: usize,
     pub packets: Vec<InternalPacket>,
 }
 
@@ -23,15 +31,26 @@ struct DatagramItem {
 pub struct AcknowledgeHandler {
     datagrams: HashMap<DatagramSequenceNumber, DatagramItem>,
     next_datagram_number: DatagramSequenceNumber,
+    bytes_in_flight: usize,
+    congestion_control: CongestionControl,
+    mtu_blackhole_detector: MtuBlackholeDetector,
+    rtt_estimator: RttEstimator,
+    /// The number of acknowledged parts of outgoing split packets, by split packet ID.
+    acked_split_packet_parts: HashMap<u16, u32>,
     remote_addr: SocketAddr,
     remote_guid: u64,    
 }
 
 impl AcknowledgeHandler {
-    pub fn new(remote_addr: SocketAddr, remote_guid: u64,) -> Self {
+    pub fn new(remote_addr: SocketAddr, remote_guid: u64, mtu: u16) -> Self {
         AcknowledgeHandler {
             datagrams: HashMap::new(),
             next_datagram_number: DatagramSequenceNumber::ZERO,
+            bytes_in_flight: 0,
+            congestion_control: CongestionControl::new(mtu),
+            mtu_blackhole_detector: MtuBlackholeDetector::new(mtu),
+            rtt_estimator: RttEstimator::new(),
+            acked_split_packet_parts: HashMap::new(),
             remote_addr,
             remote_guid,
         }
@@ -53,8 +72,14 @@ impl AcknowledgeHandler {
 
         let remote_addr = self.remote_addr;
         let remote_guid = self.remote_guid;
+        let next_datagram_number = self.next_datagram_number;
         timed_out_datagram_numbers.iter().filter_map(|number| {
             if let Some(datagram) = self.datagrams.remove(number) {
+                self.bytes_in_flight -= datagram.size;
+                self.mtu_blackhole_detector.on_datagram_lost(datagram.size);
+                if !datagram.is_nacked {
+                    self.congestion_control.on_resend(next_datagram_number);
+                }
                 Some(datagram.packets)
             } else {
                 None
@@ -73,19 +98,29 @@ impl AcknowledgeHandler {
     pub fn process_outgoing_datagram(&mut self, datagram: PacketDatagram, time: Instant, buf: &mut Vec<u8>) -> Result<()> {
         buf.clear();
         datagram.write(buf)?;
-        let timeout_time = time + Self::get_retransmission_timeout();
-        self.datagrams.insert(self.next_datagram_number, DatagramItem { timeout_time, packets: datagram.into_packets() });
+        let timeout_time = time + self.retransmission_timeout();
+        let size = buf.len();
+        self.bytes_in_flight += size;
+        self.congestion_control.on_datagram_sent(time, size);
+        self.datagrams.insert(self.next_datagram_number, DatagramItem { send_time: time, timeout_time, is_nacked: false, size, packets: datagram.into_packets() });
         self.next_datagram_number = self.next_datagram_number.wrapping_add(DatagramSequenceNumber::ONE);
         Ok(())
     }
     
-    pub fn process_incoming_ack(&mut self, datagram_range_list: DatagramRangeList, communicator: &mut Communicator<impl DatagramSocket>) {
+    pub fn process_incoming_ack(&mut self, time: Instant, datagram_range_list: DatagramRangeList, communicator: &mut Communicator<impl DatagramSocket>) {
         for range in datagram_range_list.into_vec() {
             let mut number = range.start();
             while number.wrapping_less_than(range.end()) || number == range.end() {
                 if let Some(datagram) = self.datagrams.remove(&number) {
+                    self.bytes_in_flight -= datagram.size;
+                    if !datagram.is_nacked {
+                        self.rtt_estimator.on_rtt_sample(time.saturating_duration_since(datagram.send_time));
+                    }
+                    self.mtu_blackhole_detector.on_datagram_acked(datagram.size);
+                    self.congestion_control.on_ack(number, self.next_datagram_number);
                     for packet in datagram.packets {
-                        if let Some(receipt) = packet.receipt() {
+                        let is_packet_acked = self.process_acked_split_packet_part(&packet, communicator);
+                        if let Some(receipt) = packet.receipt().filter(|_| is_packet_acked) {
                             communicator.send_event(PeerEvent::SendReceiptAcked(SendReceipt::new(self.remote_addr, self.remote_guid, receipt)));
                         }
                     }
@@ -97,6 +132,32 @@ impl AcknowledgeHandler {
         }        
     }
 
+    /// Counts the acknowledged parts of split packets with a receipt or many parts and reports
+    /// the progress of large split packets. Returns true if the packet is not a split packet
+    /// or if all parts of the split packet have been acknowledged.
+    fn process_acked_split_packet_part(&mut self, packet: &InternalPacket, communicator: &mut Communicator<impl DatagramSocket>) -> bool {
+        let header = match packet.split_packet_header() {
+            Some(header) => header,
+            None => return true,
+        };
+        let split_id = header.split_packet_id();
+        let total = header.split_packet_count();
+        let is_progress_reported = total >= MIN_SPLIT_PACKET_COUNT_FOR_PROGRESS;
+        if packet.receipt().is_none() && !is_progress_reported {
+            return false;
+        }
+        let received = self.acked_split_packet_parts.entry(split_id).or_insert(0);
+        *received += 1;
+        let received = *received;
+        if received >= total {
+            self.acked_split_packet_parts.remove(&split_id);
+        }
+        if is_progress_reported && is_split_packet_progress_reported(received, total) {
+            communicator.send_event(PeerEvent::SplitPacketProgress { addr: self.remote_addr, guid: self.remote_guid, split_id, received, total, is_incoming: false });
+        }
+        received >= total
+    }
+
     pub fn process_incoming_nack(&mut self, time: Instant, datagram_range_list: DatagramRangeList) {
         for range in datagram_range_list.into_vec() {
             let mut number = range.start();
@@ -104,6 +165,8 @@ impl AcknowledgeHandler {
                 if let Some(datagram) = self.datagrams.get_mut(&number) {
                     // Resend packets in NACK:ed datagram by setting the timeout_time to current time
                     datagram.timeout_time = time;
+                    datagram.is_nacked = true;
+                    self.congestion_control.on_nack(number, self.next_datagram_number);
                 }
                 number = number.wrapping_add(DatagramSequenceNumber::ONE);
             }
@@ -112,28 +175,61 @@ impl AcknowledgeHandler {
 
     /// Returns the retransmission timeout (RTO) duration which is the time
     /// from that a packet is sent until it should be resent if no ACK
-    /// has been received.
-    pub fn get_retransmission_timeout() -> Duration {
-        // TODO: Calculate retransmission timeout from the round-trip time (RTT) to reduce the delay
-        Duration::from_millis(1000)
+    /// has been received. It is calculated from the round trip time of acknowledged datagrams.
+    pub fn retransmission_timeout(&self) -> Duration {
+        self.rtt_estimator.retransmission_timeout()
+    }
+
+    /// Returns true if a new datagram can be sent, i.e. if the next datagram number
+    /// is free and the congestion window is not full.
+    pub fn has_room_for_datagram(&self, time: Instant) -> bool {
+        !self.datagrams.contains_key(&self.next_datagram_number) &&
+            self.congestion_control.has_room_for_datagram(time, self.bytes_in_flight)
+    }
+
+    /// Returns true if large datagrams are lost while small datagrams get
+    /// through, which indicates that the MTU is too large for the network path.
+    pub fn is_mtu_blackhole_detected(&self) -> bool {
+        self.mtu_blackhole_detector.is_blackhole_detected()
+    }
+
+    /// Sets the MTU used for congestion control and restarts the MTU black hole detection.
+    pub fn set_mtu(&mut self, mtu: u16) {
+        self.congestion_control.set_mtu(mtu);
+        self.mtu_blackhole_detector = MtuBlackholeDetector::new(mtu);
+    }
+
+    /// Registers the data arrival rate in bytes per microsecond reported in an ACK.
+    pub fn process_data_arrival_rate(&mut self, data_arrival_rate: f32) {
+        self.congestion_control.on_data_arrival_rate(data_arrival_rate);
     }
 
-    pub fn has_room_for_datagram(&self) -> bool {
-        !self.datagrams.contains_key(&self.next_datagram_number)
+    /// Removes all sent datagrams that have not been acknowledged and returns their packets.
+    pub fn take_packets_in_flight(&mut self) -> Vec<InternalPacket> {
+        self.bytes_in_flight = 0;
+        self.datagrams.drain().flat_map(|(_, datagram)| datagram.packets).collect()
     }
 
     pub fn datagrams_in_flight(&self) -> usize {
         self.datagrams.len()
     }
+
+    /// Returns the number of bytes of the sent datagrams that have not been acknowledged.
+    pub fn bytes_in_flight(&self) -> usize {
+        self.bytes_in_flight
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::{net::SocketAddr, time::{Duration, Instant}};   
+    use std::{net::SocketAddr, sync::Arc, time::{Duration, Instant}};   
     use crossbeam_channel::{Sender, Receiver, unbounded};
     use crate::{
+        SystemClock,
         communicator::Communicator,
         config::Config,
+        datagram_range::DatagramRange,
+        datagram_range_list::DatagramRangeList,
         internal_packet::{InternalOrdering, InternalPacket, InternalReliability}, 
         number::{DatagramSequenceNumber, MessageNumber},
         packet_datagram::PacketDatagram,
@@ -146,13 +242,13 @@ mod tests {
         let local_addr = "127.0.0.2:19132".parse::<SocketAddr>().expect("Could not create address");
         let remote_addr =  "127.0.0.1:19132".parse::<SocketAddr>().expect("Could not create address");
         let remote_guid = 0x112233;
-        let handler = AcknowledgeHandler::new(remote_addr, remote_guid);
+        let handler = AcknowledgeHandler::new(remote_addr, remote_guid, 1492);
         let fake_socket = FakeDatagramSocket::new(local_addr);
         let datagram_sender = fake_socket.get_datagram_sender();
         let datagram_receiver = fake_socket.get_datagram_receiver();
         let config = Config::default();
         let (event_sender, event_receiver) = unbounded();
-        let communicator = Communicator::new(fake_socket, config, event_sender);
+        let communicator = Communicator::new(fake_socket, config, event_sender, Arc::new(SystemClock));
         (handler, communicator, datagram_sender, datagram_receiver, event_receiver, remote_addr)
     }
 
@@ -246,4 +342,50 @@ mod tests {
         assert_eq!(packets, vec![packet1, packet2, packet3]);
     }
 
+    #[test]
+    fn has_room_for_datagram_limited_by_congestion_window() {
+        // Arrange
+        let (mut handler, mut communicator, mut _datagram_sender, mut _datagram_receiver, mut _event_receiver, _remote_addr) = test_setup();
+        let time = Instant::now();
+        let mut buf = Vec::new();
+        for number in 0..2 {
+            let mut datagram = PacketDatagram::new(DatagramSequenceNumber::from_masked_u32(number));
+            datagram.push(InternalPacket::new(time, InternalReliability::Unreliable, InternalOrdering::None, None, None, vec![0; 1000].into_boxed_slice()));
+            handler.process_outgoing_datagram(datagram, time, &mut buf).expect("Could not process datagram");
+        }
+        let mut ack_list = DatagramRangeList::new();
+        ack_list.push(DatagramRange::new(DatagramSequenceNumber::ZERO, DatagramSequenceNumber::ONE));
+
+        // Act
+        let has_room_before_ack = handler.has_room_for_datagram(time);
+        handler.process_incoming_ack(time, ack_list, &mut communicator);
+        let has_room_after_ack = handler.has_room_for_datagram(time);
+
+        // Assert
+        assert!(!has_room_before_ack);
+        assert!(has_room_after_ack);
+    }
+
+    #[test]
+    fn retransmission_timeout_is_calculated_from_acked_datagrams() {
+        // Arrange
+        let (mut handler, mut communicator, mut _datagram_sender, mut _datagram_receiver, mut _event_receiver, _remote_addr) = test_setup();
+        let time = Instant::now();
+        let mut buf = Vec::new();
+        let mut datagram = PacketDatagram::new(DatagramSequenceNumber::ZERO);
+        datagram.push(InternalPacket::new(time, InternalReliability::Unreliable, InternalOrdering::None, None, None, vec![0].into_boxed_slice()));
+        handler.process_outgoing_datagram(datagram, time, &mut buf).expect("Could not process datagram");
+        let mut ack_list = DatagramRangeList::new();
+        ack_list.push(DatagramRange::new(DatagramSequenceNumber::ZERO, DatagramSequenceNumber::ZERO));
+
+        // Act
+        let timeout_before_ack = handler.retransmission_timeout();
+        handler.process_incoming_ack(time + Duration::from_millis(200), ack_list, &mut communicator);
+        let timeout_after_ack = handler.retransmission_timeout();
+
+        // Assert
+        assert_eq!(Duration::from_millis(1000), timeout_before_ack);
+        assert_eq!(Duration::from_millis(600), timeout_after_ack);
+    }
+
 }
\ No newline at end of file
@@ -0,0 +1,36 @@
+use std::net::SocketAddr;
+
+/// The decision of an `AdmissionPolicy` about a connection request.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Admission {
+    /// The connection request is handled as usual.
+    Accept,
+    /// The connection request is answered with `MessageId::ConnectionBanned`
+    /// so that the remote peer stops retrying.
+    Reject,
+    /// The connection request is ignored without a reply, which hides
+    /// the peer from the remote peer.
+    Ignore,
+}
+
+/// Decides which remote peers may connect to a peer, e.g. based on the country or
+/// autonomous system of their IP addresses looked up in a GeoIP database,
+/// see `Peer::set_admission_policy`.
+///
+/// The policy is called on the processing thread for each Open Connection Request 2 that
+/// has passed the cookie, security and allow list checks, which means that the source
+/// address has been verified if cookies are used. A remote peer resends its request until
+/// it gets a reply, so the policy may be called more than once for the same remote peer
+/// and should return quickly, e.g. by looking up addresses in memory.
+///
+/// It is implemented for all functions taking the address and GUID of the remote peer.
+pub trait AdmissionPolicy: Send {
+    /// Returns whether the remote peer with the address and GUID may connect.
+    fn admit(&mut self, addr: SocketAddr, guid: u64) -> Admission;
+}
+
+impl<F: FnMut(SocketAddr, u64) -> Admission + Send> AdmissionPolicy for F {
+    fn admit(&mut self, addr: SocketAddr, guid: u64) -> Admission {
+        self(addr, guid)
+    }
+}
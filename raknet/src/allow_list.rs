@@ -0,0 +1,84 @@
+use std::net::IpAddr;
+
+use crate::ban_list::IpRange;
+
+/// The IP address ranges and GUIDs of the remote peers that may connect to a peer
+/// in allow-list mode, see `Config::allow_list`.
+///
+/// The GUID is sent by the remote peer in its connection request and can be chosen
+/// freely by it, so allowing GUIDs only keeps out peers that do not know an allowed GUID.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default))]
+pub struct AllowList {
+    /// The IP address ranges that may connect, e.g. `10.0.0.0/8`.
+    pub ranges: Vec<IpRange>,
+    /// The GUIDs of the remote peers that may connect from any address.
+    pub guids: Vec<u64>,
+}
+
+impl AllowList {
+    /// Creates an empty allow list that keeps out all remote peers.
+    pub fn new() -> Self {
+        AllowList::default()
+    }
+
+    /// Adds a range of IP addresses unless it is already in the list.
+    pub fn allow_range(&mut self, range: IpRange) {
+        if !self.ranges.contains(&range) {
+            self.ranges.push(range);
+        }
+    }
+
+    /// Adds a GUID unless it is already in the list.
+    pub fn allow_guid(&mut self, guid: u64) {
+        if !self.guids.contains(&guid) {
+            self.guids.push(guid);
+        }
+    }
+
+    /// Removes a range of IP addresses. Only exactly the same range is removed.
+    /// Returns false if the range was not in the list.
+    pub fn remove_range(&mut self, range: IpRange) -> bool {
+        let range_count = self.ranges.len();
+        self.ranges.retain(|allowed_range| *allowed_range != range);
+        self.ranges.len() != range_count
+    }
+
+    /// Removes a GUID. Returns false if the GUID was not in the list.
+    pub fn remove_guid(&mut self, guid: u64) -> bool {
+        let guid_count = self.guids.len();
+        self.guids.retain(|allowed_guid| *allowed_guid != guid);
+        self.guids.len() != guid_count
+    }
+
+    /// Returns true if the address is within an allowed range or the GUID is allowed.
+    pub fn is_allowed(&self, addr: IpAddr, guid: u64) -> bool {
+        self.guids.contains(&guid) || self.ranges.iter().any(|range| range.contains(addr))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+    use super::AllowList;
+
+    fn ip(addr: &str) -> IpAddr {
+        addr.parse().expect("Could not create address")
+    }
+
+    #[test]
+    fn address_in_range_or_guid_is_allowed() {
+        // Arrange
+        let mut allow_list = AllowList::new();
+        allow_list.allow_range("10.0.0.0/8".parse().expect("Could not create range"));
+        allow_list.allow_guid(42);
+
+        // Act/Assert
+        assert!(allow_list.is_allowed(ip("10.1.2.3"), 1));
+        assert!(allow_list.is_allowed(ip("192.168.0.1"), 42));
+        assert!(!allow_list.is_allowed(ip("192.168.0.1"), 1));
+        assert!(allow_list.remove_guid(42));
+        assert!(!allow_list.remove_guid(42));
+        assert!(!allow_list.is_allowed(ip("192.168.0.1"), 42));
+    }
+}
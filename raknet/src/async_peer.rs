@@ -0,0 +1,315 @@
+use std::{
+    net::SocketAddr,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_core::Stream;
+use futures_sink::Sink;
+use tokio::{
+    net::{lookup_host, ToSocketAddrs},
+    sync::{mpsc, oneshot},
+    task::JoinHandle,
+};
+
+use crate::{
+    Command,
+    Config,
+    ConnectionHandle,
+    ConnectOptions,
+    DatagramSocket,
+    Error,
+    Peer,
+    PeerEvent,
+    Result,
+    SendOptions,
+    SendTarget,
+    TokioUdpSocket,
+    logging::{error, info},
+};
+
+/// Requests sent from an `AsyncPeer` to its processing task.
+enum Request {
+    Connect { addrs: Vec<SocketAddr>, options: ConnectOptions, handle_sender: oneshot::Sender<Result<ConnectionHandle>> },
+    Send { addr: SocketAddr, options: SendOptions, payload: Vec<u8>, result_sender: oneshot::Sender<Result<()>> },
+    Command(Command),
+    Shutdown,
+}
+
+/// A peer that is processed by a tokio task instead of a dedicated thread.
+///
+/// The peer is created with `bind` or `bind_with_config`, which must be called from
+/// within a tokio runtime. Events are received with `next_event` or by using the
+/// `AsyncPeer` as a `Stream` of events. Packets are sent with `send` or by using the
+/// `AsyncPeer` as a `Sink` of packets. The task stops when `shutdown` is called
+/// or the `AsyncPeer` is dropped.
+pub struct AsyncPeer {
+    local_addr: SocketAddr,
+    request_sender: mpsc::UnboundedSender<Request>,
+    event_receiver: mpsc::UnboundedReceiver<PeerEvent>,
+    task: JoinHandle<()>,
+}
+
+impl AsyncPeer {
+    /// Creates an AsyncPeer with a default `Config` and binds it to
+    /// a UDP socket on the specified address.
+    pub async fn bind<A: ToSocketAddrs>(addr: A) -> Result<Self> {
+        Self::bind_with_config(addr, Config::default()).await
+    }
+
+    /// Creates an AsyncPeer with the specified `Config` and binds it to
+    /// a UDP socket on the specified address.
+    pub async fn bind_with_config<A: ToSocketAddrs>(addr: A, config: Config) -> Result<Self> {
+        info!("Binding socket");
+        let socket = TokioUdpSocket::bind(addr).await?;
+        let local_addr = socket.local_addr()?;
+        info!("Listening on {}", local_addr);
+
+        let peer = Peer::with_socket(socket, config)?;
+        let (request_sender, request_receiver) = mpsc::unbounded_channel();
+        let (event_sender, event_receiver) = mpsc::unbounded_channel();
+        let task = tokio::spawn(Self::process(peer, request_receiver, event_sender));
+        Ok(AsyncPeer {
+            local_addr,
+            request_sender,
+            event_receiver,
+            task,
+        })
+    }
+
+    /// Returns the local address of the socket.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Waits for the next incoming packet or connection event.
+    /// Returns None once the peer has stopped processing.
+    pub async fn next_event(&mut self) -> Option<PeerEvent> {
+        self.event_receiver.recv().await
+    }
+
+    /// Starts connecting to a remote peer at the specified address with the specified
+    /// options. A `PeerEvent::ConnectionEstablished` is received from `next_event`
+    /// once the connection has been established. See `Peer::connect` for details.
+    pub async fn connect<A: ToSocketAddrs>(&self, addr: A, options: ConnectOptions) -> Result<ConnectionHandle> {
+        let addrs: Vec<SocketAddr> = lookup_host(addr).await?.collect();
+        let (handle_sender, handle_receiver) = oneshot::channel();
+        self.request(Request::Connect { addrs, options, handle_sender })?;
+        handle_receiver.await.map_err(|_| Error::Stopped)?
+    }
+
+    /// Enqueues a packet for sending to the connected remote peer at the
+    /// specified address. See `Peer::send` for details.
+    pub async fn send(&self, addr: SocketAddr, options: SendOptions, payload: Vec<u8>) -> Result<()> {
+        let (result_sender, result_receiver) = oneshot::channel();
+        self.request(Request::Send { addr, options, payload, result_sender })?;
+        result_receiver.await.map_err(|_| Error::Stopped)?
+    }
+
+    /// Executes a command in the processing task, e.g. `Command::BanAddress`.
+    /// `Command::StopProcessing` stops the task without flushing queued packets.
+    pub fn command(&self, command: Command) -> Result<()> {
+        self.request(Request::Command(command))
+    }
+
+    /// Sends the queued packets, stops the processing task and waits for it to finish.
+    pub async fn shutdown(self) -> Result<()> {
+        // The task may already have stopped which is fine
+        let _ = self.request_sender.send(Request::Shutdown);
+        self.task.await.map_err(|err| Error::IoError(err.into()))
+    }
+
+    fn request(&self, request: Request) -> Result<()> {
+        self.request_sender.send(request).map_err(|_| Error::Stopped)
+    }
+
+    async fn process(mut peer: Peer<TokioUdpSocket>, mut request_receiver: mpsc::UnboundedReceiver<Request>, event_sender: mpsc::UnboundedSender<PeerEvent>) {
+        let socket = peer.socket();
+        let peer_events = peer.event_receiver();
+        if let Err(err) = socket.writable().await {
+            error!("Socket is not writable: {:?}", err);
+        }
+        loop {
+            peer.process();
+            for event in peer_events.try_iter() {
+                // The AsyncPeer may have been dropped which is fine
+                let _ = event_sender.send(event);
+            }
+            tokio::select! {
+                request = request_receiver.recv() => match request {
+                    Some(Request::Connect { addrs, options, handle_sender }) => {
+                        let _ = handle_sender.send(peer.connect_with_options(&addrs[..], options));
+                    },
+                    Some(Request::Send { addr, options, payload, result_sender }) => {
+                        let _ = result_sender.send(peer.send(addr, options, payload));
+                    },
+                    Some(Request::Command(command)) => {
+                        let _ = peer.command_sender().send(command);
+                        if !peer.execute_commands() {
+                            return;
+                        }
+                    },
+                    Some(Request::Shutdown) | None => {
+                        peer.flush();
+                        peer.process();
+                        return;
+                    },
+                },
+                _ = socket.readable() => {},
+                _ = tokio::time::sleep(peer.tick_interval()) => {},
+            }
+        }
+    }
+}
+
+impl Stream for AsyncPeer {
+    type Item = PeerEvent;
+
+    /// Polls for the next event like `next_event`.
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<PeerEvent>> {
+        self.event_receiver.poll_recv(cx)
+    }
+}
+
+/// Sends packets like `Command::Send`, i.e. packets that cannot be sent to the target
+/// are only logged since the processing task does not report back. Use `send` to get
+/// the result of each packet.
+impl Sink<(SendTarget, SendOptions, Vec<u8>)> for AsyncPeer {
+    type Error = Error;
+
+    /// Fails with `Error::Stopped` if the processing task has stopped.
+    fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+        if self.request_sender.is_closed() {
+            return Poll::Ready(Err(Error::Stopped));
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, (target, options, payload): (SendTarget, SendOptions, Vec<u8>)) -> Result<()> {
+        self.command(Command::Send { target, options, payload })
+    }
+
+    /// The packets are handed to the processing task in `start_send`, so there is nothing to flush.
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    /// The processing task keeps running until `shutdown` is called or the `AsyncPeer` is dropped.
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{future::poll_fn, pin::Pin, time::Duration};
+    use futures_core::Stream;
+    use futures_sink::Sink;
+    use crate::{ConnectOptions, Ordering, PeerEvent, Priority, Reliability, SendOptions, SendTarget};
+    use super::AsyncPeer;
+
+    async fn wait_for_event<F: Fn(&PeerEvent) -> bool>(peer: &mut AsyncPeer, predicate: F) -> PeerEvent {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let event = peer.next_event().await.expect("Peer stopped");
+                if predicate(&event) {
+                    return event;
+                }
+            }
+        }).await.expect("Event not received")
+    }
+
+    #[test]
+    fn connected_peers_exchange_packets() {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().expect("Could not create runtime");
+        runtime.block_on(async {
+            // Arrange
+            let mut server = AsyncPeer::bind("127.0.0.1:0").await.expect("Could not bind server");
+            let mut client = AsyncPeer::bind("127.0.0.1:0").await.expect("Could not bind client");
+            let server_addr = server.local_addr();
+            client.connect(server_addr, ConnectOptions::default()).await.expect("Could not connect");
+            wait_for_event(&mut client, |event| matches!(event, PeerEvent::ConnectionEstablished { .. })).await;
+
+            // Act
+            client.send(server_addr, SendOptions::new(Priority::High, Reliability::Reliable, Ordering::None), vec![0x86, 1, 2, 3]).await.expect("Could not send");
+            let event = wait_for_event(&mut server, |event| matches!(event, PeerEvent::Packet(_))).await;
+            client.shutdown().await.expect("Could not shut down client");
+            server.shutdown().await.expect("Could not shut down server");
+
+            // Assert
+            match event {
+                PeerEvent::Packet(packet) => assert_eq!(&[0x86, 1, 2, 3], packet.payload()),
+                _ => panic!("Unexpected event"),
+            }
+        });
+    }
+
+    #[test]
+    fn send_without_connection_fails() {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().expect("Could not create runtime");
+        runtime.block_on(async {
+            // Arrange
+            let peer = AsyncPeer::bind("127.0.0.1:0").await.expect("Could not bind peer");
+            let remote_addr = "127.0.0.1:19132".parse().expect("Could not create address");
+
+            // Act
+            let result = peer.send(remote_addr, SendOptions::new(Priority::High, Reliability::Reliable, Ordering::None), vec![0x86]).await;
+
+            // Assert
+            assert!(matches!(result, Err(crate::Error::NotConnected(addr)) if addr == remote_addr));
+        });
+    }
+
+    #[test]
+    fn events_are_received_as_stream() {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().expect("Could not create runtime");
+        runtime.block_on(async {
+            // Arrange
+            let mut server = AsyncPeer::bind("127.0.0.1:0").await.expect("Could not bind server");
+            let client = AsyncPeer::bind("127.0.0.1:0").await.expect("Could not bind client");
+            client.connect(server.local_addr(), ConnectOptions::default()).await.expect("Could not connect");
+
+            // Act
+            let event = tokio::time::timeout(Duration::from_secs(5), async {
+                loop {
+                    let event = poll_fn(|cx| Pin::new(&mut server).poll_next(cx)).await.expect("Peer stopped");
+                    if matches!(event, PeerEvent::IncomingConnection(_)) {
+                        return event;
+                    }
+                }
+            }).await;
+            client.shutdown().await.expect("Could not shut down client");
+            server.shutdown().await.expect("Could not shut down server");
+
+            // Assert
+            assert!(event.is_ok());
+        });
+    }
+
+    #[test]
+    fn packets_are_sent_through_sink() {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().expect("Could not create runtime");
+        runtime.block_on(async {
+            // Arrange
+            let mut server = AsyncPeer::bind("127.0.0.1:0").await.expect("Could not bind server");
+            let mut client = AsyncPeer::bind("127.0.0.1:0").await.expect("Could not bind client");
+            let server_addr = server.local_addr();
+            client.connect(server_addr, ConnectOptions::default()).await.expect("Could not connect");
+            wait_for_event(&mut client, |event| matches!(event, PeerEvent::ConnectionEstablished { .. })).await;
+
+            // Act
+            poll_fn(|cx| Pin::new(&mut client).poll_ready(cx)).await.expect("Sink is not ready");
+            Pin::new(&mut client).start_send((SendTarget::Addr(server_addr), SendOptions::new(Priority::High, Reliability::Reliable, Ordering::None), vec![0x86, 1, 2, 3])).expect("Could not send");
+            poll_fn(|cx| Pin::new(&mut client).poll_flush(cx)).await.expect("Could not flush");
+            let event = wait_for_event(&mut server, |event| matches!(event, PeerEvent::Packet(_))).await;
+            client.shutdown().await.expect("Could not shut down client");
+            server.shutdown().await.expect("Could not shut down server");
+
+            // Assert
+            match event {
+                PeerEvent::Packet(packet) => assert_eq!(&[0x86, 1, 2, 3], packet.payload()),
+                _ => panic!("Unexpected event"),
+            }
+        });
+    }
+}
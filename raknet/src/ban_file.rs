@@ -0,0 +1,169 @@
+use std::{fmt, fs, io, path::Path};
+
+use crate::ban_list::IpRange;
+
+/// A ban of a range of IP addresses in a `BanFile`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BanEntry {
+    /// The banned range of IP addresses.
+    pub range: IpRange,
+    /// The time the ban expires in seconds since the Unix epoch or None if the ban is permanent.
+    #[cfg_attr(feature = "serde", serde(default, skip_serializing_if = "Option::is_none"))]
+    pub expires_at: Option<u64>,
+}
+
+/// The bans of a peer, returned by `Peer::bans` and added with `Peer::add_bans`,
+/// that can be saved to a file so that they survive a restart or are shared between peers.
+///
+/// Files with the extension `json` or `toml` hold the entries with their expiry times,
+/// which requires the `serde` feature. Other files are IP lists with one address or range
+/// in CIDR notation per line, where anything after `#` or `;` is a comment, as used by
+/// most published block lists. All bans read from an IP list are permanent.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default))]
+pub struct BanFile {
+    /// The bans in the order they were made.
+    pub bans: Vec<BanEntry>,
+}
+
+impl BanFile {
+    /// Loads the bans from a JSON, TOML or IP list file depending on its extension.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<BanFile, BanFileError> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path).map_err(BanFileError::Io)?;
+        match path.extension().and_then(|extension| extension.to_str()) {
+            #[cfg(feature = "serde")]
+            Some("json") => serde_json::from_str(&contents).map_err(|err| BanFileError::Parse(err.to_string())),
+            #[cfg(feature = "serde")]
+            Some("toml") => toml::from_str(&contents).map_err(|err| BanFileError::Parse(err.to_string())),
+            #[cfg(not(feature = "serde"))]
+            Some(extension @ ("json" | "toml")) => Err(BanFileError::UnsupportedFormat(extension.to_string())),
+            _ => BanFile::from_ip_list(&contents),
+        }
+    }
+
+    /// Saves the bans to a JSON, TOML or IP list file depending on its extension.
+    /// The expiry times are lost when saving to an IP list.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), BanFileError> {
+        let path = path.as_ref();
+        let contents = match path.extension().and_then(|extension| extension.to_str()) {
+            #[cfg(feature = "serde")]
+            Some("json") => serde_json::to_string_pretty(self).map_err(|err| BanFileError::Parse(err.to_string()))?,
+            #[cfg(feature = "serde")]
+            Some("toml") => toml::to_string(self).map_err(|err| BanFileError::Parse(err.to_string()))?,
+            // Saving to an IP list instead would make temporary bans permanent
+            #[cfg(not(feature = "serde"))]
+            Some(extension @ ("json" | "toml")) => return Err(BanFileError::UnsupportedFormat(extension.to_string())),
+            _ => self.to_ip_list(),
+        };
+        fs::write(path, contents).map_err(BanFileError::Io)
+    }
+
+    /// Parses an IP list with one address or range per line into permanent bans.
+    pub fn from_ip_list(contents: &str) -> Result<BanFile, BanFileError> {
+        let mut bans = Vec::new();
+        for (index, line) in contents.lines().enumerate() {
+            let line = line.split(['#', ';']).next().unwrap_or_default().trim();
+            if let Some(range) = line.split_whitespace().next() {
+                let range = range.parse().map_err(|_| BanFileError::InvalidLine(index + 1))?;
+                bans.push(BanEntry { range, expires_at: None });
+            }
+        }
+        Ok(BanFile { bans })
+    }
+
+    /// Returns the banned ranges as an IP list with one range per line.
+    pub fn to_ip_list(&self) -> String {
+        self.bans.iter().map(|ban| format!("{}\n", ban.range)).collect()
+    }
+}
+
+/// The reason a `BanFile` could not be loaded or saved.
+#[derive(Debug)]
+pub enum BanFileError {
+    /// The file could not be read or written.
+    Io(io::Error),
+    /// The file is not valid TOML or JSON or has fields of the wrong type.
+    Parse(String),
+    /// The line with the number, starting at 1, of an IP list is not an address or range.
+    InvalidLine(usize),
+    /// The file has the extension `json` or `toml`, which requires the `serde` feature.
+    UnsupportedFormat(String),
+}
+
+impl std::error::Error for BanFileError {}
+
+impl fmt::Display for BanFileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BanFileError::Io(err) => write!(f, "Could not read or write the ban file: {}", err),
+            BanFileError::Parse(err) => write!(f, "Could not parse the ban file: {}", err),
+            BanFileError::InvalidLine(line) => write!(f, "Invalid IP address range on line {} of the ban file", line),
+            BanFileError::UnsupportedFormat(extension) => write!(f, "Ban files with the extension {} require the serde feature", extension),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BanFile, BanFileError};
+
+    #[test]
+    fn ip_list_is_parsed_without_comments() {
+        // Arrange
+        let contents = "# Block list\n10.0.0.0/8 ; SBL123\n\n192.168.0.1   # Single address\n2001:db8::/32\n";
+
+        // Act
+        let ban_file = BanFile::from_ip_list(contents).expect("Could not parse IP list");
+
+        // Assert
+        let ranges: Vec<String> = ban_file.bans.iter().map(|ban| ban.range.to_string()).collect();
+        assert_eq!(vec!["10.0.0.0/8", "192.168.0.1/32", "2001:db8::/32"], ranges);
+        assert!(ban_file.bans.iter().all(|ban| ban.expires_at.is_none()));
+        assert_eq!("10.0.0.0/8\n192.168.0.1/32\n2001:db8::/32\n", ban_file.to_ip_list());
+        assert!(matches!(BanFile::from_ip_list("10.0.0.1\nnot an address\n"), Err(BanFileError::InvalidLine(2))));
+    }
+
+    #[cfg(not(feature = "serde"))]
+    #[test]
+    fn json_and_toml_files_require_serde() {
+        // Arrange
+        let path = std::env::temp_dir().join(format!("raknet-bans-{}.json", std::process::id()));
+
+        // Act
+        let save_result = BanFile::default().save(&path);
+
+        // Assert
+        assert!(matches!(save_result, Err(BanFileError::UnsupportedFormat(ref extension)) if extension == "json"));
+        assert!(!path.exists());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn bans_are_saved_and_loaded_with_expiry_times() {
+        // Arrange
+        use super::BanEntry;
+        let ban_file = BanFile {
+            bans: vec![
+                BanEntry { range: "10.0.0.0/8".parse().expect("Could not create range"), expires_at: Some(1_700_000_000) },
+                BanEntry { range: "::1".parse().expect("Could not create range"), expires_at: None },
+            ],
+        };
+        let dir = std::env::temp_dir();
+        let toml_path = dir.join(format!("raknet-bans-{}.toml", std::process::id()));
+        let json_path = dir.join(format!("raknet-bans-{}.json", std::process::id()));
+
+        // Act
+        ban_file.save(&toml_path).expect("Could not save TOML file");
+        ban_file.save(&json_path).expect("Could not save JSON file");
+        let toml_ban_file = BanFile::from_path(&toml_path);
+        let json_ban_file = BanFile::from_path(&json_path);
+        std::fs::remove_file(&toml_path).expect("Could not remove file");
+        std::fs::remove_file(&json_path).expect("Could not remove file");
+
+        // Assert
+        assert_eq!(ban_file, toml_ban_file.expect("Could not load TOML file"));
+        assert_eq!(ban_file, json_ban_file.expect("Could not load JSON file"));
+    }
+}
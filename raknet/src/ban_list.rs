@@ -0,0 +1,218 @@
+use std::{
+    fmt,
+    net::IpAddr,
+    str::FromStr,
+    time::Instant,
+};
+
+/// A range of IP addresses in CIDR notation, e.g. `192.168.0.0/16`,
+/// or a single IP address, e.g. `192.168.0.1`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IpRange {
+    addr: IpAddr,
+    prefix_len: u8,
+}
+
+impl IpRange {
+    /// Creates a range with the addresses whose first `prefix_len` bits are the same as
+    /// those of `addr`. Returns None if `prefix_len` is longer than the address.
+    pub fn new(addr: IpAddr, prefix_len: u8) -> Option<Self> {
+        if prefix_len > Self::address_len(addr) {
+            return None;
+        }
+        Some(IpRange { addr, prefix_len })
+    }
+
+    /// Returns true if the address is within the range.
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        match (self.addr, addr) {
+            (IpAddr::V4(range_addr), IpAddr::V4(addr)) => {
+                Self::has_same_prefix(u32::from(range_addr).into(), u32::from(addr).into(), self.prefix_len, 32)
+            },
+            (IpAddr::V6(range_addr), IpAddr::V6(addr)) => {
+                Self::has_same_prefix(u128::from(range_addr), u128::from(addr), self.prefix_len, 128)
+            },
+            _ => false,
+        }
+    }
+
+    fn has_same_prefix(range_addr: u128, addr: u128, prefix_len: u8, address_len: u8) -> bool {
+        let shift = address_len - prefix_len;
+        shift >= 128 || (range_addr >> shift) == (addr >> shift)
+    }
+
+    fn address_len(addr: IpAddr) -> u8 {
+        if addr.is_ipv4() { 32 } else { 128 }
+    }
+}
+
+impl From<IpAddr> for IpRange {
+    fn from(addr: IpAddr) -> Self {
+        IpRange { addr, prefix_len: Self::address_len(addr) }
+    }
+}
+
+impl fmt::Display for IpRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+/// The error returned when a string is not a valid `IpRange`.
+#[derive(Debug, PartialEq)]
+pub struct InvalidIpRange;
+
+impl std::error::Error for InvalidIpRange {}
+
+impl fmt::Display for InvalidIpRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid IP address range")
+    }
+}
+
+impl FromStr for IpRange {
+    type Err = InvalidIpRange;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('/') {
+            Some((addr, prefix_len)) => {
+                let addr = addr.parse::<IpAddr>().map_err(|_| InvalidIpRange)?;
+                let prefix_len = prefix_len.parse::<u8>().map_err(|_| InvalidIpRange)?;
+                IpRange::new(addr, prefix_len).ok_or(InvalidIpRange)
+            },
+            None => s.parse::<IpAddr>().map(IpRange::from).map_err(|_| InvalidIpRange),
+        }
+    }
+}
+
+/// Serialized as a string in CIDR notation, e.g. `"192.168.0.0/16"`.
+#[cfg(feature = "serde")]
+impl serde::Serialize for IpRange {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for IpRange {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let range = String::deserialize(deserializer)?;
+        range.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// The IP address ranges that are banned, each with an optional expiry time.
+pub struct BanList {
+    bans: Vec<(IpRange, Option<Instant>)>,
+}
+
+impl BanList {
+    pub fn new() -> Self {
+        BanList {
+            bans: Vec::new(),
+        }
+    }
+
+    /// Bans a range until `expiry_time` or forever if `expiry_time` is None.
+    /// An existing ban of the same range is replaced.
+    pub fn ban(&mut self, range: IpRange, expiry_time: Option<Instant>) {
+        self.unban(range);
+        self.bans.push((range, expiry_time));
+    }
+
+    /// Removes the ban of the range. Returns false if the range was not banned.
+    /// Bans of other ranges that contain the same addresses are not affected.
+    pub fn unban(&mut self, range: IpRange) -> bool {
+        let ban_count = self.bans.len();
+        self.bans.retain(|(banned_range, _)| *banned_range != range);
+        self.bans.len() != ban_count
+    }
+
+    /// Returns true if the address is within a range that is banned at the specified time.
+    pub fn is_banned(&self, time: Instant, addr: IpAddr) -> bool {
+        self.bans.iter().any(|(range, expiry_time)|
+            range.contains(addr) && expiry_time.is_none_or(|expiry_time| time < expiry_time))
+    }
+
+    /// Returns the banned ranges with their expiry times in the order they were banned.
+    pub fn bans(&self) -> &[(IpRange, Option<Instant>)] {
+        &self.bans
+    }
+
+    /// Removes the bans that have expired.
+    pub fn remove_expired(&mut self, time: Instant) {
+        self.bans.retain(|(_, expiry_time)| expiry_time.is_none_or(|expiry_time| time < expiry_time));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::IpAddr, time::{Duration, Instant}};
+    use super::{BanList, InvalidIpRange, IpRange};
+
+    fn ip(addr: &str) -> IpAddr {
+        addr.parse().expect("Could not create address")
+    }
+
+    fn range(range: &str) -> IpRange {
+        range.parse().expect("Could not create range")
+    }
+
+    #[test]
+    fn ipv4_range_contains_addresses_with_same_prefix() {
+        // Act/Assert
+        assert!(range("192.168.0.0/16").contains(ip("192.168.255.1")));
+        assert!(!range("192.168.0.0/16").contains(ip("192.169.0.1")));
+        assert!(range("0.0.0.0/0").contains(ip("10.0.0.1")));
+        assert!(range("10.0.0.1").contains(ip("10.0.0.1")));
+        assert!(!range("10.0.0.1").contains(ip("10.0.0.2")));
+        assert!(!range("0.0.0.0/0").contains(ip("::1")));
+    }
+
+    #[test]
+    fn ipv6_range_contains_addresses_with_same_prefix() {
+        // Act/Assert
+        assert!(range("2001:db8::/32").contains(ip("2001:db8:1::1")));
+        assert!(!range("2001:db8::/32").contains(ip("2001:db9::1")));
+        assert!(range("::/0").contains(ip("::1")));
+    }
+
+    #[test]
+    fn invalid_ranges_are_not_parsed() {
+        // Act/Assert
+        assert_eq!(Err(InvalidIpRange), "192.168.0.0/33".parse::<IpRange>());
+        assert_eq!(Err(InvalidIpRange), "192.168.0/16".parse::<IpRange>());
+        assert_eq!(Err(InvalidIpRange), "192.168.0.0/".parse::<IpRange>());
+    }
+
+    #[test]
+    fn ban_expires() {
+        // Arrange
+        let mut ban_list = BanList::new();
+        let time = Instant::now();
+
+        // Act
+        ban_list.ban(range("10.0.0.0/8"), Some(time + Duration::from_secs(60)));
+
+        // Assert
+        assert!(ban_list.is_banned(time, ip("10.1.2.3")));
+        assert!(!ban_list.is_banned(time, ip("11.1.2.3")));
+        assert!(!ban_list.is_banned(time + Duration::from_secs(60), ip("10.1.2.3")));
+    }
+
+    #[test]
+    fn unban_removes_ban() {
+        // Arrange
+        let mut ban_list = BanList::new();
+        let time = Instant::now();
+        ban_list.ban(range("10.0.0.1"), None);
+
+        // Act
+        let was_banned = ban_list.unban(range("10.0.0.1"));
+
+        // Assert
+        assert!(was_banned);
+        assert!(!ban_list.is_banned(time, ip("10.0.0.1")));
+        assert!(!ban_list.unban(range("10.0.0.1")));
+    }
+}
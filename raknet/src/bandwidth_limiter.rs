@@ -0,0 +1,127 @@
+use std::{collections::VecDeque, time::{Duration, Instant}};
+
+use crate::constants::MAXIMUM_MTU_SIZE;
+
+/// Limits the number of bytes sent per second and measures the current send rate.
+///
+/// The limit works like a token bucket that is refilled at the maximum rate and
+/// that holds at most a tenth of a second worth of bytes (but at least one datagram)
+/// so that short bursts are allowed.
+pub struct BandwidthLimiter {
+    max_bytes_per_second: u64,
+    available_bytes: f64,
+    last_send_time: Option<Instant>,
+    sent_datagrams: VecDeque<(Instant, usize)>,
+    bytes_sent_in_last_second: usize,
+}
+
+impl BandwidthLimiter {
+    /// Creates a limiter with the specified maximum number of bytes
+    /// per second. If the maximum is 0 the bandwidth is unlimited.
+    pub fn new(max_bytes_per_second: u64) -> Self {
+        BandwidthLimiter {
+            max_bytes_per_second,
+            available_bytes: 0.0,
+            last_send_time: None,
+            sent_datagrams: VecDeque::new(),
+            bytes_sent_in_last_second: 0,
+        }
+    }
+
+    /// Sets the maximum number of bytes per second. If the maximum is 0 the bandwidth is unlimited.
+    pub fn set_max_bytes_per_second(&mut self, max_bytes_per_second: u64) {
+        self.max_bytes_per_second = max_bytes_per_second;
+    }
+
+    /// Returns true if a datagram may be sent at the specified time.
+    pub fn has_room_for_datagram(&self, time: Instant) -> bool {
+        self.max_bytes_per_second == 0 || self.available_bytes(time) > 0.0
+    }
+
+    /// Registers that a datagram of `size` bytes has been sent.
+    pub fn on_datagram_sent(&mut self, time: Instant, size: usize) {
+        if self.max_bytes_per_second != 0 {
+            self.available_bytes = self.available_bytes(time) - size as f64;
+            self.last_send_time = Some(time);
+        }
+        self.remove_old_datagrams(time);
+        self.sent_datagrams.push_back((time, size));
+        self.bytes_sent_in_last_second += size;
+    }
+
+    /// Returns the number of bytes sent during the last second.
+    pub fn bytes_per_second(&mut self, time: Instant) -> u64 {
+        self.remove_old_datagrams(time);
+        self.bytes_sent_in_last_second as u64
+    }
+
+    fn available_bytes(&self, time: Instant) -> f64 {
+        let max_available_bytes = (self.max_bytes_per_second as f64 / 10.0).max(MAXIMUM_MTU_SIZE as f64);
+        match self.last_send_time {
+            Some(last_send_time) => {
+                let elapsed = time.saturating_duration_since(last_send_time).as_secs_f64();
+                (self.available_bytes + self.max_bytes_per_second as f64 * elapsed).min(max_available_bytes)
+            },
+            None => max_available_bytes,
+        }
+    }
+
+    fn remove_old_datagrams(&mut self, time: Instant) {
+        while let Some((send_time, size)) = self.sent_datagrams.front() {
+            if time.saturating_duration_since(*send_time) < Duration::from_secs(1) {
+                break;
+            }
+            self.bytes_sent_in_last_second -= size;
+            self.sent_datagrams.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+    use super::BandwidthLimiter;
+
+    #[test]
+    fn unlimited_always_has_room() {
+        // Arrange
+        let time = Instant::now();
+        let mut limiter = BandwidthLimiter::new(0);
+
+        // Act
+        limiter.on_datagram_sent(time, 1_000_000);
+
+        // Assert
+        assert!(limiter.has_room_for_datagram(time));
+    }
+
+    #[test]
+    fn has_room_for_datagram_after_refill() {
+        // Arrange
+        let time = Instant::now();
+        let mut limiter = BandwidthLimiter::new(100_000);
+        limiter.on_datagram_sent(time, 11_000);
+
+        // Act/Assert
+        assert!(!limiter.has_room_for_datagram(time));
+        assert!(!limiter.has_room_for_datagram(time + Duration::from_millis(9)));
+        assert!(limiter.has_room_for_datagram(time + Duration::from_millis(11)));
+    }
+
+    #[test]
+    fn bytes_per_second_counts_last_second() {
+        // Arrange
+        let time = Instant::now();
+        let mut limiter = BandwidthLimiter::new(0);
+        limiter.on_datagram_sent(time, 1000);
+        limiter.on_datagram_sent(time + Duration::from_millis(500), 2000);
+
+        // Act
+        let bytes_per_second_before = limiter.bytes_per_second(time + Duration::from_millis(999));
+        let bytes_per_second_after = limiter.bytes_per_second(time + Duration::from_millis(1000));
+
+        // Assert
+        assert_eq!(3000, bytes_per_second_before);
+        assert_eq!(2000, bytes_per_second_after);
+    }
+}
@@ -0,0 +1,316 @@
+use std::{
+    collections::VecDeque,
+    io,
+    mem,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6, ToSocketAddrs, UdpSocket},
+    os::unix::io::AsRawFd,
+    ptr,
+};
+
+use socket2::SockRef;
+
+use crate::{constants::MAXIMUM_MTU_SIZE, logging::{debug, error}, socket::DatagramSocket, socket_options, SocketOptions};
+
+/// The maximum number of datagrams read or written with one system call.
+const BATCH_SIZE: usize = 64;
+
+/// A UDP socket that receives datagrams with `recvmmsg` and sends them with `sendmmsg`
+/// so that a busy peer makes one system call per batch instead of one per datagram.
+///
+/// Outgoing datagrams are queued until `flush` is called at the end of each processing
+/// round or until a whole batch has been queued. While the socket cannot send without
+/// blocking at most one batch stays queued and `send_datagram` fails with `WouldBlock`.
+pub struct BatchedUdpSocket {
+    socket: UdpSocket,
+    receive_buffers: Vec<[u8; MAXIMUM_MTU_SIZE as usize]>,
+    /// Index into `receive_buffers`, length, sender and whether the datagram was truncated
+    /// for each received datagram not yet read.
+    received: VecDeque<(usize, usize, SocketAddr, bool)>,
+    outgoing: Vec<(Vec<u8>, SocketAddr)>,
+}
+
+impl BatchedUdpSocket {
+    /// Wraps a UDP socket and makes it non-blocking.
+    pub fn new(socket: UdpSocket) -> io::Result<Self> {
+        socket.set_nonblocking(true)?;
+        Ok(BatchedUdpSocket {
+            socket,
+            receive_buffers: vec![[0u8; MAXIMUM_MTU_SIZE as usize]; BATCH_SIZE],
+            received: VecDeque::with_capacity(BATCH_SIZE),
+            outgoing: Vec::with_capacity(BATCH_SIZE),
+        })
+    }
+
+    /// Binds a UDP socket to the specified address.
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_broadcast(true)?;
+        Self::new(socket)
+    }
+
+    /// Reads up to a batch of waiting datagrams into the receive buffers.
+    fn receive_batch(&mut self) -> io::Result<()> {
+        // SAFETY: The headers only point into `receive_buffers`, `iovecs` and `addrs`,
+        // which all outlive the call and have the lengths given to the kernel.
+        let mut addrs: [libc::sockaddr_storage; BATCH_SIZE] = unsafe { mem::zeroed() };
+        let mut iovecs: Vec<libc::iovec> = self.receive_buffers.iter_mut()
+            .map(|buffer| libc::iovec { iov_base: buffer.as_mut_ptr() as *mut libc::c_void, iov_len: buffer.len() })
+            .collect();
+        let mut headers: Vec<libc::mmsghdr> = iovecs.iter_mut().zip(addrs.iter_mut())
+            .map(|(iovec, addr)| {
+                let mut header: libc::mmsghdr = unsafe { mem::zeroed() };
+                header.msg_hdr.msg_name = addr as *mut libc::sockaddr_storage as *mut libc::c_void;
+                header.msg_hdr.msg_namelen = mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+                header.msg_hdr.msg_iov = iovec;
+                header.msg_hdr.msg_iovlen = 1;
+                header
+            })
+            .collect();
+        let count = unsafe {
+            libc::recvmmsg(self.socket.as_raw_fd(), headers.as_mut_ptr(), BATCH_SIZE as libc::c_uint, libc::MSG_DONTWAIT, ptr::null_mut())
+        };
+        if count < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        for (index, header) in headers.iter().enumerate().take(count as usize) {
+            if let Some(addr) = to_socket_addr(&addrs[index]) {
+                let is_truncated = header.msg_hdr.msg_flags & libc::MSG_TRUNC != 0;
+                self.received.push_back((index, header.msg_len as usize, addr, is_truncated));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl DatagramSocket for BatchedUdpSocket {
+    fn receive_datagram<'a>(&mut self, buf: &'a mut [u8]) -> io::Result<(&'a [u8], SocketAddr)> {
+        if self.received.is_empty() {
+            self.receive_batch()?;
+        }
+        match self.received.pop_front() {
+            Some((_, len, addr, is_truncated)) if is_truncated || len > buf.len() =>
+                Err(io::Error::new(io::ErrorKind::InvalidData, format!("Datagram from {} was larger than the receive buffer", addr))),
+            Some((index, len, addr, _)) => {
+                buf[..len].copy_from_slice(&self.receive_buffers[index][..len]);
+                Ok((&buf[..len], addr))
+            },
+            None => Err(io::ErrorKind::WouldBlock.into()),
+        }
+    }
+
+    fn send_datagram(&mut self, payload: &[u8], addr: SocketAddr) -> io::Result<usize> {
+        if self.outgoing.len() >= BATCH_SIZE {
+            // The errors belong to datagrams that were already accepted, so they are not returned here
+            if let Err(err) = self.flush() {
+                debug!("Failed sending queued datagrams: {:?}", err);
+            }
+            if self.outgoing.len() >= BATCH_SIZE {
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+        }
+        self.outgoing.push((payload.to_vec(), addr));
+        Ok(payload.len())
+    }
+
+    /// Sends the queued datagrams. A datagram that cannot be sent is dropped, as it would
+    /// be by the network, and the following datagrams are still sent. Returns the first error.
+    ///
+    /// If the socket cannot send without blocking, the unsent datagrams stay queued
+    /// for the next flush and an error of kind `WouldBlock` is returned.
+    fn flush(&mut self) -> io::Result<()> {
+        let mut first_error = None;
+        let mut sent = 0;
+        while sent < self.outgoing.len() {
+            let batch = &mut self.outgoing[sent..];
+            // SAFETY: The headers only point into `batch`, `iovecs` and `addrs`,
+            // which all outlive the call and have the lengths given to the kernel.
+            let mut addrs: Vec<(libc::sockaddr_storage, libc::socklen_t)> = batch.iter().map(|(_, addr)| from_socket_addr(addr)).collect();
+            let mut iovecs: Vec<libc::iovec> = batch.iter_mut()
+                .map(|(payload, _)| libc::iovec { iov_base: payload.as_mut_ptr() as *mut libc::c_void, iov_len: payload.len() })
+                .collect();
+            let mut headers: Vec<libc::mmsghdr> = iovecs.iter_mut().zip(addrs.iter_mut())
+                .map(|(iovec, (addr, addr_len))| {
+                    let mut header: libc::mmsghdr = unsafe { mem::zeroed() };
+                    header.msg_hdr.msg_name = addr as *mut libc::sockaddr_storage as *mut libc::c_void;
+                    header.msg_hdr.msg_namelen = *addr_len;
+                    header.msg_hdr.msg_iov = iovec;
+                    header.msg_hdr.msg_iovlen = 1;
+                    header
+                })
+                .collect();
+            let count = unsafe {
+                libc::sendmmsg(self.socket.as_raw_fd(), headers.as_mut_ptr(), headers.len() as libc::c_uint, libc::MSG_DONTWAIT)
+            };
+            if count < 0 {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::WouldBlock {
+                    self.outgoing.drain(..sent);
+                    return Err(first_error.unwrap_or(err));
+                }
+                // The first datagram of the batch failed, drop it and continue with the next
+                debug!("Dropping datagram to {}: {:?}", self.outgoing[sent].1, err);
+                first_error.get_or_insert(err);
+                sent += 1;
+                continue;
+            }
+            sent += count as usize;
+        }
+        self.outgoing.clear();
+        match first_error {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    fn set_options(&mut self, options: &SocketOptions) -> io::Result<()> {
+        socket_options::apply(SockRef::from(&self.socket), options)
+    }
+}
+
+impl Drop for BatchedUdpSocket {
+    fn drop(&mut self) {
+        if let Err(err) = self.flush() {
+            error!("Failed sending queued datagrams: {:?}", err);
+        }
+    }
+}
+
+fn to_socket_addr(addr: &libc::sockaddr_storage) -> Option<SocketAddr> {
+    match addr.ss_family as libc::c_int {
+        libc::AF_INET => {
+            // SAFETY: The family says that the storage holds a sockaddr_in
+            let addr = unsafe { &*(addr as *const libc::sockaddr_storage as *const libc::sockaddr_in) };
+            let ip = Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr));
+            Some(SocketAddr::V4(SocketAddrV4::new(ip, u16::from_be(addr.sin_port))))
+        },
+        libc::AF_INET6 => {
+            // SAFETY: The family says that the storage holds a sockaddr_in6
+            let addr = unsafe { &*(addr as *const libc::sockaddr_storage as *const libc::sockaddr_in6) };
+            let ip = Ipv6Addr::from(addr.sin6_addr.s6_addr);
+            Some(SocketAddr::V6(SocketAddrV6::new(ip, u16::from_be(addr.sin6_port), addr.sin6_flowinfo, addr.sin6_scope_id)))
+        },
+        _ => None,
+    }
+}
+
+fn from_socket_addr(addr: &SocketAddr) -> (libc::sockaddr_storage, libc::socklen_t) {
+    // SAFETY: An all-zero sockaddr_storage is valid and is large enough for both address families
+    let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+    let len = match addr {
+        SocketAddr::V4(addr) => {
+            let sockaddr = unsafe { &mut *(&mut storage as *mut libc::sockaddr_storage as *mut libc::sockaddr_in) };
+            sockaddr.sin_family = libc::AF_INET as libc::sa_family_t;
+            sockaddr.sin_port = addr.port().to_be();
+            sockaddr.sin_addr.s_addr = u32::from(*addr.ip()).to_be();
+            mem::size_of::<libc::sockaddr_in>()
+        },
+        SocketAddr::V6(addr) => {
+            let sockaddr = unsafe { &mut *(&mut storage as *mut libc::sockaddr_storage as *mut libc::sockaddr_in6) };
+            sockaddr.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+            sockaddr.sin6_port = addr.port().to_be();
+            sockaddr.sin6_addr.s6_addr = addr.ip().octets();
+            sockaddr.sin6_flowinfo = addr.flowinfo();
+            sockaddr.sin6_scope_id = addr.scope_id();
+            mem::size_of::<libc::sockaddr_in6>()
+        },
+    };
+    (storage, len as libc::socklen_t)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io, net::UdpSocket, thread, time::Duration};
+    use crate::socket::DatagramSocket;
+    use super::{BatchedUdpSocket, BATCH_SIZE};
+
+    #[test]
+    fn batch_of_datagrams_is_sent_and_received() {
+        // Arrange
+        let mut socket = BatchedUdpSocket::bind("127.0.0.1:0").expect("Could not bind socket");
+        let mut other_socket = BatchedUdpSocket::bind("127.0.0.1:0").expect("Could not bind socket");
+        let addr = socket.local_addr().expect("Could not get address");
+        let other_addr = other_socket.local_addr().expect("Could not get address");
+        for n in 0..3u8 {
+            other_socket.send_datagram(&[n, 1, 2], addr).expect("Could not send datagram");
+        }
+        let mut buf = [0u8; 16];
+        let result_before_flush = socket.receive_datagram(&mut buf).map(|(payload, addr)| (payload.to_vec(), addr));
+
+        // Act
+        other_socket.flush().expect("Could not flush");
+        thread::sleep(Duration::from_millis(10));
+        let received: Vec<(Vec<u8>, _)> = (0..3)
+            .map(|_| socket.receive_datagram(&mut buf).map(|(payload, addr)| (payload.to_vec(), addr)).expect("Datagram not received"))
+            .collect();
+
+        // Assert
+        assert_eq!(io::ErrorKind::WouldBlock, result_before_flush.expect_err("Received datagram").kind());
+        assert_eq!(vec![(vec![0, 1, 2], other_addr), (vec![1, 1, 2], other_addr), (vec![2, 1, 2], other_addr)], received);
+        assert_eq!(io::ErrorKind::WouldBlock, socket.receive_datagram(&mut buf).map(|_| ()).expect_err("Received datagram").kind());
+    }
+
+    #[test]
+    fn failed_datagram_does_not_drop_the_rest_of_the_batch() {
+        // Arrange
+        let mut socket = BatchedUdpSocket::bind("127.0.0.1:0").expect("Could not bind socket");
+        let mut other_socket = BatchedUdpSocket::bind("127.0.0.1:0").expect("Could not bind socket");
+        let addr = socket.local_addr().expect("Could not get address");
+        let unreachable_addr = "[::1]:19132".parse().expect("Could not create address");
+        other_socket.send_datagram(&[0], addr).expect("Could not send datagram");
+        other_socket.send_datagram(&[1], unreachable_addr).expect("Could not send datagram");
+        other_socket.send_datagram(&[2], addr).expect("Could not send datagram");
+
+        // Act
+        let result = other_socket.flush();
+        thread::sleep(Duration::from_millis(10));
+        let mut buf = [0u8; 16];
+        let received: Vec<Vec<u8>> = (0..2)
+            .map(|_| socket.receive_datagram(&mut buf).map(|(payload, _)| payload.to_vec()).expect("Datagram not received"))
+            .collect();
+
+        // Assert
+        assert!(result.is_err());
+        assert_eq!(vec![vec![0], vec![2]], received);
+    }
+
+    #[test]
+    fn send_datagram_does_not_report_errors_of_queued_datagrams() {
+        // Arrange
+        let mut socket = BatchedUdpSocket::bind("127.0.0.1:0").expect("Could not bind socket");
+        let unreachable_addr = "[::1]:19132".parse().expect("Could not create address");
+        let addr = "127.0.0.1:19132".parse().expect("Could not create address");
+
+        // Act
+        let results: Vec<io::Result<usize>> = (0..BATCH_SIZE + 1)
+            .map(|n| socket.send_datagram(&[0], if n < BATCH_SIZE { unreachable_addr } else { addr }))
+            .collect();
+
+        // Assert
+        assert!(results.iter().all(|result| matches!(result, Ok(1))));
+        assert_eq!(1, socket.outgoing.len());
+    }
+
+    #[test]
+    fn truncated_datagram_fails() {
+        // Arrange
+        let mut socket = BatchedUdpSocket::bind("127.0.0.1:0").expect("Could not bind socket");
+        let other_socket = UdpSocket::bind("127.0.0.1:0").expect("Could not bind socket");
+        let addr = socket.local_addr().expect("Could not get address");
+        other_socket.send_to(&[0xFF; 2000], addr).expect("Could not send datagram");
+        other_socket.send_to(&[1, 2, 3], addr).expect("Could not send datagram");
+        thread::sleep(Duration::from_millis(10));
+        let mut buf = [0u8; 2048];
+
+        // Act
+        let truncated_result = socket.receive_datagram(&mut buf).map(|_| ());
+        let result = socket.receive_datagram(&mut buf).map(|(payload, _)| payload.to_vec());
+
+        // Assert
+        assert_eq!(io::ErrorKind::InvalidData, truncated_result.expect_err("Truncated datagram was received").kind());
+        assert_eq!(vec![1, 2, 3], result.expect("Datagram not received"));
+    }
+}
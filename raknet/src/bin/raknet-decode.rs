@@ -0,0 +1,48 @@
+//! Decodes hex dumps of RakNet datagrams, e.g. copied from Wireshark or a log.
+//!
+//! Usage: `raknet-decode [FILE]...`
+//!
+//! Every line of the files (or stdin if no file or `-` is given) is one datagram
+//! written as hex digits, optionally separated by whitespace or commas and prefixed
+//! with `0x`. Lines starting with `#` are ignored.
+
+use std::{env, fs, io::{self, Read}, process};
+
+use raknet::decode;
+
+fn main() {
+    let paths: Vec<String> = env::args().skip(1).collect();
+    let inputs = if paths.is_empty() { vec!["-".to_string()] } else { paths };
+    let mut has_errors = false;
+    for path in inputs {
+        let input = match read_input(&path) {
+            Ok(input) => input,
+            Err(err) => {
+                eprintln!("Could not read {}: {}", path, err);
+                process::exit(2);
+            },
+        };
+        for (line_number, datagram) in decode::parse_hex_dump(&input) {
+            match datagram {
+                Ok(datagram) => println!("{}", decode::explain(&datagram)),
+                Err(line) => {
+                    eprintln!("{}:{}: Invalid hex dump: {}", path, line_number, line);
+                    has_errors = true;
+                },
+            }
+        }
+    }
+    if has_errors {
+        process::exit(1);
+    }
+}
+
+fn read_input(path: &str) -> io::Result<String> {
+    if path == "-" {
+        let mut input = String::new();
+        io::stdin().read_to_string(&mut input)?;
+        Ok(input)
+    } else {
+        fs::read_to_string(path)
+    }
+}
@@ -0,0 +1,242 @@
+use std::io::{self, Write};
+
+use crate::{error::{ReadError, Result}, reader::DataRead};
+
+/// Reads bit-packed data as written by the BitStream of the original RakNet, where
+/// fields such as boolean flags take a single bit and the bits of each byte are read
+/// from the most significant bit.
+///
+/// The other data types of `DataRead` can be read at any bit position. Use
+/// `align_to_byte` before fields that the original RakNet reads aligned.
+pub struct BitReader<'a> {
+    data: &'a [u8],
+    bit_position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(data: &'a [u8]) -> BitReader<'a> {
+        BitReader {
+            data,
+            bit_position: 0,
+        }
+    }
+
+    /// Reads a single bit, e.g. a compressed boolean flag.
+    pub fn read_bit(&mut self) -> Result<bool> {
+        if self.remaining_bits() == 0 {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        let byte = self.data[self.bit_position / 8];
+        let bit = byte & (0x80 >> (self.bit_position % 8)) != 0;
+        self.bit_position += 1;
+        Ok(bit)
+    }
+
+    /// Reads `count` bits, at most 64, most significant bit first and returns them
+    /// as the lowest bits of the value.
+    pub fn read_bits(&mut self, count: usize) -> Result<u64> {
+        assert!(count <= 64, "Can not read more than 64 bits at a time");
+        if count > self.remaining_bits() {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        let mut value = 0u64;
+        for _ in 0..count {
+            value = (value << 1) | self.read_bit()? as u64;
+        }
+        Ok(value)
+    }
+
+    /// Skips the remaining bits of the current byte so that the next read starts at a byte boundary.
+    pub fn align_to_byte(&mut self) {
+        self.bit_position = self.bit_position.div_ceil(8) * 8;
+        self.bit_position = self.bit_position.min(self.data.len() * 8);
+    }
+
+    /// Returns the number of bits that are left to read.
+    pub fn remaining_bits(&self) -> usize {
+        self.data.len() * 8 - self.bit_position
+    }
+}
+
+impl<'a> DataRead for BitReader<'a> {
+    fn read_bytes(&mut self, buf: &mut [u8]) -> Result<()> {
+        if buf.len() * 8 > self.remaining_bits() {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        if self.bit_position% 8 == 0 {
+            let start = self.bit_position / 8;
+            buf.copy_from_slice(&self.data[start..start + buf.len()]);
+            self.bit_position += buf.len() * 8;
+        } else {
+            for b in buf.iter_mut() {
+                *b = self.read_bits(8)? as u8;
+            }
+        }
+        Ok(())
+    }
+
+    fn read_bytes_to_boxed_slice(&mut self, length: usize) -> Result<Box<[u8]>> {
+        if length * 8 > self.remaining_bits() {
+            return Err(ReadError::NotAllBytesRead(self.remaining_bits() / 8).into());
+        }
+        let mut buf = vec![0u8; length];
+        self.read_bytes(&mut buf)?;
+        Ok(buf.into_boxed_slice())
+    }
+
+    fn read_bytes_to_end(&mut self, buf: &mut Vec<u8>) -> Result<()> {
+        let start = buf.len();
+        buf.resize(start + self.remaining_bits() / 8, 0);
+        self.read_bytes(&mut buf[start..])?;
+        self.bit_position = self.data.len() * 8;
+        Ok(())
+    }
+
+    fn has_more(&self) -> bool {
+        self.remaining_bits() > 0
+    }
+
+    fn remaining(&self) -> usize {
+        self.remaining_bits() / 8
+    }
+
+    fn position(&self) -> usize {
+        self.bit_position / 8
+    }
+}
+
+/// Writes bit-packed data that can be read by `BitReader` and the BitStream of the
+/// original RakNet.
+///
+/// Bytes written through `std::io::Write`, and thereby all the methods of `DataWrite`,
+/// continue at the current bit position. The last byte is padded with zero bits.
+#[derive(Default)]
+pub struct BitWriter {
+    buf: Vec<u8>,
+    bit_length: usize,
+}
+
+impl BitWriter {
+    pub fn new() -> BitWriter {
+        BitWriter::default()
+    }
+
+    /// Writes a single bit, e.g. a compressed boolean flag.
+    pub fn write_bit(&mut self, bit: bool) {
+        if self.bit_length% 8 == 0 {
+            self.buf.push(0);
+        }
+        if bit {
+            let last = self.buf.len() - 1;
+            self.buf[last] |= 0x80 >> (self.bit_length % 8);
+        }
+        self.bit_length += 1;
+    }
+
+    /// Writes the lowest `count` bits, at most 64, of the value most significant bit first.
+    pub fn write_bits(&mut self, value: u64, count: usize) {
+        assert!(count <= 64, "Can not write more than 64 bits at a time");
+        for i in (0..count).rev() {
+            self.write_bit(value >> i & 1 != 0);
+        }
+    }
+
+    /// Pads the current byte with zero bits so that the next write starts at a byte boundary.
+    pub fn align_to_byte(&mut self) {
+        self.bit_length = self.buf.len() * 8;
+    }
+
+    /// Returns the number of bits written.
+    pub fn bit_length(&self) -> usize {
+        self.bit_length
+    }
+
+    /// Returns the written bytes.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf
+    }
+
+    /// Consumes the writer and returns the written bytes.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+impl Write for BitWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.bit_length% 8 == 0 {
+            self.buf.extend_from_slice(buf);
+            self.bit_length += buf.len() * 8;
+        } else {
+            for &b in buf {
+                self.write_bits(b as u64, 8);
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{reader::DataRead, writer::DataWrite};
+    use super::{BitReader, BitWriter};
+
+    #[test]
+    fn write_and_read_bit_packed_fields() {
+        // Arrange
+        let mut writer = BitWriter::new();
+        writer.write_bit(true);
+        writer.write_bit(false);
+        writer.write_bits(0b101, 3);
+        writer.write_u16(0x1234).expect("Could not write u16");
+        writer.write_bit(true);
+        writer.align_to_byte();
+        writer.write_u32_be(0xAABBCCDD).expect("Could not write u32");
+        writer.write_var_i32(-300).expect("Could not write VarInt");
+        let bit_length = writer.bit_length();
+        let bytes = writer.into_bytes();
+        let mut reader = BitReader::new(&bytes);
+
+        // Act
+        let first_flag = reader.read_bit().expect("Could not read bit");
+        let second_flag = reader.read_bit().expect("Could not read bit");
+        let bits = reader.read_bits(3).expect("Could not read bits");
+        let unaligned_value = reader.read_u16().expect("Could not read u16");
+        let third_flag = reader.read_bit().expect("Could not read bit");
+        reader.align_to_byte();
+        let aligned_value = reader.read_u32_be().expect("Could not read u32");
+        let var_int = reader.read_var_i32().expect("Could not read VarInt");
+
+        // Assert
+        assert_eq!(72, bit_length);
+        assert_eq!(vec![0xA9, 0xA0, 0x94, 0xAA, 0xBB, 0xCC, 0xDD, 0xD7, 0x04], bytes);
+        assert!(first_flag);
+        assert!(!second_flag);
+        assert_eq!(0b101, bits);
+        assert_eq!(0x1234, unaligned_value);
+        assert!(third_flag);
+        assert_eq!(0xAABBCCDD, aligned_value);
+        assert_eq!(-300, var_int);
+        assert!(!reader.has_more());
+    }
+
+    #[test]
+    fn read_past_end_fails() {
+        // Arrange
+        let bytes = [0xFFu8, 0x00];
+        let mut reader = BitReader::new(&bytes);
+        reader.read_bits(3).expect("Could not read bits");
+
+        // Act
+        let result = reader.read_u16();
+
+        // Assert
+        assert!(result.is_err());
+        assert_eq!(13, reader.remaining_bits());
+        assert_eq!(0b11111, reader.read_bits(5).expect("Could not read bits"));
+    }
+}
@@ -0,0 +1,262 @@
+use std::{
+    fs::File,
+    io::{self, Write},
+    net::{IpAddr, Ipv6Addr, SocketAddr},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::logging::error;
+
+/// The pcap link type of raw IPv4/IPv6 packets without a link layer header.
+const LINKTYPE_RAW: u32 = 101;
+const PCAP_MAGIC: u32 = 0xa1b2_c3d4;
+const SNAPLEN: u32 = 65535;
+const UDP_PROTOCOL: u8 = 17;
+const TTL: u8 = 64;
+const IPV4_HEADER_SIZE: usize = 20;
+const IPV6_HEADER_SIZE: usize = 40;
+const UDP_HEADER_SIZE: usize = 8;
+
+/// A function that is called with every datagram sent or received by a peer,
+/// see `Peer::set_packet_capture`. It is called on the processing thread and
+/// should return quickly.
+pub type PacketCapture = Box<dyn FnMut(&CapturedDatagram) + Send>;
+
+/// Whether a captured datagram was sent or received.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaptureDirection {
+    Sent,
+    Received,
+}
+
+/// A datagram as it was passed to or received from the socket,
+/// including the integrity tag if `Config::pre_shared_key` is set.
+#[derive(Clone, Copy, Debug)]
+pub struct CapturedDatagram<'a> {
+    pub direction: CaptureDirection,
+    /// The time the datagram was sent or received.
+    pub time: SystemTime,
+    /// The local address of the socket when the capture was started.
+    pub local_addr: SocketAddr,
+    /// The address of the remote peer that the datagram was sent to or received from.
+    pub remote_addr: SocketAddr,
+    pub payload: &'a [u8],
+}
+
+impl CapturedDatagram<'_> {
+    /// Returns the source and destination address of the datagram.
+    pub fn source_and_destination(&self) -> (SocketAddr, SocketAddr) {
+        match self.direction {
+            CaptureDirection::Sent => (self.local_addr, self.remote_addr),
+            CaptureDirection::Received => (self.remote_addr, self.local_addr),
+        }
+    }
+}
+
+/// Writes captured datagrams in the pcap format with made up IP and UDP headers,
+/// so that the capture can be opened in Wireshark or with `bedroxide decode`.
+pub struct PcapWriter<W: Write> {
+    writer: W,
+}
+
+impl PcapWriter<File> {
+    /// Creates the pcap file, replacing it if it exists. Every datagram
+    /// is written to the file directly so that it can be read while capturing.
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::new(File::create(path)?)
+    }
+}
+
+impl<W: Write> PcapWriter<W> {
+    /// Writes the pcap file header to the writer.
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        let mut header = Vec::with_capacity(24);
+        header.extend_from_slice(&PCAP_MAGIC.to_le_bytes());
+        header.extend_from_slice(&2u16.to_le_bytes()); // Major version
+        header.extend_from_slice(&4u16.to_le_bytes()); // Minor version
+        header.extend_from_slice(&0i32.to_le_bytes()); // Time zone offset
+        header.extend_from_slice(&0u32.to_le_bytes()); // Timestamp accuracy
+        header.extend_from_slice(&SNAPLEN.to_le_bytes());
+        header.extend_from_slice(&LINKTYPE_RAW.to_le_bytes());
+        writer.write_all(&header)?;
+        Ok(PcapWriter { writer })
+    }
+
+    /// Writes the datagram as a UDP packet from its source to its destination address.
+    ///
+    /// If the local and remote address are of different IP versions, e.g. for a dual-stack
+    /// socket, the IPv4 address is written as an IPv4-mapped IPv6 address.
+    pub fn write_datagram(&mut self, datagram: &CapturedDatagram) -> io::Result<()> {
+        let (source, destination) = datagram.source_and_destination();
+        let packet = ip_packet(source, destination, datagram.payload);
+        let timestamp = datagram.time.duration_since(UNIX_EPOCH).unwrap_or_default();
+
+        let mut record = Vec::with_capacity(16 + packet.len());
+        record.extend_from_slice(&(timestamp.as_secs() as u32).to_le_bytes());
+        record.extend_from_slice(&timestamp.subsec_micros().to_le_bytes());
+        record.extend_from_slice(&(packet.len() as u32).to_le_bytes()); // Captured length
+        record.extend_from_slice(&(packet.len() as u32).to_le_bytes()); // Original length
+        record.extend_from_slice(&packet);
+        self.writer.write_all(&record)
+    }
+
+    /// Returns the writer, e.g. to inspect a capture written to a `Vec<u8>`.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write + Send + 'static> PcapWriter<W> {
+    /// Returns a packet capture that writes every datagram to this writer.
+    /// Write errors are logged and the datagram is left out of the capture.
+    pub fn into_packet_capture(mut self) -> PacketCapture {
+        Box::new(move |datagram| {
+            if let Err(err) = self.write_datagram(datagram) {
+                error!("Could not write captured datagram: {:?}", err);
+            }
+        })
+    }
+}
+
+/// Returns an IP packet containing a UDP packet with the payload.
+fn ip_packet(source: SocketAddr, destination: SocketAddr, payload: &[u8]) -> Vec<u8> {
+    let udp_length = (UDP_HEADER_SIZE + payload.len()) as u16;
+    let mut udp = Vec::with_capacity(udp_length as usize);
+    udp.extend_from_slice(&source.port().to_be_bytes());
+    udp.extend_from_slice(&destination.port().to_be_bytes());
+    udp.extend_from_slice(&udp_length.to_be_bytes());
+    udp.extend_from_slice(&[0, 0]); // Checksum, set below
+    udp.extend_from_slice(payload);
+
+    let mut packet;
+    match (source.ip(), destination.ip()) {
+        (IpAddr::V4(source_ip), IpAddr::V4(destination_ip)) => {
+            packet = Vec::with_capacity(IPV4_HEADER_SIZE + udp.len());
+            packet.extend_from_slice(&[0x45, 0]); // Version 4, header length 5 words, no DSCP/ECN
+            packet.extend_from_slice(&((IPV4_HEADER_SIZE + udp.len()) as u16).to_be_bytes());
+            packet.extend_from_slice(&[0, 0, 0x40, 0]); // No identification, don't fragment
+            packet.extend_from_slice(&[TTL, UDP_PROTOCOL, 0, 0]);
+            packet.extend_from_slice(&source_ip.octets());
+            packet.extend_from_slice(&destination_ip.octets());
+            let header_checksum = checksum(&[&packet[..]]);
+            packet[10..12].copy_from_slice(&header_checksum.to_be_bytes());
+
+            let pseudo_header = [&source_ip.octets()[..], &destination_ip.octets()[..], &[0, UDP_PROTOCOL], &udp_length.to_be_bytes()];
+            set_udp_checksum(&mut udp, &pseudo_header);
+        },
+        (source_ip, destination_ip) => {
+            let source_ip = to_ipv6(source_ip).octets();
+            let destination_ip = to_ipv6(destination_ip).octets();
+            packet = Vec::with_capacity(IPV6_HEADER_SIZE + udp.len());
+            packet.extend_from_slice(&[0x60, 0, 0, 0]); // Version 6, no traffic class or flow label
+            packet.extend_from_slice(&udp_length.to_be_bytes());
+            packet.extend_from_slice(&[UDP_PROTOCOL, TTL]);
+            packet.extend_from_slice(&source_ip);
+            packet.extend_from_slice(&destination_ip);
+
+            let pseudo_header = [&source_ip[..], &destination_ip[..], &[0, UDP_PROTOCOL], &udp_length.to_be_bytes()];
+            set_udp_checksum(&mut udp, &pseudo_header);
+        },
+    }
+    packet.extend_from_slice(&udp);
+    packet
+}
+
+fn to_ipv6(ip: IpAddr) -> Ipv6Addr {
+    match ip {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+        IpAddr::V6(ip) => ip,
+    }
+}
+
+fn set_udp_checksum(udp: &mut [u8], pseudo_header: &[&[u8]]) {
+    let mut parts = pseudo_header.to_vec();
+    parts.push(udp);
+    // A computed checksum of 0 is sent as all ones since 0 means no checksum
+    let udp_checksum = match checksum(&parts) {
+        0 => 0xffff,
+        udp_checksum => udp_checksum,
+    };
+    udp[6..8].copy_from_slice(&udp_checksum.to_be_bytes());
+}
+
+/// Returns the internet checksum of the concatenated parts, each of an even length except the last.
+fn checksum(parts: &[&[u8]]) -> u16 {
+    let mut sum = 0u32;
+    for part in parts {
+        for word in part.chunks(2) {
+            let high = word[0] as u32;
+            let low = word.get(1).copied().unwrap_or(0) as u32;
+            sum += (high << 8) | low;
+        }
+    }
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr, time::{Duration, UNIX_EPOCH}};
+    use super::{checksum, CaptureDirection, CapturedDatagram, PcapWriter};
+
+    #[test]
+    fn pcap_writer_writes_header_and_udp_packet() {
+        // Arrange
+        let mut writer = PcapWriter::new(Vec::new()).expect("Could not write header");
+        let datagram = CapturedDatagram {
+            direction: CaptureDirection::Sent,
+            time: UNIX_EPOCH + Duration::from_micros(1_500_000),
+            local_addr: "127.0.0.1:19132".parse::<SocketAddr>().unwrap(),
+            remote_addr: "10.0.0.2:50000".parse::<SocketAddr>().unwrap(),
+            payload: &[0x01, 0x02, 0x03],
+        };
+
+        // Act
+        writer.write_datagram(&datagram).expect("Could not write datagram");
+        let pcap = writer.into_inner();
+
+        // Assert
+        assert_eq!(24 + 16 + 20 + 8 + 3, pcap.len());
+        assert_eq!([0xd4, 0xc3, 0xb2, 0xa1], pcap[0..4]);
+        assert_eq!(101u32.to_le_bytes(), pcap[20..24]);
+        assert_eq!(1u32.to_le_bytes(), pcap[24..28]);
+        assert_eq!(500_000u32.to_le_bytes(), pcap[28..32]);
+        assert_eq!(31u32.to_le_bytes(), pcap[32..36]);
+        let ip = &pcap[40..];
+        assert_eq!(0, checksum(&[&ip[..20]]));
+        assert_eq!([127, 0, 0, 1], ip[12..16]);
+        assert_eq!([10, 0, 0, 2], ip[16..20]);
+        assert_eq!(19132u16.to_be_bytes(), ip[20..22]);
+        assert_eq!(50000u16.to_be_bytes(), ip[22..24]);
+        assert_eq!(11u16.to_be_bytes(), ip[24..26]);
+        assert_eq!([0x01, 0x02, 0x03], ip[28..]);
+    }
+
+    #[test]
+    fn pcap_writer_maps_ipv4_to_ipv6_for_mixed_addresses() {
+        // Arrange
+        let mut writer = PcapWriter::new(Vec::new()).expect("Could not write header");
+        let datagram = CapturedDatagram {
+            direction: CaptureDirection::Received,
+            time: UNIX_EPOCH,
+            local_addr: "[::1]:19133".parse::<SocketAddr>().unwrap(),
+            remote_addr: "10.0.0.2:50000".parse::<SocketAddr>().unwrap(),
+            payload: &[0xff],
+        };
+
+        // Act
+        writer.write_datagram(&datagram).expect("Could not write datagram");
+        let pcap = writer.into_inner();
+
+        // Assert
+        let ip = &pcap[40..];
+        assert_eq!(40 + 8 + 1, ip.len());
+        assert_eq!(0x60, ip[0]);
+        assert_eq!([0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 10, 0, 0, 2], ip[8..24]);
+        assert_eq!([0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1], ip[24..40]);
+        assert_eq!(50000u16.to_be_bytes(), ip[40..42]);
+    }
+}
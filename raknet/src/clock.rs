@@ -0,0 +1,77 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// A source of the current time used for all timeouts, resends and acknowledgements of a peer.
+///
+/// A peer uses the `SystemClock` unless another clock is given to `Peer::with_clock`,
+/// e.g. a `ManualClock` in tests that need timeouts to pass without sleeping.
+pub trait Clock: Send + Sync {
+    /// Returns the current time.
+    fn now(&self) -> Instant;
+}
+
+/// The clock of the operating system.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock that only moves when it is advanced. Clones share the same time.
+#[derive(Clone, Debug)]
+pub struct ManualClock {
+    now: Arc<Mutex<Instant>>,
+}
+
+impl ManualClock {
+    /// Creates a clock that starts at the current time of the operating system.
+    pub fn new() -> Self {
+        ManualClock {
+            now: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    /// Moves the clock forward.
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.now.lock().expect("Clock lock poisoned");
+        *now += duration;
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().expect("Clock lock poisoned")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::{Clock, ManualClock};
+
+    #[test]
+    fn manual_clock_moves_when_advanced() {
+        // Arrange
+        let clock = ManualClock::new();
+        let shared_clock = clock.clone();
+        let start = clock.now();
+
+        // Act
+        shared_clock.advance(Duration::from_secs(5));
+
+        // Assert
+        assert_eq!(start + Duration::from_secs(5), clock.now());
+        assert_eq!(clock.now(), shared_clock.now());
+    }
+}
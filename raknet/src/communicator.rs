@@ -1,22 +1,63 @@
-use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::{collections::{HashMap, VecDeque}, io, net::{IpAddr, Ipv4Addr, SocketAddr}, sync::Arc, time::{Instant, SystemTime}};
 
 use crossbeam_channel::Sender;
-use log::error;
 
-use crate::{Config, PeerEvent, Result, constants::MAX_NUMBER_OF_INTERNAL_IDS, socket::DatagramSocket};
+use crate::{
+    Clock,
+    Config,
+    ConfigUpdate,
+    InvalidConfig,
+    ModerationAction,
+    ModerationRule,
+    PeerEvent,
+    SecurityEventKind,
+    bandwidth_limiter::BandwidthLimiter,
+    capture::{CaptureDirection, CapturedDatagram, PacketCapture},
+    connection_handle::{ConnectionResult, ConnectionResultSender},
+    constants::{INTEGRITY_TAG_SIZE, MAX_NUMBER_OF_INTERNAL_IDS},
+    logging::{debug, error},
+    peer_metrics::PeerMetrics,
+    security,
+    socket::{self, DatagramSocket},
+};
 
 pub struct Communicator<T: DatagramSocket> {
     config: Config,
     socket: T,
+    /// Whether the local address of the socket is an IPv6 address, see `socket::to_socket_family`.
+    is_ipv6_socket: bool,
     event_sender: Sender<PeerEvent>,
+    connection_result_senders: HashMap<SocketAddr, Vec<ConnectionResultSender>>,
+    bandwidth_limiter: BandwidthLimiter,
+    /// Datagrams that could not be sent without blocking, in the order they were sent.
+    send_queue: VecDeque<(Vec<u8>, SocketAddr)>,
+    /// The counters of the metrics of the peer. The gauges are set by `ConnectionManager::metrics`.
+    metrics: PeerMetrics,
+    /// Called with every datagram sent or received, together with the local address
+    /// of the socket when the capture was started, see `Peer::set_packet_capture`.
+    packet_capture: Option<(PacketCapture, SocketAddr)>,
+    clock: Arc<dyn Clock>,
+    /// The time of the clock when the peer was created.
+    start_time: Instant,
+    /// The system time when the peer was created.
+    start_system_time: SystemTime,
 }
 
 impl<T: DatagramSocket> Communicator<T> {
-    pub fn new(socket: T, config: Config, event_sender: Sender<PeerEvent>) -> Self {
+    pub fn new(socket: T, config: Config, event_sender: Sender<PeerEvent>, clock: Arc<dyn Clock>) -> Self {
         Communicator {
+            bandwidth_limiter: BandwidthLimiter::new(config.max_outgoing_bytes_per_second),
             config,
+            is_ipv6_socket: socket.local_addr().is_ok_and(|addr| addr.is_ipv6()),
             socket,
             event_sender,
+            connection_result_senders: HashMap::new(),
+            send_queue: VecDeque::new(),
+            metrics: PeerMetrics::default(),
+            packet_capture: None,
+            start_time: clock.now(),
+            start_system_time: SystemTime::now(),
+            clock,
         }
     }
 
@@ -24,30 +65,224 @@ impl<T: DatagramSocket> Communicator<T> {
         &self.config
     }
 
+    /// Applies the changes to the configuration unless the changed configuration is invalid.
+    pub fn update_config(&mut self, update: &ConfigUpdate) -> Result<(), InvalidConfig> {
+        let mut config = self.config.clone();
+        if let Some(max_incoming_connections) = update.max_incoming_connections {
+            config.max_incoming_connections = max_incoming_connections;
+        }
+        if let Some(ack_timeout_in_ms) = update.ack_timeout_in_ms {
+            config.ack_timeout_in_ms = ack_timeout_in_ms;
+        }
+        if let Some(max_outgoing_bytes_per_second) = update.max_outgoing_bytes_per_second {
+            config.max_outgoing_bytes_per_second = max_outgoing_bytes_per_second;
+        }
+        config.validate()?;
+        self.bandwidth_limiter.set_max_bytes_per_second(config.max_outgoing_bytes_per_second);
+        self.config = config;
+        Ok(())
+    }
+
+    /// Returns the current time of the clock of the peer.
+    pub fn now(&self) -> Instant {
+        self.clock.now()
+    }
+
+    /// Returns the time of the clock when the peer was created.
+    pub fn start_time(&self) -> Instant {
+        self.start_time
+    }
+
+    /// Returns the number of milliseconds from when the peer was created to the time.
+    pub fn millis_since_start(&self, time: Instant) -> u64 {
+        time.saturating_duration_since(self.start_time).as_millis() as u64
+    }
+
+    /// Returns the system time at the time of the clock, which follows the clock of
+    /// the peer instead of changes to the system time after the peer was created.
+    pub fn system_time(&self, time: Instant) -> SystemTime {
+        self.start_system_time + time.saturating_duration_since(self.start_time)
+    }
+
     pub fn socket(&mut self) -> &mut T {
         &mut self.socket
     }
 
+    /// Returns the limiter for the bandwidth used by all connections together.
+    pub fn bandwidth_limiter(&mut self) -> &mut BandwidthLimiter {
+        &mut self.bandwidth_limiter
+    }
+
+    /// Sends a datagram, with an integrity tag if `Config::pre_shared_key` is set.
+    ///
+    /// If the socket cannot send without blocking the datagram is queued and sent
+    /// by `send_queued_datagrams`. Datagrams are always sent in order.
     pub fn send_datagram(&mut self, payload: &[u8], addr: SocketAddr) {
-        if let Err(err) = self.socket.send_datagram(payload, addr) {
-            error!("Failed sending datagram to {}: {:?}", addr, err);
+        let tagged_payload;
+        let datagram = match &self.config.pre_shared_key {
+            Some(pre_shared_key) => {
+                let mut tagged = Vec::with_capacity(payload.len() + INTEGRITY_TAG_SIZE);
+                tagged.extend_from_slice(payload);
+                tagged.extend_from_slice(&security::integrity_tag(pre_shared_key, payload));
+                tagged_payload = tagged;
+                &tagged_payload[..]
+            },
+            None => payload,
+        };
+        if !self.send_queue.is_empty() {
+            self.enqueue_datagram(datagram.to_vec(), addr);
+            return;
+        }
+        match self.socket.send_datagram(datagram, socket::to_socket_family(addr, self.is_ipv6_socket)) {
+            Ok(_) => self.on_datagram_sent(datagram, addr),
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => self.enqueue_datagram(datagram.to_vec(), addr),
+            Err(err) => error!("Failed sending datagram to {}: {:?}", addr, err),
+        }
+    }
+
+    /// Sends the queued datagrams until the socket would block again.
+    pub fn send_queued_datagrams(&mut self) {
+        while let Some((datagram, addr)) = self.send_queue.pop_front() {
+            match self.socket.send_datagram(&datagram, socket::to_socket_family(addr, self.is_ipv6_socket)) {
+                Ok(_) => self.on_datagram_sent(&datagram, addr),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    self.send_queue.push_front((datagram, addr));
+                    return;
+                },
+                Err(err) => error!("Failed sending datagram to {}: {:?}", addr, err),
+            }
+        }
+    }
+
+    /// Returns the number of datagrams dropped since the send queue was full.
+    pub fn dropped_datagram_count(&self) -> u64 {
+        self.metrics.dropped_datagrams
+    }
+
+    /// Returns the number of datagrams waiting for the socket to be able to send.
+    pub fn queued_datagram_count(&self) -> usize {
+        self.send_queue.len()
+    }
+
+    /// Returns the counters of the metrics of the peer.
+    pub fn metrics(&self) -> &PeerMetrics {
+        &self.metrics
+    }
+
+    /// Returns the counters of the metrics of the peer for updating them.
+    pub fn metrics_mut(&mut self) -> &mut PeerMetrics {
+        &mut self.metrics
+    }
+
+    /// Starts capturing every datagram sent or received with the packet capture,
+    /// replacing any previous capture, or stops capturing if it is None.
+    pub fn set_packet_capture(&mut self, packet_capture: Option<PacketCapture>) {
+        let local_addr = self.socket.local_addr().unwrap_or_else(|_| SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 0));
+        self.packet_capture = packet_capture.map(|packet_capture| (packet_capture, local_addr));
+    }
+
+    /// Passes the datagram to the packet capture if capturing.
+    pub fn capture_datagram(&mut self, direction: CaptureDirection, payload: &[u8], remote_addr: SocketAddr) {
+        if let Some((packet_capture, local_addr)) = &mut self.packet_capture {
+            packet_capture(&CapturedDatagram {
+                direction,
+                time: SystemTime::now(),
+                local_addr: *local_addr,
+                remote_addr,
+                payload,
+            });
+        }
+    }
+
+    fn on_datagram_sent(&mut self, datagram: &[u8], addr: SocketAddr) {
+        self.metrics.datagrams_sent += 1;
+        self.metrics.bytes_sent += datagram.len() as u64;
+        self.capture_datagram(CaptureDirection::Sent, datagram, addr);
+    }
+
+    fn enqueue_datagram(&mut self, datagram: Vec<u8>, addr: SocketAddr) {
+        if self.send_queue.len() >= self.config.max_queued_datagrams {
+            debug!("Send queue is full, dropping datagram to {}", addr);
+            self.metrics.dropped_datagrams += 1;
+        } else {
+            self.send_queue.push_back((datagram, addr));
+        }
+    }
+
+    /// Returns the received datagram without its integrity tag if `Config::pre_shared_key`
+    /// is set or None if the tag is invalid. Otherwise the datagram is returned unchanged.
+    pub fn strip_integrity_tag<'a>(&self, datagram: &'a [u8]) -> Option<&'a [u8]> {
+        match &self.config.pre_shared_key {
+            Some(pre_shared_key) => security::strip_integrity_tag(pre_shared_key, datagram),
+            None => Some(datagram),
+        }
+    }
+
+    /// Adds a sender that receives the result of the outgoing
+    /// connection attempt to the specified address.
+    pub fn add_connection_result_sender(&mut self, addr: SocketAddr, sender: ConnectionResultSender) {
+        self.connection_result_senders.entry(addr).or_default().push(sender);
+    }
+
+    /// Moves the connection result senders from one address to another
+    /// when a connection attempt continues with a fallback address.
+    pub fn move_connection_result_senders(&mut self, from: SocketAddr, to: SocketAddr) {
+        if let Some(senders) = self.connection_result_senders.remove(&from) {
+            for sender in &senders {
+                sender.set_addr(to);
+            }
+            self.connection_result_senders.entry(to).or_default().extend(senders);
         }
     }
 
     pub fn send_event(&mut self, event: PeerEvent) {
+        let connection_result: Option<(SocketAddr, ConnectionResult)> = match &event {
+            PeerEvent::ConnectionEstablished { addr, guid, .. } => Some((*addr, Ok(*guid))),
+            PeerEvent::ConnectionAttemptFailed { addr, reason, .. } => Some((*addr, Err(*reason))),
+            // An outgoing connection attempt is abandoned in favor of an incoming
+            // connection when two peers connect to each other simultaneously
+            PeerEvent::IncomingConnection(conn) => Some((conn.addr(), Ok(conn.guid()))),
+            _ => None,
+        };
+        match &event {
+            PeerEvent::ConnectionEstablished { .. } | PeerEvent::IncomingConnection(_) => self.metrics.connections_established += 1,
+            PeerEvent::ConnectionAttemptFailed { .. } => self.metrics.connection_attempts_failed += 1,
+            PeerEvent::ConnectionLost { .. } => self.metrics.connections_lost += 1,
+            _ => {},
+        }
+        if let Some((addr, result)) = connection_result {
+            for sender in self.connection_result_senders.remove(&addr).unwrap_or_default() {
+                sender.send(result);
+            }
+        }
         if let Err(_) = self.event_sender.send(event) {
             error!("Send event failed since the event receiver has been dropped");
         }
     }
 
-    pub fn local_addr(&self) -> Result<SocketAddr> {
-        Ok(self.socket.local_addr()?)
+    /// Sends a `PeerEvent::SecurityEvent` stamped with the system time at `time`.
+    pub fn send_security_event(&mut self, time: Instant, addr: SocketAddr, kind: SecurityEventKind) {
+        let time = self.system_time(time);
+        self.send_event(PeerEvent::SecurityEvent { addr, kind, time });
+    }
+
+    /// Sends a `PeerEvent::ModerationAction` stamped with the system time at `time`.
+    pub fn send_moderation_event(&mut self, time: Instant, addr: Option<SocketAddr>, action: ModerationAction, rule: ModerationRule) {
+        let time = self.system_time(time);
+        self.send_event(PeerEvent::ModerationAction { addr, action, rule, time });
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
     }
 
+    /// Returns the local addresses, both IPv4 and IPv6, padded with unspecified addresses.
     pub fn get_addr_list(&self) -> [SocketAddr; MAX_NUMBER_OF_INTERNAL_IDS] {
         let mut addr_list = [SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 0); MAX_NUMBER_OF_INTERNAL_IDS];
-        if let Ok(local_addr) = self.local_addr() {
-            addr_list[0] = local_addr;
+        if let Ok(local_addrs) = self.socket.local_addrs() {
+            for (list_addr, local_addr) in addr_list.iter_mut().zip(local_addrs) {
+                *list_addr = local_addr;
+            }
         }
         addr_list
     }
@@ -1,8 +1,22 @@
-use rand;
+use std::fmt;
+#[cfg(any(feature = "rand", feature = "serde"))]
+use std::{fs, io, path::Path};
 
+use crate::{allow_list::AllowList, constants::{MAXIMUM_MTU_SIZE, MAX_PASSWORD_LENGTH, MAX_SPLIT_PACKET_COUNT, MINIMUM_MTU_SIZE}, inbound_limiter::InboundLimitAction, random, ConfigBuilder, SocketAddrFormat, SocketOptions};
+#[cfg(feature = "rand")]
+use crate::{constants::MAX_PROOF_OF_WORK_DIFFICULTY, security::SecurityConfig};
+
+/// The configuration of a `Peer`.
+///
+/// With the `serde` feature the configuration can be serialized and loaded from a
+/// file with `Config::from_path`. Fields missing in the file get their default values.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default))]
 pub struct Config {
-    /// A unique (random) identifier that identifies this peer in
-    /// connections with other peers.
+    /// A unique identifier that identifies this peer in connections with other peers.
+    /// It is random by default, so remote peers see a new peer after every restart.
+    /// Set it explicitly or load it with `Config::load_or_create_guid` to keep it stable.
+    /// Without the `rand` feature it is 0 by default and must be set.
     pub guid: u64,
 
     /// The maximum number of incoming connections, thus not initiated
@@ -14,21 +28,624 @@ pub struct Config {
     ///
     /// The time is measured from when this peer has sent
     /// an "open connection reply 2" until we receive a "connection request".
+    #[cfg_attr(feature = "serde", serde(with = "millis"))]
     pub incoming_connection_timeout_in_ms: u128,
 
     /// The time in milliseconds before a connection is considered dead
     /// if no datagrams have been received when this peer has sent packets
     /// that are awaiting acks.
+    #[cfg_attr(feature = "serde", serde(with = "millis"))]
     pub ack_timeout_in_ms: u128,
+
+    /// The number of "open connection request 1" messages that are sent
+    /// for an outgoing connection before the attempt is given up.
+    pub connection_attempt_count: u32,
+
+    /// The time in milliseconds to wait for a reply to an
+    /// "open connection request 1" before sending the next one.
+    #[cfg_attr(feature = "serde", serde(with = "millis"))]
+    pub time_between_connection_attempts_in_ms: u128,
+
+    /// The time in milliseconds that an outgoing connection has to
+    /// be established before the attempt is given up.
+    ///
+    /// The time is measured from when `Peer::connect` is called until
+    /// the remote peer has accepted the connection request.
+    #[cfg_attr(feature = "serde", serde(with = "millis"))]
+    pub connection_timeout_in_ms: u128,
+
+    /// If true, IPv6 addresses are tried before IPv4 addresses when
+    /// connecting to a hostname that resolves to several addresses.
+    pub prefer_ipv6: bool,
+
+    /// If false, a socket bound to an IPv6 address by `Peer::bind` or `Peer::bind_multi`
+    /// also sends and receives IPv4 datagrams (dual-stack), e.g. when bound to `[::]:19132`.
+    /// If true, such a socket only handles IPv6 and another socket is needed for IPv4.
+    pub ipv6_only: bool,
+
+    /// The format of the socket addresses in the messages sent and received, which
+    /// depends on the RakNet fork of the remote peers, see `SocketAddrFormat`.
+    pub socket_addr_format: SocketAddrFormat,
+
+    /// The IP options, e.g. TTL, DSCP marking and the don't fragment bit,
+    /// set on the socket when the peer is created.
+    pub socket_options: SocketOptions,
+
+    /// The password that remote peers must supply in their connection
+    /// request. If None, connections are accepted without a password.
+    /// At most 255 bytes.
+    pub incoming_password: Option<Vec<u8>>,
+
+    /// The time in milliseconds to wait before the first reconnect when a
+    /// connection started with `Peer::start_reconnecting` is lost or fails.
+    /// The time is doubled for every failed reconnect.
+    #[cfg_attr(feature = "serde", serde(with = "millis"))]
+    pub reconnect_initial_delay_in_ms: u128,
+
+    /// The maximum time in milliseconds to wait between reconnects.
+    #[cfg_attr(feature = "serde", serde(with = "millis"))]
+    pub reconnect_max_delay_in_ms: u128,
+
+    /// The maximum number of new datagrams sent to a remote peer per `tick_interval_in_ms`.
+    /// The datagrams are paced evenly over time to avoid bursts that cause packet loss on
+    /// some links, so at most this many datagrams are sent at once however often the
+    /// peer is processed.
+    pub max_datagrams_per_tick: usize,
+
+    /// The maximum number of datagrams queued when the socket cannot send without
+    /// blocking. Queued datagrams are sent in later processing rounds and datagrams
+    /// exceeding the limit are dropped, see `Peer::dropped_datagram_count`.
+    pub max_queued_datagrams: usize,
+
+    /// The time in milliseconds between the processing rounds of
+    /// `Peer::start_processing` while there are connections or connection attempts.
+    /// Must be larger than 0 so that the processing loop does not spin.
+    #[cfg_attr(feature = "serde", serde(with = "millis"))]
+    pub tick_interval_in_ms: u128,
+
+    /// The time in milliseconds between the processing rounds of `Peer::start_processing`
+    /// while there are no connections and no connection attempts. Incoming datagrams,
+    /// e.g. offline pings, may wait this long before they are handled but commands
+    /// still wake the loop immediately. If set to 0 `tick_interval_in_ms` is always used.
+    #[cfg_attr(feature = "serde", serde(with = "millis"))]
+    pub idle_tick_interval_in_ms: u128,
+
+    /// The time in milliseconds that a processing round may take before it is counted
+    /// as slow in `Peer::tick_stats` and reported with `PeerEvent::SlowTick`, e.g. to find
+    /// the cause of stutter in a server. If set to 0 no processing round is slow.
+    #[cfg_attr(feature = "serde", serde(with = "millis"))]
+    pub slow_tick_threshold_in_ms: u128,
+
+    /// The time in milliseconds that received packets may wait for a missing packet on an
+    /// ordering channel before it is reported with `PeerEvent::OrderingChannelBlocked`.
+    /// If set to 0 blocked channels are not reported.
+    #[cfg_attr(feature = "serde", serde(with = "millis"))]
+    pub ordering_blocked_threshold_in_ms: u128,
+
+    /// The time in milliseconds between the pings sent to connected remote peers to
+    /// measure the round trip time, see `Peer::ping` and `PeerEvent::PingUpdated`.
+    /// If set to 0 pings are only sent when connections are established.
+    #[cfg_attr(feature = "serde", serde(with = "millis"))]
+    pub ping_interval_in_ms: u128,
+
+    /// The time in milliseconds between the `PeerEvent::ConnectionQuality` events sent for
+    /// each connection, e.g. for reducing the update rate of lossy clients.
+    /// If set to 0 the events are not sent.
+    #[cfg_attr(feature = "serde", serde(with = "millis"))]
+    pub connection_quality_interval_in_ms: u128,
+
+    /// The maximum number of times a reliable packet is resent before
+    /// the connection is considered lost.
+    pub max_retransmissions: u32,
+
+    /// The time in milliseconds that an unreliable packet may wait to be sent.
+    /// Older unreliable packets are dropped instead of being sent late.
+    /// If set to 0 unreliable packets never expire.
+    #[cfg_attr(feature = "serde", serde(with = "millis"))]
+    pub unreliable_timeout_in_ms: u128,
+
+    /// The time in milliseconds that outgoing packets are held back so that
+    /// several small packets can be sent in the same datagram. The delay is
+    /// bypassed by `Peer::flush`. If set to 0 packets are sent immediately.
+    #[cfg_attr(feature = "serde", serde(with = "millis"))]
+    pub coalescing_delay_in_ms: u128,
+
+    /// The maximum number of bytes per second sent to all remote peers together.
+    /// The limit for a single connection is set with `Peer::set_connection_bandwidth_limit`.
+    /// If set to 0 the bandwidth is unlimited.
+    pub max_outgoing_bytes_per_second: u64,
+
+    /// The smallest MTU size in bytes that is tried when connecting and accepted from
+    /// remote peers. Connection requests and replies with a smaller MTU are rejected.
+    pub min_mtu_size: u16,
+
+    /// The largest MTU size in bytes that is tried when connecting. The MTU of
+    /// connections from remote peers is also clamped to this size. Lower it if
+    /// the network cannot pass datagrams of the default size, e.g. over some VPNs.
+    pub max_mtu_size: u16,
+
+    /// The maximum number of parts of an incoming split packet. Split packets
+    /// with more parts are discarded before any memory is allocated for them.
+    pub max_incoming_split_packet_count: u32,
+
+    /// If true the connection to a remote peer is dropped when it sends an invalid
+    /// split packet, e.g. one with too many parts. Otherwise only the part is discarded.
+    pub drop_connection_on_invalid_split_packet: bool,
+
+    /// The maximum number of packets per second received over each connection, counting
+    /// each part of a split packet. If set to 0 the packet rate is unlimited.
+    pub max_incoming_packets_per_second: u32,
+
+    /// The maximum number of bytes of packet payloads per second received over each
+    /// connection. If set to 0 the byte rate is unlimited.
+    pub max_incoming_bytes_per_second: u64,
+
+    /// The maximum size in bytes of an incoming packet when reassembled from its parts.
+    /// Larger split packets are discarded as soon as their size is known, so that a remote
+    /// peer cannot exhaust the memory with huge payloads. If set to 0 the size is only
+    /// limited by `max_incoming_split_packet_count`.
+    pub max_incoming_payload_size: usize,
+
+    /// What is done when a remote peer exceeds `max_incoming_packets_per_second`,
+    /// `max_incoming_bytes_per_second` or `max_incoming_payload_size`.
+    pub inbound_limit_action: InboundLimitAction,
+
+    /// The time in milliseconds that an IP address is banned when `inbound_limit_action`
+    /// is `InboundLimitAction::Ban`.
+    #[cfg_attr(feature = "serde", serde(with = "millis"))]
+    pub inbound_limit_penalty_in_ms: u128,
+
+    /// If set, remote peers connecting to this peer must perform a security
+    /// handshake in which keys are exchanged and this peer proves its identity.
+    /// Connecting peers that do not complete the handshake are ignored.
+    /// Only available with the `rand` feature, which creates the secret keys of the handshake.
+    #[cfg(feature = "rand")]
+    pub security: Option<SecurityConfig>,
+
+    /// If true, remote peers connecting to this peer must echo a cookie sent
+    /// in "open connection reply 1" to prove that they receive datagrams at their
+    /// address before any connection state is allocated. Cookies are always
+    /// used when `security` is set. Remote peers must support RakNet security.
+    /// Only available with the `rand` feature, which creates the secret key of the cookies.
+    #[cfg(feature = "rand")]
+    pub require_cookies: bool,
+
+    /// The number of leading zero bits of a hash that remote peers connecting to this peer
+    /// must find to solve the proof-of-work puzzle sent with the cookie in "open connection
+    /// reply 1", which deters floods of connection requests from botnets. Solving takes about
+    /// 2^difficulty hashes. Enables cookies like `require_cookies` and is only solved by remote
+    /// peers using this library. If set to 0 no puzzle is sent. At most 20.
+    /// Only available with the `rand` feature like `require_cookies`.
+    #[cfg(feature = "rand")]
+    pub proof_of_work_difficulty: u8,
+
+    /// The number of connections at which connecting peers start to get a proof-of-work
+    /// puzzle, see `proof_of_work_difficulty`. If set to 0 a puzzle is always sent.
+    #[cfg(feature = "rand")]
+    pub proof_of_work_connection_threshold: usize,
+
+    /// The maximum number of pings, connection requests and out-of-band messages per
+    /// second that are handled for each remote IP address. More packets are dropped, which
+    /// keeps the peer from being used to flood a spoofed address with replies.
+    /// If set to 0 the number is unlimited, which is the default.
+    pub max_offline_packets_per_second_per_ip: u32,
+
+    /// The time in milliseconds after an incoming connection from an IP address during
+    /// which new connections from the same IP address are rejected with
+    /// `ConnectionAttemptFailedReason::IpRecentlyConnected`. If set to 0 there is no limit,
+    /// which is the default so that quick reconnects on a local network are not rejected.
+    #[cfg_attr(feature = "serde", serde(with = "millis"))]
+    pub recent_connection_window_in_ms: u128,
+
+    /// The maximum number of incoming connections accepted per second from all remote
+    /// peers together, e.g. to survive all clients reconnecting after a restart. More
+    /// connection requests are rejected with `ConnectionAttemptFailedReason::NoFreeIncomingConnections`.
+    /// If set to 0 the number is unlimited.
+    pub max_new_connections_per_second: u32,
+
+    /// The number of incoming connections that are accepted at once before
+    /// `max_new_connections_per_second` applies. Must be larger than 0 if the rate is limited.
+    pub new_connection_burst: u32,
+
+    /// The time in milliseconds that an IP address is banned after sending something
+    /// other than a connection request on a connection that has not been verified yet.
+    /// The connection is always closed. If set to 0 the IP address is not banned.
+    #[cfg_attr(feature = "serde", serde(with = "millis"))]
+    pub garbage_penalty_in_ms: u128,
+
+    /// The cool-down in milliseconds during which all offline packets from the IP address of
+    /// a remote peer are ignored after its connection was dropped for abusing the protocol,
+    /// e.g. for sending garbage or invalid split packets. If set to 0 no address is greylisted.
+    #[cfg_attr(feature = "serde", serde(with = "millis"))]
+    pub greylist_duration_in_ms: u128,
+
+    /// If set, an integrity tag computed with this key is added to all datagrams and
+    /// received datagrams without a valid tag are dropped before they are parsed.
+    /// Meant for private networks of peers that all use the same key. Peers that do
+    /// not use the key, e.g. ordinary RakNet clients, cannot communicate with this peer.
+    pub pre_shared_key: Option<Vec<u8>>,
+
+    /// If set, only remote peers whose IP address is within a range of the allow list or
+    /// whose GUID is in it may connect. Connection requests from other remote peers are
+    /// rejected as if they were banned before any connection state is allocated.
+    /// The list can be changed while the peer is running with `Peer::set_allow_list`.
+    pub allow_list: Option<AllowList>,
+
+    /// If set, handoff tokens signed with this key, see `HandoffToken`, are accepted from
+    /// connecting peers, which then skip the allow list, the admission policy and the limits
+    /// on new connections. Peers moving remote peers between each other, e.g. servers behind
+    /// a proxy, must all use the same key. Connecting peers must echo a cookie before their
+    /// token is accepted, so tokens are only accepted with the `rand` feature.
+    pub handoff_key: Option<Vec<u8>>,
+
+    /// The time in milliseconds that a handoff token created with `Peer::create_handoff_token`
+    /// is valid. The clocks of the peers sharing the `handoff_key` must be synchronized.
+    #[cfg_attr(feature = "serde", serde(with = "millis"))]
+    pub handoff_token_lifetime_in_ms: u128,
 }
 
 impl Default for Config {
     fn default() -> Config {
         Config {
-            guid: rand::random(),
+            guid: if cfg!(feature = "rand") { random::random_u64() } else { 0 },
             max_incoming_connections: 50,
             incoming_connection_timeout_in_ms: 10000,
             ack_timeout_in_ms: 10000,
+            connection_attempt_count: 12,
+            time_between_connection_attempts_in_ms: 500,
+            connection_timeout_in_ms: 10000,
+            prefer_ipv6: false,
+            ipv6_only: false,
+            socket_addr_format: SocketAddrFormat::default(),
+            socket_options: SocketOptions::default(),
+            incoming_password: None,
+            reconnect_initial_delay_in_ms: 1000,
+            reconnect_max_delay_in_ms: 30000,
+            max_datagrams_per_tick: 8,
+            max_queued_datagrams: 1024,
+            tick_interval_in_ms: 1,
+            idle_tick_interval_in_ms: 0,
+            slow_tick_threshold_in_ms: 50,
+            ordering_blocked_threshold_in_ms: 1000,
+            ping_interval_in_ms: 5000,
+            connection_quality_interval_in_ms: 5000,
+            max_retransmissions: 15,
+            unreliable_timeout_in_ms: 0,
+            coalescing_delay_in_ms: 0,
+            max_outgoing_bytes_per_second: 0,
+            min_mtu_size: 576,
+            max_mtu_size: MAXIMUM_MTU_SIZE,
+            max_incoming_split_packet_count: MAX_SPLIT_PACKET_COUNT as u32,
+            drop_connection_on_invalid_split_packet: false,
+            max_incoming_packets_per_second: 0,
+            max_incoming_bytes_per_second: 0,
+            max_incoming_payload_size: 0,
+            inbound_limit_action: InboundLimitAction::DropPacket,
+            inbound_limit_penalty_in_ms: 60000,
+            #[cfg(feature = "rand")]
+            security: None,
+            #[cfg(feature = "rand")]
+            require_cookies: false,
+            #[cfg(feature = "rand")]
+            proof_of_work_difficulty: 0,
+            #[cfg(feature = "rand")]
+            proof_of_work_connection_threshold: 0,
+            max_offline_packets_per_second_per_ip: 0,
+            recent_connection_window_in_ms: 0,
+            max_new_connections_per_second: 0,
+            new_connection_burst: 20,
+            garbage_penalty_in_ms: 10000,
+            greylist_duration_in_ms: 30000,
+            pre_shared_key: None,
+            allow_list: None,
+            handoff_key: None,
+            handoff_token_lifetime_in_ms: 30000,
+        }
+    }
+}
+
+impl Config {
+    /// Returns a builder starting from the default configuration, see `ConfigBuilder`.
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::new()
+    }
+
+    /// A configuration for a server on a local network, where round trip times are short,
+    /// packet loss is rare and all remote peers are trusted, e.g. for LAN games.
+    pub fn lan_server() -> Config {
+        Config {
+            incoming_connection_timeout_in_ms: 3000,
+            ack_timeout_in_ms: 3000,
+            ping_interval_in_ms: 1000,
+            ..Config::default()
+        }
+    }
+
+    /// A configuration for a server reachable from the internet that protects itself
+    /// from spoofed and abusive remote peers, e.g. for public game servers.
+    pub fn internet_server() -> Config {
+        Config {
+            max_incoming_connections: 100,
+            #[cfg(feature = "rand")]
+            require_cookies: true,
+            max_offline_packets_per_second_per_ip: 10,
+            recent_connection_window_in_ms: 1000,
+            max_new_connections_per_second: 20,
+            new_connection_burst: 50,
+            garbage_penalty_in_ms: 60000,
+            greylist_duration_in_ms: 120000,
+            max_incoming_split_packet_count: 1024,
+            drop_connection_on_invalid_split_packet: true,
+            max_incoming_packets_per_second: 2000,
+            max_incoming_bytes_per_second: 1_000_000,
+            max_incoming_payload_size: 1_048_576,
+            inbound_limit_action: InboundLimitAction::Disconnect,
+            ..Config::default()
+        }
+    }
+
+    /// A configuration for a client that only makes outgoing connections.
+    pub fn client() -> Config {
+        Config {
+            max_incoming_connections: 0,
+            ..Config::default()
+        }
+    }
+
+    /// Returns the GUID stored as a decimal number in the file at the path. If the file does
+    /// not exist a random GUID is created and stored in it, so that the same GUID is used
+    /// after a restart. Returns an error if the file cannot be read or written or does not
+    /// contain a GUID.
+    #[cfg(feature = "rand")]
+    pub fn load_or_create_guid<P: AsRef<Path>>(path: P) -> io::Result<u64> {
+        let path = path.as_ref();
+        match fs::read_to_string(path) {
+            Ok(contents) => contents.trim().parse().map_err(|err| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("Invalid GUID in {}: {}", path.display(), err))
+            }),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                let guid = random::random_u64();
+                fs::write(path, format!("{}\n", guid))?;
+                Ok(guid)
+            },
+            Err(err) => Err(err),
         }
     }
-}
\ No newline at end of file
+
+    /// Loads a configuration from a TOML file or, if the extension of the path is
+    /// `json`, from a JSON file. The loaded configuration is validated.
+    #[cfg(feature = "serde")]
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Config, ConfigFileError> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path).map_err(ConfigFileError::Io)?;
+        let config: Config = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => serde_json::from_str(&contents).map_err(|err| ConfigFileError::Parse(err.to_string()))?,
+            _ => toml::from_str(&contents).map_err(|err| ConfigFileError::Parse(err.to_string()))?,
+        };
+        config.validate().map_err(ConfigFileError::Invalid)?;
+        Ok(config)
+    }
+
+    /// Returns an error if the configuration is inconsistent, e.g. if the MTU sizes are out
+    /// of range or a timeout is shorter than the time between the attempts it limits.
+    /// Creating a `Peer` with an invalid configuration fails with `Error::InvalidConfig`.
+    pub fn validate(&self) -> Result<(), InvalidConfig> {
+        if self.min_mtu_size < MINIMUM_MTU_SIZE || self.max_mtu_size > MAXIMUM_MTU_SIZE || self.min_mtu_size > self.max_mtu_size {
+            return Err(InvalidConfig::MtuSizes { min_mtu_size: self.min_mtu_size, max_mtu_size: self.max_mtu_size });
+        }
+        if self.connection_attempt_count == 0 {
+            return Err(InvalidConfig::ZeroValue("connection_attempt_count"));
+        }
+        if self.max_datagrams_per_tick == 0 {
+            return Err(InvalidConfig::ZeroValue("max_datagrams_per_tick"));
+        }
+        if self.tick_interval_in_ms == 0 {
+            return Err(InvalidConfig::ZeroValue("tick_interval_in_ms"));
+        }
+        if self.max_incoming_split_packet_count == 0 {
+            return Err(InvalidConfig::ZeroValue("max_incoming_split_packet_count"));
+        }
+        if self.inbound_limit_action == InboundLimitAction::Ban && self.inbound_limit_penalty_in_ms == 0 {
+            return Err(InvalidConfig::ZeroValue("inbound_limit_penalty_in_ms"));
+        }
+        if self.incoming_password.as_ref().is_some_and(|password| password.len() > MAX_PASSWORD_LENGTH) {
+            return Err(InvalidConfig::TooLarge { field: "incoming_password", max: MAX_PASSWORD_LENGTH as u64 });
+        }
+        if self.handoff_key.is_some() && self.handoff_token_lifetime_in_ms == 0 {
+            return Err(InvalidConfig::ZeroValue("handoff_token_lifetime_in_ms"));
+        }
+        #[cfg(feature = "rand")]
+        if self.proof_of_work_difficulty > MAX_PROOF_OF_WORK_DIFFICULTY {
+            return Err(InvalidConfig::TooLarge { field: "proof_of_work_difficulty", max: MAX_PROOF_OF_WORK_DIFFICULTY as u64 });
+        }
+        if self.max_new_connections_per_second > 0 && self.new_connection_burst == 0 {
+            return Err(InvalidConfig::ZeroValue("new_connection_burst"));
+        }
+        if self.time_between_connection_attempts_in_ms >= self.connection_timeout_in_ms {
+            return Err(InvalidConfig::TimeoutOrder { shorter: "time_between_connection_attempts_in_ms", longer: "connection_timeout_in_ms" });
+        }
+        if self.ping_interval_in_ms >= self.ack_timeout_in_ms {
+            return Err(InvalidConfig::TimeoutOrder { shorter: "ping_interval_in_ms", longer: "ack_timeout_in_ms" });
+        }
+        if self.reconnect_initial_delay_in_ms > self.reconnect_max_delay_in_ms {
+            return Err(InvalidConfig::TimeoutOrder { shorter: "reconnect_initial_delay_in_ms", longer: "reconnect_max_delay_in_ms" });
+        }
+        // Without a secure random source the GUID must be set
+        if !cfg!(feature = "rand") && self.guid == 0 {
+            return Err(InvalidConfig::ZeroValue("guid"));
+        }
+        Ok(())
+    }
+}
+
+/// The reason a `Config` is invalid, returned by `Config::validate` and `ConfigBuilder::build`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum InvalidConfig {
+    /// The MTU sizes are not within the supported range or the minimum is larger than the maximum.
+    MtuSizes { min_mtu_size: u16, max_mtu_size: u16 },
+    /// A field that must be larger than 0 is 0.
+    ZeroValue(&'static str),
+    /// A time that must be shorter than another time is not.
+    TimeoutOrder { shorter: &'static str, longer: &'static str },
+    /// A field is larger than its maximum.
+    TooLarge { field: &'static str, max: u64 },
+}
+
+impl std::error::Error for InvalidConfig {}
+
+impl fmt::Display for InvalidConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InvalidConfig::MtuSizes { min_mtu_size, max_mtu_size } =>
+                write!(f, "MTU sizes {}-{} are not within {}-{}", min_mtu_size, max_mtu_size, MINIMUM_MTU_SIZE, MAXIMUM_MTU_SIZE),
+            InvalidConfig::ZeroValue(field) => write!(f, "{} must be larger than 0", field),
+            InvalidConfig::TimeoutOrder { shorter, longer } => write!(f, "{} must be shorter than {}", shorter, longer),
+            InvalidConfig::TooLarge { field, max } => write!(f, "{} must not be larger than {}", field, max),
+        }
+    }
+}
+
+/// Times in milliseconds are stored as `u64` in files since TOML has no larger integers.
+#[cfg(feature = "serde")]
+mod millis {
+    use std::convert::TryFrom;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(millis: &u128, serializer: S) -> Result<S::Ok, S::Error> {
+        u64::try_from(*millis).unwrap_or(u64::MAX).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u128, D::Error> {
+        u64::deserialize(deserializer).map(u128::from)
+    }
+}
+
+/// The reason a configuration could not be loaded with `Config::from_path`.
+#[cfg(feature = "serde")]
+#[derive(Debug)]
+pub enum ConfigFileError {
+    /// The file could not be read.
+    Io(io::Error),
+    /// The file is not valid TOML or JSON or has fields of the wrong type.
+    Parse(String),
+    /// The loaded configuration is invalid.
+    Invalid(InvalidConfig),
+}
+
+#[cfg(feature = "serde")]
+impl std::error::Error for ConfigFileError {}
+
+#[cfg(feature = "serde")]
+impl fmt::Display for ConfigFileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigFileError::Io(err) => write!(f, "Could not read the config file: {}", err),
+            ConfigFileError::Parse(err) => write!(f, "Could not parse the config file: {}", err),
+            ConfigFileError::Invalid(err) => write!(f, "Invalid config: {}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ConfigBuilder, InvalidConfig};
+    use super::Config;
+
+    #[test]
+    #[cfg(feature = "rand")]
+    fn presets_are_valid() {
+        // Act/Assert
+        assert_eq!(Ok(()), Config::default().validate());
+        assert_eq!(Ok(()), Config::lan_server().validate());
+        assert_eq!(Ok(()), Config::internet_server().validate());
+        assert_eq!(Ok(()), Config::client().validate());
+    }
+
+    #[test]
+    fn builder_sets_fields() {
+        // Act
+        let config = ConfigBuilder::from(Config::client())
+            .guid(5)
+            .max_mtu_size(1200)
+            .build()
+            .expect("Invalid config");
+
+        // Assert
+        assert_eq!(5, config.guid);
+        assert_eq!(1200, config.max_mtu_size);
+        assert_eq!(0, config.max_incoming_connections);
+    }
+
+    #[test]
+    fn builder_rejects_invalid_config() {
+        // Act
+        let mtu_result = Config::builder().min_mtu_size(1400).max_mtu_size(1200).build();
+        let small_mtu_result = Config::builder().min_mtu_size(100).build();
+        let zero_result = Config::builder().connection_attempt_count(0).build();
+        let zero_tick_interval_result = Config::builder().tick_interval_in_ms(0).build();
+        let timeout_result = Config::builder().connection_timeout_in_ms(100).build();
+        let password_result = Config::builder().incoming_password(Some(vec![0; 256])).build();
+
+        // Assert
+        assert_eq!(Some(InvalidConfig::MtuSizes { min_mtu_size: 1400, max_mtu_size: 1200 }), mtu_result.err());
+        assert_eq!(Some(InvalidConfig::MtuSizes { min_mtu_size: 100, max_mtu_size: 1492 }), small_mtu_result.err());
+        assert_eq!(Some(InvalidConfig::ZeroValue("connection_attempt_count")), zero_result.err());
+        assert_eq!(Some(InvalidConfig::ZeroValue("tick_interval_in_ms")), zero_tick_interval_result.err());
+        assert_eq!(Some(InvalidConfig::TimeoutOrder { shorter: "time_between_connection_attempts_in_ms", longer: "connection_timeout_in_ms" }), timeout_result.err());
+        assert_eq!(Some(InvalidConfig::TooLarge { field: "incoming_password", max: 255 }), password_result.err());
+    }
+
+    #[test]
+    #[cfg(feature = "rand")]
+    fn guid_is_persisted_in_file() {
+        // Arrange
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("raknet-guid-{}", std::process::id()));
+        let invalid_path = dir.join(format!("raknet-guid-invalid-{}", std::process::id()));
+        std::fs::write(&invalid_path, "not a guid").expect("Could not write file");
+
+        // Act
+        let created_guid = Config::load_or_create_guid(&path);
+        let loaded_guid = Config::load_or_create_guid(&path);
+        let invalid_result = Config::load_or_create_guid(&invalid_path);
+        std::fs::remove_file(&path).expect("Could not remove file");
+        std::fs::remove_file(&invalid_path).expect("Could not remove file");
+
+        // Assert
+        let created_guid = created_guid.expect("Could not create GUID");
+        assert_eq!(created_guid, loaded_guid.expect("Could not load GUID"));
+        assert_eq!(std::io::ErrorKind::InvalidData, invalid_result.expect_err("Invalid GUID loaded").kind());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn config_is_loaded_from_toml_and_json_files() {
+        // Arrange
+        let dir = std::env::temp_dir();
+        let toml_path = dir.join(format!("raknet-config-{}.toml", std::process::id()));
+        let json_path = dir.join(format!("raknet-config-{}.json", std::process::id()));
+        std::fs::write(&toml_path, "guid = 7\nmax_incoming_connections = 20\nack_timeout_in_ms = 8000\ninbound_limit_action = \"Ban\"\n\n[allow_list]\nranges = [\"10.0.0.0/8\"]\n").expect("Could not write file");
+        std::fs::write(&json_path, r#"{ "guid": 8, "max_mtu_size": 1200 }"#).expect("Could not write file");
+
+        // Act
+        let toml_config = Config::from_path(&toml_path);
+        let json_config = Config::from_path(&json_path);
+        std::fs::remove_file(&toml_path).expect("Could not remove file");
+        std::fs::remove_file(&json_path).expect("Could not remove file");
+
+        // Assert
+        let toml_config = toml_config.expect("Could not load TOML config");
+        assert_eq!(7, toml_config.guid);
+        assert_eq!(20, toml_config.max_incoming_connections);
+        assert_eq!(8000, toml_config.ack_timeout_in_ms);
+        assert_eq!(crate::InboundLimitAction::Ban, toml_config.inbound_limit_action);
+        assert_eq!(Config::default().connection_timeout_in_ms, toml_config.connection_timeout_in_ms);
+        let allow_list = toml_config.allow_list.expect("Allow list not loaded");
+        assert_eq!(vec!["10.0.0.0/8".parse::<crate::IpRange>().expect("Could not create range")], allow_list.ranges);
+        assert!(allow_list.guids.is_empty());
+        assert_eq!(None, json_config.as_ref().ok().and_then(|config| config.allow_list.clone()));
+        let json_config = json_config.expect("Could not load JSON config");
+        assert_eq!(8, json_config.guid);
+        assert_eq!(1200, json_config.max_mtu_size);
+    }
+}
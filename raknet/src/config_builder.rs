@@ -0,0 +1,347 @@
+#[cfg(feature = "rand")]
+use std::{io, path::Path};
+
+use crate::{config::InvalidConfig, AllowList, Config, InboundLimitAction, SocketAddrFormat, SocketOptions};
+#[cfg(feature = "rand")]
+use crate::SecurityConfig;
+
+/// Builds a `Config` that is validated before it is used, e.g.
+/// `Config::builder().max_incoming_connections(100).require_cookies(true).build()`.
+///
+/// Start from a preset with `ConfigBuilder::from(Config::internet_server())`.
+/// Every setter sets the `Config` field of the same name.
+#[derive(Clone)]
+pub struct ConfigBuilder {
+    config: Config,
+}
+
+impl ConfigBuilder {
+    /// Creates a builder starting from the default configuration.
+    pub fn new() -> Self {
+        ConfigBuilder { config: Config::default() }
+    }
+
+    /// Returns the configuration or an error if it is invalid, see `Config::validate`.
+    pub fn build(self) -> Result<Config, InvalidConfig> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+
+    /// Sets `Config::guid`. Use a fixed GUID to let remote peers recognize this peer across restarts.
+    pub fn guid(mut self, guid: u64) -> Self {
+        self.config.guid = guid;
+        self
+    }
+
+    /// Sets `Config::guid` to the GUID stored in the file at the path,
+    /// creating the file with a random GUID if needed, see `Config::load_or_create_guid`.
+    #[cfg(feature = "rand")]
+    pub fn guid_from_path<P: AsRef<Path>>(mut self, path: P) -> io::Result<Self> {
+        self.config.guid = Config::load_or_create_guid(path)?;
+        Ok(self)
+    }
+
+    /// Sets `Config::max_incoming_connections`.
+    pub fn max_incoming_connections(mut self, max_incoming_connections: usize) -> Self {
+        self.config.max_incoming_connections = max_incoming_connections;
+        self
+    }
+
+    /// Sets `Config::incoming_connection_timeout_in_ms`.
+    pub fn incoming_connection_timeout_in_ms(mut self, incoming_connection_timeout_in_ms: u128) -> Self {
+        self.config.incoming_connection_timeout_in_ms = incoming_connection_timeout_in_ms;
+        self
+    }
+
+    /// Sets `Config::ack_timeout_in_ms`.
+    pub fn ack_timeout_in_ms(mut self, ack_timeout_in_ms: u128) -> Self {
+        self.config.ack_timeout_in_ms = ack_timeout_in_ms;
+        self
+    }
+
+    /// Sets `Config::connection_attempt_count`.
+    pub fn connection_attempt_count(mut self, connection_attempt_count: u32) -> Self {
+        self.config.connection_attempt_count = connection_attempt_count;
+        self
+    }
+
+    /// Sets `Config::time_between_connection_attempts_in_ms`.
+    pub fn time_between_connection_attempts_in_ms(mut self, time_between_connection_attempts_in_ms: u128) -> Self {
+        self.config.time_between_connection_attempts_in_ms = time_between_connection_attempts_in_ms;
+        self
+    }
+
+    /// Sets `Config::connection_timeout_in_ms`.
+    pub fn connection_timeout_in_ms(mut self, connection_timeout_in_ms: u128) -> Self {
+        self.config.connection_timeout_in_ms = connection_timeout_in_ms;
+        self
+    }
+
+    /// Sets `Config::prefer_ipv6`.
+    pub fn prefer_ipv6(mut self, prefer_ipv6: bool) -> Self {
+        self.config.prefer_ipv6 = prefer_ipv6;
+        self
+    }
+
+    /// Sets `Config::ipv6_only`.
+    pub fn ipv6_only(mut self, ipv6_only: bool) -> Self {
+        self.config.ipv6_only = ipv6_only;
+        self
+    }
+
+    /// Sets `Config::socket_addr_format`.
+    pub fn socket_addr_format(mut self, socket_addr_format: SocketAddrFormat) -> Self {
+        self.config.socket_addr_format = socket_addr_format;
+        self
+    }
+
+    /// Sets `Config::socket_options`.
+    pub fn socket_options(mut self, socket_options: SocketOptions) -> Self {
+        self.config.socket_options = socket_options;
+        self
+    }
+
+    /// Sets `Config::incoming_password`.
+    pub fn incoming_password(mut self, incoming_password: Option<Vec<u8>>) -> Self {
+        self.config.incoming_password = incoming_password;
+        self
+    }
+
+    /// Sets `Config::reconnect_initial_delay_in_ms`.
+    pub fn reconnect_initial_delay_in_ms(mut self, reconnect_initial_delay_in_ms: u128) -> Self {
+        self.config.reconnect_initial_delay_in_ms = reconnect_initial_delay_in_ms;
+        self
+    }
+
+    /// Sets `Config::reconnect_max_delay_in_ms`.
+    pub fn reconnect_max_delay_in_ms(mut self, reconnect_max_delay_in_ms: u128) -> Self {
+        self.config.reconnect_max_delay_in_ms = reconnect_max_delay_in_ms;
+        self
+    }
+
+    /// Sets `Config::max_datagrams_per_tick`.
+    pub fn max_datagrams_per_tick(mut self, max_datagrams_per_tick: usize) -> Self {
+        self.config.max_datagrams_per_tick = max_datagrams_per_tick;
+        self
+    }
+
+    /// Sets `Config::max_queued_datagrams`.
+    pub fn max_queued_datagrams(mut self, max_queued_datagrams: usize) -> Self {
+        self.config.max_queued_datagrams = max_queued_datagrams;
+        self
+    }
+
+    /// Sets `Config::tick_interval_in_ms`.
+    pub fn tick_interval_in_ms(mut self, tick_interval_in_ms: u128) -> Self {
+        self.config.tick_interval_in_ms = tick_interval_in_ms;
+        self
+    }
+
+    /// Sets `Config::idle_tick_interval_in_ms`.
+    pub fn idle_tick_interval_in_ms(mut self, idle_tick_interval_in_ms: u128) -> Self {
+        self.config.idle_tick_interval_in_ms = idle_tick_interval_in_ms;
+        self
+    }
+
+    /// Sets `Config::slow_tick_threshold_in_ms`.
+    pub fn slow_tick_threshold_in_ms(mut self, slow_tick_threshold_in_ms: u128) -> Self {
+        self.config.slow_tick_threshold_in_ms = slow_tick_threshold_in_ms;
+        self
+    }
+
+    /// Sets `Config::ordering_blocked_threshold_in_ms`.
+    pub fn ordering_blocked_threshold_in_ms(mut self, ordering_blocked_threshold_in_ms: u128) -> Self {
+        self.config.ordering_blocked_threshold_in_ms = ordering_blocked_threshold_in_ms;
+        self
+    }
+
+    /// Sets `Config::ping_interval_in_ms`.
+    pub fn ping_interval_in_ms(mut self, ping_interval_in_ms: u128) -> Self {
+        self.config.ping_interval_in_ms = ping_interval_in_ms;
+        self
+    }
+
+    /// Sets `Config::connection_quality_interval_in_ms`.
+    pub fn connection_quality_interval_in_ms(mut self, connection_quality_interval_in_ms: u128) -> Self {
+        self.config.connection_quality_interval_in_ms = connection_quality_interval_in_ms;
+        self
+    }
+
+    /// Sets `Config::max_retransmissions`.
+    pub fn max_retransmissions(mut self, max_retransmissions: u32) -> Self {
+        self.config.max_retransmissions = max_retransmissions;
+        self
+    }
+
+    /// Sets `Config::unreliable_timeout_in_ms`.
+    pub fn unreliable_timeout_in_ms(mut self, unreliable_timeout_in_ms: u128) -> Self {
+        self.config.unreliable_timeout_in_ms = unreliable_timeout_in_ms;
+        self
+    }
+
+    /// Sets `Config::coalescing_delay_in_ms`.
+    pub fn coalescing_delay_in_ms(mut self, coalescing_delay_in_ms: u128) -> Self {
+        self.config.coalescing_delay_in_ms = coalescing_delay_in_ms;
+        self
+    }
+
+    /// Sets `Config::max_outgoing_bytes_per_second`.
+    pub fn max_outgoing_bytes_per_second(mut self, max_outgoing_bytes_per_second: u64) -> Self {
+        self.config.max_outgoing_bytes_per_second = max_outgoing_bytes_per_second;
+        self
+    }
+
+    /// Sets `Config::min_mtu_size`.
+    pub fn min_mtu_size(mut self, min_mtu_size: u16) -> Self {
+        self.config.min_mtu_size = min_mtu_size;
+        self
+    }
+
+    /// Sets `Config::max_mtu_size`.
+    pub fn max_mtu_size(mut self, max_mtu_size: u16) -> Self {
+        self.config.max_mtu_size = max_mtu_size;
+        self
+    }
+
+    /// Sets `Config::max_incoming_split_packet_count`.
+    pub fn max_incoming_split_packet_count(mut self, max_incoming_split_packet_count: u32) -> Self {
+        self.config.max_incoming_split_packet_count = max_incoming_split_packet_count;
+        self
+    }
+
+    /// Sets `Config::drop_connection_on_invalid_split_packet`.
+    pub fn drop_connection_on_invalid_split_packet(mut self, drop_connection_on_invalid_split_packet: bool) -> Self {
+        self.config.drop_connection_on_invalid_split_packet = drop_connection_on_invalid_split_packet;
+        self
+    }
+
+    /// Sets `Config::max_incoming_packets_per_second`.
+    pub fn max_incoming_packets_per_second(mut self, max_incoming_packets_per_second: u32) -> Self {
+        self.config.max_incoming_packets_per_second = max_incoming_packets_per_second;
+        self
+    }
+
+    /// Sets `Config::max_incoming_bytes_per_second`.
+    pub fn max_incoming_bytes_per_second(mut self, max_incoming_bytes_per_second: u64) -> Self {
+        self.config.max_incoming_bytes_per_second = max_incoming_bytes_per_second;
+        self
+    }
+
+    /// Sets `Config::max_incoming_payload_size`.
+    pub fn max_incoming_payload_size(mut self, max_incoming_payload_size: usize) -> Self {
+        self.config.max_incoming_payload_size = max_incoming_payload_size;
+        self
+    }
+
+    /// Sets `Config::inbound_limit_action`.
+    pub fn inbound_limit_action(mut self, inbound_limit_action: InboundLimitAction) -> Self {
+        self.config.inbound_limit_action = inbound_limit_action;
+        self
+    }
+
+    /// Sets `Config::inbound_limit_penalty_in_ms`.
+    pub fn inbound_limit_penalty_in_ms(mut self, inbound_limit_penalty_in_ms: u128) -> Self {
+        self.config.inbound_limit_penalty_in_ms = inbound_limit_penalty_in_ms;
+        self
+    }
+
+    /// Sets `Config::security`.
+    #[cfg(feature = "rand")]
+    pub fn security(mut self, security: Option<SecurityConfig>) -> Self {
+        self.config.security = security;
+        self
+    }
+
+    /// Sets `Config::require_cookies`.
+    #[cfg(feature = "rand")]
+    pub fn require_cookies(mut self, require_cookies: bool) -> Self {
+        self.config.require_cookies = require_cookies;
+        self
+    }
+
+    /// Sets `Config::proof_of_work_difficulty`.
+    #[cfg(feature = "rand")]
+    pub fn proof_of_work_difficulty(mut self, proof_of_work_difficulty: u8) -> Self {
+        self.config.proof_of_work_difficulty = proof_of_work_difficulty;
+        self
+    }
+
+    /// Sets `Config::proof_of_work_connection_threshold`.
+    #[cfg(feature = "rand")]
+    pub fn proof_of_work_connection_threshold(mut self, proof_of_work_connection_threshold: usize) -> Self {
+        self.config.proof_of_work_connection_threshold = proof_of_work_connection_threshold;
+        self
+    }
+
+    /// Sets `Config::max_offline_packets_per_second_per_ip`.
+    pub fn max_offline_packets_per_second_per_ip(mut self, max_offline_packets_per_second_per_ip: u32) -> Self {
+        self.config.max_offline_packets_per_second_per_ip = max_offline_packets_per_second_per_ip;
+        self
+    }
+
+    /// Sets `Config::max_new_connections_per_second`.
+    pub fn max_new_connections_per_second(mut self, max_new_connections_per_second: u32) -> Self {
+        self.config.max_new_connections_per_second = max_new_connections_per_second;
+        self
+    }
+
+    /// Sets `Config::new_connection_burst`.
+    pub fn new_connection_burst(mut self, new_connection_burst: u32) -> Self {
+        self.config.new_connection_burst = new_connection_burst;
+        self
+    }
+
+    /// Sets `Config::recent_connection_window_in_ms`.
+    pub fn recent_connection_window_in_ms(mut self, recent_connection_window_in_ms: u128) -> Self {
+        self.config.recent_connection_window_in_ms = recent_connection_window_in_ms;
+        self
+    }
+
+    /// Sets `Config::greylist_duration_in_ms`.
+    pub fn greylist_duration_in_ms(mut self, greylist_duration_in_ms: u128) -> Self {
+        self.config.greylist_duration_in_ms = greylist_duration_in_ms;
+        self
+    }
+
+    /// Sets `Config::garbage_penalty_in_ms`.
+    pub fn garbage_penalty_in_ms(mut self, garbage_penalty_in_ms: u128) -> Self {
+        self.config.garbage_penalty_in_ms = garbage_penalty_in_ms;
+        self
+    }
+
+    /// Sets `Config::pre_shared_key`.
+    pub fn pre_shared_key(mut self, pre_shared_key: Option<Vec<u8>>) -> Self {
+        self.config.pre_shared_key = pre_shared_key;
+        self
+    }
+
+    /// Sets `Config::allow_list`.
+    pub fn allow_list(mut self, allow_list: Option<AllowList>) -> Self {
+        self.config.allow_list = allow_list;
+        self
+    }
+
+    /// Sets `Config::handoff_key`.
+    pub fn handoff_key(mut self, handoff_key: Option<Vec<u8>>) -> Self {
+        self.config.handoff_key = handoff_key;
+        self
+    }
+
+    /// Sets `Config::handoff_token_lifetime_in_ms`.
+    pub fn handoff_token_lifetime_in_ms(mut self, handoff_token_lifetime_in_ms: u128) -> Self {
+        self.config.handoff_token_lifetime_in_ms = handoff_token_lifetime_in_ms;
+        self
+    }
+}
+
+impl Default for ConfigBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<Config> for ConfigBuilder {
+    fn from(config: Config) -> Self {
+        ConfigBuilder { config }
+    }
+}
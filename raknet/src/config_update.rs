@@ -0,0 +1,19 @@
+/// Changes to the configuration of a running `Peer` made with `Peer::update_config`
+/// or `Command::UpdateConfig`. Fields that are None are left unchanged.
+///
+/// All changes are applied together before the next processing round.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ConfigUpdate {
+    /// Sets `Config::max_incoming_connections`. Existing connections are kept
+    /// even if there are more of them than the new maximum.
+    pub max_incoming_connections: Option<usize>,
+
+    /// Sets the response returned to an offline ping packet, see `Peer::set_offline_ping_response`.
+    pub offline_ping_response: Option<Vec<u8>>,
+
+    /// Sets `Config::ack_timeout_in_ms` for all connections.
+    pub ack_timeout_in_ms: Option<u128>,
+
+    /// Sets `Config::max_outgoing_bytes_per_second`, the bandwidth cap of all connections together.
+    pub max_outgoing_bytes_per_second: Option<u64>,
+}
@@ -0,0 +1,269 @@
+use std::time::Instant;
+
+use crate::{constants::DATA_ARRIVAL_RATE_HEADROOM, number::DatagramSequenceNumber};
+
+/// Sliding window congestion control as used by RakNet.
+///
+/// The congestion window (cwnd) is the number of bytes that may be sent without
+/// having been acknowledged. The window starts at one MTU and grows by one MTU
+/// for every acknowledged datagram (slow start) until it reaches the slow start
+/// threshold (ssthresh). After that it grows by roughly one MTU per round trip
+/// (congestion avoidance).
+///
+/// A NACK sets the slow start threshold to half the window and a resend due to a
+/// timeout also shrinks the window to one MTU. The window is backed off at most
+/// once for all datagrams that were sent before the back off.
+///
+/// When the remote peer has reported the rate that data arrives at its end the
+/// send rate is also bounded by that rate (with some headroom to let it grow).
+#[derive(Debug)]
+pub struct CongestionControl {
+    mtu: usize,
+    cwnd: usize,
+    ssthresh: usize,
+    next_congestion_control_block: DatagramSequenceNumber,
+    backoff_this_block: bool,
+    max_send_rate: Option<f32>,
+    send_tokens: f64,
+    last_send_time: Option<Instant>,
+}
+
+impl CongestionControl {
+    pub fn new(mtu: u16) -> Self {
+        CongestionControl {
+            mtu: mtu as usize,
+            cwnd: mtu as usize,
+            ssthresh: 0,
+            next_congestion_control_block: DatagramSequenceNumber::ZERO,
+            backoff_this_block: false,
+            max_send_rate: None,
+            send_tokens: 0.0,
+            last_send_time: None,
+        }
+    }
+
+    /// Sets the MTU, e.g. when the MTU has been reduced. The window is
+    /// kept at least one MTU.
+    pub fn set_mtu(&mut self, mtu: u16) {
+        self.mtu = mtu as usize;
+        self.cwnd = self.cwnd.max(self.mtu);
+    }
+
+    /// Returns the congestion window in bytes.
+    #[cfg(test)]
+    pub fn window_size(&self) -> usize {
+        self.cwnd
+    }
+
+    /// Returns the slow start threshold in bytes or 0 if it has not been set yet.
+    #[cfg(test)]
+    pub fn slow_start_threshold(&self) -> usize {
+        self.ssthresh
+    }
+
+    /// Returns true if the window is growing exponentially.
+    pub fn is_in_slow_start(&self) -> bool {
+        self.ssthresh == 0 || self.cwnd <= self.ssthresh
+    }
+
+    /// Returns the maximum send rate in bytes per microsecond or None
+    /// if the remote peer has not reported its data arrival rate.
+    #[cfg(test)]
+    pub fn max_send_rate(&self) -> Option<f32> {
+        self.max_send_rate
+    }
+
+    /// Returns true if another datagram may be sent when `bytes_in_flight`
+    /// bytes have been sent but not yet acknowledged.
+    pub fn has_room_for_datagram(&self, time: Instant, bytes_in_flight: usize) -> bool {
+        bytes_in_flight < self.cwnd && self.available_send_bytes(time) > 0.0
+    }
+
+    /// Registers that a datagram of `size` bytes has been sent.
+    pub fn on_datagram_sent(&mut self, time: Instant, size: usize) {
+        if self.max_send_rate.is_some() {
+            self.send_tokens = self.available_send_bytes(time) - size as f64;
+            self.last_send_time = Some(time);
+        }
+    }
+
+    /// Registers the data arrival rate in bytes per microsecond reported by the remote peer.
+    pub fn on_data_arrival_rate(&mut self, data_arrival_rate: f32) {
+        if data_arrival_rate > 0.0 {
+            self.max_send_rate = Some(data_arrival_rate * DATA_ARRIVAL_RATE_HEADROOM);
+        }
+    }
+
+    /// Returns the number of bytes that may be sent at the specified time without
+    /// exceeding the maximum send rate. Bursts up to the congestion window are allowed.
+    fn available_send_bytes(&self, time: Instant) -> f64 {
+        match (self.max_send_rate, self.last_send_time) {
+            (Some(max_send_rate), Some(last_send_time)) => {
+                let elapsed_us = time.saturating_duration_since(last_send_time).as_micros() as f64;
+                (self.send_tokens + max_send_rate as f64 * elapsed_us).min(self.cwnd as f64)
+            },
+            _ => self.cwnd as f64,
+        }
+    }
+
+    /// Registers that the datagram with the specified number has been acknowledged.
+    /// `next_datagram_number` is the number of the next datagram to be sent.
+    pub fn on_ack(&mut self, datagram_number: DatagramSequenceNumber, next_datagram_number: DatagramSequenceNumber) {
+        let is_new_congestion_control_period = self.start_new_period_if_passed(datagram_number, next_datagram_number);
+
+        if self.is_in_slow_start() {
+            self.cwnd += self.mtu;
+            if self.ssthresh != 0 && self.cwnd > self.ssthresh {
+                self.cwnd = self.ssthresh + self.mtu * self.mtu / self.cwnd;
+            }
+        } else if is_new_congestion_control_period {
+            self.cwnd += self.mtu * self.mtu / self.cwnd;
+        }
+    }
+
+    /// Registers that the datagram with the specified number has been NACK:ed
+    /// by the remote peer.
+    pub fn on_nack(&mut self, datagram_number: DatagramSequenceNumber, next_datagram_number: DatagramSequenceNumber) {
+        self.start_new_period_if_passed(datagram_number, next_datagram_number);
+        if !self.backoff_this_block {
+            self.ssthresh = (self.cwnd / 2).max(self.mtu);
+            self.backoff_this_block = true;
+        }
+    }
+
+    /// Registers that a datagram is resent since no ACK was received in time.
+    /// `next_datagram_number` is the number of the next datagram to be sent.
+    pub fn on_resend(&mut self, next_datagram_number: DatagramSequenceNumber) {
+        if !self.backoff_this_block && self.cwnd > self.mtu * 2 {
+            self.ssthresh = (self.cwnd / 2).max(self.mtu);
+            self.cwnd = self.mtu;
+            self.next_congestion_control_block = next_datagram_number;
+            self.backoff_this_block = true;
+        }
+    }
+
+    /// Starts a new congestion control period if a datagram sent after the
+    /// start of the current period has been acknowledged.
+    fn start_new_period_if_passed(&mut self, datagram_number: DatagramSequenceNumber, next_datagram_number: DatagramSequenceNumber) -> bool {
+        if self.next_congestion_control_block.wrapping_less_than(datagram_number) || self.next_congestion_control_block == datagram_number {
+            self.next_congestion_control_block = next_datagram_number;
+            self.backoff_this_block = false;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+    use crate::{constants::DATA_ARRIVAL_RATE_HEADROOM, number::DatagramSequenceNumber};
+    use super::CongestionControl;
+
+    fn number(n: u32) -> DatagramSequenceNumber {
+        DatagramSequenceNumber::from_masked_u32(n)
+    }
+
+    #[test]
+    fn window_starts_at_one_mtu() {
+        // Arrange
+        let congestion_control = CongestionControl::new(1000);
+
+        // Act/Assert
+        assert_eq!(1000, congestion_control.window_size());
+        assert!(congestion_control.is_in_slow_start());
+        assert!(congestion_control.has_room_for_datagram(Instant::now(), 999));
+        assert!(!congestion_control.has_room_for_datagram(Instant::now(), 1000));
+    }
+
+    #[test]
+    fn on_ack_grows_window_by_one_mtu_in_slow_start() {
+        // Arrange
+        let mut congestion_control = CongestionControl::new(1000);
+
+        // Act
+        congestion_control.on_ack(number(0), number(3));
+        congestion_control.on_ack(number(1), number(3));
+        congestion_control.on_ack(number(2), number(3));
+
+        // Assert
+        assert_eq!(4000, congestion_control.window_size());
+    }
+
+    #[test]
+    fn on_nack_sets_slow_start_threshold_once_per_block() {
+        // Arrange
+        let mut congestion_control = CongestionControl::new(1000);
+        for n in 0..7 {
+            congestion_control.on_ack(number(n), number(8));
+        }
+
+        // Act
+        congestion_control.on_nack(number(7), number(8));
+        let ssthresh_after_first_nack = congestion_control.slow_start_threshold();
+        congestion_control.on_nack(number(6), number(8));
+
+        // Assert
+        assert_eq!(4000, ssthresh_after_first_nack);
+        assert_eq!(4000, congestion_control.slow_start_threshold());
+        assert!(!congestion_control.is_in_slow_start());
+    }
+
+    #[test]
+    fn on_resend_shrinks_window_to_one_mtu() {
+        // Arrange
+        let mut congestion_control = CongestionControl::new(1000);
+        for n in 0..5 {
+            congestion_control.on_ack(number(n), number(10));
+        }
+
+        // Act
+        congestion_control.on_resend(number(10));
+
+        // Assert
+        assert_eq!(1000, congestion_control.window_size());
+        assert_eq!(3000, congestion_control.slow_start_threshold());
+    }
+
+    #[test]
+    fn on_ack_grows_window_once_per_period_in_congestion_avoidance() {
+        // Arrange
+        let mut congestion_control = CongestionControl::new(1000);
+        for n in 0..5 {
+            congestion_control.on_ack(number(n), number(10));
+        }
+        congestion_control.on_resend(number(10));
+        congestion_control.on_ack(number(10), number(11));
+        congestion_control.on_ack(number(11), number(12));
+        congestion_control.on_ack(number(12), number(13));
+        let window_size = congestion_control.window_size();
+
+        // Act
+        congestion_control.on_ack(number(13), number(20));
+        congestion_control.on_ack(number(14), number(20));
+        congestion_control.on_ack(number(15), number(20));
+
+        // Assert
+        assert!(!congestion_control.is_in_slow_start());
+        assert_eq!(window_size + 1000 * 1000 / window_size, congestion_control.window_size());
+    }
+
+    #[test]
+    fn on_data_arrival_rate_bounds_send_rate() {
+        // Arrange
+        let time = Instant::now();
+        let mut congestion_control = CongestionControl::new(1000);
+        for n in 0..9 {
+            congestion_control.on_ack(number(n), number(10));
+        }
+        congestion_control.on_data_arrival_rate(0.5);
+        congestion_control.on_datagram_sent(time, 20000);
+
+        // Act/Assert
+        assert_eq!(Some(0.5 * DATA_ARRIVAL_RATE_HEADROOM), congestion_control.max_send_rate());
+        assert!(!congestion_control.has_room_for_datagram(time, 0));
+        assert!(!congestion_control.has_room_for_datagram(time + Duration::from_micros(9999), 0));
+        assert!(congestion_control.has_room_for_datagram(time + Duration::from_micros(10001), 0));
+    }
+}
@@ -0,0 +1,28 @@
+use crate::handoff_token::HandoffToken;
+
+/// Options for an outgoing connection started with `Peer::connect_with_options`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ConnectOptions {
+    /// The password sent in the connection request. Must match the
+    /// `Config::incoming_password` of the remote peer if it has one.
+    /// Longer passwords than 255 bytes are rejected by the remote peer.
+    pub password: Vec<u8>,
+
+    /// An opaque value chosen by the user that is passed through to the
+    /// `PeerEvent::ConnectionEstablished` or `PeerEvent::ConnectionAttemptFailed`
+    /// event of the connection, e.g. to match results to requests.
+    pub user_data: u64,
+
+    /// The public key of the remote peer, see `SecurityConfig::public_key`.
+    /// If set, the attempt fails unless the remote peer uses security and
+    /// proves that it has the matching private key. If not set, the security
+    /// handshake is still performed if the remote peer uses security
+    /// but any public key is accepted. Only available with the `rand` feature.
+    #[cfg(feature = "rand")]
+    pub server_public_key: Option<[u8; 32]>,
+
+    /// A handoff token created for this peer by a peer with the same `Config::handoff_key`
+    /// as the remote peer, see `HandoffToken`. It lets this peer connect without going
+    /// through the admission checks of the remote peer, e.g. when moved between servers.
+    pub handoff_token: Option<HandoffToken>,
+}
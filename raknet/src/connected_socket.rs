@@ -0,0 +1,109 @@
+use std::{
+    io,
+    net::{SocketAddr, UdpSocket},
+};
+
+use socket2::SockRef;
+
+use crate::{socket::{self, DatagramSocket}, socket_options, SocketOptions};
+
+/// A UDP socket connected to a single remote address, for clients that only
+/// connect to one server.
+///
+/// Datagrams are sent with `send` and received with `recv` so the addresses are not
+/// handled for every datagram, and the operating system drops datagrams from other
+/// addresses before they reach the peer. Sending to other addresses fails.
+pub struct ConnectedUdpSocket {
+    socket: UdpSocket,
+    remote_addr: SocketAddr,
+    /// The remote address in the form of the family of the socket.
+    connected_addr: SocketAddr,
+}
+
+impl ConnectedUdpSocket {
+    /// Connects the UDP socket to the remote address and makes it non-blocking.
+    pub fn new(socket: UdpSocket, remote_addr: SocketAddr) -> io::Result<Self> {
+        let connected_addr = socket::to_socket_family(remote_addr, socket.local_addr()?.is_ipv6());
+        socket.connect(connected_addr)?;
+        socket.set_nonblocking(true)?;
+        Ok(ConnectedUdpSocket {
+            socket,
+            remote_addr,
+            connected_addr,
+        })
+    }
+
+    /// Returns the address that the socket is connected to.
+    pub fn remote_addr(&self) -> SocketAddr {
+        self.remote_addr
+    }
+}
+
+impl DatagramSocket for ConnectedUdpSocket {
+    fn receive_datagram<'a>(&mut self, buf: &'a mut [u8]) -> io::Result<(&'a [u8], SocketAddr)> {
+        let remote_addr = self.remote_addr;
+        self.socket.recv(buf).map(move |n| (&buf[..n], remote_addr))
+    }
+
+    fn send_datagram(&mut self, payload: &[u8], addr: SocketAddr) -> io::Result<usize> {
+        if socket::to_socket_family(addr, self.connected_addr.is_ipv6()) != self.connected_addr {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("The socket is connected to {} and cannot send to {}", self.remote_addr, addr)));
+        }
+        self.socket.send(payload)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    fn set_options(&mut self, options: &SocketOptions) -> io::Result<()> {
+        socket_options::apply(SockRef::from(&self.socket), options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io, net::UdpSocket, thread, time::Duration};
+    use crate::socket::DatagramSocket;
+    use super::ConnectedUdpSocket;
+
+    #[test]
+    fn only_datagrams_from_remote_address_are_received() {
+        // Arrange
+        let server = UdpSocket::bind("127.0.0.1:0").expect("Could not bind socket");
+        let stranger = UdpSocket::bind("127.0.0.1:0").expect("Could not bind socket");
+        let server_addr = server.local_addr().expect("Could not get address");
+        let mut socket = ConnectedUdpSocket::new(UdpSocket::bind("127.0.0.1:0").expect("Could not bind socket"), server_addr)
+            .expect("Could not connect socket");
+        let addr = socket.local_addr().expect("Could not get address");
+        let mut buf = [0u8; 16];
+
+        // Act
+        stranger.send_to(&[9, 9, 9], addr).expect("Could not send datagram");
+        server.send_to(&[1, 2, 3], addr).expect("Could not send datagram");
+        thread::sleep(Duration::from_millis(10));
+        let received = socket.receive_datagram(&mut buf).map(|(payload, addr)| (payload.to_vec(), addr));
+
+        // Assert
+        assert_eq!((vec![1, 2, 3], server_addr), received.expect("Datagram not received"));
+        assert_eq!(io::ErrorKind::WouldBlock, socket.receive_datagram(&mut buf).map(|_| ()).expect_err("Received datagram").kind());
+    }
+
+    #[test]
+    fn datagrams_are_only_sent_to_remote_address() {
+        // Arrange
+        let server = UdpSocket::bind("127.0.0.1:0").expect("Could not bind socket");
+        let stranger = UdpSocket::bind("127.0.0.1:0").expect("Could not bind socket");
+        let server_addr = server.local_addr().expect("Could not get address");
+        let mut socket = ConnectedUdpSocket::new(UdpSocket::bind("127.0.0.1:0").expect("Could not bind socket"), server_addr)
+            .expect("Could not connect socket");
+
+        // Act
+        let sent_to_server = socket.send_datagram(&[1, 2, 3], server_addr);
+        let sent_to_stranger = socket.send_datagram(&[1, 2, 3], stranger.local_addr().expect("Could not get address"));
+
+        // Assert
+        assert_eq!(3, sent_to_server.expect("Could not send datagram"));
+        assert_eq!(io::ErrorKind::InvalidInput, sent_to_stranger.expect_err("Datagram sent").kind());
+    }
+}
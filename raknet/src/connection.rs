@@ -1,18 +1,28 @@
 use std::{convert::TryFrom, net::SocketAddr, time::Instant};
-use log::{debug, error};
+use subtle::ConstantTimeEq;
 
 use crate::{
     communicator::Communicator,
+    connection_attempt_failed_reason::ConnectionAttemptFailedReason,
+    connection_info::{ConnectionInfo, ConnectionPhase},
+    connection_limits::ConnectionLimits,
+    connection_lost_reason::ConnectionLostReason,
+    connection_stats::ConnectionStats,
     incoming_connection::IncomingConnection,
+    internal_packet::PacketPayload,
+    logging::{debug, error},
     message_ids::MessageId,
-    messages::{ConnectedPingMessage, ConnectedPongMessage, ConnectionRequestMessage, ConnectionRequestAcceptedMessage, NewIncomingConnectionMessage},
+    messages::{ConnectedPingMessage, ConnectedPongMessage, ConnectionRequestMessage, ConnectionRequestAcceptedMessage, InvalidPasswordMessage, NewIncomingConnectionMessage},
     packet::{Ordering, Packet, Priority, Reliability},
     PeerEvent,
     reader::{DataReader, MessageRead},
     reliability_layer::ReliabilityLayer,
+    Result,
     socket::DatagramSocket,
-    writer::MessageWrite
+    writer::{DataWriter, MessageWrite}
 };
+#[cfg(feature = "rand")]
+use crate::security::SessionKeys;
 
 pub struct Connection {
     reliability_layer: ReliabilityLayer,
@@ -22,23 +32,71 @@ pub struct Connection {
     remote_guid: u64,
     is_incoming: bool,
     mtu: u16,
+    external_addr: Option<SocketAddr>,
+    remote_internal_addrs: Vec<SocketAddr>,
+    user_data: u64,
+    /// The user data of the handoff token that an incoming connection was made with.
+    handoff_user_data: Option<u64>,
+    challenge_answer: Option<[u8; 128]>,
+    /// The reason the connection is dropped in the next update, if it has been closed.
+    close_reason: Option<ConnectionLostReason>,
+    last_ping_time: Instant,
+    /// The time the last `PeerEvent::ConnectionQuality` was sent.
+    last_quality_time: Instant,
+    /// The smoothed round trip time in milliseconds or None before the first pong.
+    ping: Option<u32>,
+    /// The time of the remote peer minus the time of this peer in milliseconds.
+    clock_differential: Option<i64>,
     pub state: ConnectionState,
 }
 
 impl Connection {
     pub fn incoming(connection_time: Instant, peer_creation_time: Instant, remote_addr: SocketAddr, remote_guid: u64, mtu: u16) -> Connection {
         Connection {
-            reliability_layer: ReliabilityLayer::new(remote_addr, remote_guid, mtu),
+            reliability_layer: ReliabilityLayer::new(connection_time, remote_addr, remote_guid, mtu),
             connection_time,
             peer_creation_time,
             remote_addr,
             remote_guid,
             is_incoming: true,
             mtu,
+            external_addr: None,
+            remote_internal_addrs: Vec::new(),
+            user_data: 0,
+            handoff_user_data: None,
+            challenge_answer: None,
+            close_reason: None,
+            last_ping_time: connection_time,
+            last_quality_time: connection_time,
+            ping: None,
+            clock_differential: None,
             state: ConnectionState::UnverifiedSender,
         }
     }
 
+    pub fn outgoing(time: Instant, connection_time: Instant, peer_creation_time: Instant, remote_addr: SocketAddr, remote_guid: u64, mtu: u16, user_data: u64) -> Connection {
+        Connection {
+            reliability_layer: ReliabilityLayer::new(time, remote_addr, remote_guid, mtu),
+            connection_time,
+            peer_creation_time,
+            remote_addr,
+            remote_guid,
+            is_incoming: false,
+            mtu,
+            external_addr: None,
+            remote_internal_addrs: Vec::new(),
+            user_data,
+            handoff_user_data: None,
+            challenge_answer: None,
+            close_reason: None,
+            last_ping_time: time,
+            last_quality_time: time,
+            ping: None,
+            clock_differential: None,
+            state: ConnectionState::RequestedConnection,
+        }
+    }
+
     /// Returns the GUID of the remote peer.
     pub fn guid(&self) -> u64 {
         self.remote_guid
@@ -49,20 +107,171 @@ impl Connection {
         self.mtu
     }
 
+    /// Registers the user data of the handoff token that the remote peer connected with.
+    pub fn set_handoff_user_data(&mut self, handoff_user_data: u64) {
+        self.handoff_user_data = Some(handoff_user_data);
+    }
+
+    /// Registers the keys derived in the security handshake. `challenge_answer` is the
+    /// answer sent to an incoming connection which is resent if the request is repeated.
+    #[cfg(feature = "rand")]
+    pub fn set_security(&mut self, session_keys: SessionKeys, challenge_answer: Option<[u8; 128]>) {
+        self.reliability_layer.enable_encryption(&session_keys);
+        self.challenge_answer = challenge_answer;
+    }
+
+    /// Returns the answer to the security challenge sent to
+    /// the remote peer of an incoming connection.
+    pub fn challenge_answer(&self) -> Option<[u8; 128]> {
+        self.challenge_answer
+    }
+
+    /// Returns the smoothed round trip time in milliseconds or
+    /// None if no pong has been received yet.
+    pub fn ping(&self) -> Option<u32> {
+        self.ping
+    }
+
+    /// Returns the time of the remote peer minus the time of this peer in
+    /// milliseconds or None if no pong has been received yet.
+    pub fn clock_differential(&self) -> Option<i64> {
+        self.clock_differential
+    }
+
     /// Returns true if the connection was initiated
     /// by a remote peer.
     pub fn is_incoming(&self) -> bool {
         self.is_incoming
     }
 
+    /// Returns the number of packets waiting to be sent.
+    pub fn queued_packet_count(&self) -> usize {
+        self.reliability_layer.queued_packet_count()
+    }
+
+    /// Returns the statistics of the connection.
+    pub fn stats(&self, time: Instant) -> ConnectionStats {
+        ConnectionStats {
+            ping_ms: self.ping,
+            ..self.reliability_layer.stats(time)
+        }
+    }
+
+    /// Returns a snapshot of the connection for the user.
+    pub fn info(&self, time: Instant) -> ConnectionInfo {
+        let phase = match self.state {
+            _ if self.close_reason.is_some() => ConnectionPhase::Disconnecting,
+            ConnectionState::UnverifiedSender |
+            ConnectionState::HandlingConnectionRequest |
+            ConnectionState::RequestedConnection => ConnectionPhase::Connecting,
+            ConnectionState::Connected => ConnectionPhase::Connected,
+            ConnectionState::DisconnectAsapSilently |
+            ConnectionState::DisconnectAsap |
+            ConnectionState::DisconnectNow |
+            ConnectionState::SentGarbage => ConnectionPhase::Disconnecting,
+        };
+        ConnectionInfo {
+            addr: self.remote_addr,
+            guid: self.remote_guid,
+            phase,
+            is_incoming: self.is_incoming,
+            ping_ms: self.ping,
+            mtu: self.mtu,
+            uptime: time.saturating_duration_since(self.connection_time),
+            external_addr: self.external_addr,
+            remote_internal_addrs: self.remote_internal_addrs.clone(),
+        }
+    }
+
     /// Performs various connection related actions such as sending acknowledgements
     /// and resending dropped packets.
     pub fn update(&mut self, time: Instant, communicator: &mut Communicator<impl DatagramSocket>) {
         // TODO: Read outgoing packets from the user and send to the reliability layer
-        // TODO: Send a connected ping if a reliable packet has not been sent within half the timeout time
+        let ping_interval_in_ms = communicator.config().ping_interval_in_ms;
+        if self.state == ConnectionState::Connected && ping_interval_in_ms > 0
+            && time.saturating_duration_since(self.last_ping_time).as_millis() >= ping_interval_in_ms {
+            self.send_connected_ping(time, communicator);
+        }
+        let quality_interval_in_ms = communicator.config().connection_quality_interval_in_ms;
+        if self.state == ConnectionState::Connected && quality_interval_in_ms > 0
+            && time.saturating_duration_since(self.last_quality_time).as_millis() >= quality_interval_in_ms {
+            self.last_quality_time = time;
+            communicator.send_event(PeerEvent::ConnectionQuality {
+                addr: self.remote_addr,
+                guid: self.remote_guid,
+                packet_loss: self.reliability_layer.packet_loss(time),
+                ping_ms: self.ping,
+            });
+        }
         self.reliability_layer.update(time, communicator);
     }
 
+    /// Sets the maximum number of bytes per second sent over this connection.
+    pub fn set_max_outgoing_bytes_per_second(&mut self, max_bytes_per_second: u64) {
+        self.reliability_layer.set_max_outgoing_bytes_per_second(max_bytes_per_second);
+    }
+
+    /// Overrides the limits in `Config` for this connection.
+    pub fn set_limits(&mut self, limits: &ConnectionLimits) {
+        self.reliability_layer.set_limits(limits);
+    }
+
+    /// Returns the number of bytes sent over this connection during the last second.
+    pub fn outgoing_bytes_per_second(&mut self, time: Instant) -> u64 {
+        self.reliability_layer.outgoing_bytes_per_second(time)
+    }
+
+    /// Returns the largest payload in bytes that can be sent without being split.
+    pub fn max_unsplit_payload(&self) -> usize {
+        self.reliability_layer.max_unsplit_payload()
+    }
+
+    /// Returns the largest payload in bytes that can be sent over this connection.
+    pub fn max_total_payload(&self) -> usize {
+        self.reliability_layer.max_total_payload()
+    }
+
+    /// Enqueues an application packet for sending.
+    pub fn send_packet(&mut self, time: Instant, priority: Priority, reliability: Reliability, ordering: Ordering, receipt: Option<u32>, payload: impl Into<PacketPayload>) -> Result<()> {
+        self.reliability_layer.send_packet(time, priority, reliability, ordering, receipt, payload)
+    }
+
+    /// Closes the connection by sending a disconnection notification to the remote peer.
+    /// If `flush` is true the connection is dropped once all outgoing packets have been
+    /// acknowledged, otherwise as soon as the notification has been sent.
+    pub fn disconnect(&mut self, time: Instant, flush: bool, communicator: &mut Communicator<impl DatagramSocket>) {
+        debug!("Disconnecting from {} with guid {}", self.remote_addr, self.remote_guid);
+        let payload = vec![MessageId::DisconnectionNotification.into()];
+        if let Err(err) = self.reliability_layer.send_packet(time, Priority::Highest, Reliability::Reliable, Ordering::None, None, payload.into_boxed_slice()) {
+            error!("Failed sending disconnection notification: {:?}", err);
+        }
+        self.reliability_layer.flush();
+        if flush {
+            self.state = ConnectionState::DisconnectAsap;
+        } else {
+            self.reliability_layer.update(time, communicator);
+            self.state = ConnectionState::DisconnectNow;
+        }
+    }
+
+    /// Drops the connection in the next update without notifying the remote peer.
+    pub fn close(&mut self, reason: ConnectionLostReason) {
+        if self.close_reason.is_none() {
+            self.close_reason = Some(reason);
+        }
+    }
+
+    /// Discards all packets waiting to be sent or acknowledged when the connection is
+    /// dropped and sends a `PeerEvent::SendReceiptLoss` for each of them with a receipt.
+    pub fn discard_pending_packets(&mut self, communicator: &mut Communicator<impl DatagramSocket>) {
+        self.reliability_layer.discard_pending_packets(communicator);
+    }
+
+    /// Sends queued packets in the next update without waiting for the coalescing delay.
+    pub fn flush(&mut self) {
+        self.reliability_layer.flush();
+    }
+
     /// Processes an incoming datagram.
     pub fn process_incoming_datagram(&mut self, payload: &[u8], time: Instant, communicator: &mut Communicator<impl DatagramSocket>) {
         if let Some(packets) = self.reliability_layer.process_incoming_datagram(payload, time, communicator) {
@@ -83,18 +292,24 @@ impl Connection {
         if self.state == ConnectionState::UnverifiedSender {
             match MessageId::try_from(packet.payload()[0]) {
                 Ok(MessageId::ConnectionRequest) => self.handle_connection_request(packet.payload(), communicator, time),
-                _ => {}, // TODO: Close the connection and ban the user temporarily for sending garbage
+                _ => {
+                    debug!("Received garbage from unverified sender {}", self.remote_addr);
+                    self.state = ConnectionState::SentGarbage;
+                },
             }
         } else {
             match MessageId::try_from(packet.payload()[0]) {
                 Ok(MessageId::ConnectionRequest) => {}, // TODO: Implement
                 Ok(MessageId::NewIncomingConnection) => self.handle_new_incoming_connection(packet.payload(), communicator, time),
-                Ok(MessageId::ConnectedPong) => {}, // TODO: Implement
-                Ok(MessageId::ConnectedPing) => self.handle_connected_ping(packet.payload(), time),
-                Ok(MessageId::DisconnectionNotification) => {}, // TODO: Implement
+                Ok(MessageId::ConnectedPong) => self.handle_connected_pong(packet.payload(), communicator, time),
+                Ok(MessageId::ConnectedPing) => self.handle_connected_ping(packet.payload(), communicator, time),
+                Ok(MessageId::DisconnectionNotification) => {
+                    debug!("Received disconnection notification from {}", self.remote_addr);
+                    self.close(ConnectionLostReason::RemoteDisconnect);
+                },
                 Ok(MessageId::DetectLostConnections) => {}, // TODO: Implement
-                Ok(MessageId::InvalidPassword) => {}, // TODO: Implement
-                Ok(MessageId::ConnectionRequestAccepted) => {}, // TODO: Implement
+                Ok(MessageId::InvalidPassword) => self.handle_invalid_password(packet.payload(), communicator),
+                Ok(MessageId::ConnectionRequestAccepted) => self.handle_connection_request_accepted(packet.payload(), communicator, time),
                 _ => return false,
             }
         }
@@ -102,11 +317,21 @@ impl Connection {
     }
 
     fn handle_connection_request(&mut self, payload: &[u8], communicator: &mut Communicator<impl DatagramSocket>, time: Instant) {
-        let mut reader = DataReader::new(payload);
+        let mut reader = DataReader::new(payload).with_socket_addr_format(communicator.config().socket_addr_format);
         match ConnectionRequestMessage::read_message(&mut reader) {
             Ok(connection_request) => {
                 debug!("Received a connection request: {:?}", connection_request);
-                // TODO: Check proof, client key and password
+                // TODO: Check proof and client key
+                if let Some(password) = &communicator.config().incoming_password {
+                    // Compared in constant time so that the password can not be guessed byte by byte from the response time
+                    if !bool::from(password.ct_eq(&connection_request.password)) {
+                        debug!("Invalid password in connection request from {}", self.remote_addr);
+                        let message = InvalidPasswordMessage { guid: communicator.config().guid };
+                        self.send_connected_message(time, &message, Reliability::Reliable, Ordering::None, communicator);
+                        self.state = ConnectionState::DisconnectAsapSilently;
+                        return;
+                    }
+                }
                 self.state = ConnectionState::HandlingConnectionRequest;
                 let message = ConnectionRequestAcceptedMessage {
                     client_addr: self.remote_addr,
@@ -115,23 +340,86 @@ impl Connection {
                     client_time: connection_request.time,
                     server_time: time.saturating_duration_since(self.peer_creation_time).as_millis() as u64,
                 };
-                self.send_connected_message(time, &message, Reliability::Reliable, Ordering::Ordered(0));
+                self.send_connected_message(time, &message, Reliability::Reliable, Ordering::Ordered(0), communicator);
             },
             Err(err) => error!("Failed reading connection request message: {}", err),
         }
     }
 
+    /// Sends a connection request to the remote peer. Used for outgoing
+    /// connections once the offline handshake has been completed.
+    pub fn send_connection_request(&mut self, time: Instant, password: &[u8], communicator: &mut Communicator<impl DatagramSocket>) {
+        debug!("Sending a connection request to {}", self.remote_addr);
+        let message = ConnectionRequestMessage {
+            guid: communicator.config().guid,
+            time: self.get_peer_time(time),
+            proof_and_client_key: None, // TODO: Add support for security
+            password: password.into(),
+        };
+        self.send_connected_message(time, &message, Reliability::Reliable, Ordering::None, communicator);
+    }
+
+    fn handle_connection_request_accepted(&mut self, payload: &[u8], communicator: &mut Communicator<impl DatagramSocket>, time: Instant) {
+        let mut reader = DataReader::new(payload).with_socket_addr_format(communicator.config().socket_addr_format);
+        match ConnectionRequestAcceptedMessage::read_message(&mut reader) {
+            Ok(accepted) => {
+                debug!("Received a connection request accepted: {:?}", accepted);
+                if self.state == ConnectionState::RequestedConnection {
+                    self.state = ConnectionState::Connected;
+                    self.external_addr = Some(accepted.client_addr);
+                    self.remote_internal_addrs = Self::filter_addr_list(&accepted.ip_list);
+                    let message = NewIncomingConnectionMessage {
+                        server_addr: self.remote_addr,
+                        client_ip_list: communicator.get_addr_list(),
+                        send_ping_time: accepted.server_time,
+                        send_pong_time: self.get_peer_time(time),
+                    };
+                    self.send_connected_message(time, &message, Reliability::Reliable, Ordering::Ordered(0), communicator);
+                    self.send_connected_ping(time, communicator);
+                    communicator.send_event(PeerEvent::ConnectionEstablished {
+                        addr: self.remote_addr,
+                        guid: self.remote_guid,
+                        external_addr: accepted.client_addr,
+                        remote_internal_addrs: self.remote_internal_addrs.clone(),
+                        user_data: self.user_data,
+                    });
+                } else {
+                    debug!("Not requesting a connection, ignoring packet");
+                }
+            },
+            Err(err) => error!("Failed reading connection request accepted message: {}", err),
+        }
+    }
+
+    fn handle_invalid_password(&mut self, payload: &[u8], communicator: &mut Communicator<impl DatagramSocket>) {
+        let mut reader = DataReader::new(payload).with_socket_addr_format(communicator.config().socket_addr_format);
+        match InvalidPasswordMessage::read_message(&mut reader) {
+            Ok(message) => {
+                debug!("Received an invalid password: {:?}", message);
+                if self.state == ConnectionState::RequestedConnection {
+                    self.state = ConnectionState::DisconnectAsapSilently;
+                    communicator.send_event(PeerEvent::ConnectionAttemptFailed { addr: self.remote_addr, reason: ConnectionAttemptFailedReason::InvalidPassword, user_data: self.user_data });
+                } else {
+                    debug!("Not requesting a connection, ignoring packet");
+                }
+            },
+            Err(err) => error!("Failed reading invalid password message: {}", err),
+        }
+    }
+
     fn handle_new_incoming_connection(&mut self, payload: &[u8], communicator: &mut Communicator<impl DatagramSocket>, time: Instant) {
-        let mut reader = DataReader::new(payload);
+        let mut reader = DataReader::new(payload).with_socket_addr_format(communicator.config().socket_addr_format);
         match NewIncomingConnectionMessage::read_message(&mut reader) {
             Ok(incoming_connection) => {
                 debug!("Received a new incoming connection: {:?}", incoming_connection);
                 if self.state == ConnectionState::HandlingConnectionRequest {
                     self.state = ConnectionState::Connected;
-                    self.send_connected_ping(time);
-                    communicator.send_event(PeerEvent::IncomingConnection(IncomingConnection::new(self.remote_addr, self.remote_guid)));
-                    // TODO: Possibly store the received external IP and the client's internal IPs
-                    // TODO: Store the ping and clock differential
+                    self.external_addr = Some(incoming_connection.server_addr);
+                    self.remote_internal_addrs = Self::filter_addr_list(&incoming_connection.client_ip_list);
+                    self.send_connected_ping(time, communicator);
+                    communicator.send_event(PeerEvent::IncomingConnection(IncomingConnection::new(self.remote_addr, self.remote_guid, self.handoff_user_data)));
+                    // The connection request accepted and this message work as a ping and pong
+                    self.update_ping(incoming_connection.send_ping_time, incoming_connection.send_pong_time, communicator, time);
                 } else {
                     debug!("Already connected, ignoring packet");
                 }
@@ -140,20 +428,58 @@ impl Connection {
         }
     }
 
-    fn handle_connected_ping(&mut self, payload: &[u8], time: Instant) {
-        let mut reader = DataReader::new(payload);
+    fn handle_connected_ping(&mut self, payload: &[u8], communicator: &mut Communicator<impl DatagramSocket>, time: Instant) {
+        let mut reader = DataReader::new(payload).with_socket_addr_format(communicator.config().socket_addr_format);
         match ConnectedPingMessage::read_message(&mut reader) {
             Ok(ping) => {
                 let pong = ConnectedPongMessage { send_ping_time: ping.time, send_pong_time: self.get_peer_time(time) };
-                self.send_connected_message(time, &pong, Reliability::Unreliable, Ordering::None);
+                self.send_connected_message(time, &pong, Reliability::Unreliable, Ordering::None, communicator);
             },
             Err(err) => error!("Failed reading connection request message: {}", err),
         }
     }
 
-    fn send_connected_ping(&mut self, time: Instant) {
+    fn handle_connected_pong(&mut self, payload: &[u8], communicator: &mut Communicator<impl DatagramSocket>, time: Instant) {
+        let mut reader = DataReader::new(payload).with_socket_addr_format(communicator.config().socket_addr_format);
+        match ConnectedPongMessage::read_message(&mut reader) {
+            Ok(pong) => self.update_ping(pong.send_ping_time, pong.send_pong_time, communicator, time),
+            Err(err) => error!("Failed reading connected pong message: {}", err),
+        }
+    }
+
+    /// Updates the ping and clock differential from a pong to a ping sent at
+    /// `send_ping_time` and answered by the remote peer at `send_pong_time`.
+    fn update_ping(&mut self, send_ping_time: u64, send_pong_time: u64, communicator: &mut Communicator<impl DatagramSocket>, time: Instant) {
+        let receive_pong_time = self.get_peer_time(time);
+        if send_ping_time > receive_pong_time {
+            debug!("Received a pong to a ping from the future from {}", self.remote_addr);
+            return;
+        }
+        let round_trip_time = (receive_pong_time - send_ping_time).min(u32::MAX as u64) as u32;
+        let ping = match self.ping {
+            Some(ping) => ((7 * ping as u64 + round_trip_time as u64) / 8) as u32,
+            None => round_trip_time,
+        };
+        self.ping = Some(ping);
+        // The pong is assumed to have been sent halfway through the round trip
+        self.clock_differential = Some(send_pong_time as i64 - ((send_ping_time + receive_pong_time) / 2) as i64);
+        let received_ms = communicator.millis_since_start(time);
+        communicator.send_event(PeerEvent::PingUpdated { addr: self.remote_addr, guid: self.remote_guid, ping_ms: ping, received_at: time, received_ms });
+    }
+
+    /// Returns the addresses in an address list received from a remote peer
+    /// excluding the unused entries.
+    fn filter_addr_list(addr_list: &[SocketAddr]) -> Vec<SocketAddr> {
+        addr_list.iter()
+            .filter(|addr| addr.port() != 0 && !addr.ip().is_unspecified())
+            .copied()
+            .collect()
+    }
+
+    fn send_connected_ping(&mut self, time: Instant, communicator: &mut Communicator<impl DatagramSocket>) {
         let ping = ConnectedPingMessage { time: self.get_peer_time(time) };
-        self.send_connected_message(time, &ping, Reliability::Unreliable, Ordering::None);
+        self.last_ping_time = time;
+        self.send_connected_message(time, &ping, Reliability::Unreliable, Ordering::None, communicator);
     }
 
     /// Returns the time in milliseconds since the `Peer` was created.
@@ -161,23 +487,55 @@ impl Connection {
         time.saturating_duration_since(self.peer_creation_time).as_millis() as u64
     }
 
-    fn send_connected_message(&mut self, time: Instant, message: &dyn MessageWrite, reliability: Reliability, ordering: Ordering) {
-        let mut payload = Vec::new();
-        match message.write_message(&mut payload) {
-            Ok(()) => self.reliability_layer.send_packet(time, Priority::Highest, reliability, ordering, None, payload.into_boxed_slice()),
+    fn send_connected_message(&mut self, time: Instant, message: &dyn MessageWrite, reliability: Reliability, ordering: Ordering, communicator: &Communicator<impl DatagramSocket>) {
+        let mut payload = Vec::with_capacity(message.written_size());
+        match message.write_message(&mut DataWriter::new(&mut payload, communicator.config().socket_addr_format)) {
+            Ok(()) => if let Err(err) = self.reliability_layer.send_packet(time, Priority::Highest, reliability, ordering, None, payload.into_boxed_slice()) {
+                error!("Failed sending message: {:?}", err);
+            },
             Err(err) => error!("Failed writing message to buffer: {:?}", err),
         }
     }
 
+    /// Returns the reason the connection has been closed or lost or None if it is still alive.
+    pub fn lost_reason(&self) -> Option<ConnectionLostReason> {
+        self.close_reason.or_else(|| self.reliability_layer.connection_lost_reason())
+    }
+
     /// Returns true if this connection should be dropped.
     pub fn should_drop(&self, time: Instant, communicator: &mut Communicator<impl DatagramSocket>) -> bool {
-        // TODO: Add more conditions and in some scenarios notify the user that the connection was closed.
+        // A disconnection notification is acknowledged before the connection is dropped
+        let close_reason = self.close_reason
+            .filter(|reason| *reason != ConnectionLostReason::RemoteDisconnect || !self.reliability_layer.has_pending_acks());
         if (self.state == ConnectionState::UnverifiedSender || self.state == ConnectionState::HandlingConnectionRequest) &&
             time.saturating_duration_since(self.connection_time).as_millis() > communicator.config().incoming_connection_timeout_in_ms {
             debug!("Dropping connection from {} with guid {} because of connection timeout.", self.remote_addr, self.remote_guid);
+            if self.state == ConnectionState::HandlingConnectionRequest {
+                communicator.send_event(PeerEvent::ConnectionLost { addr: self.remote_addr, guid: self.remote_guid, reason: ConnectionLostReason::HandshakeTimeout });
+            }
+            true
+        } else if self.state == ConnectionState::RequestedConnection &&
+            time.saturating_duration_since(self.connection_time).as_millis() >= communicator.config().connection_timeout_in_ms {
+            debug!("Dropping connection to {} with guid {} because of connection timeout.", self.remote_addr, self.remote_guid);
+            communicator.send_event(PeerEvent::ConnectionAttemptFailed { addr: self.remote_addr, reason: ConnectionAttemptFailedReason::Timeout, user_data: self.user_data });
+            true
+        } else if self.state == ConnectionState::DisconnectAsapSilently && !self.reliability_layer.has_pending_packets() {
+            debug!("Dropping connection to {} with guid {} since it is disconnecting.", self.remote_addr, self.remote_guid);
             true
-        } else if self.reliability_layer.is_dead_connection() {
-            debug!("Dropping connection from {} with guid {} because of ack timeout.", self.remote_addr, self.remote_guid);
+        } else if self.state == ConnectionState::DisconnectNow ||
+            (self.state == ConnectionState::DisconnectAsap && !self.reliability_layer.has_pending_packets()) {
+            debug!("Dropping connection to {} with guid {} since it was disconnected locally.", self.remote_addr, self.remote_guid);
+            communicator.send_event(PeerEvent::ConnectionClosed { addr: self.remote_addr, guid: self.remote_guid, reason: ConnectionLostReason::LocalDisconnect });
+            true
+        } else if let Some(reason) = close_reason.or_else(|| self.reliability_layer.connection_lost_reason()) {
+            debug!("Dropping connection from {} with guid {}: {}", self.remote_addr, self.remote_guid, reason);
+            if self.state == ConnectionState::RequestedConnection {
+                communicator.send_event(PeerEvent::ConnectionAttemptFailed { addr: self.remote_addr, reason: ConnectionAttemptFailedReason::NoReply, user_data: self.user_data });
+            } else if self.state == ConnectionState::Connected {
+                communicator.send_event(PeerEvent::ConnectionLost { addr: self.remote_addr, guid: self.remote_guid, reason });
+            } else if self.state == ConnectionState::DisconnectAsap {
+                communicator.send_event(PeerEvent::ConnectionClosed { addr: self.remote_addr, guid: self.remote_guid, reason: ConnectionLostReason::LocalDisconnect });
+            }
             true
         } else {
             false
@@ -189,5 +547,17 @@ impl Connection {
 pub enum ConnectionState {
     UnverifiedSender,
     HandlingConnectionRequest,
+    RequestedConnection,
     Connected,
+    /// The connection is dropped without notifying the remote peer
+    /// as soon as all outgoing packets have been sent and acknowledged.
+    DisconnectAsapSilently,
+    /// The connection was closed locally and is dropped as soon as all outgoing
+    /// packets, including the disconnection notification, have been acknowledged.
+    DisconnectAsap,
+    /// The connection was closed locally and is dropped without waiting for acknowledgements.
+    DisconnectNow,
+    /// The remote peer sent something other than a connection request before
+    /// it was verified. The connection is dropped and the IP address penalized.
+    SentGarbage,
 }
\ No newline at end of file
@@ -0,0 +1,413 @@
+use std::{net::SocketAddr, time::{Duration, Instant}};
+
+use crate::{
+    config::Config,
+    connect_options::ConnectOptions,
+    connection_attempt_failed_reason::ConnectionAttemptFailedReason,
+    constants::MTU_SIZES,
+    security::PuzzleSolver,
+};
+#[cfg(feature = "rand")]
+use crate::security::ClientHandshake;
+
+/// An outgoing connection attempt that has not yet received
+/// an "open connection reply 2" from the remote peer.
+///
+/// The attempt first sends "open connection request 1" with decreasing MTU sizes
+/// until an "open connection reply 1" is received. After that it sends
+/// "open connection request 2" with the negotiated MTU.
+pub struct ConnectionAttempt {
+    addr: SocketAddr,
+    fallback_addrs: Vec<SocketAddr>,
+    options: ConnectOptions,
+    start_time: Instant,
+    mtu_sizes: Vec<u16>,
+    negotiated_mtu: Option<u16>,
+    cookie: Option<u32>,
+    #[cfg(feature = "rand")]
+    handshake: Option<ClientHandshake>,
+    /// The nonce solving the proof-of-work puzzle if the remote peer sent one and does not use security.
+    puzzle_solution: Option<[u8; 32]>,
+    /// The solver of the proof-of-work puzzle until the puzzle is solved.
+    puzzle_solver: Option<PuzzleSolver>,
+    requests_made: u32,
+    max_requests: u32,
+    time_between_requests: Duration,
+    next_request_time: Instant,
+    deadline: Instant,
+}
+
+impl ConnectionAttempt {
+    pub fn new(time: Instant, addr: SocketAddr, config: &Config) -> Self {
+        ConnectionAttempt {
+            addr,
+            fallback_addrs: Vec::new(),
+            options: ConnectOptions::default(),
+            start_time: time,
+            mtu_sizes: Self::get_mtu_sizes(config),
+            negotiated_mtu: None,
+            cookie: None,
+            #[cfg(feature = "rand")]
+            handshake: None,
+            puzzle_solution: None,
+            puzzle_solver: None,
+            requests_made: 0,
+            max_requests: config.connection_attempt_count,
+            time_between_requests: Duration::from_millis(config.time_between_connection_attempts_in_ms as u64),
+            next_request_time: time,
+            deadline: time + Duration::from_millis(config.connection_timeout_in_ms as u64),
+        }
+    }
+
+    /// Returns the MTU sizes to try in order, starting with the maximum MTU size
+    /// followed by the standard MTU sizes within the configured range.
+    fn get_mtu_sizes(config: &Config) -> Vec<u16> {
+        let mut mtu_sizes = vec![config.max_mtu_size];
+        mtu_sizes.extend(MTU_SIZES.iter().copied().filter(|mtu| *mtu < config.max_mtu_size && *mtu >= config.min_mtu_size));
+        mtu_sizes
+    }
+
+    /// Sets the addresses that are tried in order if the attempt fails,
+    /// e.g. the other addresses that a hostname resolved to.
+    pub fn with_fallback_addrs(mut self, fallback_addrs: Vec<SocketAddr>) -> Self {
+        self.fallback_addrs = fallback_addrs;
+        self
+    }
+
+    /// Sets the options supplied by the user for the connection.
+    pub fn with_options(mut self, options: ConnectOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn options(&self) -> &ConnectOptions {
+        &self.options
+    }
+
+    /// Returns a new attempt to the next fallback address or None if
+    /// there are no more addresses to try.
+    pub fn into_next_attempt(mut self, time: Instant, config: &Config) -> Option<ConnectionAttempt> {
+        if self.fallback_addrs.is_empty() {
+            return None;
+        }
+        let addr = self.fallback_addrs.remove(0);
+        Some(ConnectionAttempt::new(time, addr, config)
+            .with_fallback_addrs(self.fallback_addrs)
+            .with_options(self.options))
+    }
+
+    /// Returns the time when the connection attempt was started.
+    pub fn start_time(&self) -> Instant {
+        self.start_time
+    }
+
+    /// Returns the MTU negotiated with an "open connection reply 1" or
+    /// None if no reply has been received yet.
+    pub fn negotiated_mtu(&self) -> Option<u16> {
+        self.negotiated_mtu
+    }
+
+    /// Registers that an "open connection reply 1" has been received
+    /// so that "open connection request 2" is sent from now on.
+    /// The first request 2 is sent immediately and the request 2
+    /// messages are retried as many times as the request 1 messages.
+    pub fn on_reply1_received(&mut self, time: Instant, mtu: u16) {
+        self.negotiated_mtu = Some(mtu);
+        self.requests_made = 0;
+        self.next_request_time = time;
+    }
+
+    /// Registers the security handshake to use if the remote peer uses security.
+    /// Must be called before `set_cookie`, since the proof-of-work puzzle is solved
+    /// from the nonce of the handshake.
+    #[cfg(feature = "rand")]
+    pub fn set_handshake(&mut self, handshake: Option<ClientHandshake>) {
+        self.handshake = handshake;
+    }
+
+    /// Registers the cookie to echo in "open connection request 2".
+    /// Must be called before `on_reply1_received`.
+    ///
+    /// If `puzzle_difficulty` is not 0 the proof-of-work puzzle of the cookie must be solved
+    /// with `solve_puzzle` before "open connection request 2" is sent.
+    pub fn set_cookie(&mut self, cookie: u32, puzzle_difficulty: u8) {
+        self.cookie = Some(cookie);
+        self.puzzle_solution = None;
+        self.puzzle_solver = None;
+        #[cfg(feature = "rand")]
+        let nonce = self.handshake.as_ref().map_or([0u8; 32], |handshake| handshake.nonce());
+        #[cfg(not(feature = "rand"))]
+        let nonce = [0u8; 32];
+        if puzzle_difficulty > 0 {
+            self.puzzle_solver = Some(PuzzleSolver::new(cookie, puzzle_difficulty, nonce));
+        }
+    }
+
+    /// Continues solving the proof-of-work puzzle of the cookie with at most `max_hashes`
+    /// hashes. The solution becomes the nonce of the security challenge if the remote peer
+    /// uses security.
+    pub fn solve_puzzle(&mut self, max_hashes: u32) {
+        let nonce = match self.puzzle_solver.as_mut().and_then(|solver| solver.solve(max_hashes)) {
+            Some(nonce) => nonce,
+            None => return,
+        };
+        self.puzzle_solver = None;
+        #[cfg(feature = "rand")]
+        if let Some(handshake) = &mut self.handshake {
+            handshake.set_nonce(nonce);
+            return;
+        }
+        self.puzzle_solution = Some(nonce);
+    }
+
+    /// Returns the challenge to send in "open connection request 2", which is the security
+    /// challenge if the remote peer uses security. Otherwise it is the solution of the
+    /// proof-of-work puzzle after an all-zero key or None if no puzzle was sent.
+    pub fn challenge(&self) -> Option<[u8; 64]> {
+        #[cfg(feature = "rand")]
+        if let Some(handshake) = &self.handshake {
+            return Some(handshake.challenge());
+        }
+        self.puzzle_solution.map(|puzzle_solution| {
+            let mut challenge = [0u8; 64];
+            challenge[32..].copy_from_slice(&puzzle_solution);
+            challenge
+        })
+    }
+
+    /// Returns the cookie or None if the remote peer does not use cookies.
+    pub fn cookie(&self) -> Option<u32> {
+        self.cookie
+    }
+
+    /// Returns the security handshake or None if the remote peer does not use security.
+    #[cfg(feature = "rand")]
+    pub fn handshake(&self) -> Option<&ClientHandshake> {
+        self.handshake.as_ref()
+    }
+
+    /// Returns true if it is time to send the next open connection request.
+    /// No request is sent while the proof-of-work puzzle is being solved.
+    pub fn should_send_request(&self, time: Instant) -> bool {
+        self.puzzle_solver.is_none() && !self.is_exhausted() && time >= self.next_request_time && time < self.deadline
+    }
+
+    /// Returns true if all requests have been sent without a reply.
+    pub fn is_exhausted(&self) -> bool {
+        self.requests_made >= self.max_requests
+    }
+
+    /// Returns the reason the connection attempt has failed or None if the
+    /// attempt is still ongoing.
+    ///
+    /// The attempt fails when the overall deadline has passed or when all requests
+    /// have been sent and the time to wait for a reply to the last request has passed.
+    pub fn failure_reason(&self, time: Instant) -> Option<ConnectionAttemptFailedReason> {
+        if time >= self.deadline {
+            Some(ConnectionAttemptFailedReason::Timeout)
+        } else if self.is_exhausted() && time >= self.next_request_time {
+            Some(ConnectionAttemptFailedReason::NoReply)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the MTU to use for the next open connection request.
+    ///
+    /// Until an "open connection reply 1" is received the MTU starts at the maximum
+    /// MTU size and is decreased after every few unanswered requests in case large
+    /// datagrams cannot reach the remote peer (path MTU discovery).
+    pub fn mtu(&self) -> u16 {
+        if let Some(mtu) = self.negotiated_mtu {
+            return mtu;
+        }
+        let requests_per_mtu = (self.max_requests / self.mtu_sizes.len() as u32).max(1);
+        let index = ((self.requests_made / requests_per_mtu) as usize).min(self.mtu_sizes.len() - 1);
+        self.mtu_sizes[index]
+    }
+
+    /// Registers that an open connection request has been sent.
+    pub fn on_request_sent(&mut self, time: Instant) {
+        self.requests_made += 1;
+        self.next_request_time = time + self.time_between_requests;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr, time::{Duration, Instant}};
+    use crate::{
+        config::Config,
+        connection_attempt_failed_reason::ConnectionAttemptFailedReason,
+        constants::MTU_SIZES,
+        security,
+    };
+    use super::ConnectionAttempt;
+
+    fn create_attempt(time: Instant, config: &Config) -> ConnectionAttempt {
+        let addr = "127.0.0.1:19132".parse::<SocketAddr>().expect("Could not create address");
+        ConnectionAttempt::new(time, addr, config)
+    }
+
+    #[test]
+    fn should_send_request_initially() {
+        // Arrange
+        let time = Instant::now();
+        let attempt = create_attempt(time, &Config::default());
+
+        // Act/Assert
+        assert!(attempt.should_send_request(time));
+    }
+
+    #[test]
+    fn should_send_request_waits_between_requests() {
+        // Arrange
+        let time = Instant::now();
+        let config = Config { time_between_connection_attempts_in_ms: 300, ..Config::default() };
+        let mut attempt = create_attempt(time, &config);
+
+        // Act
+        attempt.on_request_sent(time);
+
+        // Assert
+        assert!(!attempt.should_send_request(time + Duration::from_millis(299)));
+        assert!(attempt.should_send_request(time + Duration::from_millis(300)));
+    }
+
+    #[test]
+    fn mtu_decreases_with_requests() {
+        // Arrange
+        let time = Instant::now();
+        let config = Config { connection_attempt_count: 6, ..Config::default() };
+        let mut attempt = create_attempt(time, &config);
+        let mut mtus = Vec::new();
+
+        // Act
+        while !attempt.is_exhausted() {
+            mtus.push(attempt.mtu());
+            attempt.on_request_sent(time);
+        }
+
+        // Assert
+        assert_eq!(6, mtus.len());
+        assert_eq!(MTU_SIZES[0], mtus[0]);
+        assert_eq!(MTU_SIZES[MTU_SIZES.len() - 1], mtus[mtus.len() - 1]);
+        assert!(mtus.windows(2).all(|pair| pair[0] >= pair[1]));
+    }
+
+    #[test]
+    fn failure_reason_no_reply_after_last_request() {
+        // Arrange
+        let time = Instant::now();
+        let config = Config {
+            connection_attempt_count: 2,
+            time_between_connection_attempts_in_ms: 100,
+            connection_timeout_in_ms: 10000,
+            ..Config::default()
+        };
+        let mut attempt = create_attempt(time, &config);
+        attempt.on_request_sent(time);
+        attempt.on_request_sent(time + Duration::from_millis(100));
+
+        // Act/Assert
+        assert_eq!(None, attempt.failure_reason(time + Duration::from_millis(199)));
+        assert_eq!(Some(ConnectionAttemptFailedReason::NoReply), attempt.failure_reason(time + Duration::from_millis(200)));
+    }
+
+    #[test]
+    fn failure_reason_timeout_after_deadline() {
+        // Arrange
+        let time = Instant::now();
+        let config = Config {
+            connection_attempt_count: 100,
+            time_between_connection_attempts_in_ms: 100,
+            connection_timeout_in_ms: 250,
+            ..Config::default()
+        };
+        let attempt = create_attempt(time, &config);
+
+        // Act/Assert
+        assert_eq!(None, attempt.failure_reason(time + Duration::from_millis(249)));
+        assert_eq!(Some(ConnectionAttemptFailedReason::Timeout), attempt.failure_reason(time + Duration::from_millis(250)));
+        assert!(!attempt.should_send_request(time + Duration::from_millis(250)));
+    }
+
+    #[test]
+    fn mtu_is_negotiated_mtu_after_reply1() {
+        // Arrange
+        let time = Instant::now();
+        let mut attempt = create_attempt(time, &Config::default());
+        attempt.on_request_sent(time);
+
+        // Act
+        attempt.on_reply1_received(time, 1200);
+
+        // Assert
+        assert_eq!(Some(1200), attempt.negotiated_mtu());
+        assert_eq!(1200, attempt.mtu());
+        assert!(attempt.should_send_request(time));
+    }
+
+    #[test]
+    fn into_next_attempt_uses_fallback_addrs_in_order() {
+        // Arrange
+        let time = Instant::now();
+        let config = Config::default();
+        let addr2 = "127.0.0.2:19132".parse::<SocketAddr>().expect("Could not create address");
+        let addr3 = "[::1]:19132".parse::<SocketAddr>().expect("Could not create address");
+        let attempt = create_attempt(time, &config).with_fallback_addrs(vec![addr2, addr3]);
+
+        // Act
+        let second = attempt.into_next_attempt(time, &config).expect("No second attempt");
+        let second_addr = second.addr();
+        let third = second.into_next_attempt(time, &config).expect("No third attempt");
+        let third_addr = third.addr();
+        let fourth = third.into_next_attempt(time, &config);
+
+        // Assert
+        assert_eq!(addr2, second_addr);
+        assert_eq!(addr3, third_addr);
+        assert!(fourth.is_none());
+    }
+
+    #[test]
+    fn mtu_stays_within_configured_range() {
+        // Arrange
+        let time = Instant::now();
+        let config = Config { connection_attempt_count: 6, min_mtu_size: 1000, max_mtu_size: 1400, ..Config::default() };
+        let mut attempt = create_attempt(time, &config);
+        let mut mtus = Vec::new();
+
+        // Act
+        while !attempt.is_exhausted() {
+            mtus.push(attempt.mtu());
+            attempt.on_request_sent(time);
+        }
+
+        // Assert
+        assert_eq!(vec![1400, 1400, 1400, 1200, 1200, 1200], mtus);
+    }
+
+    #[test]
+    fn request_is_not_sent_until_puzzle_is_solved() {
+        // Arrange
+        let time = Instant::now();
+        let mut attempt = create_attempt(time, &Config::default());
+        attempt.set_cookie(0x12345678, 12);
+        attempt.on_reply1_received(time, 1200);
+
+        // Act
+        let should_send_before_solving = attempt.should_send_request(time);
+        attempt.solve_puzzle(1 << 20);
+
+        // Assert
+        assert!(!should_send_before_solving);
+        assert!(attempt.should_send_request(time));
+        let challenge = attempt.challenge().expect("No challenge after solving the puzzle");
+        assert!(security::is_puzzle_solved(0x12345678, 12, &challenge[32..]));
+    }
+}
@@ -0,0 +1,50 @@
+use std::fmt;
+
+/// The reason an outgoing connection attempt failed.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ConnectionAttemptFailedReason {
+    /// All connection requests were sent without receiving a reply.
+    NoReply,
+    /// The connection was not established before the connection timeout.
+    Timeout,
+    /// The remote peer uses another RakNet protocol version.
+    IncompatibleProtocolVersion,
+    /// The remote peer does not accept more incoming connections.
+    NoFreeIncomingConnections,
+    /// This peer is banned by the remote peer.
+    Banned,
+    /// The remote peer already has a connection with this peer's address or GUID.
+    AlreadyConnected,
+    /// The remote peer rejected the connection since this peer's IP address connected recently.
+    IpRecentlyConnected,
+    /// The remote peer rejected the password supplied in the connection request.
+    InvalidPassword,
+    /// The remote peer is this peer, e.g. when connecting to a loopback address.
+    SelfConnection,
+    /// The security handshake failed, e.g. since the remote peer did not
+    /// use security or could not prove that it has the expected public key.
+    SecurityHandshakeFailed,
+    /// The remote peer replied with an MTU size below the smallest accepted MTU size.
+    MtuTooSmall,
+    /// The remote peer rejected the connection for a reason that is not known to this peer.
+    Rejected,
+}
+
+impl fmt::Display for ConnectionAttemptFailedReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConnectionAttemptFailedReason::NoReply => write!(f, "No reply from remote peer"),
+            ConnectionAttemptFailedReason::Timeout => write!(f, "Connection timed out"),
+            ConnectionAttemptFailedReason::IncompatibleProtocolVersion => write!(f, "Incompatible protocol version"),
+            ConnectionAttemptFailedReason::NoFreeIncomingConnections => write!(f, "No free incoming connections"),
+            ConnectionAttemptFailedReason::Banned => write!(f, "Banned by remote peer"),
+            ConnectionAttemptFailedReason::AlreadyConnected => write!(f, "Already connected"),
+            ConnectionAttemptFailedReason::IpRecentlyConnected => write!(f, "IP recently connected"),
+            ConnectionAttemptFailedReason::InvalidPassword => write!(f, "Invalid password"),
+            ConnectionAttemptFailedReason::SelfConnection => write!(f, "Cannot connect to self"),
+            ConnectionAttemptFailedReason::SecurityHandshakeFailed => write!(f, "Security handshake failed"),
+            ConnectionAttemptFailedReason::MtuTooSmall => write!(f, "MTU size too small"),
+            ConnectionAttemptFailedReason::Rejected => write!(f, "Rejected by remote peer"),
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use std::{net::SocketAddr, sync::{Arc, Mutex}, time::Duration};
+use crossbeam_channel::{bounded, Receiver, Sender};
+
+use crate::ConnectionAttemptFailedReason;
+
+/// The result of an outgoing connection attempt. Contains the GUID
+/// of the remote peer if the connection was established.
+pub type ConnectionResult = std::result::Result<u64, ConnectionAttemptFailedReason>;
+
+/// A handle to an outgoing connection attempt returned by `Peer::connect`
+/// that can be used for checking or waiting for the result of the attempt.
+///
+/// The result can only be received once.
+pub struct ConnectionHandle {
+    addr: Arc<Mutex<SocketAddr>>,
+    result_receiver: Receiver<ConnectionResult>,
+}
+
+impl ConnectionHandle {
+    /// Creates a handle for a connection attempt to the specified address
+    /// together with the sender used for reporting the result of the attempt.
+    pub(crate) fn new(addr: SocketAddr) -> (Self, ConnectionResultSender) {
+        let addr = Arc::new(Mutex::new(addr));
+        let (result_sender, result_receiver) = bounded(1);
+        let handle = ConnectionHandle { addr: addr.clone(), result_receiver };
+        (handle, ConnectionResultSender { addr, result_sender })
+    }
+
+    /// Returns the address of the remote peer. This is the address that
+    /// is currently tried, which changes if the attempt continues with a
+    /// fallback address.
+    pub fn addr(&self) -> SocketAddr {
+        *self.addr.lock().expect("Address lock poisoned")
+    }
+
+    /// Returns the result of the connection attempt without blocking
+    /// or None if the attempt is still ongoing.
+    pub fn try_result(&self) -> Option<ConnectionResult> {
+        self.result_receiver.try_recv().ok()
+    }
+
+    /// Waits for the result of the connection attempt for at most `timeout`.
+    /// Returns None if the attempt is still ongoing after `timeout`.
+    ///
+    /// Note that the `Peer` must be processing for the attempt to progress.
+    pub fn wait(&self, timeout: Duration) -> Option<ConnectionResult> {
+        self.result_receiver.recv_timeout(timeout).ok()
+    }
+}
+
+/// Reports the result of a connection attempt to its `ConnectionHandle`.
+pub(crate) struct ConnectionResultSender {
+    addr: Arc<Mutex<SocketAddr>>,
+    result_sender: Sender<ConnectionResult>,
+}
+
+impl ConnectionResultSender {
+    /// Updates the address of the handle when the attempt continues with a fallback address.
+    pub fn set_addr(&self, addr: SocketAddr) {
+        *self.addr.lock().expect("Address lock poisoned") = addr;
+    }
+
+    /// Sends the result of the attempt. The handle may have been dropped which is fine.
+    pub fn send(&self, result: ConnectionResult) {
+        let _ = self.result_sender.send(result);
+    }
+}
@@ -0,0 +1,47 @@
+use std::{net::SocketAddr, time::Duration};
+
+/// A snapshot of a connection returned by `Peer::connections` or
+/// sent in `PeerEvent::Connections` as the answer to `Command::QueryConnections`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConnectionInfo {
+    /// The address of the remote peer.
+    pub addr: SocketAddr,
+
+    /// The GUID of the remote peer.
+    pub guid: u64,
+
+    /// The phase of the connection.
+    pub phase: ConnectionPhase,
+
+    /// True if the connection was initiated by the remote peer.
+    pub is_incoming: bool,
+
+    /// The smoothed round trip time in milliseconds or None if
+    /// no pong has been received yet, see `Peer::ping`.
+    pub ping_ms: Option<u32>,
+
+    /// The MTU of the connection.
+    pub mtu: u16,
+
+    /// The time since the connection was created, i.e. since the connection
+    /// attempt was started or the first connection request was received.
+    pub uptime: Duration,
+
+    /// The address of this peer as seen by the remote peer or
+    /// None if the remote peer has not reported it yet.
+    pub external_addr: Option<SocketAddr>,
+
+    /// The internal addresses reported by the remote peer.
+    pub remote_internal_addrs: Vec<SocketAddr>,
+}
+
+/// The phase of a connection as seen by the user.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ConnectionPhase {
+    /// The connection handshake has not been completed.
+    Connecting,
+    /// The connection is established and packets can be sent.
+    Connected,
+    /// The connection has been closed and is dropped once pending packets have been handled.
+    Disconnecting,
+}
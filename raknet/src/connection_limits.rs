@@ -0,0 +1,17 @@
+/// Limits of a single connection that override the values in `Config`, set with
+/// `Peer::set_connection_limits` or `Command::SetConnectionLimits`, e.g. to give a
+/// trusted backend link longer timeouts than other remote peers.
+///
+/// Fields that are None keep their current value.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ConnectionLimits {
+    /// Overrides `Config::ack_timeout_in_ms` for the connection.
+    pub ack_timeout_in_ms: Option<u128>,
+
+    /// Overrides `Config::max_retransmissions` for the connection.
+    pub max_retransmissions: Option<u32>,
+
+    /// Sets the maximum number of bytes per second sent over the connection,
+    /// see `Peer::set_connection_bandwidth_limit`. If set to 0 the bandwidth is unlimited.
+    pub max_outgoing_bytes_per_second: Option<u64>,
+}
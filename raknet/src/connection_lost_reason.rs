@@ -0,0 +1,53 @@
+use std::fmt;
+
+use crate::inbound_limiter::InboundLimit;
+
+/// The reason an established connection was lost.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ConnectionLostReason {
+    /// No acknowledgements were received from the remote peer within the ack timeout.
+    AckTimeout,
+    /// A reliable packet was resent the maximum number of times without being acknowledged.
+    TooManyRetransmissions,
+    /// The remote peer sent an invalid split packet.
+    InvalidSplitPacket,
+    /// The connection was closed with `Peer::disconnect`.
+    LocalDisconnect,
+    /// The remote peer closed the connection by sending a disconnection notification.
+    RemoteDisconnect,
+    /// A remote peer that sent a connection request did not complete the connection
+    /// handshake in time. Reported before any `PeerEvent::IncomingConnection` for the peer.
+    HandshakeTimeout,
+    /// The IP address of the remote peer was banned with `Peer::ban_address`.
+    Banned,
+    /// The remote peer sent something other than a connection request before
+    /// its connection was verified.
+    Garbage,
+    /// The remote peer exceeded an inbound limit of the connection and
+    /// `Config::inbound_limit_action` is not `InboundLimitAction::DropPacket`.
+    InboundLimitExceeded { limit: InboundLimit },
+}
+
+impl ConnectionLostReason {
+    /// Returns true if the connection was lost since the remote peer abused the protocol,
+    /// in which case its IP address is greylisted, see `Config::greylist_duration_in_ms`.
+    pub fn is_protocol_abuse(&self) -> bool {
+        matches!(self, ConnectionLostReason::InvalidSplitPacket | ConnectionLostReason::Garbage | ConnectionLostReason::InboundLimitExceeded { .. })
+    }
+}
+
+impl fmt::Display for ConnectionLostReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConnectionLostReason::AckTimeout => write!(f, "Ack timeout"),
+            ConnectionLostReason::TooManyRetransmissions => write!(f, "Too many retransmissions"),
+            ConnectionLostReason::InvalidSplitPacket => write!(f, "Invalid split packet"),
+            ConnectionLostReason::LocalDisconnect => write!(f, "Disconnected locally"),
+            ConnectionLostReason::RemoteDisconnect => write!(f, "Disconnected by remote peer"),
+            ConnectionLostReason::HandshakeTimeout => write!(f, "Handshake timeout"),
+            ConnectionLostReason::Banned => write!(f, "Banned"),
+            ConnectionLostReason::Garbage => write!(f, "Garbage from unverified sender"),
+            ConnectionLostReason::InboundLimitExceeded { limit } => write!(f, "Inbound limit of {} exceeded", limit),
+        }
+    }
+}
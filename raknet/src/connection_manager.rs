@@ -1,35 +1,101 @@
-use std::{net::SocketAddr, collections::HashMap, time::Instant};
+use std::{net::SocketAddr, collections::HashMap, sync::Arc, time::{Duration, Instant, UNIX_EPOCH}};
 use crossbeam_channel::{unbounded, Receiver};
-use log::error;
 
 use crate::{
+    admission_policy::AdmissionPolicy,
+    allow_list::AllowList,
+    ban_file::{BanEntry, BanFile},
+    ban_list::IpRange,
+    capture::{CaptureDirection, PacketCapture},
+    clock::Clock,
     communicator::Communicator,
-    config::Config,
-    connection::Connection,
+    config::{Config, InvalidConfig},
+    config_update::ConfigUpdate,
+    connect_options::ConnectOptions,
+    connection::{Connection, ConnectionState},
+    connection_attempt_failed_reason::ConnectionAttemptFailedReason,
+    connection_handle::ConnectionHandle,
+    connection_info::ConnectionInfo,
+    connection_limits::ConnectionLimits,
+    connection_lost_reason::ConnectionLostReason,
+    connection_stats::ConnectionStats,
     constants::MAXIMUM_MTU_SIZE,
-    offline_packet_handler::OfflinePacketHandler,
+    inbound_limiter::InboundLimitAction,
+    Error,
+    HandoffToken,
+    logging::{debug, error},
+    ModerationAction,
+    ModerationRule,
+    offline_packet_handler::{OfflinePacketHandler, PingResponseProvider},
+    peer_metrics::PeerMetrics,
+    reconnector::{ConnectionStatus, Reconnector},
     PeerEvent,
+    Result,
+    SecurityEventKind,
+    SendOptions,
+    SendTarget,
     socket::DatagramSocket,
+    tick_stats::{TickStats, TickTimings},
 };
 
 pub struct ConnectionManager<T: DatagramSocket> {
     communicator: Communicator<T>,
     connections: HashMap<SocketAddr, Connection>,
+    /// The address of the connection to each remote GUID.
+    guids: HashMap<u64, SocketAddr>,
     event_receiver: Receiver<PeerEvent>,
     offline_packet_handler: OfflinePacketHandler,
+    reconnector: Reconnector,
     receive_buffer: Vec<u8>,
+    tick_stats: TickStats,
 }
 
 impl<T: DatagramSocket> ConnectionManager<T> {
-    pub fn new(socket: T, config: Config) -> Self {
+    #[cfg(test)]
+    pub fn new(socket: T, config: Config) -> Result<Self> {
+        Self::with_clock(socket, config, Arc::new(crate::SystemClock))
+    }
+
+    /// Creates a connection manager that takes the current time from the clock.
+    /// Returns `Error::InvalidConfig` if the configuration is invalid.
+    pub fn with_clock(mut socket: T, config: Config, clock: Arc<dyn Clock>) -> Result<Self> {
+        config.validate()?;
+        if let Err(err) = socket.set_options(&config.socket_options) {
+            error!("Could not set socket options {:?}: {:?}", config.socket_options, err);
+        }
         let receive_buffer = vec![0u8; MAXIMUM_MTU_SIZE.into()];
         let (event_sender, event_receiver) = unbounded();
-        ConnectionManager {
-            communicator: Communicator::new(socket, config, event_sender),
+        Ok(ConnectionManager {
+            reconnector: Reconnector::new(&config),
+            offline_packet_handler: OfflinePacketHandler::new(clock.now(), config.allow_list.clone()),
+            communicator: Communicator::new(socket, config, event_sender, clock),
             connections: HashMap::new(),
+            guids: HashMap::new(),
             event_receiver,
-            offline_packet_handler: OfflinePacketHandler::new(),
             receive_buffer,
+            tick_stats: TickStats::default(),
+        })
+    }
+
+    /// Returns the current time of the clock of the peer.
+    pub fn now(&self) -> Instant {
+        self.communicator.now()
+    }
+
+    /// Returns the time of the clock when the peer was created, see `Peer::start_time`.
+    pub fn start_time(&self) -> Instant {
+        self.communicator.start_time()
+    }
+
+    /// Returns the time to wait before the next processing round, which is the idle
+    /// tick interval of the config if there are no connections and no connection attempts.
+    pub fn tick_interval(&self) -> Duration {
+        let config = self.communicator.config();
+        let is_idle = self.connections.is_empty() && !self.offline_packet_handler.has_connection_attempts();
+        if is_idle && config.idle_tick_interval_in_ms > 0 {
+            Duration::from_millis(config.idle_tick_interval_in_ms as u64)
+        } else {
+            Duration::from_millis(config.tick_interval_in_ms as u64)
         }
     }
 
@@ -40,19 +106,413 @@ impl<T: DatagramSocket> ConnectionManager<T> {
         self.offline_packet_handler.set_offline_ping_response(ping_response);
     }
 
+    /// Sets a function that creates the response to each offline ping packet.
+    pub fn set_ping_response_provider(&mut self, provider: PingResponseProvider) {
+        self.offline_packet_handler.set_ping_response_provider(provider);
+    }
+
+    /// Creates a handoff token for the connected remote peer with the GUID, bound to its IP address,
+    /// or returns None if there is no handoff key or no remote peer with the GUID is connected.
+    pub fn create_handoff_token(&self, guid: u64, user_data: u64) -> Option<HandoffToken> {
+        let config = self.communicator.config();
+        let handoff_key = config.handoff_key.as_ref()?;
+        let addr = self.connections.iter().find(|(_, conn)| conn.guid() == guid).map(|(addr, _)| *addr)?;
+        let lifetime = Duration::from_millis(config.handoff_token_lifetime_in_ms as u64);
+        let expires_at = self.communicator.system_time(self.communicator.now()) + lifetime;
+        Some(HandoffToken::new(handoff_key, guid, addr.ip(), user_data, expires_at))
+    }
+
+    /// Sets the policy deciding which remote peers may connect or removes it if None.
+    pub fn set_admission_policy(&mut self, admission_policy: Option<Box<dyn AdmissionPolicy>>) {
+        self.offline_packet_handler.set_admission_policy(admission_policy);
+    }
+
+    /// Starts or stops capturing datagrams, see `Peer::set_packet_capture`.
+    pub fn set_packet_capture(&mut self, packet_capture: Option<PacketCapture>) {
+        self.communicator.set_packet_capture(packet_capture);
+    }
+
+    /// Sends an unconnected ping to the address, see `Peer::ping_address`.
+    pub fn ping_address(&mut self, time: Instant, addr: SocketAddr) {
+        self.offline_packet_handler.ping(time, addr, &mut self.communicator);
+    }
+
+    /// Sends an out-of-band message to the address, see `Peer::send_out_of_band`.
+    pub fn send_out_of_band(&mut self, addr: SocketAddr, data: &[u8]) -> Result<()> {
+        self.offline_packet_handler.send_out_of_band(addr, data, &mut self.communicator)
+    }
+
+    /// Applies the changes to the configuration, see `Peer::update_config`.
+    pub fn update_config(&mut self, update: &ConfigUpdate) -> std::result::Result<(), InvalidConfig> {
+        self.communicator.update_config(update)?;
+        if let Some(ping_response) = &update.offline_ping_response {
+            self.offline_packet_handler.set_offline_ping_response(ping_response.clone());
+        }
+        Ok(())
+    }
+
+    /// Starts connecting to a remote peer. A `PeerEvent::ConnectionEstablished`
+    /// is sent once the connection has been established or a
+    /// `PeerEvent::ConnectionAttemptFailed` if the attempt fails.
+    ///
+    /// If the remote peer connects to this peer at the same time, only one
+    /// connection is created. In that case this peer may receive a
+    /// `PeerEvent::IncomingConnection` instead of `PeerEvent::ConnectionEstablished`.
+    ///
+    /// The addresses are tried one at a time, ordered by the address family
+    /// preference in the `Config`, until a connection is established.
+    /// The returned handle receives the result of the attempt.
+    ///
+    /// Fails with `Error::InvalidAddress` if `addrs` is empty.
+    pub fn connect(&mut self, time: Instant, addrs: &[SocketAddr], options: ConnectOptions) -> Result<ConnectionHandle> {
+        let mut addrs = addrs.iter().fold(Vec::new(), |mut unique_addrs, addr| {
+            if !unique_addrs.contains(addr) {
+                unique_addrs.push(*addr);
+            }
+            unique_addrs
+        });
+        let prefer_ipv6 = self.communicator.config().prefer_ipv6;
+        addrs.sort_by_key(|addr| addr.is_ipv6() != prefer_ipv6);
+        if addrs.is_empty() {
+            return Err(Error::InvalidAddress);
+        }
+        let addr = addrs.remove(0);
+        let (handle, result_sender) = ConnectionHandle::new(addr);
+        match self.connections.get(&addr) {
+            Some(conn) if conn.is_incoming() || conn.state == ConnectionState::Connected => {
+                debug!("Already connected to {}", addr);
+                result_sender.send(Err(ConnectionAttemptFailedReason::AlreadyConnected));
+            },
+            _ => {
+                if self.offline_packet_handler.connect(time, addr, addrs, options, self.communicator.config(), &self.connections) {
+                    self.communicator.metrics_mut().outgoing_connection_attempts += 1;
+                }
+                self.communicator.add_connection_result_sender(addr, result_sender);
+            },
+        }
+        Ok(handle)
+    }
+
+    /// Starts keeping a connection to the specified address alive. A connection
+    /// attempt is made immediately if there is no connection and the peer reconnects
+    /// with exponential backoff whenever the connection is lost or an attempt fails.
+    pub fn start_reconnecting(&mut self, time: Instant, addr: SocketAddr, options: ConnectOptions) {
+        self.reconnector.start(time, addr, options);
+    }
+
+    /// Stops reconnecting to the specified address. An existing connection is not closed.
+    pub fn stop_reconnecting(&mut self, addr: SocketAddr) {
+        self.reconnector.stop(addr);
+    }
+
+    /// Bans a range of IP addresses for the specified duration or forever if `duration`
+    /// is None. All datagrams from banned addresses are ignored and their connection
+    /// requests are answered with `MessageId::ConnectionBanned`.
+    pub fn ban_address(&mut self, time: Instant, range: IpRange, duration: Option<Duration>) {
+        self.ban(time, range, duration);
+        self.communicator.send_moderation_event(time, None, ModerationAction::Banned { range, duration }, ModerationRule::Manual);
+    }
+
+    /// Bans a range of IP addresses and kicks the remote peers connected from it.
+    fn ban(&mut self, time: Instant, range: IpRange, duration: Option<Duration>) {
+        for (addr, conn) in self.connections.iter_mut() {
+            if range.contains(addr.ip()) {
+                conn.close(ConnectionLostReason::Banned);
+                self.communicator.send_moderation_event(time, Some(*addr), ModerationAction::Kicked { guid: conn.guid() }, ModerationRule::BanList);
+            }
+        }
+        self.offline_packet_handler.ban(range, duration.map(|duration| time + duration));
+        self.communicator.metrics_mut().bans += 1;
+    }
+
+    /// Removes the ban of a range of IP addresses. Returns false if the range was not banned.
+    pub fn unban_address(&mut self, time: Instant, range: IpRange) -> bool {
+        let is_unbanned = self.offline_packet_handler.unban(range);
+        if is_unbanned {
+            self.communicator.send_moderation_event(time, None, ModerationAction::Unbanned { range }, ModerationRule::Manual);
+        }
+        is_unbanned
+    }
+
+    /// Returns the bans that have not expired at the specified time with their expiry times
+    /// converted to seconds since the Unix epoch.
+    pub fn bans(&self, time: Instant) -> BanFile {
+        let now = self.communicator.system_time(time);
+        let bans = self.offline_packet_handler.bans().iter()
+            .filter(|(_, expiry_time)| expiry_time.is_none_or(|expiry_time| time < expiry_time))
+            .map(|(range, expiry_time)| BanEntry {
+                range: *range,
+                expires_at: expiry_time.map(|expiry_time| {
+                    let expiry_time = now + expiry_time.saturating_duration_since(time);
+                    expiry_time.duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs())
+                }),
+            })
+            .collect();
+        BanFile { bans }
+    }
+
+    /// Bans the ranges of IP addresses in the ban file until their expiry times.
+    /// Bans that have already expired are skipped. Returns the number of ranges banned.
+    pub fn add_bans(&mut self, time: Instant, ban_file: &BanFile) -> usize {
+        let now = self.communicator.system_time(time);
+        let mut ban_count = 0;
+        for ban in ban_file.bans.iter() {
+            let duration = match ban.expires_at {
+                Some(expires_at) => match (UNIX_EPOCH + Duration::from_secs(expires_at)).duration_since(now) {
+                    Ok(duration) if !duration.is_zero() => Some(duration),
+                    _ => continue,
+                },
+                None => None,
+            };
+            self.ban(time, ban.range, duration);
+            ban_count += 1;
+        }
+        ban_count
+    }
+
+    /// Enables allow-list mode with the allow list or disables it if None.
+    pub fn set_allow_list(&mut self, allow_list: Option<AllowList>) {
+        self.offline_packet_handler.set_allow_list(allow_list);
+    }
+
+    /// Adds a range of IP addresses to the allow list. Returns false if allow-list mode is disabled.
+    pub fn allow_address(&mut self, range: IpRange) -> bool {
+        self.offline_packet_handler.allow_list_mut().map(|allow_list| allow_list.allow_range(range)).is_some()
+    }
+
+    /// Adds a GUID to the allow list. Returns false if allow-list mode is disabled.
+    pub fn allow_guid(&mut self, guid: u64) -> bool {
+        self.offline_packet_handler.allow_list_mut().map(|allow_list| allow_list.allow_guid(guid)).is_some()
+    }
+
+    /// Removes a range of IP addresses from the allow list. Returns false if the range was not in it.
+    pub fn disallow_address(&mut self, range: IpRange) -> bool {
+        self.offline_packet_handler.allow_list_mut().is_some_and(|allow_list| allow_list.remove_range(range))
+    }
+
+    /// Removes a GUID from the allow list. Returns false if the GUID was not in it.
+    pub fn disallow_guid(&mut self, guid: u64) -> bool {
+        self.offline_packet_handler.allow_list_mut().is_some_and(|allow_list| allow_list.remove_guid(guid))
+    }
+
+    /// Sets the maximum number of bytes per second sent over the connection to the
+    /// specified address. Returns false if there is no connection to the address.
+    pub fn set_connection_bandwidth_limit(&mut self, addr: SocketAddr, max_bytes_per_second: u64) -> bool {
+        if let Some(conn) = self.connections.get_mut(&addr) {
+            conn.set_max_outgoing_bytes_per_second(max_bytes_per_second);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Overrides the limits in `Config` for the connection to the remote peer with
+    /// the GUID. Returns false if there is no connection to the remote peer.
+    pub fn set_connection_limits(&mut self, guid: u64, limits: &ConnectionLimits) -> bool {
+        match self.addr_of(guid).and_then(|addr| self.connections.get_mut(&addr)) {
+            Some(conn) => {
+                conn.set_limits(limits);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Returns the number of bytes sent to all remote peers during the last second.
+    pub fn outgoing_bytes_per_second(&mut self, time: Instant) -> u64 {
+        self.communicator.bandwidth_limiter().bytes_per_second(time)
+    }
+
+    /// Returns the local address of the socket.
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.communicator.local_addr()?)
+    }
+
+    /// Returns the number of datagrams dropped since the socket send queue was full.
+    pub fn dropped_datagram_count(&self) -> u64 {
+        self.communicator.dropped_datagram_count()
+    }
+
+    /// Returns the number of bytes sent over the connection to the specified
+    /// address during the last second or None if there is no such connection.
+    pub fn connection_outgoing_bytes_per_second(&mut self, time: Instant, addr: SocketAddr) -> Option<u64> {
+        self.connections.get_mut(&addr).map(|conn| conn.outgoing_bytes_per_second(time))
+    }
+
+    /// Returns the largest payload in bytes that can be sent without being split over
+    /// the connection to the specified address or None if there is no such connection.
+    pub fn max_unsplit_payload(&self, addr: SocketAddr) -> Option<usize> {
+        self.connections.get(&addr).map(|conn| conn.max_unsplit_payload())
+    }
+
+    /// Returns the largest payload in bytes that can be sent over the connection
+    /// to the specified address or None if there is no such connection.
+    pub fn max_total_payload(&self, addr: SocketAddr) -> Option<usize> {
+        self.connections.get(&addr).map(|conn| conn.max_total_payload())
+    }
+
+    /// Enqueues a packet for sending to the connected remote peer at the specified address.
+    /// Returns `Error::NotConnected` if no connection to the address has been established.
+    pub fn send(&mut self, time: Instant, addr: SocketAddr, options: SendOptions, payload: Box<[u8]>) -> Result<()> {
+        match self.connections.get_mut(&addr) {
+            Some(conn) if conn.state == ConnectionState::Connected =>
+                conn.send_packet(time, options.priority, options.reliability, options.ordering, options.receipt, payload),
+            _ => Err(Error::NotConnected(addr)),
+        }
+    }
+
+    /// Enqueues a packet for sending to all connected remote peers selected by the target.
+    /// The connections share the payload. Returns the number of connections the packet
+    /// was enqueued for, connections that cannot send the packet are skipped.
+    ///
+    /// Returns `Error::NoTarget` if the packet was not enqueued for any connection.
+    pub fn send_to_target(&mut self, time: Instant, target: &SendTarget, options: SendOptions, payload: Arc<[u8]>) -> Result<usize> {
+        let mut count = 0;
+        for (addr, conn) in self.connections.iter_mut() {
+            if conn.state != ConnectionState::Connected || !target.includes(*addr, conn.guid()) {
+                continue;
+            }
+            match conn.send_packet(time, options.priority, options.reliability, options.ordering, options.receipt, payload.clone()) {
+                Ok(()) => count += 1,
+                Err(err) => debug!("Could not send packet to {}: {:?}", addr, err),
+            }
+        }
+        if count == 0 {
+            return Err(Error::NoTarget);
+        }
+        Ok(count)
+    }
+
+    /// Returns the address of the connection to the remote peer with the GUID.
+    pub fn addr_of(&self, guid: u64) -> Option<SocketAddr> {
+        self.guids.get(&guid).copied()
+            .filter(|addr| self.connections.get(addr).map(|conn| conn.guid()) == Some(guid))
+    }
+
+    /// Returns a snapshot of every connection, including those being established or closed.
+    pub fn connections(&self, time: Instant) -> Vec<ConnectionInfo> {
+        self.connections.values().map(|conn| conn.info(time)).collect()
+    }
+
+    /// Returns a snapshot of the connection to the remote peer with the GUID.
+    pub fn connection_info(&self, time: Instant, guid: u64) -> Option<ConnectionInfo> {
+        self.addr_of(guid)
+            .and_then(|addr| self.connections.get(&addr))
+            .map(|conn| conn.info(time))
+    }
+
+    /// Sends a `PeerEvent::Connections` with a snapshot of every connection.
+    pub fn query_connections(&mut self, time: Instant) {
+        let connections = self.connections(time);
+        self.communicator.send_event(PeerEvent::Connections(connections));
+    }
+
+    /// Returns the smoothed round trip time in milliseconds to the specified address.
+    pub fn ping(&self, addr: SocketAddr) -> Option<u32> {
+        self.connections.get(&addr).and_then(|conn| conn.ping())
+    }
+
+    /// Returns the metrics of the peer aggregated over all connections, see `Peer::metrics`.
+    pub fn metrics(&self) -> PeerMetrics {
+        PeerMetrics {
+            connections: self.connections.len(),
+            queued_datagrams: self.communicator.queued_datagram_count(),
+            queued_packets: self.connections.values().map(|conn| conn.queued_packet_count()).sum(),
+            ..self.communicator.metrics().clone()
+        }
+    }
+
+    /// Returns the statistics of the connection to the specified address.
+    pub fn connection_stats(&self, addr: SocketAddr) -> Option<ConnectionStats> {
+        self.connections.get(&addr).map(|conn| conn.stats(self.now()))
+    }
+
+    /// Returns the clock differential in milliseconds to the specified address.
+    pub fn clock_differential(&self, addr: SocketAddr) -> Option<i64> {
+        self.connections.get(&addr).and_then(|conn| conn.clock_differential())
+    }
+
+    /// Returns the GUID of the remote peer connected from the address.
+    pub fn guid_of(&self, addr: SocketAddr) -> Option<u64> {
+        self.connections.get(&addr).map(|conn| conn.guid())
+    }
+
+    /// Closes the connection to the remote peer with the GUID, see `Connection::disconnect`.
+    /// Returns false if there is no established connection to the remote peer.
+    pub fn disconnect(&mut self, time: Instant, guid: u64, flush: bool) -> bool {
+        let addr = match self.addr_of(guid) {
+            Some(addr) => addr,
+            None => return false,
+        };
+        let conn = match self.connections.get_mut(&addr) {
+            Some(conn) if conn.state == ConnectionState::Connected => conn,
+            _ => return false,
+        };
+        conn.disconnect(time, flush, &mut self.communicator);
+        true
+    }
+
+    /// Sends the queued packets of all connections in the next processing
+    /// round without waiting for the coalescing delay.
+    pub fn flush(&mut self) {
+        for conn in self.connections.values_mut() {
+            conn.flush();
+        }
+    }
+
     /// Sends and receives packages/events and updates connections.
     pub fn process(&mut self, time: Instant) {
+        // The timings are measured with the system clock since the clock of the peer may be a `ManualClock`
+        let tick_start = Instant::now();
         let communicator = &mut self.communicator;
 
+        // Retry datagrams that could not be sent in the previous round
+        communicator.send_queued_datagrams();
+
         // Process all incoming packets
         loop
         {
             match communicator.socket().receive_datagram(self.receive_buffer.as_mut())
             {
                 Ok((payload, addr)) => {
-                    if !self.offline_packet_handler.process_offline_packet(time, addr, payload, communicator, &mut self.connections) {
+                    let metrics = communicator.metrics_mut();
+                    metrics.datagrams_received += 1;
+                    metrics.bytes_received += payload.len() as u64;
+                    // A dual-stack socket receives IPv4 datagrams from IPv4-mapped IPv6 addresses.
+                    // Use the IPv4 address so that bans, rate limits and connections match.
+                    let addr = SocketAddr::new(addr.ip().to_canonical(), addr.port());
+                    communicator.capture_datagram(CaptureDirection::Received, payload, addr);
+                    let payload = match communicator.strip_integrity_tag(payload) {
+                        Some(payload) => payload,
+                        None => {
+                            debug!("Dropping datagram from {} with invalid integrity tag", addr);
+                            communicator.metrics_mut().invalid_datagrams += 1;
+                            continue;
+                        },
+                    };
+                    if self.offline_packet_handler.process_offline_packet(time, addr, payload, communicator, &mut self.connections) {
+                        if let Some(conn) = self.connections.get(&addr) {
+                            self.guids.insert(conn.guid(), addr);
+                            communicator.socket().on_connection_created(addr);
+                        }
+                    } else {
                         if let Some(conn) = self.connections.get_mut(&addr) {
                             conn.process_incoming_datagram(payload, time, communicator);
+                            if conn.state == ConnectionState::SentGarbage {
+                                let penalty = Duration::from_millis(communicator.config().garbage_penalty_in_ms as u64);
+                                let guid = conn.guid();
+                                communicator.send_moderation_event(time, Some(addr), ModerationAction::Kicked { guid }, ModerationRule::Garbage);
+                                if penalty > Duration::ZERO {
+                                    self.offline_packet_handler.ban(addr.ip().into(), Some(time + penalty));
+                                    communicator.metrics_mut().bans += 1;
+                                    communicator.send_moderation_event(time, Some(addr), ModerationAction::Banned { range: addr.ip().into(), duration: Some(penalty) }, ModerationRule::Garbage);
+                                }
+                                self.connections.remove(&addr);
+                                communicator.socket().on_connection_dropped(addr);
+                                remove_guid(&mut self.guids, guid, addr);
+                                self.offline_packet_handler.greylist(time, addr.ip(), ConnectionLostReason::Garbage, communicator);
+                                communicator.send_security_event(time, addr, SecurityEventKind::GarbageFromUnverifiedSender { penalty });
+                            }
                         }
                     }
                 },
@@ -65,13 +525,83 @@ impl<T: DatagramSocket> ConnectionManager<T> {
             }
         }
 
+        let receive_end = Instant::now();
+
+        // Send requests for ongoing connection attempts
+        self.offline_packet_handler.update(time, communicator);
+
         // Update all connections
         for conn in self.connections.values_mut() {
             conn.update(time, communicator);
         }
 
+        let update_end = Instant::now();
+
         // Check if any connection should be dropped
-        self.connections.retain(|_, conn| !conn.should_drop(time, communicator));
+        let guids = &mut self.guids;
+        let offline_packet_handler = &mut self.offline_packet_handler;
+        self.connections.retain(|addr, conn| {
+            let should_drop = conn.should_drop(time, communicator);
+            if should_drop {
+                if let Some(reason) = conn.lost_reason().filter(|reason| reason.is_protocol_abuse()) {
+                    if let Some(rule) = ModerationRule::from_lost_reason(reason) {
+                        communicator.send_moderation_event(time, Some(*addr), ModerationAction::Kicked { guid: conn.guid() }, rule);
+                        if matches!(reason, ConnectionLostReason::InboundLimitExceeded { .. }) && communicator.config().inbound_limit_action == InboundLimitAction::Ban {
+                            let penalty = Duration::from_millis(communicator.config().inbound_limit_penalty_in_ms as u64);
+                            offline_packet_handler.ban(addr.ip().into(), Some(time + penalty));
+                            communicator.metrics_mut().bans += 1;
+                            communicator.send_moderation_event(time, Some(*addr), ModerationAction::Banned { range: addr.ip().into(), duration: Some(penalty) }, rule);
+                        }
+                    }
+                    offline_packet_handler.greylist(time, addr.ip(), reason, communicator);
+                }
+                conn.discard_pending_packets(communicator);
+                communicator.socket().on_connection_dropped(*addr);
+                remove_guid(guids, conn.guid(), *addr);
+            }
+            !should_drop
+        });
+        let drop_scan_end = Instant::now();
+
+        // Reconnect lost connections
+        let connections = &self.connections;
+        let offline_packet_handler = &self.offline_packet_handler;
+        let reconnects = self.reconnector.update(time, |addr| match connections.get(&addr) {
+            Some(conn) if conn.state == ConnectionState::Connected => ConnectionStatus::Connected,
+            Some(_) => ConnectionStatus::Connecting,
+            None if offline_packet_handler.is_connecting(addr) => ConnectionStatus::Connecting,
+            None => ConnectionStatus::Disconnected,
+        });
+        for (addr, options) in reconnects {
+            if let Err(err) = self.connect(time, &[addr], options) {
+                error!("Could not reconnect to {}: {}", addr, err);
+            }
+        }
+
+        if let Err(err) = self.communicator.socket().flush() {
+            // Datagrams that would block stay queued in the socket until the next flush
+            if err.kind() != std::io::ErrorKind::WouldBlock {
+                error!("Error sending queued datagrams: {:?}", err);
+            }
+        }
+
+        let timings = TickTimings {
+            total: tick_start.elapsed(),
+            receive: receive_end - tick_start,
+            update: update_end - receive_end,
+            drop_scan: drop_scan_end - update_end,
+        };
+        let threshold = self.communicator.config().slow_tick_threshold_in_ms;
+        let is_slow = threshold > 0 && timings.total.as_millis() > threshold;
+        self.tick_stats.add(timings, is_slow);
+        if is_slow {
+            self.communicator.send_event(PeerEvent::SlowTick(timings));
+        }
+    }
+
+    /// Returns the statistics of the processing rounds, see `Peer::tick_stats`.
+    pub fn tick_stats(&self) -> &TickStats {
+        &self.tick_stats
     }
 
     /// Gets an event receiver that can be used for receiving
@@ -79,26 +609,63 @@ impl<T: DatagramSocket> ConnectionManager<T> {
     pub fn event_receiver(&self) -> Receiver<PeerEvent> {
         self.event_receiver.clone()
     }
+
+    #[cfg(feature = "async")]
+    pub fn socket(&mut self) -> &mut T {
+        self.communicator.socket()
+    }
+}
+
+/// Removes the GUID from the index unless it has been taken over by a connection from another address.
+fn remove_guid(guids: &mut HashMap<u64, SocketAddr>, guid: u64, addr: SocketAddr) {
+    if guids.get(&guid) == Some(&addr) {
+        guids.remove(&guid);
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::{net::SocketAddr, time::Instant};   
+    use std::{net::SocketAddr, time::{Duration, Instant, SystemTime, UNIX_EPOCH}};   
     use crossbeam_channel::{Sender, Receiver};
+    #[cfg(feature = "rand")]
+    use crate::{security::SecurityConfig, HandoffToken};
     use crate::{
         config::Config,
+        Admission,
+        AllowList,
+        BanEntry,
+        ConnectionHandle,
+        ConnectOptions,
+        ConnectionAttemptFailedReason,
+        ConnectionLostReason,
+        Error,
+        IpRange,
+        ModerationAction,
+        ModerationRule,
+        Ordering,
+        PeerEvent,
+        Priority,
+        Reliability,
+        SecurityEventKind,
+        SendOptions,
+        SendTarget,
         connection_manager::ConnectionManager,
         constants::{RAKNET_PROTOCOL_VERSION, UDP_HEADER_SIZE},
+        internal_packet::{InternalOrdering, InternalPacket, InternalReliability},
         message_ids::MessageId,
         messages::{
+            ConnectErrorMessage,
             IncompatibleProtocolVersionMessage,
             OpenConnectionReply1Message,
             OpenConnectionReply2Message,
             OpenConnectionRequest1Message,
             OpenConnectionRequest2Message,
+            OutOfBandInternalMessage,
             UnconnectedPingMessage,
             UnconnectedPongMessage,
         },
+        number::DatagramSequenceNumber,
+        packet_datagram::PacketDatagram,
         reader::{MessageRead, DataReader},
         socket::FakeDatagramSocket,
         writer::MessageWrite,
@@ -106,15 +673,21 @@ mod tests {
 
     const OWN_GUID: u64 = 0xFEDCBA9876453210;
 
-    fn create_connection_manager() -> (ConnectionManager<FakeDatagramSocket>, Sender<(Vec<u8>, SocketAddr)>, Receiver<(Vec<u8>, SocketAddr)>, SocketAddr) {
+    type TestSetup = (ConnectionManager<FakeDatagramSocket>, Sender<(Vec<u8>, SocketAddr)>, Receiver<(Vec<u8>, SocketAddr)>, SocketAddr);
+
+    fn create_connection_manager() -> TestSetup {
+        let mut config = Config::default();
+        config.guid = OWN_GUID;
+        create_connection_manager_with_config(config)
+    }
+
+    fn create_connection_manager_with_config(config: Config) -> TestSetup {
         let local_addr = "127.0.0.2:19132".parse::<SocketAddr>().expect("Could not create address");
         let fake_socket = FakeDatagramSocket::new(local_addr);
         let datagram_sender = fake_socket.get_datagram_sender();
         let datagram_receiver = fake_socket.get_datagram_receiver();
         let remote_addr = "127.0.0.1:19132".parse::<SocketAddr>().expect("Could not create address");
-        let mut config = Config::default();
-        config.guid = OWN_GUID;
-        (ConnectionManager::new(fake_socket, config), datagram_sender, datagram_receiver, remote_addr)
+        (ConnectionManager::new(fake_socket, config).expect("Could not create connection manager"), datagram_sender, datagram_receiver, remote_addr)
     }
 
     fn send_datagram<M: MessageWrite>(message: M, datagram_sender: &mut Sender<(Vec<u8>, SocketAddr)>, remote_addr: SocketAddr) {
@@ -123,6 +696,34 @@ mod tests {
         datagram_sender.send((buf, remote_addr)).expect("Could not send datagram");
     }
 
+    /// Lets the remote peer start a connection with "open connection request 2" and
+    /// then send a connected ping before the connection request, which is garbage
+    /// from a sender that has not been verified yet.
+    fn send_ping_from_unverified_sender(connection_manager: &mut ConnectionManager<FakeDatagramSocket>, datagram_sender: &mut Sender<(Vec<u8>, SocketAddr)>, datagram_receiver: &Receiver<(Vec<u8>, SocketAddr)>, remote_addr: SocketAddr, time: Instant) {
+        let req2 = OpenConnectionRequest2Message {
+            cookie_and_challenge: None,
+            binding_address: SocketAddr::from(([192, 168, 1, 248], 0x1234)),
+            mtu: 576,
+            guid: 0x12345678,
+            handoff_token: None,
+        };
+        send_datagram(req2, datagram_sender, remote_addr);
+        connection_manager.process(time);
+        datagram_receiver.try_iter().for_each(drop);
+        let mut datagram = PacketDatagram::new(DatagramSequenceNumber::ZERO);
+        datagram.push(InternalPacket::new(time, InternalReliability::Unreliable, InternalOrdering::None, None, None, vec![MessageId::ConnectedPing.into(), 0, 0, 0, 0, 0, 0, 0, 0].into_boxed_slice()));
+        let mut payload = Vec::new();
+        datagram.write(&mut payload).expect("Could not write datagram");
+        datagram_sender.send((payload, remote_addr)).expect("Could not send datagram");
+    }
+
+    fn open_connection_request_1() -> OpenConnectionRequest1Message {
+        OpenConnectionRequest1Message {
+            protocol_version: RAKNET_PROTOCOL_VERSION,
+            padding_length: 400,
+        }
+    }
+
     fn receive_datagram<M: MessageRead>(datagram_receiver: &mut Receiver<(Vec<u8>, SocketAddr)>) -> (M, SocketAddr) {
         let (payload, addr) = datagram_receiver.try_recv().expect("Datagram not received");
         let mut reader = DataReader::new(&payload);
@@ -154,67 +755,1241 @@ mod tests {
     }
 
     #[test]
-    fn open_connection_request_1_incompatible_protocol_version() {
+    fn ping_from_ipv4_mapped_address_is_answered_to_ipv4_address() {
         // Arrange
         let (mut connection_manager, mut datagram_sender, mut datagram_receiver, remote_addr) = create_connection_manager();
-        let req1 = OpenConnectionRequest1Message {
-            protocol_version: RAKNET_PROTOCOL_VERSION + 1, // INVALID protocol version
-            padding_length: 8,
+        let mapped_addr = "[::ffff:127.0.0.1]:19132".parse::<SocketAddr>().expect("Could not create address");
+        let ping = UnconnectedPingMessage {
+            message_id: MessageId::UnconnectedPing,
+            time: 0x0123456789ABCDEF,
+            client_guid: 0x1122334455667788,
         };
-        send_datagram(req1, &mut datagram_sender, remote_addr);
+        send_datagram(ping, &mut datagram_sender, mapped_addr);
 
         // Act
         connection_manager.process(Instant::now());
 
         // Assert
-        let (message, addr) = receive_datagram::<IncompatibleProtocolVersionMessage>(&mut datagram_receiver);
-        assert_eq!(remote_addr, addr);      
-        assert_eq!(RAKNET_PROTOCOL_VERSION, message.protocol_version);
-        assert_eq!(OWN_GUID, message.guid);
+        let (_, addr) = receive_datagram::<UnconnectedPongMessage>(&mut datagram_receiver);
+        assert_eq!(remote_addr, addr);
     }
 
     #[test]
-    fn open_connection_request_1_responds_with_reply_1() {
+    fn datagram_that_would_block_is_sent_in_next_round() {
         // Arrange
-        let (mut connection_manager, mut datagram_sender, mut datagram_receiver, remote_addr) = create_connection_manager();
-        let req1 = OpenConnectionRequest1Message {
-            protocol_version: RAKNET_PROTOCOL_VERSION,
-            padding_length: 400,
+        let local_addr = "127.0.0.2:19132".parse::<SocketAddr>().expect("Could not create address");
+        let remote_addr = "127.0.0.1:19132".parse::<SocketAddr>().expect("Could not create address");
+        let fake_socket = FakeDatagramSocket::new(local_addr);
+        let mut datagram_sender = fake_socket.get_datagram_sender();
+        let mut datagram_receiver = fake_socket.get_datagram_receiver();
+        let send_would_block = fake_socket.get_send_would_block();
+        let mut connection_manager = ConnectionManager::new(fake_socket, Config { guid: OWN_GUID, ..Config::default() }).expect("Could not create connection manager");
+        let ping = UnconnectedPingMessage {
+            message_id: MessageId::UnconnectedPing,
+            time: 0x0123456789ABCDEF,
+            client_guid: 0x1122334455667788,
         };
-        send_datagram(req1, &mut datagram_sender, remote_addr);
+        send_datagram(ping, &mut datagram_sender, remote_addr);
+        send_would_block.store(true, std::sync::atomic::Ordering::Relaxed);
+        connection_manager.process(Instant::now());
+        let sent_while_blocked = datagram_receiver.try_recv().is_ok();
 
         // Act
+        send_would_block.store(false, std::sync::atomic::Ordering::Relaxed);
         connection_manager.process(Instant::now());
 
         // Assert
-        let (message, addr) = receive_datagram::<OpenConnectionReply1Message>(&mut datagram_receiver);
+        assert!(!sent_while_blocked);
+        let (pong, addr) = receive_datagram::<UnconnectedPongMessage>(&mut datagram_receiver);
         assert_eq!(remote_addr, addr);
-        assert_eq!(OWN_GUID, message.guid);
-        assert_eq!(None, message.cookie_and_public_key);
-        assert_eq!(UDP_HEADER_SIZE + 1 + 16 + 1 + 400, message.mtu);
+        assert_eq!(0x0123456789ABCDEF, pong.time);
+        assert_eq!(0, connection_manager.dropped_datagram_count());
     }
 
     #[test]
-    fn open_connection_request_2_responds_with_reply_2() {
+    fn datagrams_exceeding_send_queue_are_dropped() {
+        // Arrange
+        let local_addr = "127.0.0.2:19132".parse::<SocketAddr>().expect("Could not create address");
+        let remote_addr = "127.0.0.1:19132".parse::<SocketAddr>().expect("Could not create address");
+        let fake_socket = FakeDatagramSocket::new(local_addr);
+        let mut datagram_sender = fake_socket.get_datagram_sender();
+        let datagram_receiver = fake_socket.get_datagram_receiver();
+        fake_socket.get_send_would_block().store(true, std::sync::atomic::Ordering::Relaxed);
+        let config = Config { guid: OWN_GUID, max_queued_datagrams: 2, ..Config::default() };
+        let mut connection_manager = ConnectionManager::new(fake_socket, config).expect("Could not create connection manager");
+        for _ in 0..3 {
+            let ping = UnconnectedPingMessage {
+                message_id: MessageId::UnconnectedPing,
+                time: 0x0123456789ABCDEF,
+                client_guid: 0x1122334455667788,
+            };
+            send_datagram(ping, &mut datagram_sender, remote_addr);
+        }
+
+        // Act
+        connection_manager.process(Instant::now());
+
+        // Assert
+        assert_eq!(1, connection_manager.dropped_datagram_count());
+        assert!(datagram_receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn pings_over_rate_limit_are_not_answered() {
+        // Arrange
+        let config = Config { guid: OWN_GUID, max_offline_packets_per_second_per_ip: 3, ..Config::default() };
+        let (mut connection_manager, mut datagram_sender, datagram_receiver, remote_addr) = create_connection_manager_with_config(config);
+        for _ in 0..5 {
+            let ping = UnconnectedPingMessage {
+                message_id: MessageId::UnconnectedPing,
+                time: 0x0123456789ABCDEF,
+                client_guid: 0x1122334455667788,
+            };
+            send_datagram(ping, &mut datagram_sender, remote_addr);
+        }
+
+        // Act
+        connection_manager.process(Instant::now());
+
+        // Assert
+        assert_eq!(3, datagram_receiver.try_iter().count());
+    }
+
+    #[test]
+    fn out_of_band_message_has_receive_time() {
+        // Arrange
+        let (mut connection_manager, mut datagram_sender, _datagram_receiver, remote_addr) = create_connection_manager();
+        let event_receiver = connection_manager.event_receiver();
+        let time = connection_manager.start_time() + Duration::from_millis(250);
+        send_datagram(OutOfBandInternalMessage { guid: 0x1122334455667788, data: vec![0x01, 0x02] }, &mut datagram_sender, remote_addr);
+
+        // Act
+        connection_manager.process(time);
+
+        // Assert
+        let event = event_receiver.try_recv().expect("Event not received");
+        assert!(matches!(event, PeerEvent::OutOfBandMessage { addr, received_at, received_ms: 250, .. } if addr == remote_addr && received_at == time));
+    }
+
+    #[test]
+    fn pings_over_rate_limit_report_one_security_event() {
+        // Arrange
+        let config = Config { guid: OWN_GUID, max_offline_packets_per_second_per_ip: 3, ..Config::default() };
+        let (mut connection_manager, mut datagram_sender, _datagram_receiver, remote_addr) = create_connection_manager_with_config(config);
+        let event_receiver = connection_manager.event_receiver();
+        for _ in 0..5 {
+            let ping = UnconnectedPingMessage {
+                message_id: MessageId::UnconnectedPing,
+                time: 0x0123456789ABCDEF,
+                client_guid: 0x1122334455667788,
+            };
+            send_datagram(ping, &mut datagram_sender, remote_addr);
+        }
+
+        // Act
+        connection_manager.process(Instant::now());
+
+        // Assert
+        let events: Vec<PeerEvent> = event_receiver.try_iter().collect();
+        assert_eq!(1, events.len());
+        assert!(matches!(events[0], PeerEvent::SecurityEvent { addr, kind: SecurityEventKind::RateLimitExceeded, .. } if addr == remote_addr));
+    }
+
+    #[test]
+    fn banned_address_receives_connection_banned_and_no_pong() {
         // Arrange
         let (mut connection_manager, mut datagram_sender, mut datagram_receiver, remote_addr) = create_connection_manager();
-        let req2 = OpenConnectionRequest2Message {
-            cookie_and_challenge: None,
-            binding_address: SocketAddr::from(([192, 168, 1, 248], 0x1234)),
-            mtu: 446,
-            guid: 0x12345678,
+        let time = Instant::now();
+        connection_manager.ban_address(time, remote_addr.ip().into(), None);
+        let ping = UnconnectedPingMessage {
+            message_id: MessageId::UnconnectedPing,
+            time: 0x0123456789ABCDEF,
+            client_guid: 0x1122334455667788,
         };
-        send_datagram(req2, &mut datagram_sender, remote_addr);
+        send_datagram(ping, &mut datagram_sender, remote_addr);
+        let req1 = OpenConnectionRequest1Message {
+            protocol_version: RAKNET_PROTOCOL_VERSION,
+            padding_length: 400,
+        };
+        send_datagram(req1, &mut datagram_sender, remote_addr);
 
         // Act
-        connection_manager.process(Instant::now());
+        connection_manager.process(time);
 
         // Assert
-        let (message, addr) = receive_datagram::<OpenConnectionReply2Message>(&mut datagram_receiver);
+        let (message, addr) = receive_datagram::<ConnectErrorMessage>(&mut datagram_receiver);
         assert_eq!(remote_addr, addr);
-        assert_eq!(OWN_GUID, message.guid);
-        assert_eq!(remote_addr, message.client_address);
-        assert_eq!(446, message.mtu);
-        assert_eq!(None, message.challenge_answer);
-    }     
-}
\ No newline at end of file
+        assert_eq!(MessageId::ConnectionBanned, message.message_id);
+        assert!(datagram_receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn unbanned_address_receives_pong() {
+        // Arrange
+        let (mut connection_manager, mut datagram_sender, mut datagram_receiver, remote_addr) = create_connection_manager();
+        let time = Instant::now();
+        connection_manager.ban_address(time, remote_addr.ip().into(), Some(Duration::from_secs(60)));
+        connection_manager.unban_address(time, remote_addr.ip().into());
+        let ping = UnconnectedPingMessage {
+            message_id: MessageId::UnconnectedPing,
+            time: 0x0123456789ABCDEF,
+            client_guid: 0x1122334455667788,
+        };
+        send_datagram(ping, &mut datagram_sender, remote_addr);
+
+        // Act
+        connection_manager.process(time);
+
+        // Assert
+        let (pong, _) = receive_datagram::<UnconnectedPongMessage>(&mut datagram_receiver);
+        assert_eq!(0x0123456789ABCDEF, pong.time);
+    }
+
+    #[test]
+    fn bans_are_exported_and_imported_with_expiry_times() {
+        // Arrange
+        let (mut connection_manager, .., remote_addr) = create_connection_manager();
+        let (mut other_connection_manager, mut datagram_sender, datagram_receiver, _) = create_connection_manager();
+        let time = Instant::now();
+        connection_manager.ban_address(time, remote_addr.ip().into(), Some(Duration::from_secs(60)));
+        connection_manager.ban_address(time, "10.0.0.0/8".parse().expect("Could not create range"), None);
+        connection_manager.ban_address(time, "192.168.0.0/16".parse().expect("Could not create range"), Some(Duration::from_secs(1)));
+        let mut ban_file = connection_manager.bans(time + Duration::from_secs(2));
+        ban_file.bans.push(BanEntry { range: "172.16.0.0/12".parse().expect("Could not create range"), expires_at: Some(1) });
+
+        // Act
+        let ban_count = other_connection_manager.add_bans(time, &ban_file);
+        let ping = UnconnectedPingMessage {
+            message_id: MessageId::UnconnectedPing,
+            time: 0x0123456789ABCDEF,
+            client_guid: 0x1122334455667788,
+        };
+        send_datagram(ping, &mut datagram_sender, remote_addr);
+        other_connection_manager.process(time);
+
+        // Assert
+        assert_eq!(2, ban_count);
+        let bans = other_connection_manager.bans(time).bans;
+        assert_eq!(2, bans.len());
+        assert_eq!(IpRange::from(remote_addr.ip()), bans[0].range);
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).expect("Could not get time").as_secs();
+        assert!(bans[0].expires_at.is_some_and(|expires_at| expires_at > now + 50 && expires_at <= now + 60));
+        assert_eq!(None, bans[1].expires_at);
+        assert!(datagram_receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn open_connection_request_1_incompatible_protocol_version() {
+        // Arrange
+        let (mut connection_manager, mut datagram_sender, mut datagram_receiver, remote_addr) = create_connection_manager();
+        let req1 = OpenConnectionRequest1Message {
+            protocol_version: RAKNET_PROTOCOL_VERSION + 1, // INVALID protocol version
+            padding_length: 8,
+        };
+        send_datagram(req1, &mut datagram_sender, remote_addr);
+
+        // Act
+        connection_manager.process(Instant::now());
+
+        // Assert
+        let (message, addr) = receive_datagram::<IncompatibleProtocolVersionMessage>(&mut datagram_receiver);
+        assert_eq!(remote_addr, addr);      
+        assert_eq!(RAKNET_PROTOCOL_VERSION, message.protocol_version);
+        assert_eq!(OWN_GUID, message.guid);
+    }
+
+    #[test]
+    fn open_connection_request_1_from_banned_address_reports_security_event() {
+        // Arrange
+        let (mut connection_manager, mut datagram_sender, _datagram_receiver, remote_addr) = create_connection_manager();
+        let event_receiver = connection_manager.event_receiver();
+        let time = Instant::now();
+        connection_manager.ban_address(time, remote_addr.ip().into(), None);
+        event_receiver.try_iter().for_each(drop);
+        let req1 = OpenConnectionRequest1Message {
+            protocol_version: RAKNET_PROTOCOL_VERSION,
+            padding_length: 400,
+        };
+        send_datagram(req1, &mut datagram_sender, remote_addr);
+
+        // Act
+        connection_manager.process(time);
+
+        // Assert
+        assert!(matches!(event_receiver.try_recv(),
+            Ok(PeerEvent::SecurityEvent { addr, kind: SecurityEventKind::BannedAddressConnecting, .. }) if addr == remote_addr));
+    }
+
+    #[test]
+    fn open_connection_request_1_responds_with_reply_1() {
+        // Arrange
+        let (mut connection_manager, mut datagram_sender, mut datagram_receiver, remote_addr) = create_connection_manager();
+        let req1 = OpenConnectionRequest1Message {
+            protocol_version: RAKNET_PROTOCOL_VERSION,
+            padding_length: 400,
+        };
+        send_datagram(req1, &mut datagram_sender, remote_addr);
+
+        // Act
+        connection_manager.process(Instant::now());
+
+        // Assert
+        let (message, addr) = receive_datagram::<OpenConnectionReply1Message>(&mut datagram_receiver);
+        assert_eq!(remote_addr, addr);
+        assert_eq!(OWN_GUID, message.guid);
+        assert_eq!(None, message.cookie_and_public_key);
+        assert_eq!(UDP_HEADER_SIZE + 1 + 16 + 1 + 400, message.mtu);
+    }
+
+    #[test]
+    fn open_connection_request_2_responds_with_reply_2() {
+        // Arrange
+        let (mut connection_manager, mut datagram_sender, mut datagram_receiver, remote_addr) = create_connection_manager();
+        let req2 = OpenConnectionRequest2Message {
+            cookie_and_challenge: None,
+            binding_address: SocketAddr::from(([192, 168, 1, 248], 0x1234)),
+            mtu: 576,
+            guid: 0x12345678,
+            handoff_token: None,
+        };
+        send_datagram(req2, &mut datagram_sender, remote_addr);
+
+        // Act
+        connection_manager.process(Instant::now());
+
+        // Assert
+        let (message, addr) = receive_datagram::<OpenConnectionReply2Message>(&mut datagram_receiver);
+        assert_eq!(remote_addr, addr);
+        assert_eq!(OWN_GUID, message.guid);
+        assert_eq!(remote_addr, message.client_address);
+        assert_eq!(576, message.mtu);
+        assert_eq!(None, message.challenge_answer);
+    }
+
+    #[test]
+    fn open_connection_request_2_with_mtu_below_min_mtu_size_responds_with_connection_attempt_failed() {
+        // Arrange
+        let config = Config { guid: OWN_GUID, min_mtu_size: 1200, ..Config::default() };
+        let (mut connection_manager, mut datagram_sender, mut datagram_receiver, remote_addr) = create_connection_manager_with_config(config);
+        let req2 = OpenConnectionRequest2Message {
+            cookie_and_challenge: None,
+            binding_address: SocketAddr::from(([192, 168, 1, 248], 0x1234)),
+            mtu: 1199,
+            guid: 0x12345678,
+            handoff_token: None,
+        };
+        send_datagram(req2, &mut datagram_sender, remote_addr);
+
+        // Act
+        connection_manager.process(Instant::now());
+
+        // Assert
+        let (message, addr) = receive_datagram::<ConnectErrorMessage>(&mut datagram_receiver);
+        assert_eq!(remote_addr, addr);
+        assert_eq!(MessageId::ConnectionAttemptFailed, message.message_id);
+        assert_eq!(OWN_GUID, message.guid);
+        assert!(connection_manager.connections.is_empty());
+    }
+
+    #[test]
+    fn open_connection_request_2_from_recently_connected_ip_responds_with_ip_recently_connected() {
+        // Arrange
+        let config = Config { guid: OWN_GUID, recent_connection_window_in_ms: 100, ..Config::default() };
+        let (mut connection_manager, mut datagram_sender, mut datagram_receiver, remote_addr) = create_connection_manager_with_config(config);
+        let other_remote_addr = SocketAddr::new(remote_addr.ip(), remote_addr.port() + 1);
+        for (addr, guid) in [(remote_addr, 0x12345678), (other_remote_addr, 0x87654321)] {
+            let req2 = OpenConnectionRequest2Message {
+                cookie_and_challenge: None,
+                binding_address: SocketAddr::from(([192, 168, 1, 248], 0x1234)),
+                mtu: 576,
+                guid,
+                handoff_token: None,
+            };
+            send_datagram(req2, &mut datagram_sender, addr);
+        }
+
+        // Act
+        connection_manager.process(Instant::now());
+
+        // Assert
+        let (_reply2, addr) = receive_datagram::<OpenConnectionReply2Message>(&mut datagram_receiver);
+        assert_eq!(remote_addr, addr);
+        let (message, addr) = receive_datagram::<ConnectErrorMessage>(&mut datagram_receiver);
+        assert_eq!(other_remote_addr, addr);
+        assert_eq!(MessageId::IpRecentlyConnected, message.message_id);
+        assert_eq!(1, connection_manager.connections.len());
+    }
+
+    #[test]
+    fn open_connection_request_2_is_handled_according_to_admission_policy() {
+        // Arrange
+        let (mut connection_manager, mut datagram_sender, mut datagram_receiver, remote_addr) = create_connection_manager();
+        let event_receiver = connection_manager.event_receiver();
+        connection_manager.set_admission_policy(Some(Box::new(|_addr, guid| match guid {
+            0x11111111 => Admission::Reject,
+            0x22222222 => Admission::Ignore,
+            _ => Admission::Accept,
+        })));
+        for guid in [0x11111111, 0x22222222, 0x33333333] {
+            let req2 = OpenConnectionRequest2Message {
+                cookie_and_challenge: None,
+                binding_address: SocketAddr::from(([192, 168, 1, 248], 0x1234)),
+                mtu: 576,
+                guid,
+                handoff_token: None,
+            };
+            send_datagram(req2, &mut datagram_sender, remote_addr);
+        }
+
+        // Act
+        connection_manager.process(Instant::now());
+
+        // Assert
+        let (message, _) = receive_datagram::<ConnectErrorMessage>(&mut datagram_receiver);
+        assert_eq!(MessageId::ConnectionBanned, message.message_id);
+        receive_datagram::<OpenConnectionReply2Message>(&mut datagram_receiver);
+        assert!(datagram_receiver.try_recv().is_err());
+        assert_eq!(1, connection_manager.connections.len());
+        let rejected_guids: Vec<Option<u64>> = event_receiver.try_iter()
+            .filter_map(|event| match event {
+                PeerEvent::ModerationAction { action: ModerationAction::ConnectionRejected { guid }, rule: ModerationRule::AdmissionPolicy, .. } => Some(guid),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(vec![Some(0x11111111), Some(0x22222222)], rejected_guids);
+    }
+
+    #[test]
+    fn open_connection_request_2_not_in_allow_list_responds_with_connection_banned() {
+        // Arrange
+        let (mut connection_manager, mut datagram_sender, mut datagram_receiver, remote_addr) = create_connection_manager();
+        let event_receiver = connection_manager.event_receiver();
+        connection_manager.set_allow_list(Some(AllowList::new()));
+        connection_manager.allow_guid(0x87654321);
+        for guid in [0x12345678, 0x87654321] {
+            let req2 = OpenConnectionRequest2Message {
+                cookie_and_challenge: None,
+                binding_address: SocketAddr::from(([192, 168, 1, 248], 0x1234)),
+                mtu: 576,
+                guid,
+                handoff_token: None,
+            };
+            send_datagram(req2, &mut datagram_sender, remote_addr);
+        }
+
+        // Act
+        connection_manager.process(Instant::now());
+
+        // Assert
+        let (message, addr) = receive_datagram::<ConnectErrorMessage>(&mut datagram_receiver);
+        assert_eq!(remote_addr, addr);
+        assert_eq!(MessageId::ConnectionBanned, message.message_id);
+        assert!(matches!(event_receiver.try_recv(),
+            Ok(PeerEvent::SecurityEvent { kind: SecurityEventKind::NotAllowedConnecting { guid: 0x12345678 }, .. })));
+        let (_reply2, addr) = receive_datagram::<OpenConnectionReply2Message>(&mut datagram_receiver);
+        assert_eq!(remote_addr, addr);
+        assert_eq!(1, connection_manager.connections.len());
+    }
+
+    #[test]
+    fn garbage_from_unverified_sender_greylists_ip_until_cool_down_ends() {
+        // Arrange
+        let config = Config { guid: OWN_GUID, garbage_penalty_in_ms: 0, greylist_duration_in_ms: 5000, ..Config::default() };
+        let (mut connection_manager, mut datagram_sender, mut datagram_receiver, remote_addr) = create_connection_manager_with_config(config);
+        let event_receiver = connection_manager.event_receiver();
+        let time = Instant::now();
+        send_ping_from_unverified_sender(&mut connection_manager, &mut datagram_sender, &datagram_receiver, remote_addr, time);
+
+        // Act
+        connection_manager.process(time);
+        send_datagram(open_connection_request_1(), &mut datagram_sender, remote_addr);
+        connection_manager.process(time);
+        let reply_while_greylisted = datagram_receiver.try_recv().ok();
+        connection_manager.process(time + Duration::from_millis(5000));
+        send_datagram(open_connection_request_1(), &mut datagram_sender, remote_addr);
+        connection_manager.process(time + Duration::from_millis(5000));
+
+        // Assert
+        assert_eq!(None, reply_while_greylisted);
+        let events: Vec<PeerEvent> = event_receiver.try_iter().collect();
+        assert!(events.contains(&PeerEvent::AddressGreylisted { ip: remote_addr.ip(), reason: ConnectionLostReason::Garbage, duration: Duration::from_millis(5000) }));
+        assert!(events.contains(&PeerEvent::GreylistExpired { ip: remote_addr.ip() }));
+        let (_reply1, addr) = receive_datagram::<OpenConnectionReply1Message>(&mut datagram_receiver);
+        assert_eq!(remote_addr, addr);
+        assert_eq!(1, connection_manager.metrics().greylisted_datagrams);
+    }
+
+    #[test]
+    fn garbage_from_unverified_sender_closes_connection_and_bans_ip() {
+        // Arrange
+        let (mut connection_manager, mut datagram_sender, mut datagram_receiver, remote_addr) = create_connection_manager();
+        let event_receiver = connection_manager.event_receiver();
+        let time = Instant::now();
+        send_ping_from_unverified_sender(&mut connection_manager, &mut datagram_sender, &datagram_receiver, remote_addr, time);
+
+        // Act
+        connection_manager.process(time);
+        send_datagram(open_connection_request_1(), &mut datagram_sender, remote_addr);
+        connection_manager.process(time);
+
+        // Assert
+        assert!(connection_manager.connections.is_empty());
+        assert!(event_receiver.try_iter().any(|event| matches!(event,
+            PeerEvent::SecurityEvent { addr, kind: SecurityEventKind::GarbageFromUnverifiedSender { penalty }, .. }
+            if addr == remote_addr && penalty == Duration::from_millis(10000))));
+        let (message, _) = receive_datagram::<ConnectErrorMessage>(&mut datagram_receiver);
+        assert_eq!(MessageId::ConnectionBanned, message.message_id);
+    }
+
+    #[test]
+    fn moderation_actions_are_reported_with_triggering_rule() {
+        // Arrange
+        let (mut connection_manager, mut datagram_sender, datagram_receiver, remote_addr) = create_connection_manager();
+        let event_receiver = connection_manager.event_receiver();
+        let time = Instant::now();
+        send_ping_from_unverified_sender(&mut connection_manager, &mut datagram_sender, &datagram_receiver, remote_addr, time);
+
+        // Act
+        connection_manager.process(time);
+        send_datagram(open_connection_request_1(), &mut datagram_sender, remote_addr);
+        connection_manager.process(time);
+        connection_manager.unban_address(time, remote_addr.ip().into());
+
+        // Assert
+        let range = IpRange::from(remote_addr.ip());
+        let actions: Vec<(Option<SocketAddr>, ModerationAction, ModerationRule)> = event_receiver.try_iter()
+            .filter_map(|event| match event {
+                PeerEvent::ModerationAction { addr, action, rule, .. } => Some((addr, action, rule)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(vec![
+            (Some(remote_addr), ModerationAction::Kicked { guid: 0x12345678 }, ModerationRule::Garbage),
+            (Some(remote_addr), ModerationAction::Banned { range, duration: Some(Duration::from_millis(10000)) }, ModerationRule::Garbage),
+            (Some(remote_addr), ModerationAction::ConnectionRejected { guid: None }, ModerationRule::BanList),
+            (None, ModerationAction::Unbanned { range }, ModerationRule::Manual),
+        ], actions);
+        assert_eq!("kick", actions[0].1.code());
+        assert_eq!("ban_list", actions[2].2.code());
+    }
+
+    #[test]
+    #[cfg(feature = "rand")]
+    fn open_connection_request_1_with_required_cookies_responds_with_cookie() {
+        // Arrange
+        let config = Config { guid: OWN_GUID, require_cookies: true, ..Config::default() };
+        let (mut connection_manager, mut datagram_sender, mut datagram_receiver, remote_addr) = create_connection_manager_with_config(config);
+        let req1 = OpenConnectionRequest1Message {
+            protocol_version: RAKNET_PROTOCOL_VERSION,
+            padding_length: 400,
+        };
+        send_datagram(req1, &mut datagram_sender, remote_addr);
+
+        // Act
+        connection_manager.process(Instant::now());
+
+        // Assert
+        let (message, _) = receive_datagram::<OpenConnectionReply1Message>(&mut datagram_receiver);
+        let (_cookie, public_key) = message.cookie_and_public_key.expect("No cookie was sent");
+        assert!(public_key.iter().all(|byte| *byte == 0));
+    }
+
+    #[test]
+    #[cfg(feature = "rand")]
+    fn open_connection_request_2_with_invalid_cookie_is_ignored() {
+        // Arrange
+        let config = Config { guid: OWN_GUID, require_cookies: true, ..Config::default() };
+        let (mut connection_manager, mut datagram_sender, datagram_receiver, remote_addr) = create_connection_manager_with_config(config);
+        let req2 = OpenConnectionRequest2Message {
+            cookie_and_challenge: Some((0x12345678, None)),
+            binding_address: SocketAddr::from(([192, 168, 1, 248], 0x1234)),
+            mtu: 576,
+            guid: 0x12345678,
+            handoff_token: None,
+        };
+        send_datagram(req2, &mut datagram_sender, remote_addr);
+
+        // Act
+        connection_manager.process(Instant::now());
+
+        // Assert
+        assert!(datagram_receiver.try_recv().is_err());
+        assert!(connection_manager.connections.is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "rand")]
+    fn connect_with_required_cookies_establishes_connection() {
+        // Arrange
+        let server_config = Config { guid: OWN_GUID, require_cookies: true, ..Config::default() };
+
+        // Act
+        let (_client_events, server_events, handle) = connect_client_to_server(server_config, ConnectOptions::default());
+
+        // Assert
+        assert!(server_events.iter().any(|event| matches!(event, PeerEvent::IncomingConnection(_))));
+        assert_eq!(Some(Ok(OWN_GUID)), handle.try_result());
+    }
+
+    #[test]
+    #[cfg(feature = "rand")]
+    fn open_connection_request_2_without_proof_of_work_is_ignored() {
+        // Arrange
+        let config = Config { guid: OWN_GUID, proof_of_work_difficulty: 8, ..Config::default() };
+        let (mut connection_manager, mut datagram_sender, mut datagram_receiver, remote_addr) = create_connection_manager_with_config(config);
+        let event_receiver = connection_manager.event_receiver();
+        let req1 = OpenConnectionRequest1Message {
+            protocol_version: RAKNET_PROTOCOL_VERSION,
+            padding_length: 400,
+        };
+        send_datagram(req1, &mut datagram_sender, remote_addr);
+        connection_manager.process(Instant::now());
+        let (reply1, _) = receive_datagram::<OpenConnectionReply1Message>(&mut datagram_receiver);
+        let (cookie, public_key_field) = reply1.cookie_and_public_key.expect("No cookie was sent");
+        let req2 = OpenConnectionRequest2Message {
+            cookie_and_challenge: Some((cookie, None)),
+            binding_address: SocketAddr::from(([192, 168, 1, 248], 0x1234)),
+            mtu: 576,
+            guid: 0x12345678,
+            handoff_token: None,
+        };
+        send_datagram(req2, &mut datagram_sender, remote_addr);
+
+        // Act
+        connection_manager.process(Instant::now());
+
+        // Assert
+        assert_eq!(8, public_key_field[32]);
+        assert!(datagram_receiver.try_recv().is_err());
+        assert!(connection_manager.connections.is_empty());
+        assert!(event_receiver.try_iter().any(|event| matches!(event, PeerEvent::SecurityEvent { kind: SecurityEventKind::InvalidProofOfWork, .. })));
+    }
+
+    #[test]
+    #[cfg(feature = "rand")]
+    fn proof_of_work_is_not_required_below_connection_threshold() {
+        // Arrange
+        let config = Config { guid: OWN_GUID, proof_of_work_difficulty: 8, proof_of_work_connection_threshold: 1, ..Config::default() };
+        let (mut connection_manager, mut datagram_sender, mut datagram_receiver, remote_addr) = create_connection_manager_with_config(config);
+        let req1 = OpenConnectionRequest1Message {
+            protocol_version: RAKNET_PROTOCOL_VERSION,
+            padding_length: 400,
+        };
+        send_datagram(req1, &mut datagram_sender, remote_addr);
+
+        // Act
+        connection_manager.process(Instant::now());
+
+        // Assert
+        let (reply1, _) = receive_datagram::<OpenConnectionReply1Message>(&mut datagram_receiver);
+        let (_cookie, public_key_field) = reply1.cookie_and_public_key.expect("No cookie was sent");
+        assert!(public_key_field.iter().all(|byte| *byte == 0));
+    }
+
+    #[test]
+    #[cfg(feature = "rand")]
+    fn connect_with_proof_of_work_establishes_connection() {
+        // Arrange
+        let server_config = Config { guid: OWN_GUID, proof_of_work_difficulty: 8, ..Config::default() };
+
+        // Act
+        let (_client_events, server_events, handle) = connect_client_to_server(server_config, ConnectOptions::default());
+
+        // Assert
+        assert!(server_events.iter().any(|event| matches!(event, PeerEvent::IncomingConnection(_))));
+        assert_eq!(Some(Ok(OWN_GUID)), handle.try_result());
+    }
+
+    #[test]
+    #[cfg(feature = "rand")]
+    fn connect_with_security_and_proof_of_work_establishes_connection() {
+        // Arrange
+        let security = SecurityConfig::generate();
+        let options = ConnectOptions { server_public_key: Some(security.public_key()), ..ConnectOptions::default() };
+        let server_config = Config { guid: OWN_GUID, security: Some(security), proof_of_work_difficulty: 8, ..Config::default() };
+
+        // Act
+        let (_client_events, server_events, handle) = connect_client_to_server(server_config, options);
+
+        // Assert
+        assert!(server_events.iter().any(|event| matches!(event, PeerEvent::IncomingConnection(_))));
+        assert_eq!(Some(Ok(OWN_GUID)), handle.try_result());
+    }
+
+    #[test]
+    #[cfg(feature = "rand")]
+    fn connect_with_handoff_token_skips_allow_list() {
+        // Arrange
+        let server_config = Config { guid: OWN_GUID, allow_list: Some(AllowList::new()), handoff_key: Some(b"shared key".to_vec()), ..Config::default() };
+        let expires_at = SystemTime::now() + Duration::from_secs(30);
+        let client_ip = "127.0.0.3".parse().expect("Could not parse IP address");
+        let other_ip = "127.0.0.4".parse().expect("Could not parse IP address");
+        let options = ConnectOptions { handoff_token: Some(HandoffToken::new(b"shared key", 0x1122334455667788, client_ip, 42, expires_at)), ..ConnectOptions::default() };
+        let forged_options = ConnectOptions { handoff_token: Some(HandoffToken::new(b"other key", 0x1122334455667788, client_ip, 42, expires_at)), ..ConnectOptions::default() };
+        let other_ip_options = ConnectOptions { handoff_token: Some(HandoffToken::new(b"shared key", 0x1122334455667788, other_ip, 42, expires_at)), ..ConnectOptions::default() };
+
+        // Act
+        let (_client_events, server_events, handle) = connect_client_to_server(server_config.clone(), options);
+        let (_forged_client_events, forged_server_events, forged_handle) = connect_client_to_server(server_config.clone(), forged_options);
+        let (_other_ip_client_events, other_ip_server_events, other_ip_handle) = connect_client_to_server(server_config, other_ip_options);
+
+        // Assert
+        assert!(server_events.iter().any(|event| matches!(event, PeerEvent::IncomingConnection(connection) if connection.handoff_user_data() == Some(42))));
+        assert_eq!(Some(Ok(OWN_GUID)), handle.try_result());
+        assert!(forged_server_events.iter().any(|event| matches!(event, PeerEvent::SecurityEvent { kind: SecurityEventKind::InvalidHandoffToken, .. })));
+        assert_eq!(Some(Err(ConnectionAttemptFailedReason::Banned)), forged_handle.try_result());
+        assert!(other_ip_server_events.iter().any(|event| matches!(event, PeerEvent::SecurityEvent { kind: SecurityEventKind::InvalidHandoffToken, .. })));
+        assert_eq!(Some(Err(ConnectionAttemptFailedReason::Banned)), other_ip_handle.try_result());
+    }
+
+    /// Connects a client connection manager to a server connection manager by passing
+    /// datagrams between them and returns the events of the client and the server.
+    fn connect_client_to_server(server_config: Config, options: ConnectOptions) -> (Vec<PeerEvent>, Vec<PeerEvent>, ConnectionHandle) {
+        let client_config = Config { guid: 0x1122334455667788, ..Config::default() };
+        connect_client_to_server_with_config(client_config, server_config, options)
+    }
+
+    fn connect_client_to_server_with_config(client_config: Config, server_config: Config, options: ConnectOptions) -> (Vec<PeerEvent>, Vec<PeerEvent>, ConnectionHandle) {
+        let client_addr = "127.0.0.3:19132".parse::<SocketAddr>().expect("Could not create address");
+        let server_addr = "127.0.0.2:19132".parse::<SocketAddr>().expect("Could not create address");
+        connect_client_to_server_at(client_addr, server_addr, client_config, server_config, options)
+    }
+
+    fn connect_client_to_server_at(client_addr: SocketAddr, server_addr: SocketAddr, client_config: Config, server_config: Config, options: ConnectOptions) -> (Vec<PeerEvent>, Vec<PeerEvent>, ConnectionHandle) {
+        let client_socket = FakeDatagramSocket::new(client_addr);
+        let client_sender = client_socket.get_datagram_sender();
+        let client_receiver = client_socket.get_datagram_receiver();
+        let mut client = ConnectionManager::new(client_socket, client_config).expect("Could not create connection manager");
+        let server_socket = FakeDatagramSocket::new(server_addr);
+        let server_sender = server_socket.get_datagram_sender();
+        let server_receiver = server_socket.get_datagram_receiver();
+        let mut server = ConnectionManager::new(server_socket, server_config).expect("Could not create connection manager");
+        let client_events = client.event_receiver();
+        let server_events = server.event_receiver();
+        let mut time = Instant::now();
+
+        let handle = client.connect(time, &[server_addr], options).expect("Could not connect");
+        for _ in 0..10 {
+            client.process(time);
+            while let Ok((payload, _)) = client_receiver.try_recv() {
+                server_sender.send((payload, client_addr)).expect("Could not send datagram");
+            }
+            server.process(time);
+            while let Ok((payload, _)) = server_receiver.try_recv() {
+                client_sender.send((payload, server_addr)).expect("Could not send datagram");
+            }
+            time += Duration::from_millis(10);
+        }
+        (client_events.try_iter().collect(), server_events.try_iter().collect(), handle)
+    }
+
+    #[test]
+    fn incomplete_handshake_loses_connection_after_timeout() {
+        // Arrange
+        let client_addr = "127.0.0.3:19132".parse::<SocketAddr>().expect("Could not create address");
+        let server_addr = "127.0.0.2:19132".parse::<SocketAddr>().expect("Could not create address");
+        let client_socket = FakeDatagramSocket::new(client_addr);
+        let client_sender = client_socket.get_datagram_sender();
+        let client_receiver = client_socket.get_datagram_receiver();
+        let mut client = ConnectionManager::new(client_socket, Config { guid: 0x1122334455667788, ..Config::default() }).expect("Could not create connection manager");
+        let server_socket = FakeDatagramSocket::new(server_addr);
+        let server_sender = server_socket.get_datagram_sender();
+        let server_receiver = server_socket.get_datagram_receiver();
+        let mut server = ConnectionManager::new(server_socket, Config { guid: OWN_GUID, incoming_connection_timeout_in_ms: 1000, ..Config::default() }).expect("Could not create connection manager");
+        let client_events = client.event_receiver();
+        let server_events = server.event_receiver();
+        let time = Instant::now();
+        client.connect(time, &[server_addr], ConnectOptions::default()).expect("Could not connect");
+        let mut is_established = false;
+        for _ in 0..10 {
+            client.process(time);
+            is_established = is_established || client_events.try_iter().any(|event| matches!(event, PeerEvent::ConnectionEstablished { .. }));
+            // Drop the "new incoming connection" so that the server never completes the handshake
+            while let Ok((payload, _)) = client_receiver.try_recv() {
+                if !is_established {
+                    server_sender.send((payload, client_addr)).expect("Could not send datagram");
+                }
+            }
+            server.process(time);
+            while let Ok((payload, _)) = server_receiver.try_recv() {
+                client_sender.send((payload, server_addr)).expect("Could not send datagram");
+            }
+        }
+        let events_before_timeout: Vec<PeerEvent> = server_events.try_iter().collect();
+
+        // Act
+        server.process(time + Duration::from_millis(1001));
+
+        // Assert
+        assert!(is_established);
+        assert!(events_before_timeout.is_empty());
+        assert_eq!(Ok(PeerEvent::ConnectionLost { addr: client_addr, guid: 0x1122334455667788, reason: ConnectionLostReason::HandshakeTimeout }), server_events.try_recv());
+        assert_eq!(None, server.guid_of(client_addr));
+    }
+
+    #[test]
+    fn connect_establishes_connection() {
+        // Arrange
+        let client_addr = "127.0.0.3:19132".parse::<SocketAddr>().expect("Could not create address");
+        let server_addr = "127.0.0.2:19132".parse::<SocketAddr>().expect("Could not create address");
+        let server_config = Config { guid: OWN_GUID, ..Config::default() };
+        let options = ConnectOptions { user_data: 7, ..ConnectOptions::default() };
+
+        // Act
+        let (client_events, server_events, handle) = connect_client_to_server(server_config, options);
+
+        // Assert
+        assert!(client_events.contains(&PeerEvent::ConnectionEstablished {
+            addr: server_addr,
+            guid: OWN_GUID,
+            external_addr: client_addr,
+            remote_internal_addrs: vec![server_addr],
+            user_data: 7,
+        }));
+        assert!(server_events.iter().any(|event| matches!(event, PeerEvent::IncomingConnection(_))));
+        assert_eq!(Some(Ok(OWN_GUID)), handle.try_result());
+    }
+
+    #[test]
+    fn connection_info_contains_reported_addresses() {
+        // Arrange
+        let client_addr = "127.0.0.3:19132".parse::<SocketAddr>().expect("Could not create address");
+        let server_addr = "127.0.0.2:19132".parse::<SocketAddr>().expect("Could not create address");
+        let client_socket = FakeDatagramSocket::new(client_addr);
+        let client_sender = client_socket.get_datagram_sender();
+        let client_receiver = client_socket.get_datagram_receiver();
+        let mut client = ConnectionManager::new(client_socket, Config { guid: 0x1122334455667788, ..Config::default() }).expect("Could not create connection manager");
+        let server_socket = FakeDatagramSocket::new(server_addr);
+        let server_sender = server_socket.get_datagram_sender();
+        let server_receiver = server_socket.get_datagram_receiver();
+        let mut server = ConnectionManager::new(server_socket, Config { guid: OWN_GUID, ..Config::default() }).expect("Could not create connection manager");
+        let mut time = Instant::now();
+        client.connect(time, &[server_addr], ConnectOptions::default()).expect("Could not connect");
+
+        // Act
+        for _ in 0..10 {
+            client.process(time);
+            while let Ok((payload, _)) = client_receiver.try_recv() {
+                server_sender.send((payload, client_addr)).expect("Could not send datagram");
+            }
+            server.process(time);
+            while let Ok((payload, _)) = server_receiver.try_recv() {
+                client_sender.send((payload, server_addr)).expect("Could not send datagram");
+            }
+            time += Duration::from_millis(10);
+        }
+
+        // Assert
+        let client_info = client.connection_info(time, OWN_GUID).expect("Client has no connection");
+        assert_eq!(Some(client_addr), client_info.external_addr);
+        assert_eq!(vec![server_addr], client_info.remote_internal_addrs);
+        let server_info = server.connection_info(time, 0x1122334455667788).expect("Server has no connection");
+        assert_eq!(Some(server_addr), server_info.external_addr);
+        assert_eq!(vec![client_addr], server_info.remote_internal_addrs);
+    }
+
+    #[test]
+    fn connect_over_ipv6_establishes_connection() {
+        // Arrange
+        let client_addr = "[2001:db8::3]:19132".parse::<SocketAddr>().expect("Could not create address");
+        let server_addr = "[2001:db8::2]:19132".parse::<SocketAddr>().expect("Could not create address");
+        let server_config = Config { guid: OWN_GUID, ..Config::default() };
+
+        // Act
+        let (client_events, server_events, _) = connect_client_to_server_at(client_addr, server_addr, Config { guid: 0x1122334455667788, ..Config::default() }, server_config, ConnectOptions::default());
+
+        // Assert
+        assert!(client_events.contains(&PeerEvent::ConnectionEstablished {
+            addr: server_addr,
+            guid: OWN_GUID,
+            external_addr: client_addr,
+            remote_internal_addrs: vec![server_addr],
+            user_data: 0,
+        }));
+        assert!(server_events.iter().any(|event| matches!(event, PeerEvent::IncomingConnection(conn) if conn.addr() == client_addr)));
+    }
+
+    #[test]
+    fn connect_with_correct_password_establishes_connection() {
+        // Arrange
+        let server_config = Config { guid: OWN_GUID, incoming_password: Some(b"secret".to_vec()), ..Config::default() };
+        let options = ConnectOptions { password: b"secret".to_vec(), ..ConnectOptions::default() };
+
+        // Act
+        let (_client_events, server_events, handle) = connect_client_to_server(server_config, options);
+
+        // Assert
+        assert!(server_events.iter().any(|event| matches!(event, PeerEvent::IncomingConnection(_))));
+        assert_eq!(Some(Ok(OWN_GUID)), handle.try_result());
+    }
+
+    #[test]
+    fn connect_with_invalid_password_fails() {
+        // Arrange
+        let server_addr = "127.0.0.2:19132".parse::<SocketAddr>().expect("Could not create address");
+        let server_config = Config { guid: OWN_GUID, incoming_password: Some(b"secret".to_vec()), ..Config::default() };
+        let options = ConnectOptions { password: b"wrong".to_vec(), user_data: 42, ..ConnectOptions::default() };
+
+        // Act
+        let (client_events, server_events, handle) = connect_client_to_server(server_config, options);
+
+        // Assert
+        assert!(client_events.contains(&PeerEvent::ConnectionAttemptFailed { addr: server_addr, reason: ConnectionAttemptFailedReason::InvalidPassword, user_data: 42 }));
+        assert!(!server_events.iter().any(|event| matches!(event, PeerEvent::IncomingConnection(_))));
+        assert_eq!(Some(Err(ConnectionAttemptFailedReason::InvalidPassword)), handle.try_result());
+    }
+
+    #[test]
+    #[cfg(feature = "rand")]
+    fn connect_with_security_establishes_connection() {
+        // Arrange
+        let security = SecurityConfig::generate();
+        let options = ConnectOptions { server_public_key: Some(security.public_key()), ..ConnectOptions::default() };
+        let server_config = Config { guid: OWN_GUID, security: Some(security), ..Config::default() };
+
+        // Act
+        let (_client_events, server_events, handle) = connect_client_to_server(server_config, options);
+
+        // Assert
+        assert!(server_events.iter().any(|event| matches!(event, PeerEvent::IncomingConnection(_))));
+        assert_eq!(Some(Ok(OWN_GUID)), handle.try_result());
+    }
+
+    #[test]
+    #[cfg(feature = "rand")]
+    fn connect_with_unexpected_server_public_key_fails() {
+        // Arrange
+        let server_addr = "127.0.0.2:19132".parse::<SocketAddr>().expect("Could not create address");
+        let server_config = Config { guid: OWN_GUID, security: Some(SecurityConfig::generate()), ..Config::default() };
+        let options = ConnectOptions { server_public_key: Some(SecurityConfig::generate().public_key()), user_data: 42, ..ConnectOptions::default() };
+
+        // Act
+        let (client_events, server_events, handle) = connect_client_to_server(server_config, options);
+
+        // Assert
+        assert!(client_events.contains(&PeerEvent::ConnectionAttemptFailed { addr: server_addr, reason: ConnectionAttemptFailedReason::SecurityHandshakeFailed, user_data: 42 }));
+        assert!(!server_events.iter().any(|event| matches!(event, PeerEvent::IncomingConnection(_))));
+        assert_eq!(Some(Err(ConnectionAttemptFailedReason::SecurityHandshakeFailed)), handle.try_result());
+    }
+
+    #[test]
+    #[cfg(feature = "rand")]
+    fn connect_requiring_security_to_server_without_security_fails() {
+        // Arrange
+        let server_config = Config { guid: OWN_GUID, ..Config::default() };
+        let options = ConnectOptions { server_public_key: Some(SecurityConfig::generate().public_key()), ..ConnectOptions::default() };
+
+        // Act
+        let (_client_events, server_events, handle) = connect_client_to_server(server_config, options);
+
+        // Assert
+        assert!(!server_events.iter().any(|event| matches!(event, PeerEvent::IncomingConnection(_))));
+        assert_eq!(Some(Err(ConnectionAttemptFailedReason::SecurityHandshakeFailed)), handle.try_result());
+    }
+
+    #[test]
+    fn connect_with_same_pre_shared_key_establishes_connection() {
+        // Arrange
+        let client_config = Config { guid: 0x1122334455667788, pre_shared_key: Some(b"mesh key".to_vec()), ..Config::default() };
+        let server_config = Config { guid: OWN_GUID, pre_shared_key: Some(b"mesh key".to_vec()), ..Config::default() };
+
+        // Act
+        let (_client_events, server_events, handle) = connect_client_to_server_with_config(client_config, server_config, ConnectOptions::default());
+
+        // Assert
+        assert!(server_events.iter().any(|event| matches!(event, PeerEvent::IncomingConnection(_))));
+        assert_eq!(Some(Ok(OWN_GUID)), handle.try_result());
+    }
+
+    #[test]
+    fn connect_with_other_pre_shared_key_gets_no_reply() {
+        // Arrange
+        let client_config = Config { guid: 0x1122334455667788, pre_shared_key: Some(b"other key".to_vec()), ..Config::default() };
+        let server_config = Config { guid: OWN_GUID, pre_shared_key: Some(b"mesh key".to_vec()), ..Config::default() };
+
+        // Act
+        let (_client_events, server_events, handle) = connect_client_to_server_with_config(client_config, server_config, ConnectOptions::default());
+
+        // Assert
+        assert!(server_events.is_empty());
+        assert_eq!(None, handle.try_result());
+    }
+
+    #[test]
+    fn send_without_connection_fails() {
+        // Arrange
+        let (mut connection_manager, _datagram_sender, _datagram_receiver, remote_addr) = create_connection_manager();
+
+        // Act
+        let result = connection_manager.send(Instant::now(), remote_addr, SendOptions::new(Priority::High, Reliability::Reliable, Ordering::None), vec![0x86].into_boxed_slice());
+
+        // Assert
+        assert!(matches!(result, Err(Error::NotConnected(addr)) if addr == remote_addr));
+    }
+
+    #[test]
+    fn send_to_target_without_connection_fails() {
+        // Arrange
+        let (mut connection_manager, _datagram_sender, _datagram_receiver, _remote_addr) = create_connection_manager();
+
+        // Act
+        let result = connection_manager.send_to_target(Instant::now(), &SendTarget::All, SendOptions::new(Priority::High, Reliability::Reliable, Ordering::None), vec![0x86].into());
+
+        // Assert
+        assert!(matches!(result, Err(Error::NoTarget)));
+    }
+
+    #[test]
+    fn connect_without_reply_fails() {
+        // Arrange
+        let config = Config {
+            guid: OWN_GUID,
+            connection_attempt_count: 2,
+            time_between_connection_attempts_in_ms: 100,
+            ..Config::default()
+        };
+        let (mut connection_manager, _datagram_sender, datagram_receiver, remote_addr) = create_connection_manager_with_config(config);
+        let event_receiver = connection_manager.event_receiver();
+        let time = Instant::now();
+        connection_manager.connect(time, &[remote_addr], ConnectOptions { user_data: 5, ..ConnectOptions::default() }).expect("Could not connect");
+
+        // Act
+        connection_manager.process(time);
+        connection_manager.process(time + Duration::from_millis(100));
+        connection_manager.process(time + Duration::from_millis(200));
+
+        // Assert
+        let requests: Vec<(Vec<u8>, SocketAddr)> = datagram_receiver.try_iter().collect();
+        assert_eq!(2, requests.len());
+        assert!(requests.iter().all(|(payload, addr)| *addr == remote_addr && payload[0] == u8::from(MessageId::OpenConnectionRequest1)));
+        assert_eq!(Ok(PeerEvent::ConnectionAttemptFailed { addr: remote_addr, reason: ConnectionAttemptFailedReason::NoReply, user_data: 5 }), event_receiver.try_recv());
+    }
+
+    #[test]
+    fn connect_sends_request_2_after_reply_1() {
+        // Arrange
+        let (mut connection_manager, mut datagram_sender, mut datagram_receiver, remote_addr) = create_connection_manager();
+        let time = Instant::now();
+        connection_manager.connect(time, &[remote_addr], ConnectOptions::default()).expect("Could not connect");
+        connection_manager.process(time);
+        let _ = receive_datagram::<OpenConnectionRequest1Message>(&mut datagram_receiver);
+        send_datagram(OpenConnectionReply1Message::new(0x12345678, None, 1200), &mut datagram_sender, remote_addr);
+
+        // Act
+        connection_manager.process(time);
+
+        // Assert
+        let (message, addr) = receive_datagram::<OpenConnectionRequest2Message>(&mut datagram_receiver);
+        assert_eq!(remote_addr, addr);
+        assert_eq!(remote_addr, message.binding_address);
+        assert_eq!(1200, message.mtu);
+        assert_eq!(OWN_GUID, message.guid);
+    }
+
+    #[test]
+    fn connect_ignores_reply_2_before_reply_1() {
+        // Arrange
+        let (mut connection_manager, mut datagram_sender, mut datagram_receiver, remote_addr) = create_connection_manager();
+        let time = Instant::now();
+        connection_manager.connect(time, &[remote_addr], ConnectOptions::default()).expect("Could not connect");
+        connection_manager.process(time);
+        let _ = receive_datagram::<OpenConnectionRequest1Message>(&mut datagram_receiver);
+        send_datagram(OpenConnectionReply2Message::new(0x12345678, remote_addr, 1200, None), &mut datagram_sender, remote_addr);
+
+        // Act
+        connection_manager.process(time);
+
+        // Assert
+        assert!(datagram_receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn connect_fails_on_connect_error() {
+        // Arrange
+        let (mut connection_manager, mut datagram_sender, mut datagram_receiver, remote_addr) = create_connection_manager();
+        let event_receiver = connection_manager.event_receiver();
+        let time = Instant::now();
+        let handle = connection_manager.connect(time, &[remote_addr], ConnectOptions::default()).expect("Could not connect");
+        connection_manager.process(time);
+        let _ = receive_datagram::<OpenConnectionRequest1Message>(&mut datagram_receiver);
+        send_datagram(ConnectErrorMessage::new(MessageId::AlreadyConnected, 0x12345678), &mut datagram_sender, remote_addr);
+
+        // Act
+        connection_manager.process(time + Duration::from_secs(1));
+
+        // Assert
+        assert_eq!(Ok(PeerEvent::ConnectionAttemptFailed { addr: remote_addr, reason: ConnectionAttemptFailedReason::AlreadyConnected, user_data: 0 }), event_receiver.try_recv());
+        assert_eq!(Some(Err(ConnectionAttemptFailedReason::AlreadyConnected)), handle.try_result());
+        assert!(datagram_receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn connect_error_with_unknown_message_id_fails_as_rejected() {
+        // Arrange
+        let (mut connection_manager, mut datagram_sender, mut datagram_receiver, remote_addr) = create_connection_manager();
+        let handle = connection_manager.connect(Instant::now(), &[remote_addr], ConnectOptions::default()).expect("Could not connect");
+        connection_manager.process(Instant::now());
+        let _ = receive_datagram::<OpenConnectionRequest1Message>(&mut datagram_receiver);
+        send_datagram(ConnectErrorMessage::new(MessageId::ConnectionAttemptFailed, 0x12345678), &mut datagram_sender, remote_addr);
+
+        // Act
+        connection_manager.process(Instant::now());
+
+        // Assert
+        assert_eq!(Some(Err(ConnectionAttemptFailedReason::Rejected)), handle.try_result());
+    }
+
+    #[test]
+    fn connect_fails_on_reply_1_with_too_small_mtu() {
+        // Arrange
+        let (mut connection_manager, mut datagram_sender, mut datagram_receiver, remote_addr) = create_connection_manager();
+        let handle = connection_manager.connect(Instant::now(), &[remote_addr], ConnectOptions::default()).expect("Could not connect");
+        connection_manager.process(Instant::now());
+        let _ = receive_datagram::<OpenConnectionRequest1Message>(&mut datagram_receiver);
+        send_datagram(OpenConnectionReply1Message::new(0x12345678, None, Config::default().min_mtu_size - 1), &mut datagram_sender, remote_addr);
+
+        // Act
+        connection_manager.process(Instant::now());
+
+        // Assert
+        assert_eq!(Some(Err(ConnectionAttemptFailedReason::MtuTooSmall)), handle.try_result());
+        assert!(datagram_receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn connect_fails_on_reply_2_with_too_small_mtu() {
+        // Arrange
+        let (mut connection_manager, mut datagram_sender, mut datagram_receiver, remote_addr) = create_connection_manager();
+        let handle = connection_manager.connect(Instant::now(), &[remote_addr], ConnectOptions::default()).expect("Could not connect");
+        connection_manager.process(Instant::now());
+        let _ = receive_datagram::<OpenConnectionRequest1Message>(&mut datagram_receiver);
+        send_datagram(OpenConnectionReply1Message::new(0x12345678, None, 1200), &mut datagram_sender, remote_addr);
+        connection_manager.process(Instant::now());
+        let _ = receive_datagram::<OpenConnectionRequest2Message>(&mut datagram_receiver);
+        send_datagram(OpenConnectionReply2Message::new(0x12345678, remote_addr, Config::default().min_mtu_size - 1, None), &mut datagram_sender, remote_addr);
+
+        // Act
+        connection_manager.process(Instant::now());
+
+        // Assert
+        assert_eq!(Some(Err(ConnectionAttemptFailedReason::MtuTooSmall)), handle.try_result());
+        assert!(datagram_receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn connect_handle_has_no_result_while_connecting() {
+        // Arrange
+        let (mut connection_manager, _datagram_sender, _datagram_receiver, remote_addr) = create_connection_manager();
+        let time = Instant::now();
+
+        // Act
+        let handle = connection_manager.connect(time, &[remote_addr], ConnectOptions::default()).expect("Could not connect");
+        connection_manager.process(time);
+
+        // Assert
+        assert_eq!(remote_addr, handle.addr());
+        assert_eq!(None, handle.try_result());
+    }
+
+    #[test]
+    fn connect_without_addresses_fails() {
+        // Arrange
+        let (mut connection_manager, _datagram_sender, datagram_receiver, _remote_addr) = create_connection_manager();
+
+        // Act
+        let result = connection_manager.connect(Instant::now(), &[], ConnectOptions::default());
+
+        // Assert
+        assert!(matches!(result, Err(Error::InvalidAddress)));
+        connection_manager.process(Instant::now());
+        assert!(datagram_receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn connect_tries_next_address_on_failure() {
+        // Arrange
+        let (mut connection_manager, mut datagram_sender, mut datagram_receiver, remote_addr) = create_connection_manager();
+        let ipv6_addr = "[::1]:19132".parse::<SocketAddr>().expect("Could not create address");
+        let event_receiver = connection_manager.event_receiver();
+        let time = Instant::now();
+        let handle = connection_manager.connect(time, &[ipv6_addr, remote_addr], ConnectOptions::default()).expect("Could not connect");
+        connection_manager.process(time);
+        let (_, first_addr) = receive_datagram::<OpenConnectionRequest1Message>(&mut datagram_receiver);
+        let addr_before_failure = handle.addr();
+        send_datagram(ConnectErrorMessage::new(MessageId::NoFreeIncomingConnections, 0x12345678), &mut datagram_sender, remote_addr);
+
+        // Act
+        connection_manager.process(time);
+
+        // Assert
+        let (_, second_addr) = receive_datagram::<OpenConnectionRequest1Message>(&mut datagram_receiver);
+        assert_eq!(remote_addr, addr_before_failure);
+        assert_eq!(ipv6_addr, handle.addr());
+        assert_eq!(remote_addr, first_addr);
+        assert_eq!(ipv6_addr, second_addr);
+        assert!(event_receiver.try_recv().is_err());
+        assert_eq!(None, handle.try_result());
+    }
+
+    #[test]
+    fn simultaneous_connect_creates_one_connection() {
+        // Arrange
+        let (mut peer1, peer1_sender, peer1_receiver, _) = create_connection_manager();
+        let peer1_addr = "127.0.0.2:19132".parse::<SocketAddr>().expect("Could not create address");
+        let peer2_addr = "127.0.0.3:19132".parse::<SocketAddr>().expect("Could not create address");
+        let peer2_guid = 0x1122334455667788; // Lower than OWN_GUID used by peer 1
+        let peer2_socket = FakeDatagramSocket::new(peer2_addr);
+        let peer2_sender = peer2_socket.get_datagram_sender();
+        let peer2_receiver = peer2_socket.get_datagram_receiver();
+        let mut peer2 = ConnectionManager::new(peer2_socket, Config { guid: peer2_guid, ..Config::default() }).expect("Could not create connection manager");
+        let peer1_events = peer1.event_receiver();
+        let peer2_events = peer2.event_receiver();
+        let mut time = Instant::now();
+        let peer1_handle = peer1.connect(time, &[peer2_addr], ConnectOptions::default()).expect("Could not connect");
+        let peer2_handle = peer2.connect(time, &[peer1_addr], ConnectOptions::default()).expect("Could not connect");
+
+        // Act
+        for _ in 0..10 {
+            peer1.process(time);
+            peer2.process(time);
+            while let Ok((payload, _)) = peer1_receiver.try_recv() {
+                peer2_sender.send((payload, peer1_addr)).expect("Could not send datagram");
+            }
+            while let Ok((payload, _)) = peer2_receiver.try_recv() {
+                peer1_sender.send((payload, peer2_addr)).expect("Could not send datagram");
+            }
+            time += Duration::from_millis(10);
+        }
+
+        // Assert
+        let is_connection_event = |event: &PeerEvent| !matches!(event, PeerEvent::PingUpdated { .. });
+        let peer1_events: Vec<PeerEvent> = peer1_events.try_iter().filter(is_connection_event).collect();
+        let peer2_events: Vec<PeerEvent> = peer2_events.try_iter().filter(is_connection_event).collect();
+        assert_eq!(1, peer1_events.len());
+        assert!(matches!(peer1_events[0], PeerEvent::ConnectionEstablished { guid: 0x1122334455667788, .. }));
+        assert_eq!(1, peer2_events.len());
+        assert!(matches!(peer2_events[0], PeerEvent::IncomingConnection(_)));
+        assert_eq!(Some(Ok(peer2_guid)), peer1_handle.try_result());
+        assert_eq!(Some(Ok(OWN_GUID)), peer2_handle.try_result());
+    }
+
+    #[test]
+    fn connect_to_self_fails() {
+        // Arrange
+        let (mut connection_manager, datagram_sender, datagram_receiver, _) = create_connection_manager();
+        let own_addr = "127.0.0.2:19132".parse::<SocketAddr>().expect("Could not create address");
+        let time = Instant::now();
+        let handle = connection_manager.connect(time, &[own_addr], ConnectOptions::default()).expect("Could not connect");
+
+        // Act
+        for _ in 0..5 {
+            connection_manager.process(time);
+            while let Ok((payload, _)) = datagram_receiver.try_recv() {
+                datagram_sender.send((payload, own_addr)).expect("Could not send datagram");
+            }
+        }
+
+        // Assert
+        assert_eq!(Some(Err(ConnectionAttemptFailedReason::SelfConnection)), handle.try_result());
+    }
+}
@@ -0,0 +1,91 @@
+use std::time::Duration;
+
+use crate::OrderingChannelIndex;
+
+/// Statistics of a connection returned by `Peer::connection_stats`, e.g. for
+/// diagnosing packet loss or bandwidth usage.
+///
+/// Packets are counted as they are sent over the network, thus each part
+/// of a split packet is counted as a reliable packet of its own.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ConnectionStats {
+    /// The number of bytes of all datagrams sent, including resends, ACKs and NACKs.
+    pub bytes_sent: u64,
+
+    /// The number of bytes of all datagrams received.
+    pub bytes_received: u64,
+
+    /// The number of reliable packets sent for the first time.
+    pub reliable_packets_sent: u64,
+
+    /// The number of payload bytes of the reliable packets sent for the first time.
+    pub reliable_bytes_sent: u64,
+
+    /// The number of unreliable packets sent.
+    pub unreliable_packets_sent: u64,
+
+    /// The number of payload bytes of the unreliable packets sent.
+    pub unreliable_bytes_sent: u64,
+
+    /// The number of reliable packets received, including duplicates.
+    pub reliable_packets_received: u64,
+
+    /// The number of payload bytes of the reliable packets received.
+    pub reliable_bytes_received: u64,
+
+    /// The number of unreliable packets received.
+    pub unreliable_packets_received: u64,
+
+    /// The number of payload bytes of the unreliable packets received.
+    pub unreliable_bytes_received: u64,
+
+    /// The number of packets resent since they were not acknowledged in time or were NACK:ed.
+    pub packets_resent: u64,
+
+    /// The number of payload bytes of the resent packets.
+    pub bytes_resent: u64,
+
+    /// The number of resends as a percentage of the packets sent for the first time during the
+    /// last five seconds, at most 100. Packets are resent since they were NACK:ed or not
+    /// acknowledged in time, so this is an estimate of the packet loss.
+    pub packet_loss: f32,
+
+    /// The number of datagrams with ACKs sent.
+    pub acks_sent: u64,
+
+    /// The number of datagrams with ACKs received.
+    pub acks_received: u64,
+
+    /// The number of datagrams with NACKs sent.
+    pub nacks_sent: u64,
+
+    /// The number of datagrams with NACKs received.
+    pub nacks_received: u64,
+
+    /// The number of received datagrams and split packets that were dropped
+    /// for exceeding an inbound limit, see `Config::inbound_limit_action`.
+    pub inbound_limit_drops: u64,
+
+    /// The number of sent datagrams that have not been acknowledged yet.
+    pub datagrams_in_flight: usize,
+
+    /// The number of bytes of the sent datagrams that have not been acknowledged yet.
+    pub bytes_in_flight: usize,
+
+    /// The time after which an unacknowledged datagram is resent.
+    pub retransmission_timeout: Duration,
+
+    /// The smoothed round trip time in milliseconds or None
+    /// if no pong has been received yet, see `Peer::ping`.
+    pub ping_ms: Option<u32>,
+
+    /// The number of packets waiting to be sent.
+    pub queued_packets: usize,
+
+    /// The number of payload bytes of the packets waiting to be sent.
+    pub queued_bytes: usize,
+
+    /// The number of received packets waiting for a missing packet on each ordering
+    /// channel that has any, e.g. for finding head-of-line blocking.
+    pub buffered_ordered_packets: Vec<(OrderingChannelIndex, usize)>,
+}
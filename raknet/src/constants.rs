@@ -6,16 +6,59 @@ pub const RAKNET_PROTOCOL_VERSION: u8 = 10;
 
 pub const UDP_HEADER_SIZE: u16 = 28;
 
+/// The maximum number of bytes of user data in an out-of-band message.
+pub const MAX_OUT_OF_BAND_DATA_LENGTH: usize = 400;
+
+/// The maximum number of bytes of the password in a connection request, as in the original RakNet.
+pub const MAX_PASSWORD_LENGTH: usize = 255;
+
 pub const MAXIMUM_MTU_SIZE: u16 = 1492;
 
+/// The smallest MTU size that is accepted from a remote peer. Smaller
+/// datagrams could not fit the headers of the connection related messages.
+pub const MINIMUM_MTU_SIZE: u16 = 400;
+
 pub const NUMBER_OF_ORDERING_CHANNELS: u8 = 32;
 
 pub const NUMBER_OF_PRIORITIES: usize = 4;
 
 pub const TIME_BEFORE_SENDING_ACKS: Duration = Duration::from_millis(10);
 
-pub const MAX_ACK_DATAGRAM_HEADER_SIZE: usize = 1 + 4; // Bitflags (u8) + AS (f32)
+pub const MAX_NUMBER_OF_INTERNAL_IDS: usize = 10;
+
+pub const MTU_SIZES: [u16; 3] = [MAXIMUM_MTU_SIZE, 1200, 576];
+
+/// The maximum number of parts that an outgoing packet may be split into.
+pub const MAX_SPLIT_PACKET_COUNT: usize = 8192;
+
+/// The time after which an incomplete incoming split packet, that has not received
+/// any new part, is discarded.
+pub const SPLIT_PACKET_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The minimum number of parts of a split packet for progress to be reported
+/// with `PeerEvent::SplitPacketProgress`.
+pub const MIN_SPLIT_PACKET_COUNT_FOR_PROGRESS: u32 = 16;
+
+/// The factor that the data arrival rate reported by the remote peer is multiplied with
+/// to get the maximum send rate. It is larger than one so that the send rate can grow.
+pub const DATA_ARRIVAL_RATE_HEADROOM: f32 = 2.0;
+
+/// The number of bytes that encryption adds to each datagram of a connection
+/// that uses security: Bitflags (u8) + counter (u64) + authentication tag (16 bytes).
+pub const ENCRYPTION_OVERHEAD: u16 = 1 + 8 + 16;
+
+/// The number of bytes of the integrity tag added to each datagram
+/// when `Config::pre_shared_key` is set: A truncated HMAC-SHA256.
+pub const INTEGRITY_TAG_SIZE: usize = 8;
+
+/// The largest difficulty of a proof-of-work puzzle that is solved when connecting, see
+/// `Config::proof_of_work_difficulty`. Solving takes about 2^difficulty hashes, so a remote
+/// peer asking for more could keep the connecting peer busy for a long time.
+pub const MAX_PROOF_OF_WORK_DIFFICULTY: u8 = 20;
 
-pub const MAX_NACK_DATAGRAM_HEADER_SIZE: usize = 1; // Bitflags (u8)
+/// The largest number of hashes computed per processing round when solving a proof-of-work
+/// puzzle, so that solving does not delay the other connections of the connecting peer.
+pub const PUZZLE_HASHES_PER_UPDATE: u32 = 4096;
 
-pub const MAX_NUMBER_OF_INTERNAL_IDS: usize = 10;
\ No newline at end of file
+/// The size of a serialized handoff token, see `HandoffToken::to_bytes`.
+pub const HANDOFF_TOKEN_SIZE: usize = 84;
@@ -0,0 +1,105 @@
+use std::time::Instant;
+
+/// Measures the rate that datagrams arrive from the remote peer.
+///
+/// The rate is reported in ACKs (the "B and AS" fields) when the remote peer
+/// requests it and the measurement is then restarted.
+pub struct DataArrivalRate {
+    start_time: Option<Instant>,
+    bytes_received: usize,
+    is_requested: bool,
+}
+
+impl DataArrivalRate {
+    pub fn new() -> Self {
+        DataArrivalRate {
+            start_time: None,
+            bytes_received: 0,
+            is_requested: false,
+        }
+    }
+
+    /// Registers that a datagram of `size` bytes has arrived.
+    pub fn on_datagram_received(&mut self, time: Instant, size: usize) {
+        if self.start_time.is_none() {
+            self.start_time = Some(time);
+        }
+        self.bytes_received += size;
+    }
+
+    /// Registers that the remote peer wants the data arrival rate in the next ACK.
+    pub fn request(&mut self) {
+        self.is_requested = true;
+    }
+
+    /// Returns the data arrival rate in bytes per microsecond if it has been requested
+    /// and can be calculated. The measurement is restarted if a rate is returned.
+    pub fn take_requested_rate(&mut self, time: Instant) -> Option<f32> {
+        if !self.is_requested {
+            return None;
+        }
+        let elapsed_us = time.saturating_duration_since(self.start_time?).as_micros();
+        if elapsed_us == 0 {
+            return None;
+        }
+        let rate = self.bytes_received as f32 / elapsed_us as f32;
+        self.start_time = None;
+        self.bytes_received = 0;
+        self.is_requested = false;
+        Some(rate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+    use super::DataArrivalRate;
+
+    #[test]
+    fn take_requested_rate_not_requested() {
+        // Arrange
+        let time = Instant::now();
+        let mut data_arrival_rate = DataArrivalRate::new();
+        data_arrival_rate.on_datagram_received(time, 1000);
+
+        // Act
+        let rate = data_arrival_rate.take_requested_rate(time + Duration::from_millis(10));
+
+        // Assert
+        assert_eq!(None, rate);
+    }
+
+    #[test]
+    fn take_requested_rate_bytes_per_microsecond() {
+        // Arrange
+        let time = Instant::now();
+        let mut data_arrival_rate = DataArrivalRate::new();
+        data_arrival_rate.on_datagram_received(time, 1000);
+        data_arrival_rate.on_datagram_received(time + Duration::from_millis(5), 1000);
+        data_arrival_rate.request();
+
+        // Act
+        let rate = data_arrival_rate.take_requested_rate(time + Duration::from_millis(10));
+
+        // Assert
+        assert_eq!(Some(0.2), rate);
+    }
+
+    #[test]
+    fn take_requested_rate_restarts_measurement() {
+        // Arrange
+        let time = Instant::now();
+        let mut data_arrival_rate = DataArrivalRate::new();
+        data_arrival_rate.on_datagram_received(time, 1000);
+        data_arrival_rate.request();
+        data_arrival_rate.take_requested_rate(time + Duration::from_millis(10));
+        data_arrival_rate.on_datagram_received(time + Duration::from_millis(20), 500);
+        data_arrival_rate.request();
+
+        // Act
+        let rate = data_arrival_rate.take_requested_rate(time + Duration::from_millis(30));
+
+        // Assert
+        assert_eq!(Some(0.05), rate);
+    }
+}
@@ -0,0 +1,219 @@
+use chacha20poly1305::{aead::{Aead, Payload}, ChaCha20Poly1305, Nonce};
+#[cfg(feature = "rand")]
+use chacha20poly1305::{aead::KeyInit, Key};
+
+#[cfg(feature = "rand")]
+use crate::security::SessionKeys;
+
+/// The first byte of an encrypted datagram. The valid datagram bit is set
+/// so that encrypted datagrams are never mistaken for offline messages.
+const ENCRYPTED_DATAGRAM_FLAGS: u8 = 0x80;
+
+/// The number of bytes before the ciphertext: Bitflags (u8) + counter (u64).
+const ENCRYPTED_DATAGRAM_HEADER_SIZE: usize = 1 + 8;
+
+/// The number of counters below the highest received counter that are
+/// still accepted if they arrive out of order.
+const REPLAY_WINDOW_SIZE: u64 = 64;
+
+/// Keeps track of the counters of received datagrams so that
+/// a datagram that is replayed by an attacker is rejected.
+#[derive(Debug)]
+struct ReplayWindow {
+    highest_counter: Option<u64>,
+    /// Bit `n` is set if `highest_counter - n` has been received.
+    received: u64,
+}
+
+impl ReplayWindow {
+    #[cfg(feature = "rand")]
+    fn new() -> Self {
+        ReplayWindow {
+            highest_counter: None,
+            received: 0,
+        }
+    }
+
+    /// Returns true if a datagram with the counter has not been received
+    /// and is not too old to be checked.
+    fn is_acceptable(&self, counter: u64) -> bool {
+        match self.highest_counter {
+            None => true,
+            Some(highest_counter) if counter > highest_counter => true,
+            Some(highest_counter) => {
+                let age = highest_counter - counter;
+                age < REPLAY_WINDOW_SIZE && self.received & (1 << age) == 0
+            },
+        }
+    }
+
+    /// Registers that a datagram with the counter has been received.
+    /// Must only be called for acceptable counters.
+    fn insert(&mut self, counter: u64) {
+        match self.highest_counter {
+            Some(highest_counter) if counter <= highest_counter => {
+                self.received |= 1 << (highest_counter - counter);
+            },
+            Some(highest_counter) => {
+                let shift = counter - highest_counter;
+                self.received = if shift < REPLAY_WINDOW_SIZE { (self.received << shift) | 1 } else { 1 };
+                self.highest_counter = Some(counter);
+            },
+            None => {
+                self.received = 1;
+                self.highest_counter = Some(counter);
+            },
+        }
+    }
+}
+
+/// Encrypts and decrypts the datagrams of a connection that uses security
+/// with ChaCha20-Poly1305 and the keys derived in the security handshake.
+///
+/// An encrypted datagram consists of the bitflags, a counter that is used as the nonce
+/// and the ciphertext including the authentication tag. The counter is increased for each
+/// sent datagram and datagrams with a counter that has already been received are rejected.
+pub struct DatagramCipher {
+    outgoing_cipher: ChaCha20Poly1305,
+    incoming_cipher: ChaCha20Poly1305,
+    next_outgoing_counter: u64,
+    replay_window: ReplayWindow,
+}
+
+impl DatagramCipher {
+    #[cfg(feature = "rand")]
+    pub fn new(session_keys: &SessionKeys) -> Self {
+        DatagramCipher {
+            outgoing_cipher: ChaCha20Poly1305::new(Key::from_slice(&session_keys.outgoing_key)),
+            incoming_cipher: ChaCha20Poly1305::new(Key::from_slice(&session_keys.incoming_key)),
+            next_outgoing_counter: 0,
+            replay_window: ReplayWindow::new(),
+        }
+    }
+
+    /// Encrypts an outgoing datagram.
+    pub fn encrypt(&mut self, datagram: &[u8]) -> Vec<u8> {
+        let counter = self.next_outgoing_counter;
+        self.next_outgoing_counter += 1;
+        let mut header = [0u8; ENCRYPTED_DATAGRAM_HEADER_SIZE];
+        header[0] = ENCRYPTED_DATAGRAM_FLAGS;
+        header[1..].copy_from_slice(&counter.to_be_bytes());
+        let ciphertext = self.outgoing_cipher
+            .encrypt(&Self::nonce(counter), Payload { msg: datagram, aad: &header })
+            .expect("ChaCha20-Poly1305 can encrypt datagrams of any size");
+        let mut encrypted = Vec::with_capacity(header.len() + ciphertext.len());
+        encrypted.extend_from_slice(&header);
+        encrypted.extend_from_slice(&ciphertext);
+        encrypted
+    }
+
+    /// Decrypts an incoming datagram. Returns None if the datagram
+    /// is invalid, has been tampered with or has already been received.
+    pub fn decrypt(&mut self, encrypted: &[u8]) -> Option<Vec<u8>> {
+        if encrypted.len() < ENCRYPTED_DATAGRAM_HEADER_SIZE || encrypted[0] != ENCRYPTED_DATAGRAM_FLAGS {
+            return None;
+        }
+        let (header, ciphertext) = encrypted.split_at(ENCRYPTED_DATAGRAM_HEADER_SIZE);
+        let mut counter = [0u8; 8];
+        counter.copy_from_slice(&header[1..]);
+        let counter = u64::from_be_bytes(counter);
+        if !self.replay_window.is_acceptable(counter) {
+            return None;
+        }
+        let datagram = self.incoming_cipher
+            .decrypt(&Self::nonce(counter), Payload { msg: ciphertext, aad: header })
+            .ok()?;
+        self.replay_window.insert(counter);
+        Some(datagram)
+    }
+
+    fn nonce(counter: u64) -> Nonce {
+        let mut nonce = Nonce::default();
+        nonce[4..].copy_from_slice(&counter.to_be_bytes());
+        nonce
+    }
+}
+
+#[cfg(all(test, feature = "rand"))]
+mod tests {
+    use crate::{constants::ENCRYPTION_OVERHEAD, security::SessionKeys};
+    use super::DatagramCipher;
+
+    fn create_ciphers() -> (DatagramCipher, DatagramCipher) {
+        let keys = SessionKeys { outgoing_key: [1; 32], incoming_key: [2; 32] };
+        let remote_keys = SessionKeys { outgoing_key: [2; 32], incoming_key: [1; 32] };
+        (DatagramCipher::new(&keys), DatagramCipher::new(&remote_keys))
+    }
+
+    #[test]
+    fn encrypted_datagram_is_decrypted_by_remote_peer() {
+        // Arrange
+        let (mut cipher, mut remote_cipher) = create_ciphers();
+
+        // Act
+        let encrypted = cipher.encrypt(&[0x84, 1, 2, 3]);
+        let decrypted = remote_cipher.decrypt(&encrypted);
+
+        // Assert
+        assert_eq!(4 + ENCRYPTION_OVERHEAD as usize, encrypted.len());
+        assert_eq!(0x80, encrypted[0]);
+        assert_eq!(Some(vec![0x84, 1, 2, 3]), decrypted);
+    }
+
+    #[test]
+    fn tampered_datagram_is_rejected() {
+        // Arrange
+        let (mut cipher, mut remote_cipher) = create_ciphers();
+        let mut encrypted = cipher.encrypt(&[0x84, 1, 2, 3]);
+
+        // Act
+        encrypted[10] ^= 0x01;
+
+        // Assert
+        assert_eq!(None, remote_cipher.decrypt(&encrypted));
+    }
+
+    #[test]
+    fn datagram_encrypted_with_own_key_is_rejected() {
+        // Arrange
+        let (mut cipher, _) = create_ciphers();
+        let encrypted = cipher.encrypt(&[0x84, 1, 2, 3]);
+
+        // Act
+        let decrypted = cipher.decrypt(&encrypted);
+
+        // Assert
+        assert_eq!(None, decrypted);
+    }
+
+    #[test]
+    fn replayed_datagram_is_rejected() {
+        // Arrange
+        let (mut cipher, mut remote_cipher) = create_ciphers();
+        let encrypted = cipher.encrypt(&[0x84, 1, 2, 3]);
+        remote_cipher.decrypt(&encrypted).expect("Datagram was rejected");
+
+        // Act
+        let replayed = remote_cipher.decrypt(&encrypted);
+
+        // Assert
+        assert_eq!(None, replayed);
+    }
+
+    #[test]
+    fn datagrams_out_of_order_are_accepted_within_window() {
+        // Arrange
+        let (mut cipher, mut remote_cipher) = create_ciphers();
+        let encrypted: Vec<Vec<u8>> = (0..100u8).map(|n| cipher.encrypt(&[n])).collect();
+
+        // Act
+        let newest = remote_cipher.decrypt(&encrypted[99]);
+        let within_window = remote_cipher.decrypt(&encrypted[40]);
+        let outside_window = remote_cipher.decrypt(&encrypted[30]);
+
+        // Assert
+        assert_eq!(Some(vec![99]), newest);
+        assert_eq!(Some(vec![40]), within_window);
+        assert_eq!(None, outside_window);
+    }
+}
@@ -1,6 +1,6 @@
 use std::option::Option;
 
-use crate::{DataRead, DataWrite, ReadError, Result, number::{DatagramSequenceNumber, u24}};
+use crate::{DataRead, DataWrite, ReadError, Result, number::{DatagramSequenceNumber, u24}, writer::PacketWrite};
 
 #[derive(Debug, PartialEq)]
 pub enum DatagramHeader {    
@@ -37,8 +37,10 @@ impl DatagramHeader {
             }
         }
     }
+}
 
-    pub fn write(&self, writer: &mut dyn DataWrite) -> Result<()> {
+impl PacketWrite for DatagramHeader {
+    fn write(&self, writer: &mut dyn DataWrite) -> Result<()> {
         // Bit 7 = "isValid"
         let mut bitflags: u8 = 1 << 7;
         match self {
@@ -69,12 +71,21 @@ impl DatagramHeader {
         }
         Ok(())
     }
+
+    fn written_size(&self) -> usize {
+        match self {
+            DatagramHeader::Ack { data_arrival_rate: Some(_) } => 1 + 4, // Bitflags (u8) + AS (f32)
+            DatagramHeader::Ack { data_arrival_rate: None } => 1,
+            DatagramHeader::Nack => 1,
+            DatagramHeader::Packet { .. } => 1 + 3, // Bitflags (u8) + datagram number (u24)
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use std::{convert::TryFrom, matches};   
-    use crate::{datagram_header::DatagramHeader, number::DatagramSequenceNumber, reader::DataReader};
+    use crate::{datagram_header::DatagramHeader, number::DatagramSequenceNumber, reader::DataReader, writer::PacketWrite};
 
     #[test]
     fn read_ack_header_with_data_arrival_rate() {
@@ -285,4 +296,30 @@ mod tests {
         // Assert
         assert_eq!(payload, vec![0b1000_0100u8, 0x56, 0x34, 0x12]);
     }  
-}
\ No newline at end of file
+
+    #[test]
+    fn written_size_is_size_of_written_header() {
+        // Arrange
+        let headers = [
+            DatagramHeader::Ack { data_arrival_rate: Some(5.0) },
+            DatagramHeader::Ack { data_arrival_rate: None },
+            DatagramHeader::Nack,
+            DatagramHeader::Packet {
+                is_packet_pair: true,
+                is_continuous_send: true,
+                needs_data_arrival_rate: true,
+                datagram_number: DatagramSequenceNumber::try_from(0x123456u32).unwrap()
+            },
+        ];
+
+        for header in headers.iter() {
+            let mut payload = Vec::new();
+
+            // Act
+            header.write(&mut payload).expect("Couldn't write header");
+
+            // Assert
+            assert_eq!(payload.len(), header.written_size());
+        }
+    }
+}
@@ -0,0 +1,395 @@
+//! Decoding of raw RakNet datagrams for debugging and diagnostic tools.
+
+use std::{convert::TryFrom, fmt, time::Instant};
+
+use crate::{
+    datagram_header::DatagramHeader,
+    datagram_range_list::DatagramRangeList,
+    error::Result,
+    internal_packet::{InternalOrdering, InternalPacket, InternalReliability},
+    message_ids::MessageId,
+    reader::{DataRead, DataReader},
+};
+
+/// A decoded RakNet datagram.
+#[derive(Debug, PartialEq)]
+pub enum Datagram {
+    /// An offline (unconnected) message such as a ping or a connection request.
+    Offline {
+        message_id: u8,
+        length: usize,
+    },
+    /// An acknowledgement of the datagram number ranges.
+    Ack {
+        data_arrival_rate: Option<f32>,
+        ranges: Vec<(u32, u32)>,
+    },
+    /// A negative acknowledgement of the datagram number ranges.
+    Nack {
+        ranges: Vec<(u32, u32)>,
+    },
+    /// A datagram containing one or more packets.
+    Packets {
+        is_packet_pair: bool,
+        is_continuous_send: bool,
+        needs_data_arrival_rate: bool,
+        datagram_number: u32,
+        packets: Vec<DecodedPacket>,
+    },
+}
+
+/// A packet decoded from a datagram.
+#[derive(Debug, PartialEq)]
+pub struct DecodedPacket {
+    pub reliable_message_number: Option<u32>,
+    pub sequencing_index: Option<u32>,
+    pub ordering_index: Option<u32>,
+    pub ordering_channel_index: Option<u8>,
+    pub split: Option<SplitInfo>,
+    pub payload: Box<[u8]>,
+}
+
+impl DecodedPacket {
+    /// Returns the RakNet name of the reliability type of the packet,
+    /// e.g. "ReliableOrdered".
+    pub fn reliability_name(&self) -> &'static str {
+        match (self.reliable_message_number.is_some(), self.sequencing_index.is_some(), self.ordering_index.is_some()) {
+            (false, false, _) => "Unreliable",
+            (false, true, _) => "UnreliableSequenced",
+            (true, false, false) => "Reliable",
+            (true, false, true) => "ReliableOrdered",
+            (true, true, _) => "ReliableSequenced",
+        }
+    }
+}
+
+impl fmt::Display for Datagram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Datagram::Offline { message_id, length } => {
+                write!(f, "Offline {} (0x{:02x}), {} bytes", message_name(*message_id).as_deref().unwrap_or("message"), message_id, length)
+            },
+            Datagram::Ack { data_arrival_rate, ranges } => {
+                write!(f, "ACK {}", format_ranges(ranges))?;
+                if let Some(data_arrival_rate) = data_arrival_rate {
+                    write!(f, " (data arrival rate {})", data_arrival_rate)?;
+                }
+                Ok(())
+            },
+            Datagram::Nack { ranges } => write!(f, "NACK {}", format_ranges(ranges)),
+            Datagram::Packets { is_packet_pair, is_continuous_send, needs_data_arrival_rate, datagram_number, packets } => {
+                write!(f, "Datagram #{}", datagram_number)?;
+                let flags: Vec<&str> = [
+                    (*is_packet_pair, "packet pair"),
+                    (*is_continuous_send, "continuous send"),
+                    (*needs_data_arrival_rate, "needs data arrival rate"),
+                ].iter().filter(|(is_set, _)| *is_set).map(|(_, name)| *name).collect();
+                if !flags.is_empty() {
+                    write!(f, " ({})", flags.join(", "))?;
+                }
+                write!(f, " with {} packet(s)", packets.len())?;
+                for packet in packets {
+                    write!(f, "\n  {}", packet)?;
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+impl fmt::Display for DecodedPacket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.reliability_name())?;
+        if let Some(reliable_message_number) = self.reliable_message_number {
+            write!(f, " #{}", reliable_message_number)?;
+        }
+        if let Some(sequencing_index) = self.sequencing_index {
+            write!(f, ", sequencing index {}", sequencing_index)?;
+        }
+        if let (Some(ordering_index), Some(ordering_channel_index)) = (self.ordering_index, self.ordering_channel_index) {
+            write!(f, ", ordering index {} on channel {}", ordering_index, ordering_channel_index)?;
+        }
+        if let Some(split) = &self.split {
+            write!(f, ", split part {}/{} of split packet {}", split.index + 1, split.count, split.id)?;
+        }
+        write!(f, ", {} bytes", self.payload.len())?;
+        // Only the first part of a split packet starts with the message ID
+        if let Some(&message_id) = self.payload.first().filter(|_| self.split.is_none_or(|split| split.index == 0)) {
+            match message_name(message_id) {
+                Some(name) => write!(f, ": {} (0x{:02x})", name, message_id)?,
+                None => write!(f, ": 0x{:02x}", message_id)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The split packet header of a packet that is part of a split packet.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SplitInfo {
+    pub count: u32,
+    pub id: u16,
+    pub index: u32,
+}
+
+/// Decodes a raw datagram as received from or sent to a UDP socket.
+pub fn decode(payload: &[u8]) -> Result<Datagram> {
+    let mut reader = DataReader::new(payload);
+    if payload.is_empty() || payload[0] & 0x80 == 0 {
+        // Connected datagrams always have the "is valid" bit set
+        return Ok(Datagram::Offline { message_id: reader.read_u8()?, length: payload.len() });
+    }
+    match DatagramHeader::read(&mut reader)? {
+        DatagramHeader::Ack { data_arrival_rate } => Ok(Datagram::Ack {
+            data_arrival_rate,
+            ranges: read_ranges(&mut reader)?,
+        }),
+        DatagramHeader::Nack => Ok(Datagram::Nack {
+            ranges: read_ranges(&mut reader)?,
+        }),
+        DatagramHeader::Packet { is_packet_pair, is_continuous_send, needs_data_arrival_rate, datagram_number } => {
+            let mut packets = Vec::new();
+            while reader.has_more() {
+                packets.push(decode_packet(InternalPacket::read(Instant::now(), &mut reader)?));
+            }
+            Ok(Datagram::Packets {
+                is_packet_pair,
+                is_continuous_send,
+                needs_data_arrival_rate,
+                datagram_number: datagram_number.into(),
+                packets,
+            })
+        },
+    }
+}
+
+/// Decodes a raw datagram and returns a human readable description of it,
+/// e.g. for pasting into a bug report. Packets are described on separate lines.
+pub fn explain(payload: &[u8]) -> String {
+    match decode(payload) {
+        Ok(datagram) => datagram.to_string(),
+        Err(err) => format!("Invalid datagram of {} bytes: {}", payload.len(), err),
+    }
+}
+
+/// Parses a hex dump with one datagram per line, e.g. copied from Wireshark or a log.
+///
+/// The hex digits of a line may be separated by whitespace or commas and prefixed
+/// with `0x`. Empty lines and lines starting with `#` are skipped. Returns the
+/// line number (starting at 1) together with the parsed datagram or the line
+/// if it is not a valid hex dump.
+pub fn parse_hex_dump(text: &str) -> impl Iterator<Item = (usize, std::result::Result<Vec<u8>, &str>)> {
+    text.lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(line_number, line)| (line_number, parse_hex(line).ok_or(line)))
+}
+
+/// Parses one line of hex digits, optionally separated by whitespace or commas
+/// and prefixed with `0x`. Returns None if the line is not valid hex.
+pub fn parse_hex(line: &str) -> Option<Vec<u8>> {
+    let digits: String = line
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .map(|token| token.trim_start_matches("0x").trim_start_matches("0X"))
+        .collect();
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| digits.get(i..i + 2).and_then(|pair| u8::from_str_radix(pair, 16).ok()))
+        .collect()
+}
+
+/// Returns the name of a RakNet message ID, or None if the
+/// ID is not a known RakNet message (e.g. a user message).
+pub fn message_name(message_id: u8) -> Option<String> {
+    MessageId::try_from(message_id).ok().map(|id| format!("{:?}", id))
+}
+
+fn read_ranges(reader: &mut dyn DataRead) -> Result<Vec<(u32, u32)>> {
+    Ok(DatagramRangeList::read(reader)?
+        .into_vec()
+        .into_iter()
+        .map(|range| (range.start().into(), range.end().into()))
+        .collect())
+}
+
+fn format_ranges(ranges: &[(u32, u32)]) -> String {
+    ranges.iter()
+        .map(|&(start, end)| if start == end { start.to_string() } else { format!("{}-{}", start, end) })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn decode_packet(packet: InternalPacket) -> DecodedPacket {
+    let reliable_message_number = match packet.reliability() {
+        InternalReliability::Reliable(number) => number.map(u32::from),
+        InternalReliability::Unreliable => None,
+    };
+    let (sequencing_index, ordering_index, ordering_channel_index) = match packet.ordering() {
+        InternalOrdering::None => (None, None, None),
+        InternalOrdering::Ordered { ordering_index, ordering_channel_index } =>
+            (None, Some(ordering_index.into()), Some(ordering_channel_index)),
+        InternalOrdering::Sequenced { sequencing_index, ordering_index, ordering_channel_index } =>
+            (Some(sequencing_index.into()), Some(ordering_index.into()), Some(ordering_channel_index)),
+    };
+    let split = packet.split_packet_header().map(|header| SplitInfo {
+        count: header.split_packet_count(),
+        id: header.split_packet_id(),
+        index: header.split_packet_index(),
+    });
+    DecodedPacket {
+        reliable_message_number,
+        sequencing_index,
+        ordering_index,
+        ordering_channel_index,
+        split,
+        payload: packet.into_payload(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, explain, message_name, parse_hex, parse_hex_dump, Datagram, SplitInfo};
+
+    #[test]
+    fn decode_offline_message() {
+        // Arrange
+        let payload = [0x05, 0x00, 0xFF, 0xFF, 0x00];
+
+        // Act
+        let datagram = decode(&payload).expect("Could not decode datagram");
+
+        // Assert
+        assert_eq!(Datagram::Offline { message_id: 0x05, length: 5 }, datagram);
+        assert_eq!(Some("OpenConnectionRequest1".to_string()), message_name(0x05));
+    }
+
+    #[test]
+    fn decode_ack() {
+        // Arrange
+        let payload = [
+            0b1100_0000, // Bitflags: is_valid, is_ack
+            0x00, 0x02, // Range count: 2
+            0x01, 0x05, 0x00, 0x00, // Start equal to end: 5
+            0x00, 0x07, 0x00, 0x00, 0x09, 0x00, 0x00, // Range: 7-9
+        ];
+
+        // Act
+        let datagram = decode(&payload).expect("Could not decode datagram");
+
+        // Assert
+        assert_eq!(Datagram::Ack { data_arrival_rate: None, ranges: vec![(5, 5), (7, 9)] }, datagram);
+    }
+
+    #[test]
+    fn decode_packets() {
+        // Arrange
+        let payload = [
+            0b1000_0000, // Bitflags: is_valid
+            0x03, 0x00, 0x00, // Datagram number: 3
+            0b0111_0000, // Bitflags: reliability=3=Reliable Ordered, has_split_packet=1
+            0x00, 0x10, // Data bit length: 16 bits
+            0x01, 0x00, 0x00, // Reliable message number: 1
+            0x02, 0x00, 0x00, // Ordering index: 2
+            0x00, // Ordering channel: 0
+            0x00, 0x00, 0x00, 0x02, // Split packet count: 2
+            0x00, 0x07, // Split packet ID: 7
+            0x00, 0x00, 0x00, 0x01, // Split packet index: 1
+            0xFE, 0x01, // Data
+        ];
+
+        // Act
+        let datagram = decode(&payload).expect("Could not decode datagram");
+
+        // Assert
+        match datagram {
+            Datagram::Packets { datagram_number, packets, .. } => {
+                assert_eq!(3, datagram_number);
+                assert_eq!(1, packets.len());
+                assert_eq!("ReliableOrdered", packets[0].reliability_name());
+                assert_eq!(Some(1), packets[0].reliable_message_number);
+                assert_eq!(Some(2), packets[0].ordering_index);
+                assert_eq!(Some(SplitInfo { count: 2, id: 7, index: 1 }), packets[0].split);
+                assert_eq!(&[0xFE, 0x01], &*packets[0].payload);
+            },
+            _ => panic!("Not decoded as packets"),
+        }
+    }
+
+    #[test]
+    fn explain_ack() {
+        // Arrange
+        let payload = [
+            0b1100_0000, // Bitflags: is_valid, is_ack
+            0x00, 0x02, // Range count: 2
+            0x01, 0x05, 0x00, 0x00, // Start equal to end: 5
+            0x00, 0x07, 0x00, 0x00, 0x09, 0x00, 0x00, // Range: 7-9
+        ];
+
+        // Act
+        let text = explain(&payload);
+
+        // Assert
+        assert_eq!("ACK 5, 7-9", text);
+    }
+
+    #[test]
+    fn explain_packets() {
+        // Arrange
+        let payload = [
+            0b1000_0000, // Bitflags: is_valid
+            0x03, 0x00, 0x00, // Datagram number: 3
+            0b0110_0000, // Bitflags: reliability=3=Reliable Ordered, has_split_packet=0
+            0x00, 0x10, // Data bit length: 16 bits
+            0x01, 0x00, 0x00, // Reliable message number: 1
+            0x02, 0x00, 0x00, // Ordering index: 2
+            0x00, // Ordering channel: 0
+            0x09, 0x01, // Data: ConnectionRequest
+        ];
+
+        // Act
+        let text = explain(&payload);
+
+        // Assert
+        assert_eq!("Datagram #3 with 1 packet(s)\n  ReliableOrdered #1, ordering index 2 on channel 0, 2 bytes: ConnectionRequest (0x09)", text);
+    }
+
+    #[test]
+    fn explain_invalid_datagram() {
+        // Arrange
+        let payload = [0b1000_0000, 0x03];
+
+        // Act
+        let text = explain(&payload);
+
+        // Assert
+        assert!(text.starts_with("Invalid datagram of 2 bytes: "));
+    }
+
+    #[test]
+    fn parse_hex_line() {
+        // Act
+        let separated = parse_hex("0x05, 0x00 ff,FF");
+        let compact = parse_hex("0500ffFF");
+        let odd_length = parse_hex("05 0");
+        let invalid_digit = parse_hex("05 0g");
+
+        // Assert
+        assert_eq!(Some(vec![0x05, 0x00, 0xFF, 0xFF]), separated);
+        assert_eq!(Some(vec![0x05, 0x00, 0xFF, 0xFF]), compact);
+        assert_eq!(None, odd_length);
+        assert_eq!(None, invalid_digit);
+    }
+
+    #[test]
+    fn parse_hex_dump_skips_empty_and_comment_lines() {
+        // Arrange
+        let text = "# Ping\n01 02\n\n  \nzz\n0x03\n";
+
+        // Act
+        let datagrams: Vec<_> = parse_hex_dump(text).collect();
+
+        // Assert
+        assert_eq!(vec![(2, Ok(vec![0x01, 0x02])), (5, Err("zz")), (6, Ok(vec![0x03]))], datagrams);
+    }
+}
@@ -1,4 +1,6 @@
-use std::{fmt, io, result, string};
+use std::{fmt, io, net::SocketAddr, result, string};
+
+use crate::{ConnectionAttemptFailedReason, InvalidConfig};
 
 pub type Result<T> = result::Result<T, Error>;
 
@@ -13,6 +15,20 @@ pub enum Error {
     WriteError(WriteError),
     /// An unknown message ID was received.
     UnknownMessageId(u8),
+    /// An outgoing connection could not be established.
+    ConnectionAttemptFailed(ConnectionAttemptFailedReason),
+    /// A packet was larger than the maximum size that can be sent.
+    PacketTooLarge { size: usize, max_size: usize },
+    /// There is no established connection to the address.
+    NotConnected(SocketAddr),
+    /// No connected remote peer was selected by the `SendTarget` or could be sent the packet.
+    NoTarget,
+    /// The `Config` of a new peer is invalid, see `Config::validate`.
+    InvalidConfig(InvalidConfig),
+    /// The peer has stopped processing, e.g. after an `AsyncPeer` was shut down.
+    Stopped,
+    /// No socket address was given, e.g. when connecting to a host name that did not resolve to any address.
+    InvalidAddress,
 }
 
 impl std::error::Error for Error {}
@@ -24,6 +40,13 @@ impl fmt::Display for Error {
             Error::ReadError(err) => write!(f, "Error while reading: {:?}", err),
             Error::WriteError(err) => write!(f, "Error while writing: {:?}", err),
             Error::UnknownMessageId(id) => write!(f, "Received an unknown message ID: {:?}", id),
+            Error::ConnectionAttemptFailed(reason) => write!(f, "Connection attempt failed: {}", reason),
+            Error::PacketTooLarge { size, max_size } => write!(f, "Packet of {} bytes exceeds the maximum size of {} bytes", size, max_size),
+            Error::NotConnected(addr) => write!(f, "Not connected to {}", addr),
+            Error::NoTarget => write!(f, "The packet could not be sent to any remote peer of the target"),
+            Error::InvalidConfig(err) => write!(f, "Invalid configuration: {}", err),
+            Error::Stopped => write!(f, "The peer has stopped processing"),
+            Error::InvalidAddress => write!(f, "No socket address was given"),
         }
     }
 }
@@ -40,6 +63,12 @@ impl From<std::string::FromUtf8Error> for Error {
     }
 }
 
+impl From<InvalidConfig> for Error {
+    fn from(error: InvalidConfig) -> Self {
+        Error::InvalidConfig(error)
+    }
+}
+
 impl From<ReadError> for Error {
     fn from(error: ReadError) -> Self {
         Error::ReadError(error)
@@ -58,6 +87,9 @@ pub enum ReadError {
     CompareFailed,
     /// The split packet index has already been received.
     DuplicateSplitPacketIndex,
+    /// The number of parts of a split packet was zero, larger than allowed
+    /// or different from earlier parts of the same split packet.
+    InvalidSplitPacketCount,
     /// The header was invalid.
     InvalidHeader,
     /// The IP version read was not 4 or 6.
@@ -68,10 +100,20 @@ pub enum ReadError {
     InvalidString(string::FromUtf8Error),
     /// Not all bytes could be read.
     NotAllBytesRead(usize),
+    /// A split packet would be larger than `Config::max_incoming_payload_size` when reassembled.
+    ReassembledPacketTooLarge,
     /// The index of a split packet was out of range.
     SplitPacketIndexOutOfRange,
+    /// A part of a split packet was larger than the MTU allows.
+    SplitPacketPartTooLarge,
+    /// The length of a string was larger than the maximum length allowed when reading it.
+    StringTooLong(usize),
+    /// The data left at the end of a message was longer than allowed.
+    TooLongTrailingData(usize),
     /// The read zero padding was longer than allowed.
     TooLongZeroPadding,
+    /// A VarInt was too large to fit into the type it was read as.
+    VarIntTooLarge,
 }
 
 impl std::error::Error for ReadError {}
@@ -81,13 +123,19 @@ impl fmt::Display for ReadError {
         match self {
             ReadError::CompareFailed => write!(f, "Read data is not the same as the compare value."),
             ReadError::DuplicateSplitPacketIndex => write!(f, "The split packet index has already been received."),
+            ReadError::InvalidSplitPacketCount => write!(f, "The split packet count was invalid."),
             ReadError::InvalidHeader => write!(f, "Read invalid header."),
             ReadError::InvalidIpVersion => write!(f, "Received invalid IP version."),
             ReadError::InvalidOfflineMessageId => write!(f, "Received invalid Offline Message ID."),
             ReadError::InvalidString(err) => write!(f, "Could not parse string: {:?}", err),
             ReadError::NotAllBytesRead(c) => write!(f, "Could not read all bytes. Bytes read: {}", c),
+            ReadError::ReassembledPacketTooLarge => write!(f, "A split packet would be too large when reassembled."),
             ReadError::SplitPacketIndexOutOfRange => write!(f, "The index of a split packet was out of range."),
+            ReadError::SplitPacketPartTooLarge => write!(f, "A part of a split packet was larger than the MTU allows."),
+            ReadError::StringTooLong(length) => write!(f, "The string length {} was longer than allowed.", length),
+            ReadError::TooLongTrailingData(length) => write!(f, "The {} bytes of trailing data were longer than allowed.", length),
             ReadError::TooLongZeroPadding => write!(f, "The read zero padding was longer than allowed."),
+            ReadError::VarIntTooLarge => write!(f, "The VarInt was too large to fit into the desired type."),
         }
     }
 }
@@ -0,0 +1,75 @@
+use std::{collections::HashMap, net::IpAddr, time::Instant};
+
+/// The IP addresses of remote peers whose connections were dropped for abusing the
+/// protocol, each with the time its cool-down ends. All offline packets from a
+/// greylisted address are ignored without a reply, while connection requests from
+/// a banned address are answered.
+pub struct Greylist {
+    entries: HashMap<IpAddr, Instant>,
+}
+
+impl Greylist {
+    pub fn new() -> Self {
+        Greylist {
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Greylists the IP address until `expiry_time`. The cool-down of an address that is
+    /// already greylisted is extended. Returns false if the address was already greylisted.
+    pub fn add(&mut self, ip: IpAddr, expiry_time: Instant) -> bool {
+        match self.entries.insert(ip, expiry_time) {
+            Some(previous_expiry_time) => {
+                if previous_expiry_time > expiry_time {
+                    self.entries.insert(ip, previous_expiry_time);
+                }
+                false
+            },
+            None => true,
+        }
+    }
+
+    /// Returns true if the IP address is greylisted at the specified time.
+    pub fn contains(&self, time: Instant, ip: IpAddr) -> bool {
+        self.entries.get(&ip).is_some_and(|expiry_time| time < *expiry_time)
+    }
+
+    /// Removes and returns the IP addresses whose cool-down has ended.
+    pub fn remove_expired(&mut self, time: Instant) -> Vec<IpAddr> {
+        let expired: Vec<IpAddr> = self.entries.iter()
+            .filter(|(_, expiry_time)| time >= **expiry_time)
+            .map(|(ip, _)| *ip)
+            .collect();
+        for ip in expired.iter() {
+            self.entries.remove(ip);
+        }
+        expired
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::IpAddr, time::{Duration, Instant}};
+    use super::Greylist;
+
+    #[test]
+    fn greylisted_address_expires_after_cool_down() {
+        // Arrange
+        let mut greylist = Greylist::new();
+        let ip = "10.0.0.1".parse::<IpAddr>().expect("Could not create address");
+        let time = Instant::now();
+
+        // Act
+        let is_added = greylist.add(ip, time + Duration::from_secs(10));
+        let is_added_again = greylist.add(ip, time + Duration::from_secs(5));
+        let expired_early = greylist.remove_expired(time + Duration::from_secs(5));
+        let expired_late = greylist.remove_expired(time + Duration::from_secs(10));
+
+        // Assert
+        assert!(is_added);
+        assert!(!is_added_again);
+        assert!(expired_early.is_empty());
+        assert_eq!(vec![ip], expired_late);
+        assert!(!greylist.contains(time, ip));
+    }
+}
@@ -0,0 +1,146 @@
+use std::{convert::TryInto, net::{IpAddr, Ipv6Addr}, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use crate::{constants::HANDOFF_TOKEN_SIZE, random, security};
+
+/// Identifies the bytes of a handoff token at the end of an open connection request 2.
+const HANDOFF_TOKEN_MAGIC: [u8; 4] = *b"RKHO";
+
+/// The size of the fields of a handoff token that are covered by its tag.
+const FIELDS_SIZE: usize = HANDOFF_TOKEN_SIZE - 32;
+
+/// A signed token that lets a remote peer connected to one peer, e.g. a player on a
+/// server behind a proxy, connect to another peer with the same `Config::handoff_key`
+/// without going through all the admission checks again.
+///
+/// The token is created for the GUID and IP address of the remote peer with
+/// `Peer::create_handoff_token` or `HandoffToken::new` and sent to it by the application,
+/// e.g. in a connected message or an out of band message, as the bytes returned by `to_bytes`.
+/// The remote peer passes it in `ConnectOptions::handoff_token` when connecting to the other
+/// peer, which reports the `user_data` of the token in `IncomingConnection::handoff_user_data`.
+///
+/// The token is only accepted from the IP address it was created for, once the remote peer
+/// has echoed the cookie of the other peer, and each token can only be used once.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HandoffToken {
+    guid: u64,
+    ip: IpAddr,
+    user_data: u64,
+    /// The time the token expires in milliseconds since the Unix epoch.
+    expires_at: u64,
+    nonce: u64,
+    tag: [u8; 32],
+}
+
+impl HandoffToken {
+    /// Creates a token for the remote peer with the GUID and IP address that is valid until
+    /// `expires_at`, signed with the handoff key. `user_data` is an opaque value passed to
+    /// the peer accepting the token, e.g. the session of the player.
+    pub fn new(handoff_key: &[u8], guid: u64, ip: IpAddr, user_data: u64, expires_at: SystemTime) -> Self {
+        let expires_at = expires_at.duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_millis() as u64);
+        let mut token = HandoffToken { guid, ip, user_data, expires_at, nonce: random::random_u64(), tag: [0; 32] };
+        token.tag = security::handoff_tag(handoff_key, &token.fields());
+        token
+    }
+
+    /// Returns the GUID of the remote peer that may use the token.
+    pub fn guid(&self) -> u64 {
+        self.guid
+    }
+
+    /// Returns the IP address of the remote peer that may use the token.
+    pub fn ip(&self) -> IpAddr {
+        self.ip
+    }
+
+    /// Returns the opaque value passed to the peer accepting the token.
+    pub fn user_data(&self) -> u64 {
+        self.user_data
+    }
+
+    /// Returns the time the token expires.
+    pub fn expires_at(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(self.expires_at)
+    }
+
+    /// Returns a number that is unique for each token, used to prevent reuse.
+    pub(crate) fn nonce(&self) -> u64 {
+        self.nonce
+    }
+
+    /// Returns the token as bytes that can be sent to the remote peer.
+    pub fn to_bytes(&self) -> [u8; HANDOFF_TOKEN_SIZE] {
+        let mut bytes = [0u8; HANDOFF_TOKEN_SIZE];
+        bytes[..FIELDS_SIZE].copy_from_slice(&self.fields());
+        bytes[FIELDS_SIZE..].copy_from_slice(&self.tag);
+        bytes
+    }
+
+    /// Reads a token from bytes returned by `to_bytes`. Returns None if the bytes
+    /// are not a token. Whether the token is valid is checked when it is used.
+    pub fn from_bytes(bytes: &[u8]) -> Option<HandoffToken> {
+        if bytes.len() < HANDOFF_TOKEN_SIZE || bytes[..4] != HANDOFF_TOKEN_MAGIC {
+            return None;
+        }
+        let read_u64 = |offset: usize| u64::from_be_bytes(bytes[offset..offset + 8].try_into().expect("Slice has 8 bytes"));
+        let ip_bytes: [u8; 16] = bytes[12..28].try_into().expect("Slice has 16 bytes");
+        Some(HandoffToken {
+            guid: read_u64(4),
+            ip: Ipv6Addr::from(ip_bytes).to_canonical(),
+            user_data: read_u64(28),
+            expires_at: read_u64(36),
+            nonce: read_u64(44),
+            tag: bytes[FIELDS_SIZE..HANDOFF_TOKEN_SIZE].try_into().expect("Slice has 32 bytes"),
+        })
+    }
+
+    /// Returns true if the token was signed with the handoff key and has not expired at `now`.
+    pub(crate) fn is_valid(&self, handoff_key: &[u8], now: SystemTime) -> bool {
+        now < self.expires_at() && security::is_handoff_tag_valid(handoff_key, &self.fields(), &self.tag)
+    }
+
+    fn fields(&self) -> [u8; FIELDS_SIZE] {
+        let mut fields = [0u8; FIELDS_SIZE];
+        fields[..4].copy_from_slice(&HANDOFF_TOKEN_MAGIC);
+        fields[4..12].copy_from_slice(&self.guid.to_be_bytes());
+        // IPv4 addresses are stored as IPv4-mapped IPv6 addresses
+        let ip = match self.ip {
+            IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+            IpAddr::V6(ip) => ip,
+        };
+        fields[12..28].copy_from_slice(&ip.octets());
+        fields[28..36].copy_from_slice(&self.user_data.to_be_bytes());
+        fields[36..44].copy_from_slice(&self.expires_at.to_be_bytes());
+        fields[44..52].copy_from_slice(&self.nonce.to_be_bytes());
+        fields
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::IpAddr, time::{Duration, SystemTime}};
+    use super::HandoffToken;
+
+    #[test]
+    fn token_is_valid_with_same_key_until_it_expires() {
+        // Arrange
+        let now = SystemTime::now();
+        let ip = IpAddr::from([192, 168, 1, 2]);
+        let token = HandoffToken::new(b"shared key", 0x12345678, ip, 42, now + Duration::from_secs(10));
+
+        // Act
+        let read_token = HandoffToken::from_bytes(&token.to_bytes()).expect("Could not read token");
+
+        // Assert
+        assert_eq!(token, read_token);
+        assert_eq!(0x12345678, read_token.guid());
+        assert_eq!(ip, read_token.ip());
+        assert_eq!(42, read_token.user_data());
+        assert!(read_token.is_valid(b"shared key", now));
+        assert!(!read_token.is_valid(b"other key", now));
+        assert!(!read_token.is_valid(b"shared key", now + Duration::from_secs(10)));
+        let mut tampered_bytes = token.to_bytes();
+        tampered_bytes[27] ^= 1;
+        assert!(!HandoffToken::from_bytes(&tampered_bytes).expect("Could not read token").is_valid(b"shared key", now));
+        assert_eq!(None, HandoffToken::from_bytes(&[0; 100]));
+    }
+}
@@ -0,0 +1,69 @@
+use std::time::Instant;
+
+/// Limits the number of incoming connections that are accepted per second from all
+/// remote peers together, so that a storm of connection requests, e.g. when all clients
+/// reconnect after a restart or during an attack, does not overload the peer.
+///
+/// A token bucket holding at most `burst` tokens is refilled at the maximum rate,
+/// which lets a burst of connections through before the rate applies.
+pub struct HandshakeRateLimiter {
+    tokens: f64,
+    last_update_time: Option<Instant>,
+}
+
+impl HandshakeRateLimiter {
+    pub fn new() -> Self {
+        HandshakeRateLimiter {
+            tokens: 0.0,
+            last_update_time: None,
+        }
+    }
+
+    /// Returns true if a connection may be accepted at the specified time and takes a token
+    /// from the bucket. If `max_per_second` is 0 all connections are accepted.
+    pub fn try_accept(&mut self, time: Instant, max_per_second: u32, burst: u32) -> bool {
+        if max_per_second == 0 {
+            return true;
+        }
+        let max_tokens = burst.max(1) as f64;
+        self.tokens = match self.last_update_time {
+            Some(last_update_time) => {
+                let elapsed = time.saturating_duration_since(last_update_time).as_secs_f64();
+                (self.tokens + elapsed * max_per_second as f64).min(max_tokens)
+            },
+            None => max_tokens,
+        };
+        self.last_update_time = Some(time);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+    use super::HandshakeRateLimiter;
+
+    #[test]
+    fn burst_is_accepted_then_limited_to_rate() {
+        // Arrange
+        let mut limiter = HandshakeRateLimiter::new();
+        let time = Instant::now();
+
+        // Act
+        let burst: Vec<bool> = (0..4).map(|_| limiter.try_accept(time, 10, 3)).collect();
+        let after_50_ms = limiter.try_accept(time + Duration::from_millis(50), 10, 3);
+        let after_100_ms = limiter.try_accept(time + Duration::from_millis(100), 10, 3);
+
+        // Assert
+        assert_eq!(vec![true, true, true, false], burst);
+        assert!(!after_50_ms);
+        assert!(after_100_ms);
+        let mut unlimited = HandshakeRateLimiter::new();
+        assert!((0..100).all(|_| unlimited.try_accept(time, 0, 0)));
+    }
+}
@@ -0,0 +1,141 @@
+use std::{fmt, time::Instant};
+
+/// What a peer does when a remote peer exceeds one of the inbound limits of its
+/// connection, see `Config::inbound_limit_action`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum InboundLimitAction {
+    /// The datagram or packet that exceeds the limit is dropped. Datagrams dropped for
+    /// exceeding a rate are not acknowledged, so their reliable packets are resent later.
+    DropPacket,
+    /// The connection is dropped and the IP address of the remote peer is greylisted.
+    Disconnect,
+    /// The connection is dropped and the IP address of the remote peer is banned for
+    /// `Config::inbound_limit_penalty_in_ms`.
+    Ban,
+}
+
+/// An inbound limit of a connection.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum InboundLimit {
+    /// `Config::max_incoming_packets_per_second`.
+    PacketRate,
+    /// `Config::max_incoming_bytes_per_second`.
+    ByteRate,
+    /// `Config::max_incoming_payload_size`.
+    PayloadSize,
+}
+
+impl fmt::Display for InboundLimit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InboundLimit::PacketRate => write!(f, "packets per second"),
+            InboundLimit::ByteRate => write!(f, "bytes per second"),
+            InboundLimit::PayloadSize => write!(f, "payload size"),
+        }
+    }
+}
+
+/// The result of checking whether an incoming datagram may be handled.
+#[derive(Debug, PartialEq)]
+pub enum InboundCheck {
+    Allowed,
+    /// The datagram exceeds `limit`. `first` is true for the first datagram
+    /// that exceeds a limit after the connection was below the limits.
+    Exceeded { limit: InboundLimit, first: bool },
+}
+
+/// Limits the number of packets and bytes per second received over a connection.
+///
+/// Both rates have a token bucket that holds at most one second worth of tokens and is
+/// refilled at the maximum rate. A datagram is allowed while both buckets hold tokens,
+/// even if it costs more than what is left, so that a datagram larger than the byte
+/// rate still gets through once per second.
+pub struct InboundLimiter {
+    packet_tokens: f64,
+    byte_tokens: f64,
+    last_update_time: Option<Instant>,
+    is_exceeded: bool,
+}
+
+impl InboundLimiter {
+    pub fn new() -> Self {
+        InboundLimiter {
+            packet_tokens: 0.0,
+            byte_tokens: 0.0,
+            last_update_time: None,
+            is_exceeded: false,
+        }
+    }
+
+    /// Returns whether a datagram containing `packet_count` packets of `byte_count` bytes
+    /// may be handled at the specified time and takes their tokens if it may. A maximum
+    /// of 0 means that the rate is unlimited.
+    pub fn check(&mut self, time: Instant, packet_count: u32, byte_count: usize, max_packets_per_second: u32, max_bytes_per_second: u64) -> InboundCheck {
+        let elapsed = self.last_update_time.map(|last_update_time| time.saturating_duration_since(last_update_time).as_secs_f64());
+        self.last_update_time = Some(time);
+        self.packet_tokens = Self::refill(self.packet_tokens, elapsed, max_packets_per_second as f64);
+        self.byte_tokens = Self::refill(self.byte_tokens, elapsed, max_bytes_per_second as f64);
+
+        let limit = if max_packets_per_second > 0 && self.packet_tokens <= 0.0 {
+            Some(InboundLimit::PacketRate)
+        } else if max_bytes_per_second > 0 && self.byte_tokens <= 0.0 {
+            Some(InboundLimit::ByteRate)
+        } else {
+            None
+        };
+        match limit {
+            Some(limit) => {
+                let first = !self.is_exceeded;
+                self.is_exceeded = true;
+                InboundCheck::Exceeded { limit, first }
+            },
+            None => {
+                if max_packets_per_second > 0 {
+                    self.packet_tokens -= packet_count as f64;
+                }
+                if max_bytes_per_second > 0 {
+                    self.byte_tokens -= byte_count as f64;
+                }
+                self.is_exceeded = false;
+                InboundCheck::Allowed
+            },
+        }
+    }
+
+    fn refill(tokens: f64, elapsed: Option<f64>, max_tokens: f64) -> f64 {
+        match elapsed {
+            Some(elapsed) => (tokens + elapsed * max_tokens).min(max_tokens),
+            None => max_tokens,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+    use super::{InboundCheck, InboundLimit, InboundLimiter};
+
+    #[test]
+    fn datagrams_are_limited_until_buckets_are_refilled() {
+        // Arrange
+        let mut limiter = InboundLimiter::new();
+        let time = Instant::now();
+
+        // Act
+        let first = limiter.check(time, 8, 100, 10, 0);
+        let second = limiter.check(time, 8, 100, 10, 0);
+        let third = limiter.check(time, 1, 100, 10, 0);
+        let after_second = limiter.check(time + Duration::from_secs(1), 1, 2000, 10, 1000);
+        let after_large_datagram = limiter.check(time + Duration::from_secs(1), 1, 10, 10, 1000);
+
+        // Assert
+        assert_eq!(InboundCheck::Allowed, first);
+        assert_eq!(InboundCheck::Allowed, second);
+        assert_eq!(InboundCheck::Exceeded { limit: InboundLimit::PacketRate, first: true }, third);
+        assert_eq!(InboundCheck::Allowed, after_second);
+        assert_eq!(InboundCheck::Exceeded { limit: InboundLimit::ByteRate, first: true }, after_large_datagram);
+        let mut unlimited = InboundLimiter::new();
+        assert!((0..100).all(|_| unlimited.check(time, 100, 100000, 0, 0) == InboundCheck::Allowed));
+    }
+}
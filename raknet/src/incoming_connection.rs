@@ -4,11 +4,12 @@ use std::net::SocketAddr;
 pub struct IncomingConnection {
     addr: SocketAddr,
     guid: u64,
+    handoff_user_data: Option<u64>,
 }
 
 impl IncomingConnection {
-    pub(crate) fn new(addr: SocketAddr, guid: u64) -> Self {
-        IncomingConnection { addr, guid }
+    pub(crate) fn new(addr: SocketAddr, guid: u64, handoff_user_data: Option<u64>) -> Self {
+        IncomingConnection { addr, guid, handoff_user_data }
     }
 
     pub fn addr(&self) -> SocketAddr {
@@ -18,4 +19,10 @@ impl IncomingConnection {
     pub fn guid(&self) -> u64 {
         self.guid
     }
+
+    /// Returns the user data of the handoff token that the remote peer connected with,
+    /// see `HandoffToken`, or None if it did not use a valid token.
+    pub fn handoff_user_data(&self) -> Option<u64> {
+        self.handoff_user_data
+    }
 }
\ No newline at end of file
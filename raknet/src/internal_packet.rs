@@ -1,13 +1,54 @@
-use std::time::Instant;
+use std::{ops::Deref, sync::Arc, time::Instant};
 
 use crate::{
     error::{ReadError, WriteError},
     number::{MessageNumber, OrderingChannelIndex, OrderingIndex, SequencingIndex},
     reader::DataRead,
     Result,
-    writer::DataWrite,
+    writer::{DataWrite, PacketWrite},
 };
 
+/// The payload of a packet. Outgoing packets sent to several remote peers
+/// share one payload instead of each connection owning a copy.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PacketPayload {
+    Owned(Box<[u8]>),
+    Shared(Arc<[u8]>),
+}
+
+impl PacketPayload {
+    /// Returns the payload, which is copied if it is shared.
+    pub fn into_boxed_slice(self) -> Box<[u8]> {
+        match self {
+            PacketPayload::Owned(payload) => payload,
+            PacketPayload::Shared(payload) => payload.as_ref().into(),
+        }
+    }
+}
+
+impl Deref for PacketPayload {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            PacketPayload::Owned(payload) => payload,
+            PacketPayload::Shared(payload) => payload,
+        }
+    }
+}
+
+impl From<Box<[u8]>> for PacketPayload {
+    fn from(payload: Box<[u8]>) -> Self {
+        PacketPayload::Owned(payload)
+    }
+}
+
+impl From<Arc<[u8]>> for PacketPayload {
+    fn from(payload: Arc<[u8]>) -> Self {
+        PacketPayload::Shared(payload)
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum InternalReliability {
     Unreliable,
@@ -29,7 +70,6 @@ pub struct SplitPacketHeader {
 }
 
 impl SplitPacketHeader {
-    #[allow(dead_code)]
     pub fn new(split_packet_count: u32, split_packet_id: u16, split_packet_index: u32) -> Self {
         SplitPacketHeader {
             split_packet_count,
@@ -47,8 +87,7 @@ impl SplitPacketHeader {
         Ok(header)
     }
 
-    #[allow(dead_code)]
-    pub fn write(&self, writer: &mut impl DataWrite) -> Result<()> {
+    pub fn write(&self, writer: &mut dyn DataWrite) -> Result<()> {
         writer.write_u32_be(self.split_packet_count)?;
         writer.write_u16_be(self.split_packet_id)?;
         writer.write_u32_be(self.split_packet_index)?;
@@ -75,21 +114,27 @@ pub struct InternalPacket {
     ordering: InternalOrdering,
     split_packet_header: Option<SplitPacketHeader>,
     receipt: Option<u32>,
-    payload: Box<[u8]>, 
+    resend_count: u32,
+    payload: PacketPayload,
 }
 
 impl InternalPacket {
-    pub fn new(creation_time: Instant, reliability: InternalReliability, ordering: InternalOrdering, split_packet_header: Option<SplitPacketHeader>, receipt: Option<u32>, payload: Box<[u8]>) -> Self {
+    pub fn new(creation_time: Instant, reliability: InternalReliability, ordering: InternalOrdering, split_packet_header: Option<SplitPacketHeader>, receipt: Option<u32>, payload: impl Into<PacketPayload>) -> Self {
         InternalPacket {
             creation_time,
             reliability,
             ordering,
             split_packet_header,
             receipt,
-            payload,
+            resend_count: 0,
+            payload: payload.into(),
         }
     }
 
+    pub fn creation_time(&self) -> Instant {
+        self.creation_time
+    }
+
     pub fn read(creation_time: Instant, reader: &mut impl DataRead) -> Result<Self> { 
         let flags = reader.read_u8()?;
         let payload_bit_length = reader.read_u16_be()?;
@@ -145,12 +190,14 @@ impl InternalPacket {
             ordering,
             split_packet_header,
             receipt: None,
-            payload,
+            resend_count: 0,
+            payload: payload.into(),
         })
     }
+}
 
-    #[allow(dead_code)]
-    pub fn write(&self, writer: &mut impl DataWrite) -> Result<()> {
+impl PacketWrite for InternalPacket {
+    fn write(&self, writer: &mut dyn DataWrite) -> Result<()> {
         let mut flags: u8 = match (self.reliability, self.ordering) {
             (InternalReliability::Unreliable, InternalOrdering::None) => 0 << 5,
             (InternalReliability::Unreliable, InternalOrdering::Sequenced {sequencing_index: _, ordering_index: _, ordering_channel_index: _}) => 1 << 5,
@@ -197,6 +244,27 @@ impl InternalPacket {
         Ok(())
     }
 
+    fn written_size(&self) -> usize {
+        // Bitflags (u8) + Data bit length (u16)
+        let mut header_size = 1 + 2;
+        if let InternalReliability::Reliable(_) = self.reliability {
+            // Reliable message number (u24)
+            header_size = header_size + 3;
+        }
+        header_size = header_size + match self.ordering {
+            InternalOrdering::None => 0,
+            InternalOrdering::Ordered { ordering_index: _, ordering_channel_index: _ } => 3 + 1,
+            InternalOrdering::Sequenced { sequencing_index: _, ordering_index: _, ordering_channel_index: _ } => 3 + 3 + 1,
+        };
+        if let Some(_) = self.split_packet_header {
+            // Split packet count (u32) + split packet ID (u16) + split packet index (u32)
+            header_size = header_size + 4 + 2 + 4;
+        }
+        header_size + self.payload.len()
+    }
+}
+
+impl InternalPacket {
     pub fn reliability(&self) -> InternalReliability {
         self.reliability
     }
@@ -217,48 +285,33 @@ impl InternalPacket {
         self.split_packet_header != None
     }
 
-    pub fn get_size_in_bytes(&self) -> u16 {
-        self.get_header_size_in_bytes() + self.payload.len() as u16
+    pub fn receipt(&self) -> Option<u32> {
+        self.receipt
     }
 
-    fn get_header_size_in_bytes(&self) -> u16 {
-        // Bitflags (u8) + Data bit length (u16)
-        let mut header_size = 1 + 2;
-        if let InternalReliability::Reliable(_) = self.reliability {
-            // Reliable message number (u24)
-            header_size = header_size + 3;
-        }
-        header_size = header_size + match self.ordering {
-            InternalOrdering::None => 0,
-            InternalOrdering::Ordered { ordering_index: _, ordering_channel_index: _ } => 3 + 1,
-            InternalOrdering::Sequenced { sequencing_index: _, ordering_index: _, ordering_channel_index: _ } => 3 + 3 + 1,
-        };
-        if let Some(_) = self.split_packet_header {
-            // Split packet count (u32) + split packet ID (u16) + split packet index (u32)
-            header_size = header_size + 4 + 2 + 4;
-        }
-        header_size
+    /// Returns the number of times this packet has been resent.
+    pub fn resend_count(&self) -> u32 {
+        self.resend_count
     }
 
-    #[allow(dead_code)]
-    pub fn receipt(&self) -> Option<u32> {
-        self.receipt
+    /// Registers that this packet is about to be resent.
+    pub fn increment_resend_count(&mut self) {
+        self.resend_count += 1;
     }
 
-    #[allow(dead_code)]
     pub fn payload(&self) -> &[u8] {
         &self.payload
     }
 
     pub fn into_payload(self) -> Box<[u8]> {
-        self.payload
+        self.payload.into_boxed_slice()
     }
 }
 
 #[cfg(test)]
 mod tests {
     use std::{convert::TryFrom, time::Instant};
-    use crate::{number::{MessageNumber, OrderingIndex, SequencingIndex}, reader::DataReader};
+    use crate::{number::{MessageNumber, OrderingIndex, SequencingIndex}, reader::DataReader, writer::PacketWrite};
     use super::{InternalPacket, InternalOrdering, InternalReliability, SplitPacketHeader};
 
     #[test]
@@ -829,5 +882,6 @@ mod tests {
             0x01, 0x23, 0x45, 0x67, // Split packet index: 0x01234567 
             0x12, 0x34, // Data [0x12, 0x34]
         ]);
+        assert_eq!(buf.len(), packet.written_size());
     }
 }
\ No newline at end of file
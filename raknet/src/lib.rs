@@ -1,36 +1,119 @@
 pub use crossbeam_channel as channel;
 
 pub use self::{
-    config::Config,
+    admission_policy::{Admission, AdmissionPolicy},
+    allow_list::AllowList,
+    ban_file::{BanEntry, BanFile, BanFileError},
+    ban_list::{InvalidIpRange, IpRange},
+    bit_stream::{BitReader, BitWriter},
+    capture::{CaptureDirection, CapturedDatagram, PacketCapture, PcapWriter},
+    clock::{Clock, ManualClock, SystemClock},
+    config::{Config, InvalidConfig},
+    config_builder::ConfigBuilder,
+    config_update::ConfigUpdate,
+    connect_options::ConnectOptions,
+    connection_attempt_failed_reason::ConnectionAttemptFailedReason,
+    connection_handle::{ConnectionHandle, ConnectionResult},
+    connected_socket::ConnectedUdpSocket,
+    connection_info::{ConnectionInfo, ConnectionPhase},
+    connection_limits::ConnectionLimits,
+    connection_lost_reason::ConnectionLostReason,
+    connection_stats::ConnectionStats,
     error::{Error, Result, ReadError, WriteError},
+    handoff_token::HandoffToken,
+    inbound_limiter::{InboundLimit, InboundLimitAction},
     incoming_connection::IncomingConnection,
+    moderation_event::{ModerationAction, ModerationRule},
+    multi_socket::MultiSocket,
     number::OrderingChannelIndex,
+    offline_packet_handler::PingResponseProvider,
     packet::{Packet, Reliability, Ordering, Priority},
     peer::{Peer, Command},
     peer_event::PeerEvent,
+    peer_metrics::PeerMetrics,
     reader::DataRead,
+    remote_peer::RemotePeer,
+    security_event::SecurityEventKind,
+    send_options::SendOptions,
     send_receipt::SendReceipt,
+    send_target::SendTarget,
+    socket::DatagramSocket,
+    socket_addr_format::SocketAddrFormat,
+    socket_options::SocketOptions,
+    tick_stats::{TickStats, TickTimings},
     writer::DataWrite,
 };
 
+#[cfg(target_os = "linux")]
+pub use self::batched_socket::BatchedUdpSocket;
+
+#[cfg(feature = "rand")]
+pub use self::security::SecurityConfig;
+
+#[cfg(feature = "serde")]
+pub use self::config::ConfigFileError;
+
+#[cfg(feature = "async")]
+pub use self::{
+    async_peer::AsyncPeer,
+    tokio_socket::TokioUdpSocket,
+};
+
 mod acknowledge_handler;
+mod admission_policy;
+mod allow_list;
+#[cfg(feature = "async")]
+mod async_peer;
+mod ban_file;
+mod ban_list;
+mod bandwidth_limiter;
+#[cfg(target_os = "linux")]
+mod batched_socket;
+mod bit_stream;
+mod capture;
 mod communicator;
+mod clock;
 mod config;
+mod config_builder;
+mod config_update;
+mod congestion_control;
+mod connect_options;
+mod connected_socket;
 mod connection;
+mod connection_attempt;
+mod connection_attempt_failed_reason;
+mod connection_handle;
+mod connection_info;
+mod connection_limits;
+mod connection_lost_reason;
 mod connection_manager;
+mod connection_stats;
 mod constants;
+mod data_arrival_rate;
+mod datagram_cipher;
 mod datagram_header;
 mod datagram_heap;
 mod datagram_range;
 mod datagram_range_list;
+pub mod decode;
 mod error;
+mod greylist;
+mod handoff_token;
+mod handshake_rate_limiter;
+mod inbound_limiter;
 mod incoming_connection;
 mod internal_packet;
+mod logging;
+mod loss_estimator;
 mod message_ids;
 mod messages;
+mod moderation_event;
+mod mtu_blackhole_detector;
+mod multi_socket;
 mod nack;
 mod number;
 mod offline_packet_handler;
+mod offline_rate_limiter;
 mod ordering_channel;
 mod ordering_system;
 mod outgoing_acknowledgements;
@@ -39,11 +122,26 @@ mod packet;
 mod packet_datagram;
 mod peer;
 mod peer_event;
+mod peer_metrics;
+mod random;
 mod reader;
+mod reconnector;
 mod reliable_message_number_handler;
 mod reliability_layer;
+mod remote_peer;
+mod rtt_estimator;
+mod security;
+mod security_event;
+mod send_options;
+mod send_pacer;
 mod send_receipt;
+mod send_target;
 mod socket;
+mod socket_addr_format;
+mod socket_options;
 mod split_packet_handler;
+mod tick_stats;
+#[cfg(feature = "async")]
+mod tokio_socket;
 mod utils;
 mod writer;
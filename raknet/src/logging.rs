@@ -0,0 +1,36 @@
+//! Logging macros that forward to the `log` crate with the `log` feature
+//! and compile to nothing without it.
+
+#[cfg(feature = "log")]
+macro_rules! debug {
+    ($($arg:tt)*) => { log::debug!($($arg)*) };
+}
+
+#[cfg(feature = "log")]
+macro_rules! info {
+    ($($arg:tt)*) => { log::info!($($arg)*) };
+}
+
+#[cfg(feature = "log")]
+macro_rules! error {
+    ($($arg:tt)*) => { log::error!($($arg)*) };
+}
+
+// The arguments are still type checked so that they are used
+// and valid whether or not the feature is enabled.
+#[cfg(not(feature = "log"))]
+macro_rules! debug {
+    ($($arg:tt)*) => { if false { let _ = format_args!($($arg)*); } };
+}
+
+#[cfg(not(feature = "log"))]
+macro_rules! info {
+    ($($arg:tt)*) => { if false { let _ = format_args!($($arg)*); } };
+}
+
+#[cfg(not(feature = "log"))]
+macro_rules! error {
+    ($($arg:tt)*) => { if false { let _ = format_args!($($arg)*); } };
+}
+
+pub(crate) use {debug, error, info};
@@ -0,0 +1,122 @@
+use std::{collections::VecDeque, time::{Duration, Instant}};
+
+/// The duration of the packets counted by the estimate.
+const WINDOW: Duration = Duration::from_secs(5);
+
+/// The duration that the packets of one bucket are sent during.
+const BUCKET_DURATION: Duration = Duration::from_secs(1);
+
+struct Bucket {
+    start: Instant,
+    /// The number of packets sent for the first time.
+    packets_sent: u64,
+    packets_resent: u64,
+}
+
+/// Estimates the packet loss of a connection as the number of resends compared to the packets
+/// sent for the first time during the last five seconds. Packets are resent since they were
+/// NACK:ed or not acknowledged in time.
+pub struct LossEstimator {
+    buckets: VecDeque<Bucket>,
+}
+
+impl LossEstimator {
+    pub fn new() -> Self {
+        LossEstimator {
+            buckets: VecDeque::new(),
+        }
+    }
+
+    /// Registers that a packet has been sent, which is a resend if `is_resend` is true.
+    pub fn on_packet_sent(&mut self, time: Instant, is_resend: bool) {
+        let needs_bucket = self.buckets.back().is_none_or(|bucket| time.saturating_duration_since(bucket.start) >= BUCKET_DURATION);
+        if needs_bucket {
+            self.remove_old_buckets(time);
+            self.buckets.push_back(Bucket { start: time, packets_sent: 0, packets_resent: 0 });
+        }
+        if let Some(bucket) = self.buckets.back_mut() {
+            if is_resend {
+                bucket.packets_resent += 1;
+            } else {
+                bucket.packets_sent += 1;
+            }
+        }
+    }
+
+    /// Returns the number of resends as a percentage of the packets sent for the first time
+    /// during the last five seconds, at most 100, or 0 if no packets have been sent.
+    pub fn packet_loss(&self, time: Instant) -> f32 {
+        let (sent, resent) = self.buckets.iter()
+            .filter(|bucket| time.saturating_duration_since(bucket.start) < WINDOW)
+            .fold((0, 0), |(sent, resent), bucket| (sent + bucket.packets_sent, resent + bucket.packets_resent));
+        if sent == 0 {
+            0.0
+        } else {
+            (resent as f64 * 100.0 / sent as f64).min(100.0) as f32
+        }
+    }
+
+    fn remove_old_buckets(&mut self, time: Instant) {
+        while self.buckets.front().is_some_and(|bucket| time.saturating_duration_since(bucket.start) >= WINDOW) {
+            self.buckets.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+    use super::LossEstimator;
+
+    #[test]
+    fn packet_loss_is_share_of_resent_packets() {
+        // Arrange
+        let mut estimator = LossEstimator::new();
+        let time = Instant::now();
+
+        // Act
+        for i in 0..10 {
+            estimator.on_packet_sent(time + Duration::from_millis(i * 100), i % 5 == 4);
+        }
+
+        // Assert
+        assert_eq!(25.0, estimator.packet_loss(time + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn packet_loss_is_100_when_every_packet_is_resent() {
+        // Arrange
+        let mut estimator = LossEstimator::new();
+        let time = Instant::now();
+
+        // Act
+        for i in 0..10 {
+            estimator.on_packet_sent(time + Duration::from_millis(i * 10), false);
+            estimator.on_packet_sent(time + Duration::from_millis(i * 10 + 5), true);
+            estimator.on_packet_sent(time + Duration::from_millis(i * 10 + 5), true);
+        }
+
+        // Assert
+        assert_eq!(100.0, estimator.packet_loss(time + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn packet_loss_only_counts_recent_packets() {
+        // Arrange
+        let mut estimator = LossEstimator::new();
+        let time = Instant::now();
+        estimator.on_packet_sent(time, false);
+        estimator.on_packet_sent(time, true);
+
+        // Act
+        let recent_loss = estimator.packet_loss(time + Duration::from_secs(1));
+        let old_loss = estimator.packet_loss(time + Duration::from_secs(6));
+        estimator.on_packet_sent(time + Duration::from_secs(6), false);
+
+        // Assert
+        assert_eq!(100.0, recent_loss);
+        assert_eq!(0.0, old_loss);
+        assert_eq!(0.0, estimator.packet_loss(time + Duration::from_secs(6)));
+        assert_eq!(0.0, LossEstimator::new().packet_loss(time));
+    }
+}
@@ -1,55 +1,194 @@
 use std::{convert::TryFrom, net::{IpAddr, Ipv4Addr, SocketAddr}};
 
 use crate::{
-    constants::{MAX_NUMBER_OF_INTERNAL_IDS, OFFLINE_MESSAGE_ID},
+    constants::{HANDOFF_TOKEN_SIZE, MAX_NUMBER_OF_INTERNAL_IDS, MAX_PASSWORD_LENGTH, OFFLINE_MESSAGE_ID},
     error::{Error, ReadError, Result},
+    handoff_token::HandoffToken,
     message_ids::MessageId,
     reader::{DataRead, MessageRead},
-    writer::{DataWrite, MessageWrite},
+    writer::{socket_addr_size, DataWrite, MessageWrite},
 };
 
-#[derive(Debug)]
-pub struct ConnectedPingMessage {
-    pub time: u64,
+/// Defines a message struct with `MessageRead` and `MessageWrite` implementations from
+/// its message ID and its fields in the order they are sent. Each field is read and
+/// written as a `MessageField`, and `offline_message_id` marks where the offline message
+/// ID is sent.
+macro_rules! raknet_message {
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident($message_id:expr) {
+            $($layout:tt)*
+        }
+    ) => {
+        raknet_message!(@munch reader writer [$(#[$attr])* $vis struct $name($message_id)] [] [] [] [] [] $($layout)*);
+    };
+    (@munch $reader:ident $writer:ident $header:tt [$($fields:tt)*] [$($names:ident)*] [$($reads:tt)*] [$($writes:tt)*] [$($sizes:tt)*]
+        offline_message_id, $($rest:tt)*) => {
+        raknet_message!(@munch $reader $writer $header [$($fields)*] [$($names)*]
+            [$($reads)* $reader.read_bytes_and_compare(&OFFLINE_MESSAGE_ID).map_err(|_| ReadError::InvalidOfflineMessageId)?;]
+            [$($writes)* $writer.write_bytes(&OFFLINE_MESSAGE_ID)?;]
+            [$($sizes)* + OFFLINE_MESSAGE_ID.len()]
+            $($rest)*);
+    };
+    (@munch $reader:ident $writer:ident $header:tt [$($fields:tt)*] [$($names:ident)*] [$($reads:tt)*] [$($writes:tt)*] [$($sizes:tt)*]
+        $(#[$field_attr:meta])* $field_vis:vis $field:ident: $ty:ty, $($rest:tt)*) => {
+        raknet_message!(@munch $reader $writer $header
+            [$($fields)* $(#[$field_attr])* $field_vis $field: $ty,]
+            [$($names)* $field]
+            [$($reads)* let $field = <$ty as MessageField>::read_field($reader)?;]
+            [$($writes)* MessageField::write_field($field, $writer)?;]
+            [$($sizes)* + MessageField::written_size($field)]
+            $($rest)*);
+    };
+    (@munch $reader:ident $writer:ident [$(#[$attr:meta])* $vis:vis struct $name:ident($message_id:expr)]
+        [$($fields:tt)*] [$($names:ident)*] [$($reads:tt)*] [$($writes:tt)*] [$($sizes:tt)*]) => {
+        $(#[$attr])*
+        $vis struct $name {
+            $($fields)*
+        }
+
+        impl MessageRead for $name {
+            fn read_message($reader: &mut dyn DataRead) -> Result<Self> {
+                $reader.read_u8_and_compare($message_id.into())?;
+                $($reads)*
+                Ok($name { $($names),* })
+            }
+        }
+
+        impl MessageWrite for $name {
+            fn write_message(&self, $writer: &mut dyn DataWrite) -> Result<()> {
+                let $name { $($names),* } = self;
+                $writer.write_u8($message_id.into())?;
+                $($writes)*
+                Ok(())
+            }
+
+            fn written_size(&self) -> usize {
+                let $name { $($names),* } = self;
+                1 $($sizes)*
+            }
+        }
+    };
 }
 
-impl MessageRead for ConnectedPingMessage {
-    fn read_message(reader: &mut dyn DataRead) -> Result<Self> {
-        reader.read_u8_and_compare(MessageId::ConnectedPing.into())?;
-        let time = reader.read_u64_be()?;
-        Ok(ConnectedPingMessage { time })
+/// A field of a message defined with `raknet_message!`, sent in the byte order used by RakNet.
+trait MessageField: Sized {
+    fn read_field(reader: &mut dyn DataRead) -> Result<Self>;
+    fn write_field(&self, writer: &mut dyn DataWrite) -> Result<()>;
+    fn written_size(&self) -> usize;
+}
+
+impl MessageField for u8 {
+    fn read_field(reader: &mut dyn DataRead) -> Result<Self> {
+        reader.read_u8()
+    }
+
+    fn write_field(&self, writer: &mut dyn DataWrite) -> Result<()> {
+        writer.write_u8(*self)?;
+        Ok(())
+    }
+
+    fn written_size(&self) -> usize {
+        1
     }
 }
 
-impl MessageWrite for ConnectedPingMessage {
-    fn write_message(&self, writer: &mut dyn DataWrite) -> Result<()> {
-        writer.write_u8(MessageId::ConnectedPing.into())?;
-        writer.write_u64_be(self.time)?;
-        Ok(())      
+impl MessageField for u16 {
+    fn read_field(reader: &mut dyn DataRead) -> Result<Self> {
+        reader.read_u16_be()
+    }
+
+    fn write_field(&self, writer: &mut dyn DataWrite) -> Result<()> {
+        writer.write_u16_be(*self)?;
+        Ok(())
+    }
+
+    fn written_size(&self) -> usize {
+        2
     }
 }
 
-#[derive(Debug)]
-pub struct ConnectedPongMessage {
-    pub send_ping_time: u64,
-    pub send_pong_time: u64,
+impl MessageField for u64 {
+    fn read_field(reader: &mut dyn DataRead) -> Result<Self> {
+        reader.read_u64_be()
+    }
+
+    fn write_field(&self, writer: &mut dyn DataWrite) -> Result<()> {
+        writer.write_u64_be(*self)?;
+        Ok(())
+    }
+
+    fn written_size(&self) -> usize {
+        8
+    }
 }
 
-impl MessageRead for ConnectedPongMessage {
-    fn read_message(reader: &mut dyn DataRead) -> Result<Self> {
-        reader.read_u8_and_compare(MessageId::ConnectedPong.into())?;
-        let send_ping_time = reader.read_u64_be()?;
-        let send_pong_time = reader.read_u64_be()?;
-        Ok(ConnectedPongMessage { send_ping_time, send_pong_time })
+impl MessageField for SocketAddr {
+    fn read_field(reader: &mut dyn DataRead) -> Result<Self> {
+        reader.read_socket_addr()
+    }
+
+    fn write_field(&self, writer: &mut dyn DataWrite) -> Result<()> {
+        writer.write_socket_addr(self)?;
+        Ok(())
+    }
+
+    fn written_size(&self) -> usize {
+        socket_addr_size(self)
     }
 }
 
-impl MessageWrite for ConnectedPongMessage {
-    fn write_message(&self, writer: &mut dyn DataWrite) -> Result<()> {
-        writer.write_u8(MessageId::ConnectedPong.into())?;
-        writer.write_u64_be(self.send_ping_time)?;
-        writer.write_u64_be(self.send_pong_time)?;
-        Ok(())      
+impl MessageField for [SocketAddr; MAX_NUMBER_OF_INTERNAL_IDS] {
+    fn read_field(reader: &mut dyn DataRead) -> Result<Self> {
+        let mut ip_list = [SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 0); MAX_NUMBER_OF_INTERNAL_IDS];
+        for ip in ip_list.iter_mut() {
+            *ip = reader.read_socket_addr()?;
+        }
+        Ok(ip_list)
+    }
+
+    fn write_field(&self, writer: &mut dyn DataWrite) -> Result<()> {
+        for ip in self.iter() {
+            writer.write_socket_addr(ip)?;
+        }
+        Ok(())
+    }
+
+    fn written_size(&self) -> usize {
+        self.iter().map(socket_addr_size).sum()
+    }
+}
+
+/// The remaining bytes of a message, which must be its last field.
+impl MessageField for Vec<u8> {
+    fn read_field(reader: &mut dyn DataRead) -> Result<Self> {
+        let mut data = Vec::new();
+        reader.read_bytes_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    fn write_field(&self, writer: &mut dyn DataWrite) -> Result<()> {
+        writer.write_bytes(self)?;
+        Ok(())
+    }
+
+    fn written_size(&self) -> usize {
+        self.len()
+    }
+}
+
+raknet_message! {
+    #[derive(Debug)]
+    pub struct ConnectedPingMessage(MessageId::ConnectedPing) {
+        pub time: u64,
+    }
+}
+
+raknet_message! {
+    #[derive(Debug)]
+    pub struct ConnectedPongMessage(MessageId::ConnectedPong) {
+        pub send_ping_time: u64,
+        pub send_pong_time: u64,
     }
 }
 
@@ -83,13 +222,20 @@ impl MessageWrite for UnconnectedPingMessage {
         writer.write_u64_be(self.client_guid)?;
         Ok(())
     }
+
+    fn written_size(&self) -> usize {
+        1 + 8 + OFFLINE_MESSAGE_ID.len() + 8
+    }
 }
 
-#[derive(Debug)]
-pub struct UnconnectedPongMessage {
-    pub guid: u64,
-    pub time: u64,
-    pub data: Vec<u8>,
+raknet_message! {
+    #[derive(Debug)]
+    pub struct UnconnectedPongMessage(MessageId::UnconnectedPong) {
+        pub time: u64,
+        pub guid: u64,
+        offline_message_id,
+        pub data: Vec<u8>,
+    }
 }
 
 impl UnconnectedPongMessage {
@@ -102,26 +248,12 @@ impl UnconnectedPongMessage {
     }
 }
 
-impl MessageRead for UnconnectedPongMessage {
-    fn read_message(reader: &mut dyn DataRead) -> Result<Self> {
-        reader.read_u8_and_compare(MessageId::UnconnectedPong.into())?;
-        let time = reader.read_u64_be()?;
-        let guid = reader.read_u64_be()?;
-        reader.read_bytes_and_compare(&OFFLINE_MESSAGE_ID).map_err(|_| ReadError::InvalidOfflineMessageId)?;
-        let mut data = Vec::new();
-        reader.read_bytes_to_end(&mut data)?;
-        Ok(UnconnectedPongMessage { time, guid, data })
-    }
-}
-
-impl MessageWrite for UnconnectedPongMessage {
-    fn write_message(&self, writer: &mut dyn DataWrite) -> Result<()> {
-        writer.write_u8(MessageId::UnconnectedPong.into())?;
-        writer.write_u64_be(self.time)?;
-        writer.write_u64_be(self.guid)?;
-        writer.write_bytes(&OFFLINE_MESSAGE_ID)?;
-        writer.write_bytes(&self.data)?;
-        Ok(())      
+raknet_message! {
+    #[derive(Debug)]
+    pub struct OutOfBandInternalMessage(MessageId::OutOfBandInternal) {
+        pub guid: u64,
+        offline_message_id,
+        pub data: Vec<u8>,
     }
 }
 
@@ -149,6 +281,10 @@ impl MessageWrite for OpenConnectionRequest1Message {
         writer.write_zero_padding(self.padding_length)?;
         Ok(())      
     }
+
+    fn written_size(&self) -> usize {
+        1 + OFFLINE_MESSAGE_ID.len() + 1 + self.padding_length as usize
+    }
 }
 
 #[derive(Debug)]
@@ -207,6 +343,11 @@ impl MessageWrite for OpenConnectionReply1Message {
         writer.write_u16_be(self.mtu)?;
         Ok(())      
     }
+
+    fn written_size(&self) -> usize {
+        let security_size = if self.cookie_and_public_key.is_some() { 4 + 64 } else { 0 };
+        1 + OFFLINE_MESSAGE_ID.len() + 8 + 1 + security_size + 2
+    }
 }
 
 #[derive(Debug)]
@@ -215,6 +356,9 @@ pub struct OpenConnectionRequest2Message {
     pub binding_address: SocketAddr,
     pub mtu: u16,
     pub guid: u64,
+    /// A handoff token written after the GUID, which is ignored by other
+    /// RakNet implementations that do not read past the GUID.
+    pub handoff_token: Option<HandoffToken>,
 }
 
 impl MessageRead for OpenConnectionRequest2Message {
@@ -224,11 +368,13 @@ impl MessageRead for OpenConnectionRequest2Message {
         let binding_address = reader.read_socket_addr()?;
         let mtu = reader.read_u16_be()?;
         let guid = reader.read_u64_be()?;
+        let handoff_token = read_handoff_token(reader);
         Ok(OpenConnectionRequest2Message {
             cookie_and_challenge: None,
             binding_address,
             mtu,
             guid,
+            handoff_token,
         })
     }
 
@@ -247,11 +393,13 @@ impl MessageRead for OpenConnectionRequest2Message {
         let binding_address = reader.read_socket_addr()?;
         let mtu = reader.read_u16_be()?;
         let guid = reader.read_u64_be()?;
+        let handoff_token = read_handoff_token(reader);
         Ok(OpenConnectionRequest2Message {
             cookie_and_challenge: Some((cookie, challenge)),
             binding_address,
             mtu,
             guid,
+            handoff_token,
         })
     }    
 }
@@ -272,8 +420,32 @@ impl MessageWrite for OpenConnectionRequest2Message {
         writer.write_socket_addr(&self.binding_address)?;
         writer.write_u16_be(self.mtu)?;
         writer.write_u64_be(self.guid)?;
+        if let Some(handoff_token) = &self.handoff_token {
+            writer.write_bytes(&handoff_token.to_bytes())?;
+        }
         Ok(())      
     }
+
+    fn written_size(&self) -> usize {
+        let security_size = match self.cookie_and_challenge {
+            Some((_, Some(_))) => 4 + 1 + 64,
+            Some((_, None)) => 4 + 1,
+            None => 0,
+        };
+        let handoff_token_size = if self.handoff_token.is_some() { HANDOFF_TOKEN_SIZE } else { 0 };
+        1 + OFFLINE_MESSAGE_ID.len() + security_size + socket_addr_size(&self.binding_address) + 2 + 8 + handoff_token_size
+    }
+}
+
+/// Reads the optional handoff token at the end of an open connection request 2.
+/// Trailing bytes that are not a handoff token are ignored.
+fn read_handoff_token(reader: &mut dyn DataRead) -> Option<HandoffToken> {
+    if reader.remaining() < HANDOFF_TOKEN_SIZE {
+        return None;
+    }
+    let mut token_bytes = [0u8; HANDOFF_TOKEN_SIZE];
+    reader.read_bytes(&mut token_bytes).ok()?;
+    HandoffToken::from_bytes(&token_bytes)
 }
 
 #[derive(Debug)]
@@ -335,6 +507,11 @@ impl MessageWrite for OpenConnectionReply2Message {
         }
         Ok(())      
     }
+
+    fn written_size(&self) -> usize {
+        let security_size = if self.challenge_answer.is_some() { 128 } else { 0 };
+        1 + OFFLINE_MESSAGE_ID.len() + 8 + socket_addr_size(&self.client_address) + 2 + 1 + security_size
+    }
 }
 
 #[derive(Debug)]
@@ -367,7 +544,7 @@ impl MessageRead for ConnectionRequestMessage {
             None
         };        
         let mut password = Vec::new();
-        reader.read_bytes_to_end(&mut password)?;
+        reader.read_bytes_to_end_with_limit(&mut password, MAX_PASSWORD_LENGTH)?;
         Ok(ConnectionRequestMessage { guid, time, proof_and_client_key, password: password.into_boxed_slice() })
     }
 }
@@ -392,85 +569,52 @@ impl MessageWrite for ConnectionRequestMessage {
         writer.write_bytes(&self.password)?;
         Ok(())
     }
-}
 
-#[derive(Debug)]
-pub struct ConnectionRequestAcceptedMessage {
-    pub client_addr: SocketAddr,
-    pub client_index: u16,
-    pub ip_list: [SocketAddr; MAX_NUMBER_OF_INTERNAL_IDS],
-    pub client_time: u64,
-    pub server_time: u64,
-}
-
-impl MessageRead for ConnectionRequestAcceptedMessage {
-    fn read_message(reader: &mut dyn DataRead) -> Result<Self> {
-        reader.read_u8_and_compare(MessageId::ConnectionRequestAccepted.into())?;
-        let client_addr = reader.read_socket_addr()?;
-        let client_index = reader.read_u16_be()?;
-        let mut ip_list = [SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 0); MAX_NUMBER_OF_INTERNAL_IDS];
-        for ip in ip_list.iter_mut() {
-            *ip = reader.read_socket_addr()?;
-        }
-        let client_time = reader.read_u64_be()?;
-        let server_time = reader.read_u64_be()?;
-        Ok(ConnectionRequestAcceptedMessage { client_addr, client_index, ip_list, client_time, server_time })
+    fn written_size(&self) -> usize {
+        let security_size = match self.proof_and_client_key {
+            Some((_, Some(_))) => 32 + 1 + 160,
+            Some((_, None)) => 32 + 1,
+            None => 0,
+        };
+        1 + 8 + 8 + 1 + security_size + self.password.len()
     }
 }
 
-impl MessageWrite for ConnectionRequestAcceptedMessage {
-    fn write_message(&self, writer: &mut dyn DataWrite) -> Result<()> {
-        writer.write_u8(MessageId::ConnectionRequestAccepted.into())?;
-        writer.write_socket_addr(&self.client_addr)?;
-        writer.write_u16_be(self.client_index)?;
-        for ip in self.ip_list.iter() {
-            writer.write_socket_addr(ip)?;
-        }
-        writer.write_u64_be(self.client_time)?;
-        writer.write_u64_be(self.server_time)?;
-        Ok(())
+raknet_message! {
+    #[derive(Debug)]
+    pub struct ConnectionRequestAcceptedMessage(MessageId::ConnectionRequestAccepted) {
+        pub client_addr: SocketAddr,
+        pub client_index: u16,
+        pub ip_list: [SocketAddr; MAX_NUMBER_OF_INTERNAL_IDS],
+        pub client_time: u64,
+        pub server_time: u64,
     }
 }
 
-#[derive(Debug)]
-pub struct NewIncomingConnectionMessage {
-    pub server_addr: SocketAddr,
-    pub client_ip_list: [SocketAddr; MAX_NUMBER_OF_INTERNAL_IDS],
-    pub send_ping_time: u64,
-    pub send_pong_time: u64,
-}
-
-impl MessageRead for NewIncomingConnectionMessage {
-    fn read_message(reader: &mut dyn DataRead) -> Result<Self> {
-        reader.read_u8_and_compare(MessageId::NewIncomingConnection.into())?;
-        let server_addr = reader.read_socket_addr()?;
-        let mut client_ip_list = [SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 0); MAX_NUMBER_OF_INTERNAL_IDS];
-        for ip in client_ip_list.iter_mut() {
-            *ip = reader.read_socket_addr()?;
-        }
-        let send_ping_time = reader.read_u64_be()?;
-        let send_pong_time = reader.read_u64_be()?;
-        Ok(NewIncomingConnectionMessage { server_addr, client_ip_list, send_ping_time, send_pong_time })
+raknet_message! {
+    #[derive(Debug)]
+    pub struct NewIncomingConnectionMessage(MessageId::NewIncomingConnection) {
+        pub server_addr: SocketAddr,
+        pub client_ip_list: [SocketAddr; MAX_NUMBER_OF_INTERNAL_IDS],
+        pub send_ping_time: u64,
+        pub send_pong_time: u64,
     }
 }
 
-impl MessageWrite for NewIncomingConnectionMessage {
-    fn write_message(&self, writer: &mut dyn DataWrite) -> Result<()> {
-        writer.write_u8(MessageId::NewIncomingConnection.into())?;
-        writer.write_socket_addr(&self.server_addr)?;
-        for ip in self.client_ip_list.iter() {
-            writer.write_socket_addr(ip)?;
-        }
-        writer.write_u64_be(self.send_ping_time)?;
-        writer.write_u64_be(self.send_pong_time)?;
-        Ok(())
+raknet_message! {
+    #[derive(Debug)]
+    pub struct InvalidPasswordMessage(MessageId::InvalidPassword) {
+        pub guid: u64,
     }
 }
 
-#[derive(Debug)]
-pub struct IncompatibleProtocolVersionMessage {
-    pub protocol_version: u8,
-    pub guid: u64,
+raknet_message! {
+    #[derive(Debug)]
+    pub struct IncompatibleProtocolVersionMessage(MessageId::IncompatibleProtocolVersion) {
+        pub protocol_version: u8,
+        offline_message_id,
+        pub guid: u64,
+    }
 }
 
 impl IncompatibleProtocolVersionMessage {
@@ -482,29 +626,9 @@ impl IncompatibleProtocolVersionMessage {
     }
 }
 
-impl MessageRead for IncompatibleProtocolVersionMessage {
-    fn read_message(reader: &mut dyn DataRead) -> Result<Self> {
-        reader.read_u8_and_compare(MessageId::IncompatibleProtocolVersion.into())?;
-        let protocol_version = reader.read_u8()?;
-        reader.read_bytes_and_compare(&OFFLINE_MESSAGE_ID).map_err(|_| ReadError::InvalidOfflineMessageId)?;
-        let guid = reader.read_u64_be()?;
-        Ok(IncompatibleProtocolVersionMessage { protocol_version, guid })
-    }
-}
-
-impl MessageWrite for IncompatibleProtocolVersionMessage {
-    fn write_message(&self, writer: &mut dyn DataWrite) -> Result<()> {
-        writer.write_u8(MessageId::IncompatibleProtocolVersion.into())?;
-        writer.write_u8(self.protocol_version)?;
-        writer.write_bytes(&OFFLINE_MESSAGE_ID)?;
-        writer.write_u64_be(self.guid)?;
-        Ok(())      
-    }
-}
-
 /// Error message used by `MessageId::NoFreeIncomingConnections`,
-/// `MessageId::ConnectionBanned`, `MessageId::AlreadyConnected` and
-/// `MessageId::IpRecentlyConnected`.
+/// `MessageId::ConnectionBanned`, `MessageId::AlreadyConnected`,
+/// `MessageId::IpRecentlyConnected` and `MessageId::ConnectionAttemptFailed`.
 #[derive(Debug)]
 pub struct ConnectErrorMessage {
     pub message_id: MessageId,
@@ -528,6 +652,7 @@ impl MessageRead for ConnectErrorMessage {
             Ok(MessageId::ConnectionBanned) => MessageId::ConnectionBanned,
             Ok(MessageId::AlreadyConnected) => MessageId::AlreadyConnected,
             Ok(MessageId::IpRecentlyConnected) => MessageId::IpRecentlyConnected,
+            Ok(MessageId::ConnectionAttemptFailed) => MessageId::ConnectionAttemptFailed,
             _ => return Err(Error::UnknownMessageId(message_id_byte)),
         };
         reader.read_bytes_and_compare(&OFFLINE_MESSAGE_ID).map_err(|_| ReadError::InvalidOfflineMessageId)?;
@@ -543,18 +668,28 @@ impl MessageWrite for ConnectErrorMessage {
         writer.write_u64_be(self.guid)?;
         Ok(())      
     }
+
+    fn written_size(&self) -> usize {
+        1 + OFFLINE_MESSAGE_ID.len() + 8
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::net::SocketAddr;
+    use std::{net::SocketAddr, time::SystemTime};
 
     use crate::{
+        constants::MAX_NUMBER_OF_INTERNAL_IDS,
         error::{Error, ReadError},
+        handoff_token::HandoffToken,
         message_ids::MessageId,
         messages::{
             ConnectErrorMessage,
+            ConnectionRequestAcceptedMessage,
+            ConnectionRequestMessage,
             IncompatibleProtocolVersionMessage,
+            InvalidPasswordMessage,
+            OutOfBandInternalMessage,
             UnconnectedPingMessage,
             UnconnectedPongMessage,
             OpenConnectionReply1Message,
@@ -972,6 +1107,31 @@ mod tests {
         assert_eq!(SocketAddr::from(([192, 168, 1, 248], 0x1234)), req2.binding_address);
         assert_eq!(0x123, req2.mtu);
         assert_eq!(0x123456789ABCDEF0, req2.guid);
+        assert_eq!(None, req2.handoff_token);
+    }
+
+    #[test]
+    fn write_and_read_open_connection_request_2_with_handoff_token() {
+        // Arrange
+        let addr = SocketAddr::from(([192, 168, 1, 248], 0x1234));
+        let handoff_token = HandoffToken::new(b"key", 0x12345678, addr.ip(), 42, SystemTime::now());
+        let req2 = OpenConnectionRequest2Message {
+            cookie_and_challenge: Some((0x11223344, None)),
+            binding_address: addr,
+            mtu: 1400,
+            guid: 0x12345678,
+            handoff_token: Some(handoff_token.clone()),
+        };
+        let mut buf = Vec::new();
+
+        // Act
+        req2.write_message(&mut buf).expect("Could not write Open Connection Request 2");
+        let read_req2 = OpenConnectionRequest2Message::read_message_with_security(&mut DataReader::new(&buf)).expect("Failed to read Open Connection Request 2");
+
+        // Assert
+        assert_eq!(1 + 16 + 4 + 1 + 7 + 2 + 8 + 84, buf.len());
+        assert_eq!(0x12345678, read_req2.guid);
+        assert_eq!(Some(handoff_token), read_req2.handoff_token);
     }
 
     #[test]
@@ -1032,6 +1192,25 @@ mod tests {
         assert_eq!(0x123456789ABCDEF0, req2.guid);
     }    
 
+    #[test]
+    fn read_connection_request_with_too_long_password() {
+        // Arrange
+        let mut buf = vec![
+            0x09, // Message ID: Connection Request
+            0x12, 0x34, 0x56, 0x78, 0x9A, 0xBC, 0xDE, 0xF0, // GUID: 0x123456789ABCDEF0
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, // Time: 1
+            0x00, // Do security: false
+        ];
+        buf.extend_from_slice(&[0x01; 256]); // Password: 256 bytes
+        let mut reader = DataReader::new(&buf);
+
+        // Act
+        let result = ConnectionRequestMessage::read_message(&mut reader);
+
+        // Assert
+        assert!(matches!(result, Err(Error::ReadError(ReadError::TooLongTrailingData(256)))));
+    }
+
     #[test]
     fn read_open_connection_reply_2_no_security() {
         // Arrange
@@ -1381,5 +1560,130 @@ mod tests {
             0x88, 0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11, // Guid: 0x8877665544332211
         ],
         buf);
-    }    
+    }
+
+    #[test]
+    fn write_invalid_password() {
+        // Arrange
+        let message = InvalidPasswordMessage {
+            guid: 0x8877665544332211,
+        };
+        let mut buf = Vec::new();
+
+        // Act
+        message.write_message(&mut buf).expect("Could not write message");
+
+        // Assert
+        assert_eq!(vec![
+            0x18, // Message ID: Invalid Password
+            0x88, 0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11, // Guid: 0x8877665544332211
+        ],
+        buf);
+    }
+
+    #[test]
+    fn read_invalid_password() {
+        // Arrange
+        let buf = vec![
+            0x18, // Message ID: Invalid Password
+            0x88, 0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11, // Guid: 0x8877665544332211
+        ];
+        let mut reader = DataReader::new(&buf);
+
+        // Act
+        let message = InvalidPasswordMessage::read_message(&mut reader).expect("Could not read message");
+
+        // Assert
+        assert_eq!(0x8877665544332211, message.guid);
+    }
+
+    #[test]
+    fn read_out_of_band_internal() {
+        // Arrange
+        let buf = vec![
+            0x0D, // Message ID: Out Of Band Internal
+            0x88, 0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11, // Guid: 0x8877665544332211
+            0x00, 0xFF, 0xFF, 0x00, 0xFE, 0xFE, 0xFE, 0xFE, 0xFD, 0xFD, 0xFD, 0xFD, 0x12, 0x34, 0x56, 0x78, // Offline message ID
+            0x01, 0x02, // Data
+        ];
+        let mut reader = DataReader::new(&buf);
+
+        // Act
+        let message = OutOfBandInternalMessage::read_message(&mut reader).expect("Could not read message");
+
+        // Assert
+        assert_eq!(0x8877665544332211, message.guid);
+        assert_eq!(vec![0x01, 0x02], message.data);
+    }
+
+    #[test]
+    fn read_out_of_band_internal_invalid_offline_message_id() {
+        // Arrange
+        let buf = vec![
+            0x0D, // Message ID: Out Of Band Internal
+            0x88, 0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11, // Guid: 0x8877665544332211
+            0xAA, 0xAA, 0xFF, 0x00, 0xFE, 0xFE, 0xFE, 0xFE, 0xFD, 0xFD, 0xFD, 0xFD, 0x12, 0x34, 0x56, 0x78, // INVALID Offline message ID
+            0x01, 0x02, // Data
+        ];
+        let mut reader = DataReader::new(&buf);
+
+        // Act
+        let result = OutOfBandInternalMessage::read_message(&mut reader);
+
+        // Assert
+        assert!(matches!(result, Err(Error::ReadError(ReadError::InvalidOfflineMessageId))));
+    }
+
+    #[test]
+    fn write_out_of_band_internal() {
+        // Arrange
+        let message = OutOfBandInternalMessage {
+            guid: 0x8877665544332211,
+            data: vec![0x01, 0x02],
+        };
+        let mut buf = Vec::new();
+
+        // Act
+        message.write_message(&mut buf).expect("Could not write message");
+
+        // Assert
+        assert_eq!(vec![
+            0x0D, // Message ID: Out Of Band Internal
+            0x88, 0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11, // Guid: 0x8877665544332211
+            0x00, 0xFF, 0xFF, 0x00, 0xFE, 0xFE, 0xFE, 0xFE, 0xFD, 0xFD, 0xFD, 0xFD, 0x12, 0x34, 0x56, 0x78, // Offline message ID
+            0x01, 0x02, // Data
+        ],
+        buf);
+    }
+
+    #[test]
+    fn written_size_is_number_of_bytes_written() {
+        // Arrange
+        let ipv4_addr = "192.168.1.2:19132".parse::<SocketAddr>().expect("Could not parse address");
+        let ipv6_addr = "[fe80::1]:19132".parse::<SocketAddr>().expect("Could not parse address");
+        let messages: Vec<Box<dyn MessageWrite>> = vec![
+            Box::new(UnconnectedPingMessage { message_id: MessageId::UnconnectedPing, time: 1, client_guid: 2 }),
+            Box::new(UnconnectedPongMessage::new(1, 2, vec![0x01, 0x02, 0x03])),
+            Box::new(OpenConnectionRequest1Message { protocol_version: 10, padding_length: 20 }),
+            Box::new(OpenConnectionReply1Message::new(1, Some((2, [0x03; 64])), 1400)),
+            Box::new(OpenConnectionRequest2Message { cookie_and_challenge: Some((1, Some([0x02; 64]))), binding_address: ipv6_addr, mtu: 1400, guid: 3, handoff_token: None }),
+            Box::new(OpenConnectionRequest2Message { cookie_and_challenge: None, binding_address: ipv4_addr, mtu: 1400, guid: 3,
+                handoff_token: Some(HandoffToken::new(b"key", 3, ipv4_addr.ip(), 4, SystemTime::now())) }),
+            Box::new(OpenConnectionReply2Message::new(1, ipv4_addr, 1400, Some([0x02; 128]))),
+            Box::new(ConnectionRequestMessage { guid: 1, time: 2, proof_and_client_key: Some(([0x03; 32], None)), password: Box::new([0x04; 5]) }),
+            Box::new(ConnectionRequestAcceptedMessage { client_addr: ipv6_addr, client_index: 1, ip_list: [ipv4_addr; MAX_NUMBER_OF_INTERNAL_IDS], client_time: 2, server_time: 3 }),
+            Box::new(IncompatibleProtocolVersionMessage::new(10, 1)),
+            Box::new(ConnectErrorMessage::new(MessageId::ConnectionBanned, 1)),
+        ];
+
+        for message in messages {
+            let mut buf = Vec::new();
+
+            // Act
+            message.write_message(&mut buf).expect("Could not write message");
+
+            // Assert
+            assert_eq!(buf.len(), message.written_size());
+        }
+    }
 }
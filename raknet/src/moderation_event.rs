@@ -0,0 +1,111 @@
+use std::{fmt, time::Duration};
+
+use crate::{ban_list::IpRange, connection_lost_reason::ConnectionLostReason, inbound_limiter::InboundLimit};
+
+/// A moderation action taken by a peer, reported in a `PeerEvent::ModerationAction`
+/// so that an admin interface can present and audit the actions.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ModerationAction {
+    /// The range of IP addresses was banned for `duration` or forever if None.
+    Banned { range: IpRange, duration: Option<Duration> },
+    /// The ban of the range of IP addresses was removed.
+    Unbanned { range: IpRange },
+    /// A connection request was answered with `MessageId::ConnectionBanned` or
+    /// `MessageId::NoFreeIncomingConnections` or ignored. The GUID of the remote peer is only
+    /// known if the request was an Open Connection Request 2.
+    ConnectionRejected { guid: Option<u64> },
+    /// The connection to the remote peer with the GUID was dropped.
+    Kicked { guid: u64 },
+}
+
+impl ModerationAction {
+    /// Returns a short code for the kind of action that does not change between
+    /// versions, e.g. for filtering actions in an admin interface.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ModerationAction::Banned { .. } => "ban",
+            ModerationAction::Unbanned { .. } => "unban",
+            ModerationAction::ConnectionRejected { .. } => "reject",
+            ModerationAction::Kicked { .. } => "kick",
+        }
+    }
+}
+
+impl fmt::Display for ModerationAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ModerationAction::Banned { range, duration: Some(duration) } => write!(f, "Banned {} for {:?}", range, duration),
+            ModerationAction::Banned { range, duration: None } => write!(f, "Banned {}", range),
+            ModerationAction::Unbanned { range } => write!(f, "Unbanned {}", range),
+            ModerationAction::ConnectionRejected { guid: Some(guid) } => write!(f, "Rejected connection from GUID {}", guid),
+            ModerationAction::ConnectionRejected { guid: None } => write!(f, "Rejected connection"),
+            ModerationAction::Kicked { guid } => write!(f, "Kicked GUID {}", guid),
+        }
+    }
+}
+
+/// The rule that triggered a `ModerationAction`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ModerationRule {
+    /// The action was requested with `Peer::ban_address` or `Peer::unban_address`.
+    Manual,
+    /// The IP address of the remote peer is banned.
+    BanList,
+    /// The remote peer is not in `Config::allow_list`.
+    AllowList,
+    /// The admission policy set with `Peer::set_admission_policy` rejected or ignored the remote peer.
+    AdmissionPolicy,
+    /// More connections than `Config::max_new_connections_per_second` were requested.
+    HandshakeRateLimit,
+    /// The remote peer sent an invalid split packet.
+    InvalidSplitPacket,
+    /// The remote peer sent something other than a connection request before its
+    /// connection was verified, see `Config::garbage_penalty_in_ms`.
+    Garbage,
+    /// The remote peer exceeded an inbound limit of its connection, see `Config::inbound_limit_action`.
+    InboundLimit(InboundLimit),
+}
+
+impl ModerationRule {
+    /// Returns the rule that drops connections lost for the reason, if any.
+    pub(crate) fn from_lost_reason(reason: ConnectionLostReason) -> Option<ModerationRule> {
+        match reason {
+            ConnectionLostReason::InvalidSplitPacket => Some(ModerationRule::InvalidSplitPacket),
+            ConnectionLostReason::Garbage => Some(ModerationRule::Garbage),
+            ConnectionLostReason::InboundLimitExceeded { limit } => Some(ModerationRule::InboundLimit(limit)),
+            _ => None,
+        }
+    }
+
+    /// Returns a short code for the rule that does not change between
+    /// versions, e.g. for filtering actions in an admin interface.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ModerationRule::Manual => "manual",
+            ModerationRule::BanList => "ban_list",
+            ModerationRule::AllowList => "allow_list",
+            ModerationRule::AdmissionPolicy => "admission_policy",
+            ModerationRule::HandshakeRateLimit => "handshake_rate_limit",
+            ModerationRule::InvalidSplitPacket => "invalid_split_packet",
+            ModerationRule::Garbage => "garbage",
+            ModerationRule::InboundLimit(InboundLimit::PacketRate) => "inbound_packet_rate",
+            ModerationRule::InboundLimit(InboundLimit::ByteRate) => "inbound_byte_rate",
+            ModerationRule::InboundLimit(InboundLimit::PayloadSize) => "inbound_payload_size",
+        }
+    }
+}
+
+impl fmt::Display for ModerationRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ModerationRule::Manual => write!(f, "Manual"),
+            ModerationRule::BanList => write!(f, "Banned address"),
+            ModerationRule::AllowList => write!(f, "Not in allow list"),
+            ModerationRule::AdmissionPolicy => write!(f, "Rejected by admission policy"),
+            ModerationRule::HandshakeRateLimit => write!(f, "New connection rate limit exceeded"),
+            ModerationRule::InvalidSplitPacket => write!(f, "Invalid split packet"),
+            ModerationRule::Garbage => write!(f, "Garbage from unverified sender"),
+            ModerationRule::InboundLimit(limit) => write!(f, "Inbound limit of {} exceeded", limit),
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use crate::constants::{MTU_SIZES, UDP_HEADER_SIZE};
+
+/// The number of large datagrams that must be lost in a row before
+/// the MTU is considered to be too large for the network path.
+const LOST_LARGE_DATAGRAMS_THRESHOLD: u32 = 3;
+
+/// Returns the next smaller standard MTU size or None if there is none.
+pub fn next_smaller_mtu(mtu: u16) -> Option<u16> {
+    MTU_SIZES.iter().copied().find(|mtu_size| *mtu_size < mtu)
+}
+
+/// Detects an MTU "black hole", i.e. a network path that drops datagrams
+/// that are close to the negotiated MTU while smaller datagrams get through.
+///
+/// A datagram is considered large if it does not fit the next smaller MTU size.
+/// A black hole is detected when several large datagrams in a row have been
+/// lost (NACK:ed or timed out) while a small datagram has been acknowledged.
+#[derive(Debug)]
+pub struct MtuBlackholeDetector {
+    large_datagram_size: Option<usize>,
+    lost_large_datagrams: u32,
+    is_small_datagram_acked: bool,
+}
+
+impl MtuBlackholeDetector {
+    pub fn new(mtu: u16) -> Self {
+        MtuBlackholeDetector {
+            large_datagram_size: next_smaller_mtu(mtu).map(|mtu| (mtu - UDP_HEADER_SIZE) as usize + 1),
+            lost_large_datagrams: 0,
+            is_small_datagram_acked: false,
+        }
+    }
+
+    /// Registers that a datagram of `size` bytes has been acknowledged.
+    pub fn on_datagram_acked(&mut self, size: usize) {
+        if self.is_large(size) {
+            self.lost_large_datagrams = 0;
+            self.is_small_datagram_acked = false;
+        } else {
+            self.is_small_datagram_acked = true;
+        }
+    }
+
+    /// Registers that a datagram of `size` bytes has been NACK:ed or timed out.
+    pub fn on_datagram_lost(&mut self, size: usize) {
+        if self.is_large(size) {
+            self.lost_large_datagrams += 1;
+        }
+    }
+
+    /// Returns true if the MTU appears to be too large for the network path.
+    pub fn is_blackhole_detected(&self) -> bool {
+        self.lost_large_datagrams >= LOST_LARGE_DATAGRAMS_THRESHOLD && self.is_small_datagram_acked
+    }
+
+    fn is_large(&self, size: usize) -> bool {
+        matches!(self.large_datagram_size, Some(large_datagram_size) if size >= large_datagram_size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MtuBlackholeDetector, next_smaller_mtu};
+
+    #[test]
+    fn next_smaller_mtu_steps_down_standard_sizes() {
+        // Act/Assert
+        assert_eq!(Some(1200), next_smaller_mtu(1492));
+        assert_eq!(Some(1200), next_smaller_mtu(1400));
+        assert_eq!(Some(576), next_smaller_mtu(1200));
+        assert_eq!(None, next_smaller_mtu(576));
+    }
+
+    #[test]
+    fn detected_when_large_datagrams_lost_and_small_acked() {
+        // Arrange
+        let mut detector = MtuBlackholeDetector::new(1492);
+
+        // Act
+        detector.on_datagram_acked(100);
+        detector.on_datagram_lost(1400);
+        detector.on_datagram_lost(1400);
+        let detected_after_two_losses = detector.is_blackhole_detected();
+        detector.on_datagram_lost(1400);
+
+        // Assert
+        assert!(!detected_after_two_losses);
+        assert!(detector.is_blackhole_detected());
+    }
+
+    #[test]
+    fn not_detected_when_no_small_datagram_acked() {
+        // Arrange
+        let mut detector = MtuBlackholeDetector::new(1492);
+
+        // Act
+        for _ in 0..5 {
+            detector.on_datagram_lost(1400);
+            detector.on_datagram_lost(100);
+        }
+
+        // Assert
+        assert!(!detector.is_blackhole_detected());
+    }
+
+    #[test]
+    fn not_detected_when_large_datagram_acked() {
+        // Arrange
+        let mut detector = MtuBlackholeDetector::new(1492);
+        detector.on_datagram_acked(100);
+        detector.on_datagram_lost(1400);
+        detector.on_datagram_lost(1400);
+
+        // Act
+        detector.on_datagram_acked(1400);
+        detector.on_datagram_lost(1400);
+
+        // Assert
+        assert!(!detector.is_blackhole_detected());
+    }
+
+    #[test]
+    fn not_detected_for_smallest_mtu() {
+        // Arrange
+        let mut detector = MtuBlackholeDetector::new(576);
+        detector.on_datagram_acked(100);
+
+        // Act
+        for _ in 0..5 {
+            detector.on_datagram_lost(548);
+        }
+
+        // Assert
+        assert!(!detector.is_blackhole_detected());
+    }
+}
@@ -0,0 +1,255 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    net::SocketAddr,
+};
+
+use crate::{socket::{self, DatagramSocket}, SocketOptions};
+
+/// The maximum number of remembered remote addresses without a connection. The oldest
+/// address is forgotten when there are more, which only affects replies to it that are
+/// not sent right after its datagram was received.
+const MAX_RECENT_ROUTES: usize = 4096;
+
+/// A `DatagramSocket` made of several sockets, e.g. bound to different ports,
+/// so that one peer can accept connections on all of them.
+///
+/// Datagrams are received from all sockets. Datagrams to a connected remote peer are sent
+/// out the socket the connection was created on and replies to unconnected remote peers,
+/// e.g. pongs, out the socket that last received a datagram from them. Datagrams to other
+/// addresses are sent out the first socket. The socket of a connection is kept until the
+/// connection is dropped, so datagrams with a spoofed sender address cannot move it.
+///
+/// The sockets may be of different address families. Datagrams are sent to the address
+/// in the form of the family of the socket they are sent out.
+pub struct MultiSocket<T: DatagramSocket> {
+    sockets: Vec<T>,
+    /// Whether the local address of each socket is an IPv6 address.
+    is_ipv6_sockets: Vec<bool>,
+    next_receive_index: usize,
+    /// The index of the socket that each connection was created on. The routes are
+    /// keyed by the addresses with IPv4-mapped IPv6 addresses as IPv4 addresses.
+    routes: HashMap<SocketAddr, usize>,
+    /// The index of the socket that last received a datagram from each unconnected address.
+    recent_routes: HashMap<SocketAddr, usize>,
+    /// The addresses of `recent_routes` from the oldest to the newest.
+    recent_addrs: VecDeque<SocketAddr>,
+}
+
+impl<T: DatagramSocket> MultiSocket<T> {
+    /// Creates a socket that uses all the specified sockets. Returns None if there are none.
+    pub fn new(sockets: Vec<T>) -> Option<Self> {
+        if sockets.is_empty() {
+            return None;
+        }
+        Some(MultiSocket {
+            is_ipv6_sockets: sockets.iter().map(|socket| socket.local_addr().is_ok_and(|addr| addr.is_ipv6())).collect(),
+            sockets,
+            next_receive_index: 0,
+            routes: HashMap::new(),
+            recent_routes: HashMap::new(),
+            recent_addrs: VecDeque::new(),
+        })
+    }
+
+    /// Returns the index of the socket used for sending datagrams to the address.
+    pub fn socket_index(&self, addr: SocketAddr) -> usize {
+        let addr = socket::to_socket_family(addr, false);
+        self.routes.get(&addr)
+            .or_else(|| self.recent_routes.get(&addr))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Remembers the socket that received a datagram from an unconnected address.
+    fn add_recent_route(&mut self, addr: SocketAddr, index: usize) {
+        if self.recent_routes.insert(addr, index).is_none() {
+            self.recent_addrs.push_back(addr);
+            if self.recent_addrs.len() > MAX_RECENT_ROUTES {
+                if let Some(oldest_addr) = self.recent_addrs.pop_front() {
+                    self.recent_routes.remove(&oldest_addr);
+                }
+            }
+        }
+    }
+}
+
+impl<T: DatagramSocket> DatagramSocket for MultiSocket<T> {
+    fn receive_datagram<'a>(&mut self, buf: &'a mut [u8]) -> io::Result<(&'a [u8], SocketAddr)> {
+        // Take turns between the sockets so that a busy socket does not starve the others
+        for _ in 0..self.sockets.len() {
+            let index = self.next_receive_index;
+            self.next_receive_index = (index + 1) % self.sockets.len();
+            match self.sockets[index].receive_datagram(buf).map(|(payload, addr)| (payload.len(), addr)) {
+                Ok((len, addr)) => {
+                    let route_addr = socket::to_socket_family(addr, false);
+                    if !self.routes.contains_key(&route_addr) {
+                        self.add_recent_route(route_addr, index);
+                    }
+                    return Ok((&buf[..len], addr));
+                },
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {},
+                Err(err) => return Err(err),
+            }
+        }
+        Err(io::ErrorKind::WouldBlock.into())
+    }
+
+    fn send_datagram(&mut self, payload: &[u8], addr: SocketAddr) -> io::Result<usize> {
+        let index = self.socket_index(addr);
+        self.sockets[index].send_datagram(payload, socket::to_socket_family(addr, self.is_ipv6_sockets[index]))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.sockets.iter_mut().try_for_each(|socket| socket.flush())
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.sockets[0].local_addr()
+    }
+
+    fn on_connection_created(&mut self, addr: SocketAddr) {
+        let index = self.socket_index(addr);
+        self.routes.insert(socket::to_socket_family(addr, false), index);
+    }
+
+    fn on_connection_dropped(&mut self, addr: SocketAddr) {
+        self.routes.remove(&socket::to_socket_family(addr, false));
+    }
+
+    fn local_addrs(&self) -> io::Result<Vec<SocketAddr>> {
+        self.sockets.iter().map(|socket| socket.local_addr()).collect()
+    }
+
+    fn set_options(&mut self, options: &SocketOptions) -> io::Result<()> {
+        self.sockets.iter_mut().try_for_each(|socket| socket.set_options(options))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use crate::socket::{DatagramSocket, FakeDatagramSocket};
+    use super::{MultiSocket, MAX_RECENT_ROUTES};
+
+    fn addr(addr: &str) -> SocketAddr {
+        addr.parse().expect("Could not create address")
+    }
+
+    #[test]
+    fn datagrams_are_received_from_all_sockets() {
+        // Arrange
+        let first_socket = FakeDatagramSocket::new(addr("127.0.0.1:19132"));
+        let second_socket = FakeDatagramSocket::new(addr("127.0.0.1:19133"));
+        second_socket.get_datagram_sender().send((vec![1, 2, 3], addr("192.168.1.1:1234"))).expect("Could not send datagram");
+        let mut socket = MultiSocket::new(vec![first_socket, second_socket]).expect("No sockets");
+        let mut buf = [0u8; 16];
+
+        // Act
+        let (payload, remote_addr) = socket.receive_datagram(&mut buf).expect("Datagram not received");
+
+        // Assert
+        assert_eq!(&[1, 2, 3], payload);
+        assert_eq!(addr("192.168.1.1:1234"), remote_addr);
+    }
+
+    #[test]
+    fn replies_are_sent_out_the_receiving_socket() {
+        // Arrange
+        let first_socket = FakeDatagramSocket::new(addr("127.0.0.1:19132"));
+        let second_socket = FakeDatagramSocket::new(addr("127.0.0.1:19133"));
+        let first_outgoing = first_socket.get_datagram_receiver();
+        let second_outgoing = second_socket.get_datagram_receiver();
+        second_socket.get_datagram_sender().send((vec![1], addr("192.168.1.1:1234"))).expect("Could not send datagram");
+        let mut socket = MultiSocket::new(vec![first_socket, second_socket]).expect("No sockets");
+        let mut buf = [0u8; 16];
+        socket.receive_datagram(&mut buf).expect("Datagram not received");
+
+        // Act
+        socket.send_datagram(&[2], addr("192.168.1.1:1234")).expect("Could not send datagram");
+        socket.send_datagram(&[3], addr("192.168.1.2:1234")).expect("Could not send datagram");
+
+        // Assert
+        assert_eq!(1, socket.socket_index(addr("192.168.1.1:1234")));
+        assert_eq!(Ok((vec![2], addr("192.168.1.1:1234"))), second_outgoing.try_recv());
+        assert_eq!(Ok((vec![3], addr("192.168.1.2:1234"))), first_outgoing.try_recv());
+        assert!(second_outgoing.try_recv().is_err());
+    }
+
+    #[test]
+    fn datagrams_are_sent_in_the_family_of_the_socket() {
+        // Arrange
+        let ipv4_socket = FakeDatagramSocket::new(addr("127.0.0.1:19132"));
+        let ipv6_socket = FakeDatagramSocket::new(addr("[::]:19133"));
+        let ipv4_outgoing = ipv4_socket.get_datagram_receiver();
+        let ipv6_outgoing = ipv6_socket.get_datagram_receiver();
+        ipv6_socket.get_datagram_sender().send((vec![1], addr("[::ffff:192.168.1.1]:1234"))).expect("Could not send datagram");
+        let mut socket = MultiSocket::new(vec![ipv4_socket, ipv6_socket]).expect("No sockets");
+        let mut buf = [0u8; 16];
+        socket.receive_datagram(&mut buf).expect("Datagram not received");
+
+        // Act
+        socket.send_datagram(&[2], addr("192.168.1.1:1234")).expect("Could not send datagram");
+        socket.send_datagram(&[3], addr("[::ffff:192.168.1.2]:1234")).expect("Could not send datagram");
+
+        // Assert
+        assert_eq!(Ok((vec![2], addr("[::ffff:192.168.1.1]:1234"))), ipv6_outgoing.try_recv());
+        assert_eq!(Ok((vec![3], addr("192.168.1.2:1234"))), ipv4_outgoing.try_recv());
+    }
+
+    #[test]
+    fn connection_keeps_its_socket_until_dropped() {
+        // Arrange
+        let first_socket = FakeDatagramSocket::new(addr("127.0.0.1:19132"));
+        let second_socket = FakeDatagramSocket::new(addr("127.0.0.1:19133"));
+        let first_incoming = first_socket.get_datagram_sender();
+        second_socket.get_datagram_sender().send((vec![1], addr("192.168.1.1:1234"))).expect("Could not send datagram");
+        let mut socket = MultiSocket::new(vec![first_socket, second_socket]).expect("No sockets");
+        let mut buf = [0u8; 16];
+        socket.receive_datagram(&mut buf).expect("Datagram not received");
+        socket.on_connection_created(addr("192.168.1.1:1234"));
+
+        // Act
+        first_incoming.send((vec![2], addr("192.168.1.1:1234"))).expect("Could not send datagram");
+        socket.receive_datagram(&mut buf).expect("Datagram not received");
+        let index_while_connected = socket.socket_index(addr("192.168.1.1:1234"));
+        socket.on_connection_dropped(addr("192.168.1.1:1234"));
+        first_incoming.send((vec![3], addr("192.168.1.1:1234"))).expect("Could not send datagram");
+        socket.receive_datagram(&mut buf).expect("Datagram not received");
+        let index_after_drop = socket.socket_index(addr("192.168.1.1:1234"));
+
+        // Assert
+        assert_eq!(1, index_while_connected);
+        assert_eq!(0, index_after_drop);
+    }
+
+    #[test]
+    fn oldest_recent_route_is_forgotten() {
+        // Arrange
+        let first_socket = FakeDatagramSocket::new(addr("127.0.0.1:19132"));
+        let second_socket = FakeDatagramSocket::new(addr("127.0.0.1:19133"));
+        let second_incoming = second_socket.get_datagram_sender();
+        let mut socket = MultiSocket::new(vec![first_socket, second_socket]).expect("No sockets");
+        let mut buf = [0u8; 16];
+        for port in 0..MAX_RECENT_ROUTES as u16 {
+            second_incoming.send((vec![1], SocketAddr::from(([192, 168, 1, 1], port)))).expect("Could not send datagram");
+            socket.receive_datagram(&mut buf).expect("Datagram not received");
+        }
+
+        // Act
+        second_incoming.send((vec![1], addr("192.168.1.2:1234"))).expect("Could not send datagram");
+        socket.receive_datagram(&mut buf).expect("Datagram not received");
+
+        // Assert
+        assert_eq!(0, socket.socket_index(addr("192.168.1.1:0")));
+        assert_eq!(1, socket.socket_index(addr("192.168.1.1:1")));
+        assert_eq!(1, socket.socket_index(addr("192.168.1.2:1234")));
+        assert_eq!(MAX_RECENT_ROUTES, socket.recent_routes.len());
+    }
+
+    #[test]
+    fn multi_socket_without_sockets_is_not_created() {
+        // Act/Assert
+        assert!(MultiSocket::<FakeDatagramSocket>::new(Vec::new()).is_none());
+    }
+}
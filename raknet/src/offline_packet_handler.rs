@@ -1,18 +1,34 @@
 use std::{
     collections::HashMap,
     convert::TryFrom,
-    net::SocketAddr,
-    time::Instant,
+    net::{IpAddr, SocketAddr},
+    time::{Duration, Instant},
 };
 
-use log::{debug, error};
-
 use crate::{
+    admission_policy::{Admission, AdmissionPolicy},
+    allow_list::AllowList,
+    ban_list::{BanList, IpRange},
+    connection_lost_reason::ConnectionLostReason,
+    greylist::Greylist,
+    handoff_token::HandoffToken,
+    handshake_rate_limiter::HandshakeRateLimiter,
     communicator::Communicator,
     config::Config,
+    connect_options::ConnectOptions,
     connection::{Connection, ConnectionState},
-    constants::{RAKNET_PROTOCOL_VERSION, UDP_HEADER_SIZE, MAXIMUM_MTU_SIZE},
+    connection_attempt::ConnectionAttempt,
+    connection_attempt_failed_reason::ConnectionAttemptFailedReason,
+    constants::{INTEGRITY_TAG_SIZE, MAX_OUT_OF_BAND_DATA_LENGTH, MAX_PROOF_OF_WORK_DIFFICULTY, PUZZLE_HASHES_PER_UPDATE, RAKNET_PROTOCOL_VERSION, UDP_HEADER_SIZE},
+    logging::{debug, error},
     message_ids::MessageId,
+    offline_rate_limiter::{OfflineRateLimiter, RateLimit},
+    Error,
+    ModerationAction,
+    ModerationRule,
+    PeerEvent,
+    Result,
+    SecurityEventKind,
     messages::{
         ConnectErrorMessage,
         IncompatibleProtocolVersionMessage,
@@ -20,25 +36,61 @@ use crate::{
         OpenConnectionRequest2Message,
         OpenConnectionReply1Message,
         OpenConnectionReply2Message,
+        OutOfBandInternalMessage,
         UnconnectedPingMessage,
         UnconnectedPongMessage,
     },
     reader::{MessageRead, DataReader},
+    security,
     socket::DatagramSocket,
     utils,
-    writer::MessageWrite,
+    writer::{DataWriter, MessageWrite},
 };
+#[cfg(feature = "rand")]
+use crate::security::{ClientHandshake, CookieGenerator};
+
+/// A function returning the response to an offline ping from an address,
+/// see `Peer::set_ping_response_provider`.
+pub type PingResponseProvider = Box<dyn Fn(SocketAddr) -> Vec<u8> + Send>;
 
 pub struct  OfflinePacketHandler {   
     ping_response: Vec<u8>,
+    ping_response_provider: Option<PingResponseProvider>,
     peer_creation_time: Instant,
+    connection_attempts: HashMap<SocketAddr, ConnectionAttempt>,
+    #[cfg(feature = "rand")]
+    cookie_generator: CookieGenerator,
+    rate_limiter: OfflineRateLimiter,
+    handshake_rate_limiter: HandshakeRateLimiter,
+    ban_list: BanList,
+    greylist: Greylist,
+    /// The remote peers that may connect in allow-list mode or None if anyone may connect.
+    allow_list: Option<AllowList>,
+    admission_policy: Option<Box<dyn AdmissionPolicy>>,
+    /// The time of the latest incoming connection from each IP address.
+    recent_connections: HashMap<IpAddr, Instant>,
+    /// The nonces of the handoff tokens that incoming connections have been created
+    /// with and the time the tokens expire, which prevents the tokens from being reused.
+    used_handoff_nonces: HashMap<u64, Instant>,
 }
 
 impl OfflinePacketHandler {
-    pub fn new() -> OfflinePacketHandler {
+    pub fn new(time: Instant, allow_list: Option<AllowList>) -> OfflinePacketHandler {
         OfflinePacketHandler {
             ping_response: Vec::new(),
-            peer_creation_time: Instant::now(),
+            ping_response_provider: None,
+            peer_creation_time: time,
+            connection_attempts: HashMap::new(),
+            #[cfg(feature = "rand")]
+            cookie_generator: CookieGenerator::new(),
+            rate_limiter: OfflineRateLimiter::new(),
+            handshake_rate_limiter: HandshakeRateLimiter::new(),
+            ban_list: BanList::new(),
+            greylist: Greylist::new(),
+            allow_list,
+            admission_policy: None,
+            recent_connections: HashMap::new(),
+            used_handoff_nonces: HashMap::new(),
         }
     }
 
@@ -49,29 +101,217 @@ impl OfflinePacketHandler {
         let mut ping_response = ping_response;
         ping_response.truncate(399);
         self.ping_response = ping_response;
+        self.ping_response_provider = None;
+    }
+
+    /// Sets a function that creates the response to each offline ping packet
+    /// instead of returning a fixed response. Responses longer than 399 bytes
+    /// will be truncated.
+    pub fn set_ping_response_provider(&mut self, provider: PingResponseProvider) {
+        self.ping_response_provider = Some(provider);
+    }
+
+    /// Sets the policy deciding which remote peers may connect or removes it if None.
+    pub fn set_admission_policy(&mut self, admission_policy: Option<Box<dyn AdmissionPolicy>>) {
+        self.admission_policy = admission_policy;
+    }
+
+    /// Sends an unconnected ping to the address. The pong is reported with `PeerEvent::UnconnectedPong`.
+    pub fn ping(&self, time: Instant, addr: SocketAddr, communicator: &mut Communicator<impl DatagramSocket>) {
+        debug!("Sending Unconnected Ping to {}", addr);
+        let ping = UnconnectedPingMessage {
+            message_id: MessageId::UnconnectedPing,
+            time: time.saturating_duration_since(self.peer_creation_time).as_millis() as u64,
+            client_guid: communicator.config().guid,
+        };
+        Self::send_message(&ping, addr, communicator);
+    }
+
+    /// Sends an out-of-band message to the address. The message is reported
+    /// to the remote peer with `PeerEvent::OutOfBandMessage`.
+    pub fn send_out_of_band(&self, addr: SocketAddr, data: &[u8], communicator: &mut Communicator<impl DatagramSocket>) -> Result<()> {
+        if data.len() > MAX_OUT_OF_BAND_DATA_LENGTH {
+            return Err(Error::PacketTooLarge { size: data.len(), max_size: MAX_OUT_OF_BAND_DATA_LENGTH });
+        }
+        debug!("Sending Out Of Band message to {}", addr);
+        let message = OutOfBandInternalMessage { guid: communicator.config().guid, data: data.to_vec() };
+        Self::send_message(&message, addr, communicator);
+        Ok(())
+    }
+
+    /// Bans a range of IP addresses until `expiry_time` or forever if `expiry_time` is None.
+    pub fn ban(&mut self, range: IpRange, expiry_time: Option<Instant>) {
+        self.ban_list.ban(range, expiry_time);
+    }
+
+    /// Removes the ban of a range of IP addresses. Returns false if the range was not banned.
+    pub fn unban(&mut self, range: IpRange) -> bool {
+        self.ban_list.unban(range)
+    }
+
+    /// Returns the banned ranges of IP addresses with their expiry times.
+    pub fn bans(&self) -> &[(IpRange, Option<Instant>)] {
+        self.ban_list.bans()
+    }
+
+    /// Greylists the IP address of a remote peer whose connection was dropped for abusing
+    /// the protocol for `Config::greylist_duration_in_ms` and reports it to the user.
+    pub fn greylist(&mut self, time: Instant, ip: IpAddr, reason: ConnectionLostReason, communicator: &mut Communicator<impl DatagramSocket>) {
+        let duration = Duration::from_millis(communicator.config().greylist_duration_in_ms as u64);
+        if duration == Duration::ZERO {
+            return;
+        }
+        if self.greylist.add(ip, time + duration) {
+            debug!("Greylisting {} for {:?}: {}", ip, duration, reason);
+            communicator.send_event(PeerEvent::AddressGreylisted { ip, reason, duration });
+        }
+    }
+
+    /// Enables allow-list mode with the allow list or disables it if None.
+    pub fn set_allow_list(&mut self, allow_list: Option<AllowList>) {
+        self.allow_list = allow_list;
+    }
+
+    /// Returns the allow list or None if allow-list mode is disabled.
+    pub fn allow_list_mut(&mut self) -> Option<&mut AllowList> {
+        self.allow_list.as_mut()
+    }
+
+    /// Starts an outgoing connection attempt to the specified address. If the attempt fails
+    /// the fallback addresses are tried in order before the attempt is reported as failed.
+    /// Returns false if a connection or connection attempt to the address already exists.
+    pub fn connect(&mut self, time: Instant, addr: SocketAddr, fallback_addrs: Vec<SocketAddr>, options: ConnectOptions, config: &Config, connections: &HashMap<SocketAddr, Connection>) -> bool {
+        if connections.contains_key(&addr) || self.connection_attempts.contains_key(&addr) {
+            debug!("Connection or connection attempt to {} already exists", addr);
+            return false;
+        }
+        let attempt = ConnectionAttempt::new(time, addr, config)
+            .with_fallback_addrs(fallback_addrs)
+            .with_options(options);
+        self.connection_attempts.insert(addr, attempt);
+        true
+    }
+
+    /// Returns true if there is an ongoing connection attempt to the specified address.
+    pub fn is_connecting(&self, addr: SocketAddr) -> bool {
+        self.connection_attempts.contains_key(&addr)
+    }
+
+    /// Returns true if there is any ongoing connection attempt.
+    pub fn has_connection_attempts(&self) -> bool {
+        !self.connection_attempts.is_empty()
+    }
+
+    /// Sends open connection requests for ongoing connection attempts
+    /// and drops the attempts that have failed.
+    pub fn update(&mut self, time: Instant, communicator: &mut Communicator<impl DatagramSocket>) {
+        self.rate_limiter.remove_full_buckets(time, communicator.config().max_offline_packets_per_second_per_ip);
+        self.ban_list.remove_expired(time);
+        self.used_handoff_nonces.retain(|_, expiry_time| time < *expiry_time);
+        for ip in self.greylist.remove_expired(time) {
+            debug!("The greylisting of {} has expired", ip);
+            communicator.send_event(PeerEvent::GreylistExpired { ip });
+        }
+        let recent_connection_window = Duration::from_millis(communicator.config().recent_connection_window_in_ms as u64);
+        self.recent_connections.retain(|_, connection_time| time.saturating_duration_since(*connection_time) < recent_connection_window);
+        for attempt in self.connection_attempts.values_mut() {
+            attempt.solve_puzzle(PUZZLE_HASHES_PER_UPDATE);
+            if attempt.should_send_request(time) {
+                let mtu = attempt.mtu();
+                if attempt.negotiated_mtu().is_some() {
+                    debug!("Sending Open Connection Request 2 to {}: mtu={}", attempt.addr(), mtu);
+                    let request2 = OpenConnectionRequest2Message {
+                        cookie_and_challenge: attempt.cookie().map(|cookie| (cookie, attempt.challenge())),
+                        binding_address: attempt.addr(),
+                        mtu,
+                        guid: communicator.config().guid,
+                        handoff_token: attempt.options().handoff_token.clone(),
+                    };
+                    Self::send_message(&request2, attempt.addr(), communicator);
+                } else {
+                    debug!("Sending Open Connection Request 1 to {}: mtu={}", attempt.addr(), mtu);
+                    // The integrity tag is added to the datagram, so the MTU agreed
+                    // with the remote peer is the MTU without the tag
+                    let integrity_tag_size = if communicator.config().pre_shared_key.is_some() { INTEGRITY_TAG_SIZE as u16 } else { 0 };
+                    let request1 = OpenConnectionRequest1Message {
+                        protocol_version: RAKNET_PROTOCOL_VERSION,
+                        padding_length: mtu - UDP_HEADER_SIZE - 1 - 16 - 1 - integrity_tag_size,
+                    };
+                    Self::send_message(&request1, attempt.addr(), communicator);
+                }
+                attempt.on_request_sent(time);
+            }
+        }
+        let failed_attempts: Vec<(SocketAddr, ConnectionAttemptFailedReason)> = self.connection_attempts.iter()
+            .filter_map(|(addr, attempt)| attempt.failure_reason(time).map(|reason| (*addr, reason)))
+            .collect();
+        for (addr, reason) in failed_attempts {
+            self.fail_connection_attempt(time, addr, reason, communicator);
+        }
     }
 
     /// Process a possible offline packet.
     /// Returns true if the packet was handled.
-    pub fn process_offline_packet(&self, time: Instant, addr: SocketAddr, payload: &[u8], communicator: &mut Communicator<impl DatagramSocket>, connections: &mut HashMap<SocketAddr, Connection>) -> bool
+    pub fn process_offline_packet(&mut self, time: Instant, addr: SocketAddr, payload: &[u8], communicator: &mut Communicator<impl DatagramSocket>, connections: &mut HashMap<SocketAddr, Connection>) -> bool
     {
-        // TODO: Check if remote peer is banned. If so, send MessageId::ConnectionBanned.
-
         if payload.len() > 2 {
-            match MessageId::try_from(payload[0]) {
+            let message_id = MessageId::try_from(payload[0]);
+            let is_request = matches!(message_id,
+                Ok(MessageId::UnconnectedPing) |
+                Ok(MessageId::UnconnectedPingOpenConnections) |
+                Ok(MessageId::OutOfBandInternal) |
+                Ok(MessageId::OpenConnectionRequest1) |
+                Ok(MessageId::OpenConnectionRequest2));
+            if is_request {
+                match self.rate_limiter.check(time, addr.ip(), communicator.config().max_offline_packets_per_second_per_ip) {
+                    RateLimit::Allowed => {},
+                    RateLimit::Exceeded { first } => {
+                        debug!("Too many offline packets from {}, ignoring packet", addr.ip());
+                        communicator.metrics_mut().rate_limited_packets += 1;
+                        if first {
+                            communicator.send_security_event(time, addr, SecurityEventKind::RateLimitExceeded);
+                        }
+                        return true;
+                    },
+                }
+            }
+            if self.ban_list.is_banned(time, addr.ip()) {
+                communicator.metrics_mut().banned_datagrams += 1;
+                // All datagrams from banned addresses are ignored, but connection requests are answered
+                // so that the remote peer does not keep retrying
+                if matches!(message_id, Ok(MessageId::OpenConnectionRequest1) | Ok(MessageId::OpenConnectionRequest2)) {
+                    debug!("Sending Connection Banned to {}", addr);
+                    communicator.send_security_event(time, addr, SecurityEventKind::BannedAddressConnecting);
+                    communicator.send_moderation_event(time, Some(addr), ModerationAction::ConnectionRejected { guid: None }, ModerationRule::BanList);
+                    let message = ConnectErrorMessage::new(MessageId::ConnectionBanned, communicator.config().guid);
+                    Self::send_message(&message, addr, communicator);
+                }
+                return true;
+            }
+            // Datagrams of connections have the valid bit set and are handled by the connections
+            let is_offline_message = payload[0] & 0x80 == 0;
+            if is_offline_message && self.greylist.contains(time, addr.ip()) {
+                communicator.metrics_mut().greylisted_datagrams += 1;
+                return true;
+            }
+            match message_id {
                 Ok(MessageId::UnconnectedPing) => self.handle_unconnected_ping(addr, payload, communicator),
                 Ok(MessageId::UnconnectedPingOpenConnections) => self.handle_unconnected_ping_open_connections(addr, payload, communicator, connections),
-                Ok(MessageId::UnconnectedPong) => self.handle_unconnected_pong(addr, payload, communicator),
-                Ok(MessageId::OpenConnectionRequest1) => self.handle_open_connection_request1(addr, payload, communicator),
+                Ok(MessageId::UnconnectedPong) => self.handle_unconnected_pong(time, addr, payload, communicator),
+                Ok(MessageId::OpenConnectionRequest1) => {
+                    communicator.metrics_mut().incoming_handshakes += 1;
+                    self.handle_open_connection_request1(time, addr, payload, connections.len(), communicator);
+                },
                 Ok(MessageId::OpenConnectionRequest2) => self.handle_open_connection_request2(time, addr, payload, communicator, connections),
-                Ok(MessageId::OpenConnectionReply1) => {}, // TODO: Implement
-                Ok(MessageId::OpenConnectionReply2) => {}, // TODO: Implement
-                Ok(MessageId::OutOfBandInternal) => {}, // TODO: Implement
-                Ok(MessageId::ConnectionAttemptFailed) => {}, // TODO: Implement
-                Ok(MessageId::NoFreeIncomingConnections) => {}, // TODO: Implement
-                Ok(MessageId::ConnectionBanned) => {}, // TODO: Implement
-                Ok(MessageId::AlreadyConnected) => {}, // TODO: Implement
-                Ok(MessageId::IpRecentlyConnected) => {}, // TODO: Implement
+                Ok(MessageId::OpenConnectionReply1) => self.handle_open_connection_reply1(time, addr, payload, communicator),
+                Ok(MessageId::OpenConnectionReply2) => self.handle_open_connection_reply2(time, addr, payload, communicator, connections),
+                Ok(MessageId::IncompatibleProtocolVersion) => self.handle_incompatible_protocol_version(time, addr, payload, communicator),
+                Ok(MessageId::OutOfBandInternal) => self.handle_out_of_band_internal(time, addr, payload, communicator),
+                Ok(MessageId::ConnectionAttemptFailed) |
+                Ok(MessageId::NoFreeIncomingConnections) |
+                Ok(MessageId::ConnectionBanned) |
+                Ok(MessageId::AlreadyConnected) |
+                Ok(MessageId::IpRecentlyConnected) => self.handle_connect_error(time, addr, payload, communicator),
                 _ => return false,
             }
         } else {
@@ -82,12 +322,20 @@ impl OfflinePacketHandler {
     }
 
     fn handle_unconnected_ping(&self, addr: SocketAddr, payload: &[u8], communicator: &mut Communicator<impl DatagramSocket>) {
-        let mut reader = DataReader::new(payload);
+        let mut reader = DataReader::new(payload).with_socket_addr_format(communicator.config().socket_addr_format);
         match UnconnectedPingMessage::read_message(&mut reader) {
             Ok(ping) => {
                 debug!("Received Unconnected Ping: time={}, client_guid={}", ping.time, ping.client_guid);
                 debug!("Sending Unconnected Pong");
-                let pong = UnconnectedPongMessage::new(communicator.config().guid, ping.time, self.ping_response.clone());
+                let ping_response = match &self.ping_response_provider {
+                    Some(provider) => {
+                        let mut ping_response = provider(addr);
+                        ping_response.truncate(399);
+                        ping_response
+                    },
+                    None => self.ping_response.clone(),
+                };
+                let pong = UnconnectedPongMessage::new(communicator.config().guid, ping.time, ping_response);
                 Self::send_message(&pong, addr, communicator);
             },
             Err(err) => error!("Could not read ping: {:?}", err),
@@ -100,32 +348,56 @@ impl OfflinePacketHandler {
         }
     }
 
-    fn handle_unconnected_pong(&self, _addr: SocketAddr, payload: &[u8], _communicator: &mut Communicator<impl DatagramSocket>) {
-        let mut reader = DataReader::new(payload);
+    fn handle_unconnected_pong(&self, time: Instant, addr: SocketAddr, payload: &[u8], communicator: &mut Communicator<impl DatagramSocket>) {
+        let mut reader = DataReader::new(payload).with_socket_addr_format(communicator.config().socket_addr_format);
         match UnconnectedPongMessage::read_message(&mut reader) {
             Ok(pong) => {
                 debug!("Received Unconnected Pong: time={}, guid={}, data={:?}", pong.time, pong.guid, utils::to_hex(&pong.data, 40));
-                // TODO: Forward event to user
+                let receive_time = time.saturating_duration_since(self.peer_creation_time).as_millis() as u64;
+                if pong.time > receive_time {
+                    debug!("Received a pong to a ping from the future from {}", addr);
+                    return;
+                }
+                let rtt = Duration::from_millis(receive_time - pong.time);
+                let received_ms = communicator.millis_since_start(time);
+                communicator.send_event(PeerEvent::UnconnectedPong { addr, guid: pong.guid, rtt, data: pong.data, received_at: time, received_ms });
             },
             Err(err) => error!("Could not read pong: {:?}", err),
         }
     }
 
-    fn handle_open_connection_request1(&self, addr: SocketAddr, payload: &[u8], communicator: &mut Communicator<impl DatagramSocket>) {
-        let mut reader = DataReader::new(payload);
+    fn handle_out_of_band_internal(&self, time: Instant, addr: SocketAddr, payload: &[u8], communicator: &mut Communicator<impl DatagramSocket>) {
+        let mut reader = DataReader::new(payload).with_socket_addr_format(communicator.config().socket_addr_format);
+        match OutOfBandInternalMessage::read_message(&mut reader) {
+            Ok(message) => {
+                debug!("Received Out Of Band message: guid={}, data={:?}", message.guid, utils::to_hex(&message.data, 40));
+                if message.data.len() > MAX_OUT_OF_BAND_DATA_LENGTH {
+                    debug!("Out Of Band message from {} is too long, ignoring packet", addr);
+                    return;
+                }
+                let received_ms = communicator.millis_since_start(time);
+                communicator.send_event(PeerEvent::OutOfBandMessage { addr, guid: message.guid, data: message.data, received_at: time, received_ms });
+            },
+            Err(err) => error!("Could not read out of band message: {:?}", err),
+        }
+    }
+
+    fn handle_open_connection_request1(&mut self, time: Instant, addr: SocketAddr, payload: &[u8], connection_count: usize, communicator: &mut Communicator<impl DatagramSocket>) {
+        let mut reader = DataReader::new(payload).with_socket_addr_format(communicator.config().socket_addr_format);
         match OpenConnectionRequest1Message::read_message(&mut reader) {
             Ok(request1) => {
                 debug!("Received Open Connection Request 1: protocol_version={}, padding_length={}", request1.protocol_version, request1.padding_length);
                 if request1.protocol_version != RAKNET_PROTOCOL_VERSION {
                     debug!("Sending Incompatible Protocol Version");
+                    communicator.send_security_event(time, addr, SecurityEventKind::IncompatibleProtocolVersion { protocol_version: request1.protocol_version });
                     let message = IncompatibleProtocolVersionMessage::new(RAKNET_PROTOCOL_VERSION, communicator.config().guid);
                     Self::send_message(&message, addr, communicator);
                 } else {
                     let requested_mtu = UDP_HEADER_SIZE + 1 + 16 + 1 + request1.padding_length;
-                    let mtu = if requested_mtu < MAXIMUM_MTU_SIZE { requested_mtu } else { MAXIMUM_MTU_SIZE };
-                    // TODO: Add support for security
+                    let mtu = requested_mtu.min(communicator.config().max_mtu_size);
+                    let cookie_and_public_key = self.cookie_and_public_key(time, addr, connection_count, communicator.config());
                     debug!("Sending Open Connection Reply 1");
-                    let response = OpenConnectionReply1Message::new(communicator.config().guid, None, mtu);
+                    let response = OpenConnectionReply1Message::new(communicator.config().guid, cookie_and_public_key, mtu);
                     Self::send_message(&response, addr, communicator);
                 }
             },
@@ -133,13 +405,147 @@ impl OfflinePacketHandler {
         }
     }
 
-    fn handle_open_connection_request2(&self, time: Instant, addr: SocketAddr, payload: &[u8], communicator: &mut Communicator<impl DatagramSocket>, connections: &mut HashMap<SocketAddr, Connection>) {
-        let mut reader = DataReader::new(payload);
-        match OpenConnectionRequest2Message::read_message(&mut reader) {
+    /// Returns the cookie and the encoded public key sent in "open connection reply 1"
+    /// or None if connecting peers do not have to echo a cookie.
+    #[cfg(feature = "rand")]
+    fn cookie_and_public_key(&self, time: Instant, addr: SocketAddr, connection_count: usize, config: &Config) -> Option<(u32, [u8; 64])> {
+        if !Self::uses_cookies(config) {
+            return None;
+        }
+        let puzzle_difficulty = if connection_count >= config.proof_of_work_connection_threshold {
+            config.proof_of_work_difficulty
+        } else {
+            0
+        };
+        let cookie = self.cookie_generator.cookie(addr, time.saturating_duration_since(self.peer_creation_time), puzzle_difficulty);
+        let public_key = config.security.as_ref().map(|security| security.public_key());
+        Some((cookie, security::encode_public_key_field(public_key, puzzle_difficulty)))
+    }
+
+    /// Cookies need a secret key from a secure random source, so none are sent without the `rand` feature.
+    #[cfg(not(feature = "rand"))]
+    fn cookie_and_public_key(&self, _time: Instant, _addr: SocketAddr, _connection_count: usize, _config: &Config) -> Option<(u32, [u8; 64])> {
+        None
+    }
+
+    /// Returns true if a handoff token sent by a connecting remote peer is accepted. Returns false
+    /// if the token was not signed with `Config::handoff_key`, has expired, was created for another
+    /// GUID or IP address or has already been used, unless it is sent again in a duplicate request.
+    fn accept_handoff_token(&self, time: Instant, addr: SocketAddr, guid: u64, handoff_token: &HandoffToken, is_duplicate_request: bool, communicator: &mut Communicator<impl DatagramSocket>) -> bool {
+        let handoff_key = match &communicator.config().handoff_key {
+            Some(handoff_key) => handoff_key,
+            None => return false,
+        };
+        let is_reused = !is_duplicate_request && self.used_handoff_nonces.contains_key(&handoff_token.nonce());
+        if is_reused
+            || handoff_token.guid() != guid
+            || handoff_token.ip() != addr.ip().to_canonical()
+            || !handoff_token.is_valid(handoff_key, communicator.system_time(time)) {
+            debug!("Received Open Connection Request 2 from {} with invalid handoff token", addr);
+            communicator.send_security_event(time, addr, SecurityEventKind::InvalidHandoffToken);
+            return false;
+        }
+        true
+    }
+
+    fn handle_open_connection_request2(&mut self, time: Instant, addr: SocketAddr, payload: &[u8], communicator: &mut Communicator<impl DatagramSocket>, connections: &mut HashMap<SocketAddr, Connection>) {
+        let mut reader = DataReader::new(payload).with_socket_addr_format(communicator.config().socket_addr_format);
+        let request2 = if Self::uses_cookies(communicator.config()) {
+            OpenConnectionRequest2Message::read_message_with_security(&mut reader)
+        } else {
+            OpenConnectionRequest2Message::read_message(&mut reader)
+        };
+        match request2 {
             Ok(request2) => {
                 debug!("Received Open Connection Request 2: mtu={} guid={} binding_address={:?}", request2.mtu, request2.guid, request2.binding_address);        
-                
-                // TODO: Check security if enabled
+
+                // Only allocate connection state for peers that have proven that they
+                // receive datagrams at their address by echoing the cookie
+                #[cfg(feature = "rand")]
+                let challenge = match request2.cookie_and_challenge {
+                    Some((cookie, challenge)) => {
+                        // The cookie tells whether a puzzle was sent with it
+                        let elapsed = time.saturating_duration_since(self.peer_creation_time);
+                        let puzzle_difficulty = communicator.config().proof_of_work_difficulty;
+                        if self.cookie_generator.is_valid(cookie, addr, elapsed, 0) {
+                            // No puzzle was sent
+                        } else if puzzle_difficulty > 0 && self.cookie_generator.is_valid(cookie, addr, elapsed, puzzle_difficulty) {
+                            if !challenge.is_some_and(|challenge| security::is_puzzle_solved(cookie, puzzle_difficulty, &challenge[32..])) {
+                                debug!("Received Open Connection Request 2 from {} without proof of work, ignoring packet", addr);
+                                communicator.send_security_event(time, addr, SecurityEventKind::InvalidProofOfWork);
+                                return;
+                            }
+                        } else {
+                            debug!("Received Open Connection Request 2 from {} with invalid cookie, ignoring packet", addr);
+                            communicator.send_security_event(time, addr, SecurityEventKind::InvalidCookie);
+                            return;
+                        }
+                        challenge
+                    },
+                    None => None,
+                };
+                #[cfg(feature = "rand")]
+                if communicator.config().security.is_some() && challenge.is_none() {
+                    debug!("Received Open Connection Request 2 from {} without security challenge, ignoring packet", addr);
+                    communicator.send_security_event(time, addr, SecurityEventKind::InvalidSecurityChallenge);
+                    return;
+                }
+
+                if request2.guid == communicator.config().guid {
+                    debug!("Received Open Connection Request 2 from ourselves, ignoring packet");
+                    return;
+                }
+
+                // A remote peer with a valid handoff token has already been admitted by another peer.
+                // The token is only accepted once the remote peer has echoed the cookie, which proves
+                // that it receives datagrams at the IP address that the token was created for.
+                let is_duplicate_request = connections.get(&addr)
+                    .is_some_and(|conn| conn.state == ConnectionState::UnverifiedSender && conn.guid() == request2.guid);
+                let handoff_token = request2.handoff_token.as_ref()
+                    .filter(|_| Self::uses_cookies(communicator.config()))
+                    .filter(|handoff_token| self.accept_handoff_token(time, addr, request2.guid, handoff_token, is_duplicate_request, communicator));
+                let is_handoff = handoff_token.is_some();
+
+                if !is_handoff && self.allow_list.as_ref().is_some_and(|allow_list| !allow_list.is_allowed(addr.ip(), request2.guid)) {
+                    debug!("Sending Connection Banned to {} with guid {} that is not in the allow list", addr, request2.guid);
+                    communicator.send_security_event(time, addr, SecurityEventKind::NotAllowedConnecting { guid: request2.guid });
+                    communicator.send_moderation_event(time, Some(addr), ModerationAction::ConnectionRejected { guid: Some(request2.guid) }, ModerationRule::AllowList);
+                    let message = ConnectErrorMessage::new(MessageId::ConnectionBanned, communicator.config().guid);
+                    Self::send_message(&message, addr, communicator);
+                    return;
+                }
+
+                let admission = match self.admission_policy.as_mut() {
+                    Some(policy) if !is_handoff => policy.admit(addr, request2.guid),
+                    _ => Admission::Accept,
+                };
+                match admission {
+                    Admission::Accept => {},
+                    Admission::Reject => {
+                        debug!("Sending Connection Banned to {} with guid {} that is rejected by the admission policy", addr, request2.guid);
+                        communicator.send_moderation_event(time, Some(addr), ModerationAction::ConnectionRejected { guid: Some(request2.guid) }, ModerationRule::AdmissionPolicy);
+                        let message = ConnectErrorMessage::new(MessageId::ConnectionBanned, communicator.config().guid);
+                        Self::send_message(&message, addr, communicator);
+                        return;
+                    },
+                    Admission::Ignore => {
+                        debug!("Received Open Connection Request 2 from {} that is ignored by the admission policy, ignoring packet", addr);
+                        communicator.send_moderation_event(time, Some(addr), ModerationAction::ConnectionRejected { guid: Some(request2.guid) }, ModerationRule::AdmissionPolicy);
+                        return;
+                    },
+                }
+
+                if self.connection_attempts.contains_key(&addr) {
+                    // Both peers are connecting to each other at the same time.
+                    // The peer with the highest GUID keeps its outgoing connection attempt
+                    // while the other peer abandons its attempt and accepts the incoming connection.
+                    if communicator.config().guid > request2.guid {
+                        debug!("Simultaneous connect with {}, keeping the outgoing connection attempt", addr);
+                        return;
+                    }
+                    debug!("Simultaneous connect with {}, accepting the incoming connection", addr);
+                    self.connection_attempts.remove(&addr);
+                }
 
                 let (guid_in_use, guid_in_use_by_same_addr) = connections.iter().find_map(|(remote_addr, conn)|
                     if conn.guid() == request2.guid {
@@ -159,9 +565,8 @@ impl OfflinePacketHandler {
                     if let Some(conn) = conn {
                         // Duplicate connection request due to packet loss
                         // Resend the reply
-                        // TODO: Add support for security (resend challenge answer)
                         debug!("Sending Open Connection Reply2 (connection already exists)");
-                        let reply2 = OpenConnectionReply2Message::new(communicator.config().guid, addr, conn.mtu(), None);
+                        let reply2 = OpenConnectionReply2Message::new(communicator.config().guid, addr, conn.mtu(), conn.challenge_answer());
                         Self::send_message(&reply2, addr, communicator);
                         return;
                     }
@@ -182,21 +587,260 @@ impl OfflinePacketHandler {
                     return;
                 }
 
-                // TODO: Check if this IP has connected the last 100 ms. If so, send MessageId::IpRecentlyConnected.
-                // TODO: Check that the MTU is within our accepted range
+                let recent_connection_window = Duration::from_millis(communicator.config().recent_connection_window_in_ms as u64);
+                if !is_handoff && self.recent_connections.get(&addr.ip())
+                    .is_some_and(|connection_time| time.saturating_duration_since(*connection_time) < recent_connection_window) {
+                    debug!("Sending IP Recently Connected");
+                    let message = ConnectErrorMessage::new(MessageId::IpRecentlyConnected, communicator.config().guid);
+                    Self::send_message(&message, addr, communicator);
+                    return;
+                }
+
+                // An MTU above the accepted range is clamped while an MTU below it is rejected
+                if request2.mtu < communicator.config().min_mtu_size {
+                    debug!("Received Open Connection Request 2 from {} with too small MTU {}, sending Connection Attempt Failed", addr, request2.mtu);
+                    let message = ConnectErrorMessage::new(MessageId::ConnectionAttemptFailed, communicator.config().guid);
+                    Self::send_message(&message, addr, communicator);
+                    return;
+                }
+                let config = communicator.config();
+                if !is_handoff && !self.handshake_rate_limiter.try_accept(time, config.max_new_connections_per_second, config.new_connection_burst) {
+                    debug!("Too many new connections, sending No Free Incoming Connections to {}", addr);
+                    communicator.metrics_mut().rate_limited_handshakes += 1;
+                    communicator.send_moderation_event(time, Some(addr), ModerationAction::ConnectionRejected { guid: Some(request2.guid) }, ModerationRule::HandshakeRateLimit);
+                    let message = ConnectErrorMessage::new(MessageId::NoFreeIncomingConnections, communicator.config().guid);
+                    Self::send_message(&message, addr, communicator);
+                    return;
+                }
 
-                let conn = Connection::incoming(time, self.peer_creation_time, addr, request2.guid, request2.mtu);
+                let mtu = request2.mtu.min(communicator.config().max_mtu_size);
+                let mut conn = Connection::incoming(time, self.peer_creation_time, addr, request2.guid, mtu);
+                if let Some(handoff_token) = handoff_token {
+                    conn.set_handoff_user_data(handoff_token.user_data());
+                }
+                #[cfg(feature = "rand")]
+                if let (Some(security), Some(challenge)) = (&communicator.config().security, challenge) {
+                    match security::answer_challenge(&security.private_key, &challenge) {
+                        Some((answer, session_keys)) => conn.set_security(session_keys, Some(answer)),
+                        None => {
+                            debug!("Received invalid security challenge from {}, ignoring packet", addr);
+                            communicator.send_security_event(time, addr, SecurityEventKind::InvalidSecurityChallenge);
+                            return;
+                        },
+                    }
+                }
+                let challenge_answer = conn.challenge_answer();
                 connections.insert(addr, conn);
+                if let Some(handoff_token) = handoff_token {
+                    let expires_in = handoff_token.expires_at().duration_since(communicator.system_time(time)).unwrap_or_default();
+                    self.used_handoff_nonces.insert(handoff_token.nonce(), time + expires_in);
+                }
+                if recent_connection_window > Duration::ZERO {
+                    self.recent_connections.insert(addr.ip(), time);
+                }
 
-                // TODO: Add support for security and supply challenge answer.
                 debug!("Sending Open Connection Reply 2");
-                let reply2 = OpenConnectionReply2Message::new(communicator.config().guid, addr, request2.mtu, None);
+                let reply2 = OpenConnectionReply2Message::new(communicator.config().guid, addr, mtu, challenge_answer);
                 Self::send_message(&reply2, addr, communicator);
             },
             Err(err) => error!("Failed reading open connection request 2: {:?}", err),
         }
     }
 
+    fn handle_open_connection_reply1(&mut self, time: Instant, addr: SocketAddr, payload: &[u8], communicator: &mut Communicator<impl DatagramSocket>) {
+        let own_guid = communicator.config().guid;
+        let attempt = match self.connection_attempts.get_mut(&addr) {
+            Some(attempt) => attempt,
+            None => {
+                debug!("Received Open Connection Reply 1 from {} without a connection attempt, ignoring packet", addr);
+                return;
+            },
+        };
+        if attempt.negotiated_mtu().is_some() {
+            debug!("Received duplicate Open Connection Reply 1 from {}, ignoring packet", addr);
+            return;
+        }
+        let mut reader = DataReader::new(payload).with_socket_addr_format(communicator.config().socket_addr_format);
+        match OpenConnectionReply1Message::read_message(&mut reader) {
+            Ok(reply1) => {
+                debug!("Received Open Connection Reply 1: guid={} mtu={}", reply1.guid, reply1.mtu);
+                if reply1.guid == own_guid {
+                    // We are connecting to ourselves, e.g. through a loopback address
+                    self.fail_connection_attempt(time, addr, ConnectionAttemptFailedReason::SelfConnection, communicator);
+                    return;
+                }
+                let (cookie, public_key) = match reply1.cookie_and_public_key {
+                    Some((cookie, public_key)) => (Some(cookie), security::decode_public_key(&public_key)),
+                    None => (None, None),
+                };
+                #[cfg(feature = "rand")]
+                match (public_key, attempt.options().server_public_key) {
+                    (Some(public_key), Some(server_public_key)) if public_key != server_public_key => {
+                        debug!("Received Open Connection Reply 1 from {} with unexpected public key", addr);
+                        self.fail_connection_attempt(time, addr, ConnectionAttemptFailedReason::SecurityHandshakeFailed, communicator);
+                        return;
+                    },
+                    (None, Some(_)) => {
+                        debug!("Received Open Connection Reply 1 from {} without security", addr);
+                        self.fail_connection_attempt(time, addr, ConnectionAttemptFailedReason::SecurityHandshakeFailed, communicator);
+                        return;
+                    },
+                    _ => {},
+                }
+                // The security handshake needs secret keys from a secure random source
+                #[cfg(not(feature = "rand"))]
+                if public_key.is_some() {
+                    debug!("Received Open Connection Reply 1 from {} with security, which requires the rand feature", addr);
+                    self.fail_connection_attempt(time, addr, ConnectionAttemptFailedReason::SecurityHandshakeFailed, communicator);
+                    return;
+                }
+                if let Some(cookie) = cookie {
+                    let puzzle_difficulty = reply1.cookie_and_public_key.map_or(0, |(_, field)| security::decode_puzzle_difficulty(&field));
+                    if puzzle_difficulty > MAX_PROOF_OF_WORK_DIFFICULTY {
+                        debug!("Received Open Connection Reply 1 from {} with too difficult proof-of-work puzzle {}", addr, puzzle_difficulty);
+                        self.fail_connection_attempt(time, addr, ConnectionAttemptFailedReason::SecurityHandshakeFailed, communicator);
+                        return;
+                    }
+                    #[cfg(feature = "rand")]
+                    attempt.set_handshake(public_key.map(ClientHandshake::new));
+                    attempt.set_cookie(cookie, puzzle_difficulty);
+                }
+                if reply1.mtu < communicator.config().min_mtu_size {
+                    debug!("Received Open Connection Reply 1 from {} with too small MTU {}", addr, reply1.mtu);
+                    self.fail_connection_attempt(time, addr, ConnectionAttemptFailedReason::MtuTooSmall, communicator);
+                    return;
+                }
+                let mtu = reply1.mtu.min(communicator.config().max_mtu_size);
+                attempt.on_reply1_received(time, mtu);
+            },
+            Err(err) => error!("Failed reading open connection reply 1: {:?}", err),
+        }
+    }
+
+    fn handle_open_connection_reply2(&mut self, time: Instant, addr: SocketAddr, payload: &[u8], communicator: &mut Communicator<impl DatagramSocket>, connections: &mut HashMap<SocketAddr, Connection>) {
+        let negotiated_mtu = match self.connection_attempts.get(&addr) {
+            Some(attempt) => attempt.negotiated_mtu(),
+            None => {
+                debug!("Received Open Connection Reply 2 from {} without a connection attempt, ignoring packet", addr);
+                return;
+            },
+        };
+        let negotiated_mtu = match negotiated_mtu {
+            Some(mtu) => mtu,
+            None => {
+                debug!("Received Open Connection Reply 2 from {} before Open Connection Reply 1, ignoring packet", addr);
+                return;
+            },
+        };
+        let mut reader = DataReader::new(payload).with_socket_addr_format(communicator.config().socket_addr_format);
+        match OpenConnectionReply2Message::read_message(&mut reader) {
+            Ok(reply2) => {
+                debug!("Received Open Connection Reply 2: guid={} mtu={} client_address={}", reply2.guid, reply2.mtu, reply2.client_address);
+                if reply2.mtu < communicator.config().min_mtu_size {
+                    debug!("Received Open Connection Reply 2 from {} with too small MTU {}", addr, reply2.mtu);
+                    self.fail_connection_attempt(time, addr, ConnectionAttemptFailedReason::MtuTooSmall, communicator);
+                    return;
+                }
+                #[cfg(feature = "rand")]
+                let session_keys = match (self.connection_attempts.get(&addr).and_then(|attempt| attempt.handshake()), reply2.challenge_answer) {
+                    (Some(handshake), Some(answer)) => match handshake.verify_answer(&answer) {
+                        Some(session_keys) => Some(session_keys),
+                        None => {
+                            debug!("Received invalid security answer from {}", addr);
+                            self.fail_connection_attempt(time, addr, ConnectionAttemptFailedReason::SecurityHandshakeFailed, communicator);
+                            return;
+                        },
+                    },
+                    (Some(_), None) => {
+                        debug!("Received Open Connection Reply 2 from {} without security answer", addr);
+                        self.fail_connection_attempt(time, addr, ConnectionAttemptFailedReason::SecurityHandshakeFailed, communicator);
+                        return;
+                    },
+                    (None, _) => None,
+                };
+                if let Some(attempt) = self.connection_attempts.remove(&addr) {
+                    let mtu = if reply2.mtu < negotiated_mtu { reply2.mtu } else { negotiated_mtu };
+                    let mut conn = Connection::outgoing(time, attempt.start_time(), self.peer_creation_time, addr, reply2.guid, mtu, attempt.options().user_data);
+                    #[cfg(feature = "rand")]
+                    if let Some(session_keys) = session_keys {
+                        conn.set_security(session_keys, None);
+                    }
+                    conn.send_connection_request(time, &attempt.options().password, communicator);
+                    connections.insert(addr, conn);
+                }
+            },
+            Err(err) => error!("Failed reading open connection reply 2: {:?}", err),
+        }
+    }
+
+    fn handle_incompatible_protocol_version(&mut self, time: Instant, addr: SocketAddr, payload: &[u8], communicator: &mut Communicator<impl DatagramSocket>) {
+        if !self.connection_attempts.contains_key(&addr) {
+            debug!("Received Incompatible Protocol Version from {} without a connection attempt, ignoring packet", addr);
+            return;
+        }
+        let mut reader = DataReader::new(payload).with_socket_addr_format(communicator.config().socket_addr_format);
+        match IncompatibleProtocolVersionMessage::read_message(&mut reader) {
+            Ok(message) => {
+                debug!("Received Incompatible Protocol Version: protocol_version={} guid={}", message.protocol_version, message.guid);
+                self.fail_connection_attempt(time, addr, ConnectionAttemptFailedReason::IncompatibleProtocolVersion, communicator);
+            },
+            Err(err) => error!("Failed reading incompatible protocol version: {:?}", err),
+        }
+    }
+
+    fn handle_connect_error(&mut self, time: Instant, addr: SocketAddr, payload: &[u8], communicator: &mut Communicator<impl DatagramSocket>) {
+        if !self.connection_attempts.contains_key(&addr) {
+            debug!("Received connect error from {} without a connection attempt, ignoring packet", addr);
+            return;
+        }
+        let mut reader = DataReader::new(payload).with_socket_addr_format(communicator.config().socket_addr_format);
+        match ConnectErrorMessage::read_message(&mut reader) {
+            Ok(message) => {
+                debug!("Received connect error: message_id={:?} guid={}", message.message_id, message.guid);
+                let reason = match message.message_id {
+                    MessageId::NoFreeIncomingConnections => ConnectionAttemptFailedReason::NoFreeIncomingConnections,
+                    MessageId::ConnectionBanned => ConnectionAttemptFailedReason::Banned,
+                    MessageId::AlreadyConnected => ConnectionAttemptFailedReason::AlreadyConnected,
+                    MessageId::IpRecentlyConnected => ConnectionAttemptFailedReason::IpRecentlyConnected,
+                    _ => ConnectionAttemptFailedReason::Rejected,
+                };
+                self.fail_connection_attempt(time, addr, reason, communicator);
+            },
+            Err(err) => error!("Failed reading connect error: {:?}", err),
+        }
+    }
+
+    /// Removes a failed connection attempt and continues with the next fallback
+    /// address if there is one. Otherwise the failure is reported to the user.
+    fn fail_connection_attempt(&mut self, time: Instant, addr: SocketAddr, reason: ConnectionAttemptFailedReason, communicator: &mut Communicator<impl DatagramSocket>) {
+        if let Some(attempt) = self.connection_attempts.remove(&addr) {
+            let user_data = attempt.options().user_data;
+            match attempt.into_next_attempt(time, communicator.config()) {
+                Some(next_attempt) if !self.connection_attempts.contains_key(&next_attempt.addr()) => {
+                    debug!("Connection attempt to {} failed: {}. Trying {}", addr, reason, next_attempt.addr());
+                    communicator.move_connection_result_senders(addr, next_attempt.addr());
+                    self.connection_attempts.insert(next_attempt.addr(), next_attempt);
+                },
+                _ => {
+                    debug!("Connection attempt to {} failed: {}", addr, reason);
+                    communicator.send_event(PeerEvent::ConnectionAttemptFailed { addr, reason, user_data });
+                },
+            }
+        }
+    }
+
+    /// Returns true if connecting peers must echo a cookie to prove that
+    /// they receive datagrams at their address.
+    #[cfg(feature = "rand")]
+    fn uses_cookies(config: &Config) -> bool {
+        config.require_cookies || config.security.is_some() || config.proof_of_work_difficulty > 0 || config.handoff_key.is_some()
+    }
+
+    #[cfg(not(feature = "rand"))]
+    fn uses_cookies(_config: &Config) -> bool {
+        false
+    }
+
     fn allow_incoming_connections(config: &Config, connections: &HashMap<SocketAddr, Connection>) -> bool {
         // TODO: Revisit the logic below.
         // This logic is from the original RakNet C++ implementation. That we filter on ConnectionState::Connected
@@ -210,13 +854,9 @@ impl OfflinePacketHandler {
     }
 
     fn send_message(message: &dyn MessageWrite, dest: SocketAddr, communicator: &mut Communicator<impl DatagramSocket>) {
-        let mut payload = Vec::new();
-        match message.write_message(&mut payload) {
-            Ok(()) => {
-                if let Err(err) = communicator.socket().send_datagram(&payload, dest) {
-                    error!("Failed sending message: {:?}", err);
-                }
-            },
+        let mut payload = Vec::with_capacity(message.written_size());
+        match message.write_message(&mut DataWriter::new(&mut payload, communicator.config().socket_addr_format)) {
+            Ok(()) => communicator.send_datagram(&payload, dest),
             Err(err) => error!("Failed writing message to buffer: {:?}", err),
         }
     }   
@@ -224,20 +864,27 @@ impl OfflinePacketHandler {
 
 #[cfg(test)]
 mod tests {
-    use std::{collections::HashMap, net::SocketAddr, time::Instant};
+    use std::{collections::HashMap, net::SocketAddr, sync::Arc, time::Instant};
+    #[cfg(feature = "rand")]
+    use std::time::{Duration, SystemTime};
     use crossbeam_channel::{Receiver, unbounded};
 
     use crate::{        
+        SystemClock,
         communicator::Communicator,
         config::Config,
         connection::{Connection, ConnectionState},
         message_ids::MessageId,
-        messages::{ConnectErrorMessage, OpenConnectionRequest2Message, OpenConnectionReply2Message},
+        constants::{MAXIMUM_MTU_SIZE, MINIMUM_MTU_SIZE, RAKNET_PROTOCOL_VERSION},
+        messages::{ConnectErrorMessage, OpenConnectionRequest1Message, OpenConnectionRequest2Message, OpenConnectionReply1Message, OpenConnectionReply2Message},
         offline_packet_handler::OfflinePacketHandler,
         reader::{MessageRead, DataReader},
         socket::FakeDatagramSocket,
+        socket_addr_format::SocketAddrFormat,
         writer::MessageWrite,
     };
+    #[cfg(feature = "rand")]
+    use crate::{allow_list::AllowList, handoff_token::HandoffToken};
 
     const OWN_GUID: u64 = 0xFEDCBA9876453210;
     const REMOTE_GUID: u64 = 0xAABBCCDDEEFF0011;
@@ -253,10 +900,10 @@ mod tests {
         let socket = FakeDatagramSocket::new(own_addr);
         let datagram_receiver = socket.get_datagram_receiver();
         let (event_sender, _event_receiver) = unbounded();
-        let communicator = Communicator::new(socket, config, event_sender);
+        let communicator = Communicator::new(socket, config, event_sender, Arc::new(SystemClock));
         let connections = HashMap::<SocketAddr, Connection>::new();
         let remote_addr = "192.168.1.1:19132".parse::<SocketAddr>().expect("Could not create address");
-        (OfflinePacketHandler::new(), communicator, connections, datagram_receiver, remote_addr, own_addr)
+        (OfflinePacketHandler::new(Instant::now(), None), communicator, connections, datagram_receiver, remote_addr, own_addr)
     }    
 
     fn receive_datagram<M: MessageRead>(datagram_receiver: &mut Receiver<(Vec<u8>, SocketAddr)>) -> (M, SocketAddr) {
@@ -269,13 +916,14 @@ mod tests {
     #[test]
     fn open_connection_request_2_guid_and_addr_in_use_by_remote() {
         // Arrange
-        let (handler, mut communicator, mut connections, mut datagram_receiver, remote_addr, own_addr) = create_test_setup();
+        let (mut handler, mut communicator, mut connections, mut datagram_receiver, remote_addr, own_addr) = create_test_setup();
         let mut payload = Vec::new();
         let message = OpenConnectionRequest2Message {
             cookie_and_challenge: None,
             binding_address: own_addr,
             mtu: 1024,
             guid: REMOTE_GUID,
+            handoff_token: None,
         };
         message.write_message(&mut payload).expect("Could not write message");
         connections.insert(remote_addr, Connection::incoming(Instant::now(), Instant::now(), remote_addr, REMOTE_GUID, 1024));
@@ -296,13 +944,14 @@ mod tests {
     #[test]
     fn open_connection_request_2_guid_in_use_by_other() {
         // Arrange
-        let (handler, mut communicator, mut connections, mut datagram_receiver, remote_addr, own_addr) = create_test_setup();
+        let (mut handler, mut communicator, mut connections, mut datagram_receiver, remote_addr, own_addr) = create_test_setup();
         let mut payload = Vec::new();
         let message = OpenConnectionRequest2Message {
             cookie_and_challenge: None,
             binding_address: own_addr,
             mtu: 1024,
             guid: REMOTE_GUID,
+            handoff_token: None,
         };
         message.write_message(&mut payload).expect("Could not write message");
         let other_addr = "192.168.1.99:19132".parse::<SocketAddr>().expect("Could not create address");
@@ -322,13 +971,14 @@ mod tests {
     #[test]
     fn open_connection_request_2_addr_in_use_with_other_guid() {
         // Arrange
-        let (handler, mut communicator, mut connections, mut datagram_receiver, remote_addr, own_addr) = create_test_setup();
+        let (mut handler, mut communicator, mut connections, mut datagram_receiver, remote_addr, own_addr) = create_test_setup();
         let mut payload = Vec::new();
         let message = OpenConnectionRequest2Message {
             cookie_and_challenge: None,
             binding_address: own_addr,
             mtu: 1024,
             guid: REMOTE_GUID,
+            handoff_token: None,
         };
         message.write_message(&mut payload).expect("Could not write message");
         let other_guid: u64 = 0x1111111111111111;
@@ -351,13 +1001,14 @@ mod tests {
         let mut config = Config::default();
         config.guid = OWN_GUID;
         config.max_incoming_connections = 1;
-        let (handler, mut communicator, mut connections, mut datagram_receiver, remote_addr, own_addr) = create_test_setup_with_config(config);
+        let (mut handler, mut communicator, mut connections, mut datagram_receiver, remote_addr, own_addr) = create_test_setup_with_config(config);
         let mut payload = Vec::new();
         let message = OpenConnectionRequest2Message {
             cookie_and_challenge: None,
             binding_address: own_addr,
             mtu: 1024,
             guid: REMOTE_GUID,
+            handoff_token: None,
         };
         message.write_message(&mut payload).expect("Could not write message");
         let other_guid: u64 = 0x1111111111111111;
@@ -376,4 +1027,186 @@ mod tests {
         assert_eq!(MessageId::NoFreeIncomingConnections, message.message_id);
         assert_eq!(OWN_GUID, message.guid);
     }       
-}
\ No newline at end of file
+
+    #[test]
+    fn open_connection_request_2_over_new_connection_rate_is_rejected() {
+        // Arrange
+        let config = Config { guid: OWN_GUID, max_new_connections_per_second: 1, new_connection_burst: 1, ..Config::default() };
+        let (mut handler, mut communicator, mut connections, mut datagram_receiver, remote_addr, own_addr) = create_test_setup_with_config(config);
+        let other_addr = "192.168.1.99:19132".parse::<SocketAddr>().expect("Could not create address");
+        let time = Instant::now();
+        let requests = [(remote_addr, REMOTE_GUID), (other_addr, 0x1111111111111111)].map(|(addr, guid)| {
+            let mut payload = Vec::new();
+            let message = OpenConnectionRequest2Message { cookie_and_challenge: None, binding_address: own_addr, mtu: 1024, guid, handoff_token: None };
+            message.write_message(&mut payload).expect("Could not write message");
+            (addr, payload)
+        });
+
+        // Act
+        for (addr, payload) in requests.iter() {
+            handler.process_offline_packet(time, *addr, payload, &mut communicator, &mut connections);
+        }
+
+        // Assert
+        let (_reply2, addr) = receive_datagram::<OpenConnectionReply2Message>(&mut datagram_receiver);
+        assert_eq!(remote_addr, addr);
+        let (message, addr) = receive_datagram::<ConnectErrorMessage>(&mut datagram_receiver);
+        assert_eq!(other_addr, addr);
+        assert_eq!(MessageId::NoFreeIncomingConnections, message.message_id);
+        assert_eq!(1, connections.len());
+        assert_eq!(1, communicator.metrics_mut().rate_limited_handshakes);
+    }
+
+    #[test]
+    fn open_connection_request_1_mtu_clamped_to_max_mtu_size() {
+        // Arrange
+        let config = Config { guid: OWN_GUID, max_mtu_size: 1400, ..Config::default() };
+        let (mut handler, mut communicator, mut connections, mut datagram_receiver, remote_addr, _own_addr) = create_test_setup_with_config(config);
+        let mut payload = Vec::new();
+        let message = OpenConnectionRequest1Message {
+            protocol_version: RAKNET_PROTOCOL_VERSION,
+            padding_length: 1492 - 28 - 1 - 16 - 1,
+        };
+        message.write_message(&mut payload).expect("Could not write message");
+
+        // Act
+        let handled = handler.process_offline_packet(Instant::now(), remote_addr, &payload, &mut communicator, &mut connections);
+
+        // Assert
+        let (message, _addr) = receive_datagram::<OpenConnectionReply1Message>(&mut datagram_receiver);
+        assert!(handled);
+        assert_eq!(1400, message.mtu);
+    }
+
+    #[test]
+    fn open_connection_request_2_mtu_clamped_to_max_mtu_size() {
+        // Arrange
+        let config = Config { guid: OWN_GUID, max_mtu_size: 1400, ..Config::default() };
+        let (mut handler, mut communicator, mut connections, mut datagram_receiver, remote_addr, own_addr) = create_test_setup_with_config(config);
+        let mut payload = Vec::new();
+        let message = OpenConnectionRequest2Message {
+            cookie_and_challenge: None,
+            binding_address: own_addr,
+            mtu: 1492,
+            guid: REMOTE_GUID,
+            handoff_token: None,
+        };
+        message.write_message(&mut payload).expect("Could not write message");
+
+        // Act
+        handler.process_offline_packet(Instant::now(), remote_addr, &payload, &mut communicator, &mut connections);
+
+        // Assert
+        let (message, _addr) = receive_datagram::<OpenConnectionReply2Message>(&mut datagram_receiver);
+        assert_eq!(1400, message.mtu);
+        assert_eq!(1400, connections.get(&remote_addr).expect("No connection was created").mtu());
+    }
+
+    #[test]
+    fn open_connection_reply_2_is_written_with_configured_socket_addr_format() {
+        // Arrange
+        let config = Config { guid: OWN_GUID, socket_addr_format: SocketAddrFormat::Classic, ..Config::default() };
+        let (mut handler, mut communicator, mut connections, datagram_receiver, _remote_addr, own_addr) = create_test_setup_with_config(config);
+        let remote_addr = "[fe80::1]:19132".parse::<SocketAddr>().expect("Could not create address");
+        let mut payload = Vec::new();
+        let message = OpenConnectionRequest2Message {
+            cookie_and_challenge: None,
+            binding_address: own_addr,
+            mtu: 1024,
+            guid: REMOTE_GUID,
+            handoff_token: None,
+        };
+        message.write_message(&mut payload).expect("Could not write message");
+
+        // Act
+        handler.process_offline_packet(Instant::now(), remote_addr, &payload, &mut communicator, &mut connections);
+
+        // Assert
+        let (payload, _addr) = datagram_receiver.try_recv().expect("Datagram not received");
+        assert_eq!(u8::from(MessageId::OpenConnectionReply2), payload[0]);
+        assert_eq!([6u8, 0x0A, 0x00], payload[25..28]); // IP version = 6, sin6_family (little endian): 10=AF_INET6 on Linux
+    }
+
+    #[test]
+    fn open_connection_request_2_with_maximum_u16_mtu_is_clamped() {
+        // Arrange
+        let (mut handler, mut communicator, mut connections, mut datagram_receiver, remote_addr, own_addr) = create_test_setup();
+        let mut payload = Vec::new();
+        let message = OpenConnectionRequest2Message {
+            cookie_and_challenge: None,
+            binding_address: own_addr,
+            mtu: u16::MAX,
+            guid: REMOTE_GUID,
+            handoff_token: None,
+        };
+        message.write_message(&mut payload).expect("Could not write message");
+
+        // Act
+        handler.process_offline_packet(Instant::now(), remote_addr, &payload, &mut communicator, &mut connections);
+
+        // Assert
+        let (message, _addr) = receive_datagram::<OpenConnectionReply2Message>(&mut datagram_receiver);
+        assert_eq!(MAXIMUM_MTU_SIZE, message.mtu);
+        assert_eq!(MAXIMUM_MTU_SIZE, connections.get(&remote_addr).expect("No connection was created").mtu());
+    }
+
+    #[test]
+    fn open_connection_request_2_with_mtu_below_minimum_mtu_size_responds_with_connection_attempt_failed() {
+        // Arrange
+        let (mut handler, mut communicator, mut connections, mut datagram_receiver, remote_addr, own_addr) = create_test_setup();
+        let mut payload = Vec::new();
+        let message = OpenConnectionRequest2Message {
+            cookie_and_challenge: None,
+            binding_address: own_addr,
+            mtu: MINIMUM_MTU_SIZE - 1,
+            guid: REMOTE_GUID,
+            handoff_token: None,
+        };
+        message.write_message(&mut payload).expect("Could not write message");
+
+        // Act
+        handler.process_offline_packet(Instant::now(), remote_addr, &payload, &mut communicator, &mut connections);
+
+        // Assert
+        let (message, addr) = receive_datagram::<ConnectErrorMessage>(&mut datagram_receiver);
+        assert_eq!(remote_addr, addr);
+        assert_eq!(MessageId::ConnectionAttemptFailed, message.message_id);
+        assert!(connections.is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "rand")]
+    fn handoff_token_is_only_accepted_once() {
+        // Arrange
+        let config = Config { guid: OWN_GUID, handoff_key: Some(b"shared key".to_vec()), ..Config::default() };
+        let (mut handler, mut communicator, mut connections, mut datagram_receiver, remote_addr, own_addr) = create_test_setup_with_config(config);
+        handler.allow_list = Some(AllowList::new());
+        let time = Instant::now();
+        let expires_at = SystemTime::now() + Duration::from_secs(30);
+        let cookie = handler.cookie_generator.cookie(remote_addr, time.saturating_duration_since(handler.peer_creation_time), 0);
+        let mut payload = Vec::new();
+        let message = OpenConnectionRequest2Message {
+            cookie_and_challenge: Some((cookie, None)),
+            binding_address: own_addr,
+            mtu: 1024,
+            guid: REMOTE_GUID,
+            handoff_token: Some(HandoffToken::new(b"shared key", REMOTE_GUID, remote_addr.ip(), 42, expires_at)),
+        };
+        message.write_message(&mut payload).expect("Could not write message");
+
+        // Act
+        handler.process_offline_packet(time, remote_addr, &payload, &mut communicator, &mut connections);
+        let (first_reply, _addr) = receive_datagram::<OpenConnectionReply2Message>(&mut datagram_receiver);
+        handler.process_offline_packet(time, remote_addr, &payload, &mut communicator, &mut connections);
+        let (duplicate_reply, _addr) = receive_datagram::<OpenConnectionReply2Message>(&mut datagram_receiver);
+        connections.clear();
+        handler.process_offline_packet(time, remote_addr, &payload, &mut communicator, &mut connections);
+
+        // Assert
+        let (reused_reply, _addr) = receive_datagram::<ConnectErrorMessage>(&mut datagram_receiver);
+        assert_eq!(OWN_GUID, first_reply.guid);
+        assert_eq!(OWN_GUID, duplicate_reply.guid);
+        assert_eq!(MessageId::ConnectionBanned, reused_reply.message_id);
+        assert!(connections.is_empty());
+    }
+}
@@ -0,0 +1,192 @@
+use std::{collections::HashMap, net::IpAddr, time::{Duration, Instant}};
+
+/// The interval between the scans for full buckets. A bucket is full at the latest one
+/// second after it was last used, so the buckets are kept at most twice as long as needed.
+const FULL_BUCKET_REMOVAL_INTERVAL: Duration = Duration::from_secs(1);
+
+struct TokenBucket {
+    tokens: f64,
+    last_update_time: Instant,
+    is_exceeded: bool,
+}
+
+/// The result of checking whether a packet may be handled.
+#[derive(Debug, PartialEq)]
+pub enum RateLimit {
+    Allowed,
+    /// The packet should be dropped. `first` is true for the first packet
+    /// that is dropped after the IP address was below the limit.
+    Exceeded { first: bool },
+}
+
+/// Limits the number of offline packets that are handled per second from each IP address
+/// so that the peer cannot be used to amplify traffic towards a spoofed address and
+/// floods of pings or connection requests do not consume much CPU.
+///
+/// Each IP address has a token bucket that is refilled at the maximum rate and
+/// that holds at most one second worth of packets.
+pub struct OfflineRateLimiter {
+    buckets: HashMap<IpAddr, TokenBucket>,
+    /// The time of the last scan for full buckets.
+    last_removal_time: Option<Instant>,
+}
+
+impl OfflineRateLimiter {
+    pub fn new() -> Self {
+        OfflineRateLimiter {
+            buckets: HashMap::new(),
+            last_removal_time: None,
+        }
+    }
+
+    /// Returns whether a packet from the IP address may be handled at the specified time
+    /// and takes a token from its bucket. If `max_packets_per_second` is 0 all packets are allowed.
+    pub fn check(&mut self, time: Instant, ip: IpAddr, max_packets_per_second: u32) -> RateLimit {
+        if max_packets_per_second == 0 {
+            return RateLimit::Allowed;
+        }
+        let max_tokens = max_packets_per_second as f64;
+        let bucket = self.buckets.entry(ip).or_insert(TokenBucket { tokens: max_tokens, last_update_time: time, is_exceeded: false });
+        let elapsed = time.saturating_duration_since(bucket.last_update_time).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * max_tokens).min(max_tokens);
+        bucket.last_update_time = time;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            bucket.is_exceeded = false;
+            RateLimit::Allowed
+        } else {
+            let first = !bucket.is_exceeded;
+            bucket.is_exceeded = true;
+            RateLimit::Exceeded { first }
+        }
+    }
+
+    /// Removes the buckets that have been refilled completely since they were
+    /// last used so that memory is not held for IP addresses that have stopped sending.
+    /// The buckets are scanned at most once per `FULL_BUCKET_REMOVAL_INTERVAL`.
+    pub fn remove_full_buckets(&mut self, time: Instant, max_packets_per_second: u32) {
+        if self.last_removal_time.is_some_and(|last_removal_time| time.saturating_duration_since(last_removal_time) < FULL_BUCKET_REMOVAL_INTERVAL) {
+            return;
+        }
+        self.last_removal_time = Some(time);
+        let max_tokens = max_packets_per_second as f64;
+        self.buckets.retain(|_, bucket| {
+            let elapsed = time.saturating_duration_since(bucket.last_update_time).as_secs_f64();
+            bucket.tokens + elapsed * max_tokens < max_tokens
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::IpAddr, time::{Duration, Instant}};
+    use super::{OfflineRateLimiter, RateLimit, FULL_BUCKET_REMOVAL_INTERVAL};
+
+    #[test]
+    fn packets_over_rate_are_not_allowed() {
+        // Arrange
+        let mut limiter = OfflineRateLimiter::new();
+        let ip = "127.0.0.1".parse::<IpAddr>().expect("Could not create address");
+        let time = Instant::now();
+
+        // Act
+        let allowed = (0..15).filter(|_| limiter.check(time, ip, 10) == RateLimit::Allowed).count();
+
+        // Assert
+        assert_eq!(10, allowed);
+    }
+
+    #[test]
+    fn bucket_is_refilled_over_time() {
+        // Arrange
+        let mut limiter = OfflineRateLimiter::new();
+        let ip = "127.0.0.1".parse::<IpAddr>().expect("Could not create address");
+        let time = Instant::now();
+        for _ in 0..10 {
+            limiter.check(time, ip, 10);
+        }
+
+        // Act
+        let allowed_before_refill = limiter.check(time, ip, 10) == RateLimit::Allowed;
+        let allowed_after_refill = limiter.check(time + Duration::from_millis(100), ip, 10) == RateLimit::Allowed;
+
+        // Assert
+        assert!(!allowed_before_refill);
+        assert!(allowed_after_refill);
+    }
+
+    #[test]
+    fn ip_addresses_are_limited_separately() {
+        // Arrange
+        let mut limiter = OfflineRateLimiter::new();
+        let ip = "127.0.0.1".parse::<IpAddr>().expect("Could not create address");
+        let other_ip = "127.0.0.2".parse::<IpAddr>().expect("Could not create address");
+        let time = Instant::now();
+        for _ in 0..10 {
+            limiter.check(time, ip, 10);
+        }
+
+        // Act
+        let allowed = limiter.check(time, other_ip, 10) == RateLimit::Allowed;
+
+        // Assert
+        assert!(allowed);
+    }
+
+    #[test]
+    fn only_first_packet_over_rate_is_reported_as_first() {
+        // Arrange
+        let mut limiter = OfflineRateLimiter::new();
+        let ip = "127.0.0.1".parse::<IpAddr>().expect("Could not create address");
+        let time = Instant::now();
+        for _ in 0..10 {
+            limiter.check(time, ip, 10);
+        }
+
+        // Act
+        let first = limiter.check(time, ip, 10);
+        let second = limiter.check(time, ip, 10);
+        let after_refill = limiter.check(time + Duration::from_millis(100), ip, 10);
+        let exceeded_again = limiter.check(time + Duration::from_millis(100), ip, 10);
+
+        // Assert
+        assert_eq!(RateLimit::Exceeded { first: true }, first);
+        assert_eq!(RateLimit::Exceeded { first: false }, second);
+        assert_eq!(RateLimit::Allowed, after_refill);
+        assert_eq!(RateLimit::Exceeded { first: true }, exceeded_again);
+    }
+
+    #[test]
+    fn full_buckets_are_removed() {
+        // Arrange
+        let mut limiter = OfflineRateLimiter::new();
+        let ip = "127.0.0.1".parse::<IpAddr>().expect("Could not create address");
+        let time = Instant::now();
+        limiter.check(time, ip, 10);
+
+        // Act
+        limiter.remove_full_buckets(time, 10);
+        let bucket_count_before_refill = limiter.buckets.len();
+        limiter.remove_full_buckets(time + FULL_BUCKET_REMOVAL_INTERVAL, 10);
+
+        // Assert
+        assert_eq!(1, bucket_count_before_refill);
+        assert!(limiter.buckets.is_empty());
+    }
+
+    #[test]
+    fn full_buckets_are_not_scanned_before_removal_interval() {
+        // Arrange
+        let mut limiter = OfflineRateLimiter::new();
+        let ip = "127.0.0.1".parse::<IpAddr>().expect("Could not create address");
+        let time = Instant::now();
+        limiter.remove_full_buckets(time, 10);
+        limiter.check(time, ip, 10);
+
+        // Act
+        limiter.remove_full_buckets(time + Duration::from_millis(100), 10);
+
+        // Assert
+        assert_eq!(1, limiter.buckets.len());
+    }
+}
@@ -1,4 +1,4 @@
-use std::{cmp::{Ord, Ordering}, collections::BinaryHeap};
+use std::{cmp::{Ord, Ordering}, collections::BinaryHeap, time::{Duration, Instant}};
 
 use crate::number::{OrderingIndex, SequencingIndex};
 
@@ -35,6 +35,10 @@ pub struct OrderingChannel {
     expected_ordering_index: OrderingIndex,
     expected_sequencing_index: SequencingIndex,
     packets: BinaryHeap<PacketWithWeight>,
+    /// The missing ordering index that buffered packets wait for and the time the wait started.
+    blocked: Option<(OrderingIndex, Instant)>,
+    /// True if the current wait has been returned by `take_unreported_block`.
+    is_block_reported: bool,
 }
 
 impl OrderingChannel {
@@ -44,9 +48,42 @@ impl OrderingChannel {
             expected_ordering_index: OrderingIndex::ZERO,
             expected_sequencing_index: SequencingIndex::ZERO,
             packets: BinaryHeap::new(),
+            blocked: None,
+            is_block_reported: false,
         }
     }
 
+    /// Returns the number of packets buffered since they arrived before a missing ordering index.
+    pub fn buffered_packet_count(&self) -> usize {
+        self.packets.len()
+    }
+
+    /// Updates when the channel started waiting for the missing ordering index.
+    /// Call this after incoming packets have been processed.
+    pub fn update_blocked(&mut self, time: Instant) {
+        let blocked = match self.blocked {
+            _ if self.packets.is_empty() => None,
+            Some((ordering_index, since)) if ordering_index == self.expected_ordering_index => Some((ordering_index, since)),
+            _ => Some((self.expected_ordering_index, time)),
+        };
+        if blocked != self.blocked {
+            self.is_block_reported = false;
+        }
+        self.blocked = blocked;
+    }
+
+    /// Returns for how long the channel has waited for the missing ordering index if
+    /// longer than the threshold and not returned before for the same wait.
+    pub fn take_unreported_block(&mut self, time: Instant, threshold: Duration) -> Option<Duration> {
+        let (_, since) = self.blocked?;
+        let blocked_for = time.saturating_duration_since(since);
+        if self.is_block_reported || blocked_for <= threshold {
+            return None;
+        }
+        self.is_block_reported = true;
+        Some(blocked_for)
+    }
+
     pub fn process_incoming(&mut self, sequencing_index: Option<SequencingIndex>, ordering_index: OrderingIndex, payload: Box<[u8]>) -> Option<Box<[u8]>> {
         if ordering_index == self.expected_ordering_index {
             if let Some(sequencing_index) = sequencing_index {
@@ -132,6 +169,7 @@ impl<'a> Iterator for IterMut<'a> {
 
 #[cfg(test)]
 mod tests {
+    use std::time::{Duration, Instant};
     use crate::number::{OrderingIndex, SequencingIndex};
     use super::OrderingChannel;
 
@@ -252,5 +290,30 @@ mod tests {
         // Assert
         assert_eq!(packet, Some(vec![1, 2, 3].into_boxed_slice()));
         assert!(packets.is_empty());
-    }      
-}
\ No newline at end of file
+    }
+
+    #[test]
+    fn block_waiting_for_missing_ordering_index_is_reported_once() {
+        // Arrange
+        let mut channel = OrderingChannel::new();
+        let time = Instant::now();
+        let threshold = Duration::from_millis(100);
+        channel.process_incoming(None, OrderingIndex::ONE, vec![1].into_boxed_slice());
+        channel.update_blocked(time);
+
+        // Act
+        let early = channel.take_unreported_block(time + Duration::from_millis(50), threshold);
+        let late = channel.take_unreported_block(time + Duration::from_millis(150), threshold);
+        let repeated = channel.take_unreported_block(time + Duration::from_millis(200), threshold);
+        channel.process_incoming(None, OrderingIndex::ZERO, vec![0].into_boxed_slice());
+        let _: Vec<Box<[u8]>> = channel.iter_mut().collect();
+        channel.update_blocked(time + Duration::from_millis(200));
+
+        // Assert
+        assert_eq!(None, early);
+        assert_eq!(Some(Duration::from_millis(150)), late);
+        assert_eq!(None, repeated);
+        assert_eq!(0, channel.buffered_packet_count());
+        assert_eq!(None, channel.take_unreported_block(time + Duration::from_millis(500), threshold));
+    }
+}
@@ -20,6 +20,21 @@ impl OrderingSystem {
             None
         }
     }
+
+    /// Returns the channels that have been used.
+    pub fn channels_mut(&mut self) -> impl Iterator<Item = (u8, &mut OrderingChannel)> {
+        self.channels.iter_mut().map(|(channel_index, channel)| (*channel_index, channel))
+    }
+
+    /// Returns the number of buffered packets of each channel with buffered packets, ordered by channel index.
+    pub fn buffered_packet_counts(&self) -> Vec<(u8, usize)> {
+        let mut counts: Vec<(u8, usize)> = self.channels.iter()
+            .map(|(channel_index, channel)| (*channel_index, channel.buffered_packet_count()))
+            .filter(|(_, count)| *count > 0)
+            .collect();
+        counts.sort_unstable();
+        counts
+    }
 }
 
 #[cfg(test)]
@@ -1,6 +1,6 @@
-use std::{cmp::{Ord, Ordering}, collections::BinaryHeap};
+use std::{cmp::{Ord, Ordering}, collections::{BinaryHeap, HashMap, VecDeque}, time::Instant};
 
-use crate::{constants::NUMBER_OF_PRIORITIES, internal_packet::InternalPacket, packet::Priority};
+use crate::{constants::NUMBER_OF_PRIORITIES, internal_packet::{InternalPacket, InternalReliability}, packet::Priority};
 
 type PriorityLevel = u64;
 type HeapWeight = u64;
@@ -8,8 +8,10 @@ type HeapWeight = u64;
 #[derive(Debug)]
 struct HeapItem {
     weight: HeapWeight,
-    priority_level: PriorityLevel,
+    priority: Priority,
     packet: InternalPacket,
+    /// The key of the remaining parts if the packet is a part of a split packet.
+    split_parts_key: Option<u64>,
 }
 
 impl Ord for HeapItem {
@@ -33,10 +35,18 @@ impl PartialEq for HeapItem {
 
 impl Eq for HeapItem {}
 
+/// A priority queue of outgoing packets.
+///
+/// Only one part at a time of each split packet is kept in the heap. The next part
+/// is pushed when the previous part is popped which puts it behind packets of the same
+/// priority pushed in the meantime. Thereby parts of large split packets are interleaved
+/// with each other and with other packets instead of delaying them until all parts are sent.
 #[derive(Debug)]
 pub struct OutgoingPacketHeap {
     packets: BinaryHeap<HeapItem>,
     next_weights: [HeapWeight; NUMBER_OF_PRIORITIES],
+    split_parts: HashMap<u64, VecDeque<InternalPacket>>,
+    next_split_parts_key: u64,
 }
 
 impl OutgoingPacketHeap {
@@ -44,21 +54,47 @@ impl OutgoingPacketHeap {
         OutgoingPacketHeap {      
             packets: BinaryHeap::new(),
             next_weights: Self::get_initial_heap_weights(),
+            split_parts: HashMap::new(),
+            next_split_parts_key: 0,
         }
     }
 
     pub fn push(&mut self, priority: Priority, packet: InternalPacket) {
-        let weight = self.get_next_weight(priority);
-        self.packets.push(HeapItem { weight, priority_level: priority as PriorityLevel, packet });
+        self.push_item(priority, packet, None);
+    }
+
+    /// Pushes the parts of a split packet. The parts are popped in order
+    /// interleaved with other packets.
+    pub fn push_split_packet(&mut self, priority: Priority, parts: Vec<InternalPacket>) {
+        let mut parts = VecDeque::from(parts);
+        if let Some(first_part) = parts.pop_front() {
+            let key = self.next_split_parts_key;
+            self.next_split_parts_key = self.next_split_parts_key.wrapping_add(1);
+            self.push_item(priority, first_part, Some(key));
+            if !parts.is_empty() {
+                self.split_parts.insert(key, parts);
+            }
+        }
     }
 
     #[allow(dead_code)]
     pub fn pop(&mut self) -> Option<InternalPacket> {
-        if let Some(item) = self.packets.pop() {
-            Some(item.packet)
-        } else {
-            None
+        let item = self.packets.pop()?;
+        if let Some(key) = item.split_parts_key {
+            let next_part = self.split_parts.get_mut(&key).and_then(|parts| parts.pop_front());
+            if let Some(next_part) = next_part {
+                if self.split_parts.get(&key).is_some_and(|parts| parts.is_empty()) {
+                    self.split_parts.remove(&key);
+                }
+                self.push_item(item.priority, next_part, Some(key));
+            }
         }
+        Some(item.packet)
+    }
+
+    fn push_item(&mut self, priority: Priority, packet: InternalPacket, split_parts_key: Option<u64>) {
+        let weight = self.get_next_weight(priority);
+        self.packets.push(HeapItem { weight, priority, packet, split_parts_key });
     }
 
     pub fn peek(&self) -> Option<&InternalPacket> {
@@ -69,11 +105,58 @@ impl OutgoingPacketHeap {
         }
     }
 
+    /// Returns the number of packets in the heap, including the parts of split packets that are held back.
+    pub fn len(&self) -> usize {
+        self.packets.len() + self.split_parts.values().map(|parts| parts.len()).sum::<usize>()
+    }
+
+    /// Returns true if the heap has no packets. Held back parts of split packets
+    /// are always preceded by a part in the heap.
+    pub fn is_empty(&self) -> bool {
+        self.packets.is_empty()
+    }
+
+    /// Returns the number of payload bytes of all packets in the heap.
+    pub fn payload_bytes(&self) -> usize {
+        self.packets.iter().map(|item| item.packet.payload().len()).sum::<usize>() +
+            self.split_parts.values().flatten().map(|packet| packet.payload().len()).sum::<usize>()
+    }
+
+    /// Removes and returns all unreliable packets created before `created_before`.
+    pub fn remove_unreliable_created_before(&mut self, created_before: Instant) -> Vec<InternalPacket> {
+        let (expired, kept): (Vec<HeapItem>, Vec<HeapItem>) = std::mem::take(&mut self.packets)
+            .into_vec()
+            .into_iter()
+            .partition(|item| item.packet.reliability() == InternalReliability::Unreliable && item.packet.creation_time() < created_before);
+        self.packets = BinaryHeap::from(kept);
+        expired.into_iter().map(|item| item.packet).collect()
+    }
+
+    /// Removes and returns all packets that are not split packet parts and that have
+    /// a payload larger than `max_payload_size` together with their priority,
+    /// in the order they would have been popped.
+    pub fn remove_packets_larger_than(&mut self, max_payload_size: usize) -> Vec<(Priority, InternalPacket)> {
+        let (mut removed, kept): (Vec<HeapItem>, Vec<HeapItem>) = std::mem::take(&mut self.packets)
+            .into_vec()
+            .into_iter()
+            .partition(|item| !item.packet.is_split_packet() && item.packet.payload().len() > max_payload_size);
+        self.packets = BinaryHeap::from(kept);
+        removed.sort_by_key(|item| item.weight);
+        removed.into_iter().map(|item| (item.priority, item.packet)).collect()
+    }
+
+    /// Removes and returns all packets, including the parts of split packets that are held back.
+    pub fn drain(&mut self) -> Vec<InternalPacket> {
+        let mut packets: Vec<InternalPacket> = std::mem::take(&mut self.packets).into_vec().into_iter().map(|item| item.packet).collect();
+        packets.extend(self.split_parts.drain().flat_map(|(_, parts)| parts));
+        packets
+    }
+
     fn get_next_weight(&mut self, priority: Priority) -> HeapWeight {
         let priority_level = priority as u64;
         let mut next_weight = self.next_weights[priority_level as usize];
         if let Some(item) = self.packets.peek() {
-            let peek_priority_level = item.priority_level;
+            let peek_priority_level = item.priority as PriorityLevel;
             let peek_weight = item.weight;
             let min = peek_weight - (1 << peek_priority_level) * peek_priority_level + peek_priority_level;
             if next_weight < min {
@@ -102,8 +185,8 @@ impl OutgoingPacketHeap {
 
 #[cfg(test)]
 mod tests {
-    use std::time::Instant;
-    use crate::{internal_packet::{InternalOrdering, InternalPacket, InternalReliability}, packet::Priority};
+    use std::time::{Duration, Instant};
+    use crate::{internal_packet::{InternalOrdering, InternalPacket, InternalReliability}, number::MessageNumber, packet::Priority};
     use super::OutgoingPacketHeap;
 
     #[test]
@@ -191,4 +274,75 @@ mod tests {
         assert!(matches!(heap.pop(), None));
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn remove_unreliable_created_before_keeps_new_and_reliable_packets() {
+        // Arrange
+        let time = Instant::now();
+        let mut heap = OutgoingPacketHeap::new();
+        heap.push(Priority::Medium, InternalPacket::new(time, InternalReliability::Unreliable, InternalOrdering::None, None, None, vec![1].into_boxed_slice()));
+        heap.push(Priority::Medium, InternalPacket::new(time, InternalReliability::Reliable(Some(MessageNumber::ZERO)), InternalOrdering::None, None, None, vec![2].into_boxed_slice()));
+        heap.push(Priority::Medium, InternalPacket::new(time + Duration::from_millis(100), InternalReliability::Unreliable, InternalOrdering::None, None, None, vec![3].into_boxed_slice()));
+
+        // Act
+        let expired = heap.remove_unreliable_created_before(time + Duration::from_millis(50));
+
+        // Assert
+        assert_eq!(1, expired.len());
+        assert_eq!(&[1], expired[0].payload());
+        assert_eq!(&[2], heap.pop().expect("Reliable packet was removed").payload());
+        assert_eq!(&[3], heap.pop().expect("New packet was removed").payload());
+        assert!(heap.pop().is_none());
+    }
+
+    fn create_packets(payloads: &[u8]) -> Vec<InternalPacket> {
+        payloads.iter().map(|payload| InternalPacket::new(Instant::now(), InternalReliability::Reliable(None), InternalOrdering::None, None, None, vec![*payload].into_boxed_slice())).collect()
+    }
+
+    fn pop_all(heap: &mut OutgoingPacketHeap) -> Vec<u8> {
+        std::iter::from_fn(|| heap.pop()).map(|packet| packet.payload()[0]).collect()
+    }
+
+    #[test]
+    fn push_split_packet_interleaves_parts_with_later_packets() {
+        // Arrange
+        let mut heap = OutgoingPacketHeap::new();
+        heap.push_split_packet(Priority::Medium, create_packets(&[1, 2, 3, 4]));
+        heap.pop();
+
+        // Act
+        for packet in create_packets(&[10, 11]) {
+            heap.push(Priority::Medium, packet);
+        }
+
+        // Assert
+        assert_eq!(vec![2, 10, 11, 3, 4], pop_all(&mut heap));
+    }
+
+    #[test]
+    fn push_split_packet_interleaves_parts_of_split_packets() {
+        // Arrange
+        let mut heap = OutgoingPacketHeap::new();
+
+        // Act
+        heap.push_split_packet(Priority::Low, create_packets(&[1, 2, 3]));
+        heap.push_split_packet(Priority::Low, create_packets(&[10, 11, 12, 13]));
+
+        // Assert
+        assert_eq!(vec![1, 10, 2, 11, 3, 12, 13], pop_all(&mut heap));
+    }
+
+    #[test]
+    fn push_split_packet_keeps_priority() {
+        // Arrange
+        let mut heap = OutgoingPacketHeap::new();
+        heap.push_split_packet(Priority::Low, create_packets(&[1, 2, 3]));
+
+        // Act
+        for packet in create_packets(&[10, 11]) {
+            heap.push(Priority::Highest, packet);
+        }
+
+        // Assert
+        assert_eq!(vec![10, 11, 1, 2, 3], pop_all(&mut heap));
+    }
+}
@@ -1,4 +1,4 @@
-use std::net::SocketAddr;
+use std::{net::SocketAddr, time::Instant};
 
 use crate::OrderingChannelIndex;
 
@@ -7,17 +7,35 @@ pub struct Packet {
     addr: SocketAddr,
     guid: u64,
     payload: Box<[u8]>,
+    received_at: Instant,
+    received_ms: u64,
 }
 
 impl Packet {
-    pub(crate) fn new(addr: SocketAddr, guid: u64, payload: Box<[u8]>) -> Self {
+    pub(crate) fn new(addr: SocketAddr, guid: u64, payload: Box<[u8]>, received_at: Instant, received_ms: u64) -> Self {
         Packet {
             addr,
             guid,
             payload,
+            received_at,
+            received_ms,
         }        
     }
 
+    /// Returns the time of the processing round in which the datagram that made
+    /// the packet available was received, e.g. the last part of a split packet
+    /// or the missing packet that an ordered packet waited for.
+    pub fn received_at(&self) -> Instant {
+        self.received_at
+    }
+
+    /// Returns the same time as `received_at` in milliseconds since the peer
+    /// was created, see `Peer::start_time`, e.g. for timestamps in logs or
+    /// lag compensation that do not depend on `Instant`.
+    pub fn received_ms(&self) -> u64 {
+        self.received_ms
+    }
+
     pub fn addr(&self) -> SocketAddr {
         self.addr
     }
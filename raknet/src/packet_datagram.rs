@@ -4,19 +4,18 @@ use crate::{
     error::Result,
     internal_packet::InternalPacket,
     number::DatagramSequenceNumber,
-    writer::DataWrite
+    writer::{DataWrite, PacketWrite},
 };
 
 #[derive(Debug)]
 pub struct PacketDatagram {
     header: DatagramHeader,
     packets: Vec<InternalPacket>,
-    payload_size: u16,
+    payload_size: usize,
 }
 
 impl PacketDatagram {
     pub fn new(datagram_number: DatagramSequenceNumber) -> Self {
-        // TODO: Perhaps set is_continuous_send for second datagram
         PacketDatagram {
             header: DatagramHeader::Packet {
                 is_packet_pair: false,
@@ -29,8 +28,22 @@ impl PacketDatagram {
         }
     }
 
+    /// Marks the datagram as sent directly after the previous datagram.
+    pub fn set_continuous_send(&mut self) {
+        if let DatagramHeader::Packet { is_continuous_send, .. } = &mut self.header {
+            *is_continuous_send = true;
+        }
+    }
+
+    /// Requests the remote peer to include the data arrival rate in the ACK for this datagram.
+    pub fn set_needs_data_arrival_rate(&mut self) {
+        if let DatagramHeader::Packet { needs_data_arrival_rate, .. } = &mut self.header {
+            *needs_data_arrival_rate = true;
+        }
+    }
+
     pub fn push(&mut self, packet: InternalPacket) {
-        self.payload_size = self.payload_size + packet.get_size_in_bytes();
+        self.payload_size += packet.written_size();
         self.packets.push(packet);
     }
 
@@ -43,24 +56,28 @@ impl PacketDatagram {
     }
 
     pub fn has_room_for(&self, packet: &InternalPacket, mtu: u16) -> bool {
-        let packet_size = packet.get_size_in_bytes();
-        if self.payload_size + packet_size > Self::get_max_payload_size(mtu) {
-            false
-        } else {
-            true
-        }
+        self.payload_size + packet.written_size() <= Self::get_max_payload_size(mtu) as usize
     }
 
     pub fn get_max_payload_size(mtu: u16) -> u16 {
-        // Datagram bitflags (u8) + datagram number (u24)
-        let datagram_header_size = 1 + 3;
-        mtu - UDP_HEADER_SIZE - datagram_header_size
+        // The flags of a datagram header only change which bits are set, not its size
+        let datagram_header = DatagramHeader::Packet {
+            is_packet_pair: false,
+            is_continuous_send: false,
+            needs_data_arrival_rate: false,
+            datagram_number: DatagramSequenceNumber::ZERO,
+        };
+        mtu - UDP_HEADER_SIZE - datagram_header.written_size() as u16
     }
 
     pub fn is_empty(&self) -> bool {
         self.packets.is_empty()
     }
 
+    pub fn packets(&self) -> &[InternalPacket] {
+        &self.packets
+    }
+
     pub fn into_packets(self) -> Vec<InternalPacket> {
         self.packets
     }
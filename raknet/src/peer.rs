@@ -1,22 +1,58 @@
 use std::{
-    net::{UdpSocket, ToSocketAddrs},
+    io,
+    net::{SocketAddr, UdpSocket, ToSocketAddrs},
+    path::Path,
+    sync::Arc,
     time::{Duration, Instant},
 };
-use log::info;
+use socket2::{Domain, Protocol, Socket, Type};
 use crossbeam_channel::{unbounded, Sender, Receiver, Select};
 
 use crate::{
+    AdmissionPolicy,
+    AllowList,
+    BanFile,
+    Clock,
     Config,
+    ConfigUpdate,
+    ConnectOptions,
+    ConnectionAttemptFailedReason,
+    ConnectionHandle,
+    ConnectionInfo,
+    ConnectionLimits,
+    ConnectionStats,
+    ConnectedUdpSocket,
+    HandoffToken,
+    CapturedDatagram,
+    Error,
+    InvalidConfig,
+    IpRange,
     connection_manager::ConnectionManager,
+    MultiSocket,
     Result,
     PeerEvent,
+    PacketCapture,
+    PcapWriter,
+    PeerMetrics,
+    PingResponseProvider,
+    RemotePeer,
+    SendOptions,
+    SendTarget,
+    TickStats,
+    logging::{debug, error, info},
+    socket::DatagramSocket,
+    SystemClock,
 };
 
-pub struct Peer
+#[cfg(feature = "async")]
+use crate::TokioUdpSocket;
+
+pub struct Peer<T: DatagramSocket = UdpSocket>
 {
-    connection_manager: ConnectionManager<UdpSocket>,
+    connection_manager: ConnectionManager<T>,
     command_sender: Sender<Command>,
     command_receiver: Receiver<Command>,
+    next_receipt: u32,
 }
 
 /// Commands that can sent over the command sender
@@ -35,6 +71,90 @@ pub enum Command
     /// If the response is longer than 399 bytes it will be truncated.
     /// This does the same as the `set_offline_ping_response` method.
     SetOfflinePingResponse(Vec<u8>),
+    /// Sets a function that creates the response to each offline ping packet.
+    /// This does the same as the `set_ping_response_provider` method.
+    SetPingResponseProvider(PingResponseProvider),
+    /// Sets the policy deciding which remote peers may connect or removes it if None.
+    /// This does the same as the `set_admission_policy` and `remove_admission_policy` methods.
+    SetAdmissionPolicy(Option<Box<dyn AdmissionPolicy>>),
+    /// Sends an unconnected ping to an address.
+    /// This does the same as the `ping_address` method.
+    PingAddress(SocketAddr),
+    /// Changes the configuration while the peer is running.
+    /// This does the same as the `update_config` method.
+    UpdateConfig(ConfigUpdate),
+    /// Starts connecting to a remote peer.
+    /// This does the same as the `connect` method.
+    Connect(SocketAddr),
+    /// Starts reconnecting to a remote peer whenever the connection is lost.
+    /// This does the same as the `start_reconnecting` method.
+    StartReconnecting(SocketAddr, ConnectOptions),
+    /// Stops reconnecting to a remote peer.
+    /// This does the same as the `stop_reconnecting` method.
+    StopReconnecting(SocketAddr),
+    /// Enqueues a packet for sending to the connected remote peers selected by the target.
+    /// This does the same as the `send_to_target` method.
+    Send {
+        target: SendTarget,
+        options: SendOptions,
+        payload: Vec<u8>,
+    },
+    /// Closes the connection to the remote peer with the GUID.
+    /// This does the same as the `disconnect` method.
+    Disconnect { guid: u64, flush: bool },
+    /// Sends queued packets without waiting for the coalescing delay.
+    /// This does the same as the `flush` method.
+    Flush,
+    /// Sets the maximum number of bytes per second sent over a connection.
+    /// This does the same as the `set_connection_bandwidth_limit` method.
+    SetConnectionBandwidthLimit(SocketAddr, u64),
+    /// Sets the maximum number of bytes per second sent over the connection to the remote peer with the GUID.
+    /// This does the same as the `set_connection_bandwidth_limit_by_guid` method.
+    SetConnectionBandwidthLimitByGuid { guid: u64, max_bytes_per_second: u64 },
+    /// Sends a `PeerEvent::Connections` with a snapshot of every connection.
+    /// This gives the same connections as the `connections` method.
+    QueryConnections,
+    /// Sends a snapshot of the connection to the remote peer with the GUID, or None if
+    /// there is no connection to it, over the reply sender. Used by `RemotePeer::stats`.
+    QueryConnection { guid: u64, reply_sender: Sender<Option<ConnectionInfo>> },
+    /// Sends the metrics of the peer over the reply sender, e.g. to a thread serving
+    /// them to a monitoring system. This gives the same metrics as the `metrics` method.
+    QueryMetrics { reply_sender: Sender<PeerMetrics> },
+    /// Overrides the limits in `Config` for the connection to the remote peer with the GUID.
+    /// This does the same as the `set_connection_limits` method.
+    SetConnectionLimits { guid: u64, limits: ConnectionLimits },
+    /// Bans a range of IP addresses for a duration or forever if the duration is None.
+    /// This does the same as the `ban_address` method.
+    BanAddress(IpRange, Option<Duration>),
+    /// Removes the ban of a range of IP addresses.
+    /// This does the same as the `unban_address` method.
+    UnbanAddress(IpRange),
+    /// Bans the ranges of IP addresses in the ban file.
+    /// This does the same as the `add_bans` method.
+    AddBans(BanFile),
+    /// Sends the bans of the peer over the reply sender, e.g. to save them before the peer
+    /// is stopped when it runs in another thread. This gives the same bans as the `bans` method.
+    QueryBans { reply_sender: Sender<BanFile> },
+    /// Enables allow-list mode with the allow list or disables it if None.
+    /// This does the same as the `set_allow_list` method.
+    SetAllowList(Option<AllowList>),
+    /// Adds a range of IP addresses to the allow list.
+    /// This does the same as the `allow_address` method.
+    AllowAddress(IpRange),
+    /// Adds a GUID to the allow list.
+    /// This does the same as the `allow_guid` method.
+    AllowGuid(u64),
+    /// Removes a range of IP addresses from the allow list.
+    /// This does the same as the `disallow_address` method.
+    DisallowAddress(IpRange),
+    /// Removes a GUID from the allow list.
+    /// This does the same as the `disallow_guid` method.
+    DisallowGuid(u64),
+    /// Starts capturing every datagram sent or received, replacing any previous
+    /// capture, or stops capturing if None. Use `PcapWriter::into_packet_capture`
+    /// to write the datagrams to a pcap file.
+    /// This does the same as the `set_packet_capture` and `stop_packet_capture` methods.
+    SetPacketCapture(Option<PacketCapture>),
     /// Stops the processing loop.
     /// Use this to make `start_processing` and
     /// `start_processing_with_duration` return.
@@ -51,18 +171,157 @@ impl Peer {
     /// Creates a RakNetPeer with the specified `Config` and binds it to
     /// a UDP socket on the specified address.
     pub fn bind_with_config<A: ToSocketAddrs>(addr: A, config: Config) -> Result<Self> {
-        info!("Binding socket");
-        let socket = UdpSocket::bind(addr)?;
-        socket.set_broadcast(true)?;
-        socket.set_nonblocking(true)?;
+        Self::with_socket(bind_socket(addr, &config)?, config)
+    }
+}
+
+#[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+impl Peer {
+    /// Creates `worker_count` peers bound to the same address with `SO_REUSEPORT`
+    /// so that a busy server can use several threads, e.g. one `start_processing`
+    /// loop per worker in its own thread.
+    ///
+    /// The operating system distributes the remote peers between the workers by hashing
+    /// their addresses, so all datagrams from a remote peer reach the same worker.
+    /// All workers use the same `Config`, including the GUID, and
+    /// `Config::max_incoming_connections` applies to each worker.
+    pub fn bind_workers<A: ToSocketAddrs>(addr: A, worker_count: usize, config: Config) -> Result<Vec<Self>> {
+        let addr = addr.to_socket_addrs()?.next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Could not resolve to any addresses"))?;
+        let mut workers = Vec::with_capacity(worker_count);
+        // The workers bind to the port chosen for the first worker if the port is 0
+        let mut worker_addr = addr;
+        for _ in 0..worker_count {
+            let socket = bind_socket_addr_with(worker_addr, &config, |socket| socket.set_reuse_port(true))?;
+            worker_addr = socket.local_addr()?;
+            info!("Worker listening on {}", worker_addr);
+            workers.push(Self::with_socket(socket, config.clone())?);
+        }
+        Ok(workers)
+    }
+}
+
+impl Peer<MultiSocket<UdpSocket>> {
+    /// Creates a RakNetPeer with a default `Config` and binds it to
+    /// one UDP socket on each of the specified addresses.
+    pub fn bind_multi(addrs: &[SocketAddr]) -> Result<Self> {
+        Self::bind_multi_with_config(addrs, Config::default())
+    }
+
+    /// Creates a RakNetPeer with the specified `Config` and binds it to one UDP socket
+    /// on each of the specified addresses. Replies are sent out the socket that the
+    /// request arrived on and new outgoing connections use the first socket.
+    pub fn bind_multi_with_config(addrs: &[SocketAddr], config: Config) -> Result<Self> {
+        let sockets = addrs.iter().map(|addr| bind_socket(addr, &config)).collect::<Result<Vec<UdpSocket>>>()?;
+        let socket = MultiSocket::new(sockets)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "No addresses to bind to"))?;
+        Self::with_socket(socket, config)
+    }
+}
+
+impl Peer<ConnectedUdpSocket> {
+    /// Creates a RakNetPeer with a default `Config` for a client that only connects
+    /// to one remote peer, see `bind_connected_with_config`.
+    pub fn bind_connected<A: ToSocketAddrs>(addr: A, remote_addr: SocketAddr) -> Result<Self> {
+        Self::bind_connected_with_config(addr, remote_addr, Config::default())
+    }
+
+    /// Creates a RakNetPeer with the specified `Config` and binds it to a UDP socket on
+    /// the specified address that is connected to the remote address. Datagrams from other
+    /// addresses are dropped by the operating system and datagrams cannot be sent to other addresses.
+    /// Call `connect` with the remote address to connect to the remote peer.
+    pub fn bind_connected_with_config<A: ToSocketAddrs>(addr: A, remote_addr: SocketAddr, config: Config) -> Result<Self> {
+        let socket = ConnectedUdpSocket::new(bind_socket(addr, &config)?, remote_addr)?;
+        info!("Connected socket to {}", remote_addr);
+        Self::with_socket(socket, config)
+    }
+}
+
+/// Binds a UDP socket to the first of the addresses that can be bound.
+/// Sockets bound to IPv6 addresses are dual-stack unless `Config::ipv6_only` is set.
+fn bind_socket<A: ToSocketAddrs>(addr: A, config: &Config) -> Result<UdpSocket> {
+    info!("Binding socket");
+    let mut last_err = io::Error::new(io::ErrorKind::InvalidInput, "Could not resolve to any addresses");
+    for addr in addr.to_socket_addrs()? {
+        match bind_socket_addr(addr, config) {
+            Ok(socket) => {
+                info!("Listening on {}", socket.local_addr()?);
+                return Ok(socket);
+            },
+            Err(err) => last_err = err,
+        }
+    }
+    Err(last_err.into())
+}
+
+fn bind_socket_addr(addr: SocketAddr, config: &Config) -> io::Result<UdpSocket> {
+    bind_socket_addr_with(addr, config, |_| Ok(()))
+}
+
+/// Binds a UDP socket after setting the options that must be set before binding.
+fn bind_socket_addr_with<F>(addr: SocketAddr, config: &Config, set_options: F) -> io::Result<UdpSocket>
+    where F: FnOnce(&Socket) -> io::Result<()>
+{
+    let socket = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP))?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(config.ipv6_only)?;
+    }
+    set_options(&socket)?;
+    socket.bind(&addr.into())?;
+    socket.set_broadcast(true)?;
+    socket.set_nonblocking(true)?;
+    Ok(socket.into())
+}
+
+#[cfg(feature = "async")]
+impl Peer<TokioUdpSocket> {
+    /// Processes incoming and outgoing packets and the received commands until
+    /// `Command::StopProcessing` is received, like `start_processing_with_duration`
+    /// but without blocking the thread.
+    ///
+    /// Processing happens as soon as a datagram arrives or else after `sleep_time`.
+    /// Commands are executed in the next processing round.
+    pub async fn run(&mut self, sleep_time: Duration) {
+        let socket = self.socket();
+        if let Err(err) = socket.writable().await {
+            error!("Socket is not writable: {:?}", err);
+        }
+        loop {
+            self.process();
+            if !self.execute_commands() {
+                return;
+            }
+            tokio::select! {
+                _ = socket.readable() => {},
+                _ = tokio::time::sleep(sleep_time) => {},
+            }
+        }
+    }
 
-        info!("Listening on {}", socket.local_addr()?);
+    pub(crate) fn socket(&mut self) -> TokioUdpSocket {
+        self.connection_manager.socket().clone()
+    }
+}
+
+impl<T: DatagramSocket> Peer<T> {
+    /// Creates a RakNetPeer with the specified `Config` that sends and receives
+    /// datagrams with a custom socket. The socket must not block, see `DatagramSocket`.
+    ///
+    /// Returns `Error::InvalidConfig` if the configuration is invalid, see `Config::validate`.
+    pub fn with_socket(socket: T, config: Config) -> Result<Self> {
+        Self::with_clock(socket, config, SystemClock)
+    }
 
+    /// Creates a RakNetPeer like `with_socket` that takes the current time from the clock
+    /// instead of the operating system, e.g. a `ManualClock` that lets tests and simulations
+    /// pass timeouts without sleeping.
+    pub fn with_clock<C: Clock + 'static>(socket: T, config: Config, clock: C) -> Result<Self> {
         let (command_sender, command_receiver) = unbounded();
         Ok(Peer {
-            connection_manager: ConnectionManager::new(socket, config),
+            connection_manager: ConnectionManager::with_clock(socket, config, Arc::new(clock))?,
             command_sender,
-            command_receiver,           
+            command_receiver,
+            next_receipt: 0,
         })
     }
 
@@ -72,15 +331,22 @@ impl Peer {
     /// events. For an automatic processing loop use `start_processing`
     /// or `start_processing_with_duration` instead.
     pub fn process(&mut self) {
-        self.connection_manager.process(Instant::now());
+        self.connection_manager.process(self.connection_manager.now());
+    }
+
+    /// Returns the time to wait between processing rounds, which is
+    /// `Config::idle_tick_interval_in_ms` while there are no connections and no
+    /// connection attempts and `Config::tick_interval_in_ms` otherwise.
+    pub fn tick_interval(&self) -> Duration {
+        self.connection_manager.tick_interval()
     }
 
     /// Starts a loop that processes incoming and outgoing
-    /// packets with a default sleep time of 1 ms between processing.
+    /// packets with the sleep time returned by `tick_interval` between processing.
     /// 
     /// This method blocks and should be called from a spawned thread.
     pub fn start_processing(&mut self) {       
-        self.start_processing_with_duration(Duration::from_millis(1));
+        self.processing_loop(None);
     }
 
     /// Starts a loop that processes incoming and outgoing
@@ -88,29 +354,123 @@ impl Peer {
     /// 
     /// This method blocks and should be called from a spawned thread.
     pub fn start_processing_with_duration(&mut self, sleep_time: Duration) {       
+        self.processing_loop(Some(sleep_time));
+    }
+
+    fn processing_loop(&mut self, sleep_time: Option<Duration>) {
         loop {
             // Process all network packages and events
             self.process();
             
             // Wait for sleep_time to pass or until a command arrives
+            let sleep_time = sleep_time.unwrap_or_else(|| self.tick_interval());
             let mut sel = Select::new();
             sel.recv(&self.command_receiver);
             match sel.ready_timeout(sleep_time) {
                 _ => {}
             }
 
-            // Perform all received commands
-            while let Ok(command) = self.command_receiver.try_recv() {
-                match command
-                {
-                    Command::ProcessNow => {},
-                    Command::SetOfflinePingResponse(ping_response) =>
-                        self.connection_manager.set_offline_ping_response(ping_response),
-                    Command::StopProcessing => return,
-                }
+            if !self.execute_commands() {
+                return;
             }
         }
-    }    
+    }
+
+    /// Performs all received commands.
+    /// Returns false if processing should stop.
+    pub(crate) fn execute_commands(&mut self) -> bool {
+        while let Ok(command) = self.command_receiver.try_recv() {
+            match command
+            {
+                Command::ProcessNow => {},
+                Command::SetOfflinePingResponse(ping_response) =>
+                    self.connection_manager.set_offline_ping_response(ping_response),
+                Command::SetPingResponseProvider(provider) =>
+                    self.connection_manager.set_ping_response_provider(provider),
+                Command::SetAdmissionPolicy(admission_policy) =>
+                    self.connection_manager.set_admission_policy(admission_policy),
+                Command::UpdateConfig(update) => {
+                    if let Err(err) = self.update_config(update) {
+                        error!("Could not update the configuration: {}", err);
+                    }
+                },
+                Command::PingAddress(addr) => self.ping_address(addr),
+                Command::Connect(addr) => {
+                    if let Err(err) = self.connection_manager.connect(self.connection_manager.now(), &[addr], ConnectOptions::default()) {
+                        error!("Could not connect to {}: {}", addr, err);
+                    }
+                },
+                Command::StartReconnecting(addr, options) => self.start_reconnecting(addr, options),
+                Command::StopReconnecting(addr) => self.stop_reconnecting(addr),
+                Command::Send { target, options, payload } => {
+                    if let Err(err) = self.send_to_target(&target, options, payload) {
+                        error!("Could not send packet to {:?}: {}", target, err);
+                    }
+                },
+                Command::Disconnect { guid, flush } => {
+                    if !self.disconnect(guid, flush) {
+                        debug!("Could not disconnect from {} since it is not connected", guid);
+                    }
+                },
+                Command::Flush => self.flush(),
+                Command::SetConnectionBandwidthLimit(addr, max_bytes_per_second) => {
+                    if !self.set_connection_bandwidth_limit(addr, max_bytes_per_second) {
+                        debug!("Could not set bandwidth limit for {} since it is not connected", addr);
+                    }
+                },
+                Command::SetConnectionBandwidthLimitByGuid { guid, max_bytes_per_second } => {
+                    if !self.set_connection_bandwidth_limit_by_guid(guid, max_bytes_per_second) {
+                        debug!("Could not set bandwidth limit for {} since it is not connected", guid);
+                    }
+                },
+                Command::SetConnectionLimits { guid, limits } => {
+                    if !self.set_connection_limits(guid, limits) {
+                        debug!("Could not set limits for {} since it is not connected", guid);
+                    }
+                },
+                Command::QueryConnections => self.connection_manager.query_connections(self.connection_manager.now()),
+                Command::QueryConnection { guid, reply_sender } => {
+                    let info = self.connection_manager.connection_info(self.connection_manager.now(), guid);
+                    if reply_sender.send(info).is_err() {
+                        debug!("Could not reply to connection query for {} since the receiver is dropped", guid);
+                    }
+                },
+                Command::QueryMetrics { reply_sender } => {
+                    if reply_sender.send(self.metrics()).is_err() {
+                        debug!("Could not reply to metrics query since the receiver is dropped");
+                    }
+                },
+                Command::BanAddress(range, duration) => self.ban_address(range, duration),
+                Command::UnbanAddress(range) => {
+                    self.unban_address(range);
+                },
+                Command::AddBans(ban_file) => {
+                    self.add_bans(&ban_file);
+                },
+                Command::QueryBans { reply_sender } => {
+                    if reply_sender.send(self.bans()).is_err() {
+                        debug!("Could not reply to bans query since the receiver is dropped");
+                    }
+                },
+                Command::SetAllowList(allow_list) => self.set_allow_list(allow_list),
+                Command::AllowAddress(range) => {
+                    self.allow_address(range);
+                },
+                Command::AllowGuid(guid) => {
+                    self.allow_guid(guid);
+                },
+                Command::DisallowAddress(range) => {
+                    self.disallow_address(range);
+                },
+                Command::DisallowGuid(guid) => {
+                    self.disallow_guid(guid);
+                },
+                Command::SetPacketCapture(packet_capture) => self.connection_manager.set_packet_capture(packet_capture),
+                Command::StopProcessing => return false,
+            }
+        }
+        true
+    }
     
     /// Sets the response returned to an offline ping packet.
     /// If the response is longer than 399 bytes it will be truncated.
@@ -119,6 +479,423 @@ impl Peer {
         self.connection_manager.set_offline_ping_response(ping_response);
     }
 
+    /// Sets a function that creates the response to each offline ping packet from the
+    /// address of the pinging peer, e.g. to include the current number of players, instead
+    /// of returning a fixed response. Responses longer than 399 bytes will be truncated.
+    /// The provider is called on the processing thread and should return quickly.
+    /// It is replaced by a later call to `set_offline_ping_response`.
+    pub fn set_ping_response_provider<F: Fn(SocketAddr) -> Vec<u8> + Send + 'static>(&mut self, provider: F)
+    {
+        self.connection_manager.set_ping_response_provider(Box::new(provider));
+    }
+
+    /// Sets a policy deciding which remote peers may connect, e.g. by looking up the country
+    /// or autonomous system of their IP addresses, replacing any previous policy. It is
+    /// called for connection requests that have passed all other checks, including
+    /// `Config::allow_list`, and can also be a function, e.g.
+    /// `peer.set_admission_policy(|addr: SocketAddr, _guid| if addr.is_ipv4() { Admission::Accept } else { Admission::Reject })`.
+    /// Rejected and ignored requests are reported as `PeerEvent::ModerationAction`.
+    pub fn set_admission_policy<P: AdmissionPolicy + 'static>(&mut self, admission_policy: P) {
+        self.connection_manager.set_admission_policy(Some(Box::new(admission_policy)));
+    }
+
+    /// Removes the policy set with `set_admission_policy` so that all remote peers may connect.
+    pub fn remove_admission_policy(&mut self) {
+        self.connection_manager.set_admission_policy(None);
+    }
+
+    /// Creates a handoff token for the connected remote peer with the GUID, valid for
+    /// `Config::handoff_token_lifetime_in_ms` and bound to the IP address of the remote peer,
+    /// that lets it connect to another peer with the same `Config::handoff_key`, see `HandoffToken`.
+    /// `user_data` is passed to the other peer in `IncomingConnection::handoff_user_data`.
+    /// Returns None if no `Config::handoff_key` is set or no remote peer with the GUID is connected.
+    /// Use `HandoffToken::new` from other threads.
+    pub fn create_handoff_token(&self, guid: u64, user_data: u64) -> Option<HandoffToken>
+    {
+        self.connection_manager.create_handoff_token(guid, user_data)
+    }
+
+    /// Starts calling the function with every datagram sent or received, replacing any
+    /// previous capture, e.g. for debugging interoperability with other RakNet implementations.
+    /// The function is called on the processing thread and should return quickly.
+    pub fn set_packet_capture<F: FnMut(&CapturedDatagram) + Send + 'static>(&mut self, packet_capture: F) {
+        self.connection_manager.set_packet_capture(Some(Box::new(packet_capture)));
+    }
+
+    /// Starts writing every datagram sent or received to a pcap file, replacing
+    /// the file if it exists and any previous capture. The file can be opened
+    /// in Wireshark or decoded with `bedroxide decode`.
+    pub fn capture_to_file<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        let writer = PcapWriter::create(path)?;
+        self.connection_manager.set_packet_capture(Some(writer.into_packet_capture()));
+        Ok(())
+    }
+
+    /// Stops capturing datagrams started by `set_packet_capture` or `capture_to_file`.
+    pub fn stop_packet_capture(&mut self) {
+        self.connection_manager.set_packet_capture(None);
+    }
+
+    /// Changes the configuration while the peer is running. Fields of the update that are
+    /// None are left unchanged. Returns an error and changes nothing if the changed
+    /// configuration is invalid, see `Config::validate`.
+    pub fn update_config(&mut self, update: ConfigUpdate) -> std::result::Result<(), InvalidConfig>
+    {
+        self.connection_manager.update_config(&update)
+    }
+
+    /// Sends an unconnected ping to the specified address without connecting, e.g. to
+    /// query a server for a server list. The response set by the remote peer with
+    /// `set_offline_ping_response` is received in a `PeerEvent::UnconnectedPong`.
+    /// The address can be a broadcast address for discovering peers on the local network.
+    pub fn ping_address(&mut self, addr: SocketAddr)
+    {
+        self.connection_manager.ping_address(self.connection_manager.now(), addr);
+    }
+
+    /// Sends a small connectionless message of at most 400 bytes to the specified address,
+    /// e.g. for NAT punchthrough coordination or handing off a client to another server.
+    /// The remote peer receives it in a `PeerEvent::OutOfBandMessage`. The message is sent
+    /// in a single datagram and is neither acknowledged nor resent.
+    pub fn send_out_of_band(&mut self, addr: SocketAddr, data: &[u8]) -> Result<()>
+    {
+        self.connection_manager.send_out_of_band(addr, data)
+    }
+
+    /// Starts connecting to a remote peer at the specified address and returns immediately.
+    /// A `PeerEvent::ConnectionEstablished` is received from the event receiver
+    /// once the connection has been established.
+    ///
+    /// The address can be a hostname, e.g. `"example.com:19132"`. If it resolves to
+    /// several addresses they are tried in order, with IPv4 or IPv6 addresses first
+    /// depending on `Config::prefer_ipv6`, before the attempt is reported as failed.
+    ///
+    /// The returned `ConnectionHandle` can be used for checking the result of the attempt.
+    pub fn connect<A: ToSocketAddrs>(&mut self, addr: A) -> Result<ConnectionHandle>
+    {
+        self.connect_with_options(addr, ConnectOptions::default())
+    }
+
+    /// Starts connecting to a remote peer at the specified address with the
+    /// specified options, e.g. a password. See `connect` for details.
+    pub fn connect_with_options<A: ToSocketAddrs>(&mut self, addr: A, options: ConnectOptions) -> Result<ConnectionHandle>
+    {
+        let addrs: Vec<SocketAddr> = addr.to_socket_addrs()?.collect();
+        self.connection_manager.connect(self.connection_manager.now(), &addrs, options)
+    }
+
+    /// Connects to a remote peer at the specified address and processes network
+    /// events until the connection has been established, the attempt has failed
+    /// or `timeout` has passed. Returns the GUID of the remote peer on success.
+    ///
+    /// This is meant for simple tools that do not run a processing loop
+    /// and must not be called after `start_processing` has been called.
+    pub fn connect_blocking<A: ToSocketAddrs>(&mut self, addr: A, timeout: Duration) -> Result<u64>
+    {
+        let handle = self.connect(addr)?;
+        let deadline = self.connection_manager.now() + timeout;
+        loop {
+            self.process();
+            if let Some(result) = handle.wait(Duration::from_millis(1)) {
+                return result.map_err(Error::ConnectionAttemptFailed);
+            }
+            if self.connection_manager.now() >= deadline {
+                return Err(Error::ConnectionAttemptFailed(ConnectionAttemptFailedReason::Timeout));
+            }
+        }
+    }
+
+    /// Starts keeping a connection to a remote peer alive. A connection attempt is made
+    /// immediately if there is no connection to the peer. Whenever the connection is lost
+    /// or a connection attempt fails a new attempt is made after a delay that grows
+    /// exponentially, see `Config::reconnect_initial_delay_in_ms`.
+    pub fn start_reconnecting(&mut self, addr: SocketAddr, options: ConnectOptions)
+    {
+        self.connection_manager.start_reconnecting(self.connection_manager.now(), addr, options);
+    }
+
+    /// Stops reconnecting to a remote peer. An existing connection is not closed.
+    pub fn stop_reconnecting(&mut self, addr: SocketAddr)
+    {
+        self.connection_manager.stop_reconnecting(addr);
+    }
+
+    /// Enqueues a packet for sending to the connected remote peer at the specified address.
+    ///
+    /// If the receipt of the options is set either a `PeerEvent::SendReceiptAcked` or a `PeerEvent::SendReceiptLoss`
+    /// with the receipt is received once the packet has been acknowledged or lost, also for split
+    /// packets and for packets that are discarded when the connection is dropped.
+    /// Use `next_receipt` or `send_with_receipt` to get receipts that are not in use.
+    /// Returns `Error::NotConnected` if no connection to the address has been established
+    /// and `Error::PacketTooLarge` if the payload is larger than `max_total_payload`.
+    pub fn send(&mut self, addr: SocketAddr, options: SendOptions, payload: Vec<u8>) -> Result<()>
+    {
+        self.connection_manager.send(self.connection_manager.now(), addr, options, payload.into_boxed_slice())
+    }
+
+    /// Enqueues a packet for sending like `send` with a new receipt from `next_receipt`,
+    /// which replaces the receipt of the options, and returns the receipt.
+    pub fn send_with_receipt(&mut self, addr: SocketAddr, options: SendOptions, payload: Vec<u8>) -> Result<u32>
+    {
+        let receipt = self.next_receipt();
+        self.send(addr, SendOptions { receipt: Some(receipt), ..options }, payload)?;
+        Ok(receipt)
+    }
+
+    /// Returns a new receipt to pass to `send` or `send_to_target`. Receipts are
+    /// numbered from 0 and wrap around after `u32::MAX`.
+    pub fn next_receipt(&mut self) -> u32
+    {
+        let receipt = self.next_receipt;
+        self.next_receipt = self.next_receipt.wrapping_add(1);
+        receipt
+    }
+
+    /// Enqueues a packet for sending to all connected remote peers selected by the target,
+    /// e.g. `SendTarget::All` to broadcast a state update. The payload is shared by the
+    /// connections instead of being copied for each of them.
+    ///
+    /// The receipt of the options is used for every remote peer, so a `PeerEvent::SendReceiptAcked`
+    /// or `PeerEvent::SendReceiptLoss` is received for each of them. The `SendReceipt` of the
+    /// event holds the address and GUID of the remote peer, which tells the events apart.
+    ///
+    /// Returns the number of remote peers the packet was enqueued for. Remote peers that are
+    /// not connected or for which the payload is larger than `max_total_payload` are skipped.
+    /// Returns `Error::NoTarget` if the packet was not enqueued for any remote peer.
+    pub fn send_to_target(&mut self, target: &SendTarget, options: SendOptions, payload: Vec<u8>) -> Result<usize>
+    {
+        self.connection_manager.send_to_target(self.connection_manager.now(), target, options, payload.into())
+    }
+
+    /// Closes the connection to the remote peer with the GUID by sending a disconnection
+    /// notification. If `flush` is true the connection is kept until all packets sent
+    /// before have been acknowledged, otherwise it is dropped right away and unsent
+    /// packets are discarded. A `PeerEvent::ConnectionClosed` is received when the
+    /// connection has been dropped. Returns false if the remote peer is not connected.
+    pub fn disconnect(&mut self, guid: u64, flush: bool) -> bool
+    {
+        self.connection_manager.disconnect(self.connection_manager.now(), guid, flush)
+    }
+
+    /// Sends all queued packets in the next processing round without
+    /// waiting for the coalescing delay set in `Config::coalescing_delay_in_ms`.
+    pub fn flush(&mut self)
+    {
+        self.connection_manager.flush();
+    }
+
+    /// Bans a range of IP addresses, e.g. `"192.168.0.0/16".parse()?`, for the specified
+    /// duration or forever if `duration` is None. All datagrams from banned addresses are
+    /// ignored and their connection attempts fail with `ConnectionAttemptFailedReason::Banned`.
+    /// Connections to banned addresses are dropped with `ConnectionLostReason::Banned`.
+    pub fn ban_address(&mut self, range: IpRange, duration: Option<Duration>)
+    {
+        self.connection_manager.ban_address(self.connection_manager.now(), range, duration);
+    }
+
+    /// Removes the ban of a range of IP addresses. Only a ban of exactly the
+    /// same range is removed. Returns false if the range was not banned.
+    pub fn unban_address(&mut self, range: IpRange) -> bool
+    {
+        self.connection_manager.unban_address(self.connection_manager.now(), range)
+    }
+
+    /// Returns the bans that have not expired, e.g. to save them with `BanFile::save`
+    /// so that they can be restored with `add_bans` after a restart.
+    pub fn bans(&self) -> BanFile
+    {
+        self.connection_manager.bans(self.connection_manager.now())
+    }
+
+    /// Bans the ranges of IP addresses in the ban file, e.g. loaded with `BanFile::from_path`,
+    /// until their expiry times. Bans that have already expired are skipped. The bans are
+    /// not reported as `PeerEvent::ModerationAction` since they were made before.
+    /// Returns the number of ranges banned.
+    pub fn add_bans(&mut self, ban_file: &BanFile) -> usize
+    {
+        self.connection_manager.add_bans(self.connection_manager.now(), ban_file)
+    }
+
+    /// Enables allow-list mode with the allow list, replacing the current list,
+    /// or disables it if None, see `Config::allow_list`. Existing connections are kept.
+    pub fn set_allow_list(&mut self, allow_list: Option<AllowList>)
+    {
+        self.connection_manager.set_allow_list(allow_list);
+    }
+
+    /// Adds a range of IP addresses to the allow list.
+    /// Returns false if allow-list mode is disabled.
+    pub fn allow_address(&mut self, range: IpRange) -> bool
+    {
+        self.connection_manager.allow_address(range)
+    }
+
+    /// Adds a GUID to the allow list. Returns false if allow-list mode is disabled.
+    pub fn allow_guid(&mut self, guid: u64) -> bool
+    {
+        self.connection_manager.allow_guid(guid)
+    }
+
+    /// Removes a range of IP addresses from the allow list. Only exactly the same
+    /// range is removed and existing connections are kept. Returns false if the
+    /// range was not in the allow list.
+    pub fn disallow_address(&mut self, range: IpRange) -> bool
+    {
+        self.connection_manager.disallow_address(range)
+    }
+
+    /// Removes a GUID from the allow list. Existing connections are kept.
+    /// Returns false if the GUID was not in the allow list.
+    pub fn disallow_guid(&mut self, guid: u64) -> bool
+    {
+        self.connection_manager.disallow_guid(guid)
+    }
+
+    /// Sets the maximum number of bytes per second sent over the connection to
+    /// the specified address. If the maximum is 0 the bandwidth is unlimited.
+    /// Returns false if there is no connection to the address.
+    ///
+    /// The bandwidth of all connections together is limited by
+    /// `Config::max_outgoing_bytes_per_second`.
+    pub fn set_connection_bandwidth_limit(&mut self, addr: SocketAddr, max_bytes_per_second: u64) -> bool
+    {
+        self.connection_manager.set_connection_bandwidth_limit(addr, max_bytes_per_second)
+    }
+
+    /// Sets the maximum number of bytes per second sent over the connection to the remote
+    /// peer with the GUID like `set_connection_bandwidth_limit`. Returns false if there is
+    /// no connection to the remote peer.
+    pub fn set_connection_bandwidth_limit_by_guid(&mut self, guid: u64, max_bytes_per_second: u64) -> bool
+    {
+        match self.connection_manager.addr_of(guid) {
+            Some(addr) => self.set_connection_bandwidth_limit(addr, max_bytes_per_second),
+            None => false,
+        }
+    }
+
+    /// Overrides the ack timeout, maximum number of retransmissions and bandwidth limit
+    /// in `Config` for the connection to the remote peer with the GUID only, e.g. for a
+    /// trusted backend link. Returns false if there is no connection to the remote peer.
+    pub fn set_connection_limits(&mut self, guid: u64, limits: ConnectionLimits) -> bool
+    {
+        self.connection_manager.set_connection_limits(guid, &limits)
+    }
+
+    /// Returns the time when the peer was created, which `Packet::received_ms` is relative to.
+    pub fn start_time(&self) -> Instant
+    {
+        self.connection_manager.start_time()
+    }
+
+    /// Returns the local address of the socket, e.g. to find the port
+    /// chosen by the operating system when binding to port 0.
+    pub fn local_addr(&self) -> Result<SocketAddr>
+    {
+        self.connection_manager.local_addr()
+    }
+
+    /// Returns the number of bytes sent to all remote peers during the last second.
+    pub fn outgoing_bytes_per_second(&mut self) -> u64
+    {
+        self.connection_manager.outgoing_bytes_per_second(self.connection_manager.now())
+    }
+
+    /// Returns the number of datagrams that have been dropped since the socket could
+    /// not send them and the queue set by `Config::max_queued_datagrams` was full.
+    pub fn dropped_datagram_count(&self) -> u64
+    {
+        self.connection_manager.dropped_datagram_count()
+    }
+
+    /// Returns the number of bytes sent over the connection to the specified address
+    /// during the last second or None if there is no connection to the address.
+    pub fn connection_outgoing_bytes_per_second(&mut self, addr: SocketAddr) -> Option<u64>
+    {
+        self.connection_manager.connection_outgoing_bytes_per_second(self.connection_manager.now(), addr)
+    }
+
+    /// Returns the address of the connection to the remote peer with the GUID or None
+    /// if there is no such connection. Use this for applications that track remote peers by GUID.
+    pub fn addr_of(&self, guid: u64) -> Option<SocketAddr>
+    {
+        self.connection_manager.addr_of(guid)
+    }
+
+    /// Returns the GUID of the remote peer connected from the address or None
+    /// if there is no connection to the address.
+    pub fn guid_of(&self, addr: SocketAddr) -> Option<u64>
+    {
+        self.connection_manager.guid_of(addr)
+    }
+
+    /// Returns a snapshot of every connection, including connections that are being
+    /// established or closed, e.g. for listing the connected remote peers and their ping.
+    pub fn connections(&self) -> Vec<ConnectionInfo>
+    {
+        self.connection_manager.connections(self.connection_manager.now())
+    }
+
+    /// Returns a handle to the remote peer with the GUID that can be cloned and used from
+    /// other threads while this peer is processing, see `RemotePeer`.
+    pub fn remote_peer(&self, guid: u64) -> RemotePeer
+    {
+        RemotePeer::new(guid, self.command_sender())
+    }
+
+    /// Returns the smoothed round trip time in milliseconds to the specified address or
+    /// None if there is no connection to the address or no pong has been received yet.
+    /// Connected remote peers are pinged every `Config::ping_interval_in_ms`.
+    pub fn ping(&self, addr: SocketAddr) -> Option<u32>
+    {
+        self.connection_manager.ping(addr)
+    }
+
+    /// Returns the metrics of this peer aggregated over all connections, e.g. the number
+    /// of datagrams sent and received, connection attempts and bans. With the `metrics`
+    /// feature they can be written in the Prometheus format with `PeerMetrics::to_prometheus`.
+    pub fn metrics(&self) -> PeerMetrics
+    {
+        self.connection_manager.metrics()
+    }
+
+    /// Returns statistics of the time spent in the processing rounds of this peer, e.g. to
+    /// find out if stutter in a server is caused by RakNet. See also `PeerEvent::SlowTick`.
+    pub fn tick_stats(&self) -> TickStats
+    {
+        self.connection_manager.tick_stats().clone()
+    }
+
+    /// Returns the statistics of the connection to the specified address, e.g. the number
+    /// of bytes sent and resent, or None if there is no connection to the address.
+    pub fn connection_stats(&self, addr: SocketAddr) -> Option<ConnectionStats>
+    {
+        self.connection_manager.connection_stats(addr)
+    }
+
+    /// Returns the time of the remote peer at the specified address minus the time of
+    /// this peer in milliseconds, e.g. for converting timestamps in packets from the
+    /// remote peer, or None if there is no connection to the address or no pong has been received yet.
+    pub fn clock_differential(&self, addr: SocketAddr) -> Option<i64>
+    {
+        self.connection_manager.clock_differential(addr)
+    }
+
+    /// Returns the largest payload in bytes that can be sent to the specified address
+    /// without being split into several datagrams or None if there is no connection
+    /// to the address. The size depends on the MTU of the connection.
+    pub fn max_unsplit_payload(&self, addr: SocketAddr) -> Option<usize>
+    {
+        self.connection_manager.max_unsplit_payload(addr)
+    }
+
+    /// Returns the largest payload in bytes that can be sent to the specified address
+    /// or None if there is no connection to the address. Larger packets are rejected
+    /// with `Error::PacketTooLarge`.
+    pub fn max_total_payload(&self, addr: SocketAddr) -> Option<usize>
+    {
+        self.connection_manager.max_total_payload(addr)
+    }
+
     /// Gets a command sender that can be used for sending commands
     /// to the processing thread once `start_processing` or
     /// `start_processing_with_duration` has been called.
@@ -136,3 +913,768 @@ impl Peer {
         self.connection_manager.event_receiver()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{net::{SocketAddr, UdpSocket}, sync::{Arc, Mutex, atomic::{AtomicBool, AtomicU8, Ordering as AtomicOrdering}}, thread, time::{Duration, Instant, SystemTime, UNIX_EPOCH}};
+    use crate::{BanEntry, BanFile, CaptureDirection, Clock, Command, Config, ConfigUpdate, ConnectionAttemptFailedReason, ConnectionLostReason, ConnectionPhase, Error, InvalidConfig, ManualClock, Ordering, Peer, PeerEvent, Priority, Reliability, SendOptions, SendTarget, socket::FakeDatagramSocket};
+    use super::bind_socket;
+
+    #[test]
+    fn dual_stack_socket_receives_ipv4_datagrams() {
+        // Arrange
+        let socket = bind_socket("[::]:0", &Config::default()).expect("Could not bind socket");
+        let port = socket.local_addr().expect("Could not get address").port();
+        let ipv4_socket = UdpSocket::bind("127.0.0.1:0").expect("Could not bind socket");
+
+        // Act
+        let sent = ipv4_socket.send_to(&[1, 2, 3], ("127.0.0.1", port));
+        thread::sleep(Duration::from_millis(10));
+        let mut buf = [0u8; 16];
+        let received = socket.recv_from(&mut buf);
+
+        // Assert
+        assert!(sent.is_ok());
+        let (len, addr) = received.expect("Datagram not received");
+        assert_eq!(&[1, 2, 3], &buf[..len]);
+        assert_eq!(ipv4_socket.local_addr().expect("Could not get address").ip(), addr.ip().to_canonical());
+    }
+
+    #[test]
+    fn connection_attempt_times_out_when_clock_is_advanced() {
+        // Arrange
+        let clock = ManualClock::new();
+        let socket = FakeDatagramSocket::new("127.0.0.1:19132".parse().expect("Could not create address"));
+        let mut peer = Peer::with_clock(socket, Config { guid: 2, connection_timeout_in_ms: 10000, ..Config::default() }, clock.clone()).expect("Could not create peer");
+        let events = peer.event_receiver();
+        let remote_addr = "192.168.1.1:19132".parse::<SocketAddr>().expect("Could not create address");
+        peer.connect(remote_addr).expect("Could not connect");
+        peer.process();
+        clock.advance(Duration::from_millis(9999));
+        peer.process();
+        let event_before_timeout = events.try_recv();
+
+        // Act
+        clock.advance(Duration::from_millis(2));
+        peer.process();
+
+        // Assert
+        assert!(event_before_timeout.is_err());
+        match events.try_recv() {
+            Ok(PeerEvent::ConnectionAttemptFailed { addr, reason, .. }) => {
+                assert_eq!(remote_addr, addr);
+                assert_eq!(ConnectionAttemptFailedReason::Timeout, reason);
+            },
+            event => panic!("Unexpected event {:?}", event),
+        }
+    }
+
+    #[test]
+    fn bans_expire_at_the_time_of_the_clock() {
+        // Arrange
+        let clock = ManualClock::new();
+        let socket = FakeDatagramSocket::new("127.0.0.1:19132".parse().expect("Could not create address"));
+        let mut peer = Peer::with_clock(socket, Config { guid: 2, ..Config::default() }, clock.clone()).expect("Could not create peer");
+        let expires_at = (SystemTime::now() + Duration::from_secs(60)).duration_since(UNIX_EPOCH).expect("Could not get time").as_secs();
+        let ban_file = BanFile { bans: vec![BanEntry { range: "192.168.1.1".parse().expect("Could not parse IP range"), expires_at: Some(expires_at) }] };
+        let ban_count_before_expiry = peer.add_bans(&ban_file);
+        let bans_before_expiry = peer.bans();
+
+        // Act
+        clock.advance(Duration::from_secs(120));
+        let ban_count_after_expiry = peer.add_bans(&ban_file);
+
+        // Assert
+        assert_eq!(1, ban_count_before_expiry);
+        assert_eq!(1, bans_before_expiry.bans.len());
+        assert!(bans_before_expiry.bans[0].expires_at.is_some_and(|bans_expires_at| bans_expires_at.abs_diff(expires_at) <= 1));
+        assert_eq!(0, ban_count_after_expiry);
+        assert!(peer.bans().bans.is_empty());
+    }
+
+    #[test]
+    fn moderation_events_are_stamped_with_the_time_of_the_clock() {
+        // Arrange
+        let start_time = SystemTime::now();
+        let clock = ManualClock::new();
+        let socket = FakeDatagramSocket::new("127.0.0.1:19132".parse().expect("Could not create address"));
+        let mut peer = Peer::with_clock(socket, Config { guid: 2, ..Config::default() }, clock.clone()).expect("Could not create peer");
+        let events = peer.event_receiver();
+
+        // Act
+        clock.advance(Duration::from_secs(3600));
+        peer.ban_address("192.168.1.1".parse().expect("Could not parse IP range"), None);
+
+        // Assert
+        match events.try_recv() {
+            Ok(PeerEvent::ModerationAction { time, .. }) => assert!(time >= start_time + Duration::from_secs(3600)),
+            event => panic!("Unexpected event {:?}", event),
+        }
+    }
+
+    #[test]
+    fn connect_without_addresses_fails() {
+        // Arrange
+        let socket = FakeDatagramSocket::new("127.0.0.1:19132".parse().expect("Could not create address"));
+        let mut peer = Peer::with_socket(socket, Config { guid: 2, ..Config::default() }).expect("Could not create peer");
+        let addrs: &[SocketAddr] = &[];
+
+        // Act
+        let result = peer.connect(addrs);
+
+        // Assert
+        assert!(matches!(result, Err(Error::InvalidAddress)));
+    }
+
+    #[test]
+    fn connect_blocking_times_out_when_clock_is_advanced() {
+        // Arrange
+        let clock = ManualClock::new();
+        let socket = FakeDatagramSocket::new("127.0.0.1:19132".parse().expect("Could not create address"));
+        let mut peer = Peer::with_clock(socket, Config { guid: 2, connection_timeout_in_ms: 10000, ..Config::default() }, clock.clone()).expect("Could not create peer");
+        let remote_addr = "192.168.1.1:19132".parse::<SocketAddr>().expect("Could not create address");
+        let start_time = clock.now();
+        let is_done = Arc::new(AtomicBool::new(false));
+        let ticker = {
+            let clock = clock.clone();
+            let is_done = is_done.clone();
+            thread::spawn(move || {
+                while !is_done.load(AtomicOrdering::SeqCst) {
+                    clock.advance(Duration::from_millis(100));
+                    thread::sleep(Duration::from_millis(1));
+                }
+            })
+        };
+
+        // Act
+        let result = peer.connect_blocking(remote_addr, Duration::from_secs(1));
+
+        // Assert
+        let elapsed = clock.now() - start_time;
+        is_done.store(true, AtomicOrdering::SeqCst);
+        ticker.join().expect("Could not join thread");
+        assert!(matches!(result, Err(Error::ConnectionAttemptFailed(ConnectionAttemptFailedReason::Timeout))));
+        assert!(elapsed >= Duration::from_secs(1));
+        assert!(elapsed < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn connected_client_connects_to_server() {
+        // Arrange
+        let mut server = Peer::bind_with_config("127.0.0.1:0", Config { guid: 1, ..Config::default() }).expect("Could not bind server");
+        let server_addr = server.local_addr().expect("Could not get address");
+        let mut client = Peer::bind_connected_with_config("127.0.0.1:0", server_addr, Config { guid: 2, ..Config::default() }).expect("Could not bind client");
+        let client_events = client.event_receiver();
+
+        // Act
+        client.connect(server_addr).expect("Could not connect");
+        let start = Instant::now();
+        let mut event = None;
+        while event.is_none() && start.elapsed() < Duration::from_secs(5) {
+            client.process();
+            server.process();
+            thread::sleep(Duration::from_millis(1));
+            event = client_events.try_iter().find(|event| matches!(event, PeerEvent::ConnectionEstablished { .. }));
+        }
+
+        // Assert
+        match event {
+            Some(PeerEvent::ConnectionEstablished { addr, .. }) => assert_eq!(server_addr, addr),
+            _ => panic!("Connection not established"),
+        }
+    }
+
+    #[test]
+    fn send_command_sends_packet_to_connected_peer() {
+        // Arrange
+        let mut server = Peer::bind_with_config("127.0.0.1:0", Config { guid: 1, ..Config::default() }).expect("Could not bind server");
+        let server_addr = server.local_addr().expect("Could not get address");
+        let server_events = server.event_receiver();
+        let mut client = Peer::bind_with_config("127.0.0.1:0", Config { guid: 2, ..Config::default() }).expect("Could not bind client");
+        let handle = client.connect(server_addr).expect("Could not connect");
+        let start = Instant::now();
+        while handle.wait(Duration::from_millis(1)).is_none() && start.elapsed() < Duration::from_secs(5) {
+            client.process();
+            server.process();
+        }
+
+        // Act
+        client.command_sender().send(Command::Send {
+            target: SendTarget::Addr(server_addr),
+            options: SendOptions::new(Priority::Medium, Reliability::Reliable, Ordering::None),
+            payload: vec![0x86, 1, 2, 3],
+        }).expect("Could not send command");
+        client.execute_commands();
+        let mut packet = None;
+        while packet.is_none() && start.elapsed() < Duration::from_secs(5) {
+            client.process();
+            server.process();
+            thread::sleep(Duration::from_millis(1));
+            packet = server_events.try_iter().find_map(|event| match event {
+                PeerEvent::Packet(packet) => Some(packet),
+                _ => None,
+            });
+        }
+
+        // Assert
+        assert_eq!(&[0x86, 1, 2, 3], packet.expect("Packet not received").payload());
+    }
+
+    #[test]
+    fn connected_peer_is_found_by_guid() {
+        // Arrange
+        let mut server = Peer::bind_with_config("127.0.0.1:0", Config { guid: 1, ..Config::default() }).expect("Could not bind server");
+        let mut client = Peer::bind_with_config("127.0.0.1:0", Config { guid: 2, ..Config::default() }).expect("Could not bind client");
+        let client_addr = client.local_addr().expect("Could not get address");
+        connect_peers(&mut client, &mut server);
+
+        // Act
+        let addr = server.addr_of(2);
+        let guid = server.guid_of(client_addr);
+
+        // Assert
+        assert_eq!(Some(client_addr), addr);
+        assert_eq!(Some(2), guid);
+        assert_eq!(None, server.addr_of(3));
+    }
+
+    #[test]
+    fn invalid_config_is_rejected() {
+        // Act
+        let result = Peer::bind_with_config("127.0.0.1:0", Config { guid: 1, max_datagrams_per_tick: 0, ..Config::default() });
+
+        // Assert
+        assert!(matches!(result, Err(Error::InvalidConfig(InvalidConfig::ZeroValue("max_datagrams_per_tick")))));
+    }
+
+    #[test]
+    fn bandwidth_limit_is_set_by_guid() {
+        // Arrange
+        let mut server = Peer::bind_with_config("127.0.0.1:0", Config { guid: 1, ..Config::default() }).expect("Could not bind server");
+        let mut client = Peer::bind_with_config("127.0.0.1:0", Config { guid: 2, ..Config::default() }).expect("Could not bind client");
+        connect_peers(&mut client, &mut server);
+
+        // Act
+        let is_set = server.set_connection_bandwidth_limit_by_guid(2, 1000);
+        let is_set_without_connection = server.set_connection_bandwidth_limit_by_guid(3, 1000);
+
+        // Assert
+        assert!(is_set);
+        assert!(!is_set_without_connection);
+    }
+
+    #[test]
+    fn ping_is_updated_by_periodic_pings() {
+        // Arrange
+        let mut server = Peer::bind_with_config("127.0.0.1:0", Config { guid: 1, ..Config::default() }).expect("Could not bind server");
+        let server_addr = server.local_addr().expect("Could not get address");
+        let mut client = Peer::bind_with_config("127.0.0.1:0", Config { guid: 2, ping_interval_in_ms: 10, ..Config::default() }).expect("Could not bind client");
+        let client_events = client.event_receiver();
+        connect_peers(&mut client, &mut server);
+
+        // Act
+        let start = Instant::now();
+        let mut ping_update_count = 0;
+        while ping_update_count < 3 && start.elapsed() < Duration::from_secs(5) {
+            client.process();
+            server.process();
+            thread::sleep(Duration::from_millis(1));
+            ping_update_count += client_events.try_iter()
+                .filter(|event| matches!(event, PeerEvent::PingUpdated { addr, .. } if *addr == server_addr))
+                .count();
+        }
+
+        // Assert
+        assert!(ping_update_count >= 3, "Ping not updated periodically");
+        assert!(client.ping(server_addr).is_some());
+        assert!(client.clock_differential(server_addr).is_some());
+        assert_eq!(None, client.ping("127.0.0.1:1".parse::<SocketAddr>().expect("Could not create address")));
+    }
+
+    #[test]
+    fn ping_address_command_receives_unconnected_pong() {
+        // Arrange
+        let mut server = Peer::bind_with_config("127.0.0.1:0", Config { guid: 1, ..Config::default() }).expect("Could not bind server");
+        let server_addr = server.local_addr().expect("Could not get address");
+        server.set_offline_ping_response(vec![1, 2, 3]);
+        let mut client = Peer::bind_with_config("127.0.0.1:0", Config { guid: 2, ..Config::default() }).expect("Could not bind client");
+        let client_events = client.event_receiver();
+
+        // Act
+        client.command_sender().send(Command::PingAddress(server_addr)).expect("Could not send command");
+        client.execute_commands();
+        let start = Instant::now();
+        let mut pong = None;
+        while pong.is_none() && start.elapsed() < Duration::from_secs(5) {
+            server.process();
+            client.process();
+            thread::sleep(Duration::from_millis(1));
+            pong = client_events.try_iter().find_map(|event| match event {
+                PeerEvent::UnconnectedPong { addr, guid, data, .. } => Some((addr, guid, data)),
+                _ => None,
+            });
+        }
+
+        // Assert
+        assert_eq!(Some((server_addr, 1, vec![1, 2, 3])), pong);
+    }
+
+    #[test]
+    fn ping_response_provider_creates_response_for_each_ping() {
+        // Arrange
+        let mut server = Peer::bind_with_config("127.0.0.1:0", Config { guid: 1, ..Config::default() }).expect("Could not bind server");
+        let server_addr = server.local_addr().expect("Could not get address");
+        let player_count = Arc::new(AtomicU8::new(0));
+        let provider_player_count = player_count.clone();
+        server.command_sender().send(Command::SetPingResponseProvider(Box::new(move |_addr| {
+            vec![provider_player_count.load(AtomicOrdering::SeqCst)]
+        }))).expect("Could not send command");
+        server.execute_commands();
+        let mut client = Peer::bind_with_config("127.0.0.1:0", Config { guid: 2, ..Config::default() }).expect("Could not bind client");
+        let client_events = client.event_receiver();
+
+        // Act
+        let mut pongs = Vec::new();
+        for count in [3, 7].iter() {
+            player_count.store(*count, AtomicOrdering::SeqCst);
+            client.ping_address(server_addr);
+            let start = Instant::now();
+            let mut pong = None;
+            while pong.is_none() && start.elapsed() < Duration::from_secs(5) {
+                server.process();
+                client.process();
+                thread::sleep(Duration::from_millis(1));
+                pong = client_events.try_iter().find_map(|event| match event {
+                    PeerEvent::UnconnectedPong { data, .. } => Some(data),
+                    _ => None,
+                });
+            }
+            pongs.push(pong);
+        }
+
+        // Assert
+        assert_eq!(vec![Some(vec![3]), Some(vec![7])], pongs);
+    }
+
+    #[test]
+    fn out_of_band_message_is_received_without_connection() {
+        // Arrange
+        let mut server = Peer::bind_with_config("127.0.0.1:0", Config { guid: 1, ..Config::default() }).expect("Could not bind server");
+        let server_addr = server.local_addr().expect("Could not get address");
+        let server_events = server.event_receiver();
+        let mut client = Peer::bind_with_config("127.0.0.1:0", Config { guid: 2, ..Config::default() }).expect("Could not bind client");
+        let client_addr = client.local_addr().expect("Could not get address");
+
+        // Act
+        let too_large_result = client.send_out_of_band(server_addr, &[0u8; 401]);
+        client.send_out_of_band(server_addr, &[1, 2, 3]).expect("Could not send message");
+        let start = Instant::now();
+        let mut message = None;
+        while message.is_none() && start.elapsed() < Duration::from_secs(5) {
+            client.process();
+            server.process();
+            thread::sleep(Duration::from_millis(1));
+            message = server_events.try_iter().find_map(|event| match event {
+                PeerEvent::OutOfBandMessage { addr, guid, data, .. } => Some((addr, guid, data)),
+                _ => None,
+            });
+        }
+
+        // Assert
+        assert!(matches!(too_large_result, Err(Error::PacketTooLarge { size: 401, max_size: 400 })));
+        assert_eq!(Some((client_addr, 2, vec![1, 2, 3])), message);
+    }
+
+    #[test]
+    fn remote_peer_sends_packet_and_reports_stats() {
+        // Arrange
+        let mut server = Peer::bind_with_config("127.0.0.1:0", Config { guid: 1, ..Config::default() }).expect("Could not bind server");
+        let server_events = server.event_receiver();
+        let mut client = Peer::bind_with_config("127.0.0.1:0", Config { guid: 2, ..Config::default() }).expect("Could not bind client");
+        connect_peers(&mut client, &mut server);
+        let remote_peer = client.remote_peer(1);
+
+        // Act
+        remote_peer.send(SendOptions::new(Priority::Medium, Reliability::Reliable, Ordering::None), vec![0x86, 1, 2, 3]).expect("Could not send packet");
+        let stats_thread = {
+            let remote_peer = remote_peer.clone();
+            thread::spawn(move || remote_peer.stats())
+        };
+        let start = Instant::now();
+        let mut packet = None;
+        while (packet.is_none() || !stats_thread.is_finished()) && start.elapsed() < Duration::from_secs(5) {
+            client.execute_commands();
+            client.process();
+            server.process();
+            thread::sleep(Duration::from_millis(1));
+            if packet.is_none() {
+                packet = server_events.try_iter().find_map(|event| match event {
+                    PeerEvent::Packet(packet) => Some(packet),
+                    _ => None,
+                });
+            }
+        }
+        let stats = stats_thread.join().expect("Stats thread panicked");
+
+        // Assert
+        assert_eq!(vec![0x86, 1, 2, 3], packet.expect("Packet not received").payload());
+        let stats = stats.expect("Could not get stats").expect("No connection");
+        assert_eq!(1, stats.guid);
+        assert_eq!(ConnectionPhase::Connected, stats.phase);
+    }
+
+    #[test]
+    fn update_config_command_changes_running_peer() {
+        // Arrange
+        let mut server = Peer::bind_with_config("127.0.0.1:0", Config { guid: 1, ..Config::default() }).expect("Could not bind server");
+        let server_addr = server.local_addr().expect("Could not get address");
+        let mut client = Peer::bind_with_config("127.0.0.1:0", Config { guid: 2, ..Config::default() }).expect("Could not bind client");
+        let client_events = client.event_receiver();
+
+        // Act
+        server.command_sender().send(Command::UpdateConfig(ConfigUpdate {
+            max_incoming_connections: Some(0),
+            offline_ping_response: Some(vec![4, 5]),
+            ..ConfigUpdate::default()
+        })).expect("Could not send command");
+        server.execute_commands();
+        let invalid_result = server.update_config(ConfigUpdate { ack_timeout_in_ms: Some(1), ..ConfigUpdate::default() });
+        client.ping_address(server_addr);
+        let handle = client.connect(server_addr).expect("Could not connect");
+        let start = Instant::now();
+        let mut result = None;
+        let mut pong_data = None;
+        while (result.is_none() || pong_data.is_none()) && start.elapsed() < Duration::from_secs(5) {
+            client.process();
+            server.process();
+            thread::sleep(Duration::from_millis(1));
+            result = result.or_else(|| handle.try_result());
+            pong_data = pong_data.or_else(|| client_events.try_iter().find_map(|event| match event {
+                PeerEvent::UnconnectedPong { data, .. } => Some(data),
+                _ => None,
+            }));
+        }
+
+        // Assert
+        assert!(matches!(invalid_result, Err(InvalidConfig::TimeoutOrder { .. })));
+        assert_eq!(Some(vec![4, 5]), pong_data);
+        assert_eq!(Some(Err(ConnectionAttemptFailedReason::NoFreeIncomingConnections)), result);
+    }
+
+    #[test]
+    fn tick_interval_is_longer_while_idle() {
+        // Arrange
+        let mut server = Peer::bind_with_config("127.0.0.1:0", Config { guid: 1, ..Config::default() }).expect("Could not bind server");
+        let config = Config { guid: 2, tick_interval_in_ms: 2, idle_tick_interval_in_ms: 50, ..Config::default() };
+        let mut client = Peer::bind_with_config("127.0.0.1:0", config).expect("Could not bind client");
+
+        // Act
+        let idle_interval = client.tick_interval();
+        connect_peers(&mut client, &mut server);
+        let busy_interval = client.tick_interval();
+
+        // Assert
+        assert_eq!(Duration::from_millis(50), idle_interval);
+        assert_eq!(Duration::from_millis(2), busy_interval);
+    }
+
+    #[test]
+    fn metrics_count_connections_and_datagrams() {
+        // Arrange
+        let mut server = Peer::bind_with_config("127.0.0.1:0", Config { guid: 1, ..Config::default() }).expect("Could not bind server");
+        let mut client = Peer::bind_with_config("127.0.0.1:0", Config { guid: 2, ..Config::default() }).expect("Could not bind client");
+        let (reply_sender, reply_receiver) = crossbeam_channel::bounded(1);
+
+        // Act
+        connect_peers(&mut client, &mut server);
+        server.command_sender().send(Command::QueryMetrics { reply_sender }).expect("Could not send command");
+        server.execute_commands();
+        let client_metrics = client.metrics();
+
+        // Assert
+        let server_metrics = reply_receiver.try_recv().expect("No metrics received");
+        assert_eq!(1, client_metrics.outgoing_connection_attempts);
+        assert!(client_metrics.datagrams_sent > 0);
+        assert!(server_metrics.bytes_received > 0);
+        assert!(server_metrics.incoming_handshakes > 0);
+        assert_eq!(1, server_metrics.connections_established);
+        assert_eq!(1, server_metrics.connections);
+    }
+
+    #[test]
+    fn slow_tick_is_counted_and_reported() {
+        // Arrange
+        let mut server = Peer::bind_with_config("127.0.0.1:0", Config { guid: 1, slow_tick_threshold_in_ms: 5, ..Config::default() }).expect("Could not bind server");
+        let server_addr = server.local_addr().expect("Could not get address");
+        let server_events = server.event_receiver();
+        // Handling a received datagram is made slow by a packet capture that sleeps
+        server.set_packet_capture(|_| thread::sleep(Duration::from_millis(20)));
+        let mut client = Peer::bind_with_config("127.0.0.1:0", Config { guid: 2, ..Config::default() }).expect("Could not bind client");
+
+        // Act
+        server.process();
+        client.ping_address(server_addr);
+        thread::sleep(Duration::from_millis(10));
+        server.process();
+
+        // Assert
+        let tick_stats = server.tick_stats();
+        assert_eq!(2, tick_stats.ticks);
+        assert_eq!(1, tick_stats.slow_ticks);
+        assert!(tick_stats.last.receive >= Duration::from_millis(20));
+        assert_eq!(tick_stats.last, tick_stats.slowest);
+        let slow_ticks: Vec<_> = server_events.try_iter().filter_map(|event| match event {
+            PeerEvent::SlowTick(timings) => Some(timings),
+            _ => None,
+        }).collect();
+        assert_eq!(vec![tick_stats.last], slow_ticks);
+    }
+
+    #[test]
+    fn packet_capture_receives_sent_and_received_datagrams_until_stopped() {
+        // Arrange
+        let mut server = Peer::bind_with_config("127.0.0.1:0", Config { guid: 1, ..Config::default() }).expect("Could not bind server");
+        let server_addr = server.local_addr().expect("Could not get address");
+        let mut client = Peer::bind_with_config("127.0.0.1:0", Config { guid: 2, ..Config::default() }).expect("Could not bind client");
+        let client_addr = client.local_addr().expect("Could not get address");
+        let captured = Arc::new(Mutex::new(Vec::new()));
+        let capture_captured = captured.clone();
+        server.set_packet_capture(move |datagram| {
+            capture_captured.lock().unwrap().push((datagram.direction, datagram.local_addr, datagram.remote_addr));
+        });
+
+        // Act
+        connect_peers(&mut client, &mut server);
+        let captured_while_connecting = captured.lock().unwrap().clone();
+        server.command_sender().send(Command::SetPacketCapture(None)).expect("Could not send command");
+        server.execute_commands();
+        client.ping_address(server_addr);
+        for _ in 0..10 {
+            client.process();
+            server.process();
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        // Assert
+        assert!(captured_while_connecting.contains(&(CaptureDirection::Received, server_addr, client_addr)));
+        assert!(captured_while_connecting.contains(&(CaptureDirection::Sent, server_addr, client_addr)));
+        assert_eq!(captured_while_connecting, *captured.lock().unwrap());
+    }
+
+    #[test]
+    fn query_connections_command_lists_connections() {
+        // Arrange
+        let mut server = Peer::bind_with_config("127.0.0.1:0", Config { guid: 1, ..Config::default() }).expect("Could not bind server");
+        let server_events = server.event_receiver();
+        let mut client = Peer::bind_with_config("127.0.0.1:0", Config { guid: 2, ..Config::default() }).expect("Could not bind client");
+        let client_addr = client.local_addr().expect("Could not get address");
+        connect_peers(&mut client, &mut server);
+
+        // Act
+        server.command_sender().send(Command::QueryConnections).expect("Could not send command");
+        server.execute_commands();
+        let connections = server_events.try_iter().find_map(|event| match event {
+            PeerEvent::Connections(connections) => Some(connections),
+            _ => None,
+        });
+
+        // Assert
+        let connections = connections.expect("Connections not received");
+        assert_eq!(1, connections.len());
+        assert_eq!(client_addr, connections[0].addr);
+        assert_eq!(2, connections[0].guid);
+        assert_eq!(ConnectionPhase::Connected, connections[0].phase);
+        assert!(connections[0].is_incoming);
+        assert_eq!(vec![2], server.connections().iter().map(|connection| connection.guid).collect::<Vec<_>>());
+    }
+
+    /// Connects the client to the server and processes both until the server has accepted the connection.
+    fn connect_peers(client: &mut Peer, server: &mut Peer) {
+        let server_events = server.event_receiver();
+        client.connect(server.local_addr().expect("Could not get address")).expect("Could not connect");
+        let start = Instant::now();
+        let mut is_connected = false;
+        while !is_connected && start.elapsed() < Duration::from_secs(5) {
+            client.process();
+            server.process();
+            thread::sleep(Duration::from_millis(1));
+            is_connected = server_events.try_iter().any(|event| matches!(event, PeerEvent::IncomingConnection(_)));
+        }
+        assert!(is_connected, "Connection not established");
+    }
+
+    #[test]
+    fn disconnect_with_flush_closes_connection_after_acknowledgement() {
+        // Arrange
+        let mut server = Peer::bind_with_config("127.0.0.1:0", Config { guid: 1, ..Config::default() }).expect("Could not bind server");
+        let server_addr = server.local_addr().expect("Could not get address");
+        let mut client = Peer::bind_with_config("127.0.0.1:0", Config { guid: 2, ..Config::default() }).expect("Could not bind client");
+        let client_events = client.event_receiver();
+        connect_peers(&mut client, &mut server);
+        client.send(server_addr, SendOptions::new(Priority::Medium, Reliability::Reliable, Ordering::None), vec![0x86]).expect("Could not send packet");
+
+        // Act
+        let is_disconnecting = client.disconnect(1, true);
+        client.process();
+        let event_before_ack = client_events.try_iter().find(|event| matches!(event, PeerEvent::ConnectionClosed { .. }));
+        let start = Instant::now();
+        let mut event = None;
+        while event.is_none() && start.elapsed() < Duration::from_secs(5) {
+            server.process();
+            thread::sleep(Duration::from_millis(1));
+            client.process();
+            event = client_events.try_iter().find(|event| matches!(event, PeerEvent::ConnectionClosed { .. }));
+        }
+
+        // Assert
+        assert!(is_disconnecting);
+        assert_eq!(None, event_before_ack);
+        assert_eq!(Some(PeerEvent::ConnectionClosed { addr: server_addr, guid: 1, reason: ConnectionLostReason::LocalDisconnect }), event);
+        assert_eq!(None, client.addr_of(1));
+    }
+
+    #[test]
+    fn disconnect_without_flush_closes_connection_immediately() {
+        // Arrange
+        let mut server = Peer::bind_with_config("127.0.0.1:0", Config { guid: 1, ..Config::default() }).expect("Could not bind server");
+        let server_addr = server.local_addr().expect("Could not get address");
+        let mut client = Peer::bind_with_config("127.0.0.1:0", Config { guid: 2, ..Config::default() }).expect("Could not bind client");
+        let client_events = client.event_receiver();
+        connect_peers(&mut client, &mut server);
+
+        // Act
+        let is_disconnecting = client.disconnect(1, false);
+        client.process();
+
+        // Assert
+        assert!(is_disconnecting);
+        assert!(client_events.try_iter().any(|event|
+            event == PeerEvent::ConnectionClosed { addr: server_addr, guid: 1, reason: ConnectionLostReason::LocalDisconnect }));
+        assert!(!client.disconnect(1, false));
+    }
+
+    #[test]
+    fn receipts_of_unacknowledged_packets_are_lost_on_disconnect() {
+        // Arrange
+        let mut server = Peer::bind_with_config("127.0.0.1:0", Config { guid: 1, ..Config::default() }).expect("Could not bind server");
+        let server_addr = server.local_addr().expect("Could not get address");
+        let mut client = Peer::bind_with_config("127.0.0.1:0", Config { guid: 2, ..Config::default() }).expect("Could not bind client");
+        let client_events = client.event_receiver();
+        connect_peers(&mut client, &mut server);
+        client_events.try_iter().for_each(drop);
+
+        // Act
+        let receipt1 = client.send_with_receipt(server_addr, SendOptions::new(Priority::Medium, Reliability::Reliable, Ordering::None), vec![0x86]).expect("Could not send packet");
+        let receipt2 = client.send_with_receipt(server_addr, SendOptions::new(Priority::Medium, Reliability::Unreliable, Ordering::None), vec![0x86; 5000]).expect("Could not send packet");
+        client.disconnect(1, false);
+        client.process();
+
+        // Assert
+        assert_eq!((0, 1), (receipt1, receipt2));
+        let mut lost_receipts: Vec<u32> = client_events.try_iter().filter_map(|event| match event {
+            PeerEvent::SendReceiptLoss(receipt) => Some(receipt.receipt()),
+            _ => None,
+        }).collect();
+        lost_receipts.sort_unstable();
+        assert_eq!(vec![0, 1], lost_receipts);
+        assert_eq!(2, client.next_receipt());
+    }
+
+    #[test]
+    fn remote_peer_loses_connection_on_disconnect() {
+        // Arrange
+        let mut server = Peer::bind_with_config("127.0.0.1:0", Config { guid: 1, ..Config::default() }).expect("Could not bind server");
+        let server_events = server.event_receiver();
+        let mut client = Peer::bind_with_config("127.0.0.1:0", Config { guid: 2, ..Config::default() }).expect("Could not bind client");
+        let client_addr = client.local_addr().expect("Could not get address");
+        connect_peers(&mut client, &mut server);
+        let server_guid = client.guid_of(server.local_addr().expect("Could not get address")).expect("Not connected");
+
+        // Act
+        client.disconnect(server_guid, false);
+        client.process();
+        let start = Instant::now();
+        let mut event = None;
+        while event.is_none() && start.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(1));
+            server.process();
+            event = server_events.try_iter().find(|event| matches!(event, PeerEvent::ConnectionLost { .. }));
+        }
+
+        // Assert
+        assert_eq!(Some(PeerEvent::ConnectionLost { addr: client_addr, guid: 2, reason: ConnectionLostReason::RemoteDisconnect }), event);
+    }
+
+    #[test]
+    fn banned_peer_loses_connection() {
+        // Arrange
+        let mut server = Peer::bind_with_config("127.0.0.1:0", Config { guid: 1, ..Config::default() }).expect("Could not bind server");
+        let server_events = server.event_receiver();
+        let mut client = Peer::bind_with_config("127.0.0.1:0", Config { guid: 2, ..Config::default() }).expect("Could not bind client");
+        let client_addr = client.local_addr().expect("Could not get address");
+        connect_peers(&mut client, &mut server);
+
+        // Act
+        server.ban_address("127.0.0.1/32".parse().expect("Could not create range"), None);
+        server.process();
+
+        // Assert
+        assert!(server_events.try_iter().any(|event|
+            event == PeerEvent::ConnectionLost { addr: client_addr, guid: 2, reason: ConnectionLostReason::Banned }));
+        assert_eq!(None, server.addr_of(2));
+    }
+
+    #[test]
+    fn packet_is_sent_to_all_except_excluded_peer() {
+        // Arrange
+        let mut server = Peer::bind_with_config("127.0.0.1:0", Config { guid: 3, ..Config::default() }).expect("Could not bind server");
+        let server_addr = server.local_addr().expect("Could not get address");
+        let mut excluded_client = Peer::bind_with_config("127.0.0.1:0", Config { guid: 1, ..Config::default() }).expect("Could not bind client");
+        let mut client = Peer::bind_with_config("127.0.0.1:0", Config { guid: 2, ..Config::default() }).expect("Could not bind client");
+        let excluded_client_events = excluded_client.event_receiver();
+        let client_events = client.event_receiver();
+        let server_events = server.event_receiver();
+        excluded_client.connect(server_addr).expect("Could not connect");
+        client.connect(server_addr).expect("Could not connect");
+        let start = Instant::now();
+        let mut incoming_connection_count = 0;
+        while incoming_connection_count < 2 && start.elapsed() < Duration::from_secs(5) {
+            excluded_client.process();
+            client.process();
+            server.process();
+            thread::sleep(Duration::from_millis(1));
+            incoming_connection_count += server_events.try_iter().filter(|event| matches!(event, PeerEvent::IncomingConnection(_))).count();
+        }
+
+        // Act
+        let count = server.send_to_target(&SendTarget::AllExcept(1), SendOptions::new(Priority::Medium, Reliability::Reliable, Ordering::None), vec![0x86, 1]);
+        let mut packet = None;
+        while packet.is_none() && start.elapsed() < Duration::from_secs(5) {
+            server.process();
+            thread::sleep(Duration::from_millis(1));
+            excluded_client.process();
+            client.process();
+            packet = client_events.try_iter().find_map(|event| match event {
+                PeerEvent::Packet(packet) => Some(packet),
+                _ => None,
+            });
+        }
+
+        // Assert
+        assert_eq!(1, count.expect("Could not send packet"));
+        assert_eq!(&[0x86, 1], packet.expect("Packet not received").payload());
+        assert!(!excluded_client_events.try_iter().any(|event| matches!(event, PeerEvent::Packet(_))));
+    }
+
+    #[test]
+    #[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+    fn workers_are_bound_to_same_port() {
+        // Act
+        let workers = Peer::bind_workers("127.0.0.1:0", 3, Config { guid: 1, ..Config::default() }).expect("Could not bind workers");
+
+        // Assert
+        let addrs: Vec<_> = workers.iter().map(|worker| worker.local_addr().expect("Could not get address")).collect();
+        assert_eq!(3, addrs.len());
+        assert!(addrs.iter().all(|addr| *addr == addrs[0]));
+    }
+}
@@ -1,4 +1,6 @@
-use crate::{IncomingConnection, Packet, SendReceipt};
+use std::{net::{IpAddr, SocketAddr}, time::{Duration, Instant, SystemTime}};
+
+use crate::{ConnectionAttemptFailedReason, ConnectionInfo, ConnectionLostReason, IncomingConnection, ModerationAction, ModerationRule, OrderingChannelIndex, Packet, SecurityEventKind, SendReceipt, TickTimings};
 
 #[derive(Debug, PartialEq)]
 pub enum PeerEvent {
@@ -6,4 +8,62 @@ pub enum PeerEvent {
     SendReceiptAcked(SendReceipt),
     SendReceiptLoss(SendReceipt),
     IncomingConnection(IncomingConnection),
+    /// An outgoing connection initiated with `Peer::connect` has been established.
+    ///
+    /// `external_addr` is the address of this peer as seen by the remote peer
+    /// and `remote_internal_addrs` are the internal addresses reported by the remote peer.
+    /// `user_data` is the value supplied in the `ConnectOptions`.
+    ConnectionEstablished { addr: SocketAddr, guid: u64, external_addr: SocketAddr, remote_internal_addrs: Vec<SocketAddr>, user_data: u64 },
+    /// An outgoing connection initiated with `Peer::connect` could not be established.
+    /// `user_data` is the value supplied in the `ConnectOptions`.
+    ConnectionAttemptFailed { addr: SocketAddr, reason: ConnectionAttemptFailedReason, user_data: u64 },
+    /// An established connection was lost or closed by the remote peer, see `ConnectionLostReason`.
+    ConnectionLost { addr: SocketAddr, guid: u64, reason: ConnectionLostReason },
+    /// A connection closed with `Peer::disconnect` has been dropped.
+    ConnectionClosed { addr: SocketAddr, guid: u64, reason: ConnectionLostReason },
+    /// The smoothed round trip time to a remote peer was updated after a pong was received.
+    /// `received_at` and `received_ms` are when the pong was received, see `Packet::received_at`.
+    PingUpdated { addr: SocketAddr, guid: u64, ping_ms: u32, received_at: Instant, received_ms: u64 },
+    /// The quality of a connection sent every `Config::connection_quality_interval_in_ms`.
+    /// `packet_loss` is the number of resends as a percentage of the packets sent for the first time
+    /// during the last five seconds and `ping_ms` is the smoothed round trip time if a pong has been received.
+    ConnectionQuality { addr: SocketAddr, guid: u64, packet_loss: f32, ping_ms: Option<u32> },
+    /// A remote peer answered a ping sent with `Peer::ping_address`. `data` is the
+    /// offline ping response of the remote peer and `rtt` is the round trip time.
+    /// `received_at` and `received_ms` are when the pong was received, see `Packet::received_at`.
+    UnconnectedPong { addr: SocketAddr, guid: u64, rtt: Duration, data: Vec<u8>, received_at: Instant, received_ms: u64 },
+    /// A connectionless message sent by a remote peer with `Peer::send_out_of_band`.
+    /// `received_at` and `received_ms` are when the message was received, see `Packet::received_at`.
+    OutOfBandMessage { addr: SocketAddr, guid: u64, data: Vec<u8>, received_at: Instant, received_ms: u64 },
+    /// A snapshot of every connection sent as the answer to `Command::QueryConnections`.
+    Connections(Vec<ConnectionInfo>),
+    /// The MTU of a connection was reduced since large datagrams
+    /// did not seem to reach the remote peer.
+    MtuReduced { addr: SocketAddr, guid: u64, old_mtu: u16, new_mtu: u16 },
+    /// Progress of a packet that is split into many parts. Reported every time
+    /// another tenth of the parts have been received.
+    ///
+    /// If `is_incoming` is true `received` is the number of parts received from the
+    /// remote peer, otherwise it is the number of sent parts acknowledged by the remote peer.
+    SplitPacketProgress { addr: SocketAddr, guid: u64, split_id: u16, received: u32, total: u32, is_incoming: bool },
+    /// Suspicious activity from a remote address, e.g. for feeding tools that ban
+    /// abusive addresses in a firewall. `time` is when the activity was detected.
+    SecurityEvent { addr: SocketAddr, kind: SecurityEventKind, time: SystemTime },
+    /// A moderation action was taken, e.g. an address was banned or a connection was kicked
+    /// for exceeding an inbound limit, for presenting and auditing the actions in an admin
+    /// interface. `addr` is the address of the remote peer that triggered the action or
+    /// None for manual actions. `ModerationAction::code` and `ModerationRule::code`
+    /// give machine-readable codes. `time` is when the action was taken.
+    ModerationAction { addr: Option<SocketAddr>, action: ModerationAction, rule: ModerationRule, time: SystemTime },
+    /// A processing round took longer than `Config::slow_tick_threshold_in_ms`.
+    /// The timings show which phase of the round was slow.
+    SlowTick(TickTimings),
+    /// Received packets have waited for a missing packet on an ordering channel for longer
+    /// than `Config::ordering_blocked_threshold_in_ms`. Reported once for each missing packet.
+    OrderingChannelBlocked { addr: SocketAddr, guid: u64, channel: OrderingChannelIndex, buffered_packets: usize, blocked_for: Duration },
+    /// The connection of a remote peer was dropped for abusing the protocol and all offline
+    /// packets from its IP address are ignored for `duration`, see `Config::greylist_duration_in_ms`.
+    AddressGreylisted { ip: IpAddr, reason: ConnectionLostReason, duration: Duration },
+    /// The cool-down of a greylisted IP address has ended.
+    GreylistExpired { ip: IpAddr },
 }
\ No newline at end of file
@@ -0,0 +1,125 @@
+#[cfg(feature = "metrics")]
+use std::fmt::Write;
+
+/// Counters and gauges of a peer aggregated over all connections, returned by
+/// `Peer::metrics`, e.g. for monitoring a server. The counters start at 0 when
+/// the peer is created and only increase.
+///
+/// With the `metrics` feature the metrics can be written in the Prometheus
+/// text format with `to_prometheus`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PeerMetrics {
+    /// The number of datagrams passed to the socket.
+    pub datagrams_sent: u64,
+
+    /// The number of bytes of the datagrams passed to the socket.
+    pub bytes_sent: u64,
+
+    /// The number of datagrams received by the socket.
+    pub datagrams_received: u64,
+
+    /// The number of bytes of the datagrams received by the socket.
+    pub bytes_received: u64,
+
+    /// The number of outgoing datagrams dropped since the send queue was full.
+    pub dropped_datagrams: u64,
+
+    /// The number of incoming datagrams dropped since their integrity tag was invalid.
+    pub invalid_datagrams: u64,
+
+    /// The number of connection handshake requests received from remote peers,
+    /// including the retries of each attempt with smaller MTU sizes.
+    pub incoming_handshakes: u64,
+
+    /// The number of outgoing connection attempts.
+    pub outgoing_connection_attempts: u64,
+
+    /// The number of established connections, both incoming and outgoing.
+    pub connections_established: u64,
+
+    /// The number of failed outgoing connection attempts.
+    pub connection_attempts_failed: u64,
+
+    /// The number of connections lost, e.g. since the remote peer stopped responding.
+    pub connections_lost: u64,
+
+    /// The number of offline packets ignored since their sender exceeded the rate limit.
+    pub rate_limited_packets: u64,
+
+    /// The number of connection requests rejected since `Config::max_new_connections_per_second` was exceeded.
+    pub rate_limited_handshakes: u64,
+
+    /// The number of datagrams ignored since their sender was banned.
+    pub banned_datagrams: u64,
+
+    /// The number of offline packets ignored since their sender was greylisted.
+    pub greylisted_datagrams: u64,
+
+    /// The number of bans, both by the user and for sending garbage.
+    pub bans: u64,
+
+    /// The current number of connections, including connections being established or closed.
+    pub connections: usize,
+
+    /// The current number of datagrams waiting for the socket to be able to send.
+    pub queued_datagrams: usize,
+
+    /// The current number of packets of all connections waiting to be sent.
+    pub queued_packets: usize,
+}
+
+impl PeerMetrics {
+    /// Returns the metrics in the Prometheus text exposition format with
+    /// names prefixed with `raknet_`, e.g. for serving at a `/metrics` endpoint.
+    #[cfg(feature = "metrics")]
+    pub fn to_prometheus(&self) -> String {
+        let metrics: [(&str, &str, &str, u64); 19] = [
+            ("datagrams_sent_total", "counter", "The number of datagrams sent.", self.datagrams_sent),
+            ("bytes_sent_total", "counter", "The number of bytes sent.", self.bytes_sent),
+            ("datagrams_received_total", "counter", "The number of datagrams received.", self.datagrams_received),
+            ("bytes_received_total", "counter", "The number of bytes received.", self.bytes_received),
+            ("dropped_datagrams_total", "counter", "The number of outgoing datagrams dropped since the send queue was full.", self.dropped_datagrams),
+            ("invalid_datagrams_total", "counter", "The number of incoming datagrams with an invalid integrity tag.", self.invalid_datagrams),
+            ("incoming_handshakes_total", "counter", "The number of connection handshake requests received.", self.incoming_handshakes),
+            ("outgoing_connection_attempts_total", "counter", "The number of outgoing connection attempts.", self.outgoing_connection_attempts),
+            ("connections_established_total", "counter", "The number of established connections.", self.connections_established),
+            ("connection_attempts_failed_total", "counter", "The number of failed outgoing connection attempts.", self.connection_attempts_failed),
+            ("connections_lost_total", "counter", "The number of lost connections.", self.connections_lost),
+            ("rate_limited_packets_total", "counter", "The number of offline packets ignored by the rate limit.", self.rate_limited_packets),
+            ("rate_limited_handshakes_total", "counter", "The number of connection requests rejected by the new connection rate limit.", self.rate_limited_handshakes),
+            ("banned_datagrams_total", "counter", "The number of datagrams ignored from banned addresses.", self.banned_datagrams),
+            ("greylisted_datagrams_total", "counter", "The number of offline packets ignored from greylisted addresses.", self.greylisted_datagrams),
+            ("bans_total", "counter", "The number of bans.", self.bans),
+            ("connections", "gauge", "The current number of connections.", self.connections as u64),
+            ("queued_datagrams", "gauge", "The current number of datagrams waiting to be sent by the socket.", self.queued_datagrams as u64),
+            ("queued_packets", "gauge", "The current number of packets waiting to be sent.", self.queued_packets as u64),
+        ];
+        let mut text = String::new();
+        for (name, kind, help, value) in metrics.iter() {
+            // Writing to a String cannot fail
+            let _ = writeln!(text, "# HELP raknet_{} {}", name, help);
+            let _ = writeln!(text, "# TYPE raknet_{} {}", name, kind);
+            let _ = writeln!(text, "raknet_{} {}", name, value);
+        }
+        text
+    }
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use super::PeerMetrics;
+
+    #[test]
+    fn metrics_are_written_in_prometheus_format() {
+        // Arrange
+        let metrics = PeerMetrics { datagrams_sent: 12, connections: 3, ..PeerMetrics::default() };
+
+        // Act
+        let text = metrics.to_prometheus();
+
+        // Assert
+        assert!(text.contains("# TYPE raknet_datagrams_sent_total counter\nraknet_datagrams_sent_total 12\n"));
+        assert!(text.contains("# TYPE raknet_connections gauge\nraknet_connections 3\n"));
+        assert_eq!(19 * 3, text.lines().count());
+    }
+}
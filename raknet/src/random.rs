@@ -0,0 +1,48 @@
+//! Random numbers for GUIDs, keys and reconnect jitter. With the `rand` feature they
+//! come from `rand`. Without it they come from the randomly seeded hasher of the
+//! standard library, which is not cryptographically secure, so secret keys can only
+//! be created with the `rand` feature.
+
+#[cfg(not(feature = "rand"))]
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+#[cfg(feature = "rand")]
+pub fn random_u64() -> u64 {
+    rand::random()
+}
+
+#[cfg(not(feature = "rand"))]
+pub fn random_u64() -> u64 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    hasher.finish()
+}
+
+/// Returns a number from 0.0 (inclusive) to 1.0 (exclusive).
+pub fn random_f64() -> f64 {
+    (random_u64() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(feature = "rand")]
+pub fn random_bytes() -> [u8; 32] {
+    rand::random()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::random_f64;
+
+    #[test]
+    fn random_f64_is_between_zero_and_one() {
+        // Act
+        let values: Vec<f64> = (0..1000).map(|_| random_f64()).collect();
+
+        // Assert
+        assert!(values.iter().all(|value| (0.0..1.0).contains(value)));
+    }
+}
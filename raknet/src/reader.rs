@@ -1,45 +1,32 @@
 use std::{io::{Cursor, Read}, net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6}, usize};
 
-use crate::{error::{Error, ReadError, Result}, number::u24};
+use crate::{error::{Error, ReadError, Result}, number::u24, socket_addr_format::SocketAddrFormat};
 
+/// Implements reading of numbers with the given byte order as default methods of `DataRead`.
+macro_rules! read_numbers {
+    ($($name:ident: $ty:ty, $from_bytes:ident;)*) => {
+        $(
+            fn $name(&mut self) -> Result<$ty> {
+                let mut buf = [0u8; std::mem::size_of::<$ty>()];
+                self.read_bytes(&mut buf)?;
+                Ok(<$ty>::$from_bytes(buf))
+            }
+        )*
+    };
+}
+
+/// Reads the data types of RakNet messages. Implementors provide the reading of raw
+/// bytes, e.g. from a byte slice in `DataReader` or at any bit position in `BitReader`,
+/// and get the other data types from the default methods.
 pub trait DataRead {
-    fn read_u8(&mut self) -> Result<u8>;
-    fn read_u8_and_compare(&mut self, data: u8) -> Result<()>;
     fn read_bytes(&mut self, buf: &mut [u8]) -> Result<()>;
     fn read_bytes_to_boxed_slice(&mut self, length: usize) -> Result<Box<[u8]>>;
     fn read_bytes_to_end(&mut self, buf: &mut Vec<u8>) -> Result<()>;
-    fn read_bytes_and_compare(&mut self, data: &[u8]) -> Result<()>;
-    fn read_u16(&mut self) -> Result<u16>;
-    fn read_u16_be(&mut self) -> Result<u16>;
-    fn read_u24(&mut self) -> Result<u24>;
-    fn read_u32(&mut self) -> Result<u32>;
-    fn read_u32_be(&mut self) -> Result<u32>;
-    fn read_u64_be(&mut self) -> Result<u64>;
-    fn read_f32_be(&mut self) -> Result<f32>;
-    fn read_fixed_string(&mut self) -> Result<String>;
-    fn read_zero_padding(&mut self) -> Result<u16>;
-    fn read_socket_addr(&mut self) -> Result<SocketAddr>;
     fn has_more(&self) -> bool;
-}
-
-pub struct DataReader<'a> {
-    cursor: Cursor<&'a [u8]>,
-}
-
-impl<'a> DataReader<'a> {
-    pub fn new(data: &'a [u8]) -> DataReader<'a> {
-        DataReader {
-            cursor: Cursor::new(data),
-        }
-    }
-}
-
-impl<'a> DataRead for DataReader<'a> {
-    fn read_u8(&mut self) -> Result<u8> {
-        let mut buf = [0u8; 1];
-        self.cursor.read_exact(&mut buf)?;
-        Ok(u8::from_le_bytes(buf))
-    }
+    /// Returns the number of whole bytes that are left to read.
+    fn remaining(&self) -> usize;
+    /// Returns the number of whole bytes that have been read.
+    fn position(&self) -> usize;
 
     fn read_u8_and_compare(&mut self, data: u8) -> Result<()> {
         let byte = self.read_u8()?;
@@ -50,25 +37,6 @@ impl<'a> DataRead for DataReader<'a> {
         }
     }
 
-    fn read_bytes(&mut self, buf: &mut [u8]) -> Result<()> {
-        self.cursor.read_exact(buf)?;
-        Ok(())
-    }
-
-    fn read_bytes_to_boxed_slice(&mut self, length: usize) -> Result<Box<[u8]>> {
-        if length > self.cursor.get_ref().len() - self.cursor.position() as usize {
-            return Err(ReadError::NotAllBytesRead(self.cursor.get_ref().len() - self.cursor.position() as usize).into());
-        }
-        let boxed_slice = self.cursor.get_ref()[self.cursor.position() as usize..self.cursor.position() as usize + length].to_vec().into_boxed_slice();
-        self.cursor.set_position(self.cursor.position() + length as u64);
-        Ok(boxed_slice)
-    }
-
-    fn read_bytes_to_end(&mut self, buf: &mut Vec<u8>) -> Result<()> {        
-        self.cursor.read_to_end(buf)?;
-        Ok(())
-    }
-
     fn read_bytes_and_compare(&mut self, data: &[u8]) -> Result<()> {
         let mut buf = vec![0u8; data.len()];
         self.read_bytes(&mut buf)?;
@@ -79,16 +47,25 @@ impl<'a> DataRead for DataReader<'a> {
         }
     }
 
-    fn read_u16(&mut self) -> Result<u16> {
-        let mut buf = [0u8; 2];
-        self.read_bytes(&mut buf)?;
-        Ok(u16::from_le_bytes(buf))
-    }
-
-    fn read_u16_be(&mut self) -> Result<u16> {
-        let mut buf = [0u8; 2];
-        self.read_bytes(&mut buf)?;
-        Ok(u16::from_be_bytes(buf))
+    read_numbers! {
+        read_u8: u8, from_le_bytes;
+        read_i8: i8, from_le_bytes;
+        read_u16: u16, from_le_bytes;
+        read_u16_be: u16, from_be_bytes;
+        read_i16: i16, from_le_bytes;
+        read_i16_be: i16, from_be_bytes;
+        read_u32: u32, from_le_bytes;
+        read_u32_be: u32, from_be_bytes;
+        read_i32: i32, from_le_bytes;
+        read_i32_be: i32, from_be_bytes;
+        read_u64: u64, from_le_bytes;
+        read_u64_be: u64, from_be_bytes;
+        read_i64: i64, from_le_bytes;
+        read_i64_be: i64, from_be_bytes;
+        read_f32: f32, from_le_bytes;
+        read_f32_be: f32, from_be_bytes;
+        read_f64: f64, from_le_bytes;
+        read_f64_be: f64, from_be_bytes;
     }
 
     fn read_u24(&mut self) -> Result<u24> {
@@ -97,51 +74,70 @@ impl<'a> DataRead for DataReader<'a> {
         Ok(u24::from_le_bytes(buf))
     }
 
-    fn read_u32(&mut self) -> Result<u32> {
-        let mut buf = [0u8; 4];
-        self.read_bytes(&mut buf)?;
-        Ok(u32::from_le_bytes(buf))
+    /// Reads a UTF-8 string with a 16-bit big endian length prefix. Use
+    /// `read_fixed_string_with_max_length` when the length is sent by a remote peer.
+    fn read_fixed_string(&mut self) -> Result<String> {
+        self.read_fixed_string_with_max_length(u16::MAX.into())
     }
 
-    fn read_u32_be(&mut self) -> Result<u32> {
-        let mut buf = [0u8; 4];
-        self.read_bytes(&mut buf)?;
-        Ok(u32::from_be_bytes(buf))
+    /// Reads a UTF-8 string with a 16-bit big endian length prefix. Fails with
+    /// `ReadError::StringTooLong`, before allocating, if the length is larger than `max_length`.
+    fn read_fixed_string_with_max_length(&mut self, max_length: usize) -> Result<String> {
+        Ok(String::from_utf8(self.read_byte_string(max_length)?)?)
     }
 
-    fn read_u64_be(&mut self) -> Result<u64> {
-        let mut buf = [0u8; 8];
-        self.read_bytes(&mut buf)?;
-        Ok(u64::from_be_bytes(buf))
+    /// Reads a string like `read_fixed_string_with_max_length` but replaces invalid
+    /// UTF-8 sequences with U+FFFD instead of failing.
+    fn read_fixed_string_lossy(&mut self, max_length: usize) -> Result<String> {
+        Ok(String::from_utf8_lossy(&self.read_byte_string(max_length)?).into_owned())
     }
 
-    fn read_f32_be(&mut self) -> Result<f32> {
-        let mut buf = [0u8; 4];
-        self.read_bytes(&mut buf)?;
-        Ok(f32::from_be_bytes(buf))
-    }
-
-    fn read_fixed_string(&mut self) -> Result<String> {
+    /// Reads the raw bytes of a string with a 16-bit big endian length prefix. Fails with
+    /// `ReadError::StringTooLong`, before allocating, if the length is larger than `max_length`
+    /// and with `ReadError::NotAllBytesRead` if the length is larger than the bytes left.
+    fn read_byte_string(&mut self, max_length: usize) -> Result<Vec<u8>> {
         let length: usize = self.read_u16_be()?.into();
+        if length > max_length {
+            return Err(ReadError::StringTooLong(length).into());
+        }
+        if length > self.remaining() {
+            return Err(ReadError::NotAllBytesRead(self.remaining()).into());
+        }
         let mut buf = vec![0u8; length];
         self.read_bytes(&mut buf)?;
-        Ok(String::from_utf8(buf)?)
+        Ok(buf)
     }
 
     fn read_zero_padding(&mut self) -> Result<u16> {
-        let mut padding_length = 0u16;
-        let mut buf = [0u8; 1];
-        loop {
-            let n = self.cursor.read(&mut buf)?;
-            if n == 0 {
-                break;
-            }
-            if padding_length == u16::MAX {
-                return Err(ReadError::TooLongZeroPadding.into());
-            }
-            padding_length += 1;
+        self.read_zero_padding_with_limit(u16::MAX)
+    }
+
+    /// Reads the padding until the end of the data. Fails with `ReadError::TooLongZeroPadding`,
+    /// without reading anything, if more than `limit` bytes are left.
+    fn read_zero_padding_with_limit(&mut self, limit: u16) -> Result<u16> {
+        let padding_length = self.remaining();
+        if padding_length > limit.into() {
+            return Err(ReadError::TooLongZeroPadding.into());
         }
-        Ok(padding_length)
+        while self.has_more() {
+            self.read_u8()?;
+        }
+        Ok(padding_length as u16)
+    }
+
+    /// Reads the bytes until the end of the data like `read_bytes_to_end`. Fails with
+    /// `ReadError::TooLongTrailingData`, without reading anything, if more than `limit` bytes are left.
+    fn read_bytes_to_end_with_limit(&mut self, buf: &mut Vec<u8>, limit: usize) -> Result<()> {
+        let length = self.remaining();
+        if length > limit {
+            return Err(ReadError::TooLongTrailingData(length).into());
+        }
+        self.read_bytes_to_end(buf)
+    }
+
+    /// Returns the format of the socket addresses read by `read_socket_addr`.
+    fn socket_addr_format(&self) -> SocketAddrFormat {
+        SocketAddrFormat::default()
     }
 
     fn read_socket_addr(&mut self) -> Result<SocketAddr> {
@@ -149,16 +145,17 @@ impl<'a> DataRead for DataReader<'a> {
         match ip_version {
             0x04 => {
                 let mut ip = [0u8; 4];                
-                self.cursor.read_exact(&mut ip)?;
+                self.read_bytes(&mut ip)?;
+                ip.iter_mut().for_each(|b| *b = !*b);
                 let port = self.read_u16_be()?;
-                Ok(SocketAddr::new(IpAddr::V4(Ipv4Addr::new(!ip[0], !ip[1], !ip[2], !ip[3])), port))
+                Ok(SocketAddr::new(IpAddr::V4(Ipv4Addr::from(ip)), port))
             },
             0x06 => {
                 let _ = self.read_u16()?; // family
                 let port = self.read_u16_be()?;
                 let flowinfo = self.read_u32()?;
                 let mut ip = [0u8; 16];
-                self.cursor.read_exact(&mut ip)?;
+                self.read_bytes(&mut ip)?;
                 let scope_id = self.read_u32()?;
                 Ok(SocketAddr::V6(SocketAddrV6::new(Ipv6Addr::from(ip), port, flowinfo, scope_id)))
             },
@@ -166,9 +163,109 @@ impl<'a> DataRead for DataReader<'a> {
         }
     }
 
+    /// Reads an unsigned VarInt of at most 5 bytes as used by Bedrock.
+    fn read_var_u32(&mut self) -> Result<u32> {
+        Ok(read_var(self, 32)? as u32)
+    }
+
+    /// Reads a zigzag encoded signed VarInt of at most 5 bytes.
+    fn read_var_i32(&mut self) -> Result<i32> {
+        let value = self.read_var_u32()?;
+        Ok((value >> 1) as i32 ^ -((value & 1) as i32))
+    }
+
+    /// Reads an unsigned VarLong of at most 10 bytes.
+    fn read_var_u64(&mut self) -> Result<u64> {
+        read_var(self, 64)
+    }
+
+    /// Reads a zigzag encoded signed VarLong of at most 10 bytes.
+    fn read_var_i64(&mut self) -> Result<i64> {
+        let value = self.read_var_u64()?;
+        Ok((value >> 1) as i64 ^ -((value & 1) as i64))
+    }
+}
+
+/// Reads a VarInt with seven bits per byte, least significant group first, where the
+/// high bit is set in all bytes but the last. Fails if the value does not fit in `bits` bits.
+fn read_var<R: DataRead + ?Sized>(reader: &mut R, bits: u32) -> Result<u64> {
+    let mut value = 0u64;
+    let mut shift = 0;
+    loop {
+        let b = reader.read_u8()?;
+        let group = (b & 0x7f) as u64;
+        if shift >= bits || (bits - shift < 7 && group >> (bits - shift) != 0) {
+            return Err(ReadError::VarIntTooLarge.into());
+        }
+        value |= group << shift;
+        if b & 0x80 == 0 {
+            return Ok(value);
+        }
+        shift += 7;
+    }
+}
+
+pub struct DataReader<'a> {
+    cursor: Cursor<&'a [u8]>,
+    socket_addr_format: SocketAddrFormat,
+}
+
+impl<'a> DataReader<'a> {
+    pub fn new(data: &'a [u8]) -> DataReader<'a> {
+        DataReader {
+            cursor: Cursor::new(data),
+            socket_addr_format: SocketAddrFormat::default(),
+        }
+    }
+
+    /// Sets the format of the socket addresses read by `read_socket_addr`.
+    pub fn with_socket_addr_format(mut self, socket_addr_format: SocketAddrFormat) -> Self {
+        self.socket_addr_format = socket_addr_format;
+        self
+    }
+
+    /// Reads `length` bytes as a slice borrowed from the data being read instead of copying them.
+    pub fn read_slice(&mut self, length: usize) -> Result<&'a [u8]> {
+        let data: &'a [u8] = self.cursor.get_ref();
+        let position = self.cursor.position() as usize;
+        if length > data.len() - position {
+            return Err(ReadError::NotAllBytesRead(data.len() - position).into());
+        }
+        self.cursor.set_position((position + length) as u64);
+        Ok(&data[position..position + length])
+    }
+}
+
+impl<'a> DataRead for DataReader<'a> {
+    fn read_bytes(&mut self, buf: &mut [u8]) -> Result<()> {
+        self.cursor.read_exact(buf)?;
+        Ok(())
+    }
+
+    fn read_bytes_to_boxed_slice(&mut self, length: usize) -> Result<Box<[u8]>> {
+        Ok(self.read_slice(length)?.into())
+    }
+
+    fn read_bytes_to_end(&mut self, buf: &mut Vec<u8>) -> Result<()> {        
+        self.cursor.read_to_end(buf)?;
+        Ok(())
+    }
+
     fn has_more(&self) -> bool {
         (self.cursor.position() as usize) < self.cursor.get_ref().len()
     }
+
+    fn socket_addr_format(&self) -> SocketAddrFormat {
+        self.socket_addr_format
+    }
+
+    fn remaining(&self) -> usize {
+        self.cursor.get_ref().len().saturating_sub(self.cursor.position() as usize)
+    }
+
+    fn position(&self) -> usize {
+        self.cursor.position() as usize
+    }
 }
 
 pub trait MessageRead: Sized {
@@ -189,7 +286,7 @@ pub trait MessageRead: Sized {
 #[cfg(test)]
 mod tests {
     use std::net::SocketAddr;
-    use crate::DataRead;
+    use crate::{number::u24, socket_addr_format::SocketAddrFormat, DataRead, DataWrite, Error, ReadError};
     use super::DataReader;
 
     #[test]
@@ -205,6 +302,19 @@ mod tests {
         assert_eq!(SocketAddr::from(([192, 168, 1, 248], 0x1234)), socket_addr);
     }
 
+    #[test]
+    fn read_socket_addr_ipv4_with_classic_format() {
+        // Arrange
+        let buf = vec![0x04u8, !192, !168, !1, !248, 0x12, 0x34];
+        let mut reader = DataReader::new(&buf).with_socket_addr_format(SocketAddrFormat::Classic);
+
+        // Act
+        let socket_addr = reader.read_socket_addr().expect("Could not read SocketAddr");
+
+        // Assert
+        assert_eq!(SocketAddr::from(([192, 168, 1, 248], 0x1234)), socket_addr);
+    }
+
     #[test]
     fn read_socket_addr_ipv6() {
         // Arrange
@@ -231,4 +341,146 @@ mod tests {
             panic!("Did not receive IP V6");
         }
     }    
+
+    #[test]
+    fn read_var_ints() {
+        // Arrange
+        let buf = vec![
+            0x00, // 0
+            0xAC, 0x02, // 300
+            0xFF, 0xFF, 0xFF, 0xFF, 0x0F, // u32::MAX
+            0x03, // Zigzag: -2
+            0xFF, 0xFF, 0xFF, 0xFF, 0x0F, // Zigzag: i32::MIN
+            0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01, // u64::MAX
+            0xFE, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01, // Zigzag: i64::MAX
+            ];
+        let mut reader = DataReader::new(&buf);
+
+        // Act/Assert
+        assert_eq!(0, reader.read_var_u32().expect("Could not read VarInt"));
+        assert_eq!(300, reader.read_var_u32().expect("Could not read VarInt"));
+        assert_eq!(u32::MAX, reader.read_var_u32().expect("Could not read VarInt"));
+        assert_eq!(-2, reader.read_var_i32().expect("Could not read VarInt"));
+        assert_eq!(i32::MIN, reader.read_var_i32().expect("Could not read VarInt"));
+        assert_eq!(u64::MAX, reader.read_var_u64().expect("Could not read VarLong"));
+        assert_eq!(i64::MAX, reader.read_var_i64().expect("Could not read VarLong"));
+        assert!(!reader.has_more());
+    }
+
+    #[test]
+    fn read_too_large_var_ints_fails() {
+        // Arrange
+        let too_large_u32 = [0xFF, 0xFF, 0xFF, 0xFF, 0x1F];
+        let too_long_u32 = [0x80, 0x80, 0x80, 0x80, 0x80, 0x00];
+        let too_large_u64 = [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x03];
+        let truncated = [0x80];
+
+        // Act/Assert
+        assert!(matches!(DataReader::new(&too_large_u32).read_var_u32(), Err(Error::ReadError(ReadError::VarIntTooLarge))));
+        assert!(matches!(DataReader::new(&too_long_u32).read_var_u32(), Err(Error::ReadError(ReadError::VarIntTooLarge))));
+        assert!(matches!(DataReader::new(&too_large_u64).read_var_u64(), Err(Error::ReadError(ReadError::VarIntTooLarge))));
+        assert!(matches!(DataReader::new(&truncated).read_var_u64(), Err(Error::IoError(_))));
+    }
+
+    #[test]
+    fn read_strings_with_max_length() {
+        // Arrange
+        let buf = vec![
+            0x00, 0x03, b'a', b'b', b'c',
+            0x00, 0x02, 0xC3, 0x28, // Invalid UTF-8
+            0x00, 0x02, 0xC3, 0x28,
+            0x00, 0x02, 0xC3, 0x28,
+            0xFF, 0xFF, // Too long
+            ];
+        let mut reader = DataReader::new(&buf);
+
+        // Act/Assert
+        assert_eq!("abc", reader.read_fixed_string_with_max_length(3).expect("Could not read string"));
+        assert_eq!(vec![0xC3, 0x28], reader.read_byte_string(2).expect("Could not read byte string"));
+        assert_eq!("\u{FFFD}(", reader.read_fixed_string_lossy(2).expect("Could not read string"));
+        assert!(matches!(reader.read_fixed_string_with_max_length(2), Err(Error::ReadError(ReadError::InvalidString(_)))));
+        assert!(matches!(reader.read_byte_string(100), Err(Error::ReadError(ReadError::StringTooLong(0xFFFF)))));
+    }
+
+    #[test]
+    fn read_string_longer_than_data_fails_before_allocating() {
+        // Arrange
+        let buf = vec![0xFF, 0xFF, b'a', b'b'];
+        let mut reader = DataReader::new(&buf);
+
+        // Act/Assert
+        assert!(matches!(reader.read_fixed_string(), Err(Error::ReadError(ReadError::NotAllBytesRead(2)))));
+    }
+
+    #[test]
+    fn read_slice_borrows_from_data() {
+        // Arrange
+        let buf = vec![0x01, 0x02, 0x03, 0x04];
+        let mut reader = DataReader::new(&buf);
+        reader.read_u8().expect("Could not read u8");
+
+        // Act
+        let slice = reader.read_slice(2).expect("Could not read slice");
+
+        // Assert
+        assert_eq!(&buf[1..3], slice);
+        assert_eq!(buf[1..3].as_ptr(), slice.as_ptr());
+        assert!(matches!(reader.read_slice(2), Err(Error::ReadError(ReadError::NotAllBytesRead(1)))));
+        assert_eq!(0x04, reader.read_u8().expect("Could not read u8"));
+    }
+
+    #[test]
+    fn read_trailing_data_with_limit() {
+        // Arrange
+        let buf = vec![0x01, 0x00, 0x00, 0x00];
+        let mut reader = DataReader::new(&buf);
+        reader.read_u8().expect("Could not read u8");
+
+        // Act/Assert
+        assert_eq!(1, reader.position());
+        assert_eq!(3, reader.remaining());
+        assert!(matches!(reader.read_zero_padding_with_limit(2), Err(Error::ReadError(ReadError::TooLongZeroPadding))));
+        let mut data = Vec::new();
+        assert!(matches!(reader.read_bytes_to_end_with_limit(&mut data, 2), Err(Error::ReadError(ReadError::TooLongTrailingData(3)))));
+        assert_eq!(3, reader.remaining());
+        reader.read_bytes_to_end_with_limit(&mut data, 3).expect("Could not read bytes to end");
+        assert_eq!(vec![0x00, 0x00, 0x00], data);
+        assert_eq!(4, reader.position());
+        assert_eq!(0, reader.remaining());
+    }
+
+    macro_rules! assert_round_trip {
+        ($write:ident, $read:ident, $($value:expr),*) => {
+            $(
+                let mut buf = Vec::new();
+                buf.$write($value).expect("Could not write number");
+                let mut reader = DataReader::new(&buf);
+                assert_eq!($value, reader.$read().expect("Could not read number"));
+                assert!(!reader.has_more());
+            )*
+        };
+    }
+
+    #[test]
+    fn write_and_read_numbers_round_trip() {
+        assert_round_trip!(write_u8, read_u8, u8::MIN, 0x12, u8::MAX);
+        assert_round_trip!(write_i8, read_i8, i8::MIN, -0x12, i8::MAX);
+        assert_round_trip!(write_u16, read_u16, u16::MIN, 0x1234, u16::MAX);
+        assert_round_trip!(write_u16_be, read_u16_be, u16::MIN, 0x1234, u16::MAX);
+        assert_round_trip!(write_i16, read_i16, i16::MIN, -0x1234, i16::MAX);
+        assert_round_trip!(write_i16_be, read_i16_be, i16::MIN, -0x1234, i16::MAX);
+        assert_round_trip!(write_u24, read_u24, u24::from(0u8), u24::from_le_bytes([0x56, 0x34, 0x12]), u24::from_le_bytes([0xFF; 3]));
+        assert_round_trip!(write_u32, read_u32, u32::MIN, 0x12345678, u32::MAX);
+        assert_round_trip!(write_u32_be, read_u32_be, u32::MIN, 0x12345678, u32::MAX);
+        assert_round_trip!(write_i32, read_i32, i32::MIN, -0x12345678, i32::MAX);
+        assert_round_trip!(write_i32_be, read_i32_be, i32::MIN, -0x12345678, i32::MAX);
+        assert_round_trip!(write_u64, read_u64, u64::MIN, 0x123456789ABCDEF0, u64::MAX);
+        assert_round_trip!(write_u64_be, read_u64_be, u64::MIN, 0x123456789ABCDEF0, u64::MAX);
+        assert_round_trip!(write_i64, read_i64, i64::MIN, -0x123456789ABCDEF0, i64::MAX);
+        assert_round_trip!(write_i64_be, read_i64_be, i64::MIN, -0x123456789ABCDEF0, i64::MAX);
+        assert_round_trip!(write_f32, read_f32, f32::MIN, -1.5, f32::MAX);
+        assert_round_trip!(write_f32_be, read_f32_be, f32::MIN, -1.5, f32::MAX);
+        assert_round_trip!(write_f64, read_f64, f64::MIN, -1.5, f64::MAX);
+        assert_round_trip!(write_f64_be, read_f64_be, f64::MIN, -1.5, f64::MAX);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,180 @@
+use std::{collections::HashMap, net::SocketAddr, time::{Duration, Instant}};
+
+use crate::{config::Config, connect_options::ConnectOptions, logging::debug, random};
+
+/// The status of the connection to an address that the reconnector watches.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ConnectionStatus {
+    Disconnected,
+    Connecting,
+    Connected,
+}
+
+struct ReconnectTarget {
+    options: ConnectOptions,
+    failures: u32,
+    next_connect_time: Option<Instant>,
+}
+
+/// Keeps connections to a set of addresses alive by reconnecting with
+/// exponential backoff and jitter when a connection is lost or a
+/// connection attempt fails.
+pub struct Reconnector {
+    targets: HashMap<SocketAddr, ReconnectTarget>,
+    initial_delay: Duration,
+    max_delay: Duration,
+}
+
+impl Reconnector {
+    pub fn new(config: &Config) -> Self {
+        Reconnector {
+            targets: HashMap::new(),
+            initial_delay: Duration::from_millis(config.reconnect_initial_delay_in_ms as u64),
+            max_delay: Duration::from_millis(config.reconnect_max_delay_in_ms as u64),
+        }
+    }
+
+    /// Starts watching the specified address. If there is no connection
+    /// to the address a connection attempt is made immediately.
+    pub fn start(&mut self, time: Instant, addr: SocketAddr, options: ConnectOptions) {
+        debug!("Starting to reconnect to {}", addr);
+        self.targets.insert(addr, ReconnectTarget { options, failures: 0, next_connect_time: Some(time) });
+    }
+
+    /// Stops watching the specified address. An existing connection is not closed.
+    pub fn stop(&mut self, addr: SocketAddr) {
+        debug!("Stopping to reconnect to {}", addr);
+        self.targets.remove(&addr);
+    }
+
+    /// Updates the watched addresses given their current connection status and
+    /// returns the addresses, with their options, that should be connected to now.
+    pub fn update(&mut self, time: Instant, get_status: impl Fn(SocketAddr) -> ConnectionStatus) -> Vec<(SocketAddr, ConnectOptions)> {
+        let mut connects = Vec::new();
+        for (addr, target) in self.targets.iter_mut() {
+            match get_status(*addr) {
+                ConnectionStatus::Connected => {
+                    target.failures = 0;
+                    target.next_connect_time = None;
+                },
+                ConnectionStatus::Connecting => {},
+                ConnectionStatus::Disconnected => match target.next_connect_time {
+                    Some(next_connect_time) if time >= next_connect_time => {
+                        debug!("Reconnecting to {}", addr);
+                        target.next_connect_time = None;
+                        connects.push((*addr, target.options.clone()));
+                    },
+                    Some(_) => {},
+                    None => {
+                        let delay = Self::get_delay(self.initial_delay, self.max_delay, target.failures, random::random_f64());
+                        debug!("Connection to {} lost or failed, reconnecting in {:?}", addr, delay);
+                        target.failures = target.failures.saturating_add(1);
+                        target.next_connect_time = Some(time + delay);
+                    },
+                },
+            }
+        }
+        connects
+    }
+
+    /// Returns the delay before the next reconnect after the specified number of
+    /// failures. The delay doubles for each failure up to the max delay and is then
+    /// randomized to between half and all of that delay using `jitter` (0.0 to 1.0)
+    /// so that many peers do not reconnect at the same time.
+    fn get_delay(initial_delay: Duration, max_delay: Duration, failures: u32, jitter: f64) -> Duration {
+        let delay = initial_delay
+            .checked_mul(1u32.checked_shl(failures).unwrap_or(u32::MAX))
+            .unwrap_or(max_delay)
+            .min(max_delay);
+        delay / 2 + delay.div_f64(2.0).mul_f64(jitter.clamp(0.0, 1.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr, time::{Duration, Instant}};
+    use crate::{config::Config, connect_options::ConnectOptions};
+    use super::{ConnectionStatus, Reconnector};
+
+    fn create_reconnector() -> (Reconnector, SocketAddr) {
+        let config = Config { reconnect_initial_delay_in_ms: 1000, reconnect_max_delay_in_ms: 8000, ..Config::default() };
+        let addr = "127.0.0.1:19132".parse::<SocketAddr>().expect("Could not create address");
+        (Reconnector::new(&config), addr)
+    }
+
+    #[test]
+    fn get_delay_doubles_up_to_max_delay() {
+        // Arrange
+        let initial_delay = Duration::from_millis(1000);
+        let max_delay = Duration::from_millis(8000);
+
+        // Act
+        let delays: Vec<Duration> = (0..6).map(|failures| Reconnector::get_delay(initial_delay, max_delay, failures, 1.0)).collect();
+
+        // Assert
+        assert_eq!(vec![1000, 2000, 4000, 8000, 8000, 8000], delays.iter().map(|delay| delay.as_millis()).collect::<Vec<u128>>());
+    }
+
+    #[test]
+    fn get_delay_applies_jitter() {
+        // Arrange
+        let initial_delay = Duration::from_millis(1000);
+        let max_delay = Duration::from_millis(8000);
+
+        // Act
+        let min_delay = Reconnector::get_delay(initial_delay, max_delay, 1, 0.0);
+        let mid_delay = Reconnector::get_delay(initial_delay, max_delay, 1, 0.5);
+
+        // Assert
+        assert_eq!(Duration::from_millis(1000), min_delay);
+        assert_eq!(Duration::from_millis(1500), mid_delay);
+    }
+
+    #[test]
+    fn update_connects_immediately_after_start() {
+        // Arrange
+        let (mut reconnector, addr) = create_reconnector();
+        let time = Instant::now();
+        reconnector.start(time, addr, ConnectOptions::default());
+
+        // Act
+        let connects = reconnector.update(time, |_| ConnectionStatus::Disconnected);
+
+        // Assert
+        assert_eq!(vec![(addr, ConnectOptions::default())], connects);
+    }
+
+    #[test]
+    fn update_reconnects_with_backoff_after_connection_lost() {
+        // Arrange
+        let (mut reconnector, addr) = create_reconnector();
+        let time = Instant::now();
+        reconnector.start(time, addr, ConnectOptions::default());
+        reconnector.update(time, |_| ConnectionStatus::Connected);
+
+        // Act
+        let connects_when_lost = reconnector.update(time, |_| ConnectionStatus::Disconnected);
+        let connects_too_early = reconnector.update(time + Duration::from_millis(499), |_| ConnectionStatus::Disconnected);
+        let connects_after_delay = reconnector.update(time + Duration::from_millis(1000), |_| ConnectionStatus::Disconnected);
+
+        // Assert
+        assert!(connects_when_lost.is_empty());
+        assert!(connects_too_early.is_empty());
+        assert_eq!(vec![(addr, ConnectOptions::default())], connects_after_delay);
+    }
+
+    #[test]
+    fn update_does_not_connect_after_stop() {
+        // Arrange
+        let (mut reconnector, addr) = create_reconnector();
+        let time = Instant::now();
+        reconnector.start(time, addr, ConnectOptions::default());
+
+        // Act
+        reconnector.stop(addr);
+        let connects = reconnector.update(time, |_| ConnectionStatus::Disconnected);
+
+        // Assert
+        assert!(connects.is_empty());
+    }
+}
@@ -1,15 +1,24 @@
-use std::{net::SocketAddr, time::Instant};
-use log::{debug, error};
+use std::{collections::HashSet, net::SocketAddr, time::{Duration, Instant}};
 
 use crate::{
     acknowledge_handler::AcknowledgeHandler,
+    bandwidth_limiter::BandwidthLimiter,
     communicator::Communicator,
     config::Config,
-    constants::{MAX_ACK_DATAGRAM_HEADER_SIZE, MAX_NACK_DATAGRAM_HEADER_SIZE, NUMBER_OF_ORDERING_CHANNELS},
+    connection_limits::ConnectionLimits,
+    connection_lost_reason::ConnectionLostReason,
+    connection_stats::ConnectionStats,
+    constants::{ENCRYPTION_OVERHEAD, MAX_SPLIT_PACKET_COUNT, NUMBER_OF_ORDERING_CHANNELS},
+    data_arrival_rate::DataArrivalRate,
+    datagram_cipher::DatagramCipher,
     datagram_header::DatagramHeader,
     datagram_range_list::DatagramRangeList,
-    error::Result,
-    internal_packet::{InternalOrdering, InternalPacket, InternalReliability, SplitPacketHeader}, 
+    error::{Error, ReadError, Result},
+    inbound_limiter::{InboundCheck, InboundLimit, InboundLimitAction, InboundLimiter},
+    internal_packet::{InternalOrdering, InternalPacket, InternalReliability, PacketPayload, SplitPacketHeader}, 
+    logging::{debug, error},
+    loss_estimator::LossEstimator,
+    mtu_blackhole_detector::next_smaller_mtu,
     nack::OutgoingNacks,
     number::{OrderingChannelIndex, OrderingIndex, SequencingIndex},
     ordering_system::OrderingSystem,
@@ -17,17 +26,27 @@ use crate::{
     outgoing_packet_heap::OutgoingPacketHeap,
     packet::{Ordering, Packet, Priority, Reliability},
     packet_datagram::PacketDatagram,
+    peer_event::PeerEvent,
     reader::{DataRead, DataReader},
     reliable_message_number_handler::ReliableMessageNumberHandler,
+    security_event::SecurityEventKind,
+    send_pacer::SendPacer,
+    send_receipt::SendReceipt,
     socket::DatagramSocket,
-    split_packet_handler::SplitPacketHandler
+    split_packet_handler::SplitPacketHandler,
+    writer::PacketWrite,
 };
+#[cfg(feature = "rand")]
+use crate::security::SessionKeys;
 
 pub struct ReliabilityLayer {
     acknowledge_handler: AcknowledgeHandler,
     outgoing_acks: OutgoingAcknowledgements,
+    data_arrival_rate: DataArrivalRate,
     outgoing_nacks: OutgoingNacks,
     outgoing_packet_heap: OutgoingPacketHeap,
+    bandwidth_limiter: BandwidthLimiter,
+    send_pacer: SendPacer,
     reliable_message_number_handler: ReliableMessageNumberHandler,
     ordering_system: OrderingSystem,
     split_packet_handler: SplitPacketHandler,
@@ -37,60 +56,133 @@ pub struct ReliabilityLayer {
     time_last_datagram_arrived: Instant,
     next_ordering_index: [OrderingIndex; NUMBER_OF_ORDERING_CHANNELS as usize],
     next_sequencing_index: [SequencingIndex; NUMBER_OF_ORDERING_CHANNELS as usize],
+    next_split_packet_id: u16,
     send_buffer: Vec<u8>,
-    is_dead_connection: bool,
+    is_continuous_send: bool,
+    oldest_queued_packet_time: Option<Instant>,
+    is_flush_requested: bool,
+    connection_lost_reason: Option<ConnectionLostReason>,
+    cipher: Option<DatagramCipher>,
+    /// Overrides `Config::ack_timeout_in_ms` if set.
+    ack_timeout_in_ms: Option<u128>,
+    /// Overrides `Config::max_retransmissions` if set.
+    max_retransmissions: Option<u32>,
+    /// The counters of the statistics returned by `stats`.
+    stats: ConnectionStats,
+    loss_estimator: LossEstimator,
+    /// The IDs of the split packets whose receipt has been reported lost.
+    lost_split_packet_ids: HashSet<u16>,
+    inbound_limiter: InboundLimiter,
 }
 
 impl ReliabilityLayer {
-    pub fn new(remote_addr: SocketAddr, remote_guid: u64, mtu: u16) -> Self {
+    pub fn new(time: Instant, remote_addr: SocketAddr, remote_guid: u64, mtu: u16) -> Self {
         ReliabilityLayer {
-            acknowledge_handler: AcknowledgeHandler::new(remote_addr, remote_guid),
+            acknowledge_handler: AcknowledgeHandler::new(remote_addr, remote_guid, mtu),
             outgoing_acks: OutgoingAcknowledgements::new(),
+            data_arrival_rate: DataArrivalRate::new(),
             outgoing_nacks: OutgoingNacks::new(),
             outgoing_packet_heap: OutgoingPacketHeap::new(),
+            bandwidth_limiter: BandwidthLimiter::new(0),
+            send_pacer: SendPacer::new(),
             reliable_message_number_handler: ReliableMessageNumberHandler::new(),
             ordering_system: OrderingSystem::new(),
-            split_packet_handler: SplitPacketHandler::new(),
+            split_packet_handler: SplitPacketHandler::new(remote_addr, remote_guid, mtu),
             remote_addr,
             remote_guid,
             mtu,
-            time_last_datagram_arrived: Instant::now(),
+            time_last_datagram_arrived: time,
             next_ordering_index: [OrderingIndex::ZERO; NUMBER_OF_ORDERING_CHANNELS as usize],
             next_sequencing_index: [SequencingIndex::ZERO; NUMBER_OF_ORDERING_CHANNELS as usize],
+            next_split_packet_id: 0,
             send_buffer: Vec::new(),
-            is_dead_connection: false,
+            is_continuous_send: false,
+            oldest_queued_packet_time: None,
+            is_flush_requested: false,
+            connection_lost_reason: None,
+            cipher: None,
+            ack_timeout_in_ms: None,
+            max_retransmissions: None,
+            stats: ConnectionStats::default(),
+            loss_estimator: LossEstimator::new(),
+            lost_split_packet_ids: HashSet::new(),
+            inbound_limiter: InboundLimiter::new(),
         }
     }
 
+    /// Encrypts all datagrams sent to and received from the remote peer with the
+    /// keys derived in the security handshake. Datagrams that are not encrypted
+    /// with the keys of the remote peer are discarded from now on.
+    #[cfg(feature = "rand")]
+    pub fn enable_encryption(&mut self, session_keys: &SessionKeys) {
+        self.cipher = Some(DatagramCipher::new(session_keys));
+    }
+
     /// Processes an incoming datagram.
     pub fn process_incoming_datagram(&mut self, payload: &[u8], time: Instant, communicator: &mut Communicator<impl DatagramSocket>) -> Option<Vec<Packet>> {
+        self.stats.bytes_received += payload.len() as u64;
+        let decrypted;
+        let payload = match &mut self.cipher {
+            Some(cipher) => match cipher.decrypt(payload) {
+                Some(datagram) => {
+                    decrypted = datagram;
+                    &decrypted[..]
+                },
+                None => {
+                    debug!("Discarding datagram from {} that could not be decrypted", self.remote_addr);
+                    return None;
+                },
+            },
+            None => payload,
+        };
         self.time_last_datagram_arrived = time;
         let mut reader = DataReader::new(payload);
         match DatagramHeader::read(&mut reader) {
             Ok(DatagramHeader::Ack { data_arrival_rate }) => {
                 debug!("Received ACK. data_arrival_rate={:?}", data_arrival_rate);
+                self.stats.acks_received += 1;
+                if let Some(data_arrival_rate) = data_arrival_rate {
+                    self.acknowledge_handler.process_data_arrival_rate(data_arrival_rate);
+                }
                 match DatagramRangeList::read(&mut reader) {
                     Ok(datagram_range_list) => {
-                        self.acknowledge_handler.process_incoming_ack(datagram_range_list, communicator);
+                        self.acknowledge_handler.process_incoming_ack(time, datagram_range_list, communicator);
                     },
                     Err(err) => error!("Error reading ACKs: {:?}", err),
                 }
             },
             Ok(DatagramHeader::Nack) => {
                 debug!("Received NACK");
+                self.stats.nacks_received += 1;
                 match DatagramRangeList::read(&mut reader) {
-                    Ok(datagram_range_list) => self.acknowledge_handler.process_incoming_nack(time, datagram_range_list),
+                    Ok(datagram_range_list) => {
+                        self.acknowledge_handler.process_incoming_nack(time, datagram_range_list);
+                        // Resend the NACK:ed packets right away instead of waiting for the next update
+                        let datagram = self.resend_packets(time, communicator);
+                        if !datagram.is_empty() {
+                            self.send_datagram(datagram, time, communicator);
+                        }
+                    },
                     Err(err) => error!("Error reading NACKs: {:?}", err),
                 }
             },
             Ok(DatagramHeader::Packet {is_packet_pair, is_continuous_send, needs_data_arrival_rate, datagram_number }) => {
                 debug!("Received a datagram of packets. is_packet_pair={}, is_continuous_send={}, needs_data_arrival_rate={}, datagram_number={}", 
                 is_packet_pair, is_continuous_send, needs_data_arrival_rate, datagram_number);
+                let packets = Self::read_packets(reader, time);
+                if packets.as_ref().is_ok_and(|packets| !self.is_within_inbound_limits(time, packets, communicator)) {
+                    // Not acknowledged so that the remote peer resends the reliable packets later
+                    return None;
+                }
                 self.outgoing_nacks.handle_datagram(datagram_number);
                 self.outgoing_acks.handle_datagram(datagram_number, time);
+                self.data_arrival_rate.on_datagram_received(time, payload.len());
+                if needs_data_arrival_rate {
+                    self.data_arrival_rate.request();
+                }
 
-                match self.process_incoming_packets(reader, time) {
-                    Ok(packets) => return Some(packets),
+                match packets {
+                    Ok(packets) => return Some(self.process_incoming_packets(packets, time, communicator)),
                     Err(err) => error!("Error reading packets: {:?}", err),
                 }
             },
@@ -99,52 +191,67 @@ impl ReliabilityLayer {
         None
     }
 
-    pub fn is_dead_connection(&self) -> bool {
-        self.is_dead_connection
+    /// Returns true if there are packets waiting to be sent or
+    /// sent datagrams that have not been acknowledged.
+    pub fn has_pending_packets(&self) -> bool {
+        !self.outgoing_packet_heap.is_empty() || self.acknowledge_handler.datagrams_in_flight() > 0
+    }
+
+    /// Returns true if there are received datagrams that have not been acknowledged yet.
+    pub fn has_pending_acks(&self) -> bool {
+        !self.outgoing_acks.is_empty()
+    }
+
+    /// Returns the reason the connection is considered lost or None
+    /// if the connection is still alive.
+    pub fn connection_lost_reason(&self) -> Option<ConnectionLostReason> {
+        self.connection_lost_reason
     }
 
     fn is_ack_timeout(&self, time: Instant, config: &Config) -> bool {
         self.acknowledge_handler.datagrams_in_flight() > 0 &&
-            time.saturating_duration_since(self.time_last_datagram_arrived).as_millis() > config.ack_timeout_in_ms
+            time.saturating_duration_since(self.time_last_datagram_arrived).as_millis() > self.ack_timeout_in_ms.unwrap_or(config.ack_timeout_in_ms)
     }
 
     pub fn update(&mut self, time: Instant, communicator: &mut Communicator<impl DatagramSocket>) {
         if self.is_ack_timeout(time, communicator.config()) {
-            self.is_dead_connection = true;
+            self.connection_lost_reason = Some(ConnectionLostReason::AckTimeout);
             return;
         }
+
+        if self.acknowledge_handler.is_mtu_blackhole_detected() {
+            self.reduce_mtu(communicator);
+        }
         
         if self.outgoing_acks.should_send_acks(time) {
-            self.send_acks(communicator);
+            self.send_acks(time, communicator);
         }
 
         if !self.outgoing_nacks.is_empty() {
             self.send_nacks(communicator);
         }
         
-        let mut datagram = PacketDatagram::new(self.acknowledge_handler.get_next_datagram_number());
-        
+        self.remove_expired_unreliable_packets(time, communicator);
+
+        self.report_blocked_ordering_channels(time, communicator);
+
         // Resend packets that have not received an ACK.
-        // NOTE: The last datagram will be sent after this loop when sending
+        // NOTE: The last datagram will be sent after this when sending
         // outgoing packets. This is done to fit as many packets
         // as possible in one datagram.
-        let packets = self.acknowledge_handler.get_packets_to_resend(time, communicator);
-        for packet in packets {
-            if !datagram.has_room_for(&packet, self.mtu) {
-                match self.acknowledge_handler.process_outgoing_datagram(datagram, time, &mut self.send_buffer) {
-                    Ok(()) => communicator.send_datagram(&self.send_buffer, self.remote_addr),
-                    Err(err) => error!("Failed processing outgoing datagram: {:?}", err),
-                }
-                datagram = PacketDatagram::new(self.acknowledge_handler.get_next_datagram_number());
-            }
-            datagram.push(packet);            
-        }       
+        let mut datagram = self.resend_packets(time, communicator);
 
-        // Send outgoing packets
+        // Send outgoing packets. New datagrams are paced to spread the
+        // sending over time instead of sending it all at once.
+        let config = communicator.config();
+        self.send_pacer.refill(time, config.max_datagrams_per_tick, Duration::from_millis(config.tick_interval_in_ms as u64));
+        let should_send_queued_packets = self.should_send_queued_packets(time, config);
         loop {
-            if self.acknowledge_handler.has_room_for_datagram() {
+            if should_send_queued_packets && self.send_pacer.has_room_for_datagram() && self.has_room_for_datagram(time, communicator) {
                 while let Some(packet) = self.outgoing_packet_heap.peek() {
-                    if !datagram.has_room_for(packet, self.mtu) {
+                    // A split packet part that was queued before the MTU was reduced
+                    // may not fit the MTU and is sent in a datagram of its own
+                    if !datagram.has_room_for(packet, self.datagram_mtu()) && !datagram.is_empty() {
                         // Datagram full, break out of loop and send datagram
                         break;
                     }
@@ -162,34 +269,323 @@ impl ReliabilityLayer {
                 // Nothing more to send, break out of loop
                 break;
             }
-            match self.acknowledge_handler.process_outgoing_datagram(datagram, time, &mut self.send_buffer) {
-                Ok(()) => communicator.send_datagram(&self.send_buffer, self.remote_addr),
-                Err(err) => error!("Failed processing outgoing datagram: {:?}", err),
+            if self.is_continuous_send {
+                datagram.set_continuous_send();
             }
+            if !self.outgoing_packet_heap.is_empty() {
+                // Sending continuously, ask for the data arrival rate to bound the send rate
+                datagram.set_needs_data_arrival_rate();
+            }
+            self.send_datagram(datagram, time, communicator);
+            self.send_pacer.on_datagram_sent();
+            self.is_continuous_send = true;
             datagram = PacketDatagram::new(self.acknowledge_handler.get_next_datagram_number());
         }
+        // The next datagram continues this send if packets are still waiting
+        self.is_continuous_send = !self.outgoing_packet_heap.is_empty();
+        if should_send_queued_packets {
+            self.is_flush_requested = false;
+            if self.outgoing_packet_heap.is_empty() {
+                self.oldest_queued_packet_time = None;
+            }
+        }
+    }
+
+    /// Steps down to the next smaller MTU size when datagrams close to the MTU do
+    /// not seem to get through. Queued packets that no longer fit in a datagram are split.
+    fn reduce_mtu(&mut self, communicator: &mut Communicator<impl DatagramSocket>) {
+        let new_mtu = match next_smaller_mtu(self.mtu) {
+            Some(new_mtu) if new_mtu >= communicator.config().min_mtu_size => new_mtu,
+            _ => {
+                // No smaller MTU to fall back to, restart the detection
+                self.acknowledge_handler.set_mtu(self.mtu);
+                return;
+            },
+        };
+        debug!("Reducing the MTU for {} from {} to {}", self.remote_addr, self.mtu, new_mtu);
+        let old_mtu = self.mtu;
+        self.mtu = new_mtu;
+        self.acknowledge_handler.set_mtu(new_mtu);
+
+        let max_packet_payload_size = self.get_max_packet_payload_size() as usize;
+        for (priority, packet) in self.outgoing_packet_heap.remove_packets_larger_than(max_packet_payload_size) {
+            let creation_time = packet.creation_time();
+            let ordering = packet.ordering();
+            let receipt = packet.receipt();
+            self.send_split_packet(creation_time, priority, ordering, receipt, &packet.into_payload(), max_packet_payload_size);
+        }
+
+        communicator.send_event(PeerEvent::MtuReduced { addr: self.remote_addr, guid: self.remote_guid, old_mtu, new_mtu });
+    }
+
+    /// Sends a `PeerEvent::OrderingChannelBlocked` for each ordering channel that has waited
+    /// for a missing packet for longer than `Config::ordering_blocked_threshold_in_ms`.
+    fn report_blocked_ordering_channels(&mut self, time: Instant, communicator: &mut Communicator<impl DatagramSocket>) {
+        let threshold = communicator.config().ordering_blocked_threshold_in_ms;
+        if threshold == 0 {
+            return;
+        }
+        let threshold = Duration::from_millis(threshold as u64);
+        for (channel, ordering_channel) in self.ordering_system.channels_mut() {
+            if let Some(blocked_for) = ordering_channel.take_unreported_block(time, threshold) {
+                communicator.send_event(PeerEvent::OrderingChannelBlocked {
+                    addr: self.remote_addr,
+                    guid: self.remote_guid,
+                    channel,
+                    buffered_packets: ordering_channel.buffered_packet_count(),
+                    blocked_for,
+                });
+            }
+        }
+    }
+
+    /// Sets the maximum number of bytes per second sent over this connection.
+    /// If the maximum is 0 the bandwidth is unlimited.
+    pub fn set_max_outgoing_bytes_per_second(&mut self, max_bytes_per_second: u64) {
+        self.bandwidth_limiter.set_max_bytes_per_second(max_bytes_per_second);
+    }
+
+    /// Overrides the limits in `Config` for this connection. Limits that are None are kept.
+    pub fn set_limits(&mut self, limits: &ConnectionLimits) {
+        if let Some(ack_timeout_in_ms) = limits.ack_timeout_in_ms {
+            self.ack_timeout_in_ms = Some(ack_timeout_in_ms);
+        }
+        if let Some(max_retransmissions) = limits.max_retransmissions {
+            self.max_retransmissions = Some(max_retransmissions);
+        }
+        if let Some(max_bytes_per_second) = limits.max_outgoing_bytes_per_second {
+            self.bandwidth_limiter.set_max_bytes_per_second(max_bytes_per_second);
+        }
+    }
+
+    /// Returns the number of packets waiting to be sent.
+    pub fn queued_packet_count(&self) -> usize {
+        self.outgoing_packet_heap.len()
+    }
+
+    /// Returns the statistics of this connection. The ping is not known by the reliability layer and is None.
+    pub fn stats(&self, time: Instant) -> ConnectionStats {
+        ConnectionStats {
+            packet_loss: self.packet_loss(time),
+            datagrams_in_flight: self.acknowledge_handler.datagrams_in_flight(),
+            bytes_in_flight: self.acknowledge_handler.bytes_in_flight(),
+            retransmission_timeout: self.acknowledge_handler.retransmission_timeout(),
+            queued_packets: self.outgoing_packet_heap.len(),
+            queued_bytes: self.outgoing_packet_heap.payload_bytes(),
+            buffered_ordered_packets: self.ordering_system.buffered_packet_counts(),
+            ..self.stats.clone()
+        }
+    }
+
+    /// Returns the number of resends as a percentage of the packets sent for the first time
+    /// during the last five seconds, see `ConnectionStats::packet_loss`.
+    pub fn packet_loss(&self, time: Instant) -> f32 {
+        self.loss_estimator.packet_loss(time)
+    }
+
+    /// Returns the number of bytes sent over this connection during the last second.
+    pub fn outgoing_bytes_per_second(&mut self, time: Instant) -> u64 {
+        self.bandwidth_limiter.bytes_per_second(time)
+    }
+
+    /// Returns true if a new datagram may be sent with regard to the
+    /// congestion window and the bandwidth limits.
+    fn has_room_for_datagram(&self, time: Instant, communicator: &mut Communicator<impl DatagramSocket>) -> bool {
+        self.acknowledge_handler.has_room_for_datagram(time) &&
+            self.bandwidth_limiter.has_room_for_datagram(time) &&
+            communicator.bandwidth_limiter().has_room_for_datagram(time)
+    }
+
+    /// Requests that queued packets are sent in the next update
+    /// without waiting for the coalescing delay.
+    pub fn flush(&mut self) {
+        self.is_flush_requested = true;
+    }
+
+    /// Returns true if the queued packets should be sent now or false if they should
+    /// be held back for the coalescing delay to be sent together with later packets.
+    fn should_send_queued_packets(&self, time: Instant, config: &Config) -> bool {
+        match self.oldest_queued_packet_time {
+            Some(oldest_queued_packet_time) => self.is_flush_requested ||
+                time.saturating_duration_since(oldest_queued_packet_time).as_millis() >= config.coalescing_delay_in_ms,
+            None => true,
+        }
+    }
+
+    /// Drops unreliable packets that have waited longer than the unreliable timeout
+    /// to be sent and notifies the user of the loss of packets with receipts.
+    fn remove_expired_unreliable_packets(&mut self, time: Instant, communicator: &mut Communicator<impl DatagramSocket>) {
+        let unreliable_timeout = communicator.config().unreliable_timeout_in_ms;
+        if unreliable_timeout == 0 {
+            return;
+        }
+        if let Some(created_before) = time.checked_sub(Duration::from_millis(unreliable_timeout as u64)) {
+            for packet in self.outgoing_packet_heap.remove_unreliable_created_before(created_before) {
+                debug!("Dropping unreliable packet that was not sent within the unreliable timeout");
+                if let Some(receipt) = packet.receipt() {
+                    communicator.send_event(PeerEvent::SendReceiptLoss(SendReceipt::new(self.remote_addr, self.remote_guid, receipt)));
+                }
+            }
+        }
+    }
+
+    /// Resends the packets in datagrams that have timed out or been NACK:ed.
+    /// All full datagrams are sent and the last datagram is returned so that
+    /// more packets can be added to it before it is sent.
+    fn resend_packets(&mut self, time: Instant, communicator: &mut Communicator<impl DatagramSocket>) -> PacketDatagram {
+        let mut datagram = PacketDatagram::new(self.acknowledge_handler.get_next_datagram_number());
+        let max_retransmissions = self.max_retransmissions.unwrap_or(communicator.config().max_retransmissions);
+        let packets = self.acknowledge_handler.get_packets_to_resend(time, communicator);
+        for mut packet in packets {
+            if packet.resend_count() >= max_retransmissions {
+                debug!("Packet resent {} times without being acknowledged", packet.resend_count());
+                self.connection_lost_reason = Some(ConnectionLostReason::TooManyRetransmissions);
+                self.report_receipt_loss(&packet, communicator);
+                continue;
+            }
+            packet.increment_resend_count();
+            // A packet sent before the MTU was reduced is resent as it is, keeping its
+            // message number and split packet id, in a datagram of its own if it does not fit
+            if !datagram.has_room_for(&packet, self.datagram_mtu()) && !datagram.is_empty() {
+                self.send_datagram(datagram, time, communicator);
+                datagram = PacketDatagram::new(self.acknowledge_handler.get_next_datagram_number());
+            }
+            datagram.push(packet);
+        }
+        datagram
+    }
+
+    /// Discards all packets waiting to be sent or acknowledged, e.g. when the connection
+    /// is dropped, and notifies the user of the loss of packets with receipts.
+    pub fn discard_pending_packets(&mut self, communicator: &mut Communicator<impl DatagramSocket>) {
+        let mut packets = self.acknowledge_handler.take_packets_in_flight();
+        packets.extend(self.outgoing_packet_heap.drain());
+        for packet in packets {
+            self.report_receipt_loss(&packet, communicator);
+        }
+        self.oldest_queued_packet_time = None;
+    }
+
+    /// Sends a `PeerEvent::SendReceiptLoss` if the lost packet has a receipt.
+    /// The loss of a split packet is only reported for the first lost part.
+    fn report_receipt_loss(&mut self, packet: &InternalPacket, communicator: &mut Communicator<impl DatagramSocket>) {
+        let receipt = match packet.receipt() {
+            Some(receipt) => receipt,
+            None => return,
+        };
+        if let Some(header) = packet.split_packet_header() {
+            if !self.lost_split_packet_ids.insert(header.split_packet_id()) {
+                return;
+            }
+        }
+        communicator.send_event(PeerEvent::SendReceiptLoss(SendReceipt::new(self.remote_addr, self.remote_guid, receipt)));
+    }
+
+    /// Registers a datagram with the acknowledge handler and sends it.
+    fn send_datagram(&mut self, datagram: PacketDatagram, time: Instant, communicator: &mut Communicator<impl DatagramSocket>) {
+        for packet in datagram.packets() {
+            let size = packet.payload().len() as u64;
+            self.loss_estimator.on_packet_sent(time, packet.resend_count() > 0);
+            if packet.resend_count() > 0 {
+                self.stats.packets_resent += 1;
+                self.stats.bytes_resent += size;
+            } else if let InternalReliability::Unreliable = packet.reliability() {
+                self.stats.unreliable_packets_sent += 1;
+                self.stats.unreliable_bytes_sent += size;
+            } else {
+                self.stats.reliable_packets_sent += 1;
+                self.stats.reliable_bytes_sent += size;
+            }
+        }
+        match self.acknowledge_handler.process_outgoing_datagram(datagram, time, &mut self.send_buffer) {
+            Ok(()) => {
+                let size = Self::transmit(&mut self.cipher, &self.send_buffer, self.remote_addr, communicator);
+                self.stats.bytes_sent += size as u64;
+                self.bandwidth_limiter.on_datagram_sent(time, size);
+                communicator.bandwidth_limiter().on_datagram_sent(time, size);
+            },
+            Err(err) => error!("Failed processing outgoing datagram: {:?}", err),
+        }
+    }
+
+    /// Sends a datagram to the remote peer, encrypted if the connection
+    /// uses security. Returns the number of bytes sent.
+    fn transmit(cipher: &mut Option<DatagramCipher>, datagram: &[u8], remote_addr: SocketAddr, communicator: &mut Communicator<impl DatagramSocket>) -> usize {
+        match cipher {
+            Some(cipher) => {
+                let encrypted = cipher.encrypt(datagram);
+                communicator.send_datagram(&encrypted, remote_addr);
+                encrypted.len()
+            },
+            None => {
+                communicator.send_datagram(datagram, remote_addr);
+                datagram.len()
+            },
+        }
+    }
+
+    /// Returns the MTU available for a datagram before it is encrypted.
+    fn datagram_mtu(&self) -> u16 {
+        if self.cipher.is_some() {
+            self.mtu - ENCRYPTION_OVERHEAD
+        } else {
+            self.mtu
+        }
     }
 
     /// Enqueues a packet for sending.
-    pub fn send_packet(&mut self, time: Instant, priority: Priority, reliability: Reliability, ordering: Ordering, receipt: Option<u32>, payload: Box<[u8]>) {
+    ///
+    /// Packets that do not fit in a datagram are split into parts that are
+    /// reassembled by the remote peer. Split packets are always sent reliably.
+    /// Returns an error if the packet is larger than `max_total_payload`.
+    pub fn send_packet(&mut self, time: Instant, priority: Priority, reliability: Reliability, ordering: Ordering, receipt: Option<u32>, payload: impl Into<PacketPayload>) -> Result<()> {
+        let payload = payload.into();
         // TODO: Store the time when the last reliable send was done (if reliable)
-        if payload.len() > self.get_max_packet_payload_size() as usize {
-            // TODO: Split packet
-            // TODO: Set reliability to Reliability::Reliable for split packet if unreliable.
+        let max_total_payload = self.max_total_payload();
+        if payload.len() > max_total_payload {
+            return Err(Error::PacketTooLarge { size: payload.len(), max_size: max_total_payload });
+        }
+        let max_packet_payload_size = self.get_max_packet_payload_size() as usize;
+        if payload.len() > max_packet_payload_size {
+            let ordering = self.get_internal_ordering(ordering);
+            self.send_split_packet(time, priority, ordering, receipt, &payload, max_packet_payload_size);
         } else {
-            self.send_packet_internal(time, priority, reliability, ordering, None, receipt, payload);
+            let reliability = match reliability {
+                Reliability::Unreliable => InternalReliability::Unreliable,
+                Reliability::Reliable => InternalReliability::Reliable(None),
+            };
+            let ordering = self.get_internal_ordering(ordering);
+            self.enqueue_packet(priority, InternalPacket::new(time, reliability, ordering, None, receipt, payload));
         }
+        Ok(())
     }
 
-    /// Enqueues a packet that is expected to have been pre-split into parts that can fit a datagram.
-    fn send_packet_internal(&mut self, time: Instant, priority: Priority, reliability: Reliability,
-        ordering: Ordering, split_packet_header: Option<SplitPacketHeader>, receipt: Option<u32>, payload: Box<[u8]>) {
-        // TODO: Store the time when the last reliable send was done (if reliable)
-        let reliability = match reliability {
-            Reliability::Unreliable => InternalReliability::Unreliable,
-            Reliability::Reliable => InternalReliability::Reliable(None),
-        };
-        let ordering = match ordering {
+    /// Splits a packet into parts that fit a datagram and enqueues the parts.
+    /// All parts share the same ordering so that the reassembled packet is ordered
+    /// as one packet. The receipt is attached to every part and reported acknowledged
+    /// once all parts have been acknowledged or lost once any part is lost.
+    ///
+    /// The parts keep the priority of the packet and are interleaved with other
+    /// outgoing packets so that a large packet does not delay everything else.
+    fn send_split_packet(&mut self, time: Instant, priority: Priority, ordering: InternalOrdering, receipt: Option<u32>, payload: &[u8], part_size: usize) {
+        let split_packet_id = self.next_split_packet_id;
+        self.next_split_packet_id = self.next_split_packet_id.wrapping_add(1);
+        let split_packet_count = payload.len().div_ceil(part_size) as u32;
+        let parts = payload.chunks(part_size).enumerate().map(|(split_packet_index, part)| {
+            let split_packet_index = split_packet_index as u32;
+            let split_packet_header = SplitPacketHeader::new(split_packet_count, split_packet_id, split_packet_index);
+            InternalPacket::new(time, InternalReliability::Reliable(None), ordering, Some(split_packet_header), receipt, Box::<[u8]>::from(part))
+        }).collect();
+        if self.oldest_queued_packet_time.is_none() {
+            self.oldest_queued_packet_time = Some(time);
+        }
+        self.outgoing_packet_heap.push_split_packet(priority, parts);
+    }
+
+    /// Returns the internal ordering of a new packet with the specified ordering
+    /// and increments the ordering or sequencing index of the ordering channel.
+    fn get_internal_ordering(&mut self, ordering: Ordering) -> InternalOrdering {
+        match ordering {
             Ordering::None => InternalOrdering::None,
             Ordering::Ordered(ordering_channel_index) => {
                 let ordering_channel_index = if ordering_channel_index < NUMBER_OF_ORDERING_CHANNELS { ordering_channel_index } else { 0 };
@@ -208,8 +604,14 @@ impl ReliabilityLayer {
                     ordering_channel_index,
                 }
             },
-        };
-        let packet = InternalPacket::new(time, reliability, ordering, split_packet_header, receipt, payload);
+        }
+    }
+
+    /// Enqueues a packet that fits a datagram for sending.
+    fn enqueue_packet(&mut self, priority: Priority, packet: InternalPacket) {
+        if self.oldest_queued_packet_time.is_none() {
+            self.oldest_queued_packet_time = Some(packet.creation_time());
+        }
         self.outgoing_packet_heap.push(priority, packet);
     }
 
@@ -233,19 +635,30 @@ impl ReliabilityLayer {
         sequencing_index
     }    
 
+    /// Returns the largest payload in bytes that can be sent without being split.
+    pub fn max_unsplit_payload(&self) -> usize {
+        self.get_max_packet_payload_size() as usize
+    }
+
+    /// Returns the largest payload in bytes that can be sent, split into
+    /// at most `MAX_SPLIT_PACKET_COUNT` parts.
+    pub fn max_total_payload(&self) -> usize {
+        MAX_SPLIT_PACKET_COUNT * self.get_max_packet_payload_size() as usize
+    }
+
     fn get_max_packet_payload_size(&self) -> u16 {
         // Bitflags (u8) + data bit length (u16) + reliable message number (u24)
         // + seuencing index (u24) + ordering index (u24) + ordering channel (u8)
         // + split packet count (u32) + split packet ID (u16) + split packet index (u32)        
         let max_packet_header_size = 1 + 2 + 3 + 3 + 3 + 1 + 4 + 2 + 4;
-        PacketDatagram::get_max_payload_size(self.mtu) - max_packet_header_size
+        PacketDatagram::get_max_payload_size(self.datagram_mtu()) - max_packet_header_size
     }
 
     /// Sends all waiting outgoing acknowledgements.
-    fn send_acks(&mut self, communicator: &mut Communicator<impl DatagramSocket>) {
-        // TODO: Check calculation (MTU - datagram header (bitflags: u8=1, AS: f32=4))
-        let max_datagram_payload = self.mtu as usize - MAX_ACK_DATAGRAM_HEADER_SIZE;
+    fn send_acks(&mut self, time: Instant, communicator: &mut Communicator<impl DatagramSocket>) {
         while !self.outgoing_acks.is_empty() {
+            let datagram_header = DatagramHeader::Ack { data_arrival_rate: self.data_arrival_rate.take_requested_rate(time) };
+            let max_datagram_payload = self.datagram_mtu() as usize - datagram_header.written_size();
             let mut ack_range_list = DatagramRangeList::new();
             while !ack_range_list.is_full(max_datagram_payload) {
                 if let Some(range) = self.outgoing_acks.pop_range() {
@@ -256,8 +669,7 @@ impl ReliabilityLayer {
                 }
             }
 
-            let datagram_header = DatagramHeader::Ack { data_arrival_rate: None };
-            let mut buf = Vec::with_capacity(MAX_ACK_DATAGRAM_HEADER_SIZE + ack_range_list.bytes_used());
+            let mut buf = Vec::with_capacity(datagram_header.written_size() + ack_range_list.bytes_used());
             if let Err(err) = datagram_header.write(&mut buf) {
                 error!("Could not write datagram header: {:?}", err);
                 continue;
@@ -268,14 +680,16 @@ impl ReliabilityLayer {
             }
 
             debug!("Sending ACKs: {:?}", ack_range_list);
-            communicator.send_datagram(&buf, self.remote_addr);
+            let size = Self::transmit(&mut self.cipher, &buf, self.remote_addr, communicator);
+            self.stats.bytes_sent += size as u64;
+            self.stats.acks_sent += 1;
         }
     }
 
     /// Sends all waiting outgoing NACKs.
     fn send_nacks(&mut self, communicator: &mut Communicator<impl DatagramSocket>) {
-        // TODO: Check calculation (MTU - datagram header (bitflags: u8=1))
-        let max_datagram_payload = self.mtu as usize - MAX_NACK_DATAGRAM_HEADER_SIZE;
+        let datagram_header = DatagramHeader::Nack;
+        let max_datagram_payload = self.datagram_mtu() as usize - datagram_header.written_size();
         while !self.outgoing_nacks.is_empty() {
             let mut nack_range_list = DatagramRangeList::new();
             while !nack_range_list.is_full(max_datagram_payload) {
@@ -287,8 +701,7 @@ impl ReliabilityLayer {
                 }
             }
 
-            let datagram_header = DatagramHeader::Nack;
-            let mut buf = Vec::with_capacity(MAX_NACK_DATAGRAM_HEADER_SIZE + nack_range_list.bytes_used());
+            let mut buf = Vec::with_capacity(datagram_header.written_size() + nack_range_list.bytes_used());
             if let Err(err) = datagram_header.write(&mut buf) {
                 error!("Could not write datagram header: {:?}", err);
                 continue;
@@ -299,17 +712,62 @@ impl ReliabilityLayer {
             }
 
             debug!("Sending NACKs: {:?}", nack_range_list);
-            communicator.send_datagram(&buf, self.remote_addr);
+            let size = Self::transmit(&mut self.cipher, &buf, self.remote_addr, communicator);
+            self.stats.bytes_sent += size as u64;
+            self.stats.nacks_sent += 1;
         }
     }    
 
-    /// Processes all incoming packets contained in a a datagram after the datagram header
-    /// has been read.
-    fn process_incoming_packets(&mut self, mut reader: DataReader, time: Instant) -> Result<Vec<Packet>> {
+    /// Reads all packets contained in a datagram after the datagram header has been read.
+    fn read_packets(mut reader: DataReader, time: Instant) -> Result<Vec<InternalPacket>> {
         let mut packets = Vec::new();
         while reader.has_more() {
-            let mut packet = InternalPacket::read(time, &mut reader)?;
+            packets.push(InternalPacket::read(time, &mut reader)?);
+        }
+        Ok(packets)
+    }
+
+    /// Returns false if the packets of an incoming datagram exceed
+    /// `Config::max_incoming_packets_per_second` or `Config::max_incoming_bytes_per_second`.
+    fn is_within_inbound_limits(&mut self, time: Instant, packets: &[InternalPacket], communicator: &mut Communicator<impl DatagramSocket>) -> bool {
+        let config = communicator.config();
+        let byte_count = packets.iter().map(|packet| packet.payload().len()).sum();
+        match self.inbound_limiter.check(time, packets.len() as u32, byte_count, config.max_incoming_packets_per_second, config.max_incoming_bytes_per_second) {
+            InboundCheck::Allowed => true,
+            InboundCheck::Exceeded { limit, first } => {
+                if first {
+                    communicator.send_security_event(time, self.remote_addr, SecurityEventKind::InboundLimitExceeded { limit });
+                }
+                self.on_inbound_limit_exceeded(limit, communicator);
+                false
+            },
+        }
+    }
+
+    /// Drops the connection if `Config::inbound_limit_action` says so. Otherwise only
+    /// the datagram or split packet that exceeded the limit is dropped.
+    fn on_inbound_limit_exceeded(&mut self, limit: InboundLimit, communicator: &mut Communicator<impl DatagramSocket>) {
+        debug!("Inbound limit of {} exceeded by {}", limit, self.remote_addr);
+        self.stats.inbound_limit_drops += 1;
+        if communicator.config().inbound_limit_action != InboundLimitAction::DropPacket {
+            self.connection_lost_reason = Some(ConnectionLostReason::InboundLimitExceeded { limit });
+        }
+    }
+
+    /// Processes the incoming packets of a datagram.
+    fn process_incoming_packets(&mut self, incoming_packets: Vec<InternalPacket>, time: Instant, communicator: &mut Communicator<impl DatagramSocket>) -> Vec<Packet> {
+        let mut packets = Vec::new();
+        let received_ms = communicator.millis_since_start(time);
+        for mut packet in incoming_packets {
             debug!("Received a packet: {:?}, {:?}, {:?}", packet.reliability(), packet.ordering(), packet.split_packet_header());
+            let size = packet.payload().len() as u64;
+            if let InternalReliability::Unreliable = packet.reliability() {
+                self.stats.unreliable_packets_received += 1;
+                self.stats.unreliable_bytes_received += size;
+            } else {
+                self.stats.reliable_packets_received += 1;
+                self.stats.reliable_bytes_received += size;
+            }
             if let InternalReliability::Reliable(Some(reliable_message_number)) = packet.reliability() {
                 if self.reliable_message_number_handler.should_discard_packet(reliable_message_number) {
                     debug!("Dropping packet with duplicate message number: {}", reliable_message_number);
@@ -318,17 +776,28 @@ impl ReliabilityLayer {
             }
 
             if packet.is_split_packet() {
-                if let Some(defragmented_packet) = self.split_packet_handler.handle_split_packet(time, packet) {
-                    packet = defragmented_packet;
-                } else {
-                    continue;
+                match self.split_packet_handler.handle_split_packet(time, packet, communicator) {
+                    Ok(Some(defragmented_packet)) => packet = defragmented_packet,
+                    Ok(None) => continue,
+                    Err(Error::ReadError(ReadError::ReassembledPacketTooLarge)) => {
+                        communicator.send_security_event(time, self.remote_addr, SecurityEventKind::InboundLimitExceeded { limit: InboundLimit::PayloadSize });
+                        self.on_inbound_limit_exceeded(InboundLimit::PayloadSize, communicator);
+                        continue;
+                    },
+                    Err(err) => {
+                        error!("Invalid split packet from {}: {:?}", self.remote_addr, err);
+                        if communicator.config().drop_connection_on_invalid_split_packet {
+                            self.connection_lost_reason = Some(ConnectionLostReason::InvalidSplitPacket);
+                        }
+                        continue;
+                    },
                 }
             }
 
             match packet.ordering() {
                 InternalOrdering::None => {
                     debug!("Packet is Unordered");
-                    packets.push(Packet::new(self.remote_addr, self.remote_guid, packet.into_payload()));
+                    packets.push(Packet::new(self.remote_addr, self.remote_guid, packet.into_payload(), time, received_ms));
                 },
                 InternalOrdering::Ordered { ordering_index, ordering_channel_index } => {
                     debug!("Packed is Ordered. ord_idx={}, ord_ch_idx={}", ordering_index, ordering_channel_index);
@@ -339,8 +808,9 @@ impl ReliabilityLayer {
                             .process_incoming(None, ordering_index, packet.into_payload())
                             .into_iter()
                             .chain(ordering_channel.iter_mut())
-                            .map(|payload| Packet::new(addr, guid, payload))
+                            .map(|payload| Packet::new(addr, guid, payload, time, received_ms))
                         );
+                        ordering_channel.update_blocked(time);
                     } else {
                         error!("Invalid ordering channel: {}", ordering_channel_index);
                     }
@@ -349,14 +819,662 @@ impl ReliabilityLayer {
                     debug!("Packet id Reliable Sequenced. seq_idx={}, ord_idx={}, ord_ch_idx={}", sequencing_index, ordering_index, ordering_channel_index);
                     if let Some(ordering_channel) = self.ordering_system.get_channel(ordering_channel_index) {
                         if let Some(payload) = ordering_channel.process_incoming(Some(sequencing_index), ordering_index, packet.into_payload()) {
-                            packets.push(Packet::new(self.remote_addr, self.remote_guid, payload));
+                            packets.push(Packet::new(self.remote_addr, self.remote_guid, payload, time, received_ms));
                         }
+                        ordering_channel.update_blocked(time);
                     } else {
                         error!("Invalid ordering channel: {}", ordering_channel_index);
                     }
                 },
             }
         }
-        Ok(packets)
+        packets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr, sync::Arc, time::{Duration, Instant}};
+    use crossbeam_channel::{Receiver, unbounded};
+    use crate::{
+        SystemClock,
+        communicator::Communicator,
+        config::Config,
+        config_update::ConfigUpdate,
+        connection_limits::ConnectionLimits,
+        connection_lost_reason::ConnectionLostReason,
+        constants::MAX_SPLIT_PACKET_COUNT,
+        datagram_header::DatagramHeader,
+        datagram_range::DatagramRange,
+        datagram_range_list::DatagramRangeList,
+        error::Error,
+        inbound_limiter::{InboundLimit, InboundLimitAction},
+        internal_packet::{InternalOrdering, InternalPacket, InternalReliability, SplitPacketHeader},
+        number::{DatagramSequenceNumber, MessageNumber},
+        packet_datagram::PacketDatagram,
+        packet::{Ordering, Priority, Reliability},
+        peer_event::PeerEvent,
+        reader::DataReader,
+        security_event::SecurityEventKind,
+        socket::FakeDatagramSocket,
+        writer::PacketWrite,
+    };
+    #[cfg(feature = "rand")]
+    use crate::{constants::ENCRYPTION_OVERHEAD, security::SessionKeys};
+    use super::ReliabilityLayer;
+
+    type TestSetup = (ReliabilityLayer, Communicator<FakeDatagramSocket>, Receiver<(Vec<u8>, SocketAddr)>, Receiver<PeerEvent>);
+
+    fn test_setup(config: Config) -> TestSetup {
+        let local_addr = "127.0.0.2:19132".parse::<SocketAddr>().expect("Could not create address");
+        let remote_addr = "127.0.0.1:19132".parse::<SocketAddr>().expect("Could not create address");
+        let reliability_layer = ReliabilityLayer::new(Instant::now(), remote_addr, 0x112233, 1492);
+        let fake_socket = FakeDatagramSocket::new(local_addr);
+        let datagram_receiver = fake_socket.get_datagram_receiver();
+        let (event_sender, event_receiver) = unbounded();
+        let communicator = Communicator::new(fake_socket, config, event_sender, Arc::new(SystemClock));
+        (reliability_layer, communicator, datagram_receiver, event_receiver)
+    }
+
+    fn read_datagram_number(payload: &[u8]) -> Option<DatagramSequenceNumber> {
+        match DatagramHeader::read(&mut DataReader::new(payload)) {
+            Ok(DatagramHeader::Packet { datagram_number, .. }) => Some(datagram_number),
+            _ => None,
+        }
+    }
+
+    fn create_nack(datagram_number: DatagramSequenceNumber) -> Vec<u8> {
+        let mut nack_range_list = DatagramRangeList::new();
+        nack_range_list.push(DatagramRange::new(datagram_number, datagram_number));
+        let mut nack = Vec::new();
+        DatagramHeader::Nack.write(&mut nack).expect("Could not write header");
+        nack_range_list.write(&mut nack).expect("Could not write NACKs");
+        nack
+    }
+
+    fn create_ack(datagram_number: DatagramSequenceNumber) -> Vec<u8> {
+        let mut ack_range_list = DatagramRangeList::new();
+        ack_range_list.push(DatagramRange::new(datagram_number, datagram_number));
+        let mut ack = Vec::new();
+        DatagramHeader::Ack { data_arrival_rate: None }.write(&mut ack).expect("Could not write header");
+        ack_range_list.write(&mut ack).expect("Could not write ACKs");
+        ack
+    }
+
+    #[test]
+    fn nack_resends_packets_immediately() {
+        // Arrange
+        let (mut reliability_layer, mut communicator, datagram_receiver, _event_receiver) = test_setup(Config::default());
+        let time = Instant::now();
+        reliability_layer.send_packet(time, Priority::Medium, Reliability::Reliable, Ordering::None, None, vec![1, 2, 3].into_boxed_slice()).expect("Could not send packet");
+        reliability_layer.update(time, &mut communicator);
+        let (payload, _) = datagram_receiver.try_recv().expect("No datagram was sent");
+        assert_eq!(Some(DatagramSequenceNumber::ZERO), read_datagram_number(&payload));
+        let nack = create_nack(DatagramSequenceNumber::ZERO);
+
+        // Act
+        reliability_layer.process_incoming_datagram(&nack, time, &mut communicator);
+
+        // Assert
+        let (payload, _) = datagram_receiver.try_recv().expect("NACK:ed datagram was not resent");
+        assert_eq!(Some(DatagramSequenceNumber::ONE), read_datagram_number(&payload));
+        assert_eq!(&[1, 2, 3], &payload[payload.len() - 3..]);
+    }
+
+    #[test]
+    fn nack_increases_packet_loss() {
+        // Arrange
+        let (mut reliability_layer, mut communicator, datagram_receiver, _event_receiver) = test_setup(Config::default());
+        let time = Instant::now();
+        reliability_layer.send_packet(time, Priority::Medium, Reliability::Reliable, Ordering::None, None, vec![1, 2, 3].into_boxed_slice()).expect("Could not send packet");
+        reliability_layer.update(time, &mut communicator);
+        let (payload, _) = datagram_receiver.try_recv().expect("No datagram was sent");
+        let datagram_number = read_datagram_number(&payload).expect("Could not read datagram number");
+        let loss_before_nack = reliability_layer.stats(time).packet_loss;
+
+        // Act
+        reliability_layer.process_incoming_datagram(&create_nack(datagram_number), time, &mut communicator);
+
+        // Assert
+        assert_eq!(0.0, loss_before_nack);
+        assert_eq!(100.0, reliability_layer.stats(time).packet_loss);
+        assert_eq!(0.0, reliability_layer.stats(time + Duration::from_secs(10)).packet_loss);
+    }
+
+    #[test]
+    fn too_many_retransmissions_loses_connection() {
+        // Arrange
+        let config = Config { max_retransmissions: 2, ..Config::default() };
+        let (mut reliability_layer, mut communicator, datagram_receiver, _event_receiver) = test_setup(config);
+        let time = Instant::now();
+        reliability_layer.send_packet(time, Priority::Medium, Reliability::Reliable, Ordering::None, None, vec![1, 2, 3].into_boxed_slice()).expect("Could not send packet");
+        reliability_layer.update(time, &mut communicator);
+
+        // Act
+        for number in 0..2 {
+            reliability_layer.process_incoming_datagram(&create_nack(DatagramSequenceNumber::from_masked_u32(number)), time, &mut communicator);
+        }
+        let reason_after_max_retransmissions = reliability_layer.connection_lost_reason();
+        reliability_layer.process_incoming_datagram(&create_nack(DatagramSequenceNumber::from_masked_u32(2)), time, &mut communicator);
+
+        // Assert
+        assert_eq!(3, datagram_receiver.try_iter().count());
+        assert_eq!(None, reason_after_max_retransmissions);
+        assert_eq!(Some(ConnectionLostReason::TooManyRetransmissions), reliability_layer.connection_lost_reason());
+    }
+
+    #[test]
+    fn connection_limits_override_config() {
+        // Arrange
+        let config = Config { max_retransmissions: 1, ack_timeout_in_ms: 1000, ..Config::default() };
+        let (mut reliability_layer, mut communicator, _datagram_receiver, _event_receiver) = test_setup(config);
+        let time = Instant::now();
+        reliability_layer.set_limits(&ConnectionLimits { ack_timeout_in_ms: Some(5000), max_retransmissions: Some(3), ..ConnectionLimits::default() });
+        reliability_layer.send_packet(time, Priority::Medium, Reliability::Reliable, Ordering::None, None, vec![1, 2, 3].into_boxed_slice()).expect("Could not send packet");
+        reliability_layer.update(time, &mut communicator);
+
+        // Act
+        for number in 0..2 {
+            reliability_layer.process_incoming_datagram(&create_nack(DatagramSequenceNumber::from_masked_u32(number)), time, &mut communicator);
+        }
+        let reason_after_config_retransmissions = reliability_layer.connection_lost_reason();
+        reliability_layer.update(time + Duration::from_millis(2000), &mut communicator);
+        let reason_after_config_timeout = reliability_layer.connection_lost_reason();
+        reliability_layer.update(time + Duration::from_millis(6000), &mut communicator);
+
+        // Assert
+        assert_eq!(None, reason_after_config_retransmissions);
+        assert_eq!(None, reason_after_config_timeout);
+        assert_eq!(Some(ConnectionLostReason::AckTimeout), reliability_layer.connection_lost_reason());
+    }
+
+    #[test]
+    fn stats_count_sent_resent_and_acknowledged_packets() {
+        // Arrange
+        let (mut reliability_layer, mut communicator, datagram_receiver, _event_receiver) = test_setup(Config::default());
+        let time = Instant::now();
+        reliability_layer.send_packet(time, Priority::Medium, Reliability::Reliable, Ordering::None, None, vec![1, 2, 3].into_boxed_slice()).expect("Could not send packet");
+        reliability_layer.send_packet(time, Priority::Medium, Reliability::Unreliable, Ordering::None, None, vec![4, 5].into_boxed_slice()).expect("Could not send packet");
+        let queued_stats = reliability_layer.stats(time);
+        reliability_layer.update(time, &mut communicator);
+        let sent_stats = reliability_layer.stats(time);
+
+        // Act
+        reliability_layer.process_incoming_datagram(&create_nack(DatagramSequenceNumber::ZERO), time, &mut communicator);
+        reliability_layer.process_incoming_datagram(&create_ack(DatagramSequenceNumber::ONE), time, &mut communicator);
+
+        // Assert
+        let bytes_sent: usize = datagram_receiver.try_iter().map(|(payload, _)| payload.len()).sum();
+        let stats = reliability_layer.stats(time);
+        assert_eq!((2, 5), (queued_stats.queued_packets, queued_stats.queued_bytes));
+        assert_eq!((1, 3), (sent_stats.reliable_packets_sent, sent_stats.reliable_bytes_sent));
+        assert_eq!((1, 2), (sent_stats.unreliable_packets_sent, sent_stats.unreliable_bytes_sent));
+        assert_eq!(1, sent_stats.datagrams_in_flight);
+        assert_eq!((1, 3), (stats.packets_resent, stats.bytes_resent));
+        assert_eq!((1, 1), (stats.nacks_received, stats.acks_received));
+        assert_eq!(0, stats.queued_packets);
+        assert_eq!(0, stats.datagrams_in_flight);
+        assert_eq!(bytes_sent as u64, stats.bytes_sent);
+    }
+
+    #[test]
+    fn coalescing_delay_sends_small_packets_in_one_datagram() {
+        // Arrange
+        let config = Config { coalescing_delay_in_ms: 10, ..Config::default() };
+        let (mut reliability_layer, mut communicator, datagram_receiver, _event_receiver) = test_setup(config);
+        let time = Instant::now();
+        reliability_layer.send_packet(time, Priority::Medium, Reliability::Reliable, Ordering::None, None, vec![1].into_boxed_slice()).expect("Could not send packet");
+        reliability_layer.update(time, &mut communicator);
+        let datagrams_before_delay = datagram_receiver.try_iter().count();
+        reliability_layer.send_packet(time + Duration::from_millis(5), Priority::Medium, Reliability::Reliable, Ordering::None, None, vec![2].into_boxed_slice()).expect("Could not send packet");
+
+        // Act
+        reliability_layer.update(time + Duration::from_millis(10), &mut communicator);
+
+        // Assert
+        assert_eq!(0, datagrams_before_delay);
+        let datagrams: Vec<_> = datagram_receiver.try_iter().collect();
+        assert_eq!(1, datagrams.len());
+        assert_eq!(Some(DatagramSequenceNumber::ZERO), read_datagram_number(&datagrams[0].0));
+    }
+
+    #[test]
+    fn new_datagrams_are_paced_over_time() {
+        // Arrange
+        let config = Config { max_datagrams_per_tick: 2, tick_interval_in_ms: 10, ..Config::default() };
+        let (mut reliability_layer, mut communicator, datagram_receiver, _event_receiver) = test_setup(config);
+        let time = Instant::now();
+        for _ in 0..10 {
+            reliability_layer.send_packet(time, Priority::Medium, Reliability::Reliable, Ordering::None, None, vec![0; 1000].into_boxed_slice()).expect("Could not send packet");
+        }
+        // Acknowledges the sent datagrams so that only the pacing limits the sending
+        let mut update_and_ack = |time: Instant| {
+            reliability_layer.update(time, &mut communicator);
+            let datagrams: Vec<_> = datagram_receiver.try_iter().collect();
+            for (payload, _) in &datagrams {
+                let datagram_number = read_datagram_number(payload).expect("Could not read datagram number");
+                reliability_layer.process_incoming_datagram(&create_ack(datagram_number), time, &mut communicator);
+            }
+            datagrams.len()
+        };
+
+        // Act
+        let sent_counts = vec![
+            update_and_ack(time),
+            update_and_ack(time),
+            update_and_ack(time + Duration::from_millis(2)),
+            update_and_ack(time + Duration::from_millis(5)),
+            update_and_ack(time + Duration::from_millis(5)),
+        ];
+
+        // Assert
+        assert_eq!(vec![2, 0, 0, 1, 0], sent_counts);
+    }
+
+    #[test]
+    fn global_bandwidth_limit_change_applies_to_existing_connection() {
+        // Arrange
+        let (mut reliability_layer, mut communicator, datagram_receiver, _event_receiver) = test_setup(Config { guid: 0x1234, ..Config::default() });
+        let time = Instant::now();
+        for _ in 0..10 {
+            reliability_layer.send_packet(time, Priority::Medium, Reliability::Reliable, Ordering::None, None, vec![0; 1000].into_boxed_slice()).expect("Could not send packet");
+        }
+        let mut update_and_ack = |time: Instant, communicator: &mut Communicator<FakeDatagramSocket>| {
+            reliability_layer.update(time, communicator);
+            let datagrams: Vec<_> = datagram_receiver.try_iter().collect();
+            for (payload, _) in &datagrams {
+                let datagram_number = read_datagram_number(payload).expect("Could not read datagram number");
+                reliability_layer.process_incoming_datagram(&create_ack(datagram_number), time, communicator);
+            }
+            datagrams.len()
+        };
+        let sent_before_limit = update_and_ack(time, &mut communicator);
+
+        // Act
+        communicator.update_config(&ConfigUpdate { max_outgoing_bytes_per_second: Some(1000), ..ConfigUpdate::default() }).expect("Could not update config");
+        let sent_after_limit: Vec<usize> = (1..=4).map(|millis| update_and_ack(time + Duration::from_millis(millis), &mut communicator)).collect();
+
+        // Assert
+        assert_eq!(2, sent_before_limit);
+        assert_eq!(vec![2, 0, 0, 0], sent_after_limit);
+    }
+
+    #[test]
+    fn flush_bypasses_coalescing_delay() {
+        // Arrange
+        let config = Config { coalescing_delay_in_ms: 10, ..Config::default() };
+        let (mut reliability_layer, mut communicator, datagram_receiver, _event_receiver) = test_setup(config);
+        let time = Instant::now();
+        reliability_layer.send_packet(time, Priority::Medium, Reliability::Reliable, Ordering::None, None, vec![1].into_boxed_slice()).expect("Could not send packet");
+
+        // Act
+        reliability_layer.flush();
+        reliability_layer.update(time, &mut communicator);
+
+        // Assert
+        assert_eq!(1, datagram_receiver.try_iter().count());
+    }
+
+    #[test]
+    fn split_packet_is_reassembled_by_remote_peer() {
+        // Arrange
+        let (mut sender, mut sender_communicator, sender_datagrams, _sender_events) = test_setup(Config::default());
+        let (mut receiver, mut receiver_communicator, receiver_datagrams, _receiver_events) = test_setup(Config::default());
+        let payload: Vec<u8> = (0..5000).map(|i| i as u8).collect();
+        let mut time = Instant::now();
+        sender.send_packet(time, Priority::Medium, Reliability::Unreliable, Ordering::Ordered(0), None, payload.clone().into_boxed_slice()).expect("Could not send packet");
+        let mut received_packets = Vec::new();
+
+        // Act
+        for _ in 0..50 {
+            sender.update(time, &mut sender_communicator);
+            for (datagram, _) in sender_datagrams.try_iter() {
+                if let Some(packets) = receiver.process_incoming_datagram(&datagram, time, &mut receiver_communicator) {
+                    received_packets.extend(packets);
+                }
+            }
+            receiver.update(time, &mut receiver_communicator);
+            for (datagram, _) in receiver_datagrams.try_iter() {
+                sender.process_incoming_datagram(&datagram, time, &mut sender_communicator);
+            }
+            time += Duration::from_millis(20);
+        }
+
+        // Assert
+        assert_eq!(1, received_packets.len());
+        assert_eq!(payload.as_slice(), received_packets[0].payload());
+        assert!(!sender.has_pending_packets());
+    }
+
+    #[test]
+    fn split_packet_receipt_is_acked_when_all_parts_are_acked() {
+        // Arrange
+        let (mut reliability_layer, mut communicator, datagram_receiver, event_receiver) = test_setup(Config::default());
+        let time = Instant::now();
+        let payload: Vec<u8> = (0..2000).map(|i| i as u8).collect();
+        reliability_layer.send_packet(time, Priority::Medium, Reliability::Reliable, Ordering::None, Some(7), payload.into_boxed_slice()).expect("Could not send packet");
+        reliability_layer.update(time, &mut communicator);
+        assert_eq!(0, reliability_layer.queued_packet_count());
+        let datagram_numbers: Vec<DatagramSequenceNumber> = datagram_receiver.try_iter().filter_map(|(payload, _)| read_datagram_number(&payload)).collect();
+        assert!(datagram_numbers.len() > 1);
+
+        // Act
+        let mut events_before_last_ack = Vec::new();
+        for (i, datagram_number) in datagram_numbers.iter().enumerate() {
+            if i == datagram_numbers.len() - 1 {
+                events_before_last_ack.extend(event_receiver.try_iter());
+            }
+            reliability_layer.process_incoming_datagram(&create_ack(*datagram_number), time, &mut communicator);
+        }
+
+        // Assert
+        assert!(!events_before_last_ack.iter().any(|event| matches!(event, PeerEvent::SendReceiptAcked(_))));
+        let events: Vec<PeerEvent> = event_receiver.try_iter().collect();
+        assert_eq!(1, events.len());
+        assert!(matches!(&events[0], PeerEvent::SendReceiptAcked(receipt) if receipt.receipt() == 7));
+    }
+
+    #[test]
+    fn discarded_packets_report_receipt_loss_once() {
+        // Arrange
+        let (mut reliability_layer, mut communicator, datagram_receiver, event_receiver) = test_setup(Config::default());
+        let time = Instant::now();
+        let payload: Vec<u8> = (0..3000).map(|i| i as u8).collect();
+        reliability_layer.send_packet(time, Priority::Medium, Reliability::Reliable, Ordering::None, Some(7), payload.into_boxed_slice()).expect("Could not send packet");
+        reliability_layer.update(time, &mut communicator);
+        reliability_layer.send_packet(time, Priority::Medium, Reliability::Unreliable, Ordering::None, Some(8), vec![1, 2, 3].into_boxed_slice()).expect("Could not send packet");
+        reliability_layer.send_packet(time, Priority::Medium, Reliability::Reliable, Ordering::None, None, vec![4, 5, 6].into_boxed_slice()).expect("Could not send packet");
+        assert!(datagram_receiver.try_iter().count() > 1);
+
+        // Act
+        reliability_layer.discard_pending_packets(&mut communicator);
+
+        // Assert
+        let mut lost_receipts: Vec<u32> = event_receiver.try_iter().filter_map(|event| match event {
+            PeerEvent::SendReceiptLoss(receipt) => Some(receipt.receipt()),
+            _ => None,
+        }).collect();
+        lost_receipts.sort_unstable();
+        assert_eq!(vec![7, 8], lost_receipts);
+        assert!(!reliability_layer.has_pending_packets());
+    }
+
+    #[test]
+    #[cfg(feature = "rand")]
+    fn encrypted_packet_is_received_by_remote_peer() {
+        // Arrange
+        let (mut sender, mut sender_communicator, sender_datagrams, _sender_events) = test_setup(Config::default());
+        let (mut receiver, mut receiver_communicator, _receiver_datagrams, _receiver_events) = test_setup(Config::default());
+        sender.enable_encryption(&SessionKeys { outgoing_key: [1; 32], incoming_key: [2; 32] });
+        receiver.enable_encryption(&SessionKeys { outgoing_key: [2; 32], incoming_key: [1; 32] });
+        let time = Instant::now();
+        sender.send_packet(time, Priority::Medium, Reliability::Reliable, Ordering::None, None, vec![1, 2, 3].into_boxed_slice()).expect("Could not send packet");
+        sender.update(time, &mut sender_communicator);
+        let (datagram, _) = sender_datagrams.try_recv().expect("No datagram was sent");
+
+        // Act
+        let packets = receiver.process_incoming_datagram(&datagram, time, &mut receiver_communicator);
+        let replayed_packets = receiver.process_incoming_datagram(&datagram, time, &mut receiver_communicator);
+
+        // Assert
+        let packets = packets.expect("Datagram was discarded");
+        assert_eq!(1, packets.len());
+        assert_eq!(&[1, 2, 3], packets[0].payload());
+        assert!(replayed_packets.is_none());
+    }
+
+    #[test]
+    fn received_packet_has_receive_time() {
+        // Arrange
+        let (mut sender, mut sender_communicator, sender_datagrams, _sender_events) = test_setup(Config::default());
+        let (mut receiver, mut receiver_communicator, _receiver_datagrams, _receiver_events) = test_setup(Config::default());
+        let time = receiver_communicator.start_time() + Duration::from_millis(250);
+        sender.send_packet(time, Priority::Medium, Reliability::Reliable, Ordering::None, None, vec![1, 2, 3].into_boxed_slice()).expect("Could not send packet");
+        sender.update(time, &mut sender_communicator);
+        let (datagram, _) = sender_datagrams.try_recv().expect("No datagram was sent");
+
+        // Act
+        let packets = receiver.process_incoming_datagram(&datagram, time, &mut receiver_communicator);
+
+        // Assert
+        let packets = packets.expect("Datagram was discarded");
+        assert_eq!(time, packets[0].received_at());
+        assert_eq!(250, packets[0].received_ms());
+    }
+
+    #[test]
+    fn ordering_channel_blocked_by_lost_packet_is_reported() {
+        // Arrange
+        let (mut sender, mut sender_communicator, sender_datagrams, _sender_events) = test_setup(Config::default());
+        let (mut receiver, mut receiver_communicator, _receiver_datagrams, receiver_events) = test_setup(Config { ordering_blocked_threshold_in_ms: 1000, ..Config::default() });
+        let time = Instant::now();
+        for payload in [vec![1], vec![2], vec![3]].iter() {
+            sender.send_packet(time, Priority::Medium, Reliability::Reliable, Ordering::Ordered(2), None, payload.clone().into_boxed_slice()).expect("Could not send packet");
+            sender.update(time, &mut sender_communicator);
+        }
+        let datagrams: Vec<Vec<u8>> = sender_datagrams.try_iter().map(|(datagram, _)| datagram).collect();
+        assert_eq!(3, datagrams.len());
+
+        // Act
+        for datagram in &datagrams[1..] {
+            receiver.process_incoming_datagram(datagram, time, &mut receiver_communicator);
+        }
+        let stats = receiver.stats(time);
+        receiver.update(time + Duration::from_millis(500), &mut receiver_communicator);
+        let early_events: Vec<PeerEvent> = receiver_events.try_iter().collect();
+        receiver.update(time + Duration::from_millis(1500), &mut receiver_communicator);
+        receiver.update(time + Duration::from_millis(2000), &mut receiver_communicator);
+
+        // Assert
+        assert_eq!(vec![(2, 2)], stats.buffered_ordered_packets);
+        assert!(early_events.is_empty());
+        let addr = "127.0.0.1:19132".parse().unwrap();
+        assert_eq!(vec![PeerEvent::OrderingChannelBlocked { addr, guid: 0x112233, channel: 2, buffered_packets: 2, blocked_for: Duration::from_millis(1500) }], receiver_events.try_iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    #[cfg(feature = "rand")]
+    fn unencrypted_datagram_is_discarded_when_encryption_is_enabled() {
+        // Arrange
+        let (mut sender, mut sender_communicator, sender_datagrams, _sender_events) = test_setup(Config::default());
+        let (mut receiver, mut receiver_communicator, _receiver_datagrams, _receiver_events) = test_setup(Config::default());
+        receiver.enable_encryption(&SessionKeys { outgoing_key: [2; 32], incoming_key: [1; 32] });
+        let time = Instant::now();
+        sender.send_packet(time, Priority::Medium, Reliability::Reliable, Ordering::None, None, vec![1, 2, 3].into_boxed_slice()).expect("Could not send packet");
+        sender.update(time, &mut sender_communicator);
+        let (datagram, _) = sender_datagrams.try_recv().expect("No datagram was sent");
+
+        // Act
+        let packets = receiver.process_incoming_datagram(&datagram, time, &mut receiver_communicator);
+
+        // Assert
+        assert!(packets.is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "rand")]
+    fn encryption_reduces_max_unsplit_payload() {
+        // Arrange
+        let (mut reliability_layer, _communicator, _datagram_receiver, _event_receiver) = test_setup(Config::default());
+        let max_unsplit_payload = reliability_layer.max_unsplit_payload();
+
+        // Act
+        reliability_layer.enable_encryption(&SessionKeys { outgoing_key: [1; 32], incoming_key: [2; 32] });
+
+        // Assert
+        assert_eq!(max_unsplit_payload - ENCRYPTION_OVERHEAD as usize, reliability_layer.max_unsplit_payload());
+    }
+
+    #[test]
+    fn lost_large_datagrams_reduce_mtu() {
+        // Arrange
+        let (mut reliability_layer, mut communicator, datagram_receiver, event_receiver) = test_setup(Config::default());
+        let time = Instant::now();
+        reliability_layer.send_packet(time, Priority::Medium, Reliability::Reliable, Ordering::None, None, vec![1, 2, 3].into_boxed_slice()).expect("Could not send packet");
+        reliability_layer.update(time, &mut communicator);
+        let (payload, _) = datagram_receiver.try_recv().expect("No datagram was sent");
+        let small_datagram_number = read_datagram_number(&payload).expect("Could not read datagram number");
+        reliability_layer.process_incoming_datagram(&create_ack(small_datagram_number), time, &mut communicator);
+        reliability_layer.send_packet(time, Priority::Medium, Reliability::Reliable, Ordering::None, None, vec![0xFF; 1400].into_boxed_slice()).expect("Could not send packet");
+        reliability_layer.update(time, &mut communicator);
+        let mut large_datagram = Vec::new();
+        for _ in 0..3 {
+            let (payload, _) = datagram_receiver.try_recv().expect("No large datagram was sent");
+            assert!(payload.len() > 1200);
+            large_datagram = payload.clone();
+            let large_datagram_number = read_datagram_number(&payload).expect("Could not read datagram number");
+            reliability_layer.process_incoming_datagram(&create_nack(large_datagram_number), time, &mut communicator);
+        }
+
+        // Act
+        reliability_layer.update(time, &mut communicator);
+
+        // Assert
+        assert_eq!(Ok(PeerEvent::MtuReduced { addr: "127.0.0.1:19132".parse().unwrap(), guid: 0x112233, old_mtu: 1492, new_mtu: 1200 }), event_receiver.try_recv());
+        let (payload, _) = datagram_receiver.try_recv().expect("No resent datagram");
+        assert_eq!(large_datagram[4..], payload[4..], "The packet sent before the MTU was reduced must be resent as it is");
+        reliability_layer.send_packet(time, Priority::Medium, Reliability::Reliable, Ordering::None, None, vec![0xFF; 1400].into_boxed_slice()).expect("Could not send packet");
+        reliability_layer.update(time, &mut communicator);
+        let datagrams: Vec<Vec<u8>> = datagram_receiver.try_iter().map(|(payload, _)| payload).collect();
+        assert_eq!(2, datagrams.len());
+        assert!(datagrams.iter().all(|payload| payload.len() <= 1200 - 28));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn send_packet_larger_than_max_total_payload_fails() {
+        // Arrange
+        let (mut reliability_layer, _communicator, _datagram_receiver, _event_receiver) = test_setup(Config::default());
+        let max_total_payload = reliability_layer.max_total_payload();
+        let payload = vec![0; max_total_payload + 1].into_boxed_slice();
+
+        // Act
+        let result = reliability_layer.send_packet(Instant::now(), Priority::Medium, Reliability::Reliable, Ordering::None, None, payload);
+
+        // Assert
+        assert!(matches!(result, Err(Error::PacketTooLarge { size, max_size }) if size == max_total_payload + 1 && max_size == max_total_payload));
+        assert!(!reliability_layer.has_pending_packets());
+    }
+
+    #[test]
+    fn max_total_payload_is_split_packet_count_times_unsplit_payload() {
+        // Arrange
+        let (reliability_layer, _communicator, _datagram_receiver, _event_receiver) = test_setup(Config::default());
+
+        // Act/Assert
+        assert_eq!(MAX_SPLIT_PACKET_COUNT * reliability_layer.max_unsplit_payload(), reliability_layer.max_total_payload());
+        assert!(reliability_layer.max_unsplit_payload() < 1492);
+    }
+
+    #[test]
+    fn split_packet_progress_is_reported_every_tenth_part() {
+        // Arrange
+        let (mut sender, mut sender_communicator, sender_datagrams, sender_events) = test_setup(Config::default());
+        let (mut receiver, mut receiver_communicator, receiver_datagrams, receiver_events) = test_setup(Config::default());
+        let payload = vec![0xFF; 20 * sender.max_unsplit_payload()];
+        let mut time = Instant::now();
+        sender.send_packet(time, Priority::Medium, Reliability::Reliable, Ordering::None, None, payload.into_boxed_slice()).expect("Could not send packet");
+
+        // Act
+        for _ in 0..50 {
+            sender.update(time, &mut sender_communicator);
+            for (datagram, _) in sender_datagrams.try_iter() {
+                receiver.process_incoming_datagram(&datagram, time, &mut receiver_communicator);
+            }
+            receiver.update(time, &mut receiver_communicator);
+            for (datagram, _) in receiver_datagrams.try_iter() {
+                sender.process_incoming_datagram(&datagram, time, &mut sender_communicator);
+            }
+            time += Duration::from_millis(20);
+        }
+
+        // Assert
+        let progress = |events: &Receiver<PeerEvent>| events.try_iter().filter_map(|event| match event {
+            PeerEvent::SplitPacketProgress { split_id: 0, received, total: 20, is_incoming, .. } => Some((received, is_incoming)),
+            _ => None,
+        }).collect::<Vec<_>>();
+        assert_eq!((1..=10).map(|n| (n * 2, true)).collect::<Vec<_>>(), progress(&receiver_events));
+        assert_eq!((1..=10).map(|n| (n * 2, false)).collect::<Vec<_>>(), progress(&sender_events));
+    }
+
+    fn create_split_packet_datagram(split_packet_count: u32) -> Vec<u8> {
+        let header = SplitPacketHeader::new(split_packet_count, 0, 0);
+        let packet = InternalPacket::new(Instant::now(), InternalReliability::Reliable(Some(MessageNumber::ZERO)), InternalOrdering::None, Some(header), None, vec![1, 2, 3].into_boxed_slice());
+        let mut datagram = PacketDatagram::new(DatagramSequenceNumber::ZERO);
+        datagram.push(packet);
+        let mut payload = Vec::new();
+        datagram.write(&mut payload).expect("Could not write datagram");
+        payload
+    }
+
+    #[test]
+    fn split_packet_with_too_many_parts_is_discarded() {
+        // Arrange
+        let config = Config { max_incoming_split_packet_count: 100, ..Config::default() };
+        let (mut reliability_layer, mut communicator, _datagram_receiver, _event_receiver) = test_setup(config);
+        let datagram = create_split_packet_datagram(101);
+
+        // Act
+        let packets = reliability_layer.process_incoming_datagram(&datagram, Instant::now(), &mut communicator);
+
+        // Assert
+        assert_eq!(Some(0), packets.map(|packets| packets.len()));
+        assert_eq!(None, reliability_layer.connection_lost_reason());
+    }
+
+    #[test]
+    fn invalid_split_packet_loses_connection_if_configured() {
+        // Arrange
+        let config = Config { max_incoming_split_packet_count: 100, drop_connection_on_invalid_split_packet: true, ..Config::default() };
+        let (mut reliability_layer, mut communicator, _datagram_receiver, _event_receiver) = test_setup(config);
+        let datagram = create_split_packet_datagram(101);
+
+        // Act
+        reliability_layer.process_incoming_datagram(&datagram, Instant::now(), &mut communicator);
+
+        // Assert
+        assert_eq!(Some(ConnectionLostReason::InvalidSplitPacket), reliability_layer.connection_lost_reason());
+    }
+
+    #[test]
+    fn datagram_exceeding_packet_rate_is_dropped_without_ack() {
+        // Arrange
+        let config = Config { max_incoming_packets_per_second: 1, ..Config::default() };
+        let (mut reliability_layer, mut communicator, _datagram_receiver, event_receiver) = test_setup(config);
+        let time = Instant::now();
+        let create_datagram = |number| {
+            let mut datagram = PacketDatagram::new(number);
+            datagram.push(InternalPacket::new(time, InternalReliability::Unreliable, InternalOrdering::None, None, None, vec![0x86].into_boxed_slice()));
+            let mut payload = Vec::new();
+            datagram.write(&mut payload).expect("Could not write datagram");
+            payload
+        };
+
+        // Act
+        let allowed_packets = reliability_layer.process_incoming_datagram(&create_datagram(DatagramSequenceNumber::ZERO), time, &mut communicator);
+        let dropped_packets = reliability_layer.process_incoming_datagram(&create_datagram(DatagramSequenceNumber::from_masked_u32(1)), time, &mut communicator);
+        let packets_after_refill = reliability_layer.process_incoming_datagram(&create_datagram(DatagramSequenceNumber::from_masked_u32(1)), time + Duration::from_secs(1), &mut communicator);
+
+        // Assert
+        assert_eq!(Some(1), allowed_packets.map(|packets| packets.len()));
+        assert!(dropped_packets.is_none());
+        assert_eq!(Some(1), packets_after_refill.map(|packets| packets.len()));
+        assert_eq!(1, reliability_layer.stats(time).inbound_limit_drops);
+        assert_eq!(None, reliability_layer.connection_lost_reason());
+        assert!(event_receiver.try_iter().any(|event| matches!(event,
+            PeerEvent::SecurityEvent { kind: SecurityEventKind::InboundLimitExceeded { limit: InboundLimit::PacketRate }, .. })));
+    }
+
+    #[test]
+    fn too_large_split_packet_loses_connection_if_configured() {
+        // Arrange
+        let config = Config { max_incoming_payload_size: 100, inbound_limit_action: InboundLimitAction::Disconnect, ..Config::default() };
+        let (mut reliability_layer, mut communicator, _datagram_receiver, _event_receiver) = test_setup(config);
+        let datagram = create_split_packet_datagram(50);
+
+        // Act
+        reliability_layer.process_incoming_datagram(&datagram, Instant::now(), &mut communicator);
+
+        // Assert
+        assert_eq!(Some(ConnectionLostReason::InboundLimitExceeded { limit: InboundLimit::PayloadSize }), reliability_layer.connection_lost_reason());
+    }
+}
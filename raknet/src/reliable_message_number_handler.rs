@@ -19,7 +19,7 @@ impl ReliableMessageNumberHandler {
 
     pub fn get_and_increment_reliable_message_number(&mut self) -> MessageNumber {
         let number = self.next_outgoing_number;
-        self.next_outgoing_number.wrapping_add(MessageNumber::ONE);
+        self.next_outgoing_number = self.next_outgoing_number.wrapping_add(MessageNumber::ONE);
         number
     }
 
@@ -72,6 +72,20 @@ mod tests {
     use crate::number::MessageNumber;
     use super::ReliableMessageNumberHandler;
 
+    #[test]
+    fn get_and_increment_reliable_message_number_increments() {
+        // Arrange
+        let mut handler = ReliableMessageNumberHandler::new();
+
+        // Act
+        let first = handler.get_and_increment_reliable_message_number();
+        let second = handler.get_and_increment_reliable_message_number();
+
+        // Assert
+        assert_eq!(MessageNumber::from_masked_u32(0), first);
+        assert_eq!(MessageNumber::from_masked_u32(1), second);
+    }
+
     #[test]
     fn should_discard_packet_initial_state() {
         // Arrange
@@ -0,0 +1,63 @@
+use crossbeam_channel::{bounded, Sender};
+
+use crate::{Command, ConnectionInfo, Error, Result, SendOptions, SendTarget};
+
+/// A handle to a connected remote peer that sends commands to the processing thread
+/// of a `Peer`, so that application code does not have to build `Command`s itself.
+///
+/// The handle is cheap to clone and can be used from any thread. Get one with
+/// `Peer::remote_peer` or create one from a command sender and the GUID of the remote peer.
+/// The commands are only executed while the `Peer` is processing.
+#[derive(Clone, Debug)]
+pub struct RemotePeer {
+    guid: u64,
+    command_sender: Sender<Command>,
+}
+
+impl RemotePeer {
+    /// Creates a handle to the remote peer with the GUID connected
+    /// to the `Peer` that the command sender belongs to.
+    pub fn new(guid: u64, command_sender: Sender<Command>) -> Self {
+        RemotePeer { guid, command_sender }
+    }
+
+    /// Returns the GUID of the remote peer.
+    pub fn guid(&self) -> u64 {
+        self.guid
+    }
+
+    /// Enqueues a packet for sending to the remote peer, see `Peer::send`.
+    /// Returns `Error::Stopped` if the `Peer` has been dropped.
+    pub fn send(&self, options: SendOptions, payload: Vec<u8>) -> Result<()> {
+        self.send_command(Command::Send {
+            target: SendTarget::Guid(self.guid),
+            options,
+            payload,
+        })
+    }
+
+    /// Closes the connection to the remote peer, see `Peer::disconnect`.
+    /// Returns `Error::Stopped` if the `Peer` has been dropped.
+    pub fn disconnect(&self, flush: bool) -> Result<()> {
+        self.send_command(Command::Disconnect { guid: self.guid, flush })
+    }
+
+    /// Returns the smoothed round trip time in milliseconds to the remote peer,
+    /// see `Peer::ping`. Blocks until the processing thread has answered.
+    pub fn ping(&self) -> Result<Option<u32>> {
+        Ok(self.stats()?.and_then(|info| info.ping_ms))
+    }
+
+    /// Returns a snapshot of the connection to the remote peer or None if there is no
+    /// connection to it. Blocks until the processing thread has answered and returns
+    /// `Error::Stopped` if the `Peer` is dropped before answering.
+    pub fn stats(&self) -> Result<Option<ConnectionInfo>> {
+        let (reply_sender, reply_receiver) = bounded(1);
+        self.send_command(Command::QueryConnection { guid: self.guid, reply_sender })?;
+        reply_receiver.recv().map_err(|_| Error::Stopped)
+    }
+
+    fn send_command(&self, command: Command) -> Result<()> {
+        self.command_sender.send(command).map_err(|_| Error::Stopped)
+    }
+}
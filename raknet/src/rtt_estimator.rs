@@ -0,0 +1,97 @@
+use std::time::Duration;
+
+/// The retransmission timeout before the first round trip time has been measured.
+const INITIAL_RETRANSMISSION_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// The shortest retransmission timeout, which leaves room for the delay
+/// before the remote peer sends ACKs.
+const MIN_RETRANSMISSION_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// The longest retransmission timeout.
+const MAX_RETRANSMISSION_TIMEOUT: Duration = Duration::from_millis(2000);
+
+/// Estimates the round trip time (RTT) of a connection from the time until sent datagrams
+/// are acknowledged and calculates the retransmission timeout (RTO) from it as in RFC 6298.
+#[derive(Debug)]
+pub struct RttEstimator {
+    smoothed_rtt: Option<Duration>,
+    rtt_variation: Duration,
+}
+
+impl RttEstimator {
+    pub fn new() -> Self {
+        RttEstimator {
+            smoothed_rtt: None,
+            rtt_variation: Duration::ZERO,
+        }
+    }
+
+    /// Registers the time from that a datagram was sent until it was acknowledged.
+    pub fn on_rtt_sample(&mut self, rtt: Duration) {
+        match self.smoothed_rtt {
+            None => {
+                self.smoothed_rtt = Some(rtt);
+                self.rtt_variation = rtt / 2;
+            },
+            Some(smoothed_rtt) => {
+                let deviation = smoothed_rtt.abs_diff(rtt);
+                self.rtt_variation = (self.rtt_variation * 3 + deviation) / 4;
+                self.smoothed_rtt = Some((smoothed_rtt * 7 + rtt) / 8);
+            },
+        }
+    }
+
+    /// Returns the retransmission timeout (RTO), which is the time from that a datagram
+    /// is sent until it is resent if no ACK has been received.
+    pub fn retransmission_timeout(&self) -> Duration {
+        match self.smoothed_rtt {
+            Some(smoothed_rtt) => (smoothed_rtt + self.rtt_variation * 4).clamp(MIN_RETRANSMISSION_TIMEOUT, MAX_RETRANSMISSION_TIMEOUT),
+            None => INITIAL_RETRANSMISSION_TIMEOUT,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::RttEstimator;
+
+    #[test]
+    fn retransmission_timeout_is_initial_timeout_without_samples() {
+        // Arrange
+        let estimator = RttEstimator::new();
+
+        // Act/Assert
+        assert_eq!(Duration::from_millis(1000), estimator.retransmission_timeout());
+    }
+
+    #[test]
+    fn retransmission_timeout_is_calculated_from_rtt() {
+        // Arrange
+        let mut estimator = RttEstimator::new();
+
+        // Act
+        estimator.on_rtt_sample(Duration::from_millis(200));
+        let first_timeout = estimator.retransmission_timeout();
+        estimator.on_rtt_sample(Duration::from_millis(280));
+
+        // Assert
+        assert_eq!(Duration::from_millis(600), first_timeout);
+        assert_eq!(Duration::from_millis(210 + 4 * 95), estimator.retransmission_timeout());
+    }
+
+    #[test]
+    fn retransmission_timeout_is_clamped() {
+        // Arrange
+        let mut short_estimator = RttEstimator::new();
+        let mut long_estimator = RttEstimator::new();
+
+        // Act
+        short_estimator.on_rtt_sample(Duration::from_millis(1));
+        long_estimator.on_rtt_sample(Duration::from_secs(5));
+
+        // Assert
+        assert_eq!(Duration::from_millis(100), short_estimator.retransmission_timeout());
+        assert_eq!(Duration::from_millis(2000), long_estimator.retransmission_timeout());
+    }
+}
@@ -0,0 +1,487 @@
+//! The security handshake, cookies and proof-of-work puzzles of connection requests and the
+//! tags of pre-shared keys and handoff tokens.
+//!
+//! The handshake and the cookies need secret keys from a secure random source, so they are
+//! only compiled with the `rand` feature. Without it a peer can still connect to remote peers
+//! that require cookies or proof of work but not to remote peers that use security.
+
+#[cfg(feature = "rand")]
+use std::{fmt, net::SocketAddr, time::Duration};
+#[cfg(feature = "rand")]
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+#[cfg(feature = "rand")]
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use crate::constants::INTEGRITY_TAG_SIZE;
+#[cfg(feature = "rand")]
+use crate::random;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// The context string used when deriving keys in the security handshake.
+#[cfg(feature = "rand")]
+const KEY_DERIVATION_INFO: &[u8] = b"bedroxide raknet security handshake";
+
+/// The time that a cookie sent in an "open connection reply 1" is valid.
+/// A cookie is accepted during the period it was created and the next period.
+#[cfg(feature = "rand")]
+const COOKIE_PERIOD: Duration = Duration::from_secs(10);
+
+/// Security settings for connections accepted by this peer.
+///
+/// Remote peers connecting to a peer with security enabled perform a key
+/// exchange with it and can verify that they are connected to the right peer
+/// by setting `ConnectOptions::server_public_key` to the value of `public_key()`.
+#[cfg(feature = "rand")]
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SecurityConfig {
+    /// The private X25519 key of this peer. Keep it secret.
+    pub private_key: [u8; 32],
+}
+
+#[cfg(feature = "rand")]
+impl SecurityConfig {
+    /// Creates a security config with a new random private key.
+    pub fn generate() -> Self {
+        SecurityConfig {
+            private_key: random::random_bytes(),
+        }
+    }
+
+    /// Returns the public key that remote peers use to verify this peer.
+    pub fn public_key(&self) -> [u8; 32] {
+        PublicKey::from(&StaticSecret::from(self.private_key)).to_bytes()
+    }
+}
+
+#[cfg(feature = "rand")]
+impl fmt::Debug for SecurityConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SecurityConfig")
+            .field("public_key", &self.public_key())
+            .finish()
+    }
+}
+
+/// The keys derived in the security handshake of a connection.
+#[cfg(feature = "rand")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct SessionKeys {
+    /// The key for datagrams sent to the remote peer.
+    pub outgoing_key: [u8; 32],
+    /// The key for datagrams received from the remote peer.
+    pub incoming_key: [u8; 32],
+}
+
+/// The offset of the proof-of-work difficulty in the public key field of "open connection reply 1".
+const PUZZLE_DIFFICULTY_OFFSET: usize = 32;
+
+/// Writes a public key and the difficulty of the proof-of-work puzzle to the 64 byte
+/// public key field of "open connection reply 1". The public key is all zeros when
+/// only cookies are used. The bytes after the difficulty are reserved and set to zero.
+#[cfg(feature = "rand")]
+pub fn encode_public_key_field(public_key: Option<[u8; 32]>, puzzle_difficulty: u8) -> [u8; 64] {
+    let mut encoded = [0u8; 64];
+    if let Some(public_key) = public_key {
+        encoded[..32].copy_from_slice(&public_key);
+    }
+    encoded[PUZZLE_DIFFICULTY_OFFSET] = puzzle_difficulty;
+    encoded
+}
+
+/// Reads a public key from the 64 byte public key field of "open connection reply 1".
+/// Returns None if the key is all zeros, which is sent by a peer that
+/// requires cookies without using security.
+pub fn decode_public_key(encoded: &[u8; 64]) -> Option<[u8; 32]> {
+    if encoded[..32].iter().all(|byte| *byte == 0) {
+        return None;
+    }
+    let mut public_key = [0u8; 32];
+    public_key.copy_from_slice(&encoded[..32]);
+    Some(public_key)
+}
+
+/// Reads the difficulty of the proof-of-work puzzle from the 64 byte public key field
+/// of "open connection reply 1". Returns 0 if no puzzle must be solved.
+pub fn decode_puzzle_difficulty(encoded: &[u8; 64]) -> u8 {
+    encoded[PUZZLE_DIFFICULTY_OFFSET]
+}
+
+/// Solves the proof-of-work puzzle of a cookie by changing the last 8 bytes of the nonce
+/// until `is_puzzle_solved` returns true. Takes about 2^`difficulty` hashes in total, which
+/// are spread over several calls to `solve` so that the connecting peer is not blocked.
+pub struct PuzzleSolver {
+    cookie: u32,
+    difficulty: u8,
+    nonce: [u8; 32],
+    counter: u64,
+}
+
+impl PuzzleSolver {
+    pub fn new(cookie: u32, difficulty: u8, nonce: [u8; 32]) -> Self {
+        PuzzleSolver {
+            cookie,
+            difficulty,
+            nonce,
+            counter: 0,
+        }
+    }
+
+    /// Tries at most `max_hashes` nonces. Returns the nonce that solves
+    /// the puzzle or None if the puzzle has not been solved yet.
+    pub fn solve(&mut self, max_hashes: u32) -> Option<[u8; 32]> {
+        for _ in 0..max_hashes {
+            self.nonce[24..].copy_from_slice(&self.counter.to_be_bytes());
+            if is_puzzle_solved(self.cookie, self.difficulty, &self.nonce) {
+                return Some(self.nonce);
+            }
+            self.counter = self.counter.wrapping_add(1);
+        }
+        None
+    }
+}
+
+/// Returns true if the SHA-256 hash of the cookie followed by the nonce
+/// starts with at least `difficulty` zero bits.
+pub fn is_puzzle_solved(cookie: u32, difficulty: u8, nonce: &[u8]) -> bool {
+    let mut hasher = Sha256::new();
+    hasher.update(cookie.to_be_bytes());
+    hasher.update(nonce);
+    let hash = hasher.finalize();
+    let mut zero_bits = 0u32;
+    for byte in hash.iter() {
+        zero_bits += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+    zero_bits >= difficulty as u32
+}
+
+/// Returns the tag that proves that a datagram was sent by a peer that knows the pre-shared key.
+pub fn integrity_tag(pre_shared_key: &[u8], datagram: &[u8]) -> [u8; INTEGRITY_TAG_SIZE] {
+    let mut mac = HmacSha256::new_from_slice(pre_shared_key).expect("HMAC accepts keys of any size");
+    mac.update(datagram);
+    let digest = mac.finalize().into_bytes();
+    let mut tag = [0u8; INTEGRITY_TAG_SIZE];
+    tag.copy_from_slice(&digest[..INTEGRITY_TAG_SIZE]);
+    tag
+}
+
+/// Returns the datagram without its integrity tag or None if the tag is missing
+/// or was not created with the pre-shared key.
+pub fn strip_integrity_tag<'a>(pre_shared_key: &[u8], tagged_datagram: &'a [u8]) -> Option<&'a [u8]> {
+    if tagged_datagram.len() < INTEGRITY_TAG_SIZE {
+        return None;
+    }
+    let (datagram, tag) = tagged_datagram.split_at(tagged_datagram.len() - INTEGRITY_TAG_SIZE);
+    let mut mac = HmacSha256::new_from_slice(pre_shared_key).expect("HMAC accepts keys of any size");
+    mac.update(datagram);
+    mac.verify_truncated_left(tag).ok()?;
+    Some(datagram)
+}
+
+/// Returns the tag that proves that a handoff token was created by a peer that knows the handoff key.
+pub fn handoff_tag(handoff_key: &[u8], fields: &[u8]) -> [u8; 32] {
+    let mut mac = HmacSha256::new_from_slice(handoff_key).expect("HMAC accepts keys of any size");
+    mac.update(fields);
+    mac.finalize().into_bytes().into()
+}
+
+/// Returns true if the tag of a handoff token was created with the handoff key.
+pub fn is_handoff_tag_valid(handoff_key: &[u8], fields: &[u8], tag: &[u8]) -> bool {
+    let mut mac = HmacSha256::new_from_slice(handoff_key).expect("HMAC accepts keys of any size");
+    mac.update(fields);
+    mac.verify_slice(tag).is_ok()
+}
+
+/// Creates and validates the cookies that a connecting peer must echo in its
+/// "open connection request 2" to prove that it receives datagrams at its address.
+///
+/// The cookies are an HMAC of the address and the current time period
+/// so nothing needs to be stored per connecting peer.
+#[cfg(feature = "rand")]
+pub struct CookieGenerator {
+    secret: [u8; 32],
+}
+
+#[cfg(feature = "rand")]
+impl CookieGenerator {
+    pub fn new() -> Self {
+        CookieGenerator {
+            secret: random::random_bytes(),
+        }
+    }
+
+    /// Returns the cookie for the address and the difficulty of the proof-of-work puzzle sent
+    /// with it, so that the difficulty need not be stored. `elapsed` is the time since the peer was created.
+    pub fn cookie(&self, addr: SocketAddr, elapsed: Duration, puzzle_difficulty: u8) -> u32 {
+        self.cookie_for_period(addr, Self::period(elapsed), puzzle_difficulty)
+    }
+
+    /// Returns true if the cookie was created for the address and the puzzle
+    /// difficulty during the current or the previous period.
+    pub fn is_valid(&self, cookie: u32, addr: SocketAddr, elapsed: Duration, puzzle_difficulty: u8) -> bool {
+        let period = Self::period(elapsed);
+        cookie == self.cookie_for_period(addr, period, puzzle_difficulty) ||
+            (period > 0 && cookie == self.cookie_for_period(addr, period - 1, puzzle_difficulty))
+    }
+
+    fn period(elapsed: Duration) -> u64 {
+        elapsed.as_secs() / COOKIE_PERIOD.as_secs()
+    }
+
+    fn cookie_for_period(&self, addr: SocketAddr, period: u64, puzzle_difficulty: u8) -> u32 {
+        let mut mac = HmacSha256::new_from_slice(&self.secret).expect("HMAC accepts keys of any size");
+        mac.update(addr.to_string().as_bytes());
+        mac.update(&period.to_be_bytes());
+        mac.update(&[puzzle_difficulty]);
+        let digest = mac.finalize().into_bytes();
+        u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]])
+    }
+}
+
+/// The connecting peer's side of the security handshake.
+///
+/// The challenge sent in "open connection request 2" consists of an ephemeral public
+/// key followed by a random nonce. The remote peer answers with its own ephemeral public
+/// key and a proof that it knows the private key matching its public key.
+#[cfg(feature = "rand")]
+pub struct ClientHandshake {
+    server_public_key: PublicKey,
+    ephemeral_secret: StaticSecret,
+    challenge: [u8; 64],
+}
+
+#[cfg(feature = "rand")]
+impl ClientHandshake {
+    pub fn new(server_public_key: [u8; 32]) -> Self {
+        let ephemeral_secret = StaticSecret::from(random::random_bytes());
+        let mut challenge = [0u8; 64];
+        challenge[..32].copy_from_slice(PublicKey::from(&ephemeral_secret).as_bytes());
+        challenge[32..].copy_from_slice(&random::random_bytes());
+        ClientHandshake {
+            server_public_key: PublicKey::from(server_public_key),
+            ephemeral_secret,
+            challenge,
+        }
+    }
+
+    /// Returns the challenge to send in "open connection request 2".
+    pub fn challenge(&self) -> [u8; 64] {
+        self.challenge
+    }
+
+    /// Returns the random nonce of the challenge.
+    pub fn nonce(&self) -> [u8; 32] {
+        let mut nonce = [0u8; 32];
+        nonce.copy_from_slice(&self.challenge[32..]);
+        nonce
+    }
+
+    /// Replaces the nonce of the challenge, e.g. with the solution of a proof-of-work puzzle.
+    pub fn set_nonce(&mut self, nonce: [u8; 32]) {
+        self.challenge[32..].copy_from_slice(&nonce);
+    }
+
+    /// Verifies the answer received in "open connection reply 2" and returns the
+    /// session keys or None if the remote peer could not prove its identity.
+    pub fn verify_answer(&self, answer: &[u8; 128]) -> Option<SessionKeys> {
+        let mut server_ephemeral_key = [0u8; 32];
+        server_ephemeral_key.copy_from_slice(&answer[..32]);
+        let static_shared_secret = self.ephemeral_secret.diffie_hellman(&self.server_public_key);
+        let ephemeral_shared_secret = self.ephemeral_secret.diffie_hellman(&PublicKey::from(server_ephemeral_key));
+        if !static_shared_secret.was_contributory() || !ephemeral_shared_secret.was_contributory() {
+            return None;
+        }
+        let keys = derive_keys(static_shared_secret.as_bytes(), ephemeral_shared_secret.as_bytes(), &self.challenge);
+        let mut mac = HmacSha256::new_from_slice(&keys.confirmation_key).expect("HMAC accepts keys of any size");
+        mac.update(&self.challenge);
+        mac.update(&server_ephemeral_key);
+        mac.verify_slice(&answer[32..64]).ok()?;
+        Some(SessionKeys {
+            outgoing_key: keys.client_to_server_key,
+            incoming_key: keys.server_to_client_key,
+        })
+    }
+}
+
+/// Answers the challenge of a connecting peer. Returns the answer to send in
+/// "open connection reply 2" and the session keys or None if the challenge is invalid.
+#[cfg(feature = "rand")]
+pub fn answer_challenge(private_key: &[u8; 32], challenge: &[u8; 64]) -> Option<([u8; 128], SessionKeys)> {
+    let mut client_ephemeral_key = [0u8; 32];
+    client_ephemeral_key.copy_from_slice(&challenge[..32]);
+    let client_ephemeral_key = PublicKey::from(client_ephemeral_key);
+    let ephemeral_secret = StaticSecret::from(random::random_bytes());
+    let static_shared_secret = StaticSecret::from(*private_key).diffie_hellman(&client_ephemeral_key);
+    let ephemeral_shared_secret = ephemeral_secret.diffie_hellman(&client_ephemeral_key);
+    if !static_shared_secret.was_contributory() || !ephemeral_shared_secret.was_contributory() {
+        return None;
+    }
+    let keys = derive_keys(static_shared_secret.as_bytes(), ephemeral_shared_secret.as_bytes(), challenge);
+    let server_ephemeral_key = PublicKey::from(&ephemeral_secret).to_bytes();
+    let mut mac = HmacSha256::new_from_slice(&keys.confirmation_key).expect("HMAC accepts keys of any size");
+    mac.update(challenge);
+    mac.update(&server_ephemeral_key);
+    let mut answer = [0u8; 128];
+    answer[..32].copy_from_slice(&server_ephemeral_key);
+    answer[32..64].copy_from_slice(&mac.finalize().into_bytes());
+    Some((answer, SessionKeys {
+        outgoing_key: keys.server_to_client_key,
+        incoming_key: keys.client_to_server_key,
+    }))
+}
+
+#[cfg(feature = "rand")]
+struct DerivedKeys {
+    confirmation_key: [u8; 32],
+    client_to_server_key: [u8; 32],
+    server_to_client_key: [u8; 32],
+}
+
+#[cfg(feature = "rand")]
+fn derive_keys(static_shared_secret: &[u8; 32], ephemeral_shared_secret: &[u8; 32], challenge: &[u8; 64]) -> DerivedKeys {
+    let mut input_key_material = [0u8; 64];
+    input_key_material[..32].copy_from_slice(static_shared_secret);
+    input_key_material[32..].copy_from_slice(ephemeral_shared_secret);
+    let mut output_key_material = [0u8; 96];
+    Hkdf::<Sha256>::new(Some(challenge), &input_key_material)
+        .expand(KEY_DERIVATION_INFO, &mut output_key_material)
+        .expect("96 bytes is a valid length for HKDF-SHA256");
+    let mut keys = DerivedKeys {
+        confirmation_key: [0u8; 32],
+        client_to_server_key: [0u8; 32],
+        server_to_client_key: [0u8; 32],
+    };
+    keys.confirmation_key.copy_from_slice(&output_key_material[..32]);
+    keys.client_to_server_key.copy_from_slice(&output_key_material[32..64]);
+    keys.server_to_client_key.copy_from_slice(&output_key_material[64..]);
+    keys
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{integrity_tag, is_puzzle_solved, strip_integrity_tag, PuzzleSolver};
+    #[cfg(feature = "rand")]
+    use std::{net::SocketAddr, time::Duration};
+    #[cfg(feature = "rand")]
+    use super::{answer_challenge, ClientHandshake, CookieGenerator, SecurityConfig};
+
+    #[test]
+    #[cfg(feature = "rand")]
+    fn handshake_derives_matching_keys() {
+        // Arrange
+        let security = SecurityConfig::generate();
+        let client = ClientHandshake::new(security.public_key());
+
+        // Act
+        let (answer, server_keys) = answer_challenge(&security.private_key, &client.challenge()).expect("Challenge was rejected");
+        let client_keys = client.verify_answer(&answer).expect("Answer was rejected");
+
+        // Assert
+        assert_eq!(client_keys.outgoing_key, server_keys.incoming_key);
+        assert_eq!(client_keys.incoming_key, server_keys.outgoing_key);
+        assert_ne!(client_keys.outgoing_key, client_keys.incoming_key);
+    }
+
+    #[test]
+    #[cfg(feature = "rand")]
+    fn answer_from_wrong_private_key_is_rejected() {
+        // Arrange
+        let security = SecurityConfig::generate();
+        let impostor = SecurityConfig::generate();
+        let client = ClientHandshake::new(security.public_key());
+
+        // Act
+        let (answer, _) = answer_challenge(&impostor.private_key, &client.challenge()).expect("Challenge was rejected");
+
+        // Assert
+        assert_eq!(None, client.verify_answer(&answer));
+    }
+
+    #[test]
+    #[cfg(feature = "rand")]
+    fn tampered_answer_is_rejected() {
+        // Arrange
+        let security = SecurityConfig::generate();
+        let client = ClientHandshake::new(security.public_key());
+        let (mut answer, _) = answer_challenge(&security.private_key, &client.challenge()).expect("Challenge was rejected");
+
+        // Act
+        answer[40] ^= 0x01;
+
+        // Assert
+        assert_eq!(None, client.verify_answer(&answer));
+    }
+
+    #[test]
+    #[cfg(feature = "rand")]
+    fn cookie_is_valid_for_same_address_until_next_period_ends() {
+        // Arrange
+        let cookie_generator = CookieGenerator::new();
+        let addr = "127.0.0.1:19132".parse::<SocketAddr>().expect("Could not create address");
+        let other_addr = "127.0.0.1:19133".parse::<SocketAddr>().expect("Could not create address");
+
+        // Act
+        let cookie = cookie_generator.cookie(addr, Duration::from_secs(5), 0);
+
+        // Assert
+        assert!(cookie_generator.is_valid(cookie, addr, Duration::from_secs(5), 0));
+        assert!(cookie_generator.is_valid(cookie, addr, Duration::from_secs(19), 0));
+        assert!(!cookie_generator.is_valid(cookie, addr, Duration::from_secs(20), 0));
+        assert!(!cookie_generator.is_valid(cookie, other_addr, Duration::from_secs(5), 0));
+        assert!(!cookie_generator.is_valid(cookie, addr, Duration::from_secs(5), 8));
+    }
+
+    #[test]
+    fn solved_puzzle_is_verified() {
+        // Arrange
+        let mut solver = PuzzleSolver::new(0x12345678, 12, [7u8; 32]);
+
+        // Act
+        let nonce = solver.solve(1 << 20).expect("Could not solve puzzle");
+
+        // Assert
+        assert!(is_puzzle_solved(0x12345678, 12, &nonce));
+        assert!(!is_puzzle_solved(0x12345678, 255, &nonce));
+        assert!(is_puzzle_solved(0x12345678, 0, &[0u8; 32]));
+        assert_eq!([7u8; 24], nonce[..24]);
+    }
+
+    #[test]
+    fn puzzle_is_solved_over_several_calls() {
+        // Arrange
+        let mut solver = PuzzleSolver::new(0x12345678, 12, [7u8; 32]);
+        let expected_nonce = PuzzleSolver::new(0x12345678, 12, [7u8; 32]).solve(1 << 20);
+
+        // Act
+        let mut calls = 1;
+        let mut nonce = solver.solve(16);
+        while nonce.is_none() {
+            calls += 1;
+            nonce = solver.solve(16);
+        }
+
+        // Assert
+        assert!(calls > 1);
+        assert_eq!(expected_nonce, nonce);
+    }
+
+    #[test]
+    fn integrity_tag_is_verified_with_same_key() {
+        // Arrange
+        let datagram = [0x84, 1, 2, 3];
+        let mut tagged_datagram = datagram.to_vec();
+        tagged_datagram.extend_from_slice(&integrity_tag(b"key", &datagram));
+
+        // Act/Assert
+        assert_eq!(Some(&datagram[..]), strip_integrity_tag(b"key", &tagged_datagram));
+        assert_eq!(None, strip_integrity_tag(b"other key", &tagged_datagram));
+        assert_eq!(None, strip_integrity_tag(b"key", &tagged_datagram[1..]));
+        assert_eq!(None, strip_integrity_tag(b"key", &[1, 2]));
+    }
+}
@@ -0,0 +1,55 @@
+use std::{fmt, time::Duration};
+
+use crate::inbound_limiter::InboundLimit;
+
+/// The kind of suspicious activity reported in a `PeerEvent::SecurityEvent`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SecurityEventKind {
+    /// A banned address sent a connection request.
+    BannedAddressConnecting,
+    /// A remote peer that is not in `Config::allow_list` sent a connection request.
+    NotAllowedConnecting { guid: u64 },
+    /// A connection request contained a cookie that was not issued to the address or has expired.
+    InvalidCookie,
+    /// A connection request lacked a security challenge or contained an invalid one.
+    InvalidSecurityChallenge,
+    /// A connection request lacked the solution of the proof-of-work puzzle sent
+    /// with the cookie or contained a wrong one, see `Config::proof_of_work_difficulty`.
+    InvalidProofOfWork,
+    /// A connection request contained a handoff token that was not signed with
+    /// `Config::handoff_key`, has expired or was already used from another address.
+    InvalidHandoffToken,
+    /// A connection request used a RakNet protocol version that is not supported.
+    IncompatibleProtocolVersion { protocol_version: u8 },
+    /// A remote peer sent something other than a connection request before its connection
+    /// was verified. The connection was closed and the IP address is banned for `penalty`,
+    /// see `Config::garbage_penalty_in_ms`.
+    GarbageFromUnverifiedSender { penalty: Duration },
+    /// The address started sending more offline packets than allowed by
+    /// `Config::max_offline_packets_per_second_per_ip`. Reported once each time
+    /// the limit is exceeded after having been below it.
+    RateLimitExceeded,
+    /// A connected remote peer started exceeding an inbound limit of its connection,
+    /// e.g. `Config::max_incoming_packets_per_second`. Reported once each time the
+    /// limit is exceeded after having been below it.
+    InboundLimitExceeded { limit: InboundLimit },
+}
+
+impl fmt::Display for SecurityEventKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SecurityEventKind::BannedAddressConnecting => write!(f, "Banned address connecting"),
+            SecurityEventKind::NotAllowedConnecting { guid } => write!(f, "Remote peer with GUID {} not in allow list connecting", guid),
+            SecurityEventKind::InvalidCookie => write!(f, "Invalid cookie"),
+            SecurityEventKind::InvalidSecurityChallenge => write!(f, "Invalid security challenge"),
+            SecurityEventKind::InvalidProofOfWork => write!(f, "Invalid proof of work"),
+            SecurityEventKind::InvalidHandoffToken => write!(f, "Invalid handoff token"),
+            SecurityEventKind::IncompatibleProtocolVersion { protocol_version } =>
+                write!(f, "Incompatible protocol version {}", protocol_version),
+            SecurityEventKind::GarbageFromUnverifiedSender { penalty } =>
+                write!(f, "Garbage from unverified sender, banned for {:?}", penalty),
+            SecurityEventKind::RateLimitExceeded => write!(f, "Offline packet rate limit exceeded"),
+            SecurityEventKind::InboundLimitExceeded { limit } => write!(f, "Inbound limit of {} exceeded", limit),
+        }
+    }
+}
@@ -0,0 +1,31 @@
+use crate::{Ordering, Priority, Reliability};
+
+/// How a packet is sent with `Command::Send`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SendOptions {
+    /// The priority of the packet relative to other queued packets.
+    pub priority: Priority,
+
+    /// Whether the packet is resent until it has been acknowledged.
+    pub reliability: Reliability,
+
+    /// The ordering or sequencing of the packet relative to other packets
+    /// on the same ordering channel.
+    pub ordering: Ordering,
+
+    /// A number chosen by the user that is reported with a `PeerEvent::SendReceiptAcked`
+    /// or `PeerEvent::SendReceiptLoss` when the packet has been acknowledged or lost.
+    pub receipt: Option<u32>,
+}
+
+impl SendOptions {
+    /// Creates options for sending a packet without a receipt.
+    pub fn new(priority: Priority, reliability: Reliability, ordering: Ordering) -> Self {
+        SendOptions {
+            priority,
+            reliability,
+            ordering,
+            receipt: None,
+        }
+    }
+}
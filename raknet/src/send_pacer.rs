@@ -0,0 +1,90 @@
+use std::time::{Duration, Instant};
+
+/// Paces new datagrams evenly over time instead of sending all queued
+/// packets in one burst, which causes packet loss on some links (e.g. WiFi).
+///
+/// The pacer works like a token bucket that is refilled with `max_datagrams_per_tick`
+/// datagrams per tick interval and that holds at most `max_datagrams_per_tick`
+/// datagrams, so the rate is the same however often the peer is processed.
+pub struct SendPacer {
+    available_datagrams: f64,
+    last_refill_time: Option<Instant>,
+}
+
+impl SendPacer {
+    pub fn new() -> Self {
+        SendPacer {
+            available_datagrams: 0.0,
+            last_refill_time: None,
+        }
+    }
+
+    /// Adds the datagrams earned since the last refill.
+    pub fn refill(&mut self, time: Instant, max_datagrams_per_tick: usize, tick_interval: Duration) {
+        let max_datagrams = max_datagrams_per_tick as f64;
+        self.available_datagrams = match self.last_refill_time {
+            Some(last_refill_time) if !tick_interval.is_zero() => {
+                let elapsed_ticks = time.saturating_duration_since(last_refill_time).as_secs_f64() / tick_interval.as_secs_f64();
+                (self.available_datagrams + max_datagrams * elapsed_ticks).min(max_datagrams)
+            },
+            _ => max_datagrams,
+        };
+        self.last_refill_time = Some(time);
+    }
+
+    /// Returns true if another datagram may be sent.
+    pub fn has_room_for_datagram(&self) -> bool {
+        self.available_datagrams >= 1.0
+    }
+
+    /// Registers that a new datagram has been sent.
+    pub fn on_datagram_sent(&mut self) {
+        self.available_datagrams -= 1.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+    use super::SendPacer;
+
+    #[test]
+    fn datagrams_are_earned_over_time() {
+        // Arrange
+        let time = Instant::now();
+        let tick_interval = Duration::from_millis(10);
+        let mut pacer = SendPacer::new();
+        pacer.refill(time, 2, tick_interval);
+        pacer.on_datagram_sent();
+        pacer.on_datagram_sent();
+        let has_room_after_burst = pacer.has_room_for_datagram();
+
+        // Act
+        pacer.refill(time + Duration::from_millis(5), 2, tick_interval);
+
+        // Assert
+        assert!(!has_room_after_burst);
+        assert!(pacer.has_room_for_datagram());
+        pacer.on_datagram_sent();
+        assert!(!pacer.has_room_for_datagram());
+    }
+
+    #[test]
+    fn burst_is_limited_to_one_tick() {
+        // Arrange
+        let time = Instant::now();
+        let mut pacer = SendPacer::new();
+        pacer.refill(time, 2, Duration::from_millis(10));
+
+        // Act
+        pacer.refill(time + Duration::from_secs(10), 2, Duration::from_millis(10));
+
+        // Assert
+        let mut sent = 0;
+        while pacer.has_room_for_datagram() {
+            pacer.on_datagram_sent();
+            sent += 1;
+        }
+        assert_eq!(2, sent);
+    }
+}
@@ -0,0 +1,60 @@
+use std::net::SocketAddr;
+
+/// The connected remote peers that a packet is sent to with `Command::Send` or `Peer::send_to_target`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SendTarget {
+    /// The remote peer at the address.
+    Addr(SocketAddr),
+    /// The remote peer with the GUID.
+    Guid(u64),
+    /// All connected remote peers.
+    All,
+    /// All connected remote peers except the one with the GUID,
+    /// e.g. the peer that sent the update being forwarded.
+    AllExcept(u64),
+    /// The connected remote peers with the GUIDs.
+    Many(Vec<u64>),
+}
+
+impl SendTarget {
+    /// Returns true if the packet is sent to the remote peer with the address and GUID.
+    pub(crate) fn includes(&self, addr: SocketAddr, guid: u64) -> bool {
+        match self {
+            SendTarget::Addr(target_addr) => *target_addr == addr,
+            SendTarget::Guid(target_guid) => *target_guid == guid,
+            SendTarget::All => true,
+            SendTarget::AllExcept(excluded_guid) => *excluded_guid != guid,
+            SendTarget::Many(guids) => guids.contains(&guid),
+        }
+    }
+}
+
+impl From<SocketAddr> for SendTarget {
+    fn from(addr: SocketAddr) -> Self {
+        SendTarget::Addr(addr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use super::SendTarget;
+
+    #[test]
+    fn targets_include_selected_peers() {
+        // Arrange
+        let addr = "192.168.1.1:19132".parse::<SocketAddr>().expect("Could not create address");
+        let other_addr = "192.168.1.2:19132".parse::<SocketAddr>().expect("Could not create address");
+
+        // Act/Assert
+        assert!(SendTarget::Addr(addr).includes(addr, 1));
+        assert!(!SendTarget::Addr(addr).includes(other_addr, 1));
+        assert!(SendTarget::Guid(1).includes(addr, 1));
+        assert!(!SendTarget::Guid(2).includes(addr, 1));
+        assert!(SendTarget::All.includes(addr, 1));
+        assert!(!SendTarget::AllExcept(1).includes(addr, 1));
+        assert!(SendTarget::AllExcept(1).includes(addr, 2));
+        assert!(SendTarget::Many(vec![2, 3]).includes(addr, 3));
+        assert!(!SendTarget::Many(vec![2, 3]).includes(addr, 1));
+    }
+}
@@ -1,12 +1,82 @@
 use std::{
-    net::{SocketAddr, UdpSocket},
+    net::{IpAddr, SocketAddr, UdpSocket},
     io,
 };
 
+use socket2::SockRef;
+
+use crate::socket_options::{self, SocketOptions};
+
+/// A socket that sends and receives datagrams, e.g. a UDP socket.
+///
+/// Implement this for a custom transport and create a peer with `Peer::with_socket`.
+/// The peer calls the methods from its processing loop, so they must never block.
 pub trait DatagramSocket {
+    /// Receives one datagram into the start of `buffer` and returns the part of the
+    /// buffer holding the datagram together with the address it was sent from.
+    ///
+    /// Must return an error of kind `io::ErrorKind::WouldBlock` when no datagram
+    /// is waiting. Datagrams larger than the buffer may be truncated.
     fn receive_datagram<'a>(&mut self, buffer: &'a mut [u8]) -> io::Result<(&'a [u8], SocketAddr)>;
+
+    /// Sends the whole payload as one datagram to the address and returns the number of sent bytes.
+    /// The peer passes IPv4 addresses in their IPv4-mapped form if the local address is an IPv6 address.
+    ///
+    /// The datagram may be dropped silently, as with UDP. If it cannot be sent
+    /// without blocking an error of kind `io::ErrorKind::WouldBlock` must be returned.
     fn send_datagram(&mut self, payload: &[u8], addr: SocketAddr) -> io::Result<usize>;
+
+    /// Sends the datagrams that the socket has queued instead of sending them directly
+    /// in `send_datagram`. Called at the end of every processing round.
+    ///
+    /// Datagrams that cannot be sent without blocking stay queued for the next flush
+    /// and an error of kind `io::ErrorKind::WouldBlock` is returned.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Returns the local address of the socket.
     fn local_addr(&self) -> io::Result<SocketAddr>;
+
+    /// Returns all local addresses if the socket is made of several sockets.
+    /// The addresses are sent to remote peers when connecting.
+    fn local_addrs(&self) -> io::Result<Vec<SocketAddr>> {
+        Ok(vec![self.local_addr()?])
+    }
+
+    /// Called when a connection to the remote address has been created, e.g. so that a
+    /// socket made of several sockets keeps sending to it out the socket it connected on.
+    fn on_connection_created(&mut self, _addr: SocketAddr) {}
+
+    /// Called when the connection to the remote address has been dropped.
+    fn on_connection_dropped(&mut self, _addr: SocketAddr) {}
+
+    /// Sets the IP options of the sent datagrams. Called when the peer is created
+    /// with `Config::socket_options`. Sockets that do not support IP options
+    /// must return an error of kind `io::ErrorKind::Unsupported` if any option is set.
+    fn set_options(&mut self, options: &SocketOptions) -> io::Result<()> {
+        if options.is_default() {
+            Ok(())
+        } else {
+            Err(io::ErrorKind::Unsupported.into())
+        }
+    }
+}
+
+/// Returns the address in the form that a socket of the family reaches it through.
+///
+/// Some platforms only reach IPv4 addresses from dual-stack IPv6 sockets through their
+/// IPv4-mapped form, and IPv4 sockets can not send to IPv4-mapped IPv6 addresses.
+/// The peer maps every address it sends to, so sockets are given addresses of their family.
+pub(crate) fn to_socket_family(addr: SocketAddr, is_ipv6_socket: bool) -> SocketAddr {
+    match addr {
+        SocketAddr::V4(addr_v4) if is_ipv6_socket => SocketAddr::new(IpAddr::V6(addr_v4.ip().to_ipv6_mapped()), addr_v4.port()),
+        SocketAddr::V6(addr_v6) if !is_ipv6_socket => match addr_v6.ip().to_ipv4_mapped() {
+            Some(ip) => SocketAddr::new(IpAddr::V4(ip), addr_v6.port()),
+            None => addr,
+        },
+        _ => addr,
+    }
 }
 
 impl DatagramSocket for UdpSocket {
@@ -15,16 +85,22 @@ impl DatagramSocket for UdpSocket {
     }
     
     fn send_datagram(&mut self, payload: &[u8], addr: SocketAddr) -> io::Result<usize> {
-         self.send_to(payload, addr)
+        self.send_to(payload, addr)
     }
 
     fn local_addr(&self) -> io::Result<SocketAddr> {
         self.local_addr()
     }
+
+    fn set_options(&mut self, options: &SocketOptions) -> io::Result<()> {
+        socket_options::apply(SockRef::from(&*self), options)
+    }
 }
 
 #[cfg(test)]
 use crossbeam_channel::{unbounded, Sender, Receiver, TryRecvError};
+#[cfg(test)]
+use std::sync::{Arc, atomic::{AtomicBool, Ordering}};
 
 #[cfg(test)]
 pub struct FakeDatagramSocket {
@@ -33,6 +109,7 @@ pub struct FakeDatagramSocket {
     send_datagram_sender: Sender<(Vec<u8>, SocketAddr)>,
     send_datagram_receiver: Receiver<(Vec<u8>, SocketAddr)>,
     local_addr: SocketAddr,
+    send_would_block: Arc<AtomicBool>,
 }
 
 #[cfg(test)]
@@ -46,6 +123,7 @@ impl FakeDatagramSocket {
             send_datagram_sender,
             send_datagram_receiver,
             local_addr,
+            send_would_block: Arc::new(AtomicBool::new(false)),
         }
     }
 
@@ -56,6 +134,11 @@ impl FakeDatagramSocket {
     pub fn get_datagram_receiver(&self) -> Receiver<(Vec<u8>, SocketAddr)> {
         self.send_datagram_receiver.clone()
     }    
+
+    /// Returns a flag that makes sending fail with `WouldBlock` while it is set.
+    pub fn get_send_would_block(&self) -> Arc<AtomicBool> {
+        self.send_would_block.clone()
+    }
 }
 
 #[cfg(test)]
@@ -73,6 +156,9 @@ impl DatagramSocket for FakeDatagramSocket {
     }
     
     fn send_datagram(&mut self, payload: &[u8], addr: SocketAddr) -> io::Result<usize> {
+        if self.send_would_block.load(Ordering::Relaxed) {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
         let mut buf = Vec::new();
         buf.extend_from_slice(payload);
         let buf_len = buf.len();
@@ -85,3 +171,37 @@ impl DatagramSocket for FakeDatagramSocket {
         Ok(self.local_addr)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use super::to_socket_family;
+
+    fn addr(addr: &str) -> SocketAddr {
+        addr.parse().expect("Could not create address")
+    }
+
+    #[test]
+    fn ipv4_address_is_mapped_for_ipv6_socket() {
+        // Act
+        let ipv6_socket_addr = to_socket_family(addr("192.168.1.1:1234"), true);
+        let ipv4_socket_addr = to_socket_family(addr("192.168.1.1:1234"), false);
+
+        // Assert
+        assert_eq!(addr("[::ffff:192.168.1.1]:1234"), ipv6_socket_addr);
+        assert_eq!(addr("192.168.1.1:1234"), ipv4_socket_addr);
+    }
+
+    #[test]
+    fn ipv4_mapped_address_is_unmapped_for_ipv4_socket() {
+        // Act
+        let ipv4_socket_addr = to_socket_family(addr("[::ffff:192.168.1.1]:1234"), false);
+        let ipv6_socket_addr = to_socket_family(addr("[::ffff:192.168.1.1]:1234"), true);
+        let other_ipv6_addr = to_socket_family(addr("[2001:db8::1]:1234"), false);
+
+        // Assert
+        assert_eq!(addr("192.168.1.1:1234"), ipv4_socket_addr);
+        assert_eq!(addr("[::ffff:192.168.1.1]:1234"), ipv6_socket_addr);
+        assert_eq!(addr("[2001:db8::1]:1234"), other_ipv6_addr);
+    }
+}
@@ -0,0 +1,27 @@
+/// The wire format of socket addresses in messages, which differs between RakNet forks
+/// in the family written before IPv6 addresses. In both formats the bytes of IPv4
+/// addresses are bitwise complemented.
+///
+/// The format is selected with `Config::socket_addr_format` and used by
+/// `DataRead::read_socket_addr` and `DataWrite::write_socket_addr`. The family of
+/// IPv6 addresses is ignored when reading, so only the written addresses differ.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SocketAddrFormat {
+    /// IPv6 addresses have the family 24, which Bedrock Edition peers accept.
+    #[default]
+    Bedrock,
+    /// IPv6 addresses have the family 10, the value of `AF_INET6` on Linux, as sent by
+    /// the original RakNet, which copies the `sockaddr_in6` of the platform.
+    Classic,
+}
+
+impl SocketAddrFormat {
+    /// Returns the family written before IPv6 addresses.
+    pub(crate) fn ipv6_family(self) -> u16 {
+        match self {
+            SocketAddrFormat::Bedrock => 24,
+            SocketAddrFormat::Classic => 10,
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use std::io;
+
+use socket2::SockRef;
+
+/// IP options for the datagrams sent by the socket of a peer, see `Config::socket_options`.
+/// Options that are None or false keep the default of the operating system.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default))]
+pub struct SocketOptions {
+    /// The time to live, i.e. the number of hops before a datagram is discarded.
+    /// The hop limit is set for IPv6 sockets.
+    pub ttl: Option<u32>,
+    /// The type of service byte, where the six highest bits are the DSCP value,
+    /// e.g. `0xb8` for Expedited Forwarding. The traffic class is set for IPv6 sockets.
+    pub type_of_service: Option<u8>,
+    /// If true, the don't fragment bit is set so that datagrams larger than the
+    /// path MTU are dropped instead of being fragmented, which MTU discovery relies on.
+    pub dont_fragment: bool,
+}
+
+impl SocketOptions {
+    /// Returns true if no option is set.
+    pub fn is_default(&self) -> bool {
+        *self == SocketOptions::default()
+    }
+}
+
+/// Sets the options on a UDP socket.
+pub fn apply(socket: SockRef, options: &SocketOptions) -> io::Result<()> {
+    let is_ipv6 = socket.local_addr()?.is_ipv6();
+    if let Some(ttl) = options.ttl {
+        if is_ipv6 {
+            socket.set_unicast_hops_v6(ttl)?;
+        } else {
+            socket.set_ttl(ttl)?;
+        }
+    }
+    if let Some(type_of_service) = options.type_of_service {
+        if is_ipv6 {
+            set_traffic_class(&socket, type_of_service)?;
+        } else {
+            socket.set_tos(type_of_service.into())?;
+        }
+    }
+    if options.dont_fragment {
+        set_dont_fragment(&socket, is_ipv6)?;
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn set_traffic_class(socket: &SockRef, type_of_service: u8) -> io::Result<()> {
+    socket.set_tclass_v6(type_of_service.into())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_traffic_class(_socket: &SockRef, _type_of_service: u8) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "Setting the IPv6 traffic class is not supported on this platform"))
+}
+
+#[cfg(target_os = "linux")]
+fn set_dont_fragment(socket: &SockRef, is_ipv6: bool) -> io::Result<()> {
+    use std::{mem, os::unix::io::AsRawFd};
+
+    let (level, name, value) = if is_ipv6 {
+        (libc::IPPROTO_IPV6, libc::IPV6_MTU_DISCOVER, libc::IPV6_PMTUDISC_DO)
+    } else {
+        (libc::IPPROTO_IP, libc::IP_MTU_DISCOVER, libc::IP_PMTUDISC_DO)
+    };
+    // SAFETY: The option value is a c_int that outlives the call
+    let result = unsafe {
+        libc::setsockopt(socket.as_raw_fd(), level, name, &value as *const libc::c_int as *const libc::c_void, mem::size_of::<libc::c_int>() as libc::socklen_t)
+    };
+    if result < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_dont_fragment(_socket: &SockRef, _is_ipv6: bool) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "Setting the don't fragment bit is not supported on this platform"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::UdpSocket;
+    use socket2::SockRef;
+    use super::{apply, SocketOptions};
+
+    #[test]
+    fn options_are_set_on_socket() {
+        // Arrange
+        let socket = UdpSocket::bind("127.0.0.1:0").expect("Could not bind socket");
+        let options = SocketOptions { ttl: Some(32), type_of_service: Some(0xb8), dont_fragment: false };
+
+        // Act
+        apply(SockRef::from(&socket), &options).expect("Could not set options");
+
+        // Assert
+        assert_eq!(32, socket.ttl().expect("Could not get TTL"));
+        assert_eq!(0xb8, SockRef::from(&socket).tos().expect("Could not get TOS"));
+    }
+}
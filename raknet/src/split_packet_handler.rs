@@ -1,105 +1,384 @@
-use std::{collections::HashMap, time::Instant};
-use log::{debug, error};
+use std::{collections::{btree_map::Entry, BTreeMap, HashMap}, net::SocketAddr, time::Instant};
 
-use crate::{error::ReadError, internal_packet::{InternalOrdering, InternalPacket, InternalReliability}, Result};
+use crate::{
+    communicator::Communicator,
+    constants::{MIN_SPLIT_PACKET_COUNT_FOR_PROGRESS, SPLIT_PACKET_TIMEOUT},
+    error::ReadError,
+    internal_packet::{InternalOrdering, InternalPacket, InternalReliability},
+    logging::debug,
+    packet_datagram::PacketDatagram,
+    peer_event::PeerEvent,
+    socket::DatagramSocket,
+    Result,
+};
+
+/// Returns true if the progress of a split packet should be reported when `count`
+/// of its `total` parts are done, which is every time another tenth of the parts are done.
+pub fn is_split_packet_progress_reported(count: u32, total: u32) -> bool {
+    let (count, total) = (count as u64, total as u64);
+    total >= MIN_SPLIT_PACKET_COUNT_FOR_PROGRESS as u64 && count > 0 && count * 10 / total != (count - 1) * 10 / total
+}
 
 struct SplitPacketChannel {
     /// The `InternalReliability` of the split packet when reassembled.
     reliability: InternalReliability,
     /// The `InternalOrdering` of the split packet when reassembled.
     ordering: InternalOrdering,
+    /// The total number of parts of the split packet.
+    part_count: u32,
     /// The number of bytes received so far.
-    received_byte_count: u32,
-    /// The number of parts received.
-    received_part_count: u32,
-    /// A Vec with the received data of the parts at the correct index.
-    /// The Vec is preallocated to have the length of the total part count.
-    parts: Vec<Option<Box<[u8]>>>,
-} 
+    received_byte_count: usize,
+    /// The received parts keyed by their index. Only received parts take up
+    /// memory so nothing is allocated up front for the total part count.
+    parts: BTreeMap<u32, Box<[u8]>>,
+    /// True if the split packet is too large to be reassembled, in which case
+    /// only the indices of the received parts are kept.
+    is_discarded: bool,
+    /// The time when the last new part was received.
+    last_part_time: Instant,
+}
 
 impl SplitPacketChannel {
-    pub fn new(reliability: InternalReliability, ordering: InternalOrdering, split_packet_count: u32) -> Self {
+    pub fn new(time: Instant, reliability: InternalReliability, ordering: InternalOrdering, part_count: u32) -> Self {
         SplitPacketChannel {
             reliability,
             ordering,
+            part_count,
             received_byte_count: 0,
-            received_part_count: 0,
-            parts: vec![None; split_packet_count as usize],
+            parts: BTreeMap::new(),
+            is_discarded: false,
+            last_part_time: time,
         }
     }
 
-    pub fn insert(&mut self, index: u32, data: Box<[u8]>) -> Result<()> {
-        if index >= self.parts.len() as u32 {
+    /// Inserts a part at the specified index. Returns an error if the
+    /// index has already been received in which case the part is ignored.
+    pub fn insert(&mut self, time: Instant, index: u32, data: Box<[u8]>) -> Result<()> {
+        if index >= self.part_count {
             return Err(ReadError::SplitPacketIndexOutOfRange.into());
         }
 
-        if self.parts[index as usize] != None {
-            return Err(ReadError::DuplicateSplitPacketIndex.into());
+        match self.parts.entry(index) {
+            Entry::Occupied(_) => Err(ReadError::DuplicateSplitPacketIndex.into()),
+            Entry::Vacant(entry) => {
+                if self.is_discarded {
+                    entry.insert(Box::default());
+                } else {
+                    self.received_byte_count += data.len();
+                    entry.insert(data);
+                }
+                self.last_part_time = time;
+                Ok(())
+            },
         }
+    }
 
-        self.received_byte_count = self.received_byte_count + data.len() as u32;
-        self.received_part_count = self.received_part_count + 1;
-        self.parts[index as usize] = Some(data);
-        Ok(())
+    /// Returns true if no new part has been received within `SPLIT_PACKET_TIMEOUT`.
+    pub fn is_stale(&self, time: Instant) -> bool {
+        time.saturating_duration_since(self.last_part_time) > SPLIT_PACKET_TIMEOUT
     }
 
-    pub fn get_reassembled_packet(&self, time: Instant) -> Option<InternalPacket> {
-        if self.has_complete_packet() {
-            let mut payload = Vec::with_capacity(self.received_byte_count as usize);
-            for part in self.parts.iter() {
-                if let Some(data) = part {
-                    payload.extend_from_slice(&data);
-                } else {
-                    error!("Missing split packet part even though packet should be complete");
-                    return None;
-                }
-            }
-            Some(InternalPacket::new(time, self.reliability, self.ordering, None, None, payload.into_boxed_slice()))
-        } else {
-            None
+    /// Frees the data of the received parts. The parts received from now on are
+    /// only counted so that the channel can be removed when all have been received.
+    pub fn discard(&mut self) {
+        self.is_discarded = true;
+        self.received_byte_count = 0;
+        for part in self.parts.values_mut() {
+            *part = Box::default();
         }
     }
 
-    fn has_complete_packet(&self) -> bool {
-        self.received_part_count == self.parts.len() as u32
+    pub fn is_discarded(&self) -> bool {
+        self.is_discarded
+    }
+
+    pub fn received_part_count(&self) -> u32 {
+        self.parts.len() as u32
+    }
+
+    pub fn contains(&self, index: u32) -> bool {
+        self.parts.contains_key(&index)
+    }
+
+    pub fn received_byte_count(&self) -> usize {
+        self.received_byte_count
+    }
+
+    pub fn part_count(&self) -> u32 {
+        self.part_count
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.received_part_count() == self.part_count
+    }
+
+    /// Concatenates the parts in index order. Must only be called when complete.
+    pub fn into_reassembled_packet(self, time: Instant) -> InternalPacket {
+        let mut payload = Vec::with_capacity(self.received_byte_count);
+        for part in self.parts.values() {
+            payload.extend_from_slice(part);
+        }
+        InternalPacket::new(time, self.reliability, self.ordering, None, None, payload.into_boxed_slice())
     }
 }
 
 pub struct SplitPacketHandler {
     channels: HashMap<u16, SplitPacketChannel>,
+    remote_addr: SocketAddr,
+    remote_guid: u64,
+    max_part_size: usize,
 }
 
 impl SplitPacketHandler {
-    pub fn new() -> SplitPacketHandler {
+    /// Creates a handler for split packets from a remote peer. `mtu` is the MTU
+    /// agreed with the remote peer which limits the size of each part.
+    pub fn new(remote_addr: SocketAddr, remote_guid: u64, mtu: u16) -> SplitPacketHandler {
         SplitPacketHandler {            
             channels: HashMap::with_capacity(10),
+            remote_addr,
+            remote_guid,
+            max_part_size: PacketDatagram::get_max_payload_size(mtu) as usize,
         }
     }
 
-    pub fn handle_split_packet(&mut self, time: Instant, packet: InternalPacket) -> Option<InternalPacket> {
+    /// Stores a part of a split packet and returns the reassembled packet when all parts
+    /// have been received. Returns an error if the part is invalid, which is checked
+    /// before any memory is allocated for the parts.
+    pub fn handle_split_packet(&mut self, time: Instant, packet: InternalPacket, communicator: &mut Communicator<impl DatagramSocket>) -> Result<Option<InternalPacket>> {
         if let Some(header) = packet.split_packet_header() {
             debug!("Split packet. count={}, id={}, idx={}", header.split_packet_count(), header.split_packet_id(), header.split_packet_index());
 
             let id = header.split_packet_id();
-
+            let count = header.split_packet_count();
+            if count == 0 || count > communicator.config().max_incoming_split_packet_count {
+                return Err(ReadError::InvalidSplitPacketCount.into());
+            }
+            if header.split_packet_index() >= count {
+                return Err(ReadError::SplitPacketIndexOutOfRange.into());
+            }
+            if packet.payload().len() > self.max_part_size {
+                return Err(ReadError::SplitPacketPartTooLarge.into());
+            }
+            if self.channels.get(&id).is_some_and(|channel| channel.part_count() != count) {
+                return Err(ReadError::InvalidSplitPacketCount.into());
+            }
             if !self.channels.contains_key(&id) {
-                self.channels.insert(id, SplitPacketChannel::new(packet.reliability(), packet.ordering(), header.split_packet_count()));
+                // Channels are only added here, so removing the stale ones
+                // when a new split packet starts bounds the number of channels
+                self.remove_stale_channels(time);
             }
-    
-            if let Some(channel) = self.channels.get_mut(&id) {
-                if let Err(err) = channel.insert(header.split_packet_index(), packet.into_payload()) {
-                    error!("Failed inserting split packet: {:?}", err);
-                    return None;
+            let max_payload_size = communicator.config().max_incoming_payload_size;
+            if max_payload_size > 0 {
+                // All parts but the last are as large as the first, so the size is known from any of them
+                let min_size = if header.split_packet_index() + 1 < count {
+                    (count as usize - 1) * packet.payload().len() + 1
+                } else {
+                    packet.payload().len()
+                };
+                let received_byte_count = self.channels.get(&id)
+                    .filter(|channel| !channel.contains(header.split_packet_index()))
+                    .map_or(0, |channel| channel.received_byte_count());
+                let is_discarded = self.channels.get(&id).is_some_and(|channel| channel.is_discarded());
+                if !is_discarded && (min_size > max_payload_size || received_byte_count + packet.payload().len() > max_payload_size) {
+                    // Keep counting the parts so that the remaining ones are ignored without another error
+                    let channel = self.channels.entry(id).or_insert_with(|| SplitPacketChannel::new(time, packet.reliability(), packet.ordering(), count));
+                    channel.discard();
+                    channel.insert(time, header.split_packet_index(), Box::default())?;
+                    if channel.is_complete() {
+                        self.channels.remove(&id);
+                    }
+                    return Err(ReadError::ReassembledPacketTooLarge.into());
                 }
+            }
 
-                // TODO: Send progress to user
+            let channel = self.channels.entry(id).or_insert_with(|| SplitPacketChannel::new(time, packet.reliability(), packet.ordering(), count));
+            channel.insert(time, header.split_packet_index(), packet.into_payload())?;
 
-                if let Some(packet) = channel.get_reassembled_packet(time) {
+            if channel.is_discarded() {
+                if channel.is_complete() {
                     self.channels.remove(&id);
-                    return Some(packet);
                 }
+                return Ok(None);
+            }
+
+            if is_split_packet_progress_reported(channel.received_part_count(), channel.part_count()) {
+                communicator.send_event(PeerEvent::SplitPacketProgress {
+                    addr: self.remote_addr,
+                    guid: self.remote_guid,
+                    split_id: id,
+                    received: channel.received_part_count(),
+                    total: channel.part_count(),
+                    is_incoming: true,
+                });
+            }
+
+            if channel.is_complete() {
+                return Ok(self.channels.remove(&id).map(|channel| channel.into_reassembled_packet(time)));
             }
         }
-        None
+        Ok(None)
     }
-}
\ No newline at end of file
+
+    /// Removes the split packets that have not received a new part within `SPLIT_PACKET_TIMEOUT`.
+    fn remove_stale_channels(&mut self, time: Instant) {
+        let remote_addr = self.remote_addr;
+        self.channels.retain(|id, channel| {
+            if channel.is_stale(time) {
+                debug!("Discarding stale split packet from {}. id={}, received={}/{}", remote_addr, id, channel.received_part_count(), channel.part_count());
+                return false;
+            }
+            true
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::SocketAddr, sync::Arc, time::{Duration, Instant}};
+    use crossbeam_channel::unbounded;
+    use crate::{
+        Error,
+        SystemClock,
+        communicator::Communicator,
+        config::Config,
+        constants::SPLIT_PACKET_TIMEOUT,
+        error::ReadError,
+        internal_packet::{InternalOrdering, InternalPacket, InternalReliability, SplitPacketHeader},
+        socket::FakeDatagramSocket,
+    };
+    use super::SplitPacketHandler;
+
+    fn test_setup() -> (SplitPacketHandler, Communicator<FakeDatagramSocket>) {
+        test_setup_with_config(Config::default())
+    }
+
+    fn test_setup_with_config(config: Config) -> (SplitPacketHandler, Communicator<FakeDatagramSocket>) {
+        let local_addr = "127.0.0.2:19132".parse::<SocketAddr>().expect("Could not create address");
+        let remote_addr = "127.0.0.1:19132".parse::<SocketAddr>().expect("Could not create address");
+        let (event_sender, _event_receiver) = unbounded();
+        let communicator = Communicator::new(FakeDatagramSocket::new(local_addr), config, event_sender, Arc::new(SystemClock));
+        (SplitPacketHandler::new(remote_addr, 0x112233, 1492), communicator)
+    }
+
+    fn create_part(split_packet_count: u32, split_packet_index: u32, payload: &[u8]) -> InternalPacket {
+        let header = SplitPacketHeader::new(split_packet_count, 7, split_packet_index);
+        InternalPacket::new(Instant::now(), InternalReliability::Reliable(None), InternalOrdering::None, Some(header), None, payload.to_vec().into_boxed_slice())
+    }
+
+    #[test]
+    fn parts_in_any_order_are_reassembled_in_index_order() {
+        // Arrange
+        let (mut handler, mut communicator) = test_setup();
+        let time = Instant::now();
+
+        // Act
+        let result1 = handler.handle_split_packet(time, create_part(3, 2, &[5, 6]), &mut communicator).expect("Invalid part");
+        let result2 = handler.handle_split_packet(time, create_part(3, 0, &[1, 2]), &mut communicator).expect("Invalid part");
+        let result3 = handler.handle_split_packet(time, create_part(3, 1, &[3, 4]), &mut communicator).expect("Invalid part");
+
+        // Assert
+        assert!(result1.is_none());
+        assert!(result2.is_none());
+        assert_eq!(&[1, 2, 3, 4, 5, 6], result3.expect("Packet was not reassembled").payload());
+    }
+
+    #[test]
+    fn duplicate_part_fails_and_is_ignored() {
+        // Arrange
+        let (mut handler, mut communicator) = test_setup();
+        let time = Instant::now();
+        handler.handle_split_packet(time, create_part(2, 0, &[1, 2]), &mut communicator).expect("Invalid part");
+
+        // Act
+        let duplicate_result = handler.handle_split_packet(time, create_part(2, 0, &[1, 2]), &mut communicator);
+        let result = handler.handle_split_packet(time, create_part(2, 1, &[3]), &mut communicator).expect("Invalid part");
+
+        // Assert
+        assert!(matches!(duplicate_result, Err(Error::ReadError(ReadError::DuplicateSplitPacketIndex))));
+        assert_eq!(&[1, 2, 3], result.expect("Packet was not reassembled").payload());
+    }
+
+    #[test]
+    fn overlapping_part_keeps_first_received_data() {
+        // Arrange
+        let (mut handler, mut communicator) = test_setup();
+        let time = Instant::now();
+        handler.handle_split_packet(time, create_part(2, 1, &[3, 4]), &mut communicator).expect("Invalid part");
+
+        // Act
+        let overlapping_result = handler.handle_split_packet(time, create_part(2, 1, &[9, 9, 9]), &mut communicator);
+        let result = handler.handle_split_packet(time, create_part(2, 0, &[1, 2]), &mut communicator).expect("Invalid part");
+
+        // Assert
+        assert!(overlapping_result.is_err());
+        assert_eq!(&[1, 2, 3, 4], result.expect("Packet was not reassembled").payload());
+    }
+
+    #[test]
+    fn part_with_index_out_of_range_fails() {
+        // Arrange
+        let (mut handler, mut communicator) = test_setup();
+
+        // Act
+        let result = handler.handle_split_packet(Instant::now(), create_part(2, 2, &[1]), &mut communicator);
+
+        // Assert
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn part_with_different_count_fails() {
+        // Arrange
+        let (mut handler, mut communicator) = test_setup();
+        handler.handle_split_packet(Instant::now(), create_part(2, 0, &[1]), &mut communicator).expect("Invalid part");
+
+        // Act
+        let result = handler.handle_split_packet(Instant::now(), create_part(3, 1, &[2]), &mut communicator);
+
+        // Assert
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn split_packet_larger_than_max_payload_size_fails() {
+        // Arrange
+        let config = Config { max_incoming_payload_size: 10, ..Config::default() };
+        let (mut handler, mut communicator) = test_setup_with_config(config);
+        let time = Instant::now();
+
+        // Act
+        let too_large_part_result = handler.handle_split_packet(time, create_part(1, 0, &[0; 11]), &mut communicator);
+        let last_part_result = handler.handle_split_packet(time, create_part(3, 2, &[1, 2, 3, 4]), &mut communicator);
+        let duplicate_result = handler.handle_split_packet(time, create_part(3, 2, &[1, 2, 3, 4]), &mut communicator);
+        let too_many_bytes_result = handler.handle_split_packet(time, create_part(3, 1, &[1, 2, 3, 4, 5, 6, 7]), &mut communicator);
+        let discarded_part_result = handler.handle_split_packet(time, create_part(3, 0, &[1, 2, 3, 4, 5, 6, 7]), &mut communicator);
+        let allowed_result = handler.handle_split_packet(time, create_part(2, 0, &[1, 2, 3, 4, 5]), &mut communicator);
+
+        // Assert
+        assert!(too_large_part_result.is_err());
+        assert!(last_part_result.is_ok());
+        assert!(matches!(duplicate_result, Err(Error::ReadError(ReadError::DuplicateSplitPacketIndex))));
+        assert!(too_many_bytes_result.is_err());
+        assert!(discarded_part_result.expect("Discarded part was not ignored").is_none());
+        assert!(allowed_result.is_ok());
+    }
+
+    #[test]
+    fn stale_split_packet_is_removed_when_new_split_packet_starts() {
+        // Arrange
+        let (mut handler, mut communicator) = test_setup();
+        let time = Instant::now();
+        handler.handle_split_packet(time, create_part(2, 0, &[1, 2]), &mut communicator).expect("Invalid part");
+        let later = time + SPLIT_PACKET_TIMEOUT + Duration::from_millis(1);
+        let other_header = SplitPacketHeader::new(2, 8, 0);
+        let other_part = InternalPacket::new(later, InternalReliability::Reliable(None), InternalOrdering::None, Some(other_header), None, vec![5].into_boxed_slice());
+
+        // Act
+        handler.handle_split_packet(later, other_part, &mut communicator).expect("Invalid part");
+        let result = handler.handle_split_packet(later, create_part(2, 1, &[3]), &mut communicator).expect("Invalid part");
+
+        // Assert
+        assert!(result.is_none());
+        assert_eq!(2, handler.channels.len());
+    }
+}
@@ -0,0 +1,92 @@
+use std::{convert::TryFrom, time::Duration};
+
+/// The time spent in a processing round of a peer and in each of its phases,
+/// measured with the system clock regardless of the `Clock` of the peer.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct TickTimings {
+    /// The time of the whole processing round.
+    pub total: Duration,
+
+    /// The time spent sending queued datagrams and receiving and handling incoming datagrams.
+    pub receive: Duration,
+
+    /// The time spent updating connection attempts and connections, e.g. sending
+    /// queued packets, ACKs and resends.
+    pub update: Duration,
+
+    /// The time spent checking which connections should be dropped.
+    pub drop_scan: Duration,
+}
+
+/// Statistics of the processing rounds of a peer returned by `Peer::tick_stats`,
+/// e.g. for diagnosing stutter in a server.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TickStats {
+    /// The number of processing rounds.
+    pub ticks: u64,
+
+    /// The number of processing rounds longer than `Config::slow_tick_threshold_in_ms`.
+    pub slow_ticks: u64,
+
+    /// The timings of the last processing round.
+    pub last: TickTimings,
+
+    /// The timings of the longest processing round.
+    pub slowest: TickTimings,
+
+    /// The timings of all processing rounds added together.
+    pub sum: TickTimings,
+}
+
+impl TickStats {
+    /// Adds the timings of a processing round.
+    pub(crate) fn add(&mut self, timings: TickTimings, is_slow: bool) {
+        self.ticks += 1;
+        if is_slow {
+            self.slow_ticks += 1;
+        }
+        self.last = timings;
+        if timings.total > self.slowest.total {
+            self.slowest = timings;
+        }
+        self.sum.total += timings.total;
+        self.sum.receive += timings.receive;
+        self.sum.update += timings.update;
+        self.sum.drop_scan += timings.drop_scan;
+    }
+
+    /// Returns the average time of a processing round or zero if there has been none.
+    pub fn average_tick(&self) -> Duration {
+        match u32::try_from(self.ticks) {
+            Ok(0) => Duration::ZERO,
+            Ok(ticks) => self.sum.total / ticks,
+            Err(_) => Duration::from_secs_f64(self.sum.total.as_secs_f64() / self.ticks as f64),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use super::{TickStats, TickTimings};
+
+    #[test]
+    fn add_keeps_last_slowest_and_sum() {
+        // Arrange
+        let mut stats = TickStats::default();
+        let slow = TickTimings { total: Duration::from_millis(30), receive: Duration::from_millis(20), update: Duration::from_millis(5), drop_scan: Duration::from_millis(1) };
+        let fast = TickTimings { total: Duration::from_millis(10), receive: Duration::from_millis(4), update: Duration::from_millis(3), drop_scan: Duration::from_millis(2) };
+
+        // Act
+        stats.add(slow, true);
+        stats.add(fast, false);
+
+        // Assert
+        assert_eq!(2, stats.ticks);
+        assert_eq!(1, stats.slow_ticks);
+        assert_eq!(fast, stats.last);
+        assert_eq!(slow, stats.slowest);
+        assert_eq!(Duration::from_millis(24), stats.sum.receive);
+        assert_eq!(Duration::from_millis(20), stats.average_tick());
+    }
+}
@@ -0,0 +1,94 @@
+use std::{
+    io,
+    net::SocketAddr,
+    sync::Arc,
+};
+
+use tokio::net::{ToSocketAddrs, UdpSocket};
+
+use socket2::SockRef;
+
+use crate::{socket::DatagramSocket, socket_options, SocketOptions};
+
+/// A `DatagramSocket` for a tokio `UdpSocket` so that a peer can run inside
+/// a tokio runtime with `Peer::run` instead of in a dedicated processing thread.
+#[derive(Clone)]
+pub struct TokioUdpSocket {
+    socket: Arc<UdpSocket>,
+}
+
+impl TokioUdpSocket {
+    /// Wraps a tokio `UdpSocket`.
+    pub fn new(socket: UdpSocket) -> Self {
+        TokioUdpSocket {
+            socket: Arc::new(socket),
+        }
+    }
+
+    /// Binds a tokio `UdpSocket` to the specified address.
+    pub async fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        let socket = UdpSocket::bind(addr).await?;
+        socket.set_broadcast(true)?;
+        Ok(Self::new(socket))
+    }
+
+    /// Waits until a datagram may be available for receiving.
+    pub async fn readable(&self) -> io::Result<()> {
+        self.socket.readable().await
+    }
+
+    /// Waits until a datagram may be sent. Sending fails with `io::ErrorKind::WouldBlock`
+    /// until the runtime has registered that the socket is writable.
+    pub async fn writable(&self) -> io::Result<()> {
+        self.socket.writable().await
+    }
+}
+
+impl DatagramSocket for TokioUdpSocket {
+    fn receive_datagram<'a>(&mut self, buf: &'a mut [u8]) -> io::Result<(&'a [u8], SocketAddr)> {
+        self.socket.try_recv_from(buf).map(move |(n, addr)| (&buf[..n], addr))
+    }
+
+    fn send_datagram(&mut self, payload: &[u8], addr: SocketAddr) -> io::Result<usize> {
+        self.socket.try_send_to(payload, addr)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    fn set_options(&mut self, options: &SocketOptions) -> io::Result<()> {
+        socket_options::apply(SockRef::from(&*self.socket), options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use crate::socket::DatagramSocket;
+    use super::TokioUdpSocket;
+
+    #[test]
+    fn datagram_is_sent_and_received() {
+        // Arrange
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_io().build().expect("Could not create runtime");
+        runtime.block_on(async {
+            let mut socket = TokioUdpSocket::bind("127.0.0.1:0").await.expect("Could not bind socket");
+            let mut other_socket = TokioUdpSocket::bind("127.0.0.1:0").await.expect("Could not bind socket");
+            let addr = socket.local_addr().expect("Could not get address");
+            let mut buf = [0u8; 16];
+            let empty_result = socket.receive_datagram(&mut buf).map(|(payload, addr)| (payload.to_vec(), addr));
+            other_socket.writable().await.expect("Socket is not writable");
+
+            // Act
+            other_socket.send_datagram(&[1, 2, 3], addr).expect("Could not send datagram");
+            socket.readable().await.expect("Socket is not readable");
+            let (payload, from) = socket.receive_datagram(&mut buf).expect("Could not receive datagram");
+
+            // Assert
+            assert_eq!(io::ErrorKind::WouldBlock, empty_result.expect_err("Received datagram").kind());
+            assert_eq!(&[1, 2, 3], payload);
+            assert_eq!(other_socket.local_addr().expect("Could not get address"), from);
+        });
+    }
+}
@@ -3,113 +3,61 @@ use std::{
     net::SocketAddr,
 };
 
-use crate::{number::u24, Result, WriteError};
+use crate::{number::u24, socket_addr_format::SocketAddrFormat, Result, WriteError};
 
-pub trait DataWrite {
-    fn write_u8(&mut self, b: u8) -> Result<usize>;
-    fn write_bytes(&mut self, b: &[u8]) -> Result<usize>;
-    fn write_u16(&mut self, us: u16) -> Result<usize>;
-    fn write_u16_be(&mut self, us: u16) -> Result<usize>;
-    fn write_u24(&mut self, value: u24) -> Result<usize>;
-    fn write_u32(&mut self, u: u32) -> Result<usize>;
-    fn write_u32_be(&mut self, u: u32) -> Result<usize>;
-    fn write_u64_be(&mut self, ul: u64) -> Result<usize>;
-    fn write_f32_be(&mut self, value: f32) -> Result<usize>;
-    fn write_fixed_string(&mut self, s: &str) -> Result<usize>;
-    fn write_zero_padding(&mut self, mtu: u16) -> Result<usize>;
-    fn write_socket_addr(&mut self, addr: &SocketAddr) -> Result<usize>;
+/// Implements writing of numbers with the given byte order as default methods of `DataWrite`.
+macro_rules! write_numbers {
+    ($($name:ident: $ty:ty, $to_bytes:ident;)*) => {
+        $(
+            fn $name(&mut self, value: $ty) -> Result<usize> {
+                self.write_bytes(&value.$to_bytes())
+            }
+        )*
+    };
 }
 
-impl<T> DataWrite for T where T: Write {
-    fn write_u8(&mut self, b: u8) -> Result<usize> {
-        let n = self.write(&[b])?;
-        if n != 1 {
-            return Err(WriteError::NotAllBytesWritten(n).into())
-        }
-        Ok(n)
-    }
-
-    fn write_bytes(&mut self, b: &[u8]) -> Result<usize> {
-        let n = self.write(b)?;
-        if n != b.len() {
-            return Err(WriteError::NotAllBytesWritten(n).into())
-        }
-        Ok(n)
-    }
-
-    fn write_u16(&mut self, us: u16) -> Result<usize> {
-        let n = self.write(&us.to_le_bytes())?;
-        if n != 2 {
-            return Err(WriteError::NotAllBytesWritten(n).into())
-        }
-        Ok(n)
-    }
+/// Writes the data types of RakNet messages. Implemented for all `std::io::Write`
+/// types, which provide the writing of raw bytes used by the default methods.
+pub trait DataWrite {
+    fn write_bytes(&mut self, b: &[u8]) -> Result<usize>;
 
-    fn write_u16_be(&mut self, us: u16) -> Result<usize> {
-        let n = self.write(&us.to_be_bytes())?;
-        if n != 2 {
-            return Err(WriteError::NotAllBytesWritten(n).into())
-        }
-        Ok(n)
+    write_numbers! {
+        write_u8: u8, to_le_bytes;
+        write_i8: i8, to_le_bytes;
+        write_u16: u16, to_le_bytes;
+        write_u16_be: u16, to_be_bytes;
+        write_i16: i16, to_le_bytes;
+        write_i16_be: i16, to_be_bytes;
+        write_u32: u32, to_le_bytes;
+        write_u32_be: u32, to_be_bytes;
+        write_i32: i32, to_le_bytes;
+        write_i32_be: i32, to_be_bytes;
+        write_u64: u64, to_le_bytes;
+        write_u64_be: u64, to_be_bytes;
+        write_i64: i64, to_le_bytes;
+        write_i64_be: i64, to_be_bytes;
+        write_f32: f32, to_le_bytes;
+        write_f32_be: f32, to_be_bytes;
+        write_f64: f64, to_le_bytes;
+        write_f64_be: f64, to_be_bytes;
     }
 
     fn write_u24(&mut self, value: u24) -> Result<usize> {
-        let n = self.write(&value.to_le_bytes())?;
-        if n != 3 {
-            return Err(WriteError::NotAllBytesWritten(n).into())
-        }
-        Ok(n)
-    }    
-
-    fn write_u32(&mut self, u: u32) -> Result<usize> {
-        let n = self.write(&u.to_le_bytes())?;
-        if n != 4 {
-            return Err(WriteError::NotAllBytesWritten(n).into())
-        }
-        Ok(n)
-    }    
-
-    fn write_u32_be(&mut self, u: u32) -> Result<usize> {
-        let n = self.write(&u.to_be_bytes())?;
-        if n != 4 {
-            return Err(WriteError::NotAllBytesWritten(n).into())
-        }
-        Ok(n)
-    }    
-
-    fn write_u64_be(&mut self, ul: u64) -> Result<usize> {
-        let n = self.write(&ul.to_be_bytes())?;
-        if n != 8 {
-            return Err(WriteError::NotAllBytesWritten(n).into())
-        }
-        Ok(n)
-    }
-
-    fn write_f32_be(&mut self, value: f32) -> Result<usize> {
-        let n = self.write(&value.to_be_bytes())?;
-        if n != 4 {
-            return Err(WriteError::NotAllBytesWritten(n).into())
-        }
-        Ok(n)
+        self.write_bytes(&value.to_le_bytes())
     }
 
     fn write_fixed_string(&mut self, s: &str) -> Result<usize> {
-        let mut n = self.write_u16_be(s.len() as u16)?;
-        n += self.write(s.as_ref())?;
-        if n != 2 + s.len() {
-            return Err(WriteError::NotAllBytesWritten(n).into())
-        }
-        Ok(n)
+        let n = self.write_u16_be(s.len() as u16)?;
+        Ok(n + self.write_bytes(s.as_ref())?)
     }
 
     fn write_zero_padding(&mut self, mtu: u16) -> Result<usize> {
-        for i in 0..mtu {
-            let n = self.write(&[0x00])?;
-            if n != 1 {
-                return Err(WriteError::NotAllBytesWritten(i as usize + n).into())
-            }    
-        }
-        Ok(mtu as usize)
+        self.write_bytes(&vec![0x00; mtu as usize])
+    }
+
+    /// Returns the format of the socket addresses written by `write_socket_addr`.
+    fn socket_addr_format(&self) -> SocketAddrFormat {
+        SocketAddrFormat::default()
     }
 
     fn write_socket_addr(&mut self, addr: &SocketAddr) -> Result<usize> {
@@ -127,7 +75,7 @@ impl<T> DataWrite for T where T: Write {
             },
             SocketAddr::V6(addr_v6) => {
                 let mut n = self.write_u8(6)?;
-                n += self.write_u16(24)?; // family (little endian): 24=AF_INET6
+                n += self.write_u16(self.socket_addr_format().ipv6_family())?; // family (little endian): AF_INET6
                 n += self.write_u16_be(addr_v6.port())?;
                 n += self.write_u32(addr_v6.flowinfo())?;
                 n += self.write_bytes(&addr_v6.ip().octets())?;
@@ -136,18 +84,108 @@ impl<T> DataWrite for T where T: Write {
             }
         }
     }    
+
+    /// Writes an unsigned VarInt as used by Bedrock, see `DataRead::read_var_u32`.
+    fn write_var_u32(&mut self, value: u32) -> Result<usize> {
+        self.write_var_u64(value as u64)
+    }
+
+    /// Writes a zigzag encoded signed VarInt.
+    fn write_var_i32(&mut self, value: i32) -> Result<usize> {
+        self.write_var_u32(((value << 1) ^ (value >> 31)) as u32)
+    }
+
+    /// Writes an unsigned VarLong.
+    fn write_var_u64(&mut self, value: u64) -> Result<usize> {
+        let mut buf = [0u8; 10];
+        let mut length = 0;
+        let mut value = value;
+        loop {
+            let group = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                buf[length] = group;
+                length += 1;
+                break;
+            }
+            buf[length] = group | 0x80;
+            length += 1;
+        }
+        self.write_bytes(&buf[..length])
+    }
+
+    /// Writes a zigzag encoded signed VarLong.
+    fn write_var_i64(&mut self, value: i64) -> Result<usize> {
+        self.write_var_u64(((value << 1) ^ (value >> 63)) as u64)
+    }
+}
+
+impl<T> DataWrite for T where T: Write {
+    fn write_bytes(&mut self, b: &[u8]) -> Result<usize> {
+        let n = self.write(b)?;
+        if n != b.len() {
+            return Err(WriteError::NotAllBytesWritten(n).into())
+        }
+        Ok(n)
+    }
+}
+
+/// Writes the data types of RakNet messages to a byte vector like the `DataWrite`
+/// implementation of `Vec<u8>`, but with socket addresses in the given format.
+pub struct DataWriter<'a> {
+    buf: &'a mut Vec<u8>,
+    socket_addr_format: SocketAddrFormat,
+}
+
+impl<'a> DataWriter<'a> {
+    pub fn new(buf: &'a mut Vec<u8>, socket_addr_format: SocketAddrFormat) -> DataWriter<'a> {
+        DataWriter { buf, socket_addr_format }
+    }
+}
+
+impl<'a> DataWrite for DataWriter<'a> {
+    fn write_bytes(&mut self, b: &[u8]) -> Result<usize> {
+        self.buf.extend_from_slice(b);
+        Ok(b.len())
+    }
+
+    fn socket_addr_format(&self) -> SocketAddrFormat {
+        self.socket_addr_format
+    }
+}
+
+/// Returns the number of bytes written by `DataWrite::write_socket_addr`.
+pub fn socket_addr_size(addr: &SocketAddr) -> usize {
+    match addr {
+        SocketAddr::V4(_) => 1 + 4 + 2,
+        SocketAddr::V6(_) => 1 + 2 + 2 + 4 + 16 + 4,
+    }
 }
 
 pub trait MessageWrite {
     /// Writes a message including the message identifier.
     fn write_message(&self, writer: &mut dyn DataWrite) -> Result<()>;
+
+    /// Returns the number of bytes `write_message` writes, so that the buffer
+    /// for the message can be allocated once.
+    fn written_size(&self) -> usize;
+}
+
+/// Writes the parts of a datagram that carries packets of a connection,
+/// i.e. the datagram header and the packets.
+pub trait PacketWrite {
+    fn write(&self, writer: &mut dyn DataWrite) -> Result<()>;
+
+    /// Returns the number of bytes `write` writes, so that it can be checked
+    /// whether the part fits into a datagram before it is written.
+    fn written_size(&self) -> usize;
 }
 
 #[cfg(test)]
 mod tests {
     use std::net::{Ipv6Addr, SocketAddr, SocketAddrV6};
 
-    use crate::writer::DataWrite;
+    use crate::{socket_addr_format::SocketAddrFormat, writer::{DataWrite, DataWriter}};
 
     #[test]
     fn write_socket_addr_ipv4() {
@@ -187,4 +225,68 @@ mod tests {
             0x44, 0x33, 0x22, 0x11, // sin6_scope_id (little endian): 0x11223344
             ], buf);
     }    
+
+    #[test]
+    fn write_socket_addr_with_classic_format() {
+        // Arrange
+        let ipv4_addr = SocketAddr::from(([192, 168, 1, 248], 0x1234));
+        let ipv6_addr = SocketAddr::V6(SocketAddrV6::new(Ipv6Addr::LOCALHOST, 0x1234, 0, 0));
+        let mut buf = Vec::new();
+        let mut writer = DataWriter::new(&mut buf, SocketAddrFormat::Classic);
+
+        // Act
+        let bytes_written = writer.write_socket_addr(&ipv4_addr).expect("Could not write SocketAddr")
+            + writer.write_socket_addr(&ipv6_addr).expect("Could not write SocketAddr");
+
+        // Assert
+        assert_eq!(7 + 29, bytes_written);
+        assert_eq!(vec![0x04u8, !192, !168, !1, !248, 0x12, 0x34], buf[..7]);
+        assert_eq!(vec![6u8, 0x0A, 0x00], buf[7..10]); // IP version = 6, sin6_family (little endian): 10=AF_INET6 on Linux
+    }
+
+    #[test]
+    fn write_var_ints() {
+        // Arrange
+        let mut buf = Vec::new();
+
+        // Act
+        let bytes_written = buf.write_var_u32(300).expect("Could not write VarInt")
+            + buf.write_var_i32(-2).expect("Could not write VarInt")
+            + buf.write_var_u32(u32::MAX).expect("Could not write VarInt")
+            + buf.write_var_i64(i64::MIN).expect("Could not write VarLong");
+
+        // Assert
+        assert_eq!(18, bytes_written);
+        assert_eq!(vec![
+            0xAC, 0x02, // 300
+            0x03, // Zigzag: -2
+            0xFF, 0xFF, 0xFF, 0xFF, 0x0F, // u32::MAX
+            0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01, // Zigzag: i64::MIN
+            ], buf);
+    }
+
+    #[test]
+    fn write_numbers_in_both_byte_orders() {
+        // Arrange
+        let mut buf = Vec::new();
+
+        // Act
+        let bytes_written = buf.write_i8(-2).expect("Could not write i8")
+            + buf.write_i16(-2).expect("Could not write i16")
+            + buf.write_i16_be(-2).expect("Could not write i16")
+            + buf.write_u64(0x0102030405060708).expect("Could not write u64")
+            + buf.write_f64(1.0).expect("Could not write f64")
+            + buf.write_f64_be(1.0).expect("Could not write f64");
+
+        // Assert
+        assert_eq!(29, bytes_written);
+        assert_eq!(vec![
+            0xFE, // i8: -2
+            0xFE, 0xFF, // i16 (little endian): -2
+            0xFF, 0xFE, // i16 (big endian): -2
+            0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01, // u64 (little endian)
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xF0, 0x3F, // f64 (little endian): 1.0
+            0x3F, 0xF0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // f64 (big endian): 1.0
+            ], buf);
+    }
 }
\ No newline at end of file
@@ -2,18 +2,20 @@ use std::{io::Read, net::SocketAddr};
 use log::{debug, error};
 use flate2::read::DeflateDecoder;
 
-use crate::{bedrock_reader::BedrockReader, error::Result, utils::to_hex};
+use crate::{bedrock_reader::BedrockReader, error::Result, plugin_channel::PluginChannels, utils::to_hex};
 
 pub struct BedrockPacketHandler {
+    plugin_channels: PluginChannels,
 }
 
 impl BedrockPacketHandler {
-    pub fn new() -> Self {
-        BedrockPacketHandler {            
+    pub fn new(plugin_channels: PluginChannels) -> Self {
+        BedrockPacketHandler {
+            plugin_channels,
         }
     }
 
-    pub fn handle_raknet_packet(&mut self, _addr: SocketAddr, _guid: u64, payload: &[u8]) {
+    pub fn handle_raknet_packet(&mut self, addr: SocketAddr, guid: u64, payload: &[u8]) {
         if payload.len() == 0 {
             error!("Emtpy packet payload");
             return;
@@ -27,7 +29,7 @@ impl BedrockPacketHandler {
                     debug!("Decompressed data, size {}: {}", buf.len(), to_hex(&buf, 40));
                     let mut reader = BedrockReader::new(&buf);
                     while reader.has_more() {
-                        if let Err(err) = self.read_and_handle_bedrock_packet(&mut reader) {
+                        if let Err(err) = self.read_and_handle_bedrock_packet(addr, guid, &mut reader) {
                             error!("Error handling bedrock packet: {:?}", err);
                             return;
                         }
@@ -40,18 +42,19 @@ impl BedrockPacketHandler {
         }
     }
 
-    fn read_and_handle_bedrock_packet(&mut self, reader: &mut BedrockReader) -> Result<()> {
+    fn read_and_handle_bedrock_packet(&mut self, addr: SocketAddr, guid: u64, reader: &mut BedrockReader) -> Result<()> {
         let length = reader.read_varint_to_u32()?;
         let payload = reader.read_bytes_as_slice(length as usize)?;
-        self.handle_bedrock_packet(payload)
+        self.handle_bedrock_packet(addr, guid, payload)
     }
 
-    fn handle_bedrock_packet(&mut self, payload: &[u8]) -> Result<()> {
+    fn handle_bedrock_packet(&mut self, addr: SocketAddr, guid: u64, payload: &[u8]) -> Result<()> {
         let mut reader = BedrockReader::new(payload);
         let id = reader.read_varint_to_u32()?;       
         debug!("Bedrock packet with ID: {} and length (including ID): {}", id, payload.len());
         match id {
             0x01 => self.handle_login_packet(&mut reader)?,
+            0x75 => self.handle_script_custom_event_packet(addr, guid, &mut reader)?,
             _ => error!("Unknown ID: {}", id),
         }
         Ok(())
@@ -74,4 +77,12 @@ impl BedrockPacketHandler {
         Ok(())
     }
 
+    fn handle_script_custom_event_packet(&mut self, addr: SocketAddr, guid: u64, reader: &mut BedrockReader) -> Result<()> {
+        let channel = reader.read_string()?;
+        let data = reader.read_var_bytes_as_slice()?;
+        debug!("Script custom event. Channel: {:?}. Data length: {}", channel, data.len());
+        self.plugin_channels.dispatch(addr, guid, channel, data);
+        Ok(())
+    }
+
 }
\ No newline at end of file
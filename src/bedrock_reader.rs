@@ -31,15 +31,25 @@ impl<'a> BedrockReader<'a> {
         Ok(u32::from_be_bytes(buf))
     }
 
-    pub fn read_bytes_as_slice(&mut self, length: usize) -> Result<&[u8]> {
+    pub fn read_bytes_as_slice(&mut self, length: usize) -> Result<&'a [u8]> {
+        let data: &'a [u8] = self.cursor.get_ref();
         let pos = self.cursor.position() as usize;
-        if length > self.cursor.get_ref().len() - pos {
+        if length > data.len() - pos {
             return Err(Error::NotAllBytesRead);
         }
         self.cursor.set_position((pos + length) as u64);
-        Ok(&self.cursor.get_ref()[pos..pos + length])
+        Ok(&data[pos..pos + length])
     }    
 
+    pub fn read_var_bytes_as_slice(&mut self) -> Result<&'a [u8]> {
+        let length = self.read_varint_to_u32()?;
+        self.read_bytes_as_slice(length as usize)
+    }
+
+    pub fn read_string(&mut self) -> Result<&'a str> {
+        Ok(std::str::from_utf8(self.read_var_bytes_as_slice()?)?)
+    }
+
     pub fn read_varint_to_u32(&mut self) -> Result<u32> {
         let mut result: u32 = 0;
         let mut bits = 0;
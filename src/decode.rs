@@ -0,0 +1,422 @@
+use std::{collections::HashMap, fs, io::{self, Read}, net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr}};
+use flate2::read::DeflateDecoder;
+use raknet::decode::{self, Datagram, DecodedPacket};
+use serde_json::{json, Value};
+
+use crate::{bedrock_reader::BedrockReader, error::{Error, Result}};
+
+const PCAP_MAGIC_LE: [u8; 4] = [0xd4, 0xc3, 0xb2, 0xa1];
+const PCAP_MAGIC_BE: [u8; 4] = [0xa1, 0xb2, 0xc3, 0xd4];
+const PCAP_MAGIC_NANO_LE: [u8; 4] = [0x4d, 0x3c, 0xb2, 0xa1];
+const PCAP_MAGIC_NANO_BE: [u8; 4] = [0xa1, 0xb2, 0x3c, 0x4d];
+
+/// A UDP datagram extracted from the input together with its
+/// source and destination if they are known.
+struct CapturedDatagram {
+    source: Option<SocketAddr>,
+    destination: Option<SocketAddr>,
+    payload: Vec<u8>,
+}
+
+/// Runs the `decode` subcommand.
+///
+/// Usage: `bedroxide decode [FILE]`
+///
+/// The file is either a pcap capture or a text file with one hex encoded
+/// datagram per line, as parsed by `raknet::decode::parse_hex_dump`. If no file (or `-`) is given the input is read from stdin.
+/// Every datagram is printed as one JSON object per line.
+pub fn run(args: &[String]) -> Result<()> {
+    let input = match args.first().map(String::as_str) {
+        None | Some("-") => {
+            let mut buf = Vec::new();
+            io::stdin().read_to_end(&mut buf)?;
+            buf
+        },
+        Some(path) => fs::read(path)?,
+    };
+
+    let datagrams = if is_pcap(&input) {
+        read_pcap(&input)?
+    } else {
+        read_hex_dump(&input)?
+    };
+
+    let mut decoder = Decoder::new();
+    for datagram in datagrams {
+        println!("{}", decoder.decode(&datagram));
+    }
+    Ok(())
+}
+
+fn is_pcap(input: &[u8]) -> bool {
+    input.len() >= 4 && [PCAP_MAGIC_LE, PCAP_MAGIC_BE, PCAP_MAGIC_NANO_LE, PCAP_MAGIC_NANO_BE].iter().any(|magic| input[..4] == magic[..])
+}
+
+fn read_hex_dump(input: &[u8]) -> Result<Vec<CapturedDatagram>> {
+    let text = std::str::from_utf8(input)?;
+    decode::parse_hex_dump(text)
+        .map(|(_, datagram)| match datagram {
+            Ok(payload) => Ok(CapturedDatagram { source: None, destination: None, payload }),
+            Err(line) => Err(Error::InvalidHexDump(line.to_string())),
+        })
+        .collect()
+}
+
+fn read_pcap(input: &[u8]) -> Result<Vec<CapturedDatagram>> {
+    if input.len() < 24 {
+        return Err(Error::InvalidPcap);
+    }
+    let is_little_endian = input[..4] == PCAP_MAGIC_LE || input[..4] == PCAP_MAGIC_NANO_LE;
+    let read_u32 = |bytes: &[u8]| {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        if is_little_endian { u32::from_le_bytes(bytes) } else { u32::from_be_bytes(bytes) }
+    };
+    let link_type = read_u32(&input[20..24]);
+
+    let mut datagrams = Vec::new();
+    let mut pos = 24;
+    while pos + 16 <= input.len() {
+        let captured_length = read_u32(&input[pos + 8..pos + 12]) as usize;
+        pos += 16;
+        if pos + captured_length > input.len() {
+            return Err(Error::InvalidPcap);
+        }
+        let frame = &input[pos..pos + captured_length];
+        pos += captured_length;
+
+        let ip_packet = match link_type {
+            0 | 108 if frame.len() >= 4 => &frame[4..], // BSD loopback
+            1 if frame.len() >= 14 => &frame[14..], // Ethernet
+            101 => frame, // Raw IP
+            113 if frame.len() >= 16 => &frame[16..], // Linux cooked capture
+            _ => continue,
+        };
+        if let Some(datagram) = read_udp_datagram(ip_packet) {
+            datagrams.push(datagram);
+        }
+    }
+    Ok(datagrams)
+}
+
+fn read_udp_datagram(ip_packet: &[u8]) -> Option<CapturedDatagram> {
+    let (source_ip, destination_ip, udp) = match ip_packet.first()? >> 4 {
+        4 => {
+            let header_length = ((ip_packet[0] & 0x0f) as usize) * 4;
+            if ip_packet.len() < header_length || ip_packet.len() < 20 || ip_packet[9] != 17 {
+                return None;
+            }
+            let source = Ipv4Addr::new(ip_packet[12], ip_packet[13], ip_packet[14], ip_packet[15]);
+            let destination = Ipv4Addr::new(ip_packet[16], ip_packet[17], ip_packet[18], ip_packet[19]);
+            (IpAddr::V4(source), IpAddr::V4(destination), &ip_packet[header_length..])
+        },
+        6 => {
+            if ip_packet.len() < 40 || ip_packet[6] != 17 {
+                return None;
+            }
+            let mut source = [0u8; 16];
+            let mut destination = [0u8; 16];
+            source.copy_from_slice(&ip_packet[8..24]);
+            destination.copy_from_slice(&ip_packet[24..40]);
+            (IpAddr::V6(Ipv6Addr::from(source)), IpAddr::V6(Ipv6Addr::from(destination)), &ip_packet[40..])
+        },
+        _ => return None,
+    };
+    if udp.len() < 8 {
+        return None;
+    }
+    let source_port = u16::from_be_bytes([udp[0], udp[1]]);
+    let destination_port = u16::from_be_bytes([udp[2], udp[3]]);
+    let udp_length = (u16::from_be_bytes([udp[4], udp[5]]) as usize).min(udp.len()).max(8);
+    Some(CapturedDatagram {
+        source: Some(SocketAddr::new(source_ip, source_port)),
+        destination: Some(SocketAddr::new(destination_ip, destination_port)),
+        payload: udp[8..udp_length].to_vec(),
+    })
+}
+
+/// Identifies a split packet by the sender address and the split packet ID.
+type SplitPacketKey = (Option<SocketAddr>, u16);
+
+/// Decodes datagrams into JSON and reassembles split packets
+/// over consecutive datagrams.
+struct Decoder {
+    split_packets: HashMap<SplitPacketKey, Vec<Option<Box<[u8]>>>>,
+}
+
+impl Decoder {
+    fn new() -> Self {
+        Decoder {
+            split_packets: HashMap::new(),
+        }
+    }
+
+    fn decode(&mut self, datagram: &CapturedDatagram) -> Value {
+        let mut value = match decode::decode(&datagram.payload) {
+            Ok(Datagram::Offline { message_id, length }) => json!({
+                "type": "offline",
+                "message_id": message_id,
+                "message": decode::message_name(message_id),
+                "length": length,
+            }),
+            Ok(Datagram::Ack { data_arrival_rate, ranges }) => json!({
+                "type": "ack",
+                "data_arrival_rate": data_arrival_rate,
+                "ranges": ranges,
+            }),
+            Ok(Datagram::Nack { ranges }) => json!({
+                "type": "nack",
+                "ranges": ranges,
+            }),
+            Ok(Datagram::Packets { is_packet_pair, is_continuous_send, needs_data_arrival_rate, datagram_number, packets }) => {
+                let packets: Vec<Value> = packets.iter().map(|packet| self.decode_packet(datagram.source, packet)).collect();
+                json!({
+                    "type": "packets",
+                    "datagram_number": datagram_number,
+                    "is_packet_pair": is_packet_pair,
+                    "is_continuous_send": is_continuous_send,
+                    "needs_data_arrival_rate": needs_data_arrival_rate,
+                    "packets": packets,
+                })
+            },
+            Err(err) => json!({
+                "type": "error",
+                "error": err.to_string(),
+                "length": datagram.payload.len(),
+            }),
+        };
+        if let Some(source) = datagram.source {
+            value["source"] = json!(source.to_string());
+        }
+        if let Some(destination) = datagram.destination {
+            value["destination"] = json!(destination.to_string());
+        }
+        value
+    }
+
+    fn decode_packet(&mut self, source: Option<SocketAddr>, packet: &DecodedPacket) -> Value {
+        let mut value = json!({
+            "reliability": packet.reliability_name(),
+            "reliable_message_number": packet.reliable_message_number,
+            "sequencing_index": packet.sequencing_index,
+            "ordering_index": packet.ordering_index,
+            "ordering_channel": packet.ordering_channel_index,
+            "length": packet.payload.len(),
+        });
+        let payload = if let Some(split) = packet.split {
+            value["split"] = json!({ "count": split.count, "id": split.id, "index": split.index });
+            match self.insert_split_packet(source, split.id, split.count, split.index, &packet.payload) {
+                Some(payload) => {
+                    value["reassembled_length"] = json!(payload.len());
+                    payload
+                },
+                None => return value,
+            }
+        } else {
+            packet.payload.to_vec()
+        };
+        if let Some(&message_id) = payload.first() {
+            value["message_id"] = json!(message_id);
+            if message_id == 0xfe {
+                value["game_packets"] = Self::decode_game_packets(&payload[1..]);
+            } else if let Some(name) = decode::message_name(message_id) {
+                value["message"] = json!(name);
+            }
+        }
+        value
+    }
+
+    /// Inserts a part of a split packet and returns the reassembled
+    /// payload once all parts have been received.
+    fn insert_split_packet(&mut self, source: Option<SocketAddr>, id: u16, count: u32, index: u32, payload: &[u8]) -> Option<Vec<u8>> {
+        if index >= count || count > 0xffff {
+            return None;
+        }
+        let parts = self.split_packets.entry((source, id)).or_insert_with(|| vec![None; count as usize]);
+        if let Some(part) = parts.get_mut(index as usize) {
+            *part = Some(payload.into());
+        }
+        if parts.iter().all(Option::is_some) {
+            let parts = self.split_packets.remove(&(source, id))?;
+            Some(parts.into_iter().flatten().flat_map(|part| part.into_vec()).collect())
+        } else {
+            None
+        }
+    }
+
+    fn decode_game_packets(compressed: &[u8]) -> Value {
+        let mut buf = Vec::new();
+        if let Err(err) = DeflateDecoder::new(compressed).read_to_end(&mut buf) {
+            return json!({ "error": format!("Could not decompress: {}", err) });
+        }
+        let mut packets = Vec::new();
+        let mut reader = BedrockReader::new(&buf);
+        while reader.has_more() {
+            match Self::decode_game_packet(&mut reader) {
+                Ok(packet) => packets.push(packet),
+                Err(err) => {
+                    packets.push(json!({ "error": err.to_string() }));
+                    break;
+                },
+            }
+        }
+        json!(packets)
+    }
+
+    fn decode_game_packet(reader: &mut BedrockReader) -> Result<Value> {
+        let payload = reader.read_var_bytes_as_slice()?;
+        let mut packet_reader = BedrockReader::new(payload);
+        let id = packet_reader.read_varint_to_u32()? & 0x3ff;
+        Ok(json!({
+            "id": id,
+            "name": game_packet_name(id),
+            "length": payload.len(),
+        }))
+    }
+}
+
+fn game_packet_name(id: u32) -> Option<&'static str> {
+    let name = match id {
+        0x01 => "Login",
+        0x02 => "PlayStatus",
+        0x03 => "ServerToClientHandshake",
+        0x04 => "ClientToServerHandshake",
+        0x05 => "Disconnect",
+        0x06 => "ResourcePacksInfo",
+        0x07 => "ResourcePackStack",
+        0x08 => "ResourcePackClientResponse",
+        0x09 => "Text",
+        0x0a => "SetTime",
+        0x0b => "StartGame",
+        0x0c => "AddPlayer",
+        0x0d => "AddEntity",
+        0x0e => "RemoveEntity",
+        0x13 => "MovePlayer",
+        0x1d => "UpdateAttributes",
+        0x21 => "Interact",
+        0x24 => "PlayerAction",
+        0x27 => "SetEntityData",
+        0x2d => "Respawn",
+        0x3a => "LevelChunk",
+        0x3b => "SetCommandsEnabled",
+        0x45 => "RequestChunkRadius",
+        0x46 => "ChunkRadiusUpdated",
+        0x4c => "AvailableCommands",
+        0x4d => "CommandRequest",
+        0x75 => "ScriptCustomEvent",
+        0x77 => "AvailableEntityIdentifiers",
+        0x7a => "BiomeDefinitionList",
+        0x81 => "ClientCacheStatus",
+        0x8f => "NetworkSettings",
+        0xc1 => "RequestNetworkSettings",
+        _ => return None,
+    };
+    Some(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use serde_json::json;
+    use super::{is_pcap, read_hex_dump, read_pcap, CapturedDatagram, Decoder};
+
+    #[test]
+    fn read_hex_dump_with_comments_and_separators() {
+        // Arrange
+        let input = b"# Unconnected ping\n0x01, 0x02 0x03\n\n0a0B\n";
+
+        // Act
+        let datagrams = read_hex_dump(input).expect("Could not read hex dump");
+
+        // Assert
+        let payloads: Vec<Vec<u8>> = datagrams.into_iter().map(|datagram| datagram.payload).collect();
+        assert_eq!(vec![vec![0x01, 0x02, 0x03], vec![0x0A, 0x0B]], payloads);
+        assert!(!is_pcap(input));
+    }
+
+    #[test]
+    fn read_invalid_hex_dump_fails() {
+        // Act
+        let result = read_hex_dump(b"01 02\n01 0g\n");
+
+        // Assert
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn read_pcap_with_raw_ip_udp_datagram() {
+        // Arrange
+        let mut input = vec![0xd4, 0xc3, 0xb2, 0xa1, 0x02, 0x00, 0x04, 0x00];
+        input.extend_from_slice(&[0; 12]); // Time zone, accuracy and snapshot length
+        input.extend_from_slice(&101u32.to_le_bytes()); // Link type: Raw IP
+        let ip_packet = [
+            0x45, 0x00, 0x00, 0x1d, 0x00, 0x00, 0x00, 0x00, 0x40, 0x11, 0x00, 0x00, // IPv4 header, protocol UDP
+            127, 0, 0, 1, // Source
+            127, 0, 0, 2, // Destination
+            0x4a, 0xbc, 0x4a, 0xbd, 0x00, 0x09, 0x00, 0x00, // UDP header: 19132 -> 19133, length 9
+            0x01, // Payload
+        ];
+        input.extend_from_slice(&[0; 8]); // Timestamp
+        input.extend_from_slice(&(ip_packet.len() as u32).to_le_bytes());
+        input.extend_from_slice(&(ip_packet.len() as u32).to_le_bytes());
+        input.extend_from_slice(&ip_packet);
+
+        // Act
+        let datagrams = read_pcap(&input).expect("Could not read pcap");
+
+        // Assert
+        assert!(is_pcap(&input));
+        assert_eq!(1, datagrams.len());
+        assert_eq!(Some("127.0.0.1:19132".parse().unwrap()), datagrams[0].source);
+        assert_eq!(Some("127.0.0.2:19133".parse().unwrap()), datagrams[0].destination);
+        assert_eq!(vec![0x01], datagrams[0].payload);
+    }
+
+    #[test]
+    fn decode_offline_message() {
+        // Arrange
+        let source: SocketAddr = "127.0.0.1:19132".parse().unwrap();
+        let datagram = CapturedDatagram { source: Some(source), destination: None, payload: vec![0x05, 0x00, 0xFF] };
+        let mut decoder = Decoder::new();
+
+        // Act
+        let value = decoder.decode(&datagram);
+
+        // Assert
+        assert_eq!(json!({
+            "type": "offline",
+            "message_id": 5,
+            "message": "OpenConnectionRequest1",
+            "length": 3,
+            "source": "127.0.0.1:19132",
+        }), value);
+    }
+
+    #[test]
+    fn decode_reassembles_split_packets() {
+        // Arrange
+        let split_datagram = |datagram_number: u8, index: u8, payload: u8| CapturedDatagram {
+            source: None,
+            destination: None,
+            payload: vec![
+                0x84, datagram_number, 0x00, 0x00, // Bitflags and datagram number
+                0x50, 0x00, 0x08, // Reliable with split flag, 8 bits of payload
+                datagram_number, 0x00, 0x00, // Reliable message number
+                0x00, 0x00, 0x00, 0x02, // Split packet count
+                0x00, 0x07, // Split packet ID
+                0x00, 0x00, 0x00, index, // Split packet index
+                payload,
+            ],
+        };
+        let mut decoder = Decoder::new();
+
+        // Act
+        let first = decoder.decode(&split_datagram(0, 0, 0x13));
+        let second = decoder.decode(&split_datagram(1, 1, 0x37));
+
+        // Assert
+        assert_eq!(json!(null), first["packets"][0]["reassembled_length"]);
+        assert_eq!(json!(2), second["packets"][0]["reassembled_length"]);
+        assert_eq!(json!(0x13), second["packets"][0]["message_id"]);
+        assert_eq!(json!({ "count": 2, "id": 7, "index": 1 }), second["packets"][0]["split"]);
+    }
+}
@@ -17,6 +17,21 @@ pub enum Error {
     VarIntTooLarge,
     /// Not all bytes could be read.
     NotAllBytesRead,
+    /// The plugin channel ID is not a valid namespaced ID.
+    InvalidPluginChannel(String),
+    /// A handler is already registered for the plugin channel.
+    PluginChannelAlreadyRegistered(String),
+    /// A line in a hex dump could not be parsed.
+    InvalidHexDump(String),
+    /// A pcap file could not be parsed.
+    InvalidPcap,
+    /// A field of the MOTD cannot be written in the ping response format.
+    InvalidMotd(String),
+    /// The ban file could not be loaded or saved.
+    InvalidBanFile(String),
+    /// The settings file or the command line could not be parsed or contains invalid settings.
+    #[cfg(any(feature = "serde", feature = "metrics"))]
+    InvalidSettings(String),
 }
 
 impl std::error::Error for Error {}
@@ -30,6 +45,14 @@ impl fmt::Display for Error {
             Error::RakNetError(e) => write!(f, "A RakNet error occurred: {:?}", e),
             Error::VarIntTooLarge => write!(f, "The VarInt number was too large to fit into the desired type."),
             Error::NotAllBytesRead => write!(f, "Not all bytes could be read."),
+            Error::InvalidPluginChannel(s) => write!(f, "Invalid plugin channel ID: {:?}", s),
+            Error::PluginChannelAlreadyRegistered(s) => write!(f, "A handler is already registered for plugin channel: {:?}", s),
+            Error::InvalidHexDump(s) => write!(f, "Invalid hex dump line: {:?}", s),
+            Error::InvalidPcap => write!(f, "The pcap file is truncated or invalid."),
+            Error::InvalidMotd(s) => write!(f, "Invalid MOTD: {}", s),
+            Error::InvalidBanFile(s) => write!(f, "Invalid ban file: {}", s),
+            #[cfg(any(feature = "serde", feature = "metrics"))]
+            Error::InvalidSettings(s) => write!(f, "Invalid settings: {}", s),
         }
     }
 }
@@ -1,15 +1,26 @@
-use std::{io, fs::File, net::SocketAddr};
+use std::{env, io, fs::File};
 use simplelog::{SimpleLogger, WriteLogger, LevelFilter, Config, CombinedLogger};
 
-use crate::{error::Result, server::Server};
+use crate::{error::Result, plugin_channel::PluginChannels, server::Server, settings::Settings};
 
 mod bedrock_packet_handler;
 mod bedrock_reader;
+mod decode;
 mod error;
+#[cfg(feature = "metrics")]
+mod metrics;
+mod motd;
+mod plugin_channel;
 mod server;
+mod settings;
 mod utils;
 
 fn main() -> Result<()> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("decode") {
+        return decode::run(&args[1..]);
+    }
+
     CombinedLogger::init(
         vec![
             SimpleLogger::new(LevelFilter::Debug, Config::default()),
@@ -17,7 +28,10 @@ fn main() -> Result<()> {
         ]
     ).unwrap();
 
-    let server = Server::start(SocketAddr::from(([0, 0, 0, 0], 19132)))?;
+    let settings = load_settings()?;
+    #[cfg(feature = "metrics")]
+    let settings = settings.with_overrides(&args, |name| env::var(name).ok())?;
+    let server = Server::start(settings, PluginChannels::new())?;
 
     // Wait for ENTER to kill server
     let mut buffer = String::new();
@@ -28,3 +42,18 @@ fn main() -> Result<()> {
     Ok(())
 }
 
+/// Returns the settings in `bedroxide.toml` or the default settings if there is no such file.
+#[cfg(feature = "serde")]
+fn load_settings() -> Result<Settings> {
+    const SETTINGS_PATH: &str = "bedroxide.toml";
+    if std::path::Path::new(SETTINGS_PATH).exists() {
+        Settings::from_path(SETTINGS_PATH)
+    } else {
+        Ok(Settings::default())
+    }
+}
+
+#[cfg(not(feature = "serde"))]
+fn load_settings() -> Result<Settings> {
+    Ok(Settings::default())
+}
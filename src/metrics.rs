@@ -0,0 +1,56 @@
+use std::{io::{Read, Write}, net::{SocketAddr, TcpListener, TcpStream}, thread, time::Duration};
+use log::{debug, error, info};
+use raknet::{channel::{bounded, Sender}, Command};
+
+use crate::error::Result;
+
+/// The time to wait for the RakNet thread to answer a metrics query.
+const QUERY_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Starts a thread that serves the RakNet metrics in the Prometheus text format over HTTP
+/// on the address, so that the server can be scraped by Prometheus. Every request is
+/// answered with the metrics regardless of its path.
+///
+/// The thread runs until the program exits and serves each request on a thread of its own,
+/// so that a slow client does not delay other scrapes. After the RakNet thread has stopped
+/// requests are answered with `503 Service Unavailable`.
+pub fn start_exporter(addr: SocketAddr, command_sender: Sender<Command>) -> Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    info!("Serving metrics on {}", listener.local_addr()?);
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let command_sender = command_sender.clone();
+                    thread::spawn(move || {
+                        if let Err(err) = serve(stream, &command_sender) {
+                            debug!("Could not serve metrics: {:?}", err);
+                        }
+                    });
+                },
+                Err(err) => error!("Could not accept metrics connection: {:?}", err),
+            }
+        }
+    });
+    Ok(())
+}
+
+fn serve(mut stream: TcpStream, command_sender: &Sender<Command>) -> Result<()> {
+    // The request is not parsed, but read so that the client does not see a reset connection
+    stream.set_read_timeout(Some(QUERY_TIMEOUT))?;
+    let mut request = [0u8; 1024];
+    let _ = stream.read(&mut request)?;
+
+    let (reply_sender, reply_receiver) = bounded(1);
+    let metrics = command_sender.send(Command::QueryMetrics { reply_sender }).ok()
+        .and_then(|()| reply_receiver.recv_timeout(QUERY_TIMEOUT).ok());
+    let response = match metrics {
+        Some(metrics) => {
+            let body = metrics.to_prometheus();
+            format!("HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body)
+        },
+        None => "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+    };
+    stream.write_all(response.as_bytes())?;
+    Ok(())
+}
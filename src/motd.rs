@@ -0,0 +1,161 @@
+use std::fmt;
+use raknet::DataWrite;
+
+use crate::error::{Error, Result};
+
+/// The server information shown in the server list of Bedrock clients,
+/// sent as the response to offline pings in the semicolon separated format
+/// `MCPE;name;protocol;version;players;max players;guid;sub name;game mode;game mode id;IPv4 port;IPv6 port;`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BedrockMotd {
+    /// The edition, `MCPE` for Bedrock Edition or `MCEE` for Education Edition.
+    pub edition: String,
+    /// The first line of the server name.
+    pub name: String,
+    /// The network protocol version of the server.
+    pub protocol: u32,
+    /// The game version of the server, e.g. `1.14.60`.
+    pub version: String,
+    /// The number of players currently online.
+    pub player_count: u32,
+    /// The maximum number of players.
+    pub max_player_count: u32,
+    /// The RakNet GUID of the server.
+    pub server_guid: u64,
+    /// The second line of the server name.
+    pub sub_name: String,
+    /// The name of the game mode, e.g. `Survival`.
+    pub game_mode: String,
+    /// The numeric ID of the game mode.
+    pub game_mode_id: u8,
+    /// The port that the server listens on for IPv4.
+    pub port_v4: u16,
+    /// The port that the server listens on for IPv6.
+    pub port_v6: u16,
+}
+
+impl BedrockMotd {
+    /// Returns an error if a text field contains a semicolon, which would break the
+    /// format, or if the edition or version is empty.
+    pub fn validate(&self) -> Result<()> {
+        if self.edition.is_empty() {
+            return Err(Error::InvalidMotd("the edition is empty".to_string()));
+        }
+        if self.version.is_empty() {
+            return Err(Error::InvalidMotd("the version is empty".to_string()));
+        }
+        let text_fields = [
+            ("edition", &self.edition),
+            ("name", &self.name),
+            ("version", &self.version),
+            ("sub name", &self.sub_name),
+            ("game mode", &self.game_mode),
+        ];
+        for (field, value) in text_fields.iter() {
+            if value.contains(';') {
+                return Err(Error::InvalidMotd(format!("the {} contains a semicolon: {:?}", field, value)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Validates the MOTD and returns it as the response to offline pings, see `Peer::set_offline_ping_response`.
+    pub fn to_ping_response(&self) -> Result<Vec<u8>> {
+        self.validate()?;
+        let mut ping_response = Vec::new();
+        ping_response.write_fixed_string(&self.to_string())?;
+        Ok(ping_response)
+    }
+}
+
+impl Default for BedrockMotd {
+    fn default() -> Self {
+        BedrockMotd {
+            edition: "MCPE".to_string(),
+            name: "Bedroxide server".to_string(),
+            protocol: 390,
+            version: "1.14.60".to_string(),
+            player_count: 5,
+            max_player_count: 10,
+            server_guid: 0,
+            sub_name: "Second row".to_string(),
+            game_mode: "Survival".to_string(),
+            game_mode_id: 1,
+            port_v4: 19132,
+            port_v6: 19133,
+        }
+    }
+}
+
+impl fmt::Display for BedrockMotd {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{};{};{};{};{};{};{};{};{};{};{};{};",
+            self.edition,
+            self.name,
+            self.protocol,
+            self.version,
+            self.player_count,
+            self.max_player_count,
+            self.server_guid,
+            self.sub_name,
+            self.game_mode,
+            self.game_mode_id,
+            self.port_v4,
+            self.port_v6)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Error;
+    use super::BedrockMotd;
+
+    #[test]
+    fn motd_is_formatted_as_ping_response() {
+        // Arrange
+        let motd = BedrockMotd {
+            server_guid: 13253860892328930977,
+            ..BedrockMotd::default()
+        };
+
+        // Act
+        let ping_response = motd.to_ping_response().expect("Could not create ping response");
+
+        // Assert
+        let expected = "MCPE;Bedroxide server;390;1.14.60;5;10;13253860892328930977;Second row;Survival;1;19132;19133;";
+        assert_eq!(expected, motd.to_string());
+        assert_eq!((expected.len() as u16).to_be_bytes(), ping_response[..2]);
+        assert_eq!(expected.as_bytes(), &ping_response[2..]);
+    }
+
+    #[test]
+    fn motd_with_semicolon_is_invalid() {
+        // Arrange
+        let motd = BedrockMotd {
+            name: "Bedroxide; server".to_string(),
+            ..BedrockMotd::default()
+        };
+
+        // Act
+        let result = motd.to_ping_response();
+
+        // Assert
+        assert!(matches!(result, Err(Error::InvalidMotd(_))));
+    }
+
+    #[test]
+    fn motd_without_edition_or_version_is_invalid() {
+        // Arrange
+        let without_edition = BedrockMotd { edition: String::new(), ..BedrockMotd::default() };
+        let without_version = BedrockMotd { version: String::new(), ..BedrockMotd::default() };
+
+        // Act
+        let edition_result = without_edition.validate();
+        let version_result = without_version.validate();
+
+        // Assert
+        assert!(matches!(edition_result, Err(Error::InvalidMotd(_))));
+        assert!(matches!(version_result, Err(Error::InvalidMotd(_))));
+        assert!(BedrockMotd::default().validate().is_ok());
+    }
+}
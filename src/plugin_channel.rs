@@ -0,0 +1,178 @@
+use std::{collections::HashMap, io::Write, net::SocketAddr};
+use flate2::{write::DeflateEncoder, Compression};
+use log::debug;
+use raknet::{channel::Sender, Command, DataWrite, Ordering, Priority, Reliability, SendOptions, SendTarget};
+
+use crate::error::{Error, Result};
+
+/// The game packet ID of `ScriptCustomEvent`.
+const SCRIPT_CUSTOM_EVENT_PACKET_ID: u32 = 0x75;
+
+/// The channel where the server replies with its version to any message.
+pub const VERSION_CHANNEL: &str = "bedroxide:version";
+
+/// A handler that is called with the address and GUID of the sender
+/// and the data for every message received on a plugin channel.
+pub type PluginChannelHandler = Box<dyn FnMut(SocketAddr, u64, &[u8]) + Send>;
+
+/// A registry of namespaced plugin messaging channels.
+///
+/// Cooperating client mods and companion apps send messages to the server
+/// using the `ScriptCustomEvent` game packet where the event name is the
+/// namespaced channel ID, e.g. `myplugin:teleport`, and the event data is
+/// the channel payload.
+pub struct PluginChannels {
+    handlers: HashMap<String, PluginChannelHandler>,
+}
+
+impl PluginChannels {
+    pub fn new() -> Self {
+        PluginChannels {
+            handlers: HashMap::new(),
+        }
+    }
+
+    /// Registers a handler for the specified channel.
+    ///
+    /// The channel ID must be on the form `namespace:path` where both parts
+    /// are non-empty and only contain lowercase letters, digits, `_`, `-`, `.` or `/`.
+    pub fn register<F>(&mut self, channel: &str, handler: F) -> Result<()>
+        where F: FnMut(SocketAddr, u64, &[u8]) + Send + 'static
+    {
+        if !Self::is_valid_channel_id(channel) {
+            return Err(Error::InvalidPluginChannel(channel.to_string()));
+        }
+        if self.handlers.contains_key(channel) {
+            return Err(Error::PluginChannelAlreadyRegistered(channel.to_string()));
+        }
+        self.handlers.insert(channel.to_string(), Box::new(handler));
+        Ok(())
+    }
+
+    /// Dispatches a received message to the handler registered for the channel.
+    /// Returns true if the message was handled.
+    pub fn dispatch(&mut self, addr: SocketAddr, guid: u64, channel: &str, data: &[u8]) -> bool {
+        if let Some(handler) = self.handlers.get_mut(channel) {
+            handler(addr, guid, data);
+            true
+        } else {
+            debug!("No handler registered for plugin channel {:?}", channel);
+            false
+        }
+    }
+
+    fn is_valid_channel_id(channel: &str) -> bool {
+        let is_valid_char = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit() || "_-./".contains(c);
+        match channel.split_once(':') {
+            Some((namespace, path)) =>
+                !namespace.is_empty() && !path.is_empty() &&
+                namespace.chars().all(|c| is_valid_char(c) && c != '/') &&
+                path.chars().all(is_valid_char),
+            None => false,
+        }
+    }
+}
+
+/// Sends messages on plugin channels to connected clients.
+#[derive(Clone)]
+pub struct PluginMessageSender {
+    command_sender: Sender<Command>,
+}
+
+impl PluginMessageSender {
+    pub fn new(command_sender: Sender<Command>) -> Self {
+        PluginMessageSender {
+            command_sender,
+        }
+    }
+
+    /// Sends a message on the channel to the client with the GUID.
+    pub fn send(&self, guid: u64, channel: &str, data: &[u8]) -> Result<()> {
+        let payload = encode_message(channel, data)?;
+        self.command_sender.send(Command::Send {
+            target: SendTarget::Guid(guid),
+            options: SendOptions::new(Priority::Medium, Reliability::Reliable, Ordering::Ordered(0)),
+            payload,
+        })?;
+        Ok(())
+    }
+}
+
+/// Returns the RakNet payload of a compressed batch with a `ScriptCustomEvent`
+/// game packet carrying the message on the channel.
+fn encode_message(channel: &str, data: &[u8]) -> Result<Vec<u8>> {
+    let mut packet = Vec::new();
+    packet.write_var_u32(SCRIPT_CUSTOM_EVENT_PACKET_ID)?;
+    packet.write_var_u32(channel.len() as u32)?;
+    packet.write_bytes(channel.as_bytes())?;
+    packet.write_var_u32(data.len() as u32)?;
+    packet.write_bytes(data)?;
+
+    let mut encoder = DeflateEncoder::new(vec![0xfe], Compression::default());
+    let mut length = Vec::new();
+    length.write_var_u32(packet.len() as u32)?;
+    encoder.write_all(&length)?;
+    encoder.write_all(&packet)?;
+    Ok(encoder.finish()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Read, net::SocketAddr, sync::{Arc, Mutex}};
+    use flate2::read::DeflateDecoder;
+
+    use crate::{bedrock_reader::BedrockReader, error::Error};
+    use super::{encode_message, PluginChannels};
+
+    #[test]
+    fn register_rejects_invalid_and_duplicate_channels() {
+        // Arrange
+        let mut plugin_channels = PluginChannels::new();
+
+        // Act/Assert
+        assert!(plugin_channels.register("myplugin:teleport", |_, _, _| {}).is_ok());
+        assert!(matches!(plugin_channels.register("myplugin:teleport", |_, _, _| {}), Err(Error::PluginChannelAlreadyRegistered(_))));
+        assert!(matches!(plugin_channels.register("teleport", |_, _, _| {}), Err(Error::InvalidPluginChannel(_))));
+        assert!(matches!(plugin_channels.register("MyPlugin:teleport", |_, _, _| {}), Err(Error::InvalidPluginChannel(_))));
+        assert!(matches!(plugin_channels.register("my/plugin:teleport", |_, _, _| {}), Err(Error::InvalidPluginChannel(_))));
+        assert!(matches!(plugin_channels.register(":teleport", |_, _, _| {}), Err(Error::InvalidPluginChannel(_))));
+    }
+
+    #[test]
+    fn dispatch_calls_handler_of_channel() {
+        // Arrange
+        let addr: SocketAddr = "127.0.0.1:19132".parse().expect("Could not parse address");
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let handler_received = received.clone();
+        let mut plugin_channels = PluginChannels::new();
+        plugin_channels.register("myplugin:teleport", move |addr, guid, data: &[u8]| {
+            handler_received.lock().unwrap().push((addr, guid, data.to_vec()));
+        }).expect("Could not register channel");
+
+        // Act
+        let handled = plugin_channels.dispatch(addr, 42, "myplugin:teleport", &[0x01, 0x02]);
+        let unhandled = plugin_channels.dispatch(addr, 42, "myplugin:other", &[0x03]);
+
+        // Assert
+        assert!(handled);
+        assert!(!unhandled);
+        assert_eq!(vec![(addr, 42, vec![0x01, 0x02])], *received.lock().unwrap());
+    }
+
+    #[test]
+    fn encode_message_creates_script_custom_event() {
+        // Act
+        let payload = encode_message("myplugin:teleport", &[0x01, 0x02]).expect("Could not encode message");
+
+        // Assert
+        assert_eq!(0xfe, payload[0]);
+        let mut batch = Vec::new();
+        DeflateDecoder::new(&payload[1..]).read_to_end(&mut batch).expect("Could not decompress batch");
+        let mut reader = BedrockReader::new(&batch);
+        let length = reader.read_varint_to_u32().expect("Could not read length");
+        let mut packet = BedrockReader::new(reader.read_bytes_as_slice(length as usize).expect("Could not read packet"));
+        assert_eq!(0x75, packet.read_varint_to_u32().expect("Could not read ID"));
+        assert_eq!("myplugin:teleport", packet.read_string().expect("Could not read channel"));
+        assert_eq!(&[0x01, 0x02], packet.read_var_bytes_as_slice().expect("Could not read data"));
+    }
+}
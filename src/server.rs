@@ -1,24 +1,48 @@
-use std::{net::SocketAddr, thread};
-use log::{debug, error, info};
-use raknet::{channel::Sender, Peer, PeerEvent, Command, DataWrite};
+use std::{path::PathBuf, thread};
+use log::{debug, error, info, warn};
+use raknet::{channel::{bounded, Sender}, BanFile, Peer, PeerEvent, Command};
 
-use crate::{bedrock_packet_handler::BedrockPacketHandler, error::Result};
+use crate::{bedrock_packet_handler::BedrockPacketHandler, error::{Error, Result}, motd::BedrockMotd, plugin_channel::{PluginChannels, PluginMessageSender, VERSION_CHANNEL}, settings::Settings};
 
 pub struct Server {
     raknet_thread: thread::JoinHandle<()>,
     event_receiver_thread: thread::JoinHandle<()>,
     command_sender: Sender<Command>,
+    ban_path: Option<PathBuf>,
 }
 
 impl Server {
-    pub fn start(addr: SocketAddr) -> Result<Self> {
-        let mut peer = Peer::bind(addr)?;
-        let mut ping_response = Vec::new();
-        ping_response.write_fixed_string("MCPE;Bedroxide server;390;1.14.60;5;10;13253860892328930977;Second row;Survival;1;19132;19133;").expect("Could not write ping response");
-        peer.set_offline_ping_response(ping_response);
+    pub fn start(settings: Settings, mut plugin_channels: PluginChannels) -> Result<Self> {
+        let config = settings.raknet_config()?;
+        let motd = BedrockMotd {
+            server_guid: config.guid,
+            port_v4: settings.addr.port(),
+            ..BedrockMotd::default()
+        };
+        let mut peer = Peer::bind_with_config(settings.addr, config)?;
+        peer.set_offline_ping_response(motd.to_ping_response()?);
+        if let Some(capture_path) = &settings.capture_path {
+            peer.capture_to_file(capture_path)?;
+            info!("Capturing datagrams to {}", capture_path.display());
+        }
+        if let Some(ban_path) = settings.ban_path.as_ref().filter(|ban_path| ban_path.exists()) {
+            let ban_file = BanFile::from_path(ban_path).map_err(|err| Error::InvalidBanFile(err.to_string()))?;
+            let ban_count = peer.add_bans(&ban_file);
+            info!("Loaded {} bans from {}", ban_count, ban_path.display());
+        }
         let command_sender = peer.command_sender();
+        #[cfg(feature = "metrics")]
+        if let Some(metrics_addr) = settings.metrics_addr {
+            crate::metrics::start_exporter(metrics_addr, command_sender.clone())?;
+        }
+        let plugin_message_sender = PluginMessageSender::new(command_sender.clone());
+        plugin_channels.register(VERSION_CHANNEL, move |_addr, guid, _data| {
+            if let Err(err) = plugin_message_sender.send(guid, VERSION_CHANNEL, env!("CARGO_PKG_VERSION").as_bytes()) {
+                error!("Could not reply on plugin channel {}: {}", VERSION_CHANNEL, err);
+            }
+        })?;
         let event_receiver = peer.event_receiver();
-        let mut packet_handler = BedrockPacketHandler::new();
+        let mut packet_handler = BedrockPacketHandler::new(plugin_channels);
         let event_receiver_thread = thread::spawn(move || {
             loop {
                 match event_receiver.recv() {
@@ -34,7 +58,58 @@ impl Server {
                     }
                     Ok(PeerEvent::IncomingConnection(connection)) => {
                         info!("Incoming connection on addr: {:?}, guid: {}", connection.addr(), connection.guid());
-                    }       
+                    }
+                    Ok(PeerEvent::ConnectionEstablished { addr, guid, external_addr, .. }) => {
+                        info!("Connection established to addr: {:?}, guid: {}, external addr: {:?}", addr, guid, external_addr);
+                    }
+                    Ok(PeerEvent::ConnectionAttemptFailed { addr, reason, .. }) => {
+                        info!("Connection attempt to addr: {:?} failed: {}", addr, reason);
+                    }
+                    Ok(PeerEvent::ConnectionLost { addr, guid, reason }) => {
+                        info!("Connection lost to addr: {:?}, guid: {}: {}", addr, guid, reason);
+                    }
+                    Ok(PeerEvent::ConnectionClosed { addr, guid, reason }) => {
+                        info!("Connection closed to addr: {:?}, guid: {}: {}", addr, guid, reason);
+                    }
+                    Ok(PeerEvent::PingUpdated { addr, guid, ping_ms, .. }) => {
+                        debug!("Ping to addr: {:?}, guid: {} is {} ms", addr, guid, ping_ms);
+                    }
+                    Ok(PeerEvent::ConnectionQuality { addr, guid, packet_loss, ping_ms }) => {
+                        debug!("Connection quality of addr: {:?}, guid: {}: {:.1}% packet loss, ping: {:?} ms", addr, guid, packet_loss, ping_ms);
+                    }
+                    Ok(PeerEvent::UnconnectedPong { addr, guid, rtt, data, .. }) => {
+                        debug!("Received unconnected pong from addr: {:?}, guid: {} after {:?} with {} bytes of data", addr, guid, rtt, data.len());
+                    }
+                    Ok(PeerEvent::OutOfBandMessage { addr, guid, data, .. }) => {
+                        debug!("Received out of band message from addr: {:?}, guid: {} with {} bytes of data", addr, guid, data.len());
+                    }
+                    Ok(PeerEvent::Connections(connections)) => {
+                        info!("{} connections", connections.len());
+                    }
+                    Ok(PeerEvent::MtuReduced { addr, guid, old_mtu, new_mtu }) => {
+                        info!("MTU reduced from {} to {} for addr: {:?}, guid: {}", old_mtu, new_mtu, addr, guid);
+                    }
+                    Ok(PeerEvent::SplitPacketProgress { addr, guid, split_id, received, total, is_incoming }) => {
+                        debug!("Split packet {} progress for addr: {:?}, guid: {}: {}/{} (incoming: {})", split_id, addr, guid, received, total, is_incoming);
+                    }
+                    Ok(PeerEvent::SecurityEvent { addr, kind, .. }) => {
+                        info!("Security event from addr: {:?}: {}", addr, kind);
+                    }
+                    Ok(PeerEvent::ModerationAction { addr, action, rule, .. }) => {
+                        info!("Moderation action [{}/{}] for addr: {:?}: {} ({})", action.code(), rule.code(), addr, action, rule);
+                    }
+                    Ok(PeerEvent::OrderingChannelBlocked { addr, guid, channel, buffered_packets, blocked_for }) => {
+                        debug!("Ordering channel {} of addr: {:?}, guid: {} blocked for {:?} with {} buffered packets", channel, addr, guid, blocked_for, buffered_packets);
+                    }
+                    Ok(PeerEvent::AddressGreylisted { ip, reason, duration }) => {
+                        info!("Greylisted {} for {:?}: {}", ip, duration, reason);
+                    }
+                    Ok(PeerEvent::GreylistExpired { ip }) => {
+                        debug!("Greylisting of {} expired", ip);
+                    }
+                    Ok(PeerEvent::SlowTick(timings)) => {
+                        warn!("RakNet processing took {:?} (receive: {:?}, update: {:?}, drop scan: {:?})", timings.total, timings.receive, timings.update, timings.drop_scan);
+                    }
                     Err(_) => {
                         info!("Stopping event receiver thread");
                         break;
@@ -47,11 +122,24 @@ impl Server {
             raknet_thread,
             event_receiver_thread,
             command_sender,
+            ban_path: settings.ban_path,
         })
     }
 
     pub fn shutdown(self) -> Result<()> {
         info!("Shutting down server");
+
+        if let Some(ban_path) = &self.ban_path {
+            let (reply_sender, reply_receiver) = bounded(1);
+            self.command_sender.send(Command::QueryBans { reply_sender })?;
+            match reply_receiver.recv() {
+                Ok(ban_file) => match ban_file.save(ban_path) {
+                    Ok(()) => info!("Saved {} bans to {}", ban_file.bans.len(), ban_path.display()),
+                    Err(err) => error!("Could not save bans to {}: {}", ban_path.display(), err),
+                },
+                Err(_) => error!("Could not query the bans to save"),
+            }
+        }
     
         self.command_sender.send(Command::StopProcessing)?;
     
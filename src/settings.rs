@@ -0,0 +1,141 @@
+use std::{net::SocketAddr, path::PathBuf};
+#[cfg(feature = "serde")]
+use std::{fs, path::Path};
+
+use crate::error::Result;
+#[cfg(any(feature = "serde", feature = "metrics"))]
+use crate::error::Error;
+
+/// The environment variable that sets `Settings::metrics_addr`.
+#[cfg(feature = "metrics")]
+const METRICS_ADDR_VARIABLE: &str = "BEDROXIDE_METRICS_ADDR";
+
+/// The settings of the server. With the `serde` feature they are loaded
+/// from `bedroxide.toml` if the file exists.
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize), serde(default))]
+pub struct Settings {
+    /// The address that the server listens on.
+    pub addr: SocketAddr,
+    /// The GUID of the server. If not set the GUID stored in `guid_path` is used
+    /// and the GUID in the RakNet configuration is ignored.
+    pub guid: Option<u64>,
+    /// The file that the server GUID is stored in so that clients see the same
+    /// server after a restart. It is created with a random GUID if it does not exist.
+    pub guid_path: PathBuf,
+    /// The pcap file that every datagram sent or received is written to, e.g. for
+    /// debugging problems with Bedrock clients. Nothing is captured if not set.
+    pub capture_path: Option<PathBuf>,
+    /// The file that the bans are loaded from at start and saved to at shutdown so
+    /// that they survive a restart. It is a JSON or TOML file if it has that extension
+    /// and an IP list with one address or range per line otherwise. Not used if not set.
+    pub ban_path: Option<PathBuf>,
+    /// The address that the RakNet metrics are served on in the Prometheus format,
+    /// e.g. `127.0.0.1:9100`. The metrics are not served if not set. Can also be set
+    /// with `--metrics-addr <addr>` or the `BEDROXIDE_METRICS_ADDR` environment variable.
+    #[cfg(feature = "metrics")]
+    pub metrics_addr: Option<SocketAddr>,
+    /// The RakNet configuration, e.g. timeouts, MTU sizes and the maximum number of connections.
+    pub raknet: raknet::Config,
+}
+
+impl Settings {
+    /// Loads the settings from a TOML file. Settings missing in the file get their default values.
+    #[cfg(feature = "serde")]
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Settings> {
+        let contents = fs::read_to_string(path)?;
+        let settings: Settings = toml::from_str(&contents).map_err(|err| Error::InvalidSettings(err.to_string()))?;
+        settings.raknet.validate().map_err(|err| Error::InvalidSettings(err.to_string()))?;
+        Ok(settings)
+    }
+
+    /// Returns the settings with the values given on the command line, e.g. `--metrics-addr
+    /// 127.0.0.1:9100`, or in environment variables, e.g. `BEDROXIDE_METRICS_ADDR`. Command line
+    /// arguments take precedence over environment variables, which take precedence over the
+    /// settings file. `var` returns the value of an environment variable.
+    #[cfg(feature = "metrics")]
+    pub fn with_overrides(mut self, args: &[String], var: impl Fn(&str) -> Option<String>) -> Result<Settings> {
+        let mut metrics_addr = var(METRICS_ADDR_VARIABLE);
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--metrics-addr" => {
+                    let addr = args.next().ok_or_else(|| Error::InvalidSettings("--metrics-addr requires an address".to_string()))?;
+                    metrics_addr = Some(addr.clone());
+                },
+                _ => return Err(Error::InvalidSettings(format!("unknown argument {}", arg))),
+            }
+        }
+        if let Some(metrics_addr) = metrics_addr {
+            let addr = metrics_addr.parse().map_err(|_| Error::InvalidSettings(format!("invalid metrics address {}", metrics_addr)))?;
+            self.metrics_addr = Some(addr);
+        }
+        Ok(self)
+    }
+
+    /// Returns the RakNet configuration with the GUID set to `guid` or, if that is
+    /// not set, to the GUID loaded from or created in `guid_path`.
+    pub fn raknet_config(&self) -> Result<raknet::Config> {
+        let guid = match self.guid {
+            Some(guid) => guid,
+            None => raknet::Config::load_or_create_guid(&self.guid_path)?,
+        };
+        Ok(raknet::Config { guid, ..self.raknet.clone() })
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            addr: SocketAddr::from(([0, 0, 0, 0], 19132)),
+            guid: None,
+            guid_path: PathBuf::from("bedroxide.guid"),
+            capture_path: None,
+            ban_path: None,
+            #[cfg(feature = "metrics")]
+            metrics_addr: None,
+            raknet: raknet::Config::default(),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use std::net::SocketAddr;
+    use crate::error::Error;
+    use super::Settings;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn metrics_addr_is_set_from_argument() {
+        // Act
+        let settings = Settings::default()
+            .with_overrides(&args(&["--metrics-addr", "127.0.0.1:9100"]), |_| Some("127.0.0.1:9200".to_string()))
+            .expect("Could not apply overrides");
+
+        // Assert
+        assert_eq!(Some(SocketAddr::from(([127, 0, 0, 1], 9100))), settings.metrics_addr);
+    }
+
+    #[test]
+    fn metrics_addr_is_set_from_environment_variable() {
+        // Act
+        let settings = Settings::default()
+            .with_overrides(&[], |name| (name == "BEDROXIDE_METRICS_ADDR").then(|| "127.0.0.1:9200".to_string()))
+            .expect("Could not apply overrides");
+
+        // Assert
+        assert_eq!(Some(SocketAddr::from(([127, 0, 0, 1], 9200))), settings.metrics_addr);
+    }
+
+    #[test]
+    fn invalid_arguments_are_rejected() {
+        // Act/Assert
+        assert!(matches!(Settings::default().with_overrides(&args(&["--metrics-addr", "localhost"]), |_| None), Err(Error::InvalidSettings(_))));
+        assert!(matches!(Settings::default().with_overrides(&args(&["--metrics-addr"]), |_| None), Err(Error::InvalidSettings(_))));
+        assert!(matches!(Settings::default().with_overrides(&args(&["--unknown"]), |_| None), Err(Error::InvalidSettings(_))));
+    }
+}